@@ -5,6 +5,9 @@ function setup_buttons() {
             e.preventDefault();
 
             this.classList.toggle("selected");
+            // Screen readers announce whether the section is expanded
+            let expanded = this.getAttribute("aria-expanded") === "true";
+            this.setAttribute("aria-expanded", expanded ? "false" : "true");
 
             let selector = this.getAttribute("data-toggle");
             let elements = document.querySelectorAll(selector);
@@ -15,4 +18,130 @@ function setup_buttons() {
     }
 }
 
+// Filter the crates listed in the page by name and category, optionally hiding the regressions
+// caused by dependencies. The filters are stored in the URL hash (like
+// `#name=serde&hide=regressed,fixed&deps=hide`), so filtered views can be shared.
+function setup_filters() {
+    let filters = document.querySelector(".filters");
+    if (filters === null) {
+        return;
+    }
+    let search = filters.querySelector(".filter-name");
+    let count = filters.querySelector(".filter-count");
+    let crates = document.querySelectorAll(".crate[data-name]");
+
+    // Categories can only be toggled if the page lists more than one of them
+    let categories = [];
+    for (let i = 0; i < crates.length; i++) {
+        let category = crates[i].getAttribute("data-category");
+        if (categories.indexOf(category) === -1) {
+            categories.push(category);
+        }
+    }
+    let checkboxes = {};
+    if (categories.length > 1) {
+        let container = filters.querySelector(".filter-categories");
+        for (let i = 0; i < categories.length; i++) {
+            let label = document.createElement("label");
+            let checkbox = document.createElement("input");
+            checkbox.type = "checkbox";
+            checkbox.checked = true;
+            checkbox.addEventListener("change", function() { apply(true); });
+            label.appendChild(checkbox);
+            label.appendChild(document.createTextNode(" " + categories[i]));
+            container.appendChild(label);
+            checkboxes[categories[i]] = checkbox;
+        }
+    }
+
+    // Regressions caused by dependencies can only be hidden if the page lists some of them
+    let dependencies = null;
+    if (document.querySelector(".crate[data-caused-by]") !== null) {
+        let label = document.createElement("label");
+        dependencies = document.createElement("input");
+        dependencies.type = "checkbox";
+        dependencies.checked = true;
+        dependencies.addEventListener("change", function() { apply(true); });
+        label.appendChild(dependencies);
+        label.appendChild(document.createTextNode(" caused by dependencies"));
+        filters.querySelector(".filter-categories").appendChild(label);
+    }
+
+    function load_hash() {
+        let state = { name: "", hide: [], deps: "" };
+        let parts = window.location.hash.substr(1).split("&");
+        for (let i = 0; i < parts.length; i++) {
+            let pair = parts[i].split("=");
+            let value = decodeURIComponent(pair.slice(1).join("="));
+            if (pair[0] === "name") {
+                state.name = value;
+            } else if (pair[0] === "hide" && value !== "") {
+                state.hide = value.split(",");
+            } else if (pair[0] === "deps") {
+                state.deps = value;
+            }
+        }
+
+        search.value = state.name;
+        for (let category in checkboxes) {
+            checkboxes[category].checked = state.hide.indexOf(category) === -1;
+        }
+        if (dependencies !== null) {
+            dependencies.checked = state.deps !== "hide";
+        }
+    }
+
+    function apply(update_hash) {
+        let name = search.value.trim().toLowerCase();
+        let hidden = [];
+        for (let category in checkboxes) {
+            if (!checkboxes[category].checked) {
+                hidden.push(category);
+            }
+        }
+        let hide_dependencies = dependencies !== null && !dependencies.checked;
+
+        let shown = 0;
+        for (let i = 0; i < crates.length; i++) {
+            let hide = crates[i].getAttribute("data-name").toLowerCase().indexOf(name) === -1
+                || hidden.indexOf(crates[i].getAttribute("data-category")) !== -1
+                || (hide_dependencies && crates[i].hasAttribute("data-caused-by"));
+            crates[i].classList.toggle("hidden", hide);
+            if (!hide) {
+                shown++;
+            }
+        }
+        count.textContent = shown + " of " + crates.length + " crates shown";
+
+        if (update_hash) {
+            let hash = [];
+            if (name !== "") {
+                hash.push("name=" + encodeURIComponent(name));
+            }
+            if (hidden.length > 0) {
+                hash.push("hide=" + hidden.map(encodeURIComponent).join(","));
+            }
+            if (hide_dependencies) {
+                hash.push("deps=hide");
+            }
+            let url = window.location.pathname + window.location.search;
+            if (hash.length > 0) {
+                url += "#" + hash.join("&");
+            }
+            window.history.replaceState(null, "", url);
+        }
+    }
+
+    search.addEventListener("input", function() { apply(true); });
+    window.addEventListener("hashchange", function() {
+        load_hash();
+        apply(false);
+    });
+
+    load_hash();
+    apply(false);
+    filters.classList.remove("hidden");
+}
+
 setup_buttons();
+setup_filters();
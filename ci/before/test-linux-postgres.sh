@@ -0,0 +1,5 @@
+#!/bin/bash
+set -euo pipefail
+IFS=$'\n\t'
+
+psql -c "CREATE DATABASE crater_test;" -U postgres
@@ -0,0 +1,8 @@
+#!/bin/bash
+set -euo pipefail
+IFS=$'\n\t'
+
+export CRATER_TEST_POSTGRES_URL="postgres://postgres@localhost/crater_test"
+
+cargo build --features postgresql
+cargo test --features postgresql
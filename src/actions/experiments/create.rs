@@ -1,19 +1,51 @@
-use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
-use crate::db::QueryUtils;
-use crate::experiments::{CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status};
+use crate::actions::{
+    experiments::{
+        check_cargo_patches, check_feature_sets, check_private, collect_errors, CrateCounts,
+        DryRun, ExperimentError,
+    },
+    Action, ActionsCtx,
+};
+use crate::crates::{Crate, RegistryCrate, RegistryIndex};
+use crate::db::{Database, QueryUtils};
+use crate::experiments::{
+    CapLints, CargoPatch, CrateSelect, Experiment, FeatureSet, GitHubIssue, Mode, Status,
+    TestRunner,
+};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 use chrono::Utc;
+use failure::Error;
+use std::path::Path;
 
 pub struct CreateExperiment {
     pub name: String,
     pub toolchains: [Toolchain; 2],
     pub mode: Mode,
     pub crates: CrateSelect,
+    /// Crates to test instead of the ones chosen by `crates`, for example read from a file.
+    pub crate_list: Option<Vec<Crate>>,
+    /// Exact versions of crates.io crates to test instead of the ones chosen by `crates`. Unlike
+    /// the crates in `crate_list`, they're checked against the registry.
+    pub pinned_crates: Vec<RegistryCrate>,
     pub cap_lints: CapLints,
     pub priority: i32,
     pub github_issue: Option<GitHubIssue>,
     pub ignore_blacklist: bool,
+    pub fail_fast: bool,
+    pub feature_sets: Vec<FeatureSet>,
+    pub requested_by: Option<String>,
+    pub live_report: bool,
+    pub cargo_patches: Vec<CargoPatch>,
+    pub pre_analyze: bool,
+    pub private: bool,
+    /// Names of the tokens allowed to request links to the report of a private experiment.
+    pub private_readers: Vec<String>,
+    pub test_runner: Option<TestRunner>,
+    pub measure_artifacts: bool,
+    pub silent: bool,
+    /// Create the experiment even if one not completed yet already tests the same crates with
+    /// the same toolchains.
+    pub allow_duplicate: bool,
 }
 
 impl CreateExperiment {
@@ -26,34 +58,241 @@ impl CreateExperiment {
             toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
             mode: Mode::BuildAndTest,
             crates: CrateSelect::Local,
+            crate_list: None,
+            pinned_crates: Vec::new(),
             cap_lints: CapLints::Forbid,
             priority: 0,
             github_issue: None,
             ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+            // Tests create many experiments with the same configuration
+            allow_duplicate: true,
         }
     }
 }
 
-impl Action for CreateExperiment {
-    fn apply(self, ctx: &ActionsCtx) -> Fallible<()> {
+/// Name of an experiment not completed yet testing the crates with the toolchains.
+fn find_duplicate(
+    db: &Database,
+    toolchains: &[Toolchain; 2],
+    crates: &[Crate],
+) -> Fallible<Option<String>> {
+    let mut crates = crates.to_vec();
+    crates.sort();
+
+    let candidates = db.query(
+        "SELECT name FROM experiments \
+         WHERE toolchain_start = ?1 AND toolchain_end = ?2 AND status <> ?3 \
+         ORDER BY created_at;",
+        &[
+            &toolchains[0].to_string(),
+            &toolchains[1].to_string(),
+            &Status::Completed.to_str(),
+        ],
+        |row| -> String { row.get("name") },
+    )?;
+    for name in candidates {
+        if let Some(mut ex) = Experiment::get(db, &name)? {
+            ex.crates.sort();
+            if ex.crates == crates {
+                return Ok(Some(name));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Check every pinned version was published in the registry, returning the crates to test or the
+/// errors about all the missing versions.
+fn check_pinned_crates(index: &Path, pinned: &[RegistryCrate]) -> Result<Vec<Crate>, Vec<Error>> {
+    let index = RegistryIndex::open(index).map_err(|err| vec![err])?;
+
+    let mut errors = Vec::new();
+    for krate in pinned {
+        match index.versions(&krate.name) {
+            Ok(ref versions) if versions.contains(&krate.version) => {}
+            Ok(_) => errors.push(
+                ExperimentError::UnknownCrateVersion(krate.name.clone(), krate.version.clone())
+                    .into(),
+            ),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(pinned.iter().cloned().map(Crate::Registry).collect())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse a line of an uploaded crate list, checking crates.io crates against the registry.
+fn parse_crate_list_line(index: &RegistryIndex, line: &str) -> Fallible<Crate> {
+    let krate = if line.starts_with("https://github.com/") {
+        Crate::GitHub(line.parse()?)
+    } else if line.contains('/') {
+        Crate::from_id(line)?
+    } else if line.contains('@') {
+        Crate::Registry(line.parse()?)
+    } else {
+        Crate::Registry(RegistryCrate {
+            name: line.to_string(),
+            version: String::new(),
+        })
+    };
+
+    let mut krate = match krate {
+        Crate::Registry(krate) => krate,
+        other => return Ok(other),
+    };
+    let valid_name = krate
+        .name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name || krate.name.is_empty() {
+        bail!("invalid crate name {}", krate.name);
+    }
+    let mut versions = index.versions(&krate.name)?;
+    if krate.version.is_empty() {
+        // Versions are listed in the index in the order they were published
+        krate.version = match versions.pop() {
+            Some(latest) => latest,
+            None => bail!("crate {} is not in the registry", krate.name),
+        };
+    } else if !versions.contains(&krate.version) {
+        return Err(ExperimentError::UnknownCrateVersion(krate.name, krate.version).into());
+    }
+    Ok(Crate::Registry(krate))
+}
+
+/// Parse an uploaded list of crates to test, one per line: `name` for the latest version of a
+/// crates.io crate, `name@version` for an exact one, the URL of a GitHub repository or a crate id.
+/// Empty lines and the ones starting with `#` are ignored, and all the invalid lines are reported
+/// together.
+pub fn parse_crate_list(ctx: &ActionsCtx, content: &str) -> Fallible<Vec<Crate>> {
+    let index = RegistryIndex::open(&ctx.registry_index)?;
+
+    let mut crates = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_crate_list_line(&index, line) {
+            Ok(krate) => crates.push(krate),
+            Err(err) => errors.push(
+                ExperimentError::InvalidCrateListLine(i + 1, line.to_string(), err.to_string())
+                    .into(),
+            ),
+        }
+    }
+
+    collect_errors(errors)?;
+    Ok(crates)
+}
+
+impl CreateExperiment {
+    /// Check the experiment can be created, returning the crates it would test. All the problems
+    /// found are reported together.
+    fn validate(&self, ctx: &ActionsCtx) -> Fallible<Vec<Crate>> {
+        let mut errors = Vec::new();
+
         // Ensure no duplicate experiments are created
         if Experiment::exists(&ctx.db, &self.name)? {
-            return Err(ExperimentError::AlreadyExists(self.name.clone()).into());
+            errors.push(ExperimentError::AlreadyExists(self.name.clone()).into());
         }
 
         // Ensure no experiment with duplicate toolchains is created
         if self.toolchains[0] == self.toolchains[1] {
-            return Err(ExperimentError::DuplicateToolchains.into());
+            errors.push(ExperimentError::DuplicateToolchains.into());
+        }
+        for check in vec![
+            check_feature_sets(&self.feature_sets),
+            check_cargo_patches(&ctx.config, &self.cargo_patches),
+            check_private(self.private, self.live_report),
+        ] {
+            if let Err(err) = check {
+                errors.push(err);
+            }
         }
 
-        let crates = crate::crates::lists::get_crates(self.crates, &ctx.db, &ctx.config)?;
+        let crates = match self.crate_list {
+            Some(ref list) => {
+                let mut crates = list.clone();
+                crates.sort();
+                crates.dedup();
+                if crates.is_empty() {
+                    errors.push(ExperimentError::NoCrates.into());
+                }
+                crates
+            }
+            None if !self.pinned_crates.is_empty() => {
+                match check_pinned_crates(&ctx.registry_index, &self.pinned_crates) {
+                    Ok(mut crates) => {
+                        crates.sort();
+                        crates.dedup();
+                        crates
+                    }
+                    Err(mut errs) => {
+                        errors.append(&mut errs);
+                        Vec::new()
+                    }
+                }
+            }
+            None => match crate::crates::lists::get_crates(self.crates, &ctx.db, &ctx.config) {
+                Ok(crates) => crates,
+                Err(err) => {
+                    errors.push(err);
+                    Vec::new()
+                }
+            },
+        };
+
+        if !self.allow_duplicate && !crates.is_empty() {
+            if let Some(existing) = find_duplicate(&ctx.db, &self.toolchains, &crates)? {
+                errors.push(ExperimentError::DuplicateOf(existing).into());
+            }
+        }
+
+        collect_errors(errors)?;
+        Ok(crates)
+    }
+
+    /// Run all the checks of `apply`, returning the experiment that would be created without
+    /// storing anything.
+    pub fn dry_run(&self, ctx: &ActionsCtx) -> Fallible<DryRun> {
+        let crates = self.validate(ctx)?;
+        Ok(DryRun {
+            experiment: self.into(),
+            crates: CrateCounts::new(&ctx.config, &crates, self.ignore_blacklist),
+        })
+    }
+}
+
+impl Action for CreateExperiment {
+    fn apply(self, ctx: &ActionsCtx) -> Fallible<()> {
+        let crates = self.validate(ctx)?;
 
         ctx.db.transaction(|transaction| {
             transaction.execute(
                 "INSERT INTO experiments \
                  (name, mode, cap_lints, toolchain_start, toolchain_end, priority, created_at, \
-                 status, github_issue, github_issue_url, github_issue_number, ignore_blacklist) \
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12);",
+                 status, github_issue, github_issue_url, github_issue_number, ignore_blacklist, \
+                 fail_fast, feature_sets, requested_by, live_report, cargo_patches, pre_analyze, \
+                 private, private_readers, test_runner, measure_artifacts, silent) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, \
+                 ?17, ?18, ?19, ?20, ?21, ?22, ?23);",
                 &[
                     &self.name,
                     &self.mode.to_str(),
@@ -67,6 +306,17 @@ impl Action for CreateExperiment {
                     &self.github_issue.as_ref().map(|i| i.html_url.as_str()),
                     &self.github_issue.as_ref().map(|i| i.number),
                     &self.ignore_blacklist,
+                    &self.fail_fast,
+                    &::serde_json::to_string(&self.feature_sets)?,
+                    &self.requested_by,
+                    &self.live_report,
+                    &::serde_json::to_string(&self.cargo_patches)?,
+                    &self.pre_analyze,
+                    &self.private,
+                    &::serde_json::to_string(&self.private_readers)?,
+                    &self.test_runner.map(|runner| runner.to_str()),
+                    &self.measure_artifacts,
+                    &self.silent,
                 ],
             )?;
 
@@ -87,13 +337,19 @@ impl Action for CreateExperiment {
 
 #[cfg(test)]
 mod tests {
-    use super::CreateExperiment;
-    use crate::actions::{Action, ActionsCtx, ExperimentError};
+    use super::{parse_crate_list, CreateExperiment};
+    use crate::actions::{Action, ActionsCtx, ExperimentDefinition, ExperimentError};
     use crate::config::{Config, CrateConfig};
-    use crate::crates::Crate;
+    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
     use crate::db::{Database, QueryUtils};
-    use crate::experiments::{CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status};
+    use crate::experiments::{
+        CapLints, CargoPatch, CrateSelect, Experiment, FeatureSet, GitHubIssue, Mode, Status,
+        TestRunner,
+    };
+    use crate::results::{DatabaseDB, TestResult, WriteResults};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use std::fs;
+    use tempfile::tempdir;
 
     #[test]
     fn test_creation() {
@@ -111,6 +367,8 @@ mod tests {
             toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
             mode: Mode::BuildAndTest,
             crates: CrateSelect::Local,
+            crate_list: None,
+            pinned_crates: Vec::new(),
             cap_lints: CapLints::Forbid,
             priority: 5,
             github_issue: Some(GitHubIssue {
@@ -119,6 +377,18 @@ mod tests {
                 number: 10,
             }),
             ignore_blacklist: true,
+            fail_fast: true,
+            feature_sets: vec![FeatureSet::Default, FeatureSet::NoDefault],
+            requested_by: Some("octocat".to_string()),
+            live_report: true,
+            cargo_patches: Vec::new(),
+            pre_analyze: true,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: Some(TestRunner::Nextest),
+            measure_artifacts: true,
+            silent: true,
+            allow_duplicate: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -145,6 +415,20 @@ mod tests {
         assert_eq!(ex.status, Status::Queued);
         assert!(ex.assigned_to.is_none());
         assert!(ex.ignore_blacklist);
+        assert!(ex.fail_fast);
+        assert!(ex.live_report);
+        assert!(ex.pre_analyze);
+        assert_eq!(ex.test_runner, Some(TestRunner::Nextest));
+        assert!(ex.measure_artifacts);
+        assert!(ex.silent);
+        assert_eq!(
+            ex.feature_sets,
+            vec![FeatureSet::Default, FeatureSet::NoDefault]
+        );
+        assert_eq!(
+            ex.requested_by.as_ref().map(|s| s.as_str()),
+            Some("octocat")
+        );
     }
 
     #[test]
@@ -182,6 +466,7 @@ mod tests {
                 quiet: false,
                 update_lockfile: false,
                 broken: false,
+                setup: Vec::new(),
             },
         );
         let ctx = ActionsCtx::new(&db, &config);
@@ -190,6 +475,7 @@ mod tests {
 
         CreateExperiment {
             ignore_blacklist: false,
+            fail_fast: false,
             ..CreateExperiment::dummy("foo")
         }
         .apply(&ctx)
@@ -198,6 +484,7 @@ mod tests {
 
         CreateExperiment {
             ignore_blacklist: true,
+            fail_fast: false,
             ..CreateExperiment::dummy("bar")
         }
         .apply(&ctx)
@@ -219,10 +506,24 @@ mod tests {
             toolchains: [MAIN_TOOLCHAIN.clone(), MAIN_TOOLCHAIN.clone()],
             mode: Mode::BuildAndTest,
             crates: CrateSelect::Local,
+            crate_list: None,
+            pinned_crates: Vec::new(),
             cap_lints: CapLints::Forbid,
             priority: 0,
             github_issue: None,
             ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+            allow_duplicate: false,
         }
         .apply(&ctx)
         .unwrap_err();
@@ -247,10 +548,24 @@ mod tests {
             toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
             mode: Mode::BuildAndTest,
             crates: CrateSelect::Local,
+            crate_list: None,
+            pinned_crates: Vec::new(),
             cap_lints: CapLints::Forbid,
             priority: 0,
             github_issue: None,
             ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+            allow_duplicate: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -261,10 +576,24 @@ mod tests {
             toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
             mode: Mode::BuildAndTest,
             crates: CrateSelect::Local,
+            crate_list: None,
+            pinned_crates: Vec::new(),
             cap_lints: CapLints::Forbid,
             priority: 0,
             github_issue: None,
             ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+            allow_duplicate: false,
         }
         .apply(&ctx)
         .unwrap_err();
@@ -274,4 +603,402 @@ mod tests {
             Some(&ExperimentError::AlreadyExists("foo".into()))
         );
     }
+
+    #[test]
+    fn test_duplicate_experiment() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let create = |name: &str, allow_duplicate| {
+            CreateExperiment {
+                allow_duplicate,
+                ..CreateExperiment::dummy(name)
+            }
+            .apply(&ctx)
+        };
+        create("foo", false).unwrap();
+
+        // The existing experiment is returned instead of creating a new one
+        let err = create("bar", false).unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::DuplicateOf("foo".into()))
+        );
+        assert!(!Experiment::exists(&db, "bar").unwrap());
+
+        // Experiments testing other crates or toolchains are not duplicates
+        CreateExperiment {
+            allow_duplicate: false,
+            crate_list: Some(vec![Crate::Local("build-pass".into())]),
+            pinned_crates: Vec::new(),
+            ..CreateExperiment::dummy("baz")
+        }
+        .apply(&ctx)
+        .unwrap();
+        CreateExperiment {
+            allow_duplicate: false,
+            toolchains: [TEST_TOOLCHAIN.clone(), MAIN_TOOLCHAIN.clone()],
+            ..CreateExperiment::dummy("qux")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        // The flag creates the duplicate anyway
+        create("bar", true).unwrap();
+        assert!(Experiment::exists(&db, "bar").unwrap());
+
+        // Completed experiments are not duplicates
+        db.execute(
+            "UPDATE experiments SET status = 'completed' WHERE name IN ('foo', 'bar');",
+            &[],
+        )
+        .unwrap();
+        create("quux", false).unwrap();
+    }
+
+    #[test]
+    fn test_cargo_patches() {
+        let db = Database::temp().unwrap();
+        let mut config = Config::default();
+        config.sandbox.allowed_patch_sources = vec!["https://github.com/tokio-rs/".into()];
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let patch = |name: &str, git: &str| CargoPatch {
+            name: name.into(),
+            git: git.into(),
+            rev: Some("f00".into()),
+        };
+
+        CreateExperiment {
+            cargo_patches: vec![patch("tokio", "https://github.com/tokio-rs/tokio")],
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(
+            ex.cargo_patches,
+            vec![patch("tokio", "https://github.com/tokio-rs/tokio")]
+        );
+
+        // Only the allowed repositories can be used
+        let err = CreateExperiment {
+            cargo_patches: vec![patch("tokio", "https://example.com/tokio")],
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::PatchNotAllowed(
+                "https://example.com/tokio".into()
+            ))
+        );
+
+        let err = CreateExperiment {
+            cargo_patches: vec![
+                patch("tokio", "https://github.com/tokio-rs/tokio"),
+                patch("tokio", "https://github.com/tokio-rs/tokio-fork"),
+            ],
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::DuplicatePatches("tokio".into()))
+        );
+        assert!(!Experiment::exists(&db, "bar").unwrap());
+    }
+
+    #[test]
+    fn test_private() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment {
+            private: true,
+            private_readers: vec!["alice".into(), "bob".into()],
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert!(ex.private);
+        assert_eq!(ex.private_readers, vec!["alice", "bob"]);
+
+        // Live reports would be published outside of the signed links
+        let err = CreateExperiment {
+            private: true,
+            live_report: true,
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::PrivateLiveReport)
+        );
+        assert!(!Experiment::exists(&db, "bar").unwrap());
+    }
+
+    #[test]
+    fn test_crate_list() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+
+        // The list replaces the selected crates, without duplicates
+        CreateExperiment {
+            crate_list: Some(vec![krate("foo"), krate("bar"), krate("foo")]),
+            pinned_crates: Vec::new(),
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(ex.crates, vec![krate("bar"), krate("foo")]);
+
+        let err = CreateExperiment {
+            crate_list: Some(Vec::new()),
+            pinned_crates: Vec::new(),
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ExperimentError::NoCrates));
+        assert!(!Experiment::exists(&db, "bar").unwrap());
+    }
+
+    #[test]
+    fn test_pinned_crates() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let index = tempdir().unwrap();
+        fs::create_dir_all(index.path().join("la/zy")).unwrap();
+        fs::write(
+            index.path().join("la/zy/lazy_static"),
+            concat!(
+                "{\"name\":\"lazy_static\",\"vers\":\"0.1.0\",\"deps\":[],\"yanked\":false}\n",
+                "{\"name\":\"lazy_static\",\"vers\":\"1.0.0\",\"deps\":[],\"yanked\":false}\n",
+            ),
+        )
+        .unwrap();
+        let ctx = ActionsCtx::new(&db, &config).registry_index(index.path());
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // The old version is tested, not the latest one
+        let pinned = RegistryCrate {
+            name: "lazy_static".into(),
+            version: "0.1.0".into(),
+        };
+        CreateExperiment {
+            pinned_crates: vec![pinned.clone()],
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        let krate = Crate::Registry(pinned);
+        assert_eq!(ex.crates, vec![krate.clone()]);
+
+        // Its results are recorded with the pinned version
+        let results = DatabaseDB::new(&db);
+        results
+            .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                Ok(TestResult::TestPass)
+            })
+            .unwrap();
+        assert_eq!(
+            results.get_result(&ex, &MAIN_TOOLCHAIN, &krate).unwrap(),
+            Some(TestResult::TestPass)
+        );
+        let latest = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1.0.0".into(),
+        });
+        assert_eq!(
+            results.get_result(&ex, &MAIN_TOOLCHAIN, &latest).unwrap(),
+            None
+        );
+
+        // Versions never published are rejected
+        let err = CreateExperiment {
+            pinned_crates: vec!["lazy_static@0.3.0".parse().unwrap()],
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::UnknownCrateVersion(
+                "lazy_static".into(),
+                "0.3.0".into()
+            ))
+        );
+        let err = CreateExperiment {
+            pinned_crates: vec![
+                "lazy_static@0.3.0".parse().unwrap(),
+                "missing@1.0.0".parse().unwrap(),
+            ],
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        match err.downcast_ref::<ExperimentError>() {
+            Some(ExperimentError::Invalid(2, _)) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(!Experiment::exists(&db, "bar").unwrap());
+    }
+
+    #[test]
+    fn test_uploaded_crate_list() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let index = tempdir().unwrap();
+        fs::create_dir_all(index.path().join("la/zy")).unwrap();
+        fs::write(
+            index.path().join("la/zy/lazy_static"),
+            concat!(
+                "{\"name\":\"lazy_static\",\"vers\":\"0.1.0\",\"deps\":[],\"yanked\":false}\n",
+                "{\"name\":\"lazy_static\",\"vers\":\"1.0.0\",\"deps\":[],\"yanked\":false}\n",
+            ),
+        )
+        .unwrap();
+        fs::create_dir_all(index.path().join("3/l")).unwrap();
+        fs::write(
+            index.path().join("3/l/log"),
+            "{\"name\":\"log\",\"vers\":\"0.4.6\",\"deps\":[],\"yanked\":false}\n",
+        )
+        .unwrap();
+        let ctx = ActionsCtx::new(&db, &config).registry_index(index.path());
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let list = concat!(
+            "# Crates affected by the change\n",
+            "lazy_static@0.1.0\n",
+            "\n",
+            "  log  \n",
+            "https://github.com/brson/hello-rs\n",
+            "local/build-pass\n",
+        );
+        let crates = parse_crate_list(&ctx, list).unwrap();
+        let expected = vec![
+            Crate::Registry(RegistryCrate {
+                name: "lazy_static".into(),
+                version: "0.1.0".into(),
+            }),
+            // The latest version is picked when none is given
+            Crate::Registry(RegistryCrate {
+                name: "log".into(),
+                version: "0.4.6".into(),
+            }),
+            Crate::GitHub(GitHubRepo {
+                org: "brson".into(),
+                name: "hello-rs".into(),
+            }),
+            Crate::Local("build-pass".into()),
+        ];
+        assert_eq!(crates, expected);
+
+        // The experiment tests exactly the listed crates
+        CreateExperiment {
+            crate_list: Some(crates),
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let mut tested = Experiment::get(&db, "foo").unwrap().unwrap().crates;
+        tested.sort();
+        let mut expected = expected;
+        expected.sort();
+        assert_eq!(tested, expected);
+
+        // Bogus lines are reported with their number instead of being skipped
+        let err = parse_crate_list(&ctx, "log\nthis is not a crate\n").unwrap_err();
+        match err.downcast_ref::<ExperimentError>() {
+            Some(ExperimentError::InvalidCrateListLine(2, line, _)) => {
+                assert_eq!(line, "this is not a crate");
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        let err = parse_crate_list(&ctx, "lazy_static@0.3.0\nlog\nmissing\n").unwrap_err();
+        match err.downcast_ref::<ExperimentError>() {
+            Some(ExperimentError::Invalid(2, _)) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let db = Database::temp().unwrap();
+        let mut config = Config::default();
+        config.local_crates.insert(
+            "build-pass".into(),
+            CrateConfig {
+                skip: true,
+                skip_tests: false,
+                quiet: false,
+                update_lockfile: false,
+                broken: false,
+                setup: Vec::new(),
+            },
+        );
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let dry_run = CreateExperiment {
+            priority: 5,
+            ..CreateExperiment::dummy("foo")
+        }
+        .dry_run(&ctx)
+        .unwrap();
+        assert!(!Experiment::exists(&db, "foo").unwrap());
+        assert_eq!(dry_run.experiment.priority, 5);
+        assert_eq!(
+            dry_run.experiment.toolchains,
+            [MAIN_TOOLCHAIN.to_string(), TEST_TOOLCHAIN.to_string()]
+        );
+        assert!(dry_run.crates.total > 1);
+        assert_eq!(dry_run.crates.skipped, 1);
+
+        // The printed experiment can be read back
+        let toml = dry_run.to_toml().unwrap();
+        assert!(toml.starts_with(&format!("# {} crates", dry_run.crates.total)));
+        let parsed: ExperimentDefinition = ::toml::from_str(&toml).unwrap();
+        assert_eq!(parsed, dry_run.experiment);
+
+        // All the problems are reported at once
+        let err = CreateExperiment {
+            toolchains: [MAIN_TOOLCHAIN.clone(), MAIN_TOOLCHAIN.clone()],
+            private: true,
+            live_report: true,
+            ..CreateExperiment::dummy("bar")
+        }
+        .dry_run(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::Invalid(
+                2,
+                "duplicate toolchains provided; private experiments can't have live reports".into()
+            ))
+        );
+    }
 }
@@ -0,0 +1,368 @@
+//! Configuration of an experiment as printed by `define-ex --dry-run` and `dump-ex`, in a format
+//! that can be read back by `define-ex --from-file` to define the same experiment.
+
+use crate::actions::experiments::CreateExperiment;
+use crate::config::Config;
+use crate::crates::{Crate, RegistryCrate};
+use crate::experiments::{
+    CapLints, CargoPatch, CrateSelect, Experiment, FeatureSet, GitHubIssue, Mode, TestRunner,
+};
+use crate::prelude::*;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExperimentDefinition {
+    pub name: String,
+    pub toolchains: [String; 2],
+    pub mode: Mode,
+    /// List of crates tested, unless `crate_list` is present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crates: Option<CrateSelect>,
+    pub cap_lints: CapLints,
+    pub priority: i32,
+    pub ignore_blacklist: bool,
+    pub fail_fast: bool,
+    pub feature_sets: Vec<FeatureSet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<String>,
+    pub live_report: bool,
+    pub pre_analyze: bool,
+    pub private: bool,
+    pub private_readers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_runner: Option<TestRunner>,
+    pub measure_artifacts: bool,
+    pub silent: bool,
+    /// IDs of the crates tested instead of the ones chosen by `crates`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crate_list: Option<Vec<String>>,
+    /// Exact versions of crates.io crates tested instead of the ones chosen by `crates`, written
+    /// as `name@version`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_crates: Vec<String>,
+    // Tables must come after the plain values in TOML
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_issue: Option<GitHubIssue>,
+    #[serde(default)]
+    pub cargo_patches: Vec<CargoPatch>,
+}
+
+impl ExperimentDefinition {
+    /// Read the definition from a JSON file if its extension is `.json`, from a TOML file
+    /// otherwise.
+    pub fn load(path: &Path) -> Fallible<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|_| format!("failed to read {}", path.display()))?;
+        let is_json = path.extension().map(|ext| ext == "json").unwrap_or(false);
+        Ok(if is_json {
+            ::serde_json::from_str(&content)
+                .with_context(|_| format!("invalid experiment definition in {}", path.display()))?
+        } else {
+            ::toml::from_str(&content)
+                .with_context(|_| format!("invalid experiment definition in {}", path.display()))?
+        })
+    }
+
+    /// The experiment to create, checked when the action is applied.
+    pub fn into_create(self, allow_duplicate: bool) -> Fallible<CreateExperiment> {
+        let crate_list = match self.crate_list {
+            Some(ids) => Some(
+                ids.iter()
+                    .map(|id| Crate::from_id(id))
+                    .collect::<Fallible<Vec<_>>>()?,
+            ),
+            None => None,
+        };
+        let pinned_crates = self
+            .pinned_crates
+            .iter()
+            .map(|krate| krate.parse())
+            .collect::<Fallible<Vec<RegistryCrate>>>()?;
+        let crates = match (self.crates, &crate_list) {
+            (Some(crates), _) => crates,
+            // Not used when the crates are listed
+            (None, Some(_)) => CrateSelect::Full,
+            (None, None) if !pinned_crates.is_empty() => CrateSelect::Full,
+            (None, None) => bail!(
+                "the crates tested by experiment '{}' must be chosen with either `crates`, \
+                 `crate-list` or `pinned-crates`",
+                self.name
+            ),
+        };
+
+        Ok(CreateExperiment {
+            name: self.name,
+            toolchains: [self.toolchains[0].parse()?, self.toolchains[1].parse()?],
+            mode: self.mode,
+            crates,
+            crate_list,
+            pinned_crates,
+            cap_lints: self.cap_lints,
+            priority: self.priority,
+            github_issue: self.github_issue,
+            ignore_blacklist: self.ignore_blacklist,
+            fail_fast: self.fail_fast,
+            feature_sets: self.feature_sets,
+            requested_by: self.requested_by,
+            live_report: self.live_report,
+            cargo_patches: self.cargo_patches,
+            pre_analyze: self.pre_analyze,
+            private: self.private,
+            private_readers: self.private_readers,
+            test_runner: self.test_runner,
+            measure_artifacts: self.measure_artifacts,
+            silent: self.silent,
+            allow_duplicate,
+        })
+    }
+
+    pub fn to_toml(&self) -> Fallible<String> {
+        Ok(::toml::to_string(self)?)
+    }
+}
+
+impl<'a> From<&'a CreateExperiment> for ExperimentDefinition {
+    fn from(create: &'a CreateExperiment) -> Self {
+        ExperimentDefinition {
+            name: create.name.clone(),
+            toolchains: [
+                create.toolchains[0].to_string(),
+                create.toolchains[1].to_string(),
+            ],
+            mode: create.mode,
+            crates: if create.crate_list.is_some() || !create.pinned_crates.is_empty() {
+                None
+            } else {
+                Some(create.crates)
+            },
+            cap_lints: create.cap_lints,
+            priority: create.priority,
+            ignore_blacklist: create.ignore_blacklist,
+            fail_fast: create.fail_fast,
+            feature_sets: create.feature_sets.clone(),
+            requested_by: create.requested_by.clone(),
+            live_report: create.live_report,
+            pre_analyze: create.pre_analyze,
+            private: create.private,
+            private_readers: create.private_readers.clone(),
+            test_runner: create.test_runner,
+            measure_artifacts: create.measure_artifacts,
+            silent: create.silent,
+            crate_list: create
+                .crate_list
+                .as_ref()
+                .map(|list| list.iter().map(Crate::id).collect()),
+            pinned_crates: create
+                .pinned_crates
+                .iter()
+                .map(RegistryCrate::to_string)
+                .collect(),
+            github_issue: create.github_issue.clone(),
+            cargo_patches: create.cargo_patches.clone(),
+        }
+    }
+}
+
+/// The crates of an existing experiment are always listed, as the way they were chosen is not
+/// stored.
+impl<'a> From<&'a Experiment> for ExperimentDefinition {
+    fn from(ex: &'a Experiment) -> Self {
+        // Listed in the same order as when the experiment is defined from the list
+        let mut crates = ex.crates.clone();
+        crates.sort();
+
+        ExperimentDefinition {
+            name: ex.name.clone(),
+            toolchains: [ex.toolchains[0].to_string(), ex.toolchains[1].to_string()],
+            mode: ex.mode,
+            crates: None,
+            cap_lints: ex.cap_lints,
+            priority: ex.priority,
+            ignore_blacklist: ex.ignore_blacklist,
+            fail_fast: ex.fail_fast,
+            feature_sets: ex.feature_sets.clone(),
+            requested_by: ex.requested_by.clone(),
+            live_report: ex.live_report,
+            pre_analyze: ex.pre_analyze,
+            private: ex.private,
+            private_readers: ex.private_readers.clone(),
+            test_runner: ex.test_runner,
+            measure_artifacts: ex.measure_artifacts,
+            silent: ex.silent,
+            crate_list: Some(crates.iter().map(Crate::id).collect()),
+            pinned_crates: Vec::new(),
+            github_issue: ex.github_issue.clone(),
+            cargo_patches: ex.cargo_patches.clone(),
+        }
+    }
+}
+
+/// How the configuration of the crates applies to the ones tested by an experiment.
+#[derive(Serialize, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrateCounts {
+    pub total: usize,
+    /// Crates in the blacklist, not tested unless the experiment ignores it.
+    pub skipped: usize,
+    pub skip_tests: usize,
+    pub broken: usize,
+}
+
+impl CrateCounts {
+    pub(super) fn new(config: &Config, crates: &[Crate], ignore_blacklist: bool) -> Self {
+        let count = |f: &dyn Fn(&Crate) -> bool| crates.iter().filter(|c| f(c)).count();
+        CrateCounts {
+            total: crates.len(),
+            skipped: if ignore_blacklist {
+                0
+            } else {
+                count(&|c| config.should_skip(c))
+            },
+            skip_tests: count(&|c| config.should_skip_tests(c)),
+            broken: count(&|c| config.is_broken(c)),
+        }
+    }
+}
+
+/// Experiment that would be created, after all the checks passed.
+#[derive(Serialize, Debug)]
+pub struct DryRun {
+    pub experiment: ExperimentDefinition,
+    pub crates: CrateCounts,
+}
+
+impl DryRun {
+    /// The definition of the experiment in TOML, with the counts of crates as comments.
+    pub fn to_toml(&self) -> Fallible<String> {
+        Ok(format!(
+            "# {} crates ({} skipped, {} without tests, {} broken)\n{}",
+            self.crates.total,
+            self.crates.skipped,
+            self.crates.skip_tests,
+            self.crates.broken,
+            self.experiment.to_toml()?
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExperimentDefinition;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{
+        CapLints, CargoPatch, CrateSelect, Experiment, FeatureSet, GitHubIssue, Mode, TestRunner,
+    };
+
+    fn dump(db: &Database, name: &str) -> ExperimentDefinition {
+        ExperimentDefinition::from(&Experiment::get(db, name).unwrap().unwrap())
+    }
+
+    // Define the experiment in a new database, as if it was cloned on another server
+    fn define(config: &Config, definition: ExperimentDefinition) -> Database {
+        let db = Database::temp().unwrap();
+        crate::crates::lists::setup_test_lists(&db, config).unwrap();
+        definition
+            .into_create(false)
+            .unwrap()
+            .apply(&ActionsCtx::new(&db, config))
+            .unwrap();
+        db
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config::default();
+        config.sandbox.allowed_patch_sources = vec!["https://github.com/rust-lang/".into()];
+        let db = Database::temp().unwrap();
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // Every field is different from its default
+        CreateExperiment {
+            toolchains: [
+                "nightly-2019-01-01+rustflags=-Zfoo".parse().unwrap(),
+                "nightly-2019-01-02+rustflags=-Zfoo".parse().unwrap(),
+            ],
+            mode: Mode::CheckOnly,
+            crates: CrateSelect::Local,
+            cap_lints: CapLints::Warn,
+            priority: 5,
+            github_issue: Some(GitHubIssue {
+                api_url: "https://api.github.com/repos/example/example/issues/10".into(),
+                html_url: "https://github.com/example/example/issue/10".into(),
+                number: 10,
+            }),
+            ignore_blacklist: true,
+            fail_fast: true,
+            feature_sets: vec![FeatureSet::NoDefault, FeatureSet::All],
+            requested_by: Some("octocat".into()),
+            cargo_patches: vec![CargoPatch {
+                name: "libc".into(),
+                git: "https://github.com/rust-lang/libc".into(),
+                rev: Some("master".into()),
+            }],
+            pre_analyze: true,
+            private: true,
+            private_readers: vec!["alice".into()],
+            test_runner: Some(TestRunner::Nextest),
+            measure_artifacts: true,
+            silent: true,
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ActionsCtx::new(&db, &config))
+        .unwrap();
+        let dumped = dump(&db, "foo");
+        assert!(dumped.crates.is_none());
+        assert!(!dumped.crate_list.as_ref().unwrap().is_empty());
+
+        let toml = dumped.to_toml().unwrap();
+        let parsed: ExperimentDefinition = ::toml::from_str(&toml).unwrap();
+        assert_eq!(parsed, dumped);
+        let db = define(&config, parsed);
+        assert_eq!(dump(&db, "foo"), dumped);
+
+        let json = ::serde_json::to_string(&dumped).unwrap();
+        let parsed: ExperimentDefinition = ::serde_json::from_str(&json).unwrap();
+        let db = define(&config, parsed);
+        assert_eq!(dump(&db, "foo"), dumped);
+    }
+
+    #[test]
+    fn test_minimal_definition() {
+        let config = Config::default();
+        let definition: ExperimentDefinition = ::toml::from_str(
+            r#"
+            name = "foo"
+            toolchains = ["stable", "beta"]
+            mode = "build-and-test"
+            crates = "local"
+            cap-lints = "forbid"
+            priority = 0
+            ignore-blacklist = false
+            fail-fast = false
+            feature-sets = ["default"]
+            live-report = false
+            pre-analyze = false
+            private = false
+            private-readers = []
+            measure-artifacts = false
+            silent = false
+            "#,
+        )
+        .unwrap();
+        let db = define(&config, definition);
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(
+            ex.crates,
+            crate::crates::lists::get_crates(CrateSelect::Local, &db, &config).unwrap()
+        );
+
+        // The crates must be chosen somehow
+        let mut definition = dump(&db, "foo");
+        definition.crate_list = None;
+        assert!(definition.into_create(false).is_err());
+    }
+}
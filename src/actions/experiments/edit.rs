@@ -1,6 +1,11 @@
-use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
+use crate::actions::{
+    experiments::{check_cargo_patches, check_feature_sets, check_private, ExperimentError},
+    Action, ActionsCtx,
+};
 use crate::db::QueryUtils;
-use crate::experiments::{CapLints, CrateSelect, Experiment, Mode, Status};
+use crate::experiments::{
+    CapLints, CargoPatch, CrateSelect, Experiment, FeatureSet, Mode, Status, TestRunner,
+};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 
@@ -12,6 +17,16 @@ pub struct EditExperiment {
     pub cap_lints: Option<CapLints>,
     pub priority: Option<i32>,
     pub ignore_blacklist: Option<bool>,
+    pub fail_fast: Option<bool>,
+    pub feature_sets: Option<Vec<FeatureSet>>,
+    pub live_report: Option<bool>,
+    pub cargo_patches: Option<Vec<CargoPatch>>,
+    pub pre_analyze: Option<bool>,
+    pub private: Option<bool>,
+    pub private_readers: Option<Vec<String>>,
+    pub test_runner: Option<TestRunner>,
+    pub measure_artifacts: Option<bool>,
+    pub silent: Option<bool>,
 }
 
 impl EditExperiment {
@@ -25,6 +40,16 @@ impl EditExperiment {
             cap_lints: None,
             priority: None,
             ignore_blacklist: None,
+            fail_fast: None,
+            feature_sets: None,
+            live_report: None,
+            cargo_patches: None,
+            pre_analyze: None,
+            private: None,
+            private_readers: None,
+            test_runner: None,
+            measure_artifacts: None,
+            silent: None,
         }
     }
 }
@@ -135,6 +160,109 @@ impl Action for EditExperiment {
                 ex.priority = priority;
             }
 
+            // Try to update the fail_fast field
+            if let Some(fail_fast) = self.fail_fast {
+                let changes = t.execute(
+                    "UPDATE experiments SET fail_fast = ?1 WHERE name = ?2;",
+                    &[&fail_fast, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.fail_fast = fail_fast;
+            }
+
+            // Try to update the live_report field
+            if let Some(live_report) = self.live_report {
+                let changes = t.execute(
+                    "UPDATE experiments SET live_report = ?1 WHERE name = ?2;",
+                    &[&live_report, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.live_report = live_report;
+            }
+
+            // Try to update the pre_analyze field
+            if let Some(pre_analyze) = self.pre_analyze {
+                let changes = t.execute(
+                    "UPDATE experiments SET pre_analyze = ?1 WHERE name = ?2;",
+                    &[&pre_analyze, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.pre_analyze = pre_analyze;
+            }
+
+            // Try to update the private field
+            if let Some(private) = self.private {
+                let changes = t.execute(
+                    "UPDATE experiments SET private = ?1 WHERE name = ?2;",
+                    &[&private, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.private = private;
+            }
+            check_private(ex.private, ex.live_report)?;
+
+            // Try to update the readers of the private report
+            if let Some(private_readers) = self.private_readers.take() {
+                let changes = t.execute(
+                    "UPDATE experiments SET private_readers = ?1 WHERE name = ?2;",
+                    &[&::serde_json::to_string(&private_readers)?, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.private_readers = private_readers;
+            }
+
+            // Try to update the test runner
+            if let Some(test_runner) = self.test_runner {
+                let changes = t.execute(
+                    "UPDATE experiments SET test_runner = ?1 WHERE name = ?2;",
+                    &[&test_runner.to_str(), &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.test_runner = Some(test_runner);
+            }
+
+            // Try to update the measure_artifacts field
+            if let Some(measure_artifacts) = self.measure_artifacts {
+                let changes = t.execute(
+                    "UPDATE experiments SET measure_artifacts = ?1 WHERE name = ?2;",
+                    &[&measure_artifacts, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.measure_artifacts = measure_artifacts;
+            }
+
+            // Try to update the silent field
+            if let Some(silent) = self.silent {
+                let changes = t.execute(
+                    "UPDATE experiments SET silent = ?1 WHERE name = ?2;",
+                    &[&silent, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.silent = silent;
+            }
+
+            // Try to update the feature sets
+            if let Some(feature_sets) = self.feature_sets.take() {
+                check_feature_sets(&feature_sets)?;
+                let changes = t.execute(
+                    "UPDATE experiments SET feature_sets = ?1 WHERE name = ?2;",
+                    &[&::serde_json::to_string(&feature_sets)?, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.feature_sets = feature_sets;
+            }
+
+            // Try to update the patches of the dependencies
+            if let Some(cargo_patches) = self.cargo_patches.take() {
+                check_cargo_patches(&ctx.config, &cargo_patches)?;
+                let changes = t.execute(
+                    "UPDATE experiments SET cargo_patches = ?1 WHERE name = ?2;",
+                    &[&::serde_json::to_string(&cargo_patches)?, &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.cargo_patches = cargo_patches;
+            }
+
             Ok(())
         })?;
         Ok(())
@@ -148,7 +276,9 @@ mod tests {
     use crate::config::{Config, CrateConfig};
     use crate::crates::Crate;
     use crate::db::{Database, QueryUtils};
-    use crate::experiments::{CapLints, CrateSelect, Experiment, Mode, Status};
+    use crate::experiments::{
+        CapLints, CrateSelect, Experiment, FeatureSet, Mode, Status, TestRunner,
+    };
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
 
     #[test]
@@ -177,10 +307,23 @@ mod tests {
             toolchains: ["stable".parse().unwrap(), "beta".parse().unwrap()],
             mode: Mode::BuildAndTest,
             crates: CrateSelect::SmallRandom,
+            crate_list: None,
+            pinned_crates: Vec::new(),
             cap_lints: CapLints::Forbid,
             priority: 0,
             github_issue: None,
             ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -197,6 +340,16 @@ mod tests {
             cap_lints: Some(CapLints::Warn),
             priority: Some(10),
             ignore_blacklist: Some(true),
+            fail_fast: Some(true),
+            feature_sets: Some(vec![FeatureSet::NoDefault, FeatureSet::All]),
+            live_report: Some(true),
+            cargo_patches: None,
+            pre_analyze: Some(true),
+            private: None,
+            private_readers: Some(vec!["alice".into()]),
+            test_runner: Some(TestRunner::Nextest),
+            measure_artifacts: Some(true),
+            silent: Some(true),
         }
         .apply(&ctx)
         .unwrap();
@@ -210,6 +363,17 @@ mod tests {
         assert_eq!(ex.cap_lints, CapLints::Warn);
         assert_eq!(ex.priority, 10);
         assert_eq!(ex.ignore_blacklist, true);
+        assert_eq!(ex.fail_fast, true);
+        assert_eq!(ex.live_report, true);
+        assert_eq!(ex.pre_analyze, true);
+        assert_eq!(ex.private_readers, vec!["alice".to_string()]);
+        assert_eq!(ex.test_runner, Some(TestRunner::Nextest));
+        assert!(ex.measure_artifacts);
+        assert!(ex.silent);
+        assert_eq!(
+            ex.feature_sets,
+            vec![FeatureSet::NoDefault, FeatureSet::All]
+        );
 
         assert_eq!(
             ex.crates,
@@ -252,6 +416,7 @@ mod tests {
                 quiet: false,
                 update_lockfile: false,
                 broken: false,
+                setup: Vec::new(),
             },
         );
         let ctx = ActionsCtx::new(&db, &config);
@@ -260,6 +425,7 @@ mod tests {
 
         CreateExperiment {
             ignore_blacklist: false,
+            fail_fast: false,
             ..CreateExperiment::dummy("foo")
         }
         .apply(&ctx)
@@ -268,6 +434,7 @@ mod tests {
 
         EditExperiment {
             ignore_blacklist: Some(true),
+            fail_fast: None,
             ..EditExperiment::dummy("foo")
         }
         .apply(&ctx)
@@ -276,6 +443,7 @@ mod tests {
 
         EditExperiment {
             ignore_blacklist: Some(false),
+            fail_fast: None,
             ..EditExperiment::dummy("foo")
         }
         .apply(&ctx)
@@ -307,6 +475,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_private() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment {
+            live_report: true,
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        // Live reports must be disabled in the same edit making the experiment private
+        let err = EditExperiment {
+            private: Some(true),
+            ..EditExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::PrivateLiveReport)
+        );
+        assert!(!Experiment::get(&db, "foo").unwrap().unwrap().private);
+
+        EditExperiment {
+            private: Some(true),
+            live_report: Some(false),
+            ..EditExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert!(ex.private);
+        assert!(!ex.live_report);
+    }
+
     #[test]
     fn test_editing_missing_experiment() {
         let db = Database::temp().unwrap();
@@ -1,10 +1,19 @@
 mod create;
+mod definition;
 mod delete;
 mod edit;
+mod regenerate;
 
-pub use self::create::CreateExperiment;
+use crate::config::Config;
+use crate::experiments::{CargoPatch, FeatureSet};
+use crate::prelude::*;
+use failure::Error;
+
+pub use self::create::{parse_crate_list, CreateExperiment};
+pub use self::definition::{CrateCounts, DryRun, ExperimentDefinition};
 pub use self::delete::DeleteExperiment;
 pub use self::edit::EditExperiment;
+pub use self::regenerate::RegenerateReport;
 
 #[derive(Debug, failure::Fail)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
@@ -13,8 +22,89 @@ pub enum ExperimentError {
     NotFound(String),
     #[fail(display = "experiment '{}' already exists", _0)]
     AlreadyExists(String),
+    #[fail(display = "no crates provided")]
+    NoCrates,
     #[fail(display = "duplicate toolchains provided")]
     DuplicateToolchains,
     #[fail(display = "it's only possible to edit queued experiments")]
     CanOnlyEditQueuedExperiments,
+    #[fail(display = "no feature sets provided")]
+    NoFeatureSets,
+    #[fail(display = "duplicate feature sets provided")]
+    DuplicateFeatureSets,
+    #[fail(display = "patching dependencies with {} is not allowed", _0)]
+    PatchNotAllowed(String),
+    #[fail(display = "the dependency {} is patched more than once", _0)]
+    DuplicatePatches(String),
+    #[fail(display = "private experiments can't have live reports")]
+    PrivateLiveReport,
+    #[fail(display = "it's only possible to regenerate the report of completed experiments")]
+    CanOnlyRegenerateCompletedReports,
+    #[fail(display = "the logs of experiment '{}' were purged", _0)]
+    LogsPurged(String),
+    #[fail(
+        display = "experiment '{}' already tests the same crates with the same toolchains",
+        _0
+    )]
+    DuplicateOf(String),
+    #[fail(display = "version {} of crate {} is not in the registry", _1, _0)]
+    UnknownCrateVersion(String, String),
+    #[fail(display = "line {} of the crate list ({}) is invalid: {}", _0, _1, _2)]
+    InvalidCrateListLine(usize, String, String),
+    #[fail(display = "{} problems found: {}", _0, _1)]
+    Invalid(usize, String),
+}
+
+/// Report all the errors found while checking an experiment at once.
+fn collect_errors(mut errors: Vec<Error>) -> Fallible<()> {
+    match errors.len() {
+        0 => Ok(()),
+        // Keep the original error, so it can still be matched on
+        1 => Err(errors.pop().unwrap()),
+        count => Err(ExperimentError::Invalid(
+            count,
+            errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+        .into()),
+    }
+}
+
+fn check_feature_sets(feature_sets: &[FeatureSet]) -> Fallible<()> {
+    if feature_sets.is_empty() {
+        return Err(ExperimentError::NoFeatureSets.into());
+    }
+
+    for (i, features) in feature_sets.iter().enumerate() {
+        if feature_sets[..i].contains(features) {
+            return Err(ExperimentError::DuplicateFeatureSets.into());
+        }
+    }
+
+    Ok(())
+}
+
+fn check_cargo_patches(config: &Config, patches: &[CargoPatch]) -> Fallible<()> {
+    for (i, patch) in patches.iter().enumerate() {
+        if !config.is_patch_allowed(patch) {
+            return Err(ExperimentError::PatchNotAllowed(patch.git.clone()).into());
+        }
+        if patches[..i].iter().any(|other| other.name == patch.name) {
+            return Err(ExperimentError::DuplicatePatches(patch.name.clone()).into());
+        }
+    }
+
+    Ok(())
+}
+
+fn check_private(private: bool, live_report: bool) -> Fallible<()> {
+    // Live reports are published while the experiment runs, outside of the signed links
+    if private && live_report {
+        return Err(ExperimentError::PrivateLiveReport.into());
+    }
+
+    Ok(())
 }
@@ -0,0 +1,118 @@
+use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
+use crate::db::QueryUtils;
+use crate::experiments::{Experiment, Status};
+use crate::prelude::*;
+use crate::results::DatabaseDB;
+use chrono::Utc;
+
+/// Queue the report of a completed experiment to be generated again from its stored results, for
+/// example after the report generator was improved. The experiment keeps its status while the
+/// report is replaced.
+pub struct RegenerateReport {
+    pub name: String,
+}
+
+impl Action for RegenerateReport {
+    fn apply(self, ctx: &ActionsCtx) -> Fallible<()> {
+        let ex = match Experiment::get(&ctx.db, &self.name)? {
+            Some(ex) => ex,
+            None => return Err(ExperimentError::NotFound(self.name).into()),
+        };
+        if ex.status != Status::Completed {
+            return Err(ExperimentError::CanOnlyRegenerateCompletedReports.into());
+        }
+
+        // The report includes the logs, which can't be published anymore once purged
+        if DatabaseDB::new(&ctx.db).logs_purged(&ex)? {
+            return Err(ExperimentError::LogsPurged(self.name).into());
+        }
+
+        // Requesting it again while it's pending doesn't queue it twice
+        ctx.db.transaction(|t| {
+            t.execute(
+                "DELETE FROM report_regenerations WHERE experiment = ?1;",
+                &[&self.name],
+            )?;
+            t.execute(
+                "INSERT INTO report_regenerations (experiment, requested_at) VALUES (?1, ?2);",
+                &[&self.name, &Utc::now()],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RegenerateReport;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment, ExperimentError};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{Experiment, Status};
+    use crate::prelude::*;
+    use crate::results::{DatabaseDB, TestResult, WriteResults};
+
+    fn regenerate(ctx: &ActionsCtx, name: &str) -> Fallible<()> {
+        RegenerateReport { name: name.into() }.apply(ctx)
+    }
+
+    #[test]
+    fn test_regenerate_report() {
+        crate::logs::init_test();
+
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let err = regenerate(&ctx, "dummy").unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::NotFound("dummy".into()))
+        );
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let results = DatabaseDB::new(&db);
+        results
+            .record_result(&ex, &ex.toolchains[0], &ex.crates[0], None, &config, || {
+                info!("build log");
+                Ok(TestResult::TestPass)
+            })
+            .unwrap();
+
+        // Experiments still running don't have a report yet
+        let err = regenerate(&ctx, "dummy").unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::CanOnlyRegenerateCompletedReports)
+        );
+        assert!(Experiment::first_report_regeneration(&db)
+            .unwrap()
+            .is_none());
+
+        // Requesting the regeneration twice queues it once, without changing the status
+        ex.set_status(&db, Status::Completed).unwrap();
+        regenerate(&ctx, "dummy").unwrap();
+        regenerate(&ctx, "dummy").unwrap();
+        let queued = Experiment::first_report_regeneration(&db).unwrap().unwrap();
+        assert_eq!(queued.name, "dummy");
+        assert_eq!(queued.status, Status::Completed);
+        queued.clear_report_regeneration(&db).unwrap();
+        assert!(Experiment::first_report_regeneration(&db)
+            .unwrap()
+            .is_none());
+
+        // The report can't be regenerated without the logs
+        results.purge_logs(&ex).unwrap();
+        let err = regenerate(&ctx, "dummy").unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::LogsPurged("dummy".into()))
+        );
+        assert!(Experiment::first_report_regeneration(&db)
+            .unwrap()
+            .is_none());
+    }
+}
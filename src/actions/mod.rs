@@ -6,7 +6,9 @@ pub use self::lists::*;
 
 use crate::config::Config;
 use crate::db::Database;
+use crate::dirs::REGISTRY_INDEX_DIR;
 use crate::prelude::*;
+use std::path::PathBuf;
 
 pub trait Action {
     fn apply(self, ctx: &ActionsCtx) -> Fallible<()>;
@@ -15,10 +17,22 @@ pub trait Action {
 pub struct ActionsCtx<'ctx> {
     db: &'ctx Database,
     config: &'ctx Config,
+    /// Clone of the crates.io index the pinned crates are checked against.
+    registry_index: PathBuf,
 }
 
 impl<'ctx> ActionsCtx<'ctx> {
     pub fn new(db: &'ctx Database, config: &'ctx Config) -> Self {
-        ActionsCtx { db, config }
+        ActionsCtx {
+            db,
+            config,
+            registry_index: REGISTRY_INDEX_DIR.clone(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn registry_index(mut self, path: &::std::path::Path) -> Self {
+        self.registry_index = path.to_path_buf();
+        self
     }
 }
@@ -1,15 +1,57 @@
 use crate::crates::{Crate, GitHubRepo};
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::results::TestResult;
-use crate::server::api_types::{AgentConfig, ApiResponse, CraterToken};
+use crate::results::{EnvironmentFingerprint, ErrorCategory, PhaseResult, TestResult};
+use crate::server::api_types::{
+    AgentConfig, ApiResponse, CraterToken, HostInfo, ItemOutcome, AGENT_API_VERSION,
+};
 use crate::toolchain::Toolchain;
 use crate::utils;
 use base64;
 use http::{header::AUTHORIZATION, Method, StatusCode};
+use log::{log_enabled, Level};
 use reqwest::RequestBuilder;
 use serde::de::DeserializeOwned;
-use serde_json::json;
+use serde_json::{self, json, Value};
+use std::time::Duration;
+
+/// Longest string shown in the bodies logged at the debug level, as the build logs are uploaded
+/// with the results.
+const MAX_LOGGED_STRING_LEN: usize = 256;
+
+/// Whether the field of a body might contain credentials.
+fn is_secret_field(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["token", "secret", "password"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+/// Body of a request or of a response as shown in the debug logs, with the values of the fields
+/// that might contain credentials redacted and the long strings truncated.
+fn loggable_body(mut body: Value) -> String {
+    fn clean(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, value) in fields.iter_mut() {
+                    if is_secret_field(name) {
+                        *value = Value::String("[redacted]".into());
+                    } else {
+                        clean(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(clean),
+            Value::String(content) if content.len() > MAX_LOGGED_STRING_LEN => {
+                *content = format!("[{} bytes]", content.len());
+            }
+            _ => {}
+        }
+    }
+
+    clean(&mut body);
+    body.to_string()
+}
 
 #[derive(Debug, Fail)]
 pub enum AgentApiError {
@@ -23,6 +65,24 @@ pub enum AgentApiError {
     InvalidAuthorizationToken,
     #[fail(display = "internal server error: {}", _0)]
     InternalServerError(String),
+    #[fail(display = "bad request: {}", _0)]
+    BadRequest(String),
+    #[fail(display = "some items were not processed: {}", _0)]
+    PartialFailure(String),
+}
+
+trait RequestExt {
+    /// Send the body as JSON, showing it in the debug logs.
+    fn logged_json(self, body: Value) -> Self;
+}
+
+impl RequestExt for RequestBuilder {
+    fn logged_json(self, body: Value) -> Self {
+        if log_enabled!(Level::Debug) {
+            debug!("request body: {}", loggable_body(body.clone()));
+        }
+        self.json(&body)
+    }
 }
 
 trait ResponseExt {
@@ -43,19 +103,43 @@ impl ResponseExt for ::reqwest::Response {
             _ => {}
         }
 
-        let result: ApiResponse<T> = self.json().with_context(|_| {
-            format!(
-                "failed to parse API response (status code {})",
-                self.status()
-            )
-        })?;
+        let status = self.status();
+        let body = self
+            .text()
+            .with_context(|_| format!("failed to read API response (status code {})", status))?;
+        if log_enabled!(Level::Debug) {
+            match serde_json::from_str(&body) {
+                Ok(value) => debug!("response body ({}): {}", status, loggable_body(value)),
+                Err(_) => debug!(
+                    "response body ({}): {} bytes of invalid JSON",
+                    status,
+                    body.len()
+                ),
+            }
+        }
+        let result: ApiResponse<T> = serde_json::from_str(&body)
+            .with_context(|_| format!("failed to parse API response (status code {})", status))?;
         match result {
             ApiResponse::Success { result } => Ok(result),
+            ApiResponse::Partial { items, .. } => {
+                let errors = items
+                    .into_iter()
+                    .filter_map(|item| match item {
+                        ItemOutcome::Error { error } => Some(error),
+                        ItemOutcome::Success => None,
+                    })
+                    .collect::<Vec<_>>();
+                Err(AgentApiError::PartialFailure(errors.join(", ")).into())
+            }
             ApiResponse::InternalError { error } => {
                 Err(AgentApiError::InternalServerError(error).into())
             }
             ApiResponse::Unauthorized => Err(AgentApiError::InvalidAuthorizationToken.into()),
             ApiResponse::NotFound => Err(AgentApiError::InvalidEndpoint.into()),
+            ApiResponse::PayloadTooLarge { .. } => Err(AgentApiError::PayloadTooLarge.into()),
+            ApiResponse::RequestTimeout { .. } => Err(AgentApiError::ServerUnavailable.into()),
+            ApiResponse::BadRequest { error } => Err(AgentApiError::BadRequest(error).into()),
+            ApiResponse::ServiceUnavailable { .. } => Err(AgentApiError::ServerUnavailable.into()),
         }
     }
 }
@@ -65,6 +149,7 @@ const RETRY_AFTER: u64 = 5;
 pub struct AgentApi {
     url: String,
     token: String,
+    retry_after: Duration,
 }
 
 impl AgentApi {
@@ -72,11 +157,29 @@ impl AgentApi {
         AgentApi {
             url: url.to_string(),
             token: token.to_string(),
+            retry_after: Duration::from_secs(RETRY_AFTER),
         }
     }
 
+    pub(super) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Wait less before retrying the requests, to avoid slowing down the tests.
+    #[cfg(test)]
+    pub(super) fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
     fn build_request(&self, method: Method, url: &str) -> RequestBuilder {
-        utils::http::prepare_sync(method, &format!("{}/agent-api/{}", self.url, url)).header(
+        // The authorization token is not logged
+        debug!("sending {} request to {}", method, url);
+        utils::http::prepare_sync(
+            method,
+            &format!("{}/agent-api/v{}/{}", self.url, AGENT_API_VERSION, url),
+        )
+        .header(
             AUTHORIZATION,
             (CraterToken {
                 token: self.token.clone(),
@@ -86,7 +189,9 @@ impl AgentApi {
     }
 
     fn retry<T, F: Fn(&Self) -> Fallible<T>>(&self, f: F) -> Fallible<T> {
+        let mut attempt = 0;
         loop {
+            attempt += 1;
             match f(self) {
                 Ok(res) => return Ok(res),
                 Err(err) => {
@@ -109,8 +214,12 @@ impl AgentApi {
                     };
 
                     if retry {
-                        warn!("connection to the server failed. retrying in a few seconds...");
-                        ::std::thread::sleep(::std::time::Duration::from_secs(RETRY_AFTER));
+                        log_fields!(warn, "connection to the server failed, retrying";
+                            "attempt" => attempt,
+                            "retry_after_ms" => utils::duration_millis(self.retry_after),
+                            "error" => err,
+                        );
+                        ::std::thread::sleep(self.retry_after);
                         continue;
                     }
 
@@ -129,17 +238,21 @@ impl AgentApi {
     }
 
     pub fn next_experiment(&self) -> Fallible<Experiment> {
-        self.retry(|this| loop {
-            let resp: Option<_> = this
-                .build_request(Method::GET, "next-experiment")
-                .send()?
-                .to_api_response()?;
-
-            if let Some(experiment) = resp {
+        loop {
+            if let Some(experiment) = self.poll_next_experiment()? {
                 return Ok(experiment);
             }
 
-            ::std::thread::sleep(::std::time::Duration::from_secs(RETRY_AFTER));
+            ::std::thread::sleep(self.retry_after);
+        }
+    }
+
+    /// Ask the server for the experiment to run, without waiting for one to be available.
+    pub(super) fn poll_next_experiment(&self) -> Fallible<Option<Experiment>> {
+        self.retry(|this| {
+            this.build_request(Method::GET, "next-experiment")
+                .send()?
+                .to_api_response()
         })
     }
 
@@ -149,23 +262,72 @@ impl AgentApi {
         toolchain: &Toolchain,
         log: &[u8],
         result: TestResult,
+        duration: Duration,
+        warnings: u32,
+        artifacts_size: Option<u64>,
+        phases: &[PhaseResult],
+        error_category: Option<ErrorCategory>,
         shas: &[(GitHubRepo, String)],
-    ) -> Fallible<()> {
+        dependencies: &[(Crate, Vec<String>)],
+        dependency_trees: &[(Crate, Vec<(String, String)>)],
+        docker_image: Option<&str>,
+        environment: Option<&EnvironmentFingerprint>,
+    ) -> Fallible<bool> {
         self.retry(|this| {
-            let _: bool = this
-                .build_request(Method::POST, "record-progress")
-                .json(&json!({
+            this.build_request(Method::POST, "record-progress")
+                .logged_json(json!({
                     "results": [
                         {
                             "crate": krate,
                             "toolchain": toolchain,
                             "result": result,
                             "log": base64::encode(log),
+                            "duration_ms": utils::duration_millis(duration),
+                            "warnings": warnings,
+                            "artifacts_size": artifacts_size,
+                            "phases": phases,
+                            "error_category": error_category,
                         },
                     ],
                     "shas": shas,
+                    "dependencies": dependencies,
+                    "dependency_trees": dependency_trees,
+                    "docker_image": docker_image,
+                    "environment": environment,
                 }))
                 .send()?
+                .to_api_response()
+        })
+    }
+
+    /// Upload a chunk of the log of a build that's still running. This is best-effort, so it's
+    /// not retried if the server is unavailable.
+    pub fn record_progress_partial(
+        &self,
+        krate: &Crate,
+        toolchain: &Toolchain,
+        chunk: usize,
+        log: &[u8],
+    ) -> Fallible<()> {
+        let _: bool = self
+            .build_request(Method::POST, "record-progress-partial")
+            .logged_json(json!({
+                "crate": krate,
+                "toolchain": toolchain,
+                "chunk": chunk,
+                "log": base64::encode(log),
+            }))
+            .send()?
+            .to_api_response()?;
+        Ok(())
+    }
+
+    pub fn record_broken_toolchain(&self, toolchain: &Toolchain) -> Fallible<()> {
+        self.retry(|this| {
+            let _: bool = this
+                .build_request(Method::POST, "record-broken-toolchain")
+                .logged_json(json!({ "toolchain": toolchain }))
+                .send()?
                 .to_api_response()?;
             Ok(())
         })
@@ -181,13 +343,59 @@ impl AgentApi {
         })
     }
 
-    pub fn heartbeat(&self) -> Fallible<()> {
+    pub fn heartbeat(&self, host: Option<&HostInfo>, unhealthy: Option<&str>) -> Fallible<()> {
         self.retry(|this| {
             let _: bool = this
                 .build_request(Method::POST, "heartbeat")
+                .logged_json(json!({ "host": host, "unhealthy": unhealthy }))
                 .send()?
                 .to_api_response()?;
             Ok(())
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::loggable_body;
+    use crate::config::Config;
+    use crate::server::api_types::AgentConfig;
+    use serde_json::{self, json};
+
+    #[test]
+    fn test_loggable_body() {
+        let body = json!({
+            "agent": "agent-1",
+            "Token": "foo",
+            "results": [{"log": "a".repeat(1000), "result": "test-pass"}],
+            "config": {"webhooks-secret": {"nested": "bar"}},
+        });
+        assert_eq!(
+            loggable_body(body),
+            json!({
+                "agent": "agent-1",
+                "Token": "[redacted]",
+                "results": [{"log": "[1000 bytes]", "result": "test-pass"}],
+                "config": {"webhooks-secret": "[redacted]"},
+            })
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_config_without_api_version() {
+        let mut config = serde_json::to_value(AgentConfig {
+            agent_name: "agent-1".into(),
+            crater_config: Config::default(),
+            api_version: Some(1),
+            agent_update_url: None,
+            agent_update_sha256: None,
+        })
+        .unwrap();
+
+        // Servers predating the versioned API don't send it
+        config.as_object_mut().unwrap().remove("api-version");
+        let config: AgentConfig = serde_json::from_value(config).unwrap();
+        assert_eq!(config.api_version, None);
+    }
+}
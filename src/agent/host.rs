@@ -0,0 +1,70 @@
+use crate::prelude::*;
+use crate::run::RunCommand;
+use crate::server::api_types::HostInfo;
+use crate::utils;
+use std::fs;
+
+fn capture(what: &str, cmd: RunCommand) -> Option<String> {
+    match cmd.run_capture() {
+        Ok((stdout, _)) => Some(stdout.join("\n").trim().to_string()),
+        Err(err) => {
+            warn!("failed to detect the {} of the host", what);
+            utils::report_failure(&err);
+            None
+        }
+    }
+}
+
+/// Extract the `PRETTY_NAME` field from the content of `/etc/os-release`.
+fn os_release_name(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find(|line| line.starts_with("PRETTY_NAME="))
+        .map(|line| {
+            line["PRETTY_NAME=".len()..]
+                .trim()
+                .trim_matches('"')
+                .to_string()
+        })
+        .filter(|name| !name.is_empty())
+}
+
+/// Describe the host the agent is running on. Errors are logged and the related fields are left
+/// empty, as they shouldn't prevent the agent from starting.
+pub(super) fn detect() -> HostInfo {
+    let os = fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|content| os_release_name(&content))
+        .unwrap_or_else(|| ::std::env::consts::OS.to_string());
+
+    HostInfo {
+        os,
+        kernel: capture("kernel version", RunCommand::new("uname").args(&["-r"])),
+        docker: capture(
+            "docker version",
+            RunCommand::new("docker").args(&["version", "--format", "{{.Server.Version}}"]),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::os_release_name;
+
+    #[test]
+    fn test_os_release_name() {
+        let content = concat!(
+            "NAME=\"Ubuntu\"\n",
+            "VERSION=\"18.04.1 LTS (Bionic Beaver)\"\n",
+            "PRETTY_NAME=\"Ubuntu 18.04.1 LTS\"\n",
+            "VERSION_ID=\"18.04\"\n",
+        );
+        assert_eq!(
+            os_release_name(content),
+            Some("Ubuntu 18.04.1 LTS".to_string())
+        );
+
+        assert_eq!(os_release_name("NAME=Arch\n"), None);
+        assert_eq!(os_release_name("PRETTY_NAME=\"\"\n"), None);
+    }
+}
@@ -1,25 +1,64 @@
 mod api;
+mod host;
 mod results;
+mod update;
 
 use crate::agent::api::AgentApi;
+use crate::agent::results::ResultsUploader;
 use crate::config::Config;
+use crate::docker::DockerEnv;
 use crate::experiments::Experiment;
 use crate::prelude::*;
+use crate::results::WriteResults;
+use crate::server::api_types::AGENT_API_VERSION;
 use crate::utils;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+/// Runs the experiments assigned to the agent, uploading the results as they are recorded.
+trait ExperimentRunner {
+    fn run(&self, ex: &Experiment, db: &ResultsUploader, config: &Config) -> Fallible<()>;
+}
+
+/// Builds the crates in the docker environment.
+struct DockerRunner<'a> {
+    threads_count: usize,
+    docker_env: &'a str,
+}
+
+impl<'a> ExperimentRunner for DockerRunner<'a> {
+    fn run(&self, ex: &Experiment, db: &ResultsUploader, config: &Config) -> Fallible<()> {
+        crate::runner::run_ex(ex, db, self.threads_count, config, self.docker_env)
+    }
+}
+
 struct Agent {
     api: AgentApi,
     config: Config,
+    /// Why the agent can't run experiments right now, if it can't.
+    unhealthy: Arc<Mutex<Option<String>>>,
 }
 
 impl Agent {
     fn new(url: &str, token: &str) -> Fallible<Self> {
-        info!("connecting to crater server {}...", url);
+        Agent::connect(AgentApi::new(url, token))
+    }
+
+    fn connect(api: AgentApi) -> Fallible<Self> {
+        info!("connecting to crater server {}...", api.url());
 
-        let api = AgentApi::new(url, token);
         let config = api.config()?;
+        match config.api_version {
+            Some(version) if version < AGENT_API_VERSION => bail!(
+                "the crater server supports version {} of the agent API, but version {} is required",
+                version,
+                AGENT_API_VERSION
+            ),
+            Some(_) => {}
+            // Servers predating the versioned API don't report it
+            None => warn!("the crater server didn't report the version of the agent API"),
+        }
 
         info!("connected to the crater server!");
         info!("assigned agent name: {}", config.agent_name);
@@ -27,35 +66,446 @@ impl Agent {
         Ok(Agent {
             api,
             config: config.crater_config,
+            unhealthy: Arc::new(Mutex::new(None)),
         })
     }
 
+    /// Replace the agent with the binary released by the server, if any. Failing to update
+    /// isn't fatal, as the current binary can keep running experiments.
+    fn update(&self) {
+        let res = self
+            .api
+            .config()
+            .and_then(|config| update::apply(&config))
+            .with_context(|_| "failed to update the agent");
+        if let Err(err) = res {
+            utils::report_failure(&err);
+        }
+    }
+
     fn experiment(&self) -> Fallible<Experiment> {
         info!("asking the server for a new experiment...");
         Ok(self.api.next_experiment()?)
     }
+
+    /// Run the next experiment assigned by the server, and tell the server once it's completed.
+    fn run_next<R: ExperimentRunner>(&self, runner: &R) -> Fallible<()> {
+        let ex = self.experiment()?;
+        let db = ResultsUploader::new(&self.api, self.unhealthy.clone());
+        runner.run(&ex, &db, &self.config)?;
+
+        // Experiments stopped early by the server are already marked as completed
+        if !db.stop_requested() {
+            self.api.complete_experiment()?;
+        }
+        Ok(())
+    }
 }
 
-fn run_heartbeat(url: &str, token: &str) {
+fn run_heartbeat(url: &str, token: &str, unhealthy: Arc<Mutex<Option<String>>>) {
     let api = AgentApi::new(url, token);
+    // The host environment doesn't change while the agent is running, so it's only sent until the
+    // server records it
+    let mut host = Some(host::detect());
 
     thread::spawn(move || loop {
-        if let Err(e) = api.heartbeat().with_context(|_| "failed to send heartbeat") {
-            utils::report_failure(&e);
+        let unhealthy = unhealthy.lock().unwrap().clone();
+        match api
+            .heartbeat(host.as_ref(), unhealthy.as_ref().map(|s| s.as_str()))
+            .with_context(|_| "failed to send heartbeat")
+        {
+            Ok(()) => host = None,
+            Err(e) => utils::report_failure(&e),
         }
         thread::sleep(Duration::from_secs(60));
     });
 }
 
-pub fn run(url: &str, token: &str, threads_count: usize, docker_env: &str) -> Fallible<()> {
+/// Refuse to start if the environment the crates are built in is not available, instead of
+/// accepting experiments where every crate would fail with the same error.
+fn check_docker_env(docker_env: &str) -> Fallible<()> {
+    if !crate::docker::is_running() {
+        bail!("docker is not running");
+    }
+
+    DockerEnv::new(docker_env)
+        .ensure_exists_locally()
+        .with_context(|_| {
+            format!(
+                "the docker environment {} is not available: check the name of the image, or \
+                 pass --skip-docker-env-check to start the agent anyway",
+                docker_env
+            )
+        })?;
+    Ok(())
+}
+
+pub fn run(
+    url: &str,
+    token: &str,
+    threads_count: usize,
+    docker_env: &str,
+    auto_update: bool,
+    docker_env_check: bool,
+) -> Fallible<()> {
+    if docker_env_check {
+        check_docker_env(docker_env)?;
+    }
+
     let agent = Agent::new(url, token)?;
-    let db = results::ResultsUploader::new(&agent.api);
+    let runner = DockerRunner {
+        threads_count,
+        docker_env,
+    };
 
-    run_heartbeat(url, token);
+    run_heartbeat(url, token, agent.unhealthy.clone());
 
     loop {
-        let ex = agent.experiment()?;
-        crate::runner::run_ex(&ex, &db, threads_count, &agent.config, docker_env)?;
-        agent.api.complete_experiment()?;
+        // Updates only happen between experiments, to avoid interrupting one
+        if auto_update {
+            agent.update();
+        }
+
+        agent.run_next(&runner)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::api::AgentApi;
+    use super::results::ResultsUploader;
+    use super::{Agent, ExperimentRunner};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::db::{Database, QueryUtils, BUSY_TIMEOUT};
+    use crate::experiments::{Assignee, Experiment, Status};
+    use crate::prelude::*;
+    use crate::results::{DatabaseDB, ReadResults, TestResult, WriteResults};
+    use crate::server::testing::TestServer;
+    use crate::server::tokens::Tokens;
+    use crate::toolchain::MAIN_TOOLCHAIN;
+    use crate::utils;
+    use http::{header::AUTHORIZATION, Method, StatusCode};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Barrier, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Records a passing result for each crate without building anything. The hook is called
+    /// before each build with the number of builds already recorded, and can fail to simulate a
+    /// crash of the agent.
+    struct StubRunner<F: Fn(usize) -> Fallible<()>> {
+        before_build: F,
+        builds: AtomicUsize,
+    }
+
+    impl<F: Fn(usize) -> Fallible<()>> StubRunner<F> {
+        fn new(before_build: F) -> Self {
+            StubRunner {
+                before_build,
+                builds: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl<F: Fn(usize) -> Fallible<()>> ExperimentRunner for StubRunner<F> {
+        fn run(&self, ex: &Experiment, db: &ResultsUploader, config: &Config) -> Fallible<()> {
+            for krate in &ex.crates {
+                for tc in &ex.build_toolchains() {
+                    (self.before_build)(self.builds.load(Ordering::SeqCst))?;
+                    db.record_result(ex, tc, krate, None, config, || {
+                        info!("building {} with {}", krate, tc);
+                        Ok(TestResult::TestPass)
+                    })?;
+                    self.builds.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn no_hook(_builds: usize) -> Fallible<()> {
+        Ok(())
+    }
+
+    struct Harness {
+        db: Database,
+        config: Config,
+        server: Arc<Mutex<TestServer>>,
+        url: String,
+    }
+
+    impl Harness {
+        /// Start a server with a queued experiment, and two agents allowed to connect to it.
+        fn new() -> Self {
+            crate::logs::init_test();
+            let db = Database::temp().unwrap();
+            let config = Config::default();
+            crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+            CreateExperiment::dummy("dummy")
+                .apply(&ActionsCtx::new(&db, &config))
+                .unwrap();
+
+            let mut tokens = Tokens::default();
+            tokens.agents.insert("token1".into(), "agent-1".into());
+            tokens.agents.insert("token2".into(), "agent-2".into());
+            let server = TestServer::start(config.clone(), db.clone(), tokens).unwrap();
+            let url = server.url();
+
+            Harness {
+                db,
+                config,
+                server: Arc::new(Mutex::new(server)),
+                url,
+            }
+        }
+
+        fn api(&self, token: &str) -> AgentApi {
+            AgentApi::new(&self.url, token).retry_after(Duration::from_millis(50))
+        }
+
+        fn agent(&self, token: &str) -> Agent {
+            Agent::connect(self.api(token)).unwrap()
+        }
+
+        fn experiment(&self) -> Experiment {
+            Experiment::get(&self.db, "dummy").unwrap().unwrap()
+        }
+
+        fn builds(&self) -> usize {
+            let ex = self.experiment();
+            ex.crates.len() * ex.build_toolchains().len()
+        }
+
+        /// Check every crate was built with every toolchain, and the agent completed the
+        /// experiment.
+        fn assert_completed(&self, agent: &str) {
+            let ex = self.experiment();
+            assert_eq!(ex.status, Status::NeedsReport);
+            assert_eq!(ex.assigned_to, Some(Assignee::Agent(agent.into())));
+
+            let results = DatabaseDB::new(&self.db);
+            for krate in &ex.crates {
+                for tc in &ex.build_toolchains() {
+                    assert_eq!(
+                        results.load_test_result(&ex, tc, krate).unwrap(),
+                        Some(TestResult::TestPass)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_experiment() {
+        let harness = Harness::new();
+        let runner = StubRunner::new(no_hook);
+        harness.agent("token1").run_next(&runner).unwrap();
+
+        assert_eq!(runner.builds.load(Ordering::SeqCst), harness.builds());
+        harness.assert_completed("agent-1");
+        // Nothing is left for the other agents
+        assert!(harness
+            .api("token2")
+            .poll_next_experiment()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_agent_restart() {
+        let harness = Harness::new();
+        let builds = harness.builds();
+        assert!(builds > 4);
+
+        // Crash after uploading the results of the first two crates
+        let crashing = StubRunner::new(|builds| {
+            if builds == 4 {
+                bail!("the agent crashed");
+            }
+            Ok(())
+        });
+        assert!(harness.agent("token1").run_next(&crashing).is_err());
+        assert_eq!(harness.experiment().status, Status::Running);
+
+        // The restarted agent resumes the experiment, building only the remaining crates
+        let runner = StubRunner::new(no_hook);
+        harness.agent("token1").run_next(&runner).unwrap();
+        assert_eq!(runner.builds.load(Ordering::SeqCst), builds - 4);
+        harness.assert_completed("agent-1");
+    }
+
+    #[test]
+    fn test_server_restart() {
+        let harness = Harness::new();
+
+        // The server goes away while the agent is building, and comes back while the agent is
+        // retrying to upload the results
+        let server = harness.server.clone();
+        let runner = StubRunner::new(move |builds| {
+            if builds == 1 {
+                server.lock().unwrap().stop();
+                let server = server.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(500));
+                    server.lock().unwrap().restart().unwrap();
+                });
+            }
+            Ok(())
+        });
+        harness.agent("token1").run_next(&runner).unwrap();
+
+        assert_eq!(runner.builds.load(Ordering::SeqCst), harness.builds());
+        harness.assert_completed("agent-1");
+    }
+
+    #[test]
+    fn test_competing_agents() {
+        let harness = Harness::new();
+
+        // Both agents ask for the only experiment at the same time
+        let barrier = Arc::new(Barrier::new(2));
+        let polls = ["token1", "token2"]
+            .iter()
+            .map(|token| {
+                let api = harness.api(token);
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    api.poll_next_experiment().unwrap()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|poll| poll.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(polls.iter().filter(|ex| ex.is_some()).count(), 1);
+        let ((winner, winner_name), loser) = if polls[0].is_some() {
+            (("token1", "agent-1"), "token2")
+        } else {
+            (("token2", "agent-2"), "token1")
+        };
+
+        // The other agent can't upload results for the experiment
+        let loser = harness.agent(loser);
+        let db = ResultsUploader::new(&loser.api, loser.unhealthy.clone());
+        assert!(StubRunner::new(no_hook)
+            .run(&harness.experiment(), &db, &harness.config)
+            .is_err());
+
+        harness
+            .agent(winner)
+            .run_next(&StubRunner::new(no_hook))
+            .unwrap();
+        harness.assert_completed(winner_name);
+    }
+
+    #[test]
+    fn test_concurrent_uploads() {
+        const THREADS: usize = 8;
+        const CRATES: usize = 20;
+
+        let harness = Harness::new();
+        let ex = harness
+            .api("token1")
+            .poll_next_experiment()
+            .unwrap()
+            .unwrap();
+        let krate = |i: usize| {
+            Crate::Registry(RegistryCrate {
+                name: format!("crate-{}", i),
+                version: "1".into(),
+            })
+        };
+        let shas = vec![(
+            GitHubRepo {
+                org: "foo".into(),
+                name: "bar".into(),
+            },
+            "42".to_string(),
+        )];
+
+        // Another connection holds the write lock for longer than the busy timeout, as a slow
+        // transaction would
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let db = harness.db.clone();
+        let locker = thread::spawn(move || {
+            db.transaction(|_| {
+                locked_tx.send(()).unwrap();
+                thread::sleep(BUSY_TIMEOUT * 3);
+                Ok(())
+            })
+            .unwrap();
+        });
+        locked_rx.recv().unwrap();
+
+        // The server asks the agents to retry the upload later instead of failing it
+        let resp = utils::http::prepare_sync(
+            Method::POST,
+            &format!("{}/agent-api/v1/record-progress", harness.url),
+        )
+        .header(AUTHORIZATION, "CraterToken token1")
+        .json(&json!({ "results": [], "shas": [] }))
+        .send()
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Every thread uploads the results of all the crates, as agents re-sending their
+        // requests would do, starting while the database is still locked
+        let threads = (0..THREADS)
+            .map(|_| {
+                let api = harness.api("token1");
+                let shas = shas.clone();
+                thread::spawn(move || {
+                    for i in 0..CRATES {
+                        api.record_progress(
+                            &krate(i),
+                            &MAIN_TOOLCHAIN,
+                            format!("log {}", i).as_bytes(),
+                            TestResult::TestPass,
+                            Duration::from_millis(10),
+                            0,
+                            None,
+                            &[],
+                            None,
+                            &shas,
+                            &[],
+                            &[],
+                            None,
+                            None,
+                        )
+                        .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        locker.join().unwrap();
+
+        // No result was lost or recorded twice
+        let count = |table: &str| -> i64 {
+            harness
+                .db
+                .get_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE experiment = ?1;", table),
+                    &[&ex.name],
+                    |row| row.get(0),
+                )
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(count("results"), CRATES as i64);
+        assert_eq!(count("shas"), 1);
+        let results = DatabaseDB::new(&harness.db);
+        for i in 0..CRATES {
+            assert_eq!(
+                results.load_log(&ex, &MAIN_TOOLCHAIN, &krate(i)).unwrap(),
+                Some(format!("log {}", i).into_bytes())
+            );
+        }
     }
 }
@@ -4,23 +4,107 @@ use crate::crates::{Crate, GitHubRepo};
 use crate::experiments::Experiment;
 use crate::logs::{self, LogStorage};
 use crate::prelude::*;
-use crate::results::{TestResult, WriteResults};
+use crate::results::{EnvironmentFingerprint, Phase, PhaseResult, TestResult, WriteResults};
+use crate::runner::{classify_error, count_warnings};
 use crate::toolchain::Toolchain;
+use crossbeam_utils::thread::scope;
 use log::LevelFilter;
+use std::collections::HashMap;
 use std::ops::DerefMut;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Clone)]
 pub struct ResultsUploader<'a> {
     api: &'a AgentApi,
     shas: Arc<Mutex<Vec<(GitHubRepo, String)>>>,
+    dependencies: Arc<Mutex<Vec<(Crate, Vec<String>)>>>,
+    dependency_trees: Arc<Mutex<Vec<(Crate, Vec<(String, String)>)>>>,
+    docker_image: Arc<Mutex<Option<String>>>,
+    environment: Arc<Mutex<Option<EnvironmentFingerprint>>>,
+    artifacts_sizes: Arc<Mutex<HashMap<(Crate, Toolchain), u64>>>,
+    phases: Arc<Mutex<HashMap<(Crate, Toolchain), Vec<PhaseResult>>>>,
+    stopped: Arc<AtomicBool>,
+    /// Sent to the server with the heartbeats.
+    unhealthy: Arc<Mutex<Option<String>>>,
 }
 
 impl<'a> ResultsUploader<'a> {
-    pub fn new(api: &'a AgentApi) -> Self {
+    pub fn new(api: &'a AgentApi, unhealthy: Arc<Mutex<Option<String>>>) -> Self {
         ResultsUploader {
             api,
             shas: Arc::new(Mutex::new(Vec::new())),
+            dependencies: Arc::new(Mutex::new(Vec::new())),
+            dependency_trees: Arc::new(Mutex::new(Vec::new())),
+            docker_image: Arc::new(Mutex::new(None)),
+            environment: Arc::new(Mutex::new(None)),
+            artifacts_sizes: Arc::new(Mutex::new(HashMap::new())),
+            phases: Arc::new(Mutex::new(HashMap::new())),
+            stopped: Arc::new(AtomicBool::new(false)),
+            unhealthy,
+        }
+    }
+}
+
+impl<'a> ResultsUploader<'a> {
+    fn capture_streaming<F>(
+        &self,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        storage: &LogStorage,
+        config: &Config,
+        f: F,
+    ) -> Fallible<TestResult>
+    where
+        F: FnOnce() -> Fallible<TestResult>,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        scope(|scope| {
+            scope.spawn(|| self.stream_logs(toolchain, krate, storage, config, stopped));
+
+            let result = logs::capture(storage, f);
+            // Dropping the sender wakes up the streaming thread, which then exits
+            drop(stop);
+            result
+        })
+    }
+
+    fn stream_logs(
+        &self,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        storage: &LogStorage,
+        config: &Config,
+        stopped: mpsc::Receiver<()>,
+    ) {
+        let interval = Duration::from_secs(config.partial_logs.interval);
+        let max_chunk_size = config.partial_logs.max_chunk_size.to_bytes();
+
+        let mut offset = 0;
+        let mut chunk = 0;
+        while chunk < config.partial_logs.max_chunks {
+            match stopped.recv_timeout(interval) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+
+            let (content, next) = storage.read_since(offset, max_chunk_size);
+            if content.is_empty() {
+                continue;
+            }
+
+            let res = self
+                .api
+                .record_progress_partial(krate, toolchain, chunk, content.as_bytes());
+            if let Err(err) = res {
+                warn!("failed to upload partial logs: {}", err);
+                continue;
+            }
+
+            offset = next;
+            chunk += 1;
         }
     }
 }
@@ -44,6 +128,90 @@ impl<'a> WriteResults for ResultsUploader<'a> {
         Ok(())
     }
 
+    fn record_dependencies(
+        &self,
+        _ex: &Experiment,
+        krate: &Crate,
+        dependencies: &[String],
+    ) -> Fallible<()> {
+        self.dependencies
+            .lock()
+            .unwrap()
+            .push((krate.clone(), dependencies.to_vec()));
+        Ok(())
+    }
+
+    fn record_dependency_tree(
+        &self,
+        _ex: &Experiment,
+        krate: &Crate,
+        edges: &[(String, String)],
+    ) -> Fallible<()> {
+        self.dependency_trees
+            .lock()
+            .unwrap()
+            .push((krate.clone(), edges.to_vec()));
+        Ok(())
+    }
+
+    fn record_docker_image(&self, _ex: &Experiment, digest: &str) -> Fallible<()> {
+        *self.docker_image.lock().unwrap() = Some(digest.to_string());
+        Ok(())
+    }
+
+    fn record_environment(&self, _ex: &Experiment, env: &EnvironmentFingerprint) -> Fallible<()> {
+        *self.environment.lock().unwrap() = Some(env.clone());
+        Ok(())
+    }
+
+    fn record_broken_toolchain(&self, _ex: &Experiment, toolchain: &Toolchain) -> Fallible<()> {
+        self.api.record_broken_toolchain(toolchain)
+    }
+
+    fn record_artifacts_size(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        size: u64,
+    ) -> Fallible<()> {
+        self.artifacts_sizes
+            .lock()
+            .unwrap()
+            .insert((krate.clone(), toolchain.clone()), size);
+        Ok(())
+    }
+
+    fn record_phase(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        phase: Phase,
+        result: TestResult,
+        log: &[u8],
+    ) -> Fallible<()> {
+        self.phases
+            .lock()
+            .unwrap()
+            .entry((krate.clone(), toolchain.clone()))
+            .or_insert_with(Vec::new)
+            .push(PhaseResult {
+                phase,
+                result,
+                log: base64::encode(log),
+            });
+        Ok(())
+    }
+
+    fn stop_requested(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    fn set_unhealthy(&self, reason: Option<String>) {
+        *self.unhealthy.lock().unwrap() = reason;
+    }
+
     fn record_result<F>(
         &self,
         _ex: &Experiment,
@@ -57,14 +225,62 @@ impl<'a> WriteResults for ResultsUploader<'a> {
         F: FnOnce() -> Fallible<TestResult>,
     {
         let storage = existing_logs.unwrap_or_else(|| LogStorage::new(LevelFilter::Info, config));
-        let result = logs::capture(&storage, f)?;
+        let start = Instant::now();
+        let result = if config.partial_logs.enabled {
+            self.capture_streaming(toolchain, krate, &storage, config, f)?
+        } else {
+            logs::capture(&storage, f)?
+        };
+        let duration = start.elapsed();
         let output = storage.to_string();
 
         let shas = ::std::mem::replace(self.shas.lock().unwrap().deref_mut(), Vec::new());
+        let dependencies =
+            ::std::mem::replace(self.dependencies.lock().unwrap().deref_mut(), Vec::new());
+        let dependency_trees = ::std::mem::replace(
+            self.dependency_trees.lock().unwrap().deref_mut(),
+            Vec::new(),
+        );
+        let docker_image = self.docker_image.lock().unwrap().take();
+        let environment = self.environment.lock().unwrap().take();
+        let artifacts_size = self
+            .artifacts_sizes
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()));
+        let phases = self
+            .phases
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()))
+            .unwrap_or_default();
+
+        // The server doesn't accept results anymore after stopping the experiment
+        if self.stop_requested() {
+            return Ok(result);
+        }
 
         info!("sending results to the crater server...");
-        self.api
-            .record_progress(krate, toolchain, output.as_bytes(), result, &shas)?;
+        let keep_running = self.api.record_progress(
+            krate,
+            toolchain,
+            output.as_bytes(),
+            result,
+            duration,
+            count_warnings(&output),
+            artifacts_size,
+            &phases,
+            classify_error(result, &output),
+            &shas,
+            &dependencies,
+            &dependency_trees,
+            docker_image.as_ref().map(|s| s.as_str()),
+            environment.as_ref(),
+        )?;
+        if !keep_running {
+            info!("the crater server stopped the experiment early");
+            self.stopped.store(true, Ordering::SeqCst);
+        }
 
         Ok(result)
     }
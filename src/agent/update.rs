@@ -0,0 +1,102 @@
+use crate::native;
+use crate::prelude::*;
+use crate::server::api_types::AgentConfig;
+use crate::utils;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+fn sha256(content: &[u8]) -> String {
+    utils::hex::to_hex(ring::digest::digest(&ring::digest::SHA256, content).as_ref())
+}
+
+fn verify(content: &[u8], expected: &str) -> Fallible<()> {
+    let actual = sha256(content);
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        bail!(
+            "the checksum of the agent binary is {}, but {} was expected",
+            actual,
+            expected
+        );
+    }
+    Ok(())
+}
+
+/// Atomically replace `dest` with `content`, which is safe even if `dest` is running.
+fn install(content: &[u8], dest: &Path) -> Fallible<()> {
+    let parent = dest
+        .parent()
+        .ok_or_else(|| err_msg("the agent binary has no parent directory"))?;
+    let file = NamedTempFile::new_in(parent)?;
+    fs::write(file.path(), content)?;
+    native::make_executable(file.path())?;
+    file.persist(dest)?;
+    Ok(())
+}
+
+/// Replace the agent binary with the one released by the server, if it's different from the
+/// current one. Returns `false` if there is nothing to update.
+fn update(url: &str, expected: &str, dest: &Path) -> Fallible<bool> {
+    if verify(&fs::read(dest)?, expected).is_ok() {
+        return Ok(false);
+    }
+
+    info!("downloading the new agent binary from {}...", url);
+    let mut content = Vec::new();
+    utils::http::get_sync(url)?.read_to_end(&mut content)?;
+    verify(&content, expected)?;
+
+    install(&content, dest)?;
+    Ok(true)
+}
+
+/// Update the agent and restart it with the same arguments, if the server released a new binary.
+/// Returns only if there was nothing to update.
+pub(super) fn apply(config: &AgentConfig) -> Fallible<()> {
+    let url = match config.agent_update_url {
+        Some(ref url) => url,
+        None => return Ok(()),
+    };
+    let expected = config
+        .agent_update_sha256
+        .as_ref()
+        .ok_or_else(|| err_msg("the server didn't send the checksum of the agent binary"))?;
+
+    let current = env::current_exe()?;
+    if !update(url, expected, &current)? {
+        return Ok(());
+    }
+
+    info!("agent updated, restarting it...");
+    let args = env::args_os().skip(1).collect::<Vec<_>>();
+    let err = native::exec(&current, &args);
+    Err(err.context("failed to restart the agent").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{install, sha256, update, verify};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_verify_and_install() {
+        let new = b"new agent binary";
+        let checksum = sha256(new);
+        assert!(verify(new, &checksum).is_ok());
+        assert!(verify(new, &checksum.to_uppercase()).is_ok());
+        assert!(verify(b"tampered agent binary", &checksum).is_err());
+
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("crater");
+        fs::write(&dest, b"old agent binary").unwrap();
+        install(new, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), new);
+        assert!(crate::native::is_executable(&dest).unwrap());
+
+        // Agents already running the released binary don't download it again
+        assert!(!update("http://127.0.0.1:1/crater", &checksum, &dest).unwrap());
+    }
+}
@@ -61,16 +61,22 @@ load_files! {
         "ui/layout.html",
 
         "ui/agents.html",
+        "ui/usage.html",
+        "ui/crate.html",
 
         "ui/queue.html",
         "ui/experiment.html",
+        "ui/log.html",
 
         "ui/404.html",
         "ui/500.html",
 
         "report/layout.html",
         "report/downloads.html",
+        "report/index.html",
         "report/results.html",
+        "report/diff.html",
+        "report/diff.md",
     ],
     assets: [
         "ui.css" => mime::TEXT_CSS,
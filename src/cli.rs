@@ -9,24 +9,47 @@
 //! application state employs ownership techniques to ensure that
 //! parallel access is consistent and race-free.
 
+mod agents;
+mod completions;
+mod create_experiment;
+mod experiments;
+pub mod output;
+mod progress;
+mod reports;
+mod server_api;
+
+use crate::cli::output::Output;
+use crate::cli::server_api::ServerApi;
+use chrono::{NaiveDate, TimeZone, Utc};
 use crater::actions::{self, Action, ActionsCtx};
 use crater::agent;
 use crater::config::Config;
-use crater::crates::Crate;
+use crater::crates::{Crate, RegistryCrate};
 use crater::db::Database;
-use crater::experiments::{Assignee, CapLints, CrateSelect, Experiment, Mode, Status};
-use crater::report;
-use crater::results::{DatabaseDB, DeleteResults};
+use crater::experiments::{
+    Assignee, CapLints, CargoPatch, CrateSelect, Experiment, FeatureSet, Mode, Status, TestRunner,
+};
+use crater::logs::LogFormat;
+use crater::report::{self, diff::DiffCategory, Comparison, ReportSummary};
+use crater::results::export::{self, ExportFormat};
+use crater::results::{DatabaseDB, DeleteResults, TestResult};
 use crater::runner;
 use crater::server;
 use crater::toolchain::Toolchain;
 use failure::{bail, Error, Fallible};
+use log::{info, warn};
+use serde_json::json;
 use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter};
 use std::path::PathBuf;
 use std::str::FromStr;
-use structopt::clap::AppSettings;
+use std::time::Duration;
+use structopt::clap::{AppSettings, Shell};
 
 static DEFAULT_DOCKER_ENV: &str = "rustops/crates-build-env";
+/// Results loaded at once by `export`, between two updates of the progress bar.
+const EXPORT_BATCH_SIZE: u32 = 1000;
 
 // An experiment name
 #[derive(Debug, Clone)]
@@ -71,6 +94,44 @@ impl FromStr for Dest {
         setting = "AppSettings::SubcommandRequiredElseHelp"
     )
 )]
+pub struct Cli {
+    #[structopt(
+        name = "json",
+        long = "json",
+        help = "print the output as JSON, one object per line for lists, and the errors as JSON",
+        raw(global = "true")
+    )]
+    json: bool,
+    #[structopt(subcommand)]
+    cmd: Crater,
+}
+
+impl Cli {
+    pub fn output(&self) -> Output {
+        Output::new(self.json)
+    }
+
+    /// Whether only the errors should be logged.
+    pub fn quiet(&self) -> bool {
+        match self.cmd {
+            Crater::Agent { quiet, .. } => quiet,
+            _ => false,
+        }
+    }
+
+    pub fn log_format(&self) -> LogFormat {
+        match self.cmd {
+            Crater::Server { log_format, .. } | Crater::Agent { log_format, .. } => log_format,
+            _ => LogFormat::Text,
+        }
+    }
+
+    pub fn run(&self) -> Fallible<()> {
+        self.cmd.run(self.output())
+    }
+}
+
+#[derive(structopt_derive::StructOpt)]
 pub enum Crater {
     #[structopt(
         name = "prepare-local",
@@ -88,10 +149,18 @@ pub enum Crater {
     DefineEx {
         #[structopt(name = "experiment", long = "ex", default_value = "default")]
         ex: Ex,
-        #[structopt(name = "tc-1")]
-        tc1: Toolchain,
-        #[structopt(name = "tc-2")]
-        tc2: Toolchain,
+        #[structopt(name = "tc-1", required_unless = "from-file")]
+        tc1: Option<Toolchain>,
+        #[structopt(name = "tc-2", required_unless = "from-file")]
+        tc2: Option<Toolchain>,
+        #[structopt(
+            name = "from-file",
+            long = "from-file",
+            parse(from_os_str),
+            raw(conflicts_with_all = r#"&["tc-1", "tc-2"]"#),
+            help = "read the experiment from a TOML or JSON file written by dump-ex"
+        )]
+        from_file: Option<PathBuf>,
         #[structopt(
             name = "mode",
             long = "mode",
@@ -110,6 +179,21 @@ pub enum Crater {
             )
         )]
         crates: CrateSelect,
+        #[structopt(
+            name = "pin",
+            long = "pin",
+            raw(number_of_values = "1"),
+            help = "test an exact version of a crates.io crate (name@version), not the selection"
+        )]
+        pinned_crates: Vec<RegistryCrate>,
+        #[structopt(
+            name = "crate-list",
+            long = "crate-list",
+            parse(from_os_str),
+            conflicts_with = "from-file",
+            help = "file listing the crates to test, one per line, instead of the selection"
+        )]
+        crate_list: Option<PathBuf>,
         #[structopt(
             name = "level",
             long = "cap-lints",
@@ -123,6 +207,118 @@ pub enum Crater {
         priority: i32,
         #[structopt(name = "ignore-blacklist", long = "ignore-blacklist")]
         ignore_blacklist: bool,
+        #[structopt(name = "fail-fast", long = "fail-fast")]
+        fail_fast: bool,
+        #[structopt(
+            name = "feature-set",
+            long = "feature-set",
+            raw(number_of_values = "1")
+        )]
+        feature_sets: Vec<FeatureSet>,
+        #[structopt(name = "requested-by", long = "requested-by")]
+        requested_by: Option<String>,
+        #[structopt(name = "live-report", long = "live-report")]
+        live_report: bool,
+        #[structopt(
+            name = "cargo-patch",
+            long = "cargo-patch",
+            raw(number_of_values = "1"),
+            help = "replace a dependency with a git repository (name=git-url[#rev])"
+        )]
+        cargo_patches: Vec<CargoPatch>,
+        #[structopt(
+            name = "pre-analyze",
+            long = "pre-analyze",
+            help = "record the dependencies of each crate with cargo metadata"
+        )]
+        pre_analyze: bool,
+        #[structopt(
+            name = "private",
+            long = "private",
+            help = "publish the report only through signed, expiring links"
+        )]
+        private: bool,
+        #[structopt(
+            name = "private-reader",
+            long = "private-reader",
+            raw(number_of_values = "1"),
+            help = "name of a token allowed to request links to the private report"
+        )]
+        private_readers: Vec<String>,
+        #[structopt(
+            name = "test-runner",
+            long = "test-runner",
+            raw(possible_values = "TestRunner::possible_values()"),
+            help = "command running the tests of the crates (default: libtest)"
+        )]
+        test_runner: Option<TestRunner>,
+        #[structopt(
+            name = "measure-artifacts",
+            long = "measure-artifacts",
+            help = "record the size of the artifacts produced by building each crate"
+        )]
+        measure_artifacts: bool,
+        #[structopt(
+            name = "silent",
+            long = "silent",
+            help = "don't post anything about the experiment on GitHub until it's promoted"
+        )]
+        silent: bool,
+        #[structopt(
+            name = "allow-duplicate",
+            long = "allow-duplicate",
+            help = "create the experiment even if an identical one is not completed yet"
+        )]
+        allow_duplicate: bool,
+        #[structopt(
+            name = "dry-run",
+            long = "dry-run",
+            help = "check the experiment and print it without creating it"
+        )]
+        dry_run: bool,
+    },
+
+    #[structopt(
+        name = "create-experiment",
+        about = "create an experiment, prompting for its configuration"
+    )]
+    CreateExperiment {
+        #[structopt(
+            name = "non-interactive",
+            long = "non-interactive",
+            help = "take the configuration from the arguments instead of prompting for it"
+        )]
+        non_interactive: bool,
+        #[structopt(name = "name", long = "name")]
+        name: Option<String>,
+        #[structopt(name = "toolchain-a", long = "toolchain-a")]
+        toolchain_a: Option<String>,
+        #[structopt(name = "toolchain-b", long = "toolchain-b")]
+        toolchain_b: Option<String>,
+        #[structopt(
+            name = "crates",
+            long = "crates",
+            raw(possible_values = r#"&["top", "all", "file"]"#)
+        )]
+        crates: Option<String>,
+        #[structopt(
+            name = "crate-file",
+            long = "crate-file",
+            help = "file containing the crates to test, one per line"
+        )]
+        crate_file: Option<String>,
+        #[structopt(name = "crate-limit", long = "crate-limit")]
+        crate_limit: Option<usize>,
+        #[structopt(name = "priority", long = "priority", short = "p")]
+        priority: Option<i32>,
+        #[structopt(name = "github-issue", long = "github-issue")]
+        github_issue: Option<String>,
+        #[structopt(
+            name = "assignee",
+            long = "assignee",
+            raw(possible_values = r#"&["any", "cli"]"#)
+        )]
+        assignee: Option<String>,
     },
 
     #[structopt(name = "edit", about = "edit an experiment configuration")]
@@ -165,6 +361,111 @@ pub enum Crater {
             conflicts_with = "ignore-blacklist"
         )]
         no_ignore_blacklist: bool,
+        #[structopt(
+            name = "fail-fast",
+            long = "fail-fast",
+            conflicts_with = "no-fail-fast"
+        )]
+        fail_fast: bool,
+        #[structopt(
+            name = "no-fail-fast",
+            long = "no-fail-fast",
+            conflicts_with = "fail-fast"
+        )]
+        no_fail_fast: bool,
+        #[structopt(
+            name = "live-report",
+            long = "live-report",
+            conflicts_with = "no-live-report"
+        )]
+        live_report: bool,
+        #[structopt(
+            name = "no-live-report",
+            long = "no-live-report",
+            conflicts_with = "live-report"
+        )]
+        no_live_report: bool,
+        #[structopt(
+            name = "feature-set",
+            long = "feature-set",
+            raw(number_of_values = "1")
+        )]
+        feature_sets: Vec<FeatureSet>,
+        #[structopt(
+            name = "cargo-patch",
+            long = "cargo-patch",
+            raw(number_of_values = "1"),
+            conflicts_with = "no-cargo-patches",
+            help = "replace a dependency with a git repository (name=git-url[#rev])"
+        )]
+        cargo_patches: Vec<CargoPatch>,
+        #[structopt(
+            name = "no-cargo-patches",
+            long = "no-cargo-patches",
+            conflicts_with = "cargo-patch"
+        )]
+        no_cargo_patches: bool,
+        #[structopt(
+            name = "pre-analyze",
+            long = "pre-analyze",
+            conflicts_with = "no-pre-analyze"
+        )]
+        pre_analyze: bool,
+        #[structopt(
+            name = "no-pre-analyze",
+            long = "no-pre-analyze",
+            conflicts_with = "pre-analyze"
+        )]
+        no_pre_analyze: bool,
+        #[structopt(name = "private", long = "private", conflicts_with = "no-private")]
+        private: bool,
+        #[structopt(name = "no-private", long = "no-private", conflicts_with = "private")]
+        no_private: bool,
+        #[structopt(
+            name = "private-reader",
+            long = "private-reader",
+            raw(number_of_values = "1"),
+            conflicts_with = "no-private-readers",
+            help = "name of a token allowed to request links to the private report"
+        )]
+        private_readers: Vec<String>,
+        #[structopt(
+            name = "no-private-readers",
+            long = "no-private-readers",
+            conflicts_with = "private-reader"
+        )]
+        no_private_readers: bool,
+        #[structopt(
+            name = "test-runner",
+            long = "test-runner",
+            raw(possible_values = "TestRunner::possible_values()")
+        )]
+        test_runner: Option<TestRunner>,
+        #[structopt(
+            name = "measure-artifacts",
+            long = "measure-artifacts",
+            conflicts_with = "no-measure-artifacts"
+        )]
+        measure_artifacts: bool,
+        #[structopt(
+            name = "no-measure-artifacts",
+            long = "no-measure-artifacts",
+            conflicts_with = "measure-artifacts"
+        )]
+        no_measure_artifacts: bool,
+        #[structopt(name = "silent", long = "silent", conflicts_with = "no-silent")]
+        silent: bool,
+        #[structopt(name = "no-silent", long = "no-silent", conflicts_with = "silent")]
+        no_silent: bool,
+    },
+
+    #[structopt(
+        name = "dump-ex",
+        about = "print an experiment in the format read by define-ex --from-file"
+    )]
+    DumpEx {
+        #[structopt(name = "name")]
+        name: String,
     },
 
     #[structopt(name = "delete-ex", about = "delete shared data for experiment")]
@@ -195,6 +496,30 @@ pub enum Crater {
         krate: Crate,
     },
 
+    #[structopt(
+        name = "invalidate-results",
+        about = "delete the results of a list of crates so they're tested again"
+    )]
+    InvalidateResults {
+        #[structopt(name = "experiment", long = "ex", default_value = "default")]
+        ex: Ex,
+        #[structopt(
+            name = "crates",
+            long = "crates",
+            parse(from_os_str),
+            help = "file containing the crates to invalidate, one per line"
+        )]
+        crates: PathBuf,
+        #[structopt(name = "toolchain", long = "toolchain", short = "t")]
+        tc: Option<Toolchain>,
+        #[structopt(
+            name = "reopen",
+            long = "reopen",
+            help = "run the experiment again if it already finished"
+        )]
+        reopen: bool,
+    },
+
     #[structopt(name = "run-graph", about = "run a parallelized experiment")]
     RunGraph {
         #[structopt(name = "experiment", long = "ex", default_value = "default")]
@@ -205,6 +530,34 @@ pub enum Crater {
         docker_env: Option<String>,
     },
 
+    #[structopt(
+        name = "reproduce",
+        about = "build a crate of an experiment locally, the same way the agents did"
+    )]
+    Reproduce {
+        #[structopt(name = "experiment")]
+        ex: String,
+        #[structopt(
+            name = "crate",
+            help = "name-version of a crates.io crate, or the URL of a GitHub repository"
+        )]
+        krate: Crate,
+        #[structopt(
+            name = "remote",
+            long = "remote",
+            help = "load the experiment from the public API of this crater server"
+        )]
+        remote: Option<String>,
+        #[structopt(
+            name = "shell",
+            long = "shell",
+            help = "start a shell in the sandbox of the last toolchain the crate failed with"
+        )]
+        shell: bool,
+        #[structopt(name = "docker-env", long = "docker-env")]
+        docker_env: Option<String>,
+    },
+
     #[structopt(name = "gen-report", about = "generate the experiment report")]
     GenReport {
         #[structopt(name = "experiment", long = "ex", default_value = "default")]
@@ -215,6 +568,65 @@ pub enum Crater {
         force: bool,
     },
 
+    #[structopt(
+        name = "regenerate-report",
+        about = "queue the reports of completed experiments to be regenerated by the server"
+    )]
+    RegenerateReport {
+        #[structopt(name = "experiment", required_unless = "all")]
+        ex: Option<String>,
+        #[structopt(
+            name = "all",
+            long = "all",
+            requires = "since",
+            conflicts_with = "experiment",
+            help = "regenerate the reports of all the experiments completed since --since"
+        )]
+        all: bool,
+        #[structopt(
+            name = "since",
+            long = "since",
+            requires = "all",
+            help = "date (like 2019-01-01) from which the experiments completed are regenerated"
+        )]
+        since: Option<NaiveDate>,
+    },
+
+    #[structopt(
+        name = "export",
+        about = "export the results of an experiment as JSON or CSV"
+    )]
+    Export {
+        #[structopt(name = "experiment", long = "ex", default_value = "default")]
+        ex: Ex,
+        #[structopt(name = "format", long = "format", default_value = "json")]
+        format: ExportFormat,
+        #[structopt(name = "out", long = "out", parse(from_os_str))]
+        out: PathBuf,
+    },
+
+    #[structopt(
+        name = "compare",
+        about = "generate a report comparing the results of two experiments"
+    )]
+    Compare {
+        #[structopt(name = "first")]
+        first: Ex,
+        #[structopt(name = "second")]
+        second: Ex,
+        #[structopt(name = "destination", long = "dest", default_value = "compare")]
+        dest: Dest,
+    },
+
+    #[structopt(
+        name = "summaries",
+        about = "show the report summaries of the latest experiments"
+    )]
+    Summaries {
+        #[structopt(name = "last", long = "last", default_value = "10")]
+        last: u32,
+    },
+
     #[structopt(name = "publish-report", about = "publish the experiment report to S3")]
     PublishReport {
         #[structopt(
@@ -230,8 +642,21 @@ pub enum Crater {
         force: bool,
     },
 
-    #[structopt(name = "server")]
-    Server,
+    #[structopt(name = "server", about = "run the crater server")]
+    Server {
+        #[structopt(
+            name = "log-format",
+            long = "log-format",
+            raw(
+                default_value = "LogFormat::Text.to_str()",
+                possible_values = "LogFormat::possible_values()"
+            ),
+            help = "format of the logs, either human-readable or one JSON object per line"
+        )]
+        log_format: LogFormat,
+        #[structopt(subcommand)]
+        cmd: Option<ServerCommand>,
+    },
 
     #[structopt(name = "agent")]
     Agent {
@@ -243,6 +668,35 @@ pub enum Crater {
         threads: usize,
         #[structopt(name = "docker-env", long = "docker-env")]
         docker_env: Option<String>,
+        #[structopt(
+            name = "no-auto-update",
+            long = "no-auto-update",
+            help = "don't replace the agent with the binary released by the server"
+        )]
+        no_auto_update: bool,
+        #[structopt(
+            name = "skip-docker-env-check",
+            long = "skip-docker-env-check",
+            help = "don't check the docker environment is available before starting"
+        )]
+        skip_docker_env_check: bool,
+        #[structopt(
+            name = "quiet",
+            short = "q",
+            long = "quiet",
+            help = "only log the errors, ignoring RUST_LOG"
+        )]
+        quiet: bool,
+        #[structopt(
+            name = "log-format",
+            long = "log-format",
+            raw(
+                default_value = "LogFormat::Text.to_str()",
+                possible_values = "LogFormat::possible_values()"
+            ),
+            help = "format of the logs, either human-readable or one JSON object per line"
+        )]
+        log_format: LogFormat,
     },
 
     #[structopt(
@@ -264,16 +718,274 @@ pub enum Crater {
         #[structopt(name = "file")]
         filename: Option<String>,
     },
+
+    #[structopt(
+        name = "generate-token",
+        about = "generate a new token to add to tokens.toml"
+    )]
+    GenerateToken {
+        #[structopt(name = "name")]
+        name: String,
+        #[structopt(
+            name = "read-only",
+            long = "read-only",
+            help = "only allow the token to read data",
+            conflicts_with = "admin"
+        )]
+        read_only: bool,
+        #[structopt(
+            name = "admin",
+            long = "admin",
+            help = "allow the token to use the admin API",
+            conflicts_with = "read-only"
+        )]
+        admin: bool,
+    },
+
+    #[structopt(
+        name = "test-email",
+        about = "send a test email to check the configuration of the report digests"
+    )]
+    TestEmail,
+
+    #[structopt(name = "db", about = "inspect the crater database")]
+    Db {
+        #[structopt(subcommand)]
+        cmd: DbCommand,
+    },
+
+    #[structopt(
+        name = "agents",
+        about = "inspect the agents through the API of a crater server"
+    )]
+    Agents {
+        #[structopt(
+            name = "server-url",
+            long = "server-url",
+            env = "CRATER_SERVER_URL",
+            raw(global = "true")
+        )]
+        server_url: String,
+        #[structopt(
+            name = "token",
+            long = "token",
+            env = "CRATER_TOKEN",
+            help = "agent or read-only token",
+            raw(global = "true", hide_env_values = "true")
+        )]
+        token: String,
+        #[structopt(subcommand)]
+        cmd: AgentsCommand,
+    },
+
+    #[structopt(
+        name = "experiment",
+        about = "inspect the experiments through the API of a crater server"
+    )]
+    Experiment {
+        #[structopt(
+            name = "server-url",
+            long = "server-url",
+            env = "CRATER_SERVER_URL",
+            raw(global = "true")
+        )]
+        server_url: String,
+        #[structopt(subcommand)]
+        cmd: ExperimentCommand,
+    },
+
+    #[structopt(name = "completions", about = "print the completion script of a shell")]
+    Completions {
+        #[structopt(name = "shell", raw(possible_values = "completions::SHELLS"))]
+        shell: Shell,
+    },
+}
+
+#[derive(structopt_derive::StructOpt)]
+pub enum ServerCommand {
+    #[structopt(
+        name = "watch-reports",
+        about = "print the reports of a running server as they are generated"
+    )]
+    WatchReports {
+        #[structopt(
+            name = "server-url",
+            long = "server-url",
+            env = "CRATER_SERVER_URL",
+            default_value = "http://localhost:8000"
+        )]
+        server_url: String,
+    },
+}
+
+#[derive(structopt_derive::StructOpt)]
+pub enum AgentsCommand {
+    #[structopt(name = "list", about = "list the agents and what they're doing")]
+    List,
+
+    #[structopt(name = "show", about = "show everything known about an agent")]
+    Show {
+        #[structopt(name = "name")]
+        name: String,
+    },
+}
+
+#[derive(structopt_derive::StructOpt)]
+pub enum ExperimentCommand {
+    #[structopt(name = "list", about = "list the experiments, the most recent first")]
+    List {
+        #[structopt(
+            name = "status",
+            long = "status",
+            raw(possible_values = "Status::possible_values()")
+        )]
+        status: Option<Status>,
+        #[structopt(
+            name = "assignee",
+            long = "assignee",
+            help = "only list the experiments assigned to this assignee (like agent:NAME)"
+        )]
+        assignee: Option<String>,
+        #[structopt(name = "requested-by", long = "requested-by")]
+        requested_by: Option<String>,
+    },
+
+    #[structopt(
+        name = "show",
+        about = "show the configuration and progress of an experiment"
+    )]
+    Show {
+        #[structopt(name = "name")]
+        name: String,
+    },
+
+    #[structopt(
+        name = "wait",
+        about = "wait for an experiment to complete, failing if crates regressed"
+    )]
+    Wait {
+        #[structopt(name = "name")]
+        name: String,
+        #[structopt(
+            name = "interval",
+            long = "interval",
+            default_value = "60",
+            help = "seconds between two checks of the experiment"
+        )]
+        interval: u64,
+        #[structopt(
+            name = "timeout-minutes",
+            long = "timeout-minutes",
+            help = "fail if the experiment is not completed after this many minutes"
+        )]
+        timeout_minutes: Option<u64>,
+    },
+
+    #[structopt(name = "results", about = "inspect the results of experiments")]
+    Results {
+        #[structopt(subcommand)]
+        cmd: ResultsCommand,
+    },
+}
+
+#[derive(structopt_derive::StructOpt)]
+pub enum ResultsCommand {
+    #[structopt(
+        name = "diff",
+        about = "list the crates classified differently by two experiments"
+    )]
+    Diff {
+        #[structopt(name = "first")]
+        first: String,
+        #[structopt(name = "second")]
+        second: String,
+        #[structopt(
+            name = "regressions-only",
+            long = "regressions-only",
+            conflicts_with = "improvements-only"
+        )]
+        regressions_only: bool,
+        #[structopt(
+            name = "improvements-only",
+            long = "improvements-only",
+            conflicts_with = "regressions-only"
+        )]
+        improvements_only: bool,
+        #[structopt(
+            name = "output",
+            long = "output",
+            parse(from_os_str),
+            help = "also write the differences to an HTML file"
+        )]
+        html: Option<PathBuf>,
+    },
+}
+
+#[derive(structopt_derive::StructOpt)]
+pub enum DbCommand {
+    #[structopt(
+        name = "status",
+        about = "show the version of the database schema and the pending migrations"
+    )]
+    Status,
+
+    #[structopt(
+        name = "migrate",
+        about = "apply the pending migrations to the database"
+    )]
+    Migrate {
+        #[structopt(
+            name = "dry-run",
+            long = "dry-run",
+            help = "print the SQL of the pending migrations without applying them"
+        )]
+        dry_run: bool,
+    },
+
+    #[structopt(name = "stats", about = "show how much space the stored logs use")]
+    Stats,
+
+    #[structopt(
+        name = "usage",
+        about = "show how much space the logs of each experiment use"
+    )]
+    Usage,
+
+    #[structopt(
+        name = "migrate-to-postgres",
+        about = "copy the SQLite database to an empty PostgreSQL database"
+    )]
+    MigrateToPostgres {
+        #[structopt(name = "url")]
+        url: String,
+    },
+
+    #[structopt(
+        name = "backup",
+        about = "write a consistent snapshot of the database, even while the server is running"
+    )]
+    Backup {
+        #[structopt(name = "path", parse(from_os_str))]
+        path: PathBuf,
+    },
+
+    #[structopt(name = "restore", about = "replace the database with a backup")]
+    Restore {
+        #[structopt(name = "path", parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(name = "force", long = "force")]
+        force: bool,
+    },
 }
 
 impl Crater {
-    pub fn run(&self) -> Fallible<()> {
+    pub fn run(&self, output: Output) -> Fallible<()> {
         match *self {
             Crater::CreateLists { ref lists } => {
                 let mut lists: HashSet<_> = lists.iter().map(|s| s.as_str()).collect();
 
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
                 let ctx = ActionsCtx::new(&db, &config);
 
                 let action = if lists.is_empty() {
@@ -294,7 +1006,7 @@ impl Crater {
             }
             Crater::PrepareLocal => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
                 let ctx = ActionsCtx::new(&db, &config);
                 actions::UpdateLists::default().apply(&ctx)?;
             }
@@ -302,27 +1014,103 @@ impl Crater {
                 ref ex,
                 ref tc1,
                 ref tc2,
+                ref from_file,
                 ref mode,
                 ref crates,
+                ref pinned_crates,
+                ref crate_list,
                 ref cap_lints,
                 ref priority,
                 ref ignore_blacklist,
+                ref fail_fast,
+                ref feature_sets,
+                ref requested_by,
+                live_report,
+                ref cargo_patches,
+                pre_analyze,
+                private,
+                ref private_readers,
+                test_runner,
+                measure_artifacts,
+                silent,
+                allow_duplicate,
+                dry_run,
             } => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
                 let ctx = ActionsCtx::new(&db, &config);
 
-                actions::CreateExperiment {
-                    name: ex.0.clone(),
-                    toolchains: [tc1.clone(), tc2.clone()],
-                    mode: *mode,
-                    crates: *crates,
-                    cap_lints: *cap_lints,
-                    priority: *priority,
-                    github_issue: None,
-                    ignore_blacklist: *ignore_blacklist,
+                let create = if let Some(path) = from_file {
+                    actions::ExperimentDefinition::load(path)?.into_create(allow_duplicate)?
+                } else {
+                    actions::CreateExperiment {
+                        name: ex.0.clone(),
+                        // Both are required by clap without --from-file
+                        toolchains: [tc1.clone().unwrap(), tc2.clone().unwrap()],
+                        mode: *mode,
+                        crates: *crates,
+                        crate_list: match crate_list {
+                            Some(path) => {
+                                let content = ::std::fs::read_to_string(path)?;
+                                Some(actions::parse_crate_list(&ctx, &content)?)
+                            }
+                            None => None,
+                        },
+                        pinned_crates: pinned_crates.clone(),
+                        cap_lints: *cap_lints,
+                        priority: *priority,
+                        github_issue: None,
+                        ignore_blacklist: *ignore_blacklist,
+                        fail_fast: *fail_fast,
+                        feature_sets: if feature_sets.is_empty() {
+                            vec![FeatureSet::Default]
+                        } else {
+                            feature_sets.clone()
+                        },
+                        requested_by: requested_by.clone(),
+                        live_report,
+                        cargo_patches: cargo_patches.clone(),
+                        pre_analyze,
+                        private,
+                        private_readers: private_readers.clone(),
+                        test_runner,
+                        measure_artifacts,
+                        silent,
+                        allow_duplicate,
+                    }
+                };
+                if dry_run {
+                    let dry_run = create.dry_run(&ctx)?;
+                    let toml = dry_run.to_toml()?;
+                    output.object(&dry_run, |_| print!("{}", toml))?;
+                } else {
+                    create.apply(&ctx)?;
                 }
-                .apply(&ctx)?;
+            }
+            Crater::CreateExperiment {
+                non_interactive,
+                ref name,
+                ref toolchain_a,
+                ref toolchain_b,
+                ref crates,
+                ref crate_file,
+                crate_limit,
+                priority,
+                ref github_issue,
+                ref assignee,
+            } => {
+                let settings = create_experiment::Settings {
+                    name: name.clone(),
+                    toolchain_a: toolchain_a.clone(),
+                    toolchain_b: toolchain_b.clone(),
+                    crates: crates.clone(),
+                    crate_file: crate_file.clone(),
+                    crate_limit,
+                    priority,
+                    github_issue: github_issue.clone(),
+                    assignee: assignee.clone(),
+                };
+                create_experiment::run(&settings, non_interactive, output)?;
             }
             Crater::Edit {
                 ref name,
@@ -334,9 +1122,27 @@ impl Crater {
                 ref priority,
                 ref ignore_blacklist,
                 ref no_ignore_blacklist,
+                ref fail_fast,
+                ref no_fail_fast,
+                ref feature_sets,
+                live_report,
+                no_live_report,
+                ref cargo_patches,
+                no_cargo_patches,
+                pre_analyze,
+                no_pre_analyze,
+                private,
+                no_private,
+                ref private_readers,
+                no_private_readers,
+                test_runner,
+                measure_artifacts,
+                no_measure_artifacts,
+                silent,
+                no_silent,
             } => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
                 let ctx = ActionsCtx::new(&db, &config);
 
                 let ignore_blacklist = if *ignore_blacklist {
@@ -346,6 +1152,48 @@ impl Crater {
                 } else {
                     None
                 };
+                let fail_fast = if *fail_fast {
+                    Some(true)
+                } else if *no_fail_fast {
+                    Some(false)
+                } else {
+                    None
+                };
+                let live_report = if live_report {
+                    Some(true)
+                } else if no_live_report {
+                    Some(false)
+                } else {
+                    None
+                };
+                let pre_analyze = if pre_analyze {
+                    Some(true)
+                } else if no_pre_analyze {
+                    Some(false)
+                } else {
+                    None
+                };
+                let private = if private {
+                    Some(true)
+                } else if no_private {
+                    Some(false)
+                } else {
+                    None
+                };
+                let measure_artifacts = if measure_artifacts {
+                    Some(true)
+                } else if no_measure_artifacts {
+                    Some(false)
+                } else {
+                    None
+                };
+                let silent = if silent {
+                    Some(true)
+                } else if no_silent {
+                    Some(false)
+                } else {
+                    None
+                };
 
                 actions::EditExperiment {
                     name: name.clone(),
@@ -355,18 +1203,57 @@ impl Crater {
                     cap_lints: *cap_lints,
                     priority: *priority,
                     ignore_blacklist,
+                    fail_fast,
+                    feature_sets: if feature_sets.is_empty() {
+                        None
+                    } else {
+                        Some(feature_sets.clone())
+                    },
+                    live_report,
+                    cargo_patches: if no_cargo_patches {
+                        Some(Vec::new())
+                    } else if cargo_patches.is_empty() {
+                        None
+                    } else {
+                        Some(cargo_patches.clone())
+                    },
+                    pre_analyze,
+                    private,
+                    private_readers: if no_private_readers {
+                        Some(Vec::new())
+                    } else if private_readers.is_empty() {
+                        None
+                    } else {
+                        Some(private_readers.clone())
+                    },
+                    test_runner,
+                    measure_artifacts,
+                    silent,
                 }
                 .apply(&ctx)?;
             }
+            Crater::DumpEx { ref name } => {
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
+
+                let ex = match Experiment::get(&db, name)? {
+                    Some(ex) => ex,
+                    None => bail!("missing experiment: {}", name),
+                };
+                let definition = actions::ExperimentDefinition::from(&ex);
+                let toml = definition.to_toml()?;
+                output.object(&definition, |_| print!("{}", toml))?;
+            }
             Crater::DeleteEx { ref ex } => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
                 let ctx = ActionsCtx::new(&db, &config);
 
                 actions::DeleteExperiment { name: ex.0.clone() }.apply(&ctx)?;
             }
             Crater::DeleteAllResults { ref ex } => {
-                let db = Database::open()?;
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
                 let result_db = DatabaseDB::new(&db);
 
                 if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
@@ -381,14 +1268,15 @@ impl Crater {
                 ref tc,
                 ref krate,
             } => {
-                let db = Database::open()?;
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
                 let result_db = DatabaseDB::new(&db);
 
                 if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
                     if let Some(tc) = tc {
                         result_db.delete_result(&experiment, tc, krate)?;
                     } else {
-                        for tc in &experiment.toolchains {
+                        for tc in &experiment.build_toolchains() {
                             result_db.delete_result(&experiment, tc, krate)?;
                         }
                     }
@@ -398,6 +1286,40 @@ impl Crater {
                     bail!("missing experiment {}", ex.0);
                 }
             }
+            Crater::InvalidateResults {
+                ref ex,
+                ref crates,
+                ref tc,
+                reopen,
+            } => {
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
+
+                if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
+                    let crates = ::std::fs::read_to_string(crates)?
+                        .lines()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(Crate::from_str)
+                        .collect::<Fallible<Vec<_>>>()?;
+                    let toolchains = match tc {
+                        Some(tc) => vec![tc.clone()],
+                        None => experiment.build_toolchains(),
+                    };
+
+                    let removed =
+                        experiment.invalidate_results(&db, &crates, &toolchains, reopen)?;
+                    output.object(&json!({ "removed": removed }), |_| {
+                        println!("removed {} results", removed)
+                    })?;
+                    info!(
+                        "experiment {} is now {}",
+                        experiment.name, experiment.status
+                    );
+                } else {
+                    bail!("missing experiment {}", ex.0);
+                }
+            }
             Crater::RunGraph {
                 ref ex,
                 threads,
@@ -408,12 +1330,14 @@ impl Crater {
                     .map(|e| e.as_str())
                     .unwrap_or(DEFAULT_DOCKER_ENV);
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
 
                 if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
                     // Ensure the experiment is properly assigned
                     match experiment.assigned_to {
-                        None => experiment.set_assigned_to(&db, Some(&Assignee::CLI))?,
+                        None | Some(Assignee::Any) => {
+                            experiment.set_assigned_to(&db, Some(&Assignee::CLI))?
+                        }
                         Some(Assignee::CLI) => {}
                         Some(a) => bail!("experiment {} is assigned to {}", ex.0, a),
                     }
@@ -432,13 +1356,55 @@ impl Crater {
                     bail!("missing experiment {}", ex.0);
                 }
             }
+            Crater::Reproduce {
+                ref ex,
+                ref krate,
+                ref remote,
+                shell,
+                ref docker_env,
+            } => {
+                let docker_env = docker_env
+                    .as_ref()
+                    .map(|e| e.as_str())
+                    .unwrap_or(DEFAULT_DOCKER_ENV);
+                let config = Config::load()?;
+
+                let reproduction = match *remote {
+                    Some(ref url) => ServerApi::new(url, None, output).get(&format!(
+                        "experiments/{}/reproduce/{}",
+                        ex,
+                        krate.id()
+                    ))?,
+                    None => runner::Reproduction::load(&Database::open(&config)?, ex, krate)?,
+                };
+                let reproduction = match reproduction {
+                    Some(reproduction) => reproduction,
+                    None => bail!("missing crate {} in experiment {}", krate, ex),
+                };
+
+                let results = runner::reproduce(&reproduction, &config, docker_env, shell)?;
+                output.list(&results, |results| {
+                    let show = |result: Option<TestResult>| {
+                        result.map_or_else(|| "no result".to_string(), |r| r.to_string())
+                    };
+                    for result in results {
+                        println!(
+                            "{}: {} -> {} ({})",
+                            result.features,
+                            show(result.results[0]),
+                            show(result.results[1]),
+                            result.comparison.to_str()
+                        );
+                    }
+                })?;
+            }
             Crater::GenReport {
                 ref ex,
                 ref dest,
                 force,
             } => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
 
                 if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
                     // Update the status
@@ -459,25 +1425,167 @@ impl Crater {
                         &experiment,
                         &report::FileWriter::create(dest.0.clone())?,
                         &config,
+                        None,
                     );
 
-                    if let Err(err) = res {
-                        experiment.set_status(&db, Status::ReportFailed)?;
-                        return Err(err)?;
-                    } else {
-                        experiment.set_status(&db, Status::Completed)?;
+                    match res {
+                        Ok((_, summary)) => {
+                            summary.store(&db, &experiment.name)?;
+                            experiment.set_status(&db, Status::Completed)?;
+                        }
+                        Err(err) => {
+                            experiment.set_status(&db, Status::ReportFailed)?;
+                            return Err(err)?;
+                        }
                     }
                 } else {
                     bail!("missing experiment: {}", ex.0);
                 }
             }
+            Crater::RegenerateReport { ref ex, all, since } => {
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
+                let ctx = ActionsCtx::new(&db, &config);
+
+                let names = match (ex, since) {
+                    (_, Some(since)) if all => Experiment::completed_since(
+                        &db,
+                        Utc.from_utc_date(&since).and_hms(0, 0, 0),
+                    )?
+                    .into_iter()
+                    .map(|ex| ex.name)
+                    .collect(),
+                    (Some(ex), _) => vec![ex.clone()],
+                    _ => bail!("either an experiment or --all and --since are required"),
+                };
+
+                let mut queued = 0;
+                for name in names {
+                    let regenerate = actions::RegenerateReport { name: name.clone() };
+                    match regenerate.apply(&ctx) {
+                        Ok(()) => queued += 1,
+                        // A single experiment which can't be regenerated doesn't stop the batch
+                        Err(err) if all => warn!("skipping experiment {}: {}", name, err),
+                        Err(err) => return Err(err),
+                    }
+                }
+                output.object(&json!({ "queued": queued }), |_| {
+                    info!(
+                        "queued the regeneration of {} reports, the server will publish them",
+                        queued
+                    )
+                })?;
+            }
+            Crater::Export {
+                ref ex,
+                format,
+                ref out,
+            } => {
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
+
+                if let Some(experiment) = Experiment::get(&db, &ex.0)? {
+                    // The results are loaded in batches to show the progress, as searching the
+                    // logs for internal compiler errors takes a while
+                    let total = export::count_results(&db, &experiment)?;
+                    let bar = progress::bar(output, u64::from(total), "loading the results");
+                    let mut results = Vec::with_capacity(total as usize);
+                    while results.len() < total as usize {
+                        let batch = export::load_results(
+                            &db,
+                            &config,
+                            &experiment,
+                            results.len() as u32,
+                            Some(EXPORT_BATCH_SIZE),
+                        )?;
+                        if batch.is_empty() {
+                            break;
+                        }
+                        bar.inc(batch.len() as u64);
+                        results.extend(batch);
+                    }
+                    bar.finish_and_clear();
+
+                    export::write_results(&results, format, BufWriter::new(File::create(out)?))?;
+                    info!("exported {} results to {}", results.len(), out.display());
+                } else {
+                    bail!("missing experiment: {}", ex.0);
+                }
+            }
+            Crater::Compare {
+                ref first,
+                ref second,
+                ref dest,
+            } => {
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
+
+                let get = |ex: &Ex| -> Fallible<Experiment> {
+                    match Experiment::get(&db, &ex.0)? {
+                        Some(experiment) => Ok(experiment),
+                        None => bail!("missing experiment: {}", ex.0),
+                    }
+                };
+                let (first, second) = (get(first)?, get(second)?);
+
+                let diff = report::diff::gen(
+                    &DatabaseDB::new(&db),
+                    &first,
+                    &second,
+                    &report::FileWriter::create(dest.0.clone())?,
+                    &config,
+                )?;
+                info!(
+                    "{} crates newly regressed and {} fixed between {} and {}",
+                    diff.count(DiffCategory::NewlyRegressed),
+                    diff.count(DiffCategory::Fixed),
+                    first.name,
+                    second.name,
+                );
+            }
+            Crater::Summaries { last } => {
+                let config = Config::load()?;
+                let db = Database::open(&config)?;
+
+                let summaries = ReportSummary::recent(&db, last)?;
+                let values = summaries
+                    .iter()
+                    .map(|(name, summary)| json!({ "experiment": name, "summary": summary }))
+                    .collect::<Vec<_>>();
+                output.list(&values, |_| {
+                    println!(
+                        "{:<30} {:<40} {:>9} {:>6} {:>8} {:>8}  report",
+                        "experiment", "toolchains", "regressed", "fixed", "results", "duration"
+                    );
+                    for (name, summary) in &summaries {
+                        let duration = match summary.duration_secs {
+                            Some(secs) => format!("{}h{:02}m", secs / 3600, secs / 60 % 60),
+                            None => "-".into(),
+                        };
+                        println!(
+                            "{:<30} {:<40} {:>9} {:>6} {:>8} {:>8}  {}",
+                            name,
+                            summary.toolchains.join(" vs "),
+                            summary.count(Comparison::Regressed),
+                            summary.count(Comparison::Fixed),
+                            summary.results(),
+                            duration,
+                            summary
+                                .report_url
+                                .as_ref()
+                                .map(String::as_str)
+                                .unwrap_or("-"),
+                        );
+                    }
+                })?;
+            }
             Crater::PublishReport {
                 ref ex,
                 ref s3_prefix,
                 force,
             } => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
 
                 if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
                     // Update the status
@@ -500,37 +1608,59 @@ impl Crater {
                         &experiment,
                         &report::S3Writer::create(client, s3_prefix.clone())?,
                         &config,
+                        None,
                     );
 
-                    if let Err(err) = res {
-                        experiment.set_status(&db, Status::ReportFailed)?;
-                        return Err(err)?;
-                    } else {
-                        experiment.set_status(&db, Status::Completed)?;
+                    match res {
+                        Ok((_, summary)) => {
+                            summary.store(&db, &experiment.name)?;
+                            experiment.set_status(&db, Status::Completed)?;
+                        }
+                        Err(err) => {
+                            experiment.set_status(&db, Status::ReportFailed)?;
+                            return Err(err)?;
+                        }
                     }
                 } else {
                     bail!("missing experiment: {}", ex.0);
                 }
             }
-            Crater::Server => {
-                let config = Config::load()?;
-                server::run(config)?;
-            }
+            Crater::Server { ref cmd, .. } => match *cmd {
+                None => {
+                    let config = Config::load()?;
+                    server::run(config)?;
+                }
+                Some(ServerCommand::WatchReports { ref server_url }) => {
+                    let api = ServerApi::new(server_url, None, output);
+                    reports::watch(&api, output)?;
+                }
+            },
             Crater::Agent {
                 ref url,
                 ref token,
                 threads,
                 ref docker_env,
+                no_auto_update,
+                skip_docker_env_check,
+                quiet: _,
+                log_format: _,
             } => {
                 let docker_env = docker_env
                     .as_ref()
                     .map(|e| e.as_str())
                     .unwrap_or(DEFAULT_DOCKER_ENV);
-                agent::run(url, token, threads, docker_env)?;
+                agent::run(
+                    url,
+                    token,
+                    threads,
+                    docker_env,
+                    !no_auto_update,
+                    !skip_docker_env_check,
+                )?;
             }
             Crater::DumpTasksGraph { ref dest, ref ex } => {
                 let config = Config::load()?;
-                let db = Database::open()?;
+                let db = Database::open(&config)?;
 
                 if let Some(experiment) = Experiment::get(&db, &ex.0)? {
                     runner::dump_dot(&experiment, &config, dest)?;
@@ -543,6 +1673,237 @@ impl Crater {
                     bail!("check-config failed: {}", e);
                 }
             }
+            Crater::GenerateToken {
+                ref name,
+                read_only,
+                admin,
+            } => {
+                let section = if read_only {
+                    "read-only"
+                } else if admin {
+                    "admins"
+                } else {
+                    "agents"
+                };
+                let token = server::tokens::Tokens::generate();
+                output.object(
+                    &json!({ "section": section, "token": token, "name": name }),
+                    |_| {
+                        println!("# add this to the [{}] section of tokens.toml", section);
+                        println!("{:?} = {:?}", token, name);
+                    },
+                )?;
+            }
+            Crater::TestEmail => {
+                let config = Config::load()?;
+                let email_config = match config.server.email {
+                    Some(ref email_config) => email_config,
+                    None => bail!("the [server.email] section of config.toml is missing"),
+                };
+                let tokens = server::tokens::Tokens::load()?;
+                server::email::Email::test().send(email_config, &tokens)?;
+                output.object(&json!({ "recipients": email_config.recipients }), |_| {
+                    println!("test email sent to {}", email_config.recipients.join(", "))
+                })?;
+            }
+            Crater::Db { ref cmd } => match *cmd {
+                DbCommand::Status => {
+                    let config = Config::load()?;
+                    let status = Database::open_unmigrated(&config)?.schema_status()?;
+
+                    let value = json!({
+                        "version": status.version,
+                        "expected-version": status.expected_version,
+                        "pending": status.pending,
+                        "unknown": status.unknown,
+                    });
+                    output.object(&value, |_| {
+                        println!(
+                            "schema version: {} (expected {})",
+                            status.version, status.expected_version
+                        );
+                        for name in &status.pending {
+                            println!("pending migration: {}", name);
+                        }
+                        for name in &status.unknown {
+                            println!("unknown migration (applied by a newer crater): {}", name);
+                        }
+                    })?;
+                }
+                DbCommand::Migrate { dry_run } => {
+                    let config = Config::load()?;
+                    let db = Database::open_unmigrated(&config)?;
+
+                    if dry_run {
+                        let pending = db.pending_migrations()?;
+                        let values = pending
+                            .iter()
+                            .map(|(name, sql)| json!({ "name": name, "sql": sql }))
+                            .collect::<Vec<_>>();
+                        output.list(&values, |_| {
+                            for (name, sql) in &pending {
+                                println!("-- migration: {}\n{}\n", name, sql);
+                            }
+                        })?;
+                    } else {
+                        let applied = db.migrate()?;
+                        output.object(&json!({ "applied": applied }), |_| {
+                            println!("applied {} migrations", applied.len())
+                        })?;
+                    }
+                }
+                DbCommand::Stats => {
+                    let config = Config::load()?;
+                    let db = Database::open(&config)?;
+                    let stats = DatabaseDB::new(&db).logs_stats()?;
+
+                    let value = json!({
+                        "results": stats.results,
+                        "unique-logs": stats.unique_logs,
+                        "total-size": stats.total_size,
+                        "stored-size": stats.stored_size,
+                        "dedup-ratio": stats.dedup_ratio(),
+                    });
+                    output.object(&value, |_| {
+                        println!("results: {}", stats.results);
+                        println!("unique logs: {}", stats.unique_logs);
+                        println!("logs size: {} bytes", stats.total_size);
+                        println!("stored logs size: {} bytes", stats.stored_size);
+                        println!("dedup ratio: {:.2}x", stats.dedup_ratio());
+                    })?;
+                }
+                DbCommand::Usage => {
+                    let config = Config::load()?;
+                    let db = Database::open(&config)?;
+
+                    output.list(&DatabaseDB::new(&db).usage()?, |usages| {
+                        for usage in usages {
+                            println!(
+                                "{}: {} results, {} bytes of logs, {} bytes compressed, \
+                                 {} bytes stored ({:.1}%)",
+                                usage.experiment,
+                                usage.results,
+                                usage.raw_size,
+                                usage.compressed_size,
+                                usage.stored_size,
+                                usage.share * 100.0
+                            );
+                        }
+                    })?;
+                }
+                #[cfg(feature = "postgresql")]
+                DbCommand::MigrateToPostgres { ref url } => {
+                    Database::open_sqlite()?.copy_to_postgres(url)?;
+                    output.object(&json!({ "migrated": true }), |_| {
+                        println!("set database.postgres-url in config.toml to start using it")
+                    })?;
+                }
+                #[cfg(not(feature = "postgresql"))]
+                DbCommand::MigrateToPostgres { .. } => {
+                    bail!("crater was built without PostgreSQL support");
+                }
+                DbCommand::Backup { ref path } => {
+                    let config = Config::load()?;
+                    let db = Database::open(&config)?;
+                    db.backup(path)?;
+                    output.object(&json!({ "backup": path }), |_| {
+                        println!("database backed up to {}", path.display())
+                    })?;
+                }
+                DbCommand::Restore { ref path, force } => {
+                    let config = Config::load()?;
+                    let db = Database::open(&config)?;
+
+                    // The agents would lose track of their progress
+                    let running = Experiment::unfinished(&db)?
+                        .into_iter()
+                        .filter(|ex| ex.status == Status::Running)
+                        .map(|ex| ex.name)
+                        .collect::<Vec<_>>();
+                    if !running.is_empty() && !force {
+                        bail!(
+                            "some experiments are running: {} (use --force to override)",
+                            running.join(", ")
+                        );
+                    }
+
+                    db.restore(path)?;
+                    output.object(&json!({ "restored": path }), |_| {
+                        println!("database restored from {}", path.display())
+                    })?;
+                }
+            },
+            Crater::Agents {
+                ref server_url,
+                ref token,
+                ref cmd,
+            } => {
+                let api = ServerApi::new(server_url, Some(token), output);
+                match *cmd {
+                    AgentsCommand::List => agents::list(&api, output)?,
+                    AgentsCommand::Show { ref name } => agents::show(&api, name, output)?,
+                }
+            }
+            Crater::Experiment {
+                ref server_url,
+                ref cmd,
+            } => {
+                // The experiments are listed by the public API, which requires no token
+                let api = ServerApi::new(server_url, None, output);
+                match *cmd {
+                    ExperimentCommand::List {
+                        status,
+                        ref assignee,
+                        ref requested_by,
+                    } => {
+                        let filters = experiments::ListFilters {
+                            status,
+                            assignee: assignee.as_ref().map(|s| s.as_str()),
+                            requested_by: requested_by.as_ref().map(|s| s.as_str()),
+                        };
+                        experiments::list(&api, &filters, output)?;
+                    }
+                    ExperimentCommand::Show { ref name } => experiments::show(&api, name, output)?,
+                    ExperimentCommand::Wait {
+                        ref name,
+                        interval,
+                        timeout_minutes,
+                    } => experiments::wait(
+                        &api,
+                        name,
+                        Duration::from_secs(interval),
+                        timeout_minutes.map(|minutes| Duration::from_secs(minutes * 60)),
+                        output,
+                    )?,
+                    ExperimentCommand::Results {
+                        cmd:
+                            ResultsCommand::Diff {
+                                ref first,
+                                ref second,
+                                regressions_only,
+                                improvements_only,
+                                ref html,
+                            },
+                    } => {
+                        let filter = if regressions_only {
+                            experiments::DiffFilter::RegressionsOnly
+                        } else if improvements_only {
+                            experiments::DiffFilter::ImprovementsOnly
+                        } else {
+                            experiments::DiffFilter::All
+                        };
+                        experiments::diff(
+                            &api,
+                            first,
+                            second,
+                            filter,
+                            html.as_ref().map(PathBuf::as_path),
+                            output,
+                        )?;
+                    }
+                }
+            }
+            Crater::Completions { shell } => completions::generate(shell, io::stdout())?,
         }
 
         Ok(())
@@ -0,0 +1,88 @@
+//! Inspect the agents of a crater server through its public API, without having access to the
+//! server's database.
+
+use crate::cli::output::Output;
+use crate::cli::server_api::{format_table, ServerApi};
+use chrono::SecondsFormat;
+use crater::server::api_types::AgentInfo;
+use failure::{bail, Fallible};
+
+/// Length of the git revisions shown in the list of agents.
+const SHORT_REVISION: usize = 7;
+
+fn heartbeat(agent: &AgentInfo) -> String {
+    agent
+        .last_heartbeat
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| "never".into())
+}
+
+pub fn list(api: &ServerApi, output: Output) -> Fallible<()> {
+    let agents: Vec<AgentInfo> = match api.get("agents")? {
+        Some(agents) => agents,
+        None => bail!("the server doesn't support listing the agents"),
+    };
+
+    output.list(&agents, |agents| {
+        let rows = agents
+            .iter()
+            .map(|agent| {
+                vec![
+                    agent.name.clone(),
+                    agent.status.to_str().to_string(),
+                    heartbeat(agent),
+                    agent
+                        .git_revision
+                        .as_ref()
+                        .map(|rev| rev.chars().take(SHORT_REVISION).collect())
+                        .unwrap_or_else(|| "-".into()),
+                    agent
+                        .assigned_experiment
+                        .clone()
+                        .unwrap_or_else(|| "-".into()),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let header = ["name", "status", "last heartbeat", "revision", "experiment"];
+        for line in format_table(&header, &rows) {
+            println!("{}", line);
+        }
+    })
+}
+
+pub fn show(api: &ServerApi, name: &str, output: Output) -> Fallible<()> {
+    let agent: AgentInfo = match api.get(&format!("agents/{}", name))? {
+        Some(agent) => agent,
+        None => bail!("missing agent: {}", name),
+    };
+
+    output.object(&agent, print_details)
+}
+
+fn print_details(agent: &AgentInfo) {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".into());
+    let host = agent.host.as_ref();
+    let mut rows = vec![
+        ("name", agent.name.clone()),
+        ("status", agent.status.to_str().to_string()),
+        ("last heartbeat", heartbeat(agent)),
+        ("git revision", optional(&agent.git_revision)),
+        ("experiment", optional(&agent.assigned_experiment)),
+        (
+            "os",
+            host.map(|h| h.os.clone()).unwrap_or_else(|| "-".into()),
+        ),
+        ("kernel", optional(&host.and_then(|h| h.kernel.clone()))),
+        ("docker", optional(&host.and_then(|h| h.docker.clone()))),
+    ];
+    if let Some(ref reason) = agent.unhealthy {
+        rows.push(("unhealthy", reason.clone()));
+    }
+    if agent.conflict {
+        rows.push(("conflict", "token used by more than one process".into()));
+    }
+
+    for (field, value) in rows {
+        println!("{:<15} {}", format!("{}:", field), value);
+    }
+}
@@ -0,0 +1,143 @@
+//! Shell completions of the CLI. The subcommands and flags are generated by clap, and the names of
+//! the experiments and agents are completed by asking the server with `crater --json ... list`,
+//! which requires `jq` and the `CRATER_SERVER_URL` (and `CRATER_TOKEN` for the agents)
+//! environment variables.
+
+use crate::cli::Cli;
+use failure::Fallible;
+use std::io::Write;
+use structopt::clap::Shell;
+use structopt::StructOpt;
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+static BASH: &str = r#"
+_crater_experiment_names() {
+    crater --json experiment list 2>/dev/null | jq -r '.name' 2>/dev/null
+}
+
+_crater_agent_names() {
+    crater --json agents list 2>/dev/null | jq -r '.name' 2>/dev/null
+}
+
+_crater_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" names=""
+    case "${prev}" in
+        --ex|regenerate-report|edit|wait|dump-ex|reproduce)
+            names="$(_crater_experiment_names)"
+            ;;
+        show)
+            if [[ " ${COMP_WORDS[*]} " == *" agents "* ]]; then
+                names="$(_crater_agent_names)"
+            else
+                names="$(_crater_experiment_names)"
+            fi
+            ;;
+        *)
+            _crater "$@"
+            return $?
+            ;;
+    esac
+    COMPREPLY=($(compgen -W "${names}" -- "${cur}"))
+}
+
+complete -F _crater_dynamic -o bashdefault -o default crater
+"#;
+
+static ZSH: &str = r#"
+_crater_experiment_names() {
+    local -a names
+    names=(${(f)"$(crater --json experiment list 2>/dev/null | jq -r '.name' 2>/dev/null)"})
+    compadd -a names
+}
+
+_crater_agent_names() {
+    local -a names
+    names=(${(f)"$(crater --json agents list 2>/dev/null | jq -r '.name' 2>/dev/null)"})
+    compadd -a names
+}
+
+_crater_dynamic() {
+    case "${words[CURRENT-1]}" in
+        --ex|regenerate-report|edit|wait|dump-ex|reproduce)
+            _crater_experiment_names
+            ;;
+        show)
+            if (( ${words[(I)agents]} )); then
+                _crater_agent_names
+            else
+                _crater_experiment_names
+            fi
+            ;;
+        *)
+            _crater "$@"
+            ;;
+    esac
+}
+
+_crater_dynamic "$@"
+"#;
+
+static FISH: &str = r#"
+function __crater_experiment_names
+    crater --json experiment list 2>/dev/null | jq -r '.name' 2>/dev/null
+end
+
+function __crater_agent_names
+    crater --json agents list 2>/dev/null | jq -r '.name' 2>/dev/null
+end
+
+complete -c crater -l ex -x -a '(__crater_experiment_names)'
+complete -c crater -n '__fish_seen_subcommand_from regenerate-report edit wait dump-ex reproduce' -f -a '(__crater_experiment_names)'
+complete -c crater -n '__fish_seen_subcommand_from experiment; and __fish_seen_subcommand_from show' -f -a '(__crater_experiment_names)'
+complete -c crater -n '__fish_seen_subcommand_from agents; and __fish_seen_subcommand_from show' -f -a '(__crater_agent_names)'
+"#;
+
+/// Write the completion script for the shell, which must be one of `SHELLS`.
+pub fn generate<W: Write>(shell: Shell, mut out: W) -> Fallible<()> {
+    let mut script = Vec::new();
+    Cli::clap().gen_completions_to("crater", shell, &mut script);
+    let script = String::from_utf8(script)?;
+
+    let (script, dynamic) = match shell {
+        Shell::Bash => (script.as_str(), BASH),
+        // The generated script completes by calling `_crater` last, which must be replaced to
+        // complete the names first
+        Shell::Zsh => (script.trim_end().trim_end_matches("_crater \"$@\""), ZSH),
+        Shell::Fish => (script.as_str(), FISH),
+        _ => (script.as_str(), ""),
+    };
+    out.write_all(script.trim_end().as_bytes())?;
+    out.write_all(b"\n")?;
+    out.write_all(dynamic.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, SHELLS};
+    use structopt::clap::Shell;
+
+    #[test]
+    fn test_generate() {
+        for shell in SHELLS {
+            let mut out = Vec::new();
+            generate(shell.parse::<Shell>().unwrap(), &mut out).unwrap();
+            let script = String::from_utf8(out).unwrap();
+
+            // Both the static and the dynamic completions are included
+            assert!(script.contains("invalidate-results"), "{}", shell);
+            assert!(
+                script.contains("crater --json experiment list"),
+                "{}",
+                shell
+            );
+        }
+
+        let mut out = Vec::new();
+        generate(Shell::Zsh, &mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(!script.contains("\n_crater \"$@\""));
+        assert!(script.trim_end().ends_with("_crater_dynamic \"$@\""));
+    }
+}
@@ -0,0 +1,458 @@
+//! Interactive creation of experiments, prompting for each setting and validating it right away
+//! instead of failing after the whole command line was parsed.
+
+use crate::cli::output::Output;
+use crate::cli::progress;
+use crater::actions::{self, Action, ActionsCtx};
+use crater::config::Config;
+use crater::crates::{self, Crate};
+use crater::db::Database;
+use crater::experiments::{
+    Assignee, CapLints, CrateSelect, Experiment, FeatureSet, GitHubIssue, Mode,
+};
+use crater::toolchain::Toolchain;
+use failure::{bail, Fallible};
+use serde_json::json;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Channels completed from an unambiguous prefix, like `ni` for `nightly`.
+const CHANNELS: &[&str] = &["stable", "beta", "nightly"];
+const DEFAULT_TOP_CRATES: usize = 100;
+
+/// How the crates of the experiment are chosen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum CrateSource {
+    /// The most popular crates on crates.io.
+    Top,
+    /// All the crates on crates.io and the GitHub repositories.
+    All,
+    /// The crates listed in a file, one per line.
+    File,
+}
+
+impl CrateSource {
+    const NAMES: &'static [&'static str] = &["top", "all", "file"];
+}
+
+impl FromStr for CrateSource {
+    type Err = failure::Error;
+
+    fn from_str(input: &str) -> Fallible<Self> {
+        Ok(match input {
+            "top" => CrateSource::Top,
+            "all" => CrateSource::All,
+            "file" => CrateSource::File,
+            other => bail!(
+                "invalid crate selection: {} (expected top, all or file)",
+                other
+            ),
+        })
+    }
+}
+
+/// Settings passed on the command line, used as the defaults of the prompts.
+#[derive(Default)]
+pub struct Settings {
+    pub name: Option<String>,
+    pub toolchain_a: Option<String>,
+    pub toolchain_b: Option<String>,
+    pub crates: Option<String>,
+    pub crate_file: Option<String>,
+    pub crate_limit: Option<usize>,
+    pub priority: Option<i32>,
+    pub github_issue: Option<String>,
+    pub assignee: Option<String>,
+}
+
+/// Asks for each setting until a valid answer is given. Without an input the defaults are used
+/// instead, and invalid or missing ones are errors.
+struct Prompter<R: BufRead, W: Write> {
+    input: Option<R>,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    fn ask<T>(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+        parse: impl Fn(&str) -> Fallible<T>,
+    ) -> Fallible<T> {
+        let input = match self.input {
+            Some(ref mut input) => input,
+            None => {
+                return match default {
+                    Some(value) => {
+                        parse(value).map_err(|e| failure::err_msg(format!("{}: {}", question, e)))
+                    }
+                    None => bail!("{}: missing value", question),
+                };
+            }
+        };
+
+        loop {
+            match default {
+                Some(default) if !default.is_empty() => {
+                    write!(self.output, "{} [{}]: ", question, default)?
+                }
+                _ => write!(self.output, "{}: ", question)?,
+            }
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                bail!("no answer for: {}", question);
+            }
+            let answer = match line.trim() {
+                "" => default.unwrap_or(""),
+                answer => answer,
+            };
+
+            match parse(answer) {
+                Ok(value) => return Ok(value),
+                Err(err) => writeln!(self.output, "  invalid value: {}", err)?,
+            }
+        }
+    }
+}
+
+/// Complete the channel of a toolchain from an unambiguous prefix, leaving the other toolchains
+/// untouched.
+fn complete_toolchain(input: &str) -> String {
+    let split = input.find(|c| c == '+' || c == '#').unwrap_or(input.len());
+    let (source, rest) = input.split_at(split);
+    let matches = CHANNELS
+        .iter()
+        .filter(|channel| !source.is_empty() && channel.starts_with(source))
+        .collect::<Vec<_>>();
+    match matches.as_slice() {
+        [channel] => format!("{}{}", channel, rest),
+        _ => input.to_string(),
+    }
+}
+
+fn parse_toolchain(input: &str) -> Fallible<Toolchain> {
+    Ok(complete_toolchain(input).parse()?)
+}
+
+fn parse_optional<T: FromStr>(input: &str) -> Fallible<Option<T>>
+where
+    T::Err: Into<failure::Error>,
+{
+    if input.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(input.parse().map_err(Into::into)?))
+    }
+}
+
+/// Parse the URL of an issue or pull request, like `https://github.com/rust-lang/rust/pull/1`.
+fn parse_github_issue(input: &str) -> Fallible<Option<GitHubIssue>> {
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    let path = input.trim_end_matches('/');
+    let path = match path.find("github.com/") {
+        Some(start) if path.starts_with("https://") => &path[start + "github.com/".len()..],
+        _ => bail!("not a GitHub URL: {}", input),
+    };
+    match path.split('/').collect::<Vec<_>>().as_slice() {
+        [org, repo, "issues", number] | [org, repo, "pull", number] => {
+            let number = number.parse()?;
+            Ok(Some(GitHubIssue {
+                api_url: format!(
+                    "https://api.github.com/repos/{}/{}/issues/{}",
+                    org, repo, number
+                ),
+                html_url: format!("https://github.com/{}/{}/issues/{}", org, repo, number),
+                number,
+            }))
+        }
+        _ => bail!("not the URL of an issue or pull request: {}", input),
+    }
+}
+
+/// Queued experiments are only claimed by agents if nobody is assigned to them, so the only
+/// pools available are the agents and the `run-graph` command.
+fn parse_assignee(input: &str) -> Fallible<Option<Assignee>> {
+    match input.parse()? {
+        Assignee::Any => Ok(None),
+        Assignee::CLI => Ok(Some(Assignee::CLI)),
+        Assignee::Agent(_) => bail!("experiments can't be assigned to a single agent"),
+    }
+}
+
+fn read_crate_list(path: &Path) -> Fallible<Vec<Crate>> {
+    let crates = ::std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Crate::from_str)
+        .collect::<Fallible<Vec<_>>>()?;
+    if crates.is_empty() {
+        bail!("no crates listed in {}", path.display());
+    }
+    Ok(crates)
+}
+
+/// Prompt for the settings of the experiment, or take them from `settings` if `input` is `None`,
+/// and create it. Returns the created experiment.
+fn create<R: BufRead, W: Write>(
+    db: &Database,
+    config: &Config,
+    settings: &Settings,
+    input: Option<R>,
+    output: W,
+    mode: Output,
+) -> Fallible<Experiment> {
+    let mut prompt = Prompter { input, output };
+    let default = |value: &Option<String>| value.as_ref().map(|s| s.as_str());
+
+    let name = prompt.ask("Experiment name", default(&settings.name), |name| {
+        if name.is_empty() {
+            bail!("the name can't be empty");
+        } else if Experiment::exists(db, name)? {
+            bail!("experiment {} already exists", name);
+        }
+        Ok(name.to_string())
+    })?;
+
+    let channels = format!("Toolchain A ({} or a custom one)", CHANNELS.join("/"));
+    let toolchain_a = prompt.ask(
+        &channels,
+        Some(default(&settings.toolchain_a).unwrap_or("stable")),
+        parse_toolchain,
+    )?;
+    let toolchain_b = prompt.ask(
+        "Toolchain B",
+        Some(default(&settings.toolchain_b).unwrap_or("beta")),
+        |input| {
+            let toolchain = parse_toolchain(input)?;
+            if toolchain == toolchain_a {
+                bail!("both toolchains are {}", toolchain);
+            }
+            Ok(toolchain)
+        },
+    )?;
+
+    let source = prompt.ask(
+        &format!("Crate selection ({})", CrateSource::NAMES.join("/")),
+        Some(default(&settings.crates).unwrap_or("top")),
+        CrateSource::from_str,
+    )?;
+    let crate_list = match source {
+        CrateSource::Top => {
+            let limit = prompt.ask(
+                "Number of crates",
+                Some(
+                    &settings
+                        .crate_limit
+                        .unwrap_or(DEFAULT_TOP_CRATES)
+                        .to_string(),
+                ),
+                |input| {
+                    let limit: usize = input.parse()?;
+                    if limit == 0 {
+                        bail!("at least one crate is needed");
+                    }
+                    Ok(limit)
+                },
+            )?;
+            Some(crates::top_crates(db, limit)?)
+        }
+        CrateSource::All => None,
+        CrateSource::File => {
+            let mut list = prompt.ask(
+                "File listing the crates",
+                default(&settings.crate_file),
+                |path| read_crate_list(Path::new(path)),
+            )?;
+            let limit = prompt.ask(
+                "Crate count limit (empty for no limit)",
+                Some(
+                    &settings
+                        .crate_limit
+                        .map(|limit| limit.to_string())
+                        .unwrap_or_default(),
+                ),
+                parse_optional::<usize>,
+            )?;
+            if let Some(limit) = limit {
+                list.truncate(limit);
+            }
+            Some(list)
+        }
+    };
+
+    let priority = prompt.ask(
+        "Priority",
+        Some(&settings.priority.unwrap_or(0).to_string()),
+        |input| Ok(input.parse()?),
+    )?;
+    let github_issue = prompt.ask(
+        "GitHub issue URL (empty for none)",
+        Some(default(&settings.github_issue).unwrap_or("")),
+        parse_github_issue,
+    )?;
+    let assignee = prompt.ask(
+        "Assignee pool (any or cli)",
+        Some(default(&settings.assignee).unwrap_or("any")),
+        parse_assignee,
+    )?;
+
+    // Checking the experiment, for example against the existing ones, can take a while
+    let spinner = progress::spinner(mode, &format!("validating and creating {}", name));
+    let ctx = ActionsCtx::new(db, config);
+    let created = actions::CreateExperiment {
+        name: name.clone(),
+        toolchains: [toolchain_a, toolchain_b],
+        mode: Mode::BuildAndTest,
+        crates: CrateSelect::Full,
+        crate_list,
+        pinned_crates: Vec::new(),
+        cap_lints: CapLints::Forbid,
+        priority,
+        github_issue,
+        ignore_blacklist: false,
+        fail_fast: false,
+        feature_sets: vec![FeatureSet::Default],
+        requested_by: None,
+        live_report: false,
+        cargo_patches: Vec::new(),
+        pre_analyze: false,
+        private: false,
+        private_readers: Vec::new(),
+        test_runner: None,
+        measure_artifacts: false,
+        silent: false,
+        allow_duplicate: false,
+    }
+    .apply(&ctx);
+    spinner.finish_and_clear();
+    created?;
+
+    let mut experiment = match Experiment::get(db, &name)? {
+        Some(experiment) => experiment,
+        None => bail!("missing experiment {}", name),
+    };
+    if let Some(assignee) = assignee {
+        experiment.set_assigned_to(db, Some(&assignee))?;
+    }
+    Ok(experiment)
+}
+
+pub fn run(settings: &Settings, non_interactive: bool, output: Output) -> Fallible<()> {
+    let config = Config::load()?;
+    let db = Database::open(&config)?;
+
+    let stdin = ::std::io::stdin();
+    let input = if non_interactive {
+        None
+    } else {
+        Some(stdin.lock())
+    };
+    // The prompts would mix with the JSON printed on stdout
+    let experiment = if output.is_json() {
+        create(&db, &config, settings, input, ::std::io::stderr(), output)?
+    } else {
+        create(&db, &config, settings, input, ::std::io::stdout(), output)?
+    };
+
+    let github_issue = experiment
+        .github_issue
+        .as_ref()
+        .map(|issue| issue.html_url.as_str());
+    let value = json!({ "name": experiment.name, "github-issue": github_issue });
+    output.object(&value, |_| {
+        println!("created experiment {}", experiment.name);
+        if let Some(url) = github_issue {
+            println!("GitHub issue: {}", url);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete_toolchain, parse_assignee, parse_github_issue, Prompter};
+    use crater::experiments::Assignee;
+    use std::io::{Cursor, Empty};
+
+    #[test]
+    fn test_prompter() {
+        let parse = |input: &str| -> failure::Fallible<u32> { Ok(input.parse()?) };
+
+        // Invalid answers are asked again, and empty ones take the default
+        let mut output = Vec::new();
+        {
+            let mut prompt = Prompter {
+                input: Some(Cursor::new("foo\n\n42\n")),
+                output: &mut output,
+            };
+            assert_eq!(prompt.ask("Priority", Some("1"), parse).unwrap(), 1);
+            assert_eq!(prompt.ask("Priority", None, parse).unwrap(), 42);
+            assert!(prompt.ask("Priority", None, parse).is_err());
+        }
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Priority [1]: "));
+        assert!(output.contains("invalid value"));
+
+        // Without input the defaults are used, and the invalid ones are errors
+        let mut prompt = Prompter {
+            input: None::<Empty>,
+            output: Vec::new(),
+        };
+        assert_eq!(prompt.ask("Priority", Some("3"), parse).unwrap(), 3);
+        assert!(prompt.ask("Priority", Some("high"), parse).is_err());
+        assert!(prompt.ask("Priority", None, parse).is_err());
+    }
+
+    #[test]
+    fn test_complete_toolchain() {
+        assert_eq!(complete_toolchain("ni"), "nightly");
+        assert_eq!(
+            complete_toolchain("s+rustflags=-Zfoo"),
+            "stable+rustflags=-Zfoo"
+        );
+        assert_eq!(complete_toolchain("beta"), "beta");
+        assert_eq!(
+            complete_toolchain("nightly-2019-01-01"),
+            "nightly-2019-01-01"
+        );
+        assert_eq!(complete_toolchain("1.32.0"), "1.32.0");
+        assert_eq!(complete_toolchain("try#f00"), "try#f00");
+    }
+
+    #[test]
+    fn test_parse_github_issue() {
+        let issue = parse_github_issue("https://github.com/rust-lang/rust/pull/12345")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            issue.api_url,
+            "https://api.github.com/repos/rust-lang/rust/issues/12345"
+        );
+        assert_eq!(
+            issue.html_url,
+            "https://github.com/rust-lang/rust/issues/12345"
+        );
+        assert_eq!(issue.number, 12345);
+
+        assert!(parse_github_issue("").unwrap().is_none());
+        assert!(parse_github_issue("https://github.com/rust-lang/rust").is_err());
+        assert!(parse_github_issue("https://example.com/a/b/issues/1").is_err());
+    }
+
+    #[test]
+    fn test_parse_assignee() {
+        assert!(parse_assignee("any").unwrap().is_none());
+        match parse_assignee("cli").unwrap() {
+            Some(Assignee::CLI) => {}
+            _ => panic!("cli is not parsed as the cli pool"),
+        }
+        assert!(parse_assignee("agent:foo").is_err());
+        assert!(parse_assignee("").is_err());
+    }
+}
@@ -0,0 +1,446 @@
+//! Inspect the experiments of a crater server through its public API, without having access to
+//! the server's database.
+
+use crate::cli::output::Output;
+use crate::cli::progress;
+use crate::cli::server_api::{format_table, ServerApi};
+use chrono::{DateTime, SecondsFormat, Utc};
+use crater::experiments::{FeatureSet, Status};
+use crater::report::diff::{
+    diff_changed, render_standalone, DiffCategory, DiffEntry, DiffExperiment, ExperimentsDiff,
+};
+use crater::report::Comparison;
+use crater::results::ChangedCrate;
+use crater::server::api_types::{ExperimentDetails, ExperimentSummary};
+use failure::{bail, Fallible};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use url::form_urlencoded;
+
+/// Filters of the list of experiments, sent as the query parameters of the public API.
+pub struct ListFilters<'a> {
+    pub status: Option<Status>,
+    pub assignee: Option<&'a str>,
+    pub requested_by: Option<&'a str>,
+}
+
+impl<'a> ListFilters<'a> {
+    fn to_query(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(status) = self.status {
+            query.append_pair("status", status.to_str());
+        }
+        if let Some(assignee) = self.assignee {
+            query.append_pair("assignee", assignee);
+        }
+        if let Some(requested_by) = self.requested_by {
+            query.append_pair("requested_by", requested_by);
+        }
+        query.finish()
+    }
+}
+
+fn time(time: &Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| "-".into())
+}
+
+pub fn list(api: &ServerApi, filters: &ListFilters, output: Output) -> Fallible<()> {
+    let query = filters.to_query();
+    let path = if query.is_empty() {
+        "experiments".to_string()
+    } else {
+        format!("experiments?{}", query)
+    };
+    let experiments: Vec<ExperimentSummary> = match api.get(&path)? {
+        Some(experiments) => experiments,
+        None => bail!("the server doesn't support listing the experiments"),
+    };
+
+    output.list(&experiments, |experiments| {
+        let rows = experiments
+            .iter()
+            .map(|ex| {
+                vec![
+                    ex.name.clone(),
+                    ex.status.to_str().to_string(),
+                    ex.assigned_to.clone().unwrap_or_else(|| "-".into()),
+                    ex.priority.to_string(),
+                    ex.created_at.format("%Y-%m-%d").to_string(),
+                    ex.crates.to_string(),
+                    format!("{}%", ex.progress),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let header = [
+            "name", "status", "assignee", "priority", "created", "crates", "progress",
+        ];
+        for line in format_table(&header, &rows) {
+            println!("{}", line);
+        }
+    })
+}
+
+pub fn show(api: &ServerApi, name: &str, output: Output) -> Fallible<()> {
+    let ex: ExperimentDetails = match api.get(&format!("experiments/{}", name))? {
+        Some(ex) => ex,
+        None => bail!("missing experiment: {}", name),
+    };
+
+    output.object(&ex, print_details)
+}
+
+/// Whether the experiment stopped running and its report was generated, or failed to be.
+fn is_finished(status: Status) -> bool {
+    match status {
+        Status::Completed | Status::ReportFailed => true,
+        Status::Queued | Status::Running | Status::NeedsReport | Status::GeneratingReport => false,
+    }
+}
+
+/// Number of crates with all their results recorded, as each crate is tested by multiple jobs.
+fn completed_crates(ex: &ExperimentDetails) -> u64 {
+    if ex.total_jobs == 0 {
+        return 0;
+    }
+    u64::from(ex.completed_jobs) * u64::from(ex.summary.crates) / u64::from(ex.total_jobs)
+}
+
+/// Block until the experiment is finished, checking it every `interval`. The experiment having
+/// regressions is reported as an error, so scripts can check the exit code.
+pub fn wait(
+    api: &ServerApi,
+    name: &str,
+    interval: Duration,
+    timeout: Option<Duration>,
+    output: Output,
+) -> Fallible<()> {
+    let start = Instant::now();
+    let bar = progress::bar(output, 0, name);
+    bar.set_style(
+        indicatif::ProgressStyle::default_bar().template("{msg} [{bar:40}] {pos}/{len} crates"),
+    );
+
+    let ex = loop {
+        let ex: ExperimentDetails = match api.get(&format!("experiments/{}", name))? {
+            Some(ex) => ex,
+            None => {
+                bar.finish_and_clear();
+                bail!("missing experiment: {} (it might have been aborted)", name);
+            }
+        };
+        bar.set_length(u64::from(ex.summary.crates));
+        bar.set_position(completed_crates(&ex));
+        if is_finished(ex.summary.status) {
+            break ex;
+        }
+
+        if let Some(timeout) = timeout {
+            if start.elapsed() >= timeout {
+                bar.finish_and_clear();
+                bail!(
+                    "experiment {} is still {} after {} minutes",
+                    name,
+                    ex.summary.status.to_str(),
+                    timeout.as_secs() / 60
+                );
+            }
+        }
+        thread::sleep(interval);
+    };
+    bar.finish_and_clear();
+
+    output.object(&ex, print_details)?;
+    if ex.results.regressed > 0 {
+        bail!(
+            "{} crates regressed in experiment {}",
+            ex.results.regressed,
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Which changes between two experiments are shown by `diff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFilter {
+    All,
+    RegressionsOnly,
+    ImprovementsOnly,
+}
+
+impl DiffFilter {
+    /// Categories of the changes shown, the most severe first.
+    fn categories(self) -> &'static [DiffCategory] {
+        match self {
+            DiffFilter::All => &[
+                DiffCategory::NewlyRegressed,
+                DiffCategory::Fixed,
+                DiffCategory::ChangedCategory,
+            ],
+            DiffFilter::RegressionsOnly => &[DiffCategory::NewlyRegressed],
+            DiffFilter::ImprovementsOnly => &[DiffCategory::Fixed],
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+struct DiffRow<'a> {
+    #[serde(rename = "crate")]
+    name: &'a str,
+    #[serde(skip_serializing_if = "FeatureSet::is_default")]
+    features: &'a FeatureSet,
+    old: Comparison,
+    new: Comparison,
+    change: DiffCategory,
+}
+
+fn diff_rows(diff: &ExperimentsDiff, filter: DiffFilter) -> Vec<DiffRow> {
+    filter
+        .categories()
+        .iter()
+        .flat_map(|&category| {
+            diff.categories
+                .get(&category)
+                .map(Vec::as_slice)
+                .unwrap_or(&[])
+                .iter()
+                .map(move |entry: &DiffEntry| DiffRow {
+                    name: &entry.name,
+                    features: &entry.features,
+                    old: entry.before,
+                    new: entry.after,
+                    change: category,
+                })
+        })
+        .collect()
+}
+
+fn diff_experiment(ex: &ExperimentDetails) -> DiffExperiment {
+    DiffExperiment {
+        name: &ex.summary.name,
+        report_url: ex.summary.report_url.as_ref().map(String::as_str),
+    }
+}
+
+pub fn diff(
+    api: &ServerApi,
+    first: &str,
+    second: &str,
+    filter: DiffFilter,
+    html: Option<&Path>,
+    output: Output,
+) -> Fallible<()> {
+    let changed: Vec<ChangedCrate> = match api.get(&format!("compare/{}/{}", first, second))? {
+        Some(changed) => changed,
+        None => bail!("missing experiment: {} or {}", first, second),
+    };
+    let mut diff = diff_changed(&changed)?;
+    diff.categories
+        .retain(|category, _| filter.categories().contains(category));
+
+    if let Some(html) = html {
+        let details = |name: &str| -> Fallible<ExperimentDetails> {
+            match api.get(&format!("experiments/{}", name))? {
+                Some(ex) => Ok(ex),
+                None => bail!("missing experiment: {}", name),
+            }
+        };
+        let (first, second) = (details(first)?, details(second)?);
+        fs::write(
+            html,
+            render_standalone(diff_experiment(&first), diff_experiment(&second), &diff)?,
+        )?;
+    }
+
+    output.list(&diff_rows(&diff, filter), |rows| {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let name = if row.features.is_default() {
+                    row.name.to_string()
+                } else {
+                    format!("{} ({})", row.name, row.features)
+                };
+                vec![
+                    name,
+                    row.old.to_str().to_string(),
+                    row.new.to_str().to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        for line in format_table(&["crate", first, second], &rows) {
+            println!("{}", line);
+        }
+    })
+}
+
+fn print_details(ex: &ExperimentDetails) {
+    let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".into());
+    let summary = &ex.summary;
+    let rows = vec![
+        ("name", summary.name.clone()),
+        ("status", summary.status.to_str().to_string()),
+        ("mode", summary.mode.to_str().to_string()),
+        ("toolchains", ex.toolchains.join(" -> ")),
+        ("cap lints", ex.cap_lints.to_str().to_string()),
+        ("priority", summary.priority.to_string()),
+        ("assignee", optional(&summary.assigned_to)),
+        ("requested by", optional(&summary.requested_by)),
+        ("github issue", optional(&summary.github_issue_url)),
+        ("report", optional(&summary.report_url)),
+        ("created", time(&Some(summary.created_at))),
+        ("started", time(&summary.started_at)),
+        ("completed", time(&summary.completed_at)),
+        ("crates", summary.crates.to_string()),
+        (
+            "progress",
+            format!(
+                "{}% ({}/{} jobs)",
+                summary.progress, ex.completed_jobs, ex.total_jobs
+            ),
+        ),
+        ("eta", time(&ex.estimated_end)),
+    ];
+    for (field, value) in rows {
+        println!("{:<15} {}", format!("{}:", field), value);
+    }
+
+    let results = &ex.results;
+    println!();
+    println!("results:");
+    for (outcome, count) in &[
+        ("regressed", results.regressed),
+        ("fixed", results.fixed),
+        ("spurious regressed", results.spurious_regressed),
+        ("spurious fixed", results.spurious_fixed),
+        ("build fail", results.build_fail),
+        ("test fail", results.test_fail),
+        ("test skipped", results.test_skipped),
+        ("test pass", results.test_pass),
+        ("error", results.error),
+    ] {
+        println!("  {:<20} {}", format!("{}:", outcome), count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{completed_crates, diff_rows, is_finished, DiffFilter, ListFilters};
+    use chrono::Utc;
+    use crater::crates::{Crate, RegistryCrate};
+    use crater::experiments::{CapLints, FeatureSet, Mode, Status};
+    use crater::report::diff::{diff_changed, DiffCategory};
+    use crater::report::Comparison;
+    use crater::results::{ChangedCrate, ResultsSummary};
+    use crater::server::api_types::{ExperimentDetails, ExperimentSummary};
+
+    #[test]
+    fn test_list_query() {
+        let filters = ListFilters {
+            status: None,
+            assignee: None,
+            requested_by: None,
+        };
+        assert_eq!(filters.to_query(), "");
+
+        let filters = ListFilters {
+            status: Some(Status::Running),
+            assignee: Some("agent:agent-1"),
+            requested_by: None,
+        };
+        assert_eq!(
+            filters.to_query(),
+            "status=running&assignee=agent%3Aagent-1"
+        );
+    }
+
+    #[test]
+    fn test_diff_rows() {
+        let changed = |name: &str, first, second| ChangedCrate {
+            krate: Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            }),
+            features: FeatureSet::Default,
+            first,
+            second,
+        };
+        let diff = diff_changed(&[
+            changed("fixed", Comparison::Regressed, Comparison::SameTestPass),
+            changed("other", Comparison::SameBuildFail, Comparison::Error),
+            changed("regressed", Comparison::SameTestPass, Comparison::Regressed),
+        ])
+        .unwrap();
+
+        let names = |filter| {
+            diff_rows(&diff, filter)
+                .iter()
+                .map(|row| (row.name.to_string(), row.change))
+                .collect::<Vec<_>>()
+        };
+
+        // Regressions are listed first, then the improvements
+        assert_eq!(
+            names(DiffFilter::All),
+            vec![
+                ("regressed-1.0.0".to_string(), DiffCategory::NewlyRegressed),
+                ("fixed-1.0.0".to_string(), DiffCategory::Fixed),
+                ("other-1.0.0".to_string(), DiffCategory::ChangedCategory),
+            ]
+        );
+        assert_eq!(
+            names(DiffFilter::RegressionsOnly),
+            vec![("regressed-1.0.0".to_string(), DiffCategory::NewlyRegressed)]
+        );
+        assert_eq!(
+            names(DiffFilter::ImprovementsOnly),
+            vec![("fixed-1.0.0".to_string(), DiffCategory::Fixed)]
+        );
+
+        let rows = diff_rows(&diff, DiffFilter::RegressionsOnly);
+        assert_eq!(rows[0].old, Comparison::SameTestPass);
+        assert_eq!(rows[0].new, Comparison::Regressed);
+    }
+
+    #[test]
+    fn test_wait_progress() {
+        assert!(is_finished(Status::Completed));
+        assert!(is_finished(Status::ReportFailed));
+        assert!(!is_finished(Status::GeneratingReport));
+
+        let details = |completed_jobs, total_jobs| ExperimentDetails {
+            summary: ExperimentSummary {
+                name: "foo".into(),
+                status: Status::Running,
+                mode: Mode::BuildAndTest,
+                priority: 0,
+                assigned_to: None,
+                created_at: Utc::now(),
+                started_at: None,
+                completed_at: None,
+                requested_by: None,
+                github_issue_url: None,
+                report_url: None,
+                crates: 10,
+                progress: 0,
+            },
+            toolchains: vec!["stable".into(), "beta".into()],
+            cap_lints: CapLints::Forbid,
+            completed_jobs,
+            total_jobs,
+            results: ResultsSummary::default(),
+            estimated_end: None,
+        };
+
+        // Each crate is tested with both toolchains
+        assert_eq!(completed_crates(&details(0, 20)), 0);
+        assert_eq!(completed_crates(&details(9, 20)), 4);
+        assert_eq!(completed_crates(&details(20, 20)), 10);
+        assert_eq!(completed_crates(&details(0, 0)), 0);
+    }
+}
@@ -0,0 +1,125 @@
+//! Output of the commands, as human-readable text or, with the global `--json` flag, as JSON for
+//! scripts. Lists are printed as one JSON object per line, everything else as a single object.
+
+use failure::{Error, Fallible};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        if json {
+            Output::Json
+        } else {
+            Output::Text
+        }
+    }
+
+    pub fn is_json(self) -> bool {
+        self == Output::Json
+    }
+
+    /// Print a single object, calling `text` to print it in text mode.
+    pub fn object<T: Serialize, F: FnOnce(&T)>(self, value: &T, text: F) -> Fallible<()> {
+        match self {
+            Output::Text => text(value),
+            Output::Json => println!("{}", serde_json::to_string(value)?),
+        }
+        Ok(())
+    }
+
+    /// Print a list of objects, calling `text` to print them in text mode.
+    pub fn list<T: Serialize, F: FnOnce(&[T])>(self, values: &[T], text: F) -> Fallible<()> {
+        match self {
+            Output::Text => text(values),
+            Output::Json => {
+                for line in json_lines(values)? {
+                    println!("{}", line);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn json_lines<T: Serialize>(values: &[T]) -> Fallible<Vec<String>> {
+    values
+        .iter()
+        .map(|value| Ok(serde_json::to_string(value)?))
+        .collect()
+}
+
+/// Printed instead of the output when a command fails in JSON mode. The code is the exit code of
+/// the process.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub error: String,
+    pub code: i32,
+}
+
+impl JsonError {
+    pub fn new(err: &Error, code: i32) -> Self {
+        JsonError {
+            // The causes are included, as they're usually needed to understand the error
+            error: err
+                .iter_chain()
+                .map(|cause| cause.to_string())
+                .collect::<Vec<_>>()
+                .join(": "),
+            code,
+        }
+    }
+
+    pub fn print(&self) {
+        match serde_json::to_string(self) {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("failed to serialize the error: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_lines, JsonError};
+    use failure::{err_msg, Error, ResultExt};
+
+    #[test]
+    fn test_json_lines() {
+        let values = vec![
+            serde_json::json!({"name": "foo", "crates": 2}),
+            serde_json::json!({"name": "bar", "crates": 0}),
+        ];
+        assert_eq!(
+            json_lines(&values).unwrap(),
+            vec![
+                r#"{"crates":2,"name":"foo"}"#,
+                r#"{"crates":0,"name":"bar"}"#
+            ]
+        );
+        assert!(json_lines::<u32>(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_json_error() {
+        let err: Error = Err::<(), _>(err_msg("no such table: experiments"))
+            .context("failed to load the experiment")
+            .unwrap_err()
+            .into();
+        let error = JsonError::new(&err, 1);
+        assert_eq!(
+            error,
+            JsonError {
+                error: "failed to load the experiment: no such table: experiments".into(),
+                code: 1,
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#"{"error":"failed to load the experiment: no such table: experiments","code":1}"#
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Progress bars of the long-running commands, drawn on stderr. They're hidden in JSON mode and
+//! when stdout is not a terminal, so the output of scripts isn't cluttered by them.
+
+use crate::cli::output::Output;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Milliseconds between two frames of the spinners.
+const SPINNER_TICK: u64 = 100;
+
+pub fn enabled(output: Output) -> bool {
+    !output.is_json() && atty::is(atty::Stream::Stdout)
+}
+
+/// Bar counting the `len` steps of an operation.
+pub fn bar(output: Output, len: u64, message: &str) -> ProgressBar {
+    if !enabled(output) {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len);
+    bar.set_style(ProgressStyle::default_bar().template("{msg} [{bar:40}] {pos}/{len} ({eta})"));
+    bar.set_message(message);
+    bar
+}
+
+/// Spinner shown while waiting for an operation of unknown length.
+pub fn spinner(output: Output, message: &str) -> ProgressBar {
+    if !enabled(output) {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}"));
+    spinner.set_message(message);
+    spinner.enable_steady_tick(SPINNER_TICK);
+    spinner
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bar, enabled, spinner};
+    use crate::cli::output::Output;
+
+    #[test]
+    fn test_hidden_in_json_mode() {
+        assert!(!enabled(Output::Json));
+        assert!(bar(Output::Json, 10, "exporting").is_hidden());
+        assert!(spinner(Output::Json, "connecting").is_hidden());
+    }
+}
@@ -0,0 +1,110 @@
+//! Follow the generation of the reports by a crater server, through the stream of events of its
+//! public API.
+
+use crate::cli::output::Output;
+use crate::cli::server_api::ServerApi;
+use chrono::{DateTime, Utc};
+use crater::experiments::Status;
+use crater::server::api_types::ReportEvent;
+use failure::Fallible;
+use std::collections::HashMap;
+
+fn format_millis(millis: i64) -> String {
+    format!("{:.1}s", millis as f64 / 1000.0)
+}
+
+/// Line shown for the event, with how long the experiment waited for its report to be generated
+/// if the previous event of the experiment is known.
+fn describe(event: &ReportEvent, previous: Option<DateTime<Utc>>) -> String {
+    let mut line = format!(
+        "{}  {}  {}",
+        event.at.format("%H:%M:%S"),
+        event.experiment,
+        event.status.to_str()
+    );
+    match event.status {
+        Status::GeneratingReport => {
+            if let Some(previous) = previous {
+                let waited = event.at.signed_duration_since(previous);
+                line.push_str(&format!(
+                    " (waited {})",
+                    format_millis(waited.num_milliseconds())
+                ));
+            }
+        }
+        Status::Completed | Status::ReportFailed => {
+            if let Some(duration) = event.duration_ms {
+                line.push_str(&format!(" (took {})", format_millis(duration as i64)));
+            }
+            if let Some(ref error) = event.error {
+                line.push_str(&format!(": {}", error));
+            }
+        }
+        Status::Queued | Status::Running | Status::NeedsReport => {}
+    }
+    line
+}
+
+/// Print the reports changing status until the server goes away.
+pub fn watch(api: &ServerApi, output: Output) -> Fallible<()> {
+    let mut previous = HashMap::new();
+    for event in api.events::<ReportEvent>("reports/events")? {
+        let event = event?;
+        let line = describe(&event, previous.get(&event.experiment).cloned());
+        output.object(&event, |_| println!("{}", line))?;
+
+        match event.status {
+            Status::Completed | Status::ReportFailed => previous.remove(&event.experiment),
+            _ => previous.insert(event.experiment.clone(), event.at),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+    use chrono::{Duration, TimeZone, Utc};
+    use crater::experiments::Status;
+    use crater::server::api_types::ReportEvent;
+
+    #[test]
+    fn test_describe() {
+        let at = Utc.ymd(2019, 1, 1).and_hms(12, 0, 0);
+        let event = |status, duration_ms, error: Option<&str>| ReportEvent {
+            experiment: "pr-1".into(),
+            status,
+            at,
+            duration_ms,
+            error: error.map(String::from),
+        };
+
+        assert_eq!(
+            describe(&event(Status::NeedsReport, None, None), None),
+            "12:00:00  pr-1  needs-report"
+        );
+        assert_eq!(
+            describe(
+                &event(Status::GeneratingReport, None, None),
+                Some(at - Duration::milliseconds(2500))
+            ),
+            "12:00:00  pr-1  generating-report (waited 2.5s)"
+        );
+        // Started watching while the report was being generated
+        assert_eq!(
+            describe(&event(Status::GeneratingReport, None, None), None),
+            "12:00:00  pr-1  generating-report"
+        );
+        assert_eq!(
+            describe(&event(Status::Completed, Some(61_000), None), None),
+            "12:00:00  pr-1  completed (took 61.0s)"
+        );
+        assert_eq!(
+            describe(
+                &event(Status::ReportFailed, Some(100), Some("no space left")),
+                None
+            ),
+            "12:00:00  pr-1  report-failed (took 0.1s): no space left"
+        );
+    }
+}
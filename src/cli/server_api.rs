@@ -0,0 +1,242 @@
+//! Client of the public API of a crater server, used by the commands inspecting its state without
+//! having access to the server's database.
+
+use crate::cli::output::Output;
+use crate::cli::progress;
+use crater::server::api_types::{ApiResponse, CraterToken};
+use failure::{bail, Fallible};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Client, Response};
+use serde::de::DeserializeOwned;
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Read};
+use std::thread;
+use std::time::Duration;
+
+/// Attempts to reach the server before giving up, waiting longer after each failed one.
+const CONNECT_ATTEMPTS: u32 = 4;
+
+pub struct ServerApi {
+    url: String,
+    token: Option<String>,
+    client: Client,
+    output: Output,
+    /// Only the first request is retried, later ones failing mean the server went away.
+    connected: Cell<bool>,
+}
+
+impl ServerApi {
+    pub fn new(url: &str, token: Option<&str>, output: Output) -> Self {
+        ServerApi {
+            url: url.trim_end_matches('/').to_string(),
+            token: token.map(|token| token.to_string()),
+            client: Client::new(),
+            output,
+            connected: Cell::new(false),
+        }
+    }
+
+    fn send(&self, client: &Client, url: &str) -> Fallible<Response> {
+        let request = || {
+            let mut req = client.get(url);
+            if let Some(ref token) = self.token {
+                req = req.header(
+                    AUTHORIZATION,
+                    (CraterToken {
+                        token: token.clone(),
+                    })
+                    .to_string(),
+                );
+            }
+            req.send()
+        };
+        if self.connected.get() {
+            return Ok(request()?);
+        }
+
+        let spinner = progress::spinner(self.output, &format!("connecting to {}", self.url));
+        let mut attempt = 1;
+        let resp = loop {
+            match request() {
+                Ok(resp) => break resp,
+                Err(err) if attempt < CONNECT_ATTEMPTS => {
+                    spinner.set_message(&format!(
+                        "connecting to {} (attempt {} of {}: {})",
+                        self.url,
+                        attempt + 1,
+                        CONNECT_ATTEMPTS,
+                        err
+                    ));
+                    thread::sleep(Duration::from_secs(1 << (attempt - 1)));
+                    attempt += 1;
+                }
+                Err(err) => {
+                    spinner.finish_and_clear();
+                    return Err(err.into());
+                }
+            }
+        };
+        spinner.finish_and_clear();
+        self.connected.set(true);
+        Ok(resp)
+    }
+
+    /// Request `path`, relative to the API prefix, returning `None` if it doesn't exist.
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Fallible<Option<T>> {
+        let url = format!("{}/api/v1/{}", self.url, path);
+        let mut resp = self.send(&self.client, &url)?;
+
+        let status = resp.status();
+        let result: ApiResponse<T> = match resp.json() {
+            Ok(result) => result,
+            Err(_) => bail!("unexpected response from {} (status code {})", url, status),
+        };
+        match result {
+            ApiResponse::Success { result } => Ok(Some(result)),
+            ApiResponse::NotFound => Ok(None),
+            ApiResponse::Unauthorized => bail!("the token was rejected by the server"),
+            ApiResponse::InternalError { error } => bail!("internal server error: {}", error),
+            _ => bail!("request to {} failed with status code {}", url, status),
+        }
+    }
+
+    /// Follow the server-sent events of `path`, relative to the API prefix, until the server
+    /// closes the connection.
+    pub fn events<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Fallible<impl Iterator<Item = Fallible<T>>> {
+        let url = format!("{}/api/v1/{}", self.url, path);
+        // The stream stays open as long as the server runs
+        let client = Client::builder().timeout(None).build()?;
+        let resp = self.send(&client, &url)?;
+        if !resp.status().is_success() {
+            bail!(
+                "request to {} failed with status code {}",
+                url,
+                resp.status()
+            );
+        }
+
+        Ok(EventsReader::new(resp)
+            .map(|data| data.and_then(|data| Ok(serde_json::from_str(&data)?))))
+    }
+}
+
+/// Data of the events of a `text/event-stream`, joining the lines of multi-line events. The names
+/// of the events, their ids and the comments are ignored.
+struct EventsReader<R: Read> {
+    lines: std::io::Lines<BufReader<R>>,
+}
+
+impl<R: Read> EventsReader<R> {
+    fn new(reader: R) -> Self {
+        EventsReader {
+            lines: BufReader::new(reader).lines(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for EventsReader<R> {
+    type Item = Fallible<String>;
+
+    fn next(&mut self) -> Option<Fallible<String>> {
+        let mut data: Option<String> = None;
+        loop {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => return Some(Err(err.into())),
+                // An event not terminated by an empty line is incomplete
+                None => return None,
+            };
+
+            if line.is_empty() {
+                if data.is_some() {
+                    return data.map(Ok);
+                }
+            } else if line.starts_with("data:") {
+                let value = line["data:".len()..].trim_start_matches(' ');
+                match data {
+                    Some(ref mut data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Format the rows with each column padded to its widest cell.
+pub fn format_table(header: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths = header.iter().map(|cell| cell.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let mut lines = vec![format_row(header.to_vec())];
+    for row in rows {
+        lines.push(format_row(row.iter().map(String::as_str).collect()));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_table, EventsReader, ServerApi};
+    use crate::cli::output::Output;
+
+    #[test]
+    fn test_format_table() {
+        let rows = vec![
+            vec![
+                "agent-1".to_string(),
+                "working".to_string(),
+                "foo".to_string(),
+            ],
+            vec!["a".to_string(), "unreachable".to_string(), "-".to_string()],
+        ];
+        assert_eq!(
+            format_table(&["name", "status", "experiment"], &rows),
+            vec![
+                "name     status       experiment",
+                "agent-1  working      foo",
+                "a        unreachable  -",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_server_url() {
+        // The API prefix is appended to the URL, so a trailing slash is removed
+        assert_eq!(
+            ServerApi::new("https://crater.example.com/", None, Output::Text).url,
+            "https://crater.example.com"
+        );
+    }
+
+    #[test]
+    fn test_events_reader() {
+        let stream = "event: report\ndata: {\"a\":1}\n\n\
+                      : comment\n\n\
+                      data: first\ndata:second\n\n\
+                      data: incomplete\n";
+        let events = EventsReader::new(stream.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(events, vec!["{\"a\":1}", "first\nsecond"]);
+    }
+}
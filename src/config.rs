@@ -1,6 +1,11 @@
 use crate::crates::Crate;
+use crate::experiments::CargoPatch;
 use crate::prelude::*;
+use crate::report::ClassificationRule;
+use crate::results::Diagnostic;
+use crate::toolchain::Toolchain;
 use crate::utils::size::Size;
+use crate::utils::string::glob_matches;
 use regex::Regex;
 use serde_regex;
 use std::collections::{HashMap, HashSet};
@@ -8,7 +13,8 @@ use std::env;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use url::Url;
 
 fn default_config_file() -> PathBuf {
     env::var_os("CRATER_CONFIG")
@@ -20,6 +26,66 @@ fn default_config_file() -> PathBuf {
 #[fail(display = "the configuration file has errors")]
 pub struct BadConfig;
 
+/// Maximum size of the content of a file created by a setup step.
+const MAX_SETUP_FILE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum SetupError {
+    #[fail(display = "apt package `{}` is not allowed in the sandbox", _0)]
+    AptPackageNotAllowed(String),
+    #[fail(display = "submodules can only be initialized in GitHub repositories")]
+    SubmodulesWithoutRepo,
+    #[fail(display = "invalid path for the created file: {}", _0)]
+    InvalidFilePath(String),
+    #[fail(display = "the content of the created file is too big")]
+    FileTooBig,
+}
+
+/// Declarative setup operations executed before building a crate. Arbitrary shell commands are
+/// intentionally not supported: every operation is validated before the experiment runs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum SetupStep {
+    AptInstall { packages: Vec<String> },
+    SubmoduleInit,
+    CreateFile { path: String, content: String },
+}
+
+impl SetupStep {
+    pub fn validate(&self, is_repo: bool, allowed_packages: &[String]) -> Result<(), SetupError> {
+        match self {
+            SetupStep::AptInstall { packages } => {
+                for package in packages {
+                    if !allowed_packages.contains(package) {
+                        return Err(SetupError::AptPackageNotAllowed(package.clone()));
+                    }
+                }
+            }
+            SetupStep::SubmoduleInit => {
+                if !is_repo {
+                    return Err(SetupError::SubmodulesWithoutRepo);
+                }
+            }
+            SetupStep::CreateFile { path, content } => {
+                let mut components = Path::new(path).components().peekable();
+                if components.peek().is_none() {
+                    return Err(SetupError::InvalidFilePath(path.clone()));
+                }
+                for (i, component) in components.enumerate() {
+                    match component {
+                        Component::Normal(name) if i != 0 || name != ".git" => {}
+                        _ => return Err(SetupError::InvalidFilePath(path.clone())),
+                    }
+                }
+                if content.len() > MAX_SETUP_FILE_SIZE {
+                    return Err(SetupError::FileTooBig);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct CrateConfig {
@@ -33,6 +99,8 @@ pub struct CrateConfig {
     pub update_lockfile: bool,
     #[serde(default = "default_false")]
     pub broken: bool,
+    #[serde(default)]
+    pub setup: Vec<SetupStep>,
 }
 
 fn default_false() -> bool {
@@ -44,6 +112,148 @@ fn default_false() -> bool {
 pub struct ServerConfig {
     pub bot_acl: Vec<String>,
     pub labels: ServerLabels,
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    #[serde(default)]
+    pub body_limits: BodyLimitsConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    #[serde(default = "ClassificationRule::defaults")]
+    pub classification: Vec<ClassificationRule>,
+    #[serde(default)]
+    pub agent_update: Option<AgentUpdateConfig>,
+    #[serde(default)]
+    pub live_reports: LiveReportsConfig,
+    #[serde(default)]
+    pub reports: ReportsConfig,
+    /// Interleave the crates likely to be slow with the fast ones when handing out experiments,
+    /// based on how long the previous experiments took to build them.
+    #[serde(default = "default_false")]
+    pub weighted_scheduling: bool,
+    /// URL the web UI of the server is reachable at. The crates in the reports link to their
+    /// history on it.
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// Digest emailed when the report of an experiment is published.
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+}
+
+/// SMTP server sending the digests of the reports, authenticated with the credentials in the
+/// `[email]` section of `tokens.toml` if there are any.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmailConfig {
+    /// Host of the server, which must accept TLS connections on the submissions port (465).
+    pub smtp_host: String,
+    pub from: String,
+    pub recipients: Vec<String>,
+}
+
+/// Binary the agents replace themselves with between experiments.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AgentUpdateConfig {
+    pub url: String,
+    /// Hex-encoded SHA256 checksum of the binary, verified before installing it.
+    pub sha256: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct BodyLimitsConfig {
+    /// Results uploaded by the agents, including their base64-encoded build logs.
+    pub results: Size,
+    /// Other requests sent by the agents, which only contain small payloads.
+    pub agent: Size,
+    /// Events delivered by GitHub.
+    pub webhooks: Size,
+}
+
+/// Seconds clients have to send the request and get a response, depending on the kind of token
+/// they send.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct TimeoutsConfig {
+    pub agent: u64,
+    pub anonymous: u64,
+}
+
+/// How often the live reports of the running experiments are updated: they're updated once
+/// enough new results are recorded, or once some time passed since the last update.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LiveReportsConfig {
+    pub interval_minutes: u64,
+    pub results: u32,
+}
+
+/// Where the server publishes the reports of the experiments.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ReportsConfig {
+    pub destination: ReportsDestination,
+    /// Base URL the published reports are served from, included in the messages posted on
+    /// GitHub. Defaults to the `public-url` of the bucket configured in `tokens.toml`.
+    pub public_url: Option<String>,
+    /// How long the links to the reports of private experiments stay valid.
+    pub private_link_hours: u32,
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        ReportsConfig {
+            destination: ReportsDestination::Bucket { public_read: true },
+            public_url: None,
+            private_link_hours: 24,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ReportsDestination {
+    /// The S3-compatible bucket configured in `tokens.toml`.
+    #[serde(rename_all = "kebab-case")]
+    Bucket {
+        /// Upload the files with the `public-read` ACL, for buckets that are not public.
+        #[serde(default = "default_true")]
+        public_read: bool,
+    },
+    /// Local directory, for example served by a web server.
+    Directory { path: PathBuf },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for LiveReportsConfig {
+    fn default() -> Self {
+        LiveReportsConfig {
+            interval_minutes: 30,
+            results: 5000,
+        }
+    }
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        TimeoutsConfig {
+            agent: 30,
+            anonymous: 10,
+        }
+    }
+}
+
+impl Default for BodyLimitsConfig {
+    fn default() -> Self {
+        BodyLimitsConfig {
+            results: Size::Megabytes(32),
+            agent: Size::Kilobytes(64),
+            webhooks: Size::Megabytes(5),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -69,6 +279,194 @@ pub struct SandboxConfig {
     pub memory_limit: Size,
     pub build_log_max_size: Size,
     pub build_log_max_lines: usize,
+    #[serde(default)]
+    pub allowed_apt_packages: Vec<String>,
+    /// Git repositories experiments can patch the dependencies of the crates with. Entries ending
+    /// with a slash allow all the repositories starting with them.
+    #[serde(default)]
+    pub allowed_patch_sources: Vec<String>,
+    #[serde(default, with = "optional_url")]
+    pub registry_mirror: Option<Url>,
+    /// Store the result and the log of the build and of the tests of each crate separately, in
+    /// addition to the ones of the whole job.
+    #[serde(default)]
+    pub record_phases: bool,
+}
+
+mod optional_url {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use url::Url;
+
+    pub(super) fn serialize<S: Serializer>(
+        url: &Option<Url>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match url {
+            Some(url) => serializer.serialize_some(url.as_str()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Url>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(url) => Url::parse(&url).map(Some).map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PartialLogsConfig {
+    pub enabled: bool,
+    pub interval: u64,
+    pub max_chunk_size: Size,
+    pub max_chunks: usize,
+}
+
+impl Default for PartialLogsConfig {
+    fn default() -> Self {
+        PartialLogsConfig {
+            enabled: false,
+            interval: 60,
+            max_chunk_size: Size::Kilobytes(256),
+            max_chunks: 64,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PrefetchConfig {
+    /// How many crates are downloaded ahead of the ones being built.
+    pub crates: usize,
+    /// Prefetching stops when the source cache reaches this size.
+    pub max_cache_size: Size,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        PrefetchConfig {
+            crates: 16,
+            max_cache_size: Size::Gigabytes(10),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DiskSpaceConfig {
+    /// Free space required on the disk of the work directory before preparing a crate.
+    pub min_free: Size,
+    /// How often the free space is checked again while the agent is paused.
+    pub retry_interval: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DownloadsConfig {
+    /// How many times a crates.io crate is downloaded again when the download fails or its
+    /// checksum doesn't match the one recorded by the registry.
+    pub retries: u32,
+}
+
+impl Default for DownloadsConfig {
+    fn default() -> Self {
+        DownloadsConfig { retries: 3 }
+    }
+}
+
+/// GitHub instance the bot talks to.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct GitHubConfig {
+    /// Base URL of the REST API, like `https://github.example.com/api/v3` for GitHub Enterprise.
+    pub api_url: String,
+    /// Maximum number of seconds to wait before retrying a failed call, even if GitHub asks to
+    /// wait longer for the rate limit to reset.
+    pub max_retry_delay: u64,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        GitHubConfig {
+            api_url: "https://api.github.com".into(),
+            max_retry_delay: 300,
+        }
+    }
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        DiskSpaceConfig {
+            min_free: Size::Gigabytes(10),
+            retry_interval: 60,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct DatabaseConfig {
+    /// Store the server data in this PostgreSQL database instead of the local SQLite file.
+    pub postgres_url: Option<String>,
+    pub backups: Option<BackupsConfig>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupsConfig {
+    /// Local directory or S3 prefix (`s3://bucket/path`) the snapshots are written to.
+    pub destination: String,
+    #[serde(default = "default_backups_interval_hours")]
+    pub interval_hours: u64,
+    /// Number of snapshots kept in the destination, the older ones are removed.
+    #[serde(default = "default_backups_keep")]
+    pub keep: usize,
+}
+
+/// Failure expected in the experiments, like a crate requiring a nightly feature that's not
+/// stabilized yet. Regressions matching it are reported as known failures instead.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KnownFailure {
+    /// Glob matching the name of the crate, like `tokio-*`.
+    pub crate_pattern: String,
+    /// Glob matching the toolchain the crate fails with, any toolchain by default.
+    #[serde(default = "default_toolchain_pattern")]
+    pub toolchain_pattern: String,
+    /// Only match the failures emitting an error with this code, like `E0658`.
+    #[serde(default)]
+    pub error_code: Option<String>,
+    pub reason: String,
+}
+
+fn default_toolchain_pattern() -> String {
+    "*".into()
+}
+
+impl KnownFailure {
+    fn matches(&self, krate: &Crate, toolchain: &Toolchain, diagnostics: &[Diagnostic]) -> bool {
+        glob_matches(&self.crate_pattern, &krate.name())
+            && glob_matches(&self.toolchain_pattern, &toolchain.to_string())
+            && match self.error_code {
+                Some(ref code) => diagnostics.iter().any(|diagnostic| {
+                    diagnostic.level == "error" && diagnostic.code.as_ref() == Some(code)
+                }),
+                None => true,
+            }
+    }
+}
+
+fn default_backups_interval_hours() -> u64 {
+    24
+}
+
+fn default_backups_keep() -> usize {
+    7
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -80,13 +478,30 @@ pub struct Config {
     pub local_crates: HashMap<String, CrateConfig>,
     pub server: ServerConfig,
     pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub partial_logs: PartialLogsConfig,
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+    #[serde(default)]
+    pub disk_space: DiskSpaceConfig,
+    #[serde(default)]
+    pub downloads: DownloadsConfig,
+    #[serde(default)]
+    pub github: GitHubConfig,
+    #[serde(default)]
+    pub known_failures: Vec<KnownFailure>,
+    // The connection string might contain credentials, so it's not sent to the agents
+    #[serde(default, skip_serializing)]
+    pub database: DatabaseConfig,
 }
 
 impl Config {
     pub fn load() -> Fallible<Self> {
         let buffer = Self::load_as_string(default_config_file())?;
 
-        Ok(::toml::from_str(&buffer)?)
+        let config: Self = ::toml::from_str(&buffer)?;
+        config.check_setup_steps()?;
+        Ok(config)
     }
 
     fn load_as_string(filename: PathBuf) -> Fallible<String> {
@@ -126,6 +541,32 @@ impl Config {
         self.crate_config(c).map(|c| c.broken).unwrap_or(false)
     }
 
+    /// Known failure matching the failure of the crate with the toolchain, given the diagnostics
+    /// emitted by the failed build.
+    pub fn known_failure(
+        &self,
+        c: &Crate,
+        toolchain: &Toolchain,
+        diagnostics: &[Diagnostic],
+    ) -> Option<&KnownFailure> {
+        self.known_failures
+            .iter()
+            .find(|known| known.matches(c, toolchain, diagnostics))
+    }
+
+    pub fn setup_steps(&self, c: &Crate) -> &[SetupStep] {
+        self.crate_config(c)
+            .map(|c| c.setup.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether experiments are allowed to patch the crates' dependencies with this repository.
+    pub fn is_patch_allowed(&self, patch: &CargoPatch) -> bool {
+        self.sandbox.allowed_patch_sources.iter().any(|allowed| {
+            patch.git == *allowed || (allowed.ends_with('/') && patch.git.starts_with(allowed))
+        })
+    }
+
     pub fn demo_crates(&self) -> &DemoCrates {
         &self.demo_crates
     }
@@ -144,7 +585,8 @@ impl Config {
         let buffer = Self::load_as_string(filename)?;
         let mut has_errors = Self::check_for_dup_keys(&buffer).is_err();
         let cfg: Self = ::toml::from_str(&buffer)?;
-        let db = crate::db::Database::open()?;
+        has_errors |= cfg.check_setup_steps().is_err();
+        let db = crate::db::Database::open(&cfg)?;
         let crates = crate::crates::lists::get_crates(CrateSelect::Full, &db, &cfg)?;
         has_errors |= cfg.check_for_missing_crates(&crates).is_err();
         has_errors |= cfg.check_for_missing_repos(&crates).is_err();
@@ -164,6 +606,31 @@ impl Config {
         }
     }
 
+    fn check_setup_steps(&self) -> Fallible<()> {
+        let allowed = &self.sandbox.allowed_apt_packages;
+        let all = self
+            .crates
+            .iter()
+            .map(|(name, c)| (name, c, false))
+            .chain(self.github_repos.iter().map(|(name, c)| (name, c, true)))
+            .chain(self.local_crates.iter().map(|(name, c)| (name, c, false)));
+
+        let mut any_invalid = false;
+        for (name, crate_config, is_repo) in all {
+            for step in &crate_config.setup {
+                if let Err(err) = step.validate(is_repo, allowed) {
+                    error!("invalid setup step for `{}`: {}", name, err);
+                    any_invalid = true;
+                }
+            }
+        }
+        if any_invalid {
+            Err(BadConfig.into())
+        } else {
+            Ok(())
+        }
+    }
+
     fn check_for_missing_crates(&self, crates: &[Crate]) -> Fallible<()> {
         if self.crates.is_empty() {
             return Ok(());
@@ -245,7 +712,18 @@ impl Default for Config {
                 memory_limit: Size::Gigabytes(2),
                 build_log_max_size: Size::Megabytes(1),
                 build_log_max_lines: 1000,
+                allowed_apt_packages: Vec::new(),
+                allowed_patch_sources: Vec::new(),
+                registry_mirror: None,
+                record_phases: false,
             },
+            partial_logs: PartialLogsConfig::default(),
+            prefetch: PrefetchConfig::default(),
+            disk_space: DiskSpaceConfig::default(),
+            downloads: DownloadsConfig::default(),
+            github: GitHubConfig::default(),
+            known_failures: Vec::new(),
+            database: DatabaseConfig::default(),
             server: ServerConfig {
                 bot_acl: Vec::new(),
                 labels: ServerLabels {
@@ -253,6 +731,16 @@ impl Default for Config {
                     experiment_queued: "".into(),
                     experiment_completed: "".into(),
                 },
+                cors_allowed_origins: Vec::new(),
+                body_limits: BodyLimitsConfig::default(),
+                timeouts: TimeoutsConfig::default(),
+                classification: ClassificationRule::defaults(),
+                agent_update: None,
+                live_reports: LiveReportsConfig::default(),
+                reports: ReportsConfig::default(),
+                weighted_scheduling: false,
+                public_url: None,
+                email: None,
             },
         }
     }
@@ -260,8 +748,12 @@ impl Default for Config {
 
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use super::{Config, KnownFailure, SetupError, SetupStep};
     use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::experiments::CargoPatch;
+    use crate::results::Diagnostic;
+    use crate::toolchain::Toolchain;
+    use crate::utils::size::Size;
 
     #[test]
     fn test_config() {
@@ -273,6 +765,10 @@ mod tests {
             "remove = \"\"\n",
             "experiment-queued = \"\"\n",
             "experiment-completed = \"\"\n",
+            "[server.body-limits]\n",
+            "results = \"64M\"\n",
+            "[server.timeouts]\n",
+            "agent = 60\n",
             "[demo-crates]\n",
             "crates = []\n",
             "github-repos = []\n",
@@ -281,8 +777,13 @@ mod tests {
             "memory-limit = \"2G\"\n",
             "build-log-max-size = \"2M\"\n",
             "build-log-max-lines = 1000\n",
+            "allowed-apt-packages = [\"libasound2-dev\"]\n",
+            "[partial-logs]\n",
+            "enabled = true\n",
+            "max-chunk-size = \"64K\"\n",
             "[crates]\n",
             "lazy_static = { skip = true }\n",
+            "alsa-sys = { setup = [{ action = \"apt-install\", packages = [\"libasound2-dev\"] }] }\n",
             "[github-repos]\n",
             "\"rust-lang/rust\" = { quiet = true }\n", // :(
             "[local-crates]\n"
@@ -307,5 +808,173 @@ mod tests {
             org: "rust-lang".into(),
             name: "cargo".into(),
         })));
+
+        assert_eq!(
+            list.setup_steps(&Crate::Registry(RegistryCrate {
+                name: "alsa-sys".into(),
+                version: "0.1.2".into(),
+            })),
+            &[SetupStep::AptInstall {
+                packages: vec!["libasound2-dev".into()],
+            }]
+        );
+        assert!(list.check_setup_steps().is_ok());
+
+        assert!(list.partial_logs.enabled);
+        assert_eq!(list.partial_logs.max_chunk_size, Size::Kilobytes(64));
+        assert_eq!(list.partial_logs.max_chunks, 64);
+
+        assert_eq!(list.server.body_limits.results, Size::Megabytes(64));
+        assert_eq!(list.server.body_limits.agent, Size::Kilobytes(64));
+        assert_eq!(list.server.body_limits.webhooks, Size::Megabytes(5));
+        assert_eq!(list.server.timeouts.agent, 60);
+        assert_eq!(list.server.timeouts.anonymous, 10);
+    }
+
+    #[test]
+    fn test_known_failures() {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct KnownFailures {
+            known_failures: Vec<KnownFailure>,
+        }
+
+        let mut config = Config::default();
+        config.known_failures = ::toml::from_str::<KnownFailures>(concat!(
+            "[[known-failures]]\n",
+            "crate-pattern = \"packed_simd*\"\n",
+            "toolchain-pattern = \"beta-*\"\n",
+            "error-code = \"E0658\"\n",
+            "reason = \"unstable feature\"\n",
+            "[[known-failures]]\n",
+            "crate-pattern = \"rust-lang.*\"\n",
+            "reason = \"upstream bug\"\n",
+        ))
+        .unwrap()
+        .known_failures;
+
+        let packed_simd = Crate::Registry(RegistryCrate {
+            name: "packed_simd_2".into(),
+            version: "0.3.0".into(),
+        });
+        let diagnostic = |level: &str, code: &str| Diagnostic {
+            level: level.into(),
+            code: Some(code.into()),
+            message: "use of unstable library feature".into(),
+            file: None,
+            line: None,
+            column: None,
+        };
+        let beta: Toolchain = "beta-2019-01-01".parse().unwrap();
+        let stable: Toolchain = "stable".parse().unwrap();
+
+        let known = config
+            .known_failure(&packed_simd, &beta, &[diagnostic("error", "E0658")])
+            .unwrap();
+        assert_eq!(known.reason, "unstable feature");
+        // The toolchain and the error code must match too
+        assert!(config
+            .known_failure(&packed_simd, &stable, &[diagnostic("error", "E0658")])
+            .is_none());
+        assert!(config
+            .known_failure(&packed_simd, &beta, &[diagnostic("error", "E0308")])
+            .is_none());
+        assert!(config
+            .known_failure(&packed_simd, &beta, &[diagnostic("warning", "E0658")])
+            .is_none());
+
+        // Without an error code any failure with any toolchain matches
+        let repo = Crate::GitHub(GitHubRepo {
+            org: "rust-lang".into(),
+            name: "rust".into(),
+        });
+        assert_eq!(
+            config.known_failure(&repo, &stable, &[]).unwrap().reason,
+            "upstream bug"
+        );
+        assert!(config
+            .known_failure(
+                &Crate::Registry(RegistryCrate {
+                    name: "lazy_static".into(),
+                    version: "1.0.0".into(),
+                }),
+                &stable,
+                &[],
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_setup_steps_validation() {
+        let allowed = vec!["libasound2-dev".to_string()];
+
+        let apt = |pkg: &str| SetupStep::AptInstall {
+            packages: vec![pkg.into()],
+        };
+        assert_eq!(apt("libasound2-dev").validate(false, &allowed), Ok(()));
+        assert_eq!(
+            apt("libudev-dev").validate(false, &allowed),
+            Err(SetupError::AptPackageNotAllowed("libudev-dev".into()))
+        );
+        assert_eq!(
+            apt("libasound2-dev; rm -rf /").validate(false, &allowed),
+            Err(SetupError::AptPackageNotAllowed(
+                "libasound2-dev; rm -rf /".into()
+            ))
+        );
+
+        assert_eq!(SetupStep::SubmoduleInit.validate(true, &allowed), Ok(()));
+        assert_eq!(
+            SetupStep::SubmoduleInit.validate(false, &allowed),
+            Err(SetupError::SubmodulesWithoutRepo)
+        );
+
+        let file = |path: &str, content: String| SetupStep::CreateFile {
+            path: path.into(),
+            content,
+        };
+        assert_eq!(
+            file("src/generated.rs", String::new()).validate(false, &allowed),
+            Ok(())
+        );
+        for path in &[
+            "",
+            "/etc/passwd",
+            "../outside",
+            "src/../../outside",
+            ".git/config",
+        ] {
+            assert_eq!(
+                file(path, String::new()).validate(false, &allowed),
+                Err(SetupError::InvalidFilePath(path.to_string()))
+            );
+        }
+        assert_eq!(
+            file("big.txt", ".".repeat(super::MAX_SETUP_FILE_SIZE + 1)).validate(false, &allowed),
+            Err(SetupError::FileTooBig)
+        );
+    }
+
+    #[test]
+    fn test_allowed_patch_sources() {
+        let mut config = Config::default();
+        config.sandbox.allowed_patch_sources = vec![
+            "https://github.com/tokio-rs/tokio".into(),
+            "https://github.com/rust-lang/".into(),
+        ];
+        let allowed = |git: &str| {
+            config.is_patch_allowed(&CargoPatch {
+                name: "foo".into(),
+                git: git.into(),
+                rev: None,
+            })
+        };
+
+        assert!(allowed("https://github.com/tokio-rs/tokio"));
+        assert!(allowed("https://github.com/rust-lang/futures-rs"));
+        // Only entries ending with a slash are prefixes
+        assert!(!allowed("https://github.com/tokio-rs/tokio-evil"));
+        assert!(!allowed("https://github.com/rust-lang-evil/futures-rs"));
+        assert!(!allowed("https://example.com/tokio"));
     }
 }
@@ -61,6 +61,13 @@ pub(crate) trait List {
     }
 }
 
+/// The `count` most popular crates on crates.io, starting from the most popular one.
+pub fn top_crates(db: &Database, count: usize) -> Fallible<Vec<Crate>> {
+    let mut crates = RegistryList::get(db)?;
+    crates.truncate(count);
+    Ok(crates)
+}
+
 pub(crate) fn get_crates(
     select: CrateSelect,
     db: &Database,
@@ -123,8 +130,7 @@ pub(crate) fn get_crates(
             crates.truncate(SMALL_RANDOM_COUNT);
         }
         CrateSelect::Top100 => {
-            crates.append(&mut RegistryList::get(db)?);
-            crates.truncate(100);
+            crates.append(&mut top_crates(db, 100)?);
         }
         CrateSelect::Local => {
             crates.append(&mut LocalList::get(db)?);
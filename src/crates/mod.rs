@@ -1,16 +1,22 @@
 pub(crate) mod lists;
 mod sources;
 
+use crate::config::Config;
 use crate::dirs::LOCAL_CRATES_DIR;
 use crate::prelude::*;
 use std::fmt;
 use std::path::Path;
 use std::str::FromStr;
+use utoipa::ToSchema;
 
+pub use crate::crates::lists::top_crates;
 pub(crate) use crate::crates::sources::github::GitHubRepo;
-pub(crate) use crate::crates::sources::registry::RegistryCrate;
+pub(crate) use crate::crates::sources::registry::cache_size as registry_cache_size;
+pub(crate) use crate::crates::sources::registry::prune_cache as prune_registry_cache;
+pub use crate::crates::sources::registry::RegistryCrate;
+pub(crate) use crate::crates::sources::registry::RegistryIndex;
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone, ToSchema)]
 pub enum Crate {
     Registry(RegistryCrate),
     GitHub(GitHubRepo),
@@ -26,7 +32,7 @@ impl Crate {
         }
     }
 
-    pub(crate) fn id(&self) -> String {
+    pub fn id(&self) -> String {
         match *self {
             Crate::Registry(ref details) => format!("reg/{}/{}", details.name, details.version),
             Crate::GitHub(ref repo) => format!("gh/{}/{}", repo.org, repo.name),
@@ -34,14 +40,50 @@ impl Crate {
         }
     }
 
-    pub(crate) fn fetch(&self) -> Fallible<()> {
+    /// Name shared by all the versions of the crate, which the history of its results is looked
+    /// up by. GitHub repositories are named like in the reports, as `org.repo`.
+    pub(crate) fn name(&self) -> String {
         match *self {
-            Crate::Registry(ref krate) => krate.fetch(),
+            Crate::Registry(ref details) => details.name.clone(),
+            Crate::GitHub(ref repo) => format!("{}.{}", repo.org, repo.name),
+            Crate::Local(ref name) => name.clone(),
+        }
+    }
+
+    pub(crate) fn from_id(id: &str) -> Fallible<Self> {
+        let parts = id.split('/').collect::<Vec<_>>();
+        Ok(match parts.as_slice() {
+            ["reg", name, version] => Crate::Registry(RegistryCrate {
+                name: name.to_string(),
+                version: version.to_string(),
+            }),
+            ["gh", org, name] => Crate::GitHub(GitHubRepo {
+                org: org.to_string(),
+                name: name.to_string(),
+            }),
+            ["local", name] => Crate::Local(name.to_string()),
+            _ => bail!("invalid crate id: {}", id),
+        })
+    }
+
+    pub(crate) fn fetch(&self, config: &Config) -> Fallible<()> {
+        match *self {
+            Crate::Registry(ref krate) => krate.fetch(config),
             Crate::GitHub(ref repo) => repo.fetch(),
             Crate::Local(_) => Ok(()),
         }
     }
 
+    /// Download the crate ahead of time, returning how many bytes were added to the cache. Only
+    /// crates.io crates are prefetched, as cloning the same repository twice at the same time
+    /// would fail.
+    pub(crate) fn prefetch(&self, config: &Config) -> Fallible<u64> {
+        match *self {
+            Crate::Registry(ref krate) => krate.prefetch(config),
+            Crate::GitHub(_) | Crate::Local(_) => Ok(0),
+        }
+    }
+
     pub(crate) fn copy_to(&self, dest: &Path) -> Fallible<()> {
         if dest.exists() {
             info!(
@@ -95,3 +137,30 @@ impl FromStr for Crate {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Crate, GitHubRepo, RegistryCrate};
+
+    #[test]
+    fn test_id_roundtrip() {
+        let crates = vec![
+            Crate::Registry(RegistryCrate {
+                name: "lazy_static".into(),
+                version: "1.0.0".into(),
+            }),
+            Crate::GitHub(GitHubRepo {
+                org: "brson".into(),
+                name: "hello-rs".into(),
+            }),
+            Crate::Local("build-pass".into()),
+        ];
+
+        for krate in crates {
+            assert_eq!(Crate::from_id(&krate.id()).unwrap(), krate);
+        }
+
+        assert!(Crate::from_id("reg/lazy_static").is_err());
+        assert!(Crate::from_id("foo/bar/baz").is_err());
+    }
+}
@@ -5,6 +5,7 @@ use crate::run::RunCommand;
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use utoipa::ToSchema;
 
 static CACHED_LIST: &'static str =
     "https://raw.githubusercontent.com/rust-ops/rust-repos/master/data/github.csv";
@@ -66,7 +67,7 @@ impl List for GitHubList {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone, ToSchema)]
 pub struct GitHubRepo {
     pub org: String,
     pub name: String,
@@ -1,15 +1,46 @@
+use crate::config::Config;
 use crate::crates::{lists::List, Crate};
-use crate::dirs::{LOCAL_DIR, SOURCE_CACHE_DIR};
+use crate::dirs::{REGISTRY_INDEX_DIR, SOURCE_CACHE_DIR};
 use crate::prelude::*;
 use crates_index::Index;
 use flate2::read::GzDecoder;
+use ring::digest;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Read};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use tar::Archive;
+use tempfile::NamedTempFile;
+use utoipa::ToSchema;
+use walkdir::WalkDir;
 
 static CRATES_ROOT: &str = "https://crates-io.s3-us-west-1.amazonaws.com/crates";
+static CRATES_API: &str = "https://crates.io/api/v1/crates";
+
+#[derive(Debug, Fail)]
+pub enum DownloadError {
+    #[fail(display = "checksum mismatch: expected {}, got {}", expected, actual)]
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+/// Version of a crate in the registry index, only including the needed fields.
+#[derive(Deserialize)]
+struct IndexVersion {
+    vers: String,
+}
+
+/// Metadata of a version returned by the crates.io API, only including the needed fields.
+#[derive(Deserialize)]
+struct VersionMetadata {
+    version: VersionChecksum,
+}
+
+#[derive(Deserialize)]
+struct VersionChecksum {
+    checksum: String,
+}
 
 pub(crate) struct RegistryList;
 
@@ -20,7 +51,7 @@ impl List for RegistryList {
         let mut list = Vec::new();
         let mut counts = HashMap::new();
 
-        let index = Index::new(LOCAL_DIR.join("crates.io-index"));
+        let index = Index::new(&*REGISTRY_INDEX_DIR);
         index.retrieve_or_update().to_failure()?;
 
         for krate in index.crates() {
@@ -60,21 +91,107 @@ impl List for RegistryList {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+/// Local clone of the crates.io index, used to check which versions of a crate were published.
+pub(crate) struct RegistryIndex {
+    path: PathBuf,
+}
+
+impl RegistryIndex {
+    /// Open the index at `path`, cloning it first if it's missing.
+    pub(crate) fn open(path: &Path) -> Fallible<Self> {
+        if !path.exists() {
+            Index::new(path).retrieve().to_failure()?;
+        }
+        Ok(RegistryIndex {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Path of the file listing the versions of the crate, following the layout of the index.
+    fn crate_path(&self, name: &str) -> PathBuf {
+        let name = name.to_lowercase();
+        match name.len() {
+            1 => self.path.join("1").join(&name),
+            2 => self.path.join("2").join(&name),
+            3 => self.path.join("3").join(&name[..1]).join(&name),
+            _ => self.path.join(&name[..2]).join(&name[2..4]).join(&name),
+        }
+    }
+
+    /// Versions of the crate published in the registry, yanked ones included as they can still
+    /// be downloaded. Crates missing from the index have no versions.
+    pub(crate) fn versions(&self, name: &str) -> Fallible<Vec<String>> {
+        let path = self.crate_path(name);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        fs::read_to_string(&path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| -> Fallible<String> {
+                Ok(::serde_json::from_str::<IndexVersion>(line)?.vers)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone, ToSchema)]
 pub struct RegistryCrate {
     pub name: String,
     pub version: String,
 }
 
+/// Crates pinned to a version are written as `name@version`, like in `cargo install`.
+impl FromStr for RegistryCrate {
+    type Err = ::failure::Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut parts = s.splitn(2, '@');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(version)) if !name.is_empty() && !version.is_empty() => {
+                Ok(RegistryCrate {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            _ => bail!("invalid crate version {}, expected name@version", s),
+        }
+    }
+}
+
+impl fmt::Display for RegistryCrate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
 impl RegistryCrate {
+    fn cache_dir(&self) -> PathBuf {
+        SOURCE_CACHE_DIR.join("reg").join(&self.name)
+    }
+
     fn cached_path(&self) -> PathBuf {
-        SOURCE_CACHE_DIR
-            .join("reg")
-            .join(&self.name)
+        self.cache_dir()
             .join(format!("{}-{}.crate", self.name, self.version))
     }
 
-    pub(in crate::crates) fn fetch(&self) -> Fallible<()> {
+    /// SHA256 checksum of the crate, recorded by the registry when the crate was published.
+    fn expected_checksum(&self) -> Fallible<String> {
+        let url = format!("{}/{}/{}", CRATES_API, self.name, self.version);
+        let metadata: VersionMetadata = crate::utils::http::get_sync(&url)?.json()?;
+        Ok(metadata.version.checksum)
+    }
+
+    /// The archive of this exact version, even if a newer one was published.
+    fn download_url(&self) -> String {
+        format!(
+            "{0}/{1}/{1}-{2}.crate",
+            CRATES_ROOT, self.name, self.version
+        )
+    }
+
+    pub(in crate::crates) fn fetch(&self, config: &Config) -> Fallible<()> {
         let local = self.cached_path();
         if local.exists() {
             info!("crate {} {} is already in cache", self.name, self.version);
@@ -82,19 +199,39 @@ impl RegistryCrate {
         }
 
         info!("fetching crate {} {}...", self.name, self.version);
-        if let Some(parent) = local.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        let remote = format!(
-            "{0}/{1}/{1}-{2}.crate",
-            CRATES_ROOT, self.name, self.version
-        );
-        let mut resp = crate::utils::http::get_sync(&remote)?;
-        resp.copy_to(&mut BufWriter::new(File::create(&local)?))?;
+        let cache_dir = self.cache_dir();
+        fs::create_dir_all(&cache_dir)?;
+        let expected = self.expected_checksum().with_context(|_| {
+            format!(
+                "unable to load the checksum of {} version {}",
+                self.name, self.version
+            )
+        })?;
+        let remote = self.download_url();
+
+        // The crate is downloaded to a temporary file first, so a partial download is never
+        // mistaken for a cached crate if the prefetcher and a worker fetch it at the same time
+        let tmp = download_verified(&cache_dir, &expected, config.downloads.retries, |dest| {
+            let mut resp = crate::utils::http::get_sync(&remote)?;
+            io::copy(&mut resp, dest)?;
+            Ok(())
+        })
+        .with_context(|_| format!("unable to download {} version {}", self.name, self.version))?;
+        tmp.persist(&local)?;
 
         Ok(())
     }
 
+    /// Fetch the crate if it's not cached yet, returning how many bytes were downloaded.
+    pub(in crate::crates) fn prefetch(&self, config: &Config) -> Fallible<u64> {
+        if self.cached_path().exists() {
+            return Ok(0);
+        }
+
+        self.fetch(config)?;
+        Ok(fs::metadata(self.cached_path())?.len())
+    }
+
     pub(in crate::crates) fn copy_to(&self, dest: &Path) -> Fallible<()> {
         let cached = self.cached_path();
         let mut file = File::open(cached)?;
@@ -120,6 +257,63 @@ impl RegistryCrate {
     }
 }
 
+/// Download a file to a temporary file in `dir`, downloading it again up to `retries` times if the
+/// download fails or its SHA256 checksum doesn't match the expected one.
+fn download_verified<F>(
+    dir: &Path,
+    expected: &str,
+    retries: u32,
+    download: F,
+) -> Fallible<NamedTempFile>
+where
+    F: Fn(&mut dyn Write) -> Fallible<()>,
+{
+    let attempt = || -> Fallible<NamedTempFile> {
+        let mut tmp = NamedTempFile::new_in(dir)?;
+        {
+            let mut writer = BufWriter::new(tmp.as_file_mut());
+            download(&mut writer)?;
+            writer.flush()?;
+        }
+
+        let actual = file_sha256(tmp.path())?;
+        if actual != expected {
+            return Err(DownloadError::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual,
+            }
+            .into());
+        }
+        Ok(tmp)
+    };
+
+    let mut retry = 0;
+    loop {
+        match attempt() {
+            Ok(tmp) => return Ok(tmp),
+            Err(err) if retry < retries => {
+                retry += 1;
+                warn!("download failed: {} (retry {}/{})", err, retry, retries);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn file_sha256(path: &Path) -> Fallible<String> {
+    let mut file = File::open(path)?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buffer = [0; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(crate::utils::hex::to_hex(context.finish().as_ref()))
+}
+
 fn unpack_without_first_dir<R: Read>(archive: &mut Archive<R>, path: &Path) -> Fallible<()> {
     let entries = archive.entries()?;
     for entry in entries {
@@ -141,3 +335,139 @@ fn unpack_without_first_dir<R: Read>(archive: &mut Archive<R>, path: &Path) -> F
 
     Ok(())
 }
+
+/// Total size of the crates.io crates stored in the cache, in bytes.
+pub(crate) fn cache_size() -> Fallible<u64> {
+    let dir = SOURCE_CACHE_DIR.join("reg");
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut size = 0;
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Remove all the crates.io crates stored in the cache, to free some disk space. They're
+/// downloaded again when they're needed.
+pub(crate) fn prune_cache() -> Fallible<()> {
+    let dir = SOURCE_CACHE_DIR.join("reg");
+    if dir.exists() {
+        crate::utils::fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{download_verified, file_sha256, DownloadError, RegistryCrate, RegistryIndex};
+    use std::cell::Cell;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    // SHA256 checksum of "crate"
+    const CHECKSUM: &str = "f5fe331d2367a7a67ee20bd579c77b929ae49439d8b0d8e9c3b98609797b6b69";
+
+    #[test]
+    fn test_download_verified() {
+        let dir = tempdir().unwrap();
+        let checksum = CHECKSUM.to_string();
+
+        // A clean download passes the verification right away
+        let attempts = Cell::new(0);
+        let tmp = download_verified(dir.path(), &checksum, 3, |dest| {
+            attempts.set(attempts.get() + 1);
+            dest.write_all(b"crate")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(file_sha256(tmp.path()).unwrap(), CHECKSUM);
+
+        // Corrupt downloads are retried until a clean one is received
+        attempts.set(0);
+        let tmp = download_verified(dir.path(), &checksum, 3, |dest| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                dest.write_all(b"corrupt")?;
+            } else {
+                dest.write_all(b"crate")?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(fs::read(tmp.path()).unwrap(), b"crate");
+
+        // The download fails once the retries are exhausted
+        attempts.set(0);
+        let err = download_verified(dir.path(), &checksum, 2, |dest| {
+            attempts.set(attempts.get() + 1);
+            dest.write_all(b"corrupt")?;
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(attempts.get(), 3);
+        match err.downcast_ref::<DownloadError>() {
+            Some(DownloadError::ChecksumMismatch { expected, .. }) => {
+                assert_eq!(expected, &checksum)
+            }
+            None => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_pinned_crate() {
+        let krate: RegistryCrate = "lazy_static@0.2.11".parse().unwrap();
+        assert_eq!(
+            krate,
+            RegistryCrate {
+                name: "lazy_static".into(),
+                version: "0.2.11".into(),
+            }
+        );
+        assert_eq!(krate.to_string(), "lazy_static@0.2.11");
+        // The pinned version is downloaded, not the latest one
+        assert!(krate
+            .download_url()
+            .ends_with("/lazy_static/lazy_static-0.2.11.crate"));
+
+        assert!("lazy_static".parse::<RegistryCrate>().is_err());
+        assert!("lazy_static@".parse::<RegistryCrate>().is_err());
+        assert!("@0.2.11".parse::<RegistryCrate>().is_err());
+    }
+
+    #[test]
+    fn test_registry_index_versions() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("la/zy")).unwrap();
+        fs::write(
+            dir.path().join("la/zy/lazy_static"),
+            concat!(
+                "{\"name\":\"lazy_static\",\"vers\":\"0.1.0\",\"deps\":[],\"yanked\":false}\n",
+                "{\"name\":\"lazy_static\",\"vers\":\"0.2.11\",\"deps\":[],\"yanked\":true}\n",
+            ),
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("3/l")).unwrap();
+        fs::write(
+            dir.path().join("3/l/log"),
+            "{\"name\":\"log\",\"vers\":\"0.4.6\",\"deps\":[],\"yanked\":false}\n",
+        )
+        .unwrap();
+
+        let index = RegistryIndex::open(dir.path()).unwrap();
+        assert_eq!(
+            index.versions("lazy_static").unwrap(),
+            vec!["0.1.0", "0.2.11"]
+        );
+        assert_eq!(index.versions("Log").unwrap(), vec!["0.4.6"]);
+        assert!(index.versions("missing").unwrap().is_empty());
+    }
+}
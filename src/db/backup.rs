@@ -0,0 +1,173 @@
+use crate::db::{migrations, Backend, Database};
+use crate::prelude::*;
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+
+/// Pages copied at a time, releasing the lock on the database between the steps so the server
+/// can keep writing to it.
+const PAGES_PER_STEP: i32 = 1024;
+const PAUSE_BETWEEN_STEPS: Duration = Duration::from_millis(10);
+/// SQLite restarts the backup every time the database is changed by another connection. After
+/// this many restarts the rest of the database is copied in a single step.
+const MAX_RESTARTS: usize = 10;
+
+fn copy(src: &Connection, dest: &mut Connection) -> Fallible<()> {
+    let backup = Backup::new(src, dest)?;
+
+    let mut restarts = 0;
+    let mut last_remaining = None;
+    loop {
+        let pages = if restarts >= MAX_RESTARTS {
+            -1
+        } else {
+            PAGES_PER_STEP
+        };
+        match backup.step(pages)? {
+            StepResult::Done => break,
+            StepResult::More | StepResult::Busy | StepResult::Locked => {}
+        }
+
+        let remaining = backup.progress().remaining;
+        if last_remaining.map(|last| remaining > last).unwrap_or(false) {
+            restarts += 1;
+        }
+        last_remaining = Some(remaining);
+
+        thread::sleep(PAUSE_BETWEEN_STEPS);
+    }
+
+    Ok(())
+}
+
+fn check_integrity(conn: &Connection) -> Fallible<()> {
+    let mut prepared = conn.prepare("PRAGMA integrity_check;")?;
+    let messages = prepared
+        .query_map(::std::iter::empty::<&str>(), |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if messages.len() != 1 || messages[0] != "ok" {
+        bail!("integrity check failed: {}", messages.join(", "));
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Write a consistent snapshot of the database to `dest`, without blocking the writes for the
+    /// whole duration of the backup. The snapshot is checked for corruption before replacing
+    /// `dest`.
+    pub fn backup(&self, dest: &Path) -> Fallible<()> {
+        let pool = match self.backend {
+            Backend::Sqlite(ref pool) => pool,
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(_) => bail!("PostgreSQL databases should be backed up with pg_dump"),
+        };
+
+        let parent = match dest.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let file = NamedTempFile::new_in(parent)?;
+        {
+            let mut conn = Connection::open(file.path())?;
+            copy(&pool.get()?, &mut conn)?;
+            check_integrity(&conn).context("the backup is corrupted")?;
+        }
+        file.persist(dest)?;
+
+        Ok(())
+    }
+
+    /// Replace the content of the database with the backup at `src`, which is checked for
+    /// corruption first.
+    pub fn restore(&self, src: &Path) -> Fallible<()> {
+        let pool = match self.backend {
+            Backend::Sqlite(ref pool) => pool,
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(_) => {
+                bail!("PostgreSQL databases should be restored with pg_restore")
+            }
+        };
+
+        if !src.is_file() {
+            bail!("missing backup: {}", src.display());
+        }
+        let backup = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        check_integrity(&backup).context("the backup is corrupted")?;
+
+        let mut conn = pool.get()?;
+        copy(&backup, &mut conn)?;
+
+        // The backup might have been taken by an older version of crater
+        migrations::execute(&mut conn)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Database, QueryUtils};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::tempdir;
+
+    fn count(db: &Database) -> i64 {
+        db.get_row("SELECT COUNT(*) FROM backup_test;", &[], |row| row.get(0))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_backup_under_concurrent_writes() {
+        let db = Database::temp_sqlite().unwrap();
+        db.execute("CREATE TABLE backup_test (value INTEGER NOT NULL);", &[])
+            .unwrap();
+        for i in 0..100 {
+            db.execute("INSERT INTO backup_test (value) VALUES (?1);", &[&i])
+                .unwrap();
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (db, stop) = (db.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut i = 100;
+                while !stop.load(Ordering::SeqCst) {
+                    db.execute("INSERT INTO backup_test (value) VALUES (?1);", &[&i])
+                        .unwrap();
+                    i += 1;
+                }
+            })
+        };
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("backup.db");
+        db.backup(&path).unwrap();
+
+        stop.store(true, Ordering::SeqCst);
+        writer.join().unwrap();
+        let written = count(&db);
+
+        // The backup contains a consistent snapshot of the database
+        let restored = Database::temp_sqlite().unwrap();
+        restored.restore(&path).unwrap();
+        let backed_up = count(&restored);
+        assert!(backed_up >= 100);
+        assert!(backed_up <= written);
+        let max: i64 = restored
+            .get_row("SELECT MAX(value) FROM backup_test;", &[], |row| row.get(0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(max, backed_up - 1);
+
+        // Corrupted backups are refused
+        ::std::fs::write(&path, b"not a database").unwrap();
+        assert!(restored.restore(&path).is_err());
+        assert_eq!(count(&restored), backed_up);
+    }
+}
@@ -1,6 +1,7 @@
+use crate::crates::Crate;
 use crate::prelude::*;
 use rand::{self, distributions::Alphanumeric, Rng};
-use rusqlite::{Connection, Transaction};
+use rusqlite::{types::ToSql, Connection, Transaction};
 use serde_json;
 use std::collections::HashSet;
 
@@ -8,9 +9,17 @@ fn no_args() -> impl Iterator<Item = &'static str> {
     ::std::iter::empty()
 }
 
+/// Number of results whose log is moved to the `log_blobs` table in a single transaction.
+const LOGS_DEDUP_BATCH_SIZE: i64 = 1000;
+/// Number of crates whose results are assigned the name of the crate in a single transaction.
+const CRATE_NAMES_BATCH_SIZE: i64 = 1000;
+
 enum MigrationKind {
     SQL(&'static str),
     Code(Box<Fn(&Transaction) -> ::rusqlite::Result<()>>),
+    /// Migration executed in multiple transactions, to avoid locking the database for a long
+    /// time. The function is called until it returns `false`.
+    Batched(Box<Fn(&Transaction) -> ::rusqlite::Result<bool>>),
 }
 
 fn migrations() -> Vec<(&'static str, MigrationKind)> {
@@ -253,45 +262,1368 @@ fn migrations() -> Vec<(&'static str, MigrationKind)> {
         ),
     ));
 
-    migrations
-}
+    migrations.push((
+        "create_partial_logs_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE partial_logs (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                toolchain TEXT NOT NULL,
+                chunk INTEGER NOT NULL,
+                log BLOB NOT NULL,
+                received_at DATETIME NOT NULL,
 
-pub fn execute(db: &mut Connection) -> Fallible<()> {
-    // If the database version is 0, create the migrations table and bump it
-    let version: i32 = db.query_row("PRAGMA user_version;", no_args(), |r| r.get(0))?;
-    if version == 0 {
-        db.execute(
-            "CREATE TABLE migrations (name TEXT PRIMARY KEY);",
-            no_args(),
-        )?;
-        db.execute("PRAGMA user_version = 1;", no_args())?;
-    }
+                PRIMARY KEY (experiment, crate, toolchain, chunk) ON CONFLICT REPLACE,
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
 
-    let executed_migrations = {
-        let mut prepared = db.prepare("SELECT name FROM migrations;")?;
-        let mut result = HashSet::new();
-        for value in prepared.query_map(no_args(), |row| -> String { row.get("name") })? {
-            result.insert(value?);
-        }
+    migrations.push((
+        "add_experiment_field_docker_image",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN docker_image TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_field_recorded_at",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN recorded_at DATETIME;
+            ",
+        ),
+    ));
 
-        result
-    };
+    migrations.push((
+        "create_results_crate_index",
+        MigrationKind::SQL(
+            "
+            CREATE INDEX results__crate ON results (crate);
+            ",
+        ),
+    ));
 
-    for &(name, ref migration) in &migrations() {
-        if !executed_migrations.contains(&name.to_string()) {
-            let t = db.transaction()?;
-            match migration {
-                MigrationKind::SQL(sql) => t.execute_batch(sql),
-                MigrationKind::Code(code) => code(&t),
+    migrations.push((
+        "create_environments_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE environments (
+                experiment TEXT NOT NULL,
+                fingerprint TEXT NOT NULL,
+
+                PRIMARY KEY (experiment, fingerprint) ON CONFLICT IGNORE,
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_fail_fast",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN fail_fast INTEGER NOT NULL DEFAULT 0;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_log_blobs_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE log_blobs (
+                hash TEXT PRIMARY KEY,
+                log BLOB NOT NULL
+            );
+
+            ALTER TABLE results ADD COLUMN log_hash TEXT REFERENCES log_blobs(hash);
+            CREATE INDEX results__log_hash ON results (log_hash);
+
+            CREATE TRIGGER results__delete_unused_log_blobs
+            AFTER DELETE ON results
+            WHEN OLD.log_hash IS NOT NULL
+                AND NOT EXISTS (SELECT 1 FROM results WHERE log_hash = OLD.log_hash)
+            BEGIN
+                DELETE FROM log_blobs WHERE hash = OLD.log_hash;
+            END;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "deduplicate_results_logs",
+        MigrationKind::Batched(Box::new(|t| {
+            let rows = t
+                .prepare("SELECT rowid, log FROM results WHERE log_hash IS NULL LIMIT ?1;")?
+                .query_map(&[&LOGS_DEDUP_BATCH_SIZE], |row| {
+                    let rowid: i64 = row.get("rowid");
+                    let log: Vec<u8> = row.get("log");
+                    (rowid, log)
+                })?
+                .collect::<::rusqlite::Result<Vec<_>>>()?;
+
+            for (rowid, log) in &rows {
+                let hash = crate::results::log_hash(log);
+                t.execute(
+                    "INSERT OR IGNORE INTO log_blobs (hash, log) VALUES (?1, ?2);",
+                    &[&hash as &ToSql, log],
+                )?;
+                t.execute(
+                    "UPDATE results SET log_hash = ?1, log = X'' WHERE rowid = ?2;",
+                    &[&hash as &ToSql, rowid],
+                )?;
             }
-            .with_context(|_| format!("error running migration: {}", name))?;
 
-            t.execute("INSERT INTO migrations (name) VALUES (?1)", &[&name])?;
-            t.commit()?;
+            Ok(rows.len() as i64 == LOGS_DEDUP_BATCH_SIZE)
+        })),
+    ));
 
-            info!("executed migration: {}", name);
-        }
-    }
+    migrations.push((
+        "add_experiment_field_feature_sets",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN feature_sets TEXT NOT NULL DEFAULT '[\"default\"]';
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_field_duration_ms",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN duration_ms INTEGER;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_running_comments_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE running_comments (
+                experiment TEXT PRIMARY KEY,
+                comment_url TEXT NOT NULL,
+                milestone INTEGER NOT NULL,
+
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_requested_by",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN requested_by TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_field_warnings",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN warnings INTEGER;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_field_log_size",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN log_size INTEGER NOT NULL DEFAULT 0;
+
+            UPDATE results SET log_size = COALESCE(
+                (SELECT LENGTH(log_blobs.log) FROM log_blobs WHERE log_blobs.hash = results.log_hash),
+                LENGTH(results.log)
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_agents_field_host",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE agents ADD COLUMN host TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "deduplicate_shas",
+        MigrationKind::SQL(
+            "
+            DELETE FROM shas WHERE rowid NOT IN (
+                SELECT MIN(rowid) FROM shas GROUP BY experiment, org, name, sha
+            );
+            CREATE UNIQUE INDEX shas__unique ON shas (experiment, org, name, sha);
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_report_summaries_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE report_summaries (
+                experiment TEXT PRIMARY KEY,
+                summary TEXT NOT NULL,
+                generated_at DATETIME NOT NULL,
+
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_live_report",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN live_report INTEGER NOT NULL DEFAULT 0;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_agents_field_unhealthy",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE agents ADD COLUMN unhealthy TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_crate_assignments_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE crate_assignments (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                status TEXT NOT NULL,
+                agent TEXT,
+                updated_at DATETIME NOT NULL,
+
+                PRIMARY KEY (experiment, crate) ON CONFLICT REPLACE,
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            CREATE INDEX crate_assignments__experiment_agent
+            ON crate_assignments (experiment, agent);
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_status_overrides_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE status_overrides (
+                experiment TEXT NOT NULL,
+                old_status TEXT NOT NULL,
+                new_status TEXT NOT NULL,
+                changed_by TEXT NOT NULL,
+                forced INTEGER NOT NULL,
+                changed_at DATETIME NOT NULL,
+
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_cargo_patches",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN cargo_patches TEXT NOT NULL DEFAULT '[]';
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_log_blobs_field_compression",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE log_blobs ADD COLUMN compression TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_pre_analyze",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN pre_analyze INTEGER NOT NULL DEFAULT 0;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_crate_dependencies_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE crate_dependencies (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+
+                PRIMARY KEY (experiment, crate, dependency),
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_dependency_edges_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE dependency_edges (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                package TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+
+                PRIMARY KEY (experiment, crate, package, dependency),
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_annotations_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE annotations (
+                experiment TEXT NOT NULL,
+                crate TEXT NOT NULL,
+                status TEXT NOT NULL,
+                note TEXT NOT NULL,
+                author TEXT NOT NULL,
+                updated_at DATETIME NOT NULL,
+
+                PRIMARY KEY (experiment, crate),
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
 
-    Ok(())
+    migrations.push((
+        "add_experiment_fields_private",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN private INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE experiments ADD COLUMN private_readers TEXT NOT NULL DEFAULT '[]';
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_report_regenerations_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE report_regenerations (
+                experiment TEXT PRIMARY KEY,
+                requested_at DATETIME NOT NULL,
+
+                FOREIGN KEY (experiment) REFERENCES experiments(name) ON DELETE CASCADE
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_agent_tokens_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE agent_tokens (
+                agent TEXT PRIMARY KEY ON CONFLICT REPLACE,
+                token_hash TEXT NOT NULL,
+                rotated_at DATETIME NOT NULL
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_field_crate_name",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN crate_name TEXT;
+            CREATE INDEX results__crate_name ON results (crate_name);
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "fill_results_field_crate_name",
+        MigrationKind::Batched(Box::new(|t| {
+            let crates = t
+                .prepare("SELECT DISTINCT crate FROM results WHERE crate_name IS NULL LIMIT ?1;")?
+                .query_map(&[&CRATE_NAMES_BATCH_SIZE], |row| -> String {
+                    row.get("crate")
+                })?
+                .collect::<::rusqlite::Result<Vec<_>>>()?;
+
+            for krate in &crates {
+                // Crates that can't be parsed anymore are not looked up by name
+                let name = serde_json::from_str::<Crate>(krate)
+                    .map(|krate| krate.name())
+                    .unwrap_or_default();
+                t.execute(
+                    "UPDATE results SET crate_name = ?1 WHERE crate = ?2;",
+                    &[&name as &ToSql, krate],
+                )?;
+            }
+
+            Ok(crates.len() as i64 == CRATE_NAMES_BATCH_SIZE)
+        })),
+    ));
+
+    migrations.push((
+        "add_experiment_field_test_runner",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN test_runner TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_measure_artifacts",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN measure_artifacts INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE results ADD COLUMN artifacts_size INTEGER;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_field_silent",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN silent INTEGER NOT NULL DEFAULT 0;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_result_phases_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE result_phases (
+                experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+                crate TEXT NOT NULL,
+                toolchain TEXT NOT NULL,
+                phase TEXT NOT NULL,
+                result TEXT NOT NULL,
+                log BLOB NOT NULL,
+                compression TEXT,
+
+                PRIMARY KEY (experiment, crate, toolchain, phase)
+            );
+
+            CREATE TRIGGER results__delete_phases
+            AFTER DELETE ON results
+            BEGIN
+                DELETE FROM result_phases WHERE experiment = OLD.experiment
+                    AND crate = OLD.crate AND toolchain = OLD.toolchain;
+            END;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_result_field_error_category",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN error_category TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_result_field_diagnostics",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN diagnostics TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_queued_messages_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE queued_messages (
+                issue_url TEXT NOT NULL,
+                message TEXT NOT NULL,
+                label TEXT,
+                queued_at DATETIME NOT NULL
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_experiments_created_at_index",
+        MigrationKind::SQL(
+            "
+            CREATE INDEX experiments__created_at ON experiments (created_at);
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_agents_fields_revision_conflicts",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE agents ADD COLUMN git_revision_changed_at DATETIME;
+            ALTER TABLE agents ADD COLUMN revision_conflict_at DATETIME;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "create_read_only_tokens_table",
+        MigrationKind::SQL(
+            "
+            CREATE TABLE read_only_tokens (
+                name TEXT PRIMARY KEY,
+                token_hash TEXT NOT NULL,
+                created_at DATETIME NOT NULL,
+                created_by TEXT NOT NULL
+            );
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_field_stored_log_size",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN stored_log_size INTEGER NOT NULL DEFAULT 0;
+
+            UPDATE results SET stored_log_size = COALESCE(
+                (SELECT LENGTH(log_blobs.log) FROM log_blobs WHERE log_blobs.hash = results.log_hash),
+                LENGTH(results.log)
+            );
+            ",
+        ),
+    ));
+
+    migrations
+}
+
+/// State of the schema of a database, compared to the migrations known by this version of
+/// crater. The version of the schema is the number of known migrations applied to it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SchemaStatus {
+    pub version: usize,
+    pub expected_version: usize,
+    pub pending: Vec<&'static str>,
+    /// Migrations applied by a newer version of crater.
+    pub unknown: Vec<String>,
+}
+
+impl SchemaStatus {
+    fn new(known: &[&'static str], executed: &HashSet<String>) -> Self {
+        let mut unknown = executed
+            .iter()
+            .filter(|name| !known.contains(&name.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        unknown.sort();
+
+        SchemaStatus {
+            version: known
+                .iter()
+                .filter(|name| executed.contains(**name))
+                .count(),
+            expected_version: known.len(),
+            pending: known
+                .iter()
+                .filter(|name| !executed.contains(**name))
+                .cloned()
+                .collect(),
+            unknown,
+        }
+    }
+
+    /// Refuse to touch a database migrated by a newer version of crater, as this version doesn't
+    /// know how to keep it consistent.
+    fn ensure_known(&self) -> Fallible<()> {
+        if !self.unknown.is_empty() {
+            bail!(
+                "the database schema is newer than this version of crater supports \
+                 (unknown migrations: {}), update crater before using it",
+                self.unknown.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// SQL executed by each migration, as printed by `crater db migrate --dry-run`.
+fn describe(migration: &MigrationKind) -> String {
+    match migration {
+        MigrationKind::SQL(sql) => sql.trim().to_string(),
+        MigrationKind::Code(_) | MigrationKind::Batched(_) => {
+            "-- implemented in Rust, see src/db/migrations.rs".to_string()
+        }
+    }
+}
+
+fn executed_migrations(db: &Connection) -> Fallible<HashSet<String>> {
+    // Databases with version 0 don't have the migrations table yet
+    let version: i32 = db.query_row("PRAGMA user_version;", no_args(), |r| r.get(0))?;
+    if version == 0 {
+        return Ok(HashSet::new());
+    }
+
+    let mut prepared = db.prepare("SELECT name FROM migrations;")?;
+    let mut result = HashSet::new();
+    for value in prepared.query_map(no_args(), |row| -> String { row.get("name") })? {
+        result.insert(value?);
+    }
+
+    Ok(result)
+}
+
+pub fn status(db: &Connection) -> Fallible<SchemaStatus> {
+    let names = migrations().iter().map(|m| m.0).collect::<Vec<_>>();
+    Ok(SchemaStatus::new(&names, &executed_migrations(db)?))
+}
+
+pub fn pending(db: &Connection) -> Fallible<Vec<(&'static str, String)>> {
+    let executed = executed_migrations(db)?;
+    Ok(migrations()
+        .iter()
+        .filter(|m| !executed.contains(m.0))
+        .map(|m| (m.0, describe(&m.1)))
+        .collect())
+}
+
+/// Apply the pending migrations, returning their names.
+pub fn execute(db: &mut Connection) -> Fallible<Vec<&'static str>> {
+    status(db)?.ensure_known()?;
+    apply(db, &migrations())
+}
+
+fn apply(
+    db: &mut Connection,
+    migrations: &[(&'static str, MigrationKind)],
+) -> Fallible<Vec<&'static str>> {
+    // If the database version is 0, create the migrations table and bump it
+    let version: i32 = db.query_row("PRAGMA user_version;", no_args(), |r| r.get(0))?;
+    if version == 0 {
+        let t = db.transaction()?;
+        t.execute(
+            "CREATE TABLE migrations (name TEXT PRIMARY KEY);",
+            no_args(),
+        )?;
+        t.execute("PRAGMA user_version = 1;", no_args())?;
+        t.commit()?;
+    }
+
+    let executed_migrations = executed_migrations(db)?;
+    let mut applied = Vec::new();
+    for &(name, ref migration) in migrations {
+        if !executed_migrations.contains(&name.to_string()) {
+            if let MigrationKind::Batched(batch) = migration {
+                loop {
+                    let t = db.transaction()?;
+                    let more =
+                        batch(&t).with_context(|_| format!("error running migration: {}", name))?;
+                    t.commit()?;
+
+                    if !more {
+                        break;
+                    }
+                }
+            }
+
+            // The migration is recorded in the same transaction, so it's either fully applied or
+            // not applied at all
+            let t = db.transaction()?;
+            match migration {
+                MigrationKind::SQL(sql) => t.execute_batch(sql),
+                MigrationKind::Code(code) => code(&t),
+                // All the batches were already executed and committed
+                MigrationKind::Batched(_) => Ok(()),
+            }
+            .with_context(|_| format!("error running migration: {}", name))?;
+
+            t.execute("INSERT INTO migrations (name) VALUES (?1)", &[&name])?;
+            t.commit()?;
+
+            info!("executed migration: {}", name);
+            applied.push(name);
+        }
+    }
+
+    Ok(applied)
+}
+
+/// The PostgreSQL backend was introduced after all the migrations above, so it starts from the
+/// current schema. New migrations need to be added to both lists.
+#[cfg(feature = "postgresql")]
+fn postgres_migrations() -> Vec<(&'static str, &'static str)> {
+    let mut migrations = Vec::new();
+
+    migrations.push((
+        "initial",
+        "
+        CREATE TABLE agents (
+            name TEXT PRIMARY KEY,
+            last_heartbeat TEXT,
+            git_revision TEXT
+        );
+
+        CREATE TABLE experiments (
+            rowid BIGSERIAL,
+            name TEXT PRIMARY KEY,
+            mode TEXT NOT NULL,
+            cap_lints TEXT NOT NULL,
+
+            toolchain_start TEXT NOT NULL,
+            toolchain_end TEXT NOT NULL,
+
+            created_at TEXT NOT NULL,
+            started_at TEXT,
+            completed_at TEXT,
+
+            priority BIGINT NOT NULL,
+            status TEXT NOT NULL,
+            report_url TEXT,
+            github_issue TEXT,
+            github_issue_url TEXT,
+            github_issue_number BIGINT,
+            assigned_to TEXT,
+            ignore_blacklist BIGINT NOT NULL DEFAULT 0,
+            docker_image TEXT,
+            fail_fast BIGINT NOT NULL DEFAULT 0,
+            feature_sets TEXT NOT NULL DEFAULT '[\"default\"]'
+        );
+
+        CREATE TABLE experiment_crates (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            skipped BIGINT NOT NULL DEFAULT 0
+        );
+        CREATE INDEX experiment_crates__experiment_skipped
+        ON experiment_crates (experiment, skipped);
+
+        CREATE TABLE log_blobs (
+            hash TEXT PRIMARY KEY,
+            log BYTEA NOT NULL
+        );
+
+        CREATE TABLE results (
+            rowid BIGSERIAL,
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            toolchain TEXT NOT NULL,
+            result TEXT NOT NULL,
+            log BYTEA NOT NULL,
+            recorded_at TEXT,
+            log_hash TEXT REFERENCES log_blobs(hash),
+            duration_ms BIGINT,
+
+            PRIMARY KEY (experiment, crate, toolchain)
+        );
+        CREATE INDEX results__experiment ON results (experiment);
+        CREATE INDEX results__crate ON results (crate);
+        CREATE INDEX results__log_hash ON results (log_hash);
+
+        CREATE FUNCTION results__delete_unused_log_blobs() RETURNS TRIGGER AS $$
+        BEGIN
+            DELETE FROM log_blobs WHERE hash = OLD.log_hash
+                AND NOT EXISTS (SELECT 1 FROM results WHERE log_hash = OLD.log_hash);
+            RETURN NULL;
+        END;
+        $$ LANGUAGE plpgsql;
+
+        CREATE TRIGGER results__delete_unused_log_blobs
+        AFTER DELETE ON results
+        FOR EACH ROW WHEN (OLD.log_hash IS NOT NULL)
+        EXECUTE PROCEDURE results__delete_unused_log_blobs();
+
+        -- Searching the logs relies on the SQLite function
+        CREATE FUNCTION instr(haystack BYTEA, needle TEXT) RETURNS INTEGER AS $$
+            SELECT POSITION(convert_to(needle, 'UTF8') IN haystack);
+        $$ LANGUAGE SQL IMMUTABLE;
+
+        CREATE TABLE shas (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            org TEXT NOT NULL,
+            name TEXT NOT NULL,
+            sha TEXT NOT NULL
+        );
+
+        CREATE TABLE saved_names (
+            issue BIGINT PRIMARY KEY,
+            experiment TEXT NOT NULL
+        );
+
+        CREATE TABLE crates (
+            rowid BIGSERIAL,
+            crate TEXT NOT NULL,
+            list TEXT NOT NULL,
+            loaded_at TEXT NOT NULL,
+
+            PRIMARY KEY (crate, list)
+        );
+        CREATE INDEX crates__list ON crates (list);
+
+        CREATE TABLE partial_logs (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            toolchain TEXT NOT NULL,
+            chunk BIGINT NOT NULL,
+            log BYTEA NOT NULL,
+            received_at TEXT NOT NULL,
+
+            PRIMARY KEY (experiment, crate, toolchain, chunk)
+        );
+
+        CREATE TABLE environments (
+            rowid BIGSERIAL,
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            fingerprint TEXT NOT NULL,
+
+            PRIMARY KEY (experiment, fingerprint)
+        );
+        ",
+    ));
+
+    migrations.push((
+        "create_running_comments_table",
+        "
+        CREATE TABLE running_comments (
+            experiment TEXT PRIMARY KEY REFERENCES experiments(name) ON DELETE CASCADE,
+            comment_url TEXT NOT NULL,
+            milestone BIGINT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_requested_by",
+        "
+        ALTER TABLE experiments ADD COLUMN requested_by TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "add_results_field_warnings",
+        "
+        ALTER TABLE results ADD COLUMN warnings BIGINT;
+        ",
+    ));
+
+    migrations.push((
+        "add_results_field_log_size",
+        "
+        ALTER TABLE results ADD COLUMN log_size BIGINT NOT NULL DEFAULT 0;
+
+        UPDATE results SET log_size = COALESCE(
+            (SELECT LENGTH(log_blobs.log) FROM log_blobs WHERE log_blobs.hash = results.log_hash),
+            LENGTH(results.log)
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_agents_field_host",
+        "
+        ALTER TABLE agents ADD COLUMN host TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "deduplicate_shas",
+        "
+        DELETE FROM shas a USING shas b
+            WHERE a.ctid > b.ctid AND a.experiment = b.experiment
+            AND a.org = b.org AND a.name = b.name AND a.sha = b.sha;
+        CREATE UNIQUE INDEX shas__unique ON shas (experiment, org, name, sha);
+        ",
+    ));
+
+    migrations.push((
+        "create_report_summaries_table",
+        "
+        CREATE TABLE report_summaries (
+            experiment TEXT PRIMARY KEY REFERENCES experiments(name) ON DELETE CASCADE,
+            summary TEXT NOT NULL,
+            generated_at TEXT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_live_report",
+        "
+        ALTER TABLE experiments ADD COLUMN live_report BIGINT NOT NULL DEFAULT 0;
+        ",
+    ));
+
+    migrations.push((
+        "add_agents_field_unhealthy",
+        "
+        ALTER TABLE agents ADD COLUMN unhealthy TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "create_crate_assignments_table",
+        "
+        CREATE TABLE crate_assignments (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            status TEXT NOT NULL,
+            agent TEXT,
+            updated_at TEXT NOT NULL,
+
+            PRIMARY KEY (experiment, crate)
+        );
+        CREATE INDEX crate_assignments__experiment_agent
+        ON crate_assignments (experiment, agent);
+        ",
+    ));
+
+    migrations.push((
+        "create_status_overrides_table",
+        "
+        CREATE TABLE status_overrides (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            old_status TEXT NOT NULL,
+            new_status TEXT NOT NULL,
+            changed_by TEXT NOT NULL,
+            forced BIGINT NOT NULL,
+            changed_at TEXT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_cargo_patches",
+        "
+        ALTER TABLE experiments ADD COLUMN cargo_patches TEXT NOT NULL DEFAULT '[]';
+        ",
+    ));
+
+    migrations.push((
+        "add_log_blobs_field_compression",
+        "
+        ALTER TABLE log_blobs ADD COLUMN compression TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_pre_analyze",
+        "
+        ALTER TABLE experiments ADD COLUMN pre_analyze BIGINT NOT NULL DEFAULT 0;
+        ",
+    ));
+
+    migrations.push((
+        "create_crate_dependencies_table",
+        "
+        CREATE TABLE crate_dependencies (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            dependency TEXT NOT NULL,
+
+            PRIMARY KEY (experiment, crate, dependency)
+        );
+        ",
+    ));
+
+    migrations.push((
+        "create_dependency_edges_table",
+        "
+        CREATE TABLE dependency_edges (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            package TEXT NOT NULL,
+            dependency TEXT NOT NULL,
+
+            PRIMARY KEY (experiment, crate, package, dependency)
+        );
+        ",
+    ));
+
+    migrations.push((
+        "create_annotations_table",
+        "
+        CREATE TABLE annotations (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            status TEXT NOT NULL,
+            note TEXT NOT NULL,
+            author TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+
+            PRIMARY KEY (experiment, crate)
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_fields_private",
+        "
+        ALTER TABLE experiments ADD COLUMN private BIGINT NOT NULL DEFAULT 0;
+        ALTER TABLE experiments ADD COLUMN private_readers TEXT NOT NULL DEFAULT '[]';
+        ",
+    ));
+
+    migrations.push((
+        "create_report_regenerations_table",
+        "
+        CREATE TABLE report_regenerations (
+            experiment TEXT PRIMARY KEY REFERENCES experiments(name) ON DELETE CASCADE,
+            requested_at TEXT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "create_agent_tokens_table",
+        "
+        CREATE TABLE agent_tokens (
+            agent TEXT PRIMARY KEY,
+            token_hash TEXT NOT NULL,
+            rotated_at TEXT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_results_field_crate_name",
+        "
+        ALTER TABLE results ADD COLUMN crate_name TEXT;
+        UPDATE results SET crate_name = COALESCE(
+            crate::JSON #>> '{Registry,name}',
+            (crate::JSON #>> '{GitHub,org}') || '.' || (crate::JSON #>> '{GitHub,name}'),
+            crate::JSON ->> 'Local',
+            ''
+        );
+        CREATE INDEX results__crate_name ON results (crate_name);
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_test_runner",
+        "
+        ALTER TABLE experiments ADD COLUMN test_runner TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_measure_artifacts",
+        "
+        ALTER TABLE experiments ADD COLUMN measure_artifacts BIGINT NOT NULL DEFAULT 0;
+        ALTER TABLE results ADD COLUMN artifacts_size BIGINT;
+        ",
+    ));
+
+    migrations.push((
+        "add_experiment_field_silent",
+        "
+        ALTER TABLE experiments ADD COLUMN silent BIGINT NOT NULL DEFAULT 0;
+        ",
+    ));
+
+    migrations.push((
+        "create_result_phases_table",
+        "
+        CREATE TABLE result_phases (
+            experiment TEXT NOT NULL REFERENCES experiments(name) ON DELETE CASCADE,
+            crate TEXT NOT NULL,
+            toolchain TEXT NOT NULL,
+            phase TEXT NOT NULL,
+            result TEXT NOT NULL,
+            log BYTEA NOT NULL,
+            compression TEXT,
+
+            PRIMARY KEY (experiment, crate, toolchain, phase)
+        );
+
+        CREATE FUNCTION results__delete_phases() RETURNS TRIGGER AS $$
+        BEGIN
+            DELETE FROM result_phases WHERE experiment = OLD.experiment
+                AND crate = OLD.crate AND toolchain = OLD.toolchain;
+            RETURN NULL;
+        END;
+        $$ LANGUAGE plpgsql;
+
+        CREATE TRIGGER results__delete_phases
+        AFTER DELETE ON results
+        FOR EACH ROW EXECUTE PROCEDURE results__delete_phases();
+        ",
+    ));
+
+    migrations.push((
+        "add_result_field_error_category",
+        "
+        ALTER TABLE results ADD COLUMN error_category TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "add_result_field_diagnostics",
+        "
+        ALTER TABLE results ADD COLUMN diagnostics TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "create_queued_messages_table",
+        "
+        CREATE TABLE queued_messages (
+            rowid BIGSERIAL,
+            issue_url TEXT NOT NULL,
+            message TEXT NOT NULL,
+            label TEXT,
+            queued_at TEXT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "create_experiments_created_at_index",
+        "
+        CREATE INDEX experiments__created_at ON experiments (created_at);
+        ",
+    ));
+
+    migrations.push((
+        "add_agents_fields_revision_conflicts",
+        "
+        ALTER TABLE agents ADD COLUMN git_revision_changed_at TEXT;
+        ALTER TABLE agents ADD COLUMN revision_conflict_at TEXT;
+        ",
+    ));
+
+    migrations.push((
+        "create_read_only_tokens_table",
+        "
+        CREATE TABLE read_only_tokens (
+            name TEXT PRIMARY KEY,
+            token_hash TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            created_by TEXT NOT NULL
+        );
+        ",
+    ));
+
+    migrations.push((
+        "add_results_field_stored_log_size",
+        "
+        ALTER TABLE results ADD COLUMN stored_log_size BIGINT NOT NULL DEFAULT 0;
+
+        UPDATE results SET stored_log_size = COALESCE(
+            (SELECT LENGTH(log_blobs.log) FROM log_blobs WHERE log_blobs.hash = results.log_hash),
+            LENGTH(results.log)
+        );
+        ",
+    ));
+
+    migrations
+}
+
+#[cfg(feature = "postgresql")]
+fn executed_migrations_postgres(db: &::postgres::Connection) -> Fallible<HashSet<String>> {
+    db.batch_execute("CREATE TABLE IF NOT EXISTS migrations (name TEXT PRIMARY KEY);")?;
+
+    Ok(db
+        .query("SELECT name FROM migrations;", &[])?
+        .iter()
+        .map(|row| row.get::<_, String>("name"))
+        .collect())
+}
+
+#[cfg(feature = "postgresql")]
+pub fn status_postgres(db: &::postgres::Connection) -> Fallible<SchemaStatus> {
+    let names = postgres_migrations()
+        .iter()
+        .map(|m| m.0)
+        .collect::<Vec<_>>();
+    Ok(SchemaStatus::new(
+        &names,
+        &executed_migrations_postgres(db)?,
+    ))
+}
+
+#[cfg(feature = "postgresql")]
+pub fn pending_postgres(db: &::postgres::Connection) -> Fallible<Vec<(&'static str, String)>> {
+    let executed = executed_migrations_postgres(db)?;
+    Ok(postgres_migrations()
+        .into_iter()
+        .filter(|m| !executed.contains(m.0))
+        .map(|(name, sql)| (name, sql.trim().to_string()))
+        .collect())
+}
+
+/// Apply the pending migrations, returning their names.
+#[cfg(feature = "postgresql")]
+pub fn execute_postgres(db: &::postgres::Connection) -> Fallible<Vec<&'static str>> {
+    status_postgres(db)?.ensure_known()?;
+    let executed_migrations = executed_migrations_postgres(db)?;
+
+    let mut applied = Vec::new();
+    for &(name, sql) in &postgres_migrations() {
+        if !executed_migrations.contains(name) {
+            let t = db.transaction()?;
+            t.batch_execute(sql)
+                .with_context(|_| format!("error running migration: {}", name))?;
+            t.execute("INSERT INTO migrations (name) VALUES ($1);", &[&name])?;
+            t.commit()?;
+
+            info!("executed migration: {}", name);
+            applied.push(name);
+        }
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, execute, migrations, no_args, pending, status};
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::{Database, QueryUtils, TempStorage};
+    use crate::experiments::{Assignee, Experiment, Status};
+    use crate::results::{DatabaseDB, ReadResults, TestResult};
+    use chrono::{TimeZone, Utc};
+    use r2d2_sqlite::SqliteConnectionManager;
+    use rusqlite::types::ToSql;
+    use rusqlite::Connection;
+    use tempfile::NamedTempFile;
+
+    /// Database created by an older release of crater, which only knew the first `known`
+    /// migrations.
+    fn older_release(conn: &mut Connection, known: usize) {
+        apply(conn, &migrations()[..known]).unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_from_every_older_release() {
+        let count = migrations().len();
+        for known in 0..count {
+            let mut conn = Connection::open_in_memory().unwrap();
+            older_release(&mut conn, known);
+
+            let before = status(&conn).unwrap();
+            assert_eq!(before.version, known);
+            assert_eq!(before.expected_version, count);
+            assert_eq!(pending(&conn).unwrap().len(), count - known);
+
+            assert_eq!(execute(&mut conn).unwrap().len(), count - known);
+            let after = status(&conn).unwrap();
+            assert_eq!(after.version, count);
+            assert!(after.pending.is_empty());
+            assert!(pending(&conn).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_upgrade_keeps_data() {
+        let file = NamedTempFile::new().unwrap();
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+
+        // Data stored by the first release, before the toolchains were stringified and the logs
+        // were deduplicated
+        {
+            let mut conn = Connection::open(file.path()).unwrap();
+            older_release(&mut conn, 1);
+            conn.execute("INSERT INTO agents (name) VALUES ('agent-1');", no_args())
+                .unwrap();
+            conn.execute(
+                "INSERT INTO experiments (name, mode, cap_lints, toolchain_start, toolchain_end, \
+                 priority, created_at, status, assigned_to) VALUES ('legacy', 'build-and-test', \
+                 'forbid', '{\"Dist\":\"stable\"}', '{\"Dist\":\"beta\"}', 0, ?1, 'completed', \
+                 'agent-1');",
+                &[&Utc.ymd(2018, 1, 1).and_hms(0, 0, 0) as &ToSql],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO results (experiment, crate, toolchain, result, log) \
+                 VALUES ('legacy', ?1, '{\"Dist\":\"stable\"}', 'test-pass', ?2);",
+                &[
+                    &::serde_json::to_string(&krate).unwrap() as &ToSql,
+                    &b"hello".to_vec(),
+                ],
+            )
+            .unwrap();
+        }
+
+        let db = Database::new_sqlite(
+            SqliteConnectionManager::file(file.path()),
+            Some(TempStorage::File(file)),
+        )
+        .unwrap()
+        .migrated()
+        .unwrap();
+
+        let ex = Experiment::get(&db, "legacy").unwrap().unwrap();
+        assert_eq!(ex.toolchains[0].to_string(), "stable");
+        assert_eq!(ex.toolchains[1].to_string(), "beta");
+        assert_eq!(ex.status, Status::Completed);
+        assert_eq!(ex.assigned_to, Some(Assignee::Agent("agent-1".into())));
+
+        let results = DatabaseDB::new(&db);
+        assert_eq!(
+            results.load_log(&ex, &ex.toolchains[0], &krate).unwrap(),
+            Some(b"hello".to_vec())
+        );
+        assert_eq!(
+            results
+                .load_test_result(&ex, &ex.toolchains[0], &krate)
+                .unwrap(),
+            Some(TestResult::TestPass)
+        );
+
+        // The results stored before are found by the name of the crate
+        assert_eq!(
+            db.get_row("SELECT crate_name FROM results;", &[], |row| {
+                row.get::<_, String>("crate_name")
+            })
+            .unwrap(),
+            Some("lazy_static".to_string())
+        );
+    }
+
+    #[test]
+    fn test_refuse_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        execute(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO migrations (name) VALUES ('from_a_newer_release');",
+            no_args(),
+        )
+        .unwrap();
+
+        let status = status(&conn).unwrap();
+        assert_eq!(status.unknown, vec!["from_a_newer_release".to_string()]);
+        assert!(status.pending.is_empty());
+        assert!(execute(&mut conn).is_err());
+    }
 }
@@ -1,37 +1,260 @@
+mod backup;
 mod migrations;
+#[cfg(feature = "postgresql")]
+mod pg;
 
+pub use self::migrations::SchemaStatus;
+
+use crate::config::Config;
 use crate::dirs::WORK_DIR;
 use crate::prelude::*;
+use log::{log_enabled, Level};
 use r2d2::{CustomizeConnection, Pool};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::types::ToSql;
-use rusqlite::{Connection, Row, Transaction};
+#[cfg(feature = "postgresql")]
+use rusqlite::types::Value;
+use rusqlite::types::{FromSql, ToSql};
+use rusqlite::{Connection, ErrorCode, Transaction, TransactionBehavior};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tempfile::NamedTempFile;
 
 static LEGACY_DATABASE_PATHS: &[&str] = &["server.db"];
 static DATABASE_PATH: &str = "crater.db";
 
+/// Tables copied by `Database::copy_to_postgres`, in an order satisfying the foreign keys.
+#[cfg(feature = "postgresql")]
+static TABLES: &[(&str, &[&str])] = &[
+    (
+        "agents",
+        &[
+            "name",
+            "last_heartbeat",
+            "git_revision",
+            "host",
+            "unhealthy",
+            "git_revision_changed_at",
+            "revision_conflict_at",
+        ],
+    ),
+    (
+        "experiments",
+        &[
+            "rowid",
+            "name",
+            "mode",
+            "cap_lints",
+            "toolchain_start",
+            "toolchain_end",
+            "created_at",
+            "started_at",
+            "completed_at",
+            "priority",
+            "status",
+            "report_url",
+            "github_issue",
+            "github_issue_url",
+            "github_issue_number",
+            "assigned_to",
+            "ignore_blacklist",
+            "docker_image",
+            "fail_fast",
+            "feature_sets",
+            "requested_by",
+            "live_report",
+            "cargo_patches",
+            "pre_analyze",
+            "private",
+            "private_readers",
+            "test_runner",
+            "measure_artifacts",
+            "silent",
+        ],
+    ),
+    ("experiment_crates", &["experiment", "crate", "skipped"]),
+    ("log_blobs", &["hash", "log", "compression"]),
+    (
+        "results",
+        &[
+            "rowid",
+            "experiment",
+            "crate",
+            "toolchain",
+            "result",
+            "log",
+            "recorded_at",
+            "log_hash",
+            "duration_ms",
+            "warnings",
+            "log_size",
+            "crate_name",
+            "artifacts_size",
+            "stored_log_size",
+        ],
+    ),
+    ("shas", &["experiment", "org", "name", "sha"]),
+    ("saved_names", &["issue", "experiment"]),
+    ("crates", &["rowid", "crate", "list", "loaded_at"]),
+    (
+        "partial_logs",
+        &[
+            "experiment",
+            "crate",
+            "toolchain",
+            "chunk",
+            "log",
+            "received_at",
+        ],
+    ),
+    ("environments", &["rowid", "experiment", "fingerprint"]),
+    (
+        "running_comments",
+        &["experiment", "comment_url", "milestone"],
+    ),
+    (
+        "report_summaries",
+        &["experiment", "summary", "generated_at"],
+    ),
+    (
+        "crate_assignments",
+        &["experiment", "crate", "status", "agent", "updated_at"],
+    ),
+    (
+        "status_overrides",
+        &[
+            "experiment",
+            "old_status",
+            "new_status",
+            "changed_by",
+            "forced",
+            "changed_at",
+        ],
+    ),
+    ("crate_dependencies", &["experiment", "crate", "dependency"]),
+    (
+        "dependency_edges",
+        &["experiment", "crate", "package", "dependency"],
+    ),
+    (
+        "annotations",
+        &[
+            "experiment",
+            "crate",
+            "status",
+            "note",
+            "author",
+            "updated_at",
+        ],
+    ),
+    ("report_regenerations", &["experiment", "requested_at"]),
+    ("agent_tokens", &["agent", "token_hash", "rotated_at"]),
+    (
+        "result_phases",
+        &[
+            "experiment",
+            "crate",
+            "toolchain",
+            "phase",
+            "result",
+            "log",
+            "compression",
+        ],
+    ),
+    (
+        "queued_messages",
+        &["rowid", "issue_url", "message", "label", "queued_at"],
+    ),
+    (
+        "read_only_tokens",
+        &["name", "token_hash", "created_at", "created_by"],
+    ),
+];
+#[cfg(feature = "postgresql")]
+const COPY_BATCH_SIZE: i64 = 1000;
+
+/// How long a SQLite connection waits for the locks held by other connections to be released
+/// before failing with a "database is locked" error. It's shorter in the tests, so the ones holding
+/// a lock on purpose don't have to wait as long.
+#[cfg(not(test))]
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+#[cfg(test)]
+pub(crate) const BUSY_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 struct ConnectionCustomizer;
 
 impl CustomizeConnection<Connection, ::rusqlite::Error> for ConnectionCustomizer {
     fn on_acquire(&self, conn: &mut Connection) -> Result<(), ::rusqlite::Error> {
         conn.execute("PRAGMA foreign_keys = ON;", ::std::iter::empty::<&ToSql>())?;
+        // With the write-ahead log readers don't block the writer and the other way around, which
+        // avoids most of the contention when multiple agents upload their results
+        conn.query_row(
+            "PRAGMA journal_mode = WAL;",
+            ::std::iter::empty::<&ToSql>(),
+            |_| (),
+        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
         Ok(())
     }
 }
 
+/// Whether the error was caused by another connection holding a lock on the SQLite database for
+/// longer than the busy timeout, meaning the operation can be retried later.
+pub fn is_busy(err: &::failure::Error) -> bool {
+    err.iter_chain()
+        .any(|cause| match cause.downcast_ref::<::rusqlite::Error>() {
+            Some(::rusqlite::Error::SqliteFailure(err, _)) => {
+                err.code == ErrorCode::DatabaseBusy || err.code == ErrorCode::DatabaseLocked
+            }
+            _ => false,
+        })
+}
+
+#[derive(Clone)]
+enum Backend {
+    Sqlite(Pool<SqliteConnectionManager>),
+    #[cfg(feature = "postgresql")]
+    Postgres(Pool<pg::ConnectionManager>),
+}
+
+/// Storage removed when the last handle to a temporary database is dropped.
+#[cfg_attr(not(test), allow(dead_code))]
+enum TempStorage {
+    File(NamedTempFile),
+    #[cfg(all(test, feature = "postgresql"))]
+    Schema(pg::TempSchema),
+}
+
 #[derive(Clone)]
 pub struct Database {
-    pool: Pool<SqliteConnectionManager>,
-    // The tempfile is stored here to drop it after all the connections are closed
-    tempfile: Option<Arc<NamedTempFile>>,
+    backend: Backend,
+    // The temporary storage is stored here to drop it after all the connections are closed
+    temp: Option<Arc<TempStorage>>,
 }
 
 impl Database {
-    pub fn open() -> Fallible<Self> {
+    /// Open the database configured in `config.toml`, which is a local SQLite file by default,
+    /// and apply the pending migrations.
+    pub fn open(config: &Config) -> Fallible<Self> {
+        Database::open_unmigrated(config)?.migrated()
+    }
+
+    /// Open the database configured in `config.toml` without applying the pending migrations.
+    pub fn open_unmigrated(config: &Config) -> Fallible<Self> {
+        match config.database.postgres_url {
+            #[cfg(feature = "postgresql")]
+            Some(ref url) => Database::connect_postgres(url),
+            #[cfg(not(feature = "postgresql"))]
+            Some(_) => bail!("crater was built without PostgreSQL support"),
+            None => Database::connect_sqlite(),
+        }
+    }
+
+    pub fn open_sqlite() -> Fallible<Self> {
+        Database::connect_sqlite()?.migrated()
+    }
+
+    fn connect_sqlite() -> Fallible<Self> {
         let path = WORK_DIR.join(DATABASE_PATH);
         if !path.exists() {
             // If the database doesn't exist check if it's present in a legacy path
@@ -52,28 +275,183 @@ impl Database {
 
         let path = WORK_DIR.join(DATABASE_PATH);
         std::fs::create_dir_all(&*WORK_DIR)?;
-        Database::new(SqliteConnectionManager::file(path), None)
+        Database::new_sqlite(SqliteConnectionManager::file(path), None)
+    }
+
+    #[cfg(feature = "postgresql")]
+    pub fn open_postgres(url: &str) -> Fallible<Self> {
+        Database::connect_postgres(url)?.migrated()
+    }
+
+    #[cfg(feature = "postgresql")]
+    fn connect_postgres(url: &str) -> Fallible<Self> {
+        Database::new_postgres(pg::ConnectionManager::new(url, None)?, None)
     }
 
+    /// Create an empty database, removed when it's dropped. If crater is built with PostgreSQL
+    /// support and `CRATER_TEST_POSTGRES_URL` is set, a new schema in that database is used
+    /// instead of a SQLite file.
     #[cfg(test)]
     pub fn temp() -> Fallible<Self> {
+        #[cfg(feature = "postgresql")]
+        {
+            if let Ok(url) = ::std::env::var("CRATER_TEST_POSTGRES_URL") {
+                let schema = pg::TempSchema::create(&url)?;
+                let manager = pg::ConnectionManager::new(&url, Some(schema.name().into()))?;
+                return Database::new_postgres(manager, Some(TempStorage::Schema(schema)))?
+                    .migrated();
+            }
+        }
+
+        Database::temp_sqlite()
+    }
+
+    /// Create an empty SQLite database, removed when it's dropped.
+    #[cfg(test)]
+    pub fn temp_sqlite() -> Fallible<Self> {
         let tempfile = NamedTempFile::new()?;
-        Database::new(
+        Database::new_sqlite(
             SqliteConnectionManager::file(tempfile.path()),
-            Some(tempfile),
-        )
+            Some(TempStorage::File(tempfile)),
+        )?
+        .migrated()
     }
 
-    fn new(conn: SqliteConnectionManager, tempfile: Option<NamedTempFile>) -> Fallible<Self> {
+    /// Database whose connections can never be opened, to check how its failures are handled.
+    #[cfg(test)]
+    pub fn unreachable() -> Self {
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(SqliteConnectionManager::file("/nonexistent/crater.db"));
+
+        Database {
+            backend: Backend::Sqlite(pool),
+            temp: None,
+        }
+    }
+
+    fn new_sqlite(conn: SqliteConnectionManager, temp: Option<TempStorage>) -> Fallible<Self> {
         let pool = Pool::builder()
             .connection_customizer(Box::new(ConnectionCustomizer))
             .build(conn)?;
 
-        migrations::execute(&mut pool.get()? as &mut Connection)?;
+        Ok(Database {
+            backend: Backend::Sqlite(pool),
+            temp: temp.map(Arc::new),
+        })
+    }
+
+    #[cfg(feature = "postgresql")]
+    fn new_postgres(conn: pg::ConnectionManager, temp: Option<TempStorage>) -> Fallible<Self> {
+        let pool = Pool::builder().build(conn)?;
 
         Ok(Database {
-            pool,
-            tempfile: tempfile.map(Arc::new),
+            backend: Backend::Postgres(pool),
+            temp: temp.map(Arc::new),
+        })
+    }
+
+    fn migrated(self) -> Fallible<Self> {
+        self.migrate()?;
+        Ok(self)
+    }
+
+    /// Apply the pending migrations, returning their names. Databases migrated by a newer version
+    /// of crater are refused.
+    pub fn migrate(&self) -> Fallible<Vec<&'static str>> {
+        match self.backend {
+            Backend::Sqlite(ref pool) => migrations::execute(&mut pool.get()? as &mut Connection),
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(ref pool) => migrations::execute_postgres(&pool.get()?),
+        }
+    }
+
+    pub fn schema_status(&self) -> Fallible<SchemaStatus> {
+        match self.backend {
+            Backend::Sqlite(ref pool) => migrations::status(&pool.get()?),
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(ref pool) => migrations::status_postgres(&pool.get()?),
+        }
+    }
+
+    /// Names and SQL of the migrations not applied to the database yet.
+    pub fn pending_migrations(&self) -> Fallible<Vec<(&'static str, String)>> {
+        match self.backend {
+            Backend::Sqlite(ref pool) => migrations::pending(&pool.get()?),
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(ref pool) => migrations::pending_postgres(&pool.get()?),
+        }
+    }
+
+    /// Copy all the data of this SQLite database to an empty PostgreSQL database.
+    #[cfg(feature = "postgresql")]
+    pub fn copy_to_postgres(&self, url: &str) -> Fallible<()> {
+        if let Backend::Postgres(_) = self.backend {
+            bail!("the source database must be a SQLite database");
+        }
+
+        let dest = Database::open_postgres(url)?;
+        for &(table, _) in TABLES {
+            if dest.exists(&format!("SELECT 1 FROM {} LIMIT 1;", table), &[])? {
+                bail!(
+                    "the {} table of the PostgreSQL database is not empty",
+                    table
+                );
+            }
+        }
+
+        dest.transaction(|t| {
+            for &(table, columns) in TABLES {
+                let select = format!(
+                    "SELECT {} FROM {} ORDER BY rowid LIMIT ?1 OFFSET ?2;",
+                    columns.join(", "),
+                    table
+                );
+                let insert = format!(
+                    "INSERT INTO {} ({}) VALUES ({});",
+                    table,
+                    columns.join(", "),
+                    (1..=columns.len())
+                        .map(|i| format!("?{}", i))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+
+                let mut copied = 0;
+                loop {
+                    let rows = self.query(&select, &[&COPY_BATCH_SIZE, &copied], |row| {
+                        (0..columns.len() as i32)
+                            .map(|i| row.get::<_, Value>(i))
+                            .collect::<Vec<_>>()
+                    })?;
+                    for values in &rows {
+                        let params = values.iter().map(|v| v as &ToSql).collect::<Vec<_>>();
+                        t.execute(&insert, &params)?;
+                    }
+
+                    copied += rows.len() as i64;
+                    if (rows.len() as i64) < COPY_BATCH_SIZE {
+                        break;
+                    }
+                }
+
+                // The rows were inserted with their original rowid, so the sequence needs to
+                // continue after the highest one
+                if columns.contains(&"rowid") {
+                    t.execute(
+                        &format!(
+                            "SELECT setval(pg_get_serial_sequence('{0}', 'rowid'), \
+                             (SELECT COALESCE(MAX(rowid), 0) + 1 FROM {0}), false);",
+                            table
+                        ),
+                        &[],
+                    )?;
+                }
+
+                info!("copied {} rows of the {} table", copied, table);
+            }
+
+            Ok(())
         })
     }
 
@@ -81,58 +459,165 @@ impl Database {
         &self,
         f: F,
     ) -> Fallible<T> {
-        let mut conn = self.pool.get()?;
-        let handle = TransactionHandle {
-            transaction: conn.transaction()?,
-        };
+        match self.backend {
+            Backend::Sqlite(ref pool) => {
+                let mut conn = pool.get()?;
+                // Take the write lock right away: upgrading a read lock fails immediately instead
+                // of waiting for the busy timeout when another connection is writing
+                let handle = TransactionHandle::Sqlite(
+                    conn.transaction_with_behavior(TransactionBehavior::Immediate)?,
+                );
+                let result = f(&handle);
+                handle.finish(result)
+            }
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(ref pool) => {
+                let conn = pool.get()?;
+                let handle = TransactionHandle::Postgres(conn.transaction()?);
+                let result = f(&handle);
+                handle.finish(result)
+            }
+        }
+    }
+}
 
-        match f(&handle) {
+pub enum TransactionHandle<'a> {
+    Sqlite(Transaction<'a>),
+    #[cfg(feature = "postgresql")]
+    Postgres(::postgres::transaction::Transaction<'a>),
+}
+
+impl<'a> TransactionHandle<'a> {
+    fn finish<T>(self, result: Fallible<T>) -> Fallible<T> {
+        match result {
             Ok(res) => {
-                handle.commit()?;
+                self.commit()?;
                 Ok(res)
             }
             Err(err) => {
-                handle.rollback()?;
+                self.rollback()?;
                 Err(err)
             }
         }
     }
-}
 
-pub struct TransactionHandle<'a> {
-    transaction: Transaction<'a>,
-}
-
-impl<'a> TransactionHandle<'a> {
     pub fn commit(self) -> Fallible<()> {
-        self.transaction.commit()?;
+        match self {
+            TransactionHandle::Sqlite(t) => t.commit()?,
+            #[cfg(feature = "postgresql")]
+            TransactionHandle::Postgres(t) => t.commit()?,
+        }
         Ok(())
     }
 
     pub fn rollback(self) -> Fallible<()> {
-        self.transaction.rollback()?;
+        match self {
+            TransactionHandle::Sqlite(t) => t.rollback()?,
+            #[cfg(feature = "postgresql")]
+            TransactionHandle::Postgres(t) => {
+                t.set_rollback();
+                t.finish()?;
+            }
+        }
         Ok(())
     }
 }
 
+/// How the backend executes the query, one line per step.
+fn query_plan(conn: Conn, sql: &str, params: &[&ToSql]) -> Fallible<Vec<String>> {
+    match conn {
+        Conn::Sqlite(conn) => {
+            let mut prepared = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+            let steps = prepared.query_map(params, |row| -> String { row.get("detail") })?;
+            Ok(steps.collect::<::rusqlite::Result<_>>()?)
+        }
+        #[cfg(feature = "postgresql")]
+        Conn::Postgres(conn) => Ok(pg::query(conn, &format!("EXPLAIN {}", sql), params)?
+            .iter()
+            .map(|row| -> String { Row(RowInner::Postgres(row)).get(0) })
+            .collect()),
+    }
+}
+
+/// Connection to one of the supported backends.
+#[derive(Copy, Clone)]
+pub enum Conn<'a> {
+    Sqlite(&'a Connection),
+    #[cfg(feature = "postgresql")]
+    Postgres(&'a ::postgres::GenericConnection),
+}
+
+/// Row returned by a query. Queries are written in the SQLite dialect and their values are
+/// converted with the `rusqlite` traits regardless of the backend.
+pub struct Row<'a>(RowInner<'a>);
+
+enum RowInner<'a> {
+    Sqlite(&'a ::rusqlite::Row<'a, 'a>),
+    #[cfg(feature = "postgresql")]
+    Postgres(&'a pg::PgRow),
+}
+
+impl<'a> Row<'a> {
+    /// Get the value of a column, panicking if it doesn't exist or can't be converted.
+    pub fn get<I: RowIndex, T: FromSql>(&self, idx: I) -> T {
+        match self.0 {
+            RowInner::Sqlite(row) => row.get(idx),
+            #[cfg(feature = "postgresql")]
+            RowInner::Postgres(row) => row.get(idx),
+        }
+    }
+}
+
+/// Columns can be selected either by their position or by their name.
+pub trait RowIndex: ::rusqlite::RowIndex + ::std::fmt::Debug {
+    #[cfg(feature = "postgresql")]
+    fn position(&self, columns: &[String]) -> Option<usize>;
+}
+
+impl RowIndex for i32 {
+    #[cfg(feature = "postgresql")]
+    fn position(&self, columns: &[String]) -> Option<usize> {
+        if *self >= 0 && (*self as usize) < columns.len() {
+            Some(*self as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> RowIndex for &'a str {
+    #[cfg(feature = "postgresql")]
+    fn position(&self, columns: &[String]) -> Option<usize> {
+        columns.iter().position(|column| column == self)
+    }
+}
+
 pub trait QueryUtils {
-    fn with_conn<T, F: FnOnce(&Connection) -> Fallible<T>>(&self, f: F) -> Fallible<T>;
+    fn with_conn<T, F: FnOnce(Conn) -> Fallible<T>>(&self, f: F) -> Fallible<T>;
 
     fn exists(&self, sql: &str, params: &[&ToSql]) -> Fallible<bool> {
         self.with_conn(|conn| {
-            self.trace(sql, || {
-                let mut prepared = conn.prepare(sql)?;
-                Ok(prepared.exists(params)?)
+            self.trace(conn, sql, params, || match conn {
+                Conn::Sqlite(conn) => {
+                    let mut prepared = conn.prepare(sql)?;
+                    Ok(prepared.exists(params)?)
+                }
+                #[cfg(feature = "postgresql")]
+                Conn::Postgres(conn) => Ok(!pg::query(conn, sql, params)?.is_empty()),
             })
         })
     }
 
     fn execute(&self, sql: &str, params: &[&ToSql]) -> Fallible<usize> {
         self.with_conn(|conn| {
-            self.trace(sql, || {
-                let mut prepared = conn.prepare(sql)?;
-                let changes = prepared.execute(params)?;
-                Ok(changes)
+            self.trace(conn, sql, params, || match conn {
+                Conn::Sqlite(conn) => {
+                    let mut prepared = conn.prepare(sql)?;
+                    let changes = prepared.execute(params)?;
+                    Ok(changes)
+                }
+                #[cfg(feature = "postgresql")]
+                Conn::Postgres(conn) => pg::execute(conn, sql, params),
             })
         })
     }
@@ -141,18 +626,26 @@ pub trait QueryUtils {
         &self,
         sql: &str,
         params: &[&ToSql],
-        func: F,
+        mut func: F,
     ) -> Fallible<Option<T>> {
         self.with_conn(|conn| {
-            self.trace(sql, || {
-                let mut prepared = conn.prepare(sql)?;
-                let mut iter = prepared.query_map(params, func)?;
-
-                if let Some(item) = iter.next() {
-                    Ok(Some(item?))
-                } else {
-                    Ok(None)
+            self.trace(conn, sql, params, || match conn {
+                Conn::Sqlite(conn) => {
+                    let mut prepared = conn.prepare(sql)?;
+                    let mut iter = prepared.query_map(params, |row: &::rusqlite::Row| {
+                        func(&Row(RowInner::Sqlite(row)))
+                    })?;
+
+                    if let Some(item) = iter.next() {
+                        Ok(Some(item?))
+                    } else {
+                        Ok(None)
+                    }
                 }
+                #[cfg(feature = "postgresql")]
+                Conn::Postgres(conn) => Ok(pg::query(conn, sql, params)?
+                    .first()
+                    .map(|row| func(&Row(RowInner::Postgres(row))))),
             })
         })
     }
@@ -161,24 +654,41 @@ pub trait QueryUtils {
         &self,
         sql: &str,
         params: &[&ToSql],
-        func: F,
+        mut func: F,
     ) -> Fallible<Vec<T>> {
         self.with_conn(|conn| {
-            self.trace(sql, || {
-                let mut prepared = conn.prepare(sql)?;
-                let rows = prepared.query_map(params, func)?;
+            self.trace(conn, sql, params, || match conn {
+                Conn::Sqlite(conn) => {
+                    let mut prepared = conn.prepare(sql)?;
+                    let rows = prepared.query_map(params, |row: &::rusqlite::Row| {
+                        func(&Row(RowInner::Sqlite(row)))
+                    })?;
 
-                let mut results = Vec::new();
-                for row in rows {
-                    results.push(row?);
-                }
+                    let mut results = Vec::new();
+                    for row in rows {
+                        results.push(row?);
+                    }
 
-                Ok(results)
+                    Ok(results)
+                }
+                #[cfg(feature = "postgresql")]
+                Conn::Postgres(conn) => Ok(pg::query(conn, sql, params)?
+                    .iter()
+                    .map(|row| func(&Row(RowInner::Postgres(row))))
+                    .collect()),
             })
         })
     }
 
-    fn trace<T, F: FnOnce() -> T>(&self, sql: &str, f: F) -> T {
+    fn trace<T, F: FnOnce() -> T>(&self, conn: Conn, sql: &str, params: &[&ToSql], f: F) -> T {
+        // Only the plans of the queries reading data depend on the indexes
+        if log_enabled!(Level::Debug) && sql.trim_start().starts_with("SELECT") {
+            match query_plan(conn, sql, params) {
+                Ok(plan) => debug!("plan of sql query \"{}\": {}", sql, plan.join("; ")),
+                Err(err) => debug!("failed to explain sql query \"{}\": {}", sql, err),
+            }
+        }
+
         let start = Instant::now();
         let res = f();
         trace!("sql query \"{}\" executed in {:?}", sql, start.elapsed());
@@ -187,13 +697,23 @@ pub trait QueryUtils {
 }
 
 impl QueryUtils for Database {
-    fn with_conn<T, F: FnOnce(&Connection) -> Fallible<T>>(&self, f: F) -> Fallible<T> {
-        f(&self.pool.get()? as &Connection)
+    fn with_conn<T, F: FnOnce(Conn) -> Fallible<T>>(&self, f: F) -> Fallible<T> {
+        match self.backend {
+            Backend::Sqlite(ref pool) => f(Conn::Sqlite(&pool.get()? as &Connection)),
+            #[cfg(feature = "postgresql")]
+            Backend::Postgres(ref pool) => {
+                f(Conn::Postgres(&pool.get()? as &::postgres::Connection))
+            }
+        }
     }
 }
 
 impl<'a> QueryUtils for TransactionHandle<'a> {
-    fn with_conn<T, F: FnOnce(&Connection) -> Fallible<T>>(&self, f: F) -> Fallible<T> {
-        f(&self.transaction)
+    fn with_conn<T, F: FnOnce(Conn) -> Fallible<T>>(&self, f: F) -> Fallible<T> {
+        match *self {
+            TransactionHandle::Sqlite(ref t) => f(Conn::Sqlite(t)),
+            #[cfg(feature = "postgresql")]
+            TransactionHandle::Postgres(ref t) => f(Conn::Postgres(t)),
+        }
     }
 }
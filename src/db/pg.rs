@@ -0,0 +1,353 @@
+//! PostgreSQL backend of the database. Queries are written in the SQLite dialect: they're
+//! translated to PostgreSQL before being executed, and their parameters and results are converted
+//! with the `rusqlite` traits so the rest of crater doesn't need to know which backend is used.
+
+use crate::prelude::*;
+use postgres::types::{
+    FromSql, IsNull, ToSql, Type, BOOL, BPCHAR, BYTEA, FLOAT4, FLOAT8, INT2, INT4, INT8, NAME,
+    TEXT, VARCHAR,
+};
+use postgres::{Connection, GenericConnection};
+use r2d2::ManageConnection;
+use r2d2_postgres::PostgresConnectionManager;
+use regex::Regex;
+use rusqlite::types::{
+    FromSql as SqliteFromSql, ToSql as SqliteToSql, ToSqlOutput, Value, ValueRef,
+};
+use std::error::Error as StdError;
+use std::sync::Arc;
+
+type ConversionResult<T> = Result<T, Box<StdError + Sync + Send>>;
+
+lazy_static! {
+    static ref INSERT_INTO: Regex = Regex::new(r"^\s*INSERT INTO (\w+)\s*\(([^)]*)\)").unwrap();
+}
+
+/// Tables whose primary key was declared with a conflict clause in the SQLite schema, as inserting
+/// a duplicate row into them replaces or ignores it instead of failing.
+static CONFLICT_CLAUSES: &[(&str, &str, bool)] = &[
+    ("results", "experiment, crate, toolchain", true),
+    ("saved_names", "issue", true),
+    ("crates", "crate, list", true),
+    ("partial_logs", "experiment, crate, toolchain, chunk", true),
+    ("environments", "experiment, fingerprint", false),
+    ("crate_assignments", "experiment, crate", true),
+    ("agent_tokens", "agent", true),
+];
+
+/// Rewrite a query from the SQLite dialect used by crater to PostgreSQL.
+pub(super) fn translate(sql: &str) -> String {
+    let mut conflict = None;
+    let mut sql = sql.trim().trim_end_matches(';').to_string();
+
+    if sql.starts_with("INSERT OR IGNORE INTO") {
+        sql = sql.replacen("INSERT OR IGNORE INTO", "INSERT INTO", 1);
+        conflict = Some("ON CONFLICT DO NOTHING".to_string());
+    } else if let Some(captures) = INSERT_INTO.captures(&sql) {
+        let table = &captures[1];
+        for &(name, key, replace) in CONFLICT_CLAUSES {
+            if table != name {
+                continue;
+            }
+
+            conflict = Some(if replace {
+                let updates = captures[2]
+                    .split(',')
+                    .map(|column| column.trim())
+                    .filter(|column| !key.split(", ").any(|k| k == *column))
+                    .map(|column| format!("{} = EXCLUDED.{}", column, column))
+                    .collect::<Vec<_>>();
+                format!("ON CONFLICT ({}) DO UPDATE SET {}", key, updates.join(", "))
+            } else {
+                "ON CONFLICT DO NOTHING".to_string()
+            });
+        }
+    }
+
+    // Replace the placeholders and the blob literals, skipping the string literals
+    let mut translated = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            in_string = c != '\'';
+            translated.push(c);
+        } else if c == '\'' {
+            in_string = true;
+            translated.push(c);
+        } else if c == '?' {
+            translated.push('$');
+        } else if c == 'X' && chars.peek() == Some(&'\'') {
+            chars.next();
+            let mut hex = String::new();
+            while let Some(c) = chars.next() {
+                if c == '\'' {
+                    break;
+                }
+                hex.push(c);
+            }
+            translated.push_str(&format!("'\\x{}'::BYTEA", hex));
+        } else {
+            translated.push(c);
+        }
+    }
+
+    if let Some(conflict) = conflict {
+        translated.push(' ');
+        translated.push_str(&conflict);
+    }
+    translated.push(';');
+    translated
+}
+
+/// Value passed to or returned by PostgreSQL, converted to the type of the column.
+#[derive(Debug)]
+struct PgValue(Value);
+
+impl ToSql for PgValue {
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> ConversionResult<IsNull> {
+        match self.0 {
+            Value::Null => Ok(IsNull::Yes),
+            Value::Integer(int) if *ty == BOOL => (int != 0).to_sql(ty, out),
+            Value::Integer(int) if *ty == INT2 => (int as i16).to_sql(ty, out),
+            Value::Integer(int) if *ty == INT4 => (int as i32).to_sql(ty, out),
+            Value::Integer(int) if *ty == INT8 => int.to_sql(ty, out),
+            Value::Real(real) if *ty == FLOAT4 => (real as f32).to_sql(ty, out),
+            Value::Real(real) if *ty == FLOAT8 => real.to_sql(ty, out),
+            Value::Text(ref text) if *ty == BYTEA => text.as_bytes().to_sql(ty, out),
+            Value::Text(ref text) => text.to_sql(ty, out),
+            Value::Blob(ref blob) => blob.to_sql(ty, out),
+            ref other => Err(format!("can't convert {:?} to {}", other, ty).into()),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn to_sql_checked(&self, ty: &Type, out: &mut Vec<u8>) -> ConversionResult<IsNull> {
+        self.to_sql(ty, out)
+    }
+}
+
+impl FromSql for PgValue {
+    fn from_sql(ty: &Type, raw: &[u8]) -> ConversionResult<Self> {
+        Ok(PgValue(if *ty == BOOL {
+            Value::Integer(bool::from_sql(ty, raw)? as i64)
+        } else if *ty == INT2 {
+            Value::Integer(i64::from(i16::from_sql(ty, raw)?))
+        } else if *ty == INT4 {
+            Value::Integer(i64::from(i32::from_sql(ty, raw)?))
+        } else if *ty == INT8 {
+            Value::Integer(i64::from_sql(ty, raw)?)
+        } else if *ty == FLOAT4 {
+            Value::Real(f64::from(f32::from_sql(ty, raw)?))
+        } else if *ty == FLOAT8 {
+            Value::Real(f64::from_sql(ty, raw)?)
+        } else if *ty == TEXT || *ty == VARCHAR || *ty == BPCHAR || *ty == NAME {
+            Value::Text(String::from_sql(ty, raw)?)
+        } else if *ty == BYTEA {
+            Value::Blob(Vec::from_sql(ty, raw)?)
+        } else {
+            return Err(format!("unsupported column type: {}", ty).into());
+        }))
+    }
+
+    fn from_sql_null(_ty: &Type) -> ConversionResult<Self> {
+        Ok(PgValue(Value::Null))
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+}
+
+pub struct PgRow {
+    columns: Arc<Vec<String>>,
+    values: Vec<Value>,
+}
+
+impl PgRow {
+    pub(super) fn get<I: super::RowIndex, T: SqliteFromSql>(&self, idx: I) -> T {
+        let position = idx
+            .position(&self.columns)
+            .unwrap_or_else(|| panic!("invalid column {:?}", idx));
+        match T::column_result(ValueRef::from(&self.values[position])) {
+            Ok(value) => value,
+            Err(err) => panic!("failed to convert column {:?}: {}", idx, err),
+        }
+    }
+}
+
+fn convert_params(params: &[&SqliteToSql]) -> Fallible<Vec<PgValue>> {
+    params
+        .iter()
+        .map(|param| {
+            Ok(PgValue(match param.to_sql()? {
+                ToSqlOutput::Borrowed(value) => value.into(),
+                ToSqlOutput::Owned(value) => value,
+            }))
+        })
+        .collect()
+}
+
+pub(super) fn execute(
+    conn: &GenericConnection,
+    sql: &str,
+    params: &[&SqliteToSql],
+) -> Fallible<usize> {
+    let params = convert_params(params)?;
+    let params = params.iter().map(|p| p as &ToSql).collect::<Vec<_>>();
+    Ok(conn.execute(&translate(sql), &params)? as usize)
+}
+
+pub(super) fn query(
+    conn: &GenericConnection,
+    sql: &str,
+    params: &[&SqliteToSql],
+) -> Fallible<Vec<PgRow>> {
+    let params = convert_params(params)?;
+    let params = params.iter().map(|p| p as &ToSql).collect::<Vec<_>>();
+    let rows = conn.query(&translate(sql), &params)?;
+
+    let columns = Arc::new(
+        rows.columns()
+            .iter()
+            .map(|column| column.name().to_string())
+            .collect::<Vec<_>>(),
+    );
+    let mut result = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let mut values = Vec::with_capacity(columns.len());
+        for i in 0..columns.len() {
+            let value: PgValue = row.get_opt(i).unwrap()?;
+            values.push(value.0);
+        }
+        result.push(PgRow {
+            columns: columns.clone(),
+            values,
+        });
+    }
+    Ok(result)
+}
+
+/// Connections to PostgreSQL, optionally restricted to a single schema.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    inner: PostgresConnectionManager,
+    schema: Option<String>,
+}
+
+impl ConnectionManager {
+    pub(super) fn new(url: &str, schema: Option<String>) -> Fallible<Self> {
+        Ok(ConnectionManager {
+            inner: PostgresConnectionManager::new(url, r2d2_postgres::TlsMode::None)?,
+            schema,
+        })
+    }
+}
+
+impl ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = postgres::Error;
+
+    fn connect(&self) -> Result<Connection, postgres::Error> {
+        let conn = self.inner.connect()?;
+        if let Some(ref schema) = self.schema {
+            conn.batch_execute(&format!("SET search_path TO {};", schema))?;
+        }
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<(), postgres::Error> {
+        self.inner.is_valid(conn)
+    }
+
+    fn has_broken(&self, conn: &mut Connection) -> bool {
+        self.inner.has_broken(conn)
+    }
+}
+
+/// Schema created for a temporary database, dropped with all its content when this is dropped.
+#[cfg(test)]
+pub struct TempSchema {
+    url: String,
+    name: String,
+}
+
+#[cfg(test)]
+impl TempSchema {
+    pub(super) fn create(url: &str) -> Fallible<Self> {
+        use postgres::TlsMode;
+        use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+        let name = format!(
+            "crater_temp_{}",
+            thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .collect::<String>()
+                .to_lowercase()
+        );
+        Connection::connect(url, TlsMode::None)?
+            .batch_execute(&format!("CREATE SCHEMA {};", name))?;
+
+        Ok(TempSchema {
+            url: url.to_string(),
+            name,
+        })
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempSchema {
+    fn drop(&mut self) {
+        let result = Connection::connect(self.url.as_str(), postgres::TlsMode::None)
+            .and_then(|conn| conn.batch_execute(&format!("DROP SCHEMA {} CASCADE;", self.name)));
+        if let Err(err) = result {
+            warn!("failed to drop the temporary schema {}: {}", self.name, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::translate;
+
+    #[test]
+    fn test_translate() {
+        // Placeholders are replaced outside of string literals
+        assert_eq!(
+            translate("SELECT * FROM results WHERE result != 'a?1' AND crate = ?1;"),
+            "SELECT * FROM results WHERE result != 'a?1' AND crate = $1;"
+        );
+        assert_eq!(
+            translate("UPDATE results SET log = X'' WHERE experiment = ?1"),
+            "UPDATE results SET log = '\\x'::BYTEA WHERE experiment = $1;"
+        );
+
+        // Conflict clauses of the SQLite schema are added to the inserts
+        assert_eq!(
+            translate("INSERT OR IGNORE INTO log_blobs (hash, log) VALUES (?1, ?2);"),
+            "INSERT INTO log_blobs (hash, log) VALUES ($1, $2) ON CONFLICT DO NOTHING;"
+        );
+        assert_eq!(
+            translate("INSERT INTO saved_names (issue, experiment) VALUES (?1, ?2);"),
+            "INSERT INTO saved_names (issue, experiment) VALUES ($1, $2) \
+             ON CONFLICT (issue) DO UPDATE SET experiment = EXCLUDED.experiment;"
+        );
+        assert_eq!(
+            translate("INSERT INTO environments (experiment, fingerprint) VALUES (?1, ?2);"),
+            "INSERT INTO environments (experiment, fingerprint) VALUES ($1, $2) \
+             ON CONFLICT DO NOTHING;"
+        );
+        assert_eq!(
+            translate("INSERT INTO shas (experiment, org, name, sha) VALUES (?1, ?2, ?3, ?4)"),
+            "INSERT INTO shas (experiment, org, name, sha) VALUES ($1, $2, $3, $4);"
+        );
+    }
+}
@@ -25,6 +25,9 @@ lazy_static! {
     // in docker containers
     pub static ref TEST_SOURCE_DIR: PathBuf = LOCAL_DIR.join("test-source");
 
+    // Clone of the crates.io index
+    pub static ref REGISTRY_INDEX_DIR: PathBuf = LOCAL_DIR.join("crates.io-index");
+
     // Where GitHub crate mirrors are stored
     pub static ref GH_MIRRORS_DIR: PathBuf = LOCAL_DIR.join("gh-mirrors");
 
@@ -5,6 +5,7 @@ use std::env;
 use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 pub(crate) fn is_running() -> bool {
     info!("checking if the docker daemon is running");
@@ -15,6 +16,14 @@ pub(crate) fn is_running() -> bool {
         .is_ok()
 }
 
+/// Remove the stopped containers, for example the ones left behind by a crash of crater.
+pub(crate) fn prune_containers() -> Fallible<()> {
+    info!("removing the stopped docker containers");
+    RunCommand::new("docker")
+        .args(&["container", "prune", "--force"])
+        .run()
+}
+
 pub(crate) struct DockerEnv {
     image: String,
     local: bool,
@@ -44,6 +53,25 @@ impl DockerEnv {
         Ok(())
     }
 
+    /// Resolve the digest of the image, to know exactly which image is used even if the tag
+    /// is later updated. Images that were never pushed to a registry are identified by their
+    /// local ID instead.
+    pub(crate) fn digest(&self) -> Fallible<String> {
+        let (stdout, _) = RunCommand::new("docker")
+            .args(&[
+                "image",
+                "inspect",
+                "--format",
+                "{{.Id}} {{range .RepoDigests}}{{.}} {{end}}",
+                &self.image,
+            ])
+            .hide_output(true)
+            .run_capture()?;
+
+        parse_digest(&stdout.join("\n"))
+            .ok_or_else(|| err_msg(format!("failed to resolve the digest of {}", self.image)))
+    }
+
     fn pull(&self) -> Fallible<()> {
         info!("pulling image {} from Docker Hub", self.image);
         RunCommand::new("docker")
@@ -53,6 +81,12 @@ impl DockerEnv {
     }
 }
 
+fn parse_digest(inspect_output: &str) -> Option<String> {
+    let mut parts = inspect_output.split_whitespace();
+    let id = parts.next()?;
+    Some(parts.next().unwrap_or(id).to_string())
+}
+
 #[derive(Copy, Clone)]
 pub(crate) enum MountPerms {
     ReadWrite,
@@ -84,10 +118,12 @@ pub(crate) struct ContainerBuilder<'a> {
     image: &'a DockerEnv,
     mounts: Vec<MountConfig>,
     env: Vec<(String, String)>,
+    secret_env: Vec<(String, String)>,
     memory_limit: Option<Size>,
     workdir: Option<String>,
     cmd: Vec<String>,
     enable_networking: bool,
+    interactive: bool,
 }
 
 impl<'a> ContainerBuilder<'a> {
@@ -96,10 +132,12 @@ impl<'a> ContainerBuilder<'a> {
             image,
             mounts: Vec::new(),
             env: Vec::new(),
+            secret_env: Vec::new(),
             workdir: None,
             memory_limit: None,
             cmd: Vec::new(),
             enable_networking: true,
+            interactive: false,
         }
     }
 
@@ -122,6 +160,17 @@ impl<'a> ContainerBuilder<'a> {
         self
     }
 
+    /// Set an environment variable without passing its value on the command line of docker,
+    /// which would leak it in the logs.
+    pub(crate) fn secret_env<S1: Into<String>, S2: Into<String>>(
+        mut self,
+        key: S1,
+        value: S2,
+    ) -> Self {
+        self.secret_env.push((key.into(), value.into()));
+        self
+    }
+
     pub(crate) fn workdir<S: Into<String>>(mut self, workdir: S) -> Self {
         self.workdir = Some(workdir.into());
         self
@@ -142,9 +191,20 @@ impl<'a> ContainerBuilder<'a> {
         self
     }
 
+    /// Attach the terminal to the container, for commands reading from it.
+    pub(crate) fn interactive(mut self, enable: bool) -> Self {
+        self.interactive = enable;
+        self
+    }
+
     pub(crate) fn create(self) -> Fallible<Container> {
         let mut args: Vec<String> = vec!["create".into()];
 
+        if self.interactive {
+            args.push("--interactive".into());
+            args.push("--tty".into());
+        }
+
         for mount in &self.mounts {
             fs::create_dir_all(&mount.host_path)?;
             args.push("-v".into());
@@ -156,6 +216,14 @@ impl<'a> ContainerBuilder<'a> {
             args.push(format! {"{}={}", var, value})
         }
 
+        // Docker reads the value of the variables passed without one from its own environment
+        let mut create = RunCommand::new("docker");
+        for (var, value) in self.secret_env {
+            args.push("-e".into());
+            args.push(var.clone());
+            create = create.secret_env(var, value);
+        }
+
         if let Some(workdir) = self.workdir {
             args.push("-w".into());
             args.push(workdir);
@@ -177,11 +245,17 @@ impl<'a> ContainerBuilder<'a> {
             args.push(arg);
         }
 
-        let (out, _) = RunCommand::new("docker").args(&*args).run_capture()?;
+        let (out, _) = create.args(&*args).run_capture()?;
+        debug!(
+            "created container {} with `docker {}`",
+            out[0],
+            args.join(" ")
+        );
         Ok(Container { id: out[0].clone() })
     }
 
     pub(crate) fn run(self, quiet: bool) -> Fallible<()> {
+        let interactive = self.interactive;
         let container = self.create()?;
 
         // Ensure the container is properly deleted even if something panics
@@ -193,7 +267,11 @@ impl<'a> ContainerBuilder<'a> {
             }
         }}
 
-        container.run(quiet)?;
+        if interactive {
+            container.run_interactive()?;
+        } else {
+            container.run(quiet)?;
+        }
         Ok(())
     }
 }
@@ -207,10 +285,15 @@ fn absolute(path: &Path) -> PathBuf {
     }
 }
 
+/// Exit status of a container whose setup failed before the command was started.
+pub(crate) const SETUP_FAILED_EXIT_CODE: i32 = 97;
+
 #[derive(Debug, Fail)]
 pub(crate) enum DockerError {
     #[fail(display = "container ran out of memory")]
     ContainerOOM,
+    #[fail(display = "setup of the container failed")]
+    SetupFailed,
 }
 
 #[derive(Deserialize)]
@@ -223,6 +306,8 @@ struct InspectContainer {
 struct InspectState {
     #[serde(rename = "OOMKilled")]
     oom_killed: bool,
+    #[serde(rename = "ExitCode")]
+    exit_code: i32,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -263,14 +348,52 @@ impl Container {
             } else {
                 Err(DockerError::ContainerOOM.into())
             }
+        } else if details.state.exit_code == SETUP_FAILED_EXIT_CODE {
+            res.map_err(|err| err.context(DockerError::SetupFailed).into())
         } else {
             res
         }
     }
 
+    /// Run the container with the terminal attached to it. Its output is not logged, and its exit
+    /// status is the one of the last command the user ran in it, so it's not checked.
+    fn run_interactive(&self) -> Fallible<()> {
+        Command::new("docker")
+            .args(&["start", "--attach", "--interactive", &self.id])
+            .status()?;
+        Ok(())
+    }
+
     pub(crate) fn delete(&self) -> Fallible<()> {
         RunCommand::new("docker")
             .args(&["rm", "-f", &self.id])
             .run()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_digest;
+
+    #[test]
+    fn test_parse_digest() {
+        // Images pulled from a registry are identified by their repo digest
+        assert_eq!(
+            parse_digest(
+                "sha256:0123 rustops/crates-build-env@sha256:abcd \
+                 rustops/crates-build-env@sha256:ef01 \n"
+            )
+            .as_ref()
+            .map(|s| s.as_str()),
+            Some("rustops/crates-build-env@sha256:abcd")
+        );
+
+        // Local images only have an ID
+        assert_eq!(
+            parse_digest("sha256:0123 \n").as_ref().map(|s| s.as_str()),
+            Some("sha256:0123")
+        );
+
+        assert_eq!(parse_digest(""), None);
+    }
+}
@@ -1,12 +1,14 @@
 use crate::crates::Crate;
-use crate::db::{Database, QueryUtils};
+use crate::db::{Database, QueryUtils, Row};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 use chrono::{DateTime, Utc};
-use rusqlite::Row;
+use rusqlite::types::ToSql;
 use serde_json;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::str::FromStr;
+use utoipa::ToSchema;
 
 string_enum!(pub enum Status {
     Queued => "queued",
@@ -17,6 +19,42 @@ string_enum!(pub enum Status {
     Completed => "completed",
 });
 
+// Crates without an assignment are pending, either because no agent started testing them or
+// because the agent testing them stopped responding.
+string_enum!(pub enum CrateAssignment {
+    Pending => "pending",
+    InProgress => "in-progress",
+    Completed => "completed",
+});
+
+impl Status {
+    /// Whether an administrator can move an experiment from this status to `to` without forcing
+    /// it, which is only allowed to recover experiments stuck in a status.
+    pub fn can_override_to(self, to: Status) -> bool {
+        match (self, to) {
+            (from, to) if from == to => true,
+            // The agent running the experiment stopped, or it finished without reporting it
+            (Status::Running, Status::Queued) | (Status::Running, Status::NeedsReport) => true,
+            // The reports worker stopped while generating the report, or it needs to run again
+            (Status::GeneratingReport, Status::NeedsReport)
+            | (Status::GeneratingReport, Status::ReportFailed)
+            | (Status::ReportFailed, Status::NeedsReport)
+            | (Status::Completed, Status::NeedsReport) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "can't change the status of the experiment from {} to {} without forcing it",
+    from, to
+)]
+pub struct InvalidStatusOverride {
+    pub from: Status,
+    pub to: Status,
+}
+
 string_enum!(pub enum Mode {
     BuildAndTest => "build-and-test",
     BuildOnly => "build-only",
@@ -33,6 +71,11 @@ string_enum!(pub enum CrateSelect {
     Local => "local",
 });
 
+string_enum!(pub enum TestRunner {
+    Libtest => "libtest",
+    Nextest => "nextest",
+});
+
 string_enum!(pub enum CapLints {
     Allow => "allow",
     Warn => "warn",
@@ -40,11 +83,97 @@ string_enum!(pub enum CapLints {
     Forbid => "forbid",
 });
 
+/// Cargo features enabled while building a crate. Every crate of an experiment is built once for
+/// each of its feature sets.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum FeatureSet {
+    Default,
+    NoDefault,
+    All,
+    /// The listed features, in addition to the default ones.
+    Features(Vec<String>),
+}
+
+impl FeatureSet {
+    pub fn is_default(&self) -> bool {
+        *self == FeatureSet::Default
+    }
+
+    pub(crate) fn cargo_args(&self) -> Vec<String> {
+        match self {
+            FeatureSet::Default => Vec::new(),
+            FeatureSet::NoDefault => vec!["--no-default-features".into()],
+            FeatureSet::All => vec!["--all-features".into()],
+            FeatureSet::Features(features) => vec!["--features".into(), features.join(",")],
+        }
+    }
+}
+
+impl Default for FeatureSet {
+    fn default() -> Self {
+        FeatureSet::Default
+    }
+}
+
+impl fmt::Display for FeatureSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FeatureSet::Default => write!(f, "default"),
+            FeatureSet::NoDefault => write!(f, "no-default-features"),
+            FeatureSet::All => write!(f, "all-features"),
+            FeatureSet::Features(features) => write!(f, "{}", features.join(",")),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub enum FeatureSetParseError {
+    #[fail(display = "the feature set is empty")]
+    Empty,
+    #[fail(display = "invalid feature name: {}", _0)]
+    InvalidFeature(String),
+}
+
+impl FromStr for FeatureSet {
+    type Err = FeatureSetParseError;
+
+    fn from_str(input: &str) -> Result<Self, FeatureSetParseError> {
+        match input {
+            "" => Err(FeatureSetParseError::Empty),
+            "default" => Ok(FeatureSet::Default),
+            "no-default-features" => Ok(FeatureSet::NoDefault),
+            "all-features" => Ok(FeatureSet::All),
+            list => {
+                let mut features = Vec::new();
+                for feature in list.split(',') {
+                    // Features of dependencies (`dependency/feature`) are not supported, as the
+                    // feature set is part of the paths of the logs
+                    let valid = !feature.is_empty()
+                        && feature
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                    if !valid {
+                        return Err(FeatureSetParseError::InvalidFeature(feature.into()));
+                    }
+                    features.push(feature.to_string());
+                }
+                Ok(FeatureSet::Features(features))
+            }
+        }
+    }
+}
+
+impl_serde_from_parse!(FeatureSet, expecting = "a feature set");
+impl_string_schema!(FeatureSet, example = "no-default-features");
+
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub enum Assignee {
     Agent(String),
     CLI,
+    /// Pool of queued experiments any agent can claim.
+    Any,
 }
 
 impl fmt::Display for Assignee {
@@ -52,6 +181,7 @@ impl fmt::Display for Assignee {
         match self {
             Assignee::Agent(ref name) => write!(f, "agent:{}", name),
             Assignee::CLI => write!(f, "cli"),
+            Assignee::Any => write!(f, "any"),
         }
     }
 }
@@ -87,26 +217,89 @@ impl FromStr for Assignee {
 
                 Ok(Assignee::Agent(name.to_string()))
             }
-            "cli" => {
+            "cli" | "any" => {
                 if split.next().is_some() {
                     return Err(AssigneeParseError::UnexpectedPayload);
                 }
 
-                Ok(Assignee::CLI)
+                Ok(if kind == "cli" {
+                    Assignee::CLI
+                } else {
+                    Assignee::Any
+                })
             }
             invalid => Err(AssigneeParseError::InvalidKind(invalid.into())),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Dependency replaced with a git repository in the `[patch.crates-io]` section of the crates
+/// tested by an experiment, for example to test them against a new version of the dependency.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, ToSchema)]
+pub struct CargoPatch {
+    pub name: String,
+    pub git: String,
+    pub rev: Option<String>,
+}
+
+#[derive(Debug, Fail)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub enum CargoPatchParseError {
+    #[fail(
+        display = "the patch must be in the `name=git-url[#rev]` format: {}",
+        _0
+    )]
+    InvalidFormat(String),
+}
+
+impl FromStr for CargoPatch {
+    type Err = CargoPatchParseError;
+
+    fn from_str(input: &str) -> Result<Self, CargoPatchParseError> {
+        let invalid = || CargoPatchParseError::InvalidFormat(input.into());
+
+        let mut parts = input.splitn(2, '=');
+        let name = parts
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(invalid)?;
+        let source = parts.next().ok_or_else(invalid)?;
+        let mut source = source.splitn(2, '#');
+        let git = source
+            .next()
+            .filter(|git| !git.is_empty())
+            .ok_or_else(invalid)?;
+        let rev = match source.next() {
+            Some("") => return Err(invalid()),
+            rev => rev.map(|rev| rev.to_string()),
+        };
+
+        Ok(CargoPatch {
+            name: name.to_string(),
+            git: git.to_string(),
+            rev,
+        })
+    }
+}
+
+impl fmt::Display for CargoPatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.git)?;
+        if let Some(ref rev) = self.rev {
+            write!(f, "#{}", rev)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, ToSchema)]
 pub struct GitHubIssue {
     pub api_url: String,
     pub html_url: String,
     pub number: i32,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Experiment {
     pub name: String,
     pub crates: Vec<Crate>,
@@ -122,6 +315,43 @@ pub struct Experiment {
     pub assigned_to: Option<Assignee>,
     pub report_url: Option<String>,
     pub ignore_blacklist: bool,
+    pub fail_fast: bool,
+    pub feature_sets: Vec<FeatureSet>,
+    #[serde(default)]
+    pub requested_by: Option<String>,
+    /// Publish a report while the experiment is running, updated as the results come in.
+    #[serde(default)]
+    pub live_report: bool,
+    #[serde(default)]
+    pub cargo_patches: Vec<CargoPatch>,
+    /// Record the direct dependencies of each crate with `cargo metadata` while preparing it.
+    #[serde(default)]
+    pub pre_analyze: bool,
+    /// Publish the report with private ACLs, only reachable through expiring links signed by the
+    /// server for the tokens in `private_readers`.
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub private_readers: Vec<String>,
+    /// Command running the tests of the crates, `cargo test` if not set.
+    #[serde(default)]
+    pub test_runner: Option<TestRunner>,
+    /// Record the size of the artifacts produced by building each crate.
+    #[serde(default)]
+    pub measure_artifacts: bool,
+    /// Record the results without posting anything about the experiment on GitHub, until it's
+    /// promoted by turning the flag off.
+    #[serde(default)]
+    pub silent: bool,
+}
+
+/// Percentage of the jobs completed, rounded up.
+pub fn progress_percent(completed_jobs: u32, total_jobs: u32) -> u8 {
+    if total_jobs != 0 {
+        (completed_jobs as f32 * 100.0 / total_jobs as f32).ceil() as u8
+    } else {
+        0
+    }
 }
 
 impl Experiment {
@@ -172,21 +402,79 @@ impl Experiment {
         }
     }
 
+    /// Experiments completed since `since`, oldest first.
+    pub fn completed_since(db: &Database, since: DateTime<Utc>) -> Fallible<Vec<Experiment>> {
+        let records = db.query(
+            "SELECT * FROM experiments \
+             WHERE status = ?1 AND completed_at >= ?2 \
+             ORDER BY completed_at;",
+            &[&Status::Completed.to_str() as &ToSql, &since],
+            |r| ExperimentDBRecord::from_row(r),
+        )?;
+        records
+            .into_iter()
+            .map(|record| record.into_experiment(db))
+            .collect::<Fallible<_>>()
+    }
+
+    /// Experiment whose report regeneration was requested first, see `RegenerateReport`.
+    pub fn first_report_regeneration(db: &Database) -> Fallible<Option<Experiment>> {
+        let record = db.get_row(
+            "SELECT experiments.* FROM experiments \
+             INNER JOIN report_regenerations \
+             ON report_regenerations.experiment = experiments.name \
+             ORDER BY report_regenerations.requested_at;",
+            &[],
+            |r| ExperimentDBRecord::from_row(r),
+        )?;
+
+        if let Some(record) = record {
+            Ok(Some(record.into_experiment(db)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Remove the pending report regeneration of the experiment, once it was handled.
+    pub fn clear_report_regeneration(&self, db: &Database) -> Fallible<()> {
+        db.execute(
+            "DELETE FROM report_regenerations WHERE experiment = ?1;",
+            &[&self.name],
+        )?;
+        Ok(())
+    }
+
     pub fn next(db: &Database, assignee: &Assignee) -> Fallible<Option<(bool, Experiment)>> {
         // Avoid assigning two experiments to the same agent
         if let Some(experiment) = Experiment::run_by(db, assignee)? {
             return Ok(Some((false, experiment)));
         }
 
+        // Experiments in the `Any` pool are claimed like the unassigned ones
         let record = db.get_row(
             "SELECT * FROM experiments \
-             WHERE status = \"queued\" \
+             WHERE status = ?1 AND (assigned_to IS NULL OR assigned_to = ?2) \
              ORDER BY priority DESC, created_at;",
-            &[],
+            &[&Status::Queued.to_str(), &Assignee::Any.to_string()],
             |r| ExperimentDBRecord::from_row(r),
         )?;
 
         if let Some(record) = record {
+            // Another agent might have claimed the experiment since it was selected
+            let claimed = db.execute(
+                "UPDATE experiments SET assigned_to = ?1 \
+                 WHERE name = ?2 AND status = ?3 AND (assigned_to IS NULL OR assigned_to = ?4);",
+                &[
+                    &assignee.to_string(),
+                    &record.name,
+                    &Status::Queued.to_str(),
+                    &Assignee::Any.to_string(),
+                ],
+            )?;
+            if claimed == 0 {
+                return Experiment::next(db, assignee);
+            }
+
             let mut experiment = record.into_experiment(db)?;
             experiment.set_status(&db, Status::Running)?;
             experiment.set_assigned_to(&db, Some(assignee))?;
@@ -238,6 +526,44 @@ impl Experiment {
         Ok(())
     }
 
+    /// Change the status of the experiment on behalf of an administrator, recording who did it.
+    /// Transitions not allowed by `Status::can_override_to` fail unless `force` is true.
+    pub fn override_status(
+        &mut self,
+        db: &Database,
+        status: Status,
+        user: &str,
+        force: bool,
+    ) -> Fallible<()> {
+        let from = self.status;
+        if !force && !from.can_override_to(status) {
+            return Err(InvalidStatusOverride { from, to: status }.into());
+        }
+
+        self.set_status(db, status)?;
+        // Queued experiments are assigned to an agent again when it picks them up
+        if status == Status::Queued {
+            if let Some(Assignee::Agent(_)) = self.assigned_to {
+                self.set_assigned_to(db, None)?;
+            }
+        }
+
+        db.execute(
+            "INSERT INTO status_overrides \
+             (experiment, old_status, new_status, changed_by, forced, changed_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+            &[
+                &self.name as &ToSql,
+                &from.to_str(),
+                &status.to_str(),
+                &user,
+                &force,
+                &Utc::now(),
+            ],
+        )?;
+        Ok(())
+    }
+
     pub fn set_assigned_to(
         &mut self,
         db: &Database,
@@ -260,6 +586,15 @@ impl Experiment {
         Ok(())
     }
 
+    /// Issue where the messages about the experiment are posted, unless it's silent.
+    pub fn notification_issue(&self) -> Option<&GitHubIssue> {
+        if self.silent {
+            None
+        } else {
+            self.github_issue.as_ref()
+        }
+    }
+
     pub fn raw_progress(&self, db: &Database) -> Fallible<(u32, u32)> {
         let results_len: u32 = db
             .get_row(
@@ -278,33 +613,137 @@ impl Experiment {
             )?
             .unwrap();
 
-        Ok((results_len, crates_len * 2))
+        let builds_per_crate = self.build_toolchains().len() as u32;
+        Ok((results_len, crates_len * builds_per_crate))
     }
 
     pub fn progress(&self, db: &Database) -> Fallible<u8> {
         let (results_len, crates_len) = self.raw_progress(db)?;
+        Ok(progress_percent(results_len, crates_len))
+    }
 
-        if crates_len != 0 {
-            Ok((results_len as f32 * 100.0 / crates_len as f32).ceil() as u8)
-        } else {
-            Ok(0)
+    /// When the running experiment is expected to finish, assuming the remaining jobs take as
+    /// long as the completed ones on average.
+    pub fn estimated_end(
+        &self,
+        completed_jobs: u32,
+        total_jobs: u32,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let started_at = match (self.status, self.started_at) {
+            (Status::Running, Some(started_at)) if completed_jobs > 0 => started_at,
+            _ => return None,
+        };
+        let job_duration = now.signed_duration_since(started_at) / completed_jobs as i32;
+        let remaining = total_jobs.saturating_sub(completed_jobs);
+        Some(now + job_duration * remaining as i32)
+    }
+
+    /// Toolchains the crates are built with when using the provided feature set.
+    pub fn toolchains_for(&self, features: &FeatureSet) -> [Toolchain; 2] {
+        [
+            self.toolchains[0].with_features(features),
+            self.toolchains[1].with_features(features),
+        ]
+    }
+
+    /// All the toolchains the crates are built with, combining each toolchain of the experiment
+    /// with each of its feature sets. Results are recorded for each one of them.
+    pub fn build_toolchains(&self) -> Vec<Toolchain> {
+        self.feature_sets
+            .iter()
+            .flat_map(|features| self.toolchains_for(features).to_vec())
+            .collect()
+    }
+
+    /// Delete the results of the crates on the provided toolchains, so they're tested again.
+    /// If `reopen` is true and the experiment already finished running, it's handed back to the
+    /// agents. Returns how many results were deleted.
+    pub fn invalidate_results(
+        &mut self,
+        db: &Database,
+        crates: &[Crate],
+        toolchains: &[Toolchain],
+        reopen: bool,
+    ) -> Fallible<usize> {
+        let (removed, status) = db.transaction(|t| {
+            // The status is loaded again to avoid racing with the reports generator
+            let status: Status = t
+                .get_row(
+                    "SELECT status FROM experiments WHERE name = ?1;",
+                    &[&self.name],
+                    |r| r.get::<_, String>("status"),
+                )?
+                .ok_or_else(|| err_msg("the experiment doesn't exist anymore"))?
+                .parse()?;
+            if status == Status::GeneratingReport {
+                bail!(
+                    "can't invalidate results while the report of {} is being generated",
+                    self.name
+                );
+            }
+
+            let mut removed = 0;
+            for krate in crates {
+                let krate = serde_json::to_string(krate)?;
+                for tc in toolchains {
+                    removed += t.execute(
+                        "DELETE FROM results \
+                         WHERE experiment = ?1 AND crate = ?2 AND toolchain = ?3;",
+                        &[&self.name, &krate, &tc.to_string()],
+                    )?;
+                }
+                // The crate is pending again
+                t.execute(
+                    "DELETE FROM crate_assignments WHERE experiment = ?1 AND crate = ?2;",
+                    &[&self.name, &krate],
+                )?;
+            }
+
+            let finished = match status {
+                Status::NeedsReport | Status::ReportFailed | Status::Completed => true,
+                Status::Queued | Status::Running | Status::GeneratingReport => false,
+            };
+            if !reopen || !finished || removed == 0 {
+                return Ok((removed, status));
+            }
+
+            // The agent that ran the experiment picks it up again
+            let status = match self.assigned_to {
+                Some(Assignee::Agent(_)) | Some(Assignee::CLI) => Status::Running,
+                Some(Assignee::Any) | None => Status::Queued,
+            };
+            t.execute(
+                "UPDATE experiments SET status = ?1, completed_at = NULL WHERE name = ?2;",
+                &[&status.to_str(), &self.name.as_str()],
+            )?;
+            Ok((removed, status))
+        })?;
+
+        if status != self.status {
+            self.completed_at = None;
         }
+        self.status = status;
+        Ok(removed)
     }
 
+    /// Remove the crates with a result for each toolchain, as they don't need to be tested again
+    /// by the agent resuming the experiment.
     pub fn remove_completed_crates(&mut self, db: &Database) -> Fallible<()> {
-        // FIXME: optimize this
+        let builds_per_crate = self.build_toolchains().len() as i64;
+        let completed = db
+            .query(
+                "SELECT crate FROM results WHERE experiment = ?1 \
+                 GROUP BY crate HAVING COUNT(*) >= ?2;",
+                &[&self.name as &ToSql, &builds_per_crate],
+                |r| r.get::<_, String>("crate"),
+            )?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
         let mut new_crates = Vec::with_capacity(self.crates.len());
         for krate in self.crates.drain(..) {
-            let results_len: u32 = db
-                .get_row(
-                    "SELECT COUNT(*) AS count FROM results \
-                     WHERE experiment = ?1 AND crate = ?2;",
-                    &[&self.name.as_str(), &serde_json::to_string(&krate)?],
-                    |r| r.get("count"),
-                )?
-                .unwrap();
-
-            if results_len < 2 {
+            if !completed.contains(&serde_json::to_string(&krate)?) {
                 new_crates.push(krate);
             }
         }
@@ -312,6 +751,134 @@ impl Experiment {
         self.crates = new_crates;
         Ok(())
     }
+
+    /// Reorder the crates so the ones that took the longest to build in previous experiments are
+    /// interleaved with the quickest ones, spreading the load of the agent over the whole run.
+    pub fn interleave_by_duration(&mut self, db: &Database) -> Fallible<()> {
+        let durations = db
+            .query(
+                "SELECT results.crate, CAST(AVG(results.duration_ms) AS BIGINT) AS duration \
+                 FROM results \
+                 INNER JOIN experiment_crates ON experiment_crates.crate = results.crate \
+                 WHERE experiment_crates.experiment = ?1 AND results.duration_ms IS NOT NULL \
+                 GROUP BY results.crate;",
+                &[&self.name],
+                |r| (r.get::<_, String>("crate"), r.get::<_, i64>("duration")),
+            )?
+            .into_iter()
+            .map(|(krate, duration)| Ok((serde_json::from_str(&krate)?, duration as u64)))
+            .collect::<Fallible<HashMap<Crate, u64>>>()?;
+
+        let crates = ::std::mem::replace(&mut self.crates, Vec::new());
+        self.crates = interleave_by_weight(crates, &durations);
+        Ok(())
+    }
+
+    /// Mark the crates left to test as being tested by the agent, replacing the assignments of
+    /// the agents that ran the experiment before.
+    pub fn assign_crates(&self, db: &Database, agent: &str) -> Fallible<()> {
+        let now = Utc::now();
+        db.transaction(|t| {
+            t.execute(
+                "DELETE FROM crate_assignments WHERE experiment = ?1 AND status = ?2;",
+                &[&self.name.as_str(), &CrateAssignment::InProgress.to_str()],
+            )?;
+            for krate in &self.crates {
+                t.execute(
+                    "INSERT INTO crate_assignments (experiment, crate, status, agent, updated_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5);",
+                    &[
+                        &self.name as &ToSql,
+                        &serde_json::to_string(krate)?,
+                        &CrateAssignment::InProgress.to_str(),
+                        &agent,
+                        &now,
+                    ],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Mark the crates with a result for each toolchain as completed by the agent.
+    pub fn complete_crates<'a, I>(&self, db: &Database, agent: &str, crates: I) -> Fallible<()>
+    where
+        I: IntoIterator<Item = &'a Crate>,
+    {
+        let builds_per_crate = self.build_toolchains().len() as i64;
+        let now = Utc::now();
+        db.transaction(|t| {
+            for krate in crates {
+                let krate = serde_json::to_string(krate)?;
+                let results: i64 = t
+                    .get_row(
+                        "SELECT COUNT(*) AS count FROM results \
+                         WHERE experiment = ?1 AND crate = ?2;",
+                        &[&self.name, &krate],
+                        |r| r.get("count"),
+                    )?
+                    .unwrap_or(0);
+                if results < builds_per_crate {
+                    continue;
+                }
+
+                t.execute(
+                    "INSERT INTO crate_assignments (experiment, crate, status, agent, updated_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5);",
+                    &[
+                        &self.name as &ToSql,
+                        &krate,
+                        &CrateAssignment::Completed.to_str(),
+                        &agent,
+                        &now,
+                    ],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Number of crates with the provided assignment. Pending crates are not counted, as they
+    /// might have no assignment at all.
+    pub fn count_assigned_crates(
+        &self,
+        db: &Database,
+        assignment: CrateAssignment,
+    ) -> Fallible<u32> {
+        Ok(db
+            .get_row(
+                "SELECT COUNT(*) AS count FROM crate_assignments \
+                 WHERE experiment = ?1 AND status = ?2;",
+                &[&self.name.as_str(), &assignment.to_str()],
+                |r| r.get("count"),
+            )?
+            .unwrap_or(0))
+    }
+}
+
+/// Sort the crates from the heaviest to the lightest, and then alternate between the two ends of
+/// the list. Crates without a known weight are considered average, and crates with the same
+/// weight keep their original order.
+fn interleave_by_weight(mut crates: Vec<Crate>, weights: &HashMap<Crate, u64>) -> Vec<Crate> {
+    if weights.is_empty() {
+        return crates;
+    }
+    let average = weights.values().sum::<u64>() / weights.len() as u64;
+    crates.sort_by_key(|krate| ::std::cmp::Reverse(weights.get(krate).cloned().unwrap_or(average)));
+
+    let mut result = Vec::with_capacity(crates.len());
+    let mut crates = crates.into_iter();
+    loop {
+        match (crates.next(), crates.next_back()) {
+            (Some(heavy), Some(light)) => {
+                result.push(heavy);
+                result.push(light);
+            }
+            (Some(last), None) => result.push(last),
+            _ => break,
+        }
+    }
+    result
 }
 
 struct ExperimentDBRecord {
@@ -331,6 +898,17 @@ struct ExperimentDBRecord {
     assigned_to: Option<String>,
     report_url: Option<String>,
     ignore_blacklist: bool,
+    fail_fast: bool,
+    feature_sets: String,
+    requested_by: Option<String>,
+    live_report: bool,
+    cargo_patches: String,
+    pre_analyze: bool,
+    private: bool,
+    private_readers: String,
+    test_runner: Option<String>,
+    measure_artifacts: bool,
+    silent: bool,
 }
 
 impl ExperimentDBRecord {
@@ -352,6 +930,17 @@ impl ExperimentDBRecord {
             assigned_to: row.get("assigned_to"),
             report_url: row.get("report_url"),
             ignore_blacklist: row.get("ignore_blacklist"),
+            fail_fast: row.get("fail_fast"),
+            feature_sets: row.get("feature_sets"),
+            requested_by: row.get("requested_by"),
+            live_report: row.get("live_report"),
+            cargo_patches: row.get("cargo_patches"),
+            pre_analyze: row.get("pre_analyze"),
+            private: row.get("private"),
+            private_readers: row.get("private_readers"),
+            test_runner: row.get("test_runner"),
+            measure_artifacts: row.get("measure_artifacts"),
+            silent: row.get("silent"),
         }
     }
 
@@ -399,18 +988,42 @@ impl ExperimentDBRecord {
             status: self.status.parse()?,
             report_url: self.report_url,
             ignore_blacklist: self.ignore_blacklist,
+            fail_fast: self.fail_fast,
+            feature_sets: serde_json::from_str(&self.feature_sets)?,
+            requested_by: self.requested_by,
+            live_report: self.live_report,
+            cargo_patches: serde_json::from_str(&self.cargo_patches)?,
+            pre_analyze: self.pre_analyze,
+            private: self.private,
+            private_readers: serde_json::from_str(&self.private_readers)?,
+            test_runner: if let Some(test_runner) = self.test_runner {
+                Some(test_runner.parse()?)
+            } else {
+                None
+            },
+            measure_artifacts: self.measure_artifacts,
+            silent: self.silent,
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Assignee, AssigneeParseError, Experiment, Status};
-    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use super::{
+        interleave_by_weight, progress_percent, Assignee, AssigneeParseError, CargoPatch,
+        CargoPatchParseError, Experiment, FeatureSet, FeatureSetParseError, GitHubIssue,
+        InvalidStatusOverride, Status,
+    };
+    use crate::actions::{Action, ActionsCtx, CreateExperiment, EditExperiment};
     use crate::config::Config;
-    use crate::db::Database;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::{Database, QueryUtils};
+    use crate::results::{DatabaseDB, ProgressData, TaskResult, TestResult};
     use crate::server::agents::Agents;
     use crate::server::tokens::Tokens;
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use chrono::{Duration, TimeZone, Utc};
+    use std::collections::HashMap;
     use std::str::FromStr;
 
     #[test]
@@ -426,6 +1039,8 @@ mod tests {
 
         assert_eq!(Assignee::CLI.to_string().as_str(), "cli");
         assert_eq!(Assignee::from_str("cli").unwrap(), Assignee::CLI);
+        assert_eq!(Assignee::Any.to_string().as_str(), "any");
+        assert_eq!(Assignee::from_str("any").unwrap(), Assignee::Any);
 
         for empty in &["", "agent:"] {
             let err = Assignee::from_str(empty).unwrap_err();
@@ -435,12 +1050,65 @@ mod tests {
         let err = Assignee::from_str("foo").unwrap_err();
         assert_eq!(err, AssigneeParseError::InvalidKind("foo".into()));
 
-        for invalid in &["cli:", "cli:foo"] {
+        for invalid in &["cli:", "cli:foo", "any:foo"] {
             let err = Assignee::from_str(invalid).unwrap_err();
             assert_eq!(err, AssigneeParseError::UnexpectedPayload);
         }
     }
 
+    #[test]
+    fn test_feature_set_parsing() {
+        for (repr, features) in &[
+            ("default", FeatureSet::Default),
+            ("no-default-features", FeatureSet::NoDefault),
+            ("all-features", FeatureSet::All),
+            ("foo", FeatureSet::Features(vec!["foo".into()])),
+            (
+                "foo,bar_baz",
+                FeatureSet::Features(vec!["foo".into(), "bar_baz".into()]),
+            ),
+        ] {
+            assert_eq!(&FeatureSet::from_str(repr).unwrap(), features);
+            assert_eq!(features.to_string().as_str(), *repr);
+        }
+
+        assert_eq!(
+            FeatureSet::from_str("").unwrap_err(),
+            FeatureSetParseError::Empty
+        );
+        for invalid in &["foo,", "foo bar", "foo+bar", "serde/std"] {
+            assert!(FeatureSet::from_str(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_cargo_patch_parsing() {
+        let patch = CargoPatch::from_str("tokio=https://github.com/tokio-rs/tokio").unwrap();
+        assert_eq!(
+            patch,
+            CargoPatch {
+                name: "tokio".into(),
+                git: "https://github.com/tokio-rs/tokio".into(),
+                rev: None,
+            }
+        );
+        assert_eq!(patch.to_string(), "tokio=https://github.com/tokio-rs/tokio");
+
+        let patch = CargoPatch::from_str("tokio=https://github.com/tokio-rs/tokio#f00").unwrap();
+        assert_eq!(patch.rev, Some("f00".into()));
+        assert_eq!(
+            patch.to_string(),
+            "tokio=https://github.com/tokio-rs/tokio#f00"
+        );
+
+        for invalid in &["", "tokio", "tokio=", "=https://example.com", "tokio=foo#"] {
+            assert_eq!(
+                CargoPatch::from_str(invalid).unwrap_err(),
+                CargoPatchParseError::InvalidFormat(invalid.to_string())
+            );
+        }
+    }
+
     #[test]
     fn test_assigning_experiment() {
         let db = Database::temp().unwrap();
@@ -491,4 +1159,301 @@ mod tests {
         // Test no other experiment is available for the other agents
         assert!(Experiment::next(&db, &agent3).unwrap().is_none());
     }
+
+    #[test]
+    fn test_claiming_pooled_experiment() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token1".into(), "agent-1".into());
+        let _ = Agents::new(db.clone(), &tokens).unwrap();
+        let agent1 = Assignee::Agent("agent-1".to_string());
+
+        CreateExperiment::dummy("pooled").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "pooled").unwrap().unwrap();
+        ex.set_assigned_to(&db, Some(&Assignee::Any)).unwrap();
+
+        // Experiments in the pool are still queued
+        let ex = Experiment::get(&db, "pooled").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Queued);
+        assert_eq!(ex.assigned_to.unwrap(), Assignee::Any);
+
+        // A concrete agent claims the experiment from the pool
+        let (new, ex) = Experiment::next(&db, &agent1).unwrap().unwrap();
+        assert!(new);
+        assert_eq!(ex.name.as_str(), "pooled");
+        assert_eq!(ex.status, Status::Running);
+        assert_eq!(ex.assigned_to.unwrap(), agent1);
+
+        // The experiment is not in the pool anymore
+        let ex = Experiment::get(&db, "pooled").unwrap().unwrap();
+        assert_eq!(ex.assigned_to.unwrap(), agent1);
+    }
+
+    #[test]
+    fn test_invalidate_results() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("test").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "test").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        let task = |krate, toolchain| TaskResult {
+            krate,
+            toolchain,
+            result: TestResult::TestPass,
+            log: String::new(),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        let mut results = Vec::new();
+        for name in &["lazy_static", "rand"] {
+            results.push(task(krate(name), MAIN_TOOLCHAIN.clone()));
+            results.push(task(krate(name), TEST_TOOLCHAIN.clone()));
+        }
+        DatabaseDB::new(&db)
+            .store(
+                &ex,
+                &ProgressData {
+                    results,
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        let count = || -> u32 {
+            db.get_row("SELECT COUNT(*) AS count FROM results;", &[], |r| {
+                r.get("count")
+            })
+            .unwrap()
+            .unwrap()
+        };
+
+        // Results can't be removed while the report is being generated
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
+        let toolchains = ex.build_toolchains();
+        assert!(ex
+            .invalidate_results(&db, &[krate("rand")], &toolchains, true)
+            .is_err());
+        assert_eq!(count(), 4);
+
+        // Only the results of the selected crates and toolchains are removed
+        ex.set_status(&db, Status::Completed).unwrap();
+        let removed = ex
+            .invalidate_results(&db, &[krate("rand")], &[TEST_TOOLCHAIN.clone()], false)
+            .unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(count(), 3);
+        assert_eq!(ex.status, Status::Completed);
+
+        // Reopening the experiment queues it again
+        let removed = ex
+            .invalidate_results(&db, &[krate("lazy_static")], &toolchains, true)
+            .unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(count(), 1);
+        assert_eq!(ex.status, Status::Queued);
+        assert!(ex.completed_at.is_none());
+        let ex = Experiment::get(&db, "test").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Queued);
+    }
+
+    #[test]
+    fn test_interleave_by_weight() {
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        let names = |crates: &[Crate]| {
+            crates
+                .iter()
+                .map(|krate| match krate {
+                    Crate::Registry(krate) => krate.name.clone(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The heavy crates are listed together in the original order
+        let crates = vec![
+            krate("heavy-1"),
+            krate("heavy-2"),
+            krate("heavy-3"),
+            krate("light-1"),
+            krate("light-2"),
+            krate("light-3"),
+            krate("unknown"),
+        ];
+        let mut durations = HashMap::new();
+        durations.insert(krate("heavy-1"), 600_000);
+        durations.insert(krate("heavy-2"), 500_000);
+        durations.insert(krate("heavy-3"), 400_000);
+        durations.insert(krate("light-1"), 1_000);
+        durations.insert(krate("light-2"), 2_000);
+        durations.insert(krate("light-3"), 3_000);
+
+        let order = interleave_by_weight(crates.clone(), &durations);
+        assert_eq!(
+            names(&order),
+            vec!["heavy-1", "light-1", "heavy-2", "light-2", "heavy-3", "light-3", "unknown"]
+        );
+        // No two heavy crates are handed out one after the other
+        for pair in order.windows(2) {
+            assert!(
+                durations.get(&pair[0]).cloned().unwrap_or(0) < 100_000
+                    || durations.get(&pair[1]).cloned().unwrap_or(0) < 100_000
+            );
+        }
+
+        // Without any history the order is left untouched
+        assert_eq!(
+            interleave_by_weight(crates.clone(), &HashMap::new()),
+            crates
+        );
+    }
+
+    #[test]
+    fn test_override_status() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("test").apply(&ctx).unwrap();
+
+        let agent = Assignee::Agent("agent-1".into());
+        let mut ex = Experiment::get(&db, "test").unwrap().unwrap();
+        ex.set_assigned_to(&db, Some(&agent)).unwrap();
+        ex.set_status(&db, Status::Running).unwrap();
+
+        // Putting a stuck experiment back in the queue is allowed
+        ex.override_status(&db, Status::Queued, "admin", false)
+            .unwrap();
+        let ex = Experiment::get(&db, "test").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Queued);
+        assert!(ex.assigned_to.is_none());
+
+        // Completed experiments can't be queued again without forcing it
+        let mut ex = ex;
+        ex.set_status(&db, Status::Completed).unwrap();
+        let err = ex
+            .override_status(&db, Status::Queued, "admin", false)
+            .unwrap_err();
+        let err = err.downcast::<InvalidStatusOverride>().unwrap();
+        assert_eq!((err.from, err.to), (Status::Completed, Status::Queued));
+        let ex = Experiment::get(&db, "test").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Completed);
+
+        let mut ex = ex;
+        ex.override_status(&db, Status::Queued, "admin", true)
+            .unwrap();
+        assert_eq!(ex.status, Status::Queued);
+
+        // Only the changes that were applied are recorded
+        let overrides: Vec<(String, String, String, bool)> = db
+            .query(
+                "SELECT old_status, new_status, changed_by, forced FROM status_overrides \
+                 WHERE experiment = 'test' ORDER BY changed_at;",
+                &[],
+                |r| {
+                    (
+                        r.get("old_status"),
+                        r.get("new_status"),
+                        r.get("changed_by"),
+                        r.get("forced"),
+                    )
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            overrides,
+            vec![
+                ("running".into(), "queued".into(), "admin".into(), false),
+                ("completed".into(), "queued".into(), "admin".into(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_estimated_end() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("test").apply(&ctx).unwrap();
+
+        let mut ex = Experiment::get(&db, "test").unwrap().unwrap();
+        let now = Utc.ymd(2019, 1, 1).and_hms(12, 0, 0);
+        assert_eq!(ex.estimated_end(10, 40, now), None);
+
+        // A job took 6 minutes on average, and 30 jobs are left
+        ex.status = Status::Running;
+        ex.started_at = Some(now - Duration::hours(1));
+        assert_eq!(ex.estimated_end(0, 40, now), None);
+        assert_eq!(
+            ex.estimated_end(10, 40, now),
+            Some(now + Duration::hours(3))
+        );
+        assert_eq!(ex.estimated_end(40, 40, now), Some(now));
+
+        assert_eq!(progress_percent(10, 40), 25);
+        assert_eq!(progress_percent(1, 3), 34);
+        assert_eq!(progress_percent(0, 0), 0);
+    }
+
+    #[test]
+    fn test_silent_notifications() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let mut create = CreateExperiment::dummy("test");
+        create.github_issue = Some(GitHubIssue {
+            api_url: "https://api.github.com/repos/example/example/issues/10".into(),
+            html_url: "https://github.com/example/example/issue/10".into(),
+            number: 10,
+        });
+        create.silent = true;
+        create.apply(&ctx).unwrap();
+
+        // No status change of a silent experiment is announced
+        let mut ex = Experiment::get(&db, "test").unwrap().unwrap();
+        for &status in &[
+            Status::Queued,
+            Status::Running,
+            Status::NeedsReport,
+            Status::GeneratingReport,
+            Status::ReportFailed,
+            Status::Completed,
+        ] {
+            ex.set_status(&db, status).unwrap();
+            let ex = Experiment::get(&db, "test").unwrap().unwrap();
+            assert!(ex.notification_issue().is_none());
+        }
+
+        // Promoting the experiment posts its next messages on the issue
+        let mut edit = EditExperiment::dummy("test");
+        edit.silent = Some(false);
+        edit.apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "test").unwrap().unwrap();
+        assert_eq!(ex.notification_issue().unwrap().number, 10);
+    }
 }
@@ -8,12 +8,13 @@
 #[cfg_attr(test, macro_use)]
 extern crate toml;
 
+#[macro_use]
+pub mod utils;
+#[macro_use]
+pub mod logs;
 pub mod actions;
 pub mod agent;
 mod assets;
-pub mod logs;
-#[macro_use]
-pub mod utils;
 pub mod config;
 pub mod crates;
 pub mod db;
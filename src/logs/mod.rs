@@ -1,11 +1,22 @@
 mod storage;
+#[macro_use]
+mod structured;
 
+use chrono::Utc;
 use log::{Log, Metadata, Record};
 use std::cell::RefCell;
+use std::io::Write;
 use std::sync::Once;
 use std::thread::LocalKey;
 
 pub use self::storage::LogStorage;
+#[doc(hidden)]
+pub use self::structured::with_fields;
+
+string_enum!(pub enum LogFormat {
+    Text => "text",
+    Json => "json",
+});
 
 static INIT_LOGS: Once = Once::new();
 
@@ -73,15 +84,28 @@ where
     result
 }
 
-pub fn init() {
+/// Setup the logging to stderr. Crater logs at the info level by default, which can be changed
+/// with the `RUST_LOG` environment variable (for example `RUST_LOG=crater=debug`). In quiet mode
+/// only the errors of crater are logged, regardless of `RUST_LOG`. The JSON format emits one object
+/// per line, with the fields attached by `log_fields!` kept separate from the message.
+pub fn init(quiet: bool, format: LogFormat) {
     INIT_LOGS.call_once(|| {
         // Initialize env_logger
         // This doesn't use from_default_env() because it doesn't allow to override filter_module()
         // with the RUST_LOG environment variable
         let mut env = env_logger::Builder::new();
-        env.filter_module("crater", log::LevelFilter::Info);
-        if let Ok(content) = std::env::var("RUST_LOG") {
-            env.parse(&content);
+        if quiet {
+            env.filter_module("crater", log::LevelFilter::Error);
+        } else {
+            env.filter_module("crater", log::LevelFilter::Info);
+            if let Ok(content) = std::env::var("RUST_LOG") {
+                env.parse(&content);
+            }
+        }
+        if format == LogFormat::Json {
+            env.format(|buf, record| {
+                writeln!(buf, "{}", structured::render_json(record, Utc::now()))
+            });
         }
 
         let multi = MultiLogger::new(vec![Box::new(env.build())], &SCOPED);
@@ -22,8 +22,12 @@ pub struct LogStorage {
     min_level: LevelFilter,
     max_size: usize,
     max_lines: usize,
+    redacted: Arc<Vec<String>>,
 }
 
+/// Replacement of the redacted values in the stored records.
+static REDACTED: &str = "[REDACTED]";
+
 impl LogStorage {
     pub(crate) fn new(min_level: LevelFilter, config: &Config) -> Self {
         LogStorage {
@@ -35,9 +39,18 @@ impl LogStorage {
             min_level,
             max_size: config.sandbox.build_log_max_size.to_bytes(),
             max_lines: config.sandbox.build_log_max_lines,
+            redacted: Arc::new(Vec::new()),
         }
     }
 
+    /// Scrub the occurrences of the values from the records stored from now on.
+    pub(crate) fn redact<I: IntoIterator<Item = String>>(mut self, values: I) -> Self {
+        let mut redacted = (*self.redacted).clone();
+        redacted.extend(values.into_iter().filter(|value| !value.is_empty()));
+        self.redacted = Arc::new(redacted);
+        self
+    }
+
     pub(crate) fn duplicate(&self) -> LogStorage {
         let inner = self.inner.lock().unwrap();
         LogStorage {
@@ -49,7 +62,37 @@ impl LogStorage {
             min_level: self.min_level,
             max_size: self.max_size,
             max_lines: self.max_lines,
+            redacted: self.redacted.clone(),
+        }
+    }
+
+    /// Render the records stored after the first `start` ones, stopping before the rendered
+    /// content exceeds `max_size` bytes. Returns the content and the index of the first record
+    /// that wasn't rendered, which can be used as the `start` of the next call.
+    pub(crate) fn read_since(&self, start: usize, max_size: usize) -> (String, usize) {
+        let inner = self.inner.lock().unwrap();
+        let mut content = String::new();
+        let mut next = start;
+
+        for record in inner.records.iter().skip(start) {
+            let line = format!("[{}] {}\n", record.level, record.message);
+            if content.len() + line.len() > max_size {
+                if content.is_empty() {
+                    // Always make progress, even if a single line is too big
+                    let mut end = max_size;
+                    while !line.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    content.push_str(&line[..end]);
+                    next += 1;
+                }
+                break;
+            }
+            content.push_str(&line);
+            next += 1;
         }
+
+        (content, next)
     }
 }
 
@@ -74,7 +117,12 @@ impl Log for LogStorage {
             inner.truncated = true;
             return;
         }
-        let message = record.args().to_string();
+        let mut message = record.args().to_string();
+        for value in self.redacted.iter() {
+            if message.contains(value.as_str()) {
+                message = message.replace(value.as_str(), REDACTED);
+            }
+        }
         if inner.size + message.len() >= self.max_size {
             inner.records.push(StoredRecord {
                 level: Level::Warn,
@@ -139,6 +187,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_redact() {
+        logs::init_test();
+        let config = Config::default();
+
+        let storage = LogStorage::new(LevelFilter::Info, &config)
+            .redact(vec!["hunter2".to_string(), String::new()]);
+        logs::capture(&storage, || {
+            info!("the password is hunter2");
+        });
+        // Duplicated storages keep redacting the values
+        let duplicate = storage.duplicate();
+        logs::capture(&duplicate, || {
+            info!("hunter2hunter2");
+        });
+
+        assert_eq!(
+            duplicate.to_string(),
+            "[INFO] the password is [REDACTED]\n[INFO] [REDACTED][REDACTED]\n"
+        );
+    }
+
+    #[test]
+    fn test_read_since() {
+        logs::init_test();
+        let config = Config::default();
+
+        let storage = LogStorage::new(LevelFilter::Info, &config);
+        logs::capture(&storage, || {
+            info!("first");
+            info!("second");
+            info!("third");
+        });
+
+        assert_eq!(
+            storage.read_since(0, 1024),
+            ("[INFO] first\n[INFO] second\n[INFO] third\n".to_string(), 3)
+        );
+        assert_eq!(
+            storage.read_since(1, 16),
+            ("[INFO] second\n".to_string(), 2)
+        );
+        assert_eq!(storage.read_since(3, 1024), (String::new(), 3));
+
+        // Lines bigger than the limit are truncated instead of stalling the reader
+        assert_eq!(storage.read_since(0, 4), ("[INF".to_string(), 1));
+    }
+
     #[test]
     fn test_too_much_content() {
         logs::init_test();
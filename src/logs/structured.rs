@@ -0,0 +1,168 @@
+//! Structured fields attached to the log records by the `log_fields!` macro, and rendering of the
+//! records as JSON objects.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::Record;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+
+struct Current {
+    message: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Current>> = RefCell::new(None);
+}
+
+/// Text appended to the message in the human-readable output.
+fn text_fields(fields: &[(&'static str, String)]) -> String {
+    let rendered = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    format!(" ({})", rendered.join(", "))
+}
+
+/// Call `f` with the message followed by the fields, keeping the plain message and the fields
+/// around for the JSON logger while `f` logs it. Use the `log_fields!` macro instead of calling
+/// this directly.
+#[doc(hidden)]
+pub fn with_fields<F: FnOnce(&str)>(message: &str, fields: Vec<(&'static str, String)>, f: F) {
+    let text = format!("{}{}", message, text_fields(&fields));
+    let previous = CURRENT.with(|current| {
+        current.borrow_mut().replace(Current {
+            message: message.to_string(),
+            fields,
+        })
+    });
+    f(&text);
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+}
+
+/// Render the record as a single line JSON object.
+pub(super) fn render_json(record: &Record, at: DateTime<Utc>) -> String {
+    let mut object = Map::new();
+    object.insert("level".into(), record.level().to_string().into());
+    object.insert(
+        "timestamp".into(),
+        at.to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+    );
+    object.insert("target".into(), record.target().into());
+
+    let text = record.args().to_string();
+    let (message, fields) = CURRENT.with(|current| match *current.borrow() {
+        // Records logged while the fields are set but not by the macro don't have them
+        Some(ref current) if text.starts_with(&current.message) => {
+            let fields = current
+                .fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), Value::from(value.as_str())))
+                .collect::<Map<_, _>>();
+            (current.message.clone(), fields)
+        }
+        _ => (text, Map::new()),
+    });
+    object.insert("message".into(), message.into());
+    object.insert("fields".into(), Value::Object(fields));
+
+    Value::Object(object).to_string()
+}
+
+/// Log a message with structured fields, for example
+/// `log_fields!(info, "received progress"; "experiment" => ex.name, "agent" => agent)`. The fields
+/// are appended to the message in the human-readable output, and emitted separately in the JSON
+/// output.
+macro_rules! log_fields {
+    ($level:ident, $message:expr; $($key:expr => $value:expr),+ $(,)*) => {
+        $crate::logs::with_fields(
+            $message,
+            vec![$(($key, $value.to_string())),+],
+            |text| $level!("{}", text),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_json, with_fields};
+    use chrono::{TimeZone, Utc};
+    use log::{Level, Record};
+    use serde_json::{json, Value};
+
+    fn render(level: Level, message: &str) -> Value {
+        let at = Utc.ymd(2019, 1, 1).and_hms_milli(12, 0, 0, 250);
+        let line = render_json(
+            &Record::builder()
+                .level(level)
+                .target("crater::server::routes::agent")
+                .args(format_args!("{}", message))
+                .build(),
+            at,
+        );
+        assert!(!line.contains('\n'));
+        ::serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn test_render_plain_record() {
+        assert_eq!(
+            render(Level::Warn, "connection to the server failed"),
+            json!({
+                "level": "WARN",
+                "timestamp": "2019-01-01T12:00:00.250Z",
+                "target": "crater::server::routes::agent",
+                "message": "connection to the server failed",
+                "fields": {},
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_fields() {
+        let mut logged = None;
+        with_fields(
+            "received progress",
+            vec![("experiment", "pr-1".into()), ("agent", "agent-1".into())],
+            |text| {
+                assert_eq!(text, "received progress (experiment=pr-1, agent=agent-1)");
+                logged = Some(render(Level::Info, text));
+            },
+        );
+        let logged = logged.unwrap();
+        assert_eq!(logged["level"], "INFO");
+        assert_eq!(logged["message"], "received progress");
+        assert_eq!(
+            logged["fields"],
+            json!({"experiment": "pr-1", "agent": "agent-1"})
+        );
+
+        // The fields don't leak into the following records
+        assert_eq!(
+            render(Level::Info, "received progress")["fields"],
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_render_nested_fields() {
+        with_fields(
+            "testing crate",
+            vec![("crate", "reg/lazy_static/1.0.0".into())],
+            |outer| {
+                with_fields(
+                    "retrying the request",
+                    vec![("attempt", "2".into())],
+                    |inner| {
+                        let logged = render(Level::Warn, inner);
+                        assert_eq!(logged["message"], "retrying the request");
+                        assert_eq!(logged["fields"], json!({"attempt": "2"}));
+                    },
+                );
+                let logged = render(Level::Info, outer);
+                assert_eq!(logged["message"], "testing crate");
+                assert_eq!(logged["fields"], json!({"crate": "reg/lazy_static/1.0.0"}));
+            },
+        );
+    }
+}
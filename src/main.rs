@@ -1,8 +1,9 @@
 use log::info;
 mod cli;
 
+use crate::cli::output::JsonError;
 use crater::utils;
-use failure::Fallible;
+use failure::err_msg;
 use std::panic;
 use std::process;
 use structopt::StructOpt;
@@ -11,29 +12,36 @@ fn main() {
     // Ignore errors loading `.env` file.
     let _ = dotenv::dotenv();
 
-    crater::logs::init();
-    let success = match panic::catch_unwind(main_) {
-        Ok(Ok(())) => true,
+    let args = cli::Cli::from_args();
+    crater::logs::init(args.quiet(), args.log_format());
+    let output = args.output();
+
+    let error = match panic::catch_unwind(panic::AssertUnwindSafe(|| args.run())) {
+        Ok(Ok(())) => None,
         Ok(Err(e)) => {
             utils::report_failure(&e);
-            false
+            Some(e)
         }
         Err(e) => {
             utils::report_panic(&*e);
-            false
+            Some(err_msg("crater panicked"))
         }
     };
     info!(
         "{}",
-        if success {
+        if error.is_none() {
             "command succeeded"
         } else {
             "command failed"
         }
     );
-    process::exit(if success { 0 } else { 1 });
-}
 
-fn main_() -> Fallible<()> {
-    cli::Crater::from_args().run()
+    let code = if error.is_none() { 0 } else { 1 };
+    if let Some(error) = error {
+        // Scripts parsing the output need the error in the same format
+        if output.is_json() {
+            JsonError::new(&error, code).print();
+        }
+    }
+    process::exit(code);
 }
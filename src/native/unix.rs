@@ -1,11 +1,16 @@
 use crate::prelude::*;
+use failure::Error;
 use nix::{
     sys::signal::{kill, Signal},
+    sys::statvfs::statvfs,
     unistd::{Gid, Pid, Uid},
 };
 use std::convert::AsRef;
+use std::ffi::OsString;
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::process::CommandExt;
 use std::path::Path;
+use std::process::Command;
 
 const EXECUTABLE_BITS: u32 = 0o5;
 
@@ -53,9 +58,22 @@ pub(crate) fn make_executable<P: AsRef<Path>>(path: P) -> Fallible<()> {
     Ok(())
 }
 
+/// Space available to unprivileged users on the filesystem containing `path`, in bytes.
+pub(crate) fn free_disk_space(path: &Path) -> Fallible<u64> {
+    let stat = statvfs(path)?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+/// Replace the current process with the executable at `path`, only returning if that fails.
+pub(crate) fn exec(path: &Path, args: &[OsString]) -> Error {
+    Command::new(path).args(args).exec().into()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{current_group, current_user, is_executable, kill_process, make_executable};
+    use super::{
+        current_group, current_user, free_disk_space, is_executable, kill_process, make_executable,
+    };
     use nix::unistd::{Gid, Uid};
     use std::fs::File;
     use std::os::unix::process::ExitStatusExt;
@@ -82,6 +100,13 @@ mod tests {
         assert_eq!(current_group(), u32::from(Gid::effective()));
     }
 
+    #[test]
+    fn test_free_disk_space() {
+        let dir = tempdir().unwrap();
+        assert!(free_disk_space(dir.path()).unwrap() > 0);
+        assert!(free_disk_space(&dir.path().join("missing")).is_err());
+    }
+
     #[test]
     fn test_executables() {
         let dir = tempdir().unwrap();
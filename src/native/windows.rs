@@ -1,4 +1,6 @@
 use crate::prelude::*;
+use failure::Error;
+use std::ffi::OsString;
 use std::path::Path;
 use winapi::um::handleapi::CloseHandle;
 use winapi::um::processthreadsapi::{OpenProcess, TerminateProcess};
@@ -29,3 +31,11 @@ pub(crate) fn is_executable<P: AsRef<Path>>(_path: P) -> Fallible<bool> {
 pub(crate) fn make_executable<P: AsRef<Path>>(_path: P) -> Fallible<()> {
     unimplemented!();
 }
+
+pub(crate) fn free_disk_space(_path: &Path) -> Fallible<u64> {
+    bail!("checking the free disk space is not supported on Windows");
+}
+
+pub(crate) fn exec(_path: &Path, _args: &[OsString]) -> Error {
+    err_msg("replacing the current process is not supported on Windows")
+}
@@ -5,6 +5,7 @@ use crate::report::{compare, ReportWriter};
 use crate::results::ReadResults;
 use flate2::{write::GzEncoder, Compression};
 use std::collections::HashMap;
+use std::io::Write;
 use tar::{Builder as TarBuilder, Header as TarHeader};
 
 #[derive(Serialize)]
@@ -28,38 +29,41 @@ pub fn write_logs_archives<DB: ReadResults, W: ReportWriter>(
             continue;
         }
 
-        let res1 = db.load_test_result(ex, &ex.toolchains[0], krate)?;
-        let res2 = db.load_test_result(ex, &ex.toolchains[1], krate)?;
-        let comparison = compare(config, krate, res1, res2);
-
-        for tc in &ex.toolchains {
-            let log = db
-                .load_log(ex, tc, krate)
-                .and_then(|c| c.ok_or_else(|| err_msg("missing logs")))
-                .with_context(|_| format!("failed to read log of {} on {}", krate, tc));
-
-            let log_bytes: &[u8] = match log {
-                Ok(ref l) => l,
-                Err(e) => {
-                    crate::utils::report_failure(&e);
-                    continue;
-                }
-            };
-
-            let path = format!("{}/{}/{}.txt", comparison, krate.id(), tc);
-
-            let mut header = TarHeader::new_gnu();
-            header.set_size(log_bytes.len() as u64);
-            header.set_mode(0o644);
-            header.set_cksum();
-
-            all.append_data(&mut header, &path, log_bytes)?;
-            by_comparison
-                .entry(comparison)
-                .or_insert_with(|| {
-                    TarBuilder::new(GzEncoder::new(Vec::new(), Compression::default()))
-                })
-                .append_data(&mut header, &path, log_bytes)?;
+        for features in &ex.feature_sets {
+            let toolchains = ex.toolchains_for(features);
+            let res1 = db.load_test_result(ex, &toolchains[0], krate)?;
+            let res2 = db.load_test_result(ex, &toolchains[1], krate)?;
+            let comparison = compare(config, krate, res1, res2);
+
+            for tc in &toolchains {
+                let log = db
+                    .load_log(ex, tc, krate)
+                    .and_then(|c| c.ok_or_else(|| err_msg("missing logs")))
+                    .with_context(|_| format!("failed to read log of {} on {}", krate, tc));
+
+                let log_bytes: &[u8] = match log {
+                    Ok(ref l) => l,
+                    Err(e) => {
+                        crate::utils::report_failure(&e);
+                        continue;
+                    }
+                };
+
+                let path = format!("{}/{}/{}.txt", comparison, krate.id(), tc);
+
+                let mut header = TarHeader::new_gnu();
+                header.set_size(log_bytes.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+
+                all.append_data(&mut header, &path, log_bytes)?;
+                by_comparison
+                    .entry(comparison)
+                    .or_insert_with(|| {
+                        TarBuilder::new(GzEncoder::new(Vec::new(), Compression::default()))
+                    })
+                    .append_data(&mut header, &path, log_bytes)?;
+            }
         }
     }
 
@@ -92,9 +96,32 @@ pub fn write_logs_archives<DB: ReadResults, W: ReportWriter>(
     Ok(archives)
 }
 
+/// Write a gzipped tarball of every log stored for the experiment into `dest`, laid out as
+/// `{crate}/{toolchain}.txt`. Builds without a stored log are left out of the archive.
+pub fn write_all_logs<DB: ReadResults, W: Write>(db: &DB, ex: &Experiment, dest: W) -> Fallible<W> {
+    let mut archive = TarBuilder::new(GzEncoder::new(dest, Compression::default()));
+    let toolchains = ex.build_toolchains();
+
+    for krate in &ex.crates {
+        for tc in &toolchains {
+            if let Some(log) = db.load_log(ex, tc, krate)? {
+                let mut header = TarHeader::new_gnu();
+                header.set_size(log.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+
+                let path = format!("{}/{}.txt", krate.id(), tc);
+                archive.append_data(&mut header, &path, log.as_slice())?;
+            }
+        }
+    }
+
+    Ok(archive.into_inner()?.finish()?)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::write_logs_archives;
+    use super::{write_all_logs, write_logs_archives};
     use crate::actions::{Action, ActionsCtx, CreateExperiment};
     use crate::config::Config;
     use crate::db::Database;
@@ -226,4 +253,60 @@ mod tests {
             format!("test-pass/{}/{}.txt", crate2.id(), ex.toolchains[1]) => "tc2 crate2",
         });
     }
+
+    #[test]
+    fn test_all_logs_archive() {
+        crate::logs::init_test();
+
+        let config = Config::default();
+        let db = Database::temp().unwrap();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let crate1 = ex.crates[0].clone();
+        let crate2 = ex.crates[1].clone();
+
+        // The second crate only has a log for the first toolchain
+        let logs = [
+            (&crate1, &ex.toolchains[0], "tc1 crate1"),
+            (&crate1, &ex.toolchains[1], "tc2 crate1"),
+            (&crate2, &ex.toolchains[0], "tc1 crate2"),
+        ];
+        let results = DatabaseDB::new(&db);
+        for (krate, tc, line) in &logs {
+            results
+                .record_result(&ex, tc, krate, None, &config, || {
+                    info!("{}", line);
+                    Ok(TestResult::TestPass)
+                })
+                .unwrap();
+        }
+
+        let content = write_all_logs(&results, &ex, Vec::new()).unwrap();
+        let mut archive = Archive::new(GzDecoder::new(content.as_slice()));
+
+        let mut entries = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            assert_eq!(entry.header().mode().unwrap(), 0o644);
+
+            let mut log = String::new();
+            entry.read_to_string(&mut log).unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            entries.push((path, log));
+        }
+
+        assert_eq!(entries.len(), logs.len());
+        for (krate, tc, line) in &logs {
+            let path = format!("{}/{}.txt", krate.id(), tc);
+            let (_, log) = entries
+                .iter()
+                .find(|(p, _)| *p == path)
+                .unwrap_or_else(|| panic!("missing log in the archive: {}", path));
+            assert!(log.contains(line));
+        }
+    }
 }
@@ -0,0 +1,606 @@
+use crate::assets;
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::experiments::{Experiment, FeatureSet};
+use crate::prelude::*;
+use crate::report::{compare_known, crate_to_name, crate_to_url, Comparison, ReportWriter};
+use crate::results::{ChangedCrate, EnvironmentFingerprint, ReadResults};
+use mime;
+use minifier;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// How the comparison of a crate changed between two experiments.
+string_enum!(pub enum DiffCategory {
+    Fixed => "fixed",
+    StillRegressed => "still-regressed",
+    NewlyRegressed => "newly-regressed",
+    ChangedCategory => "changed-category",
+    Unchanged => "unchanged",
+});
+
+/// Order the categories are shown in the comparison report.
+const SHOWN_CATEGORIES: &[DiffCategory] = &[
+    DiffCategory::NewlyRegressed,
+    DiffCategory::StillRegressed,
+    DiffCategory::Fixed,
+    DiffCategory::ChangedCategory,
+];
+
+/// Results of each crate of an experiment, identified by the crate and the feature set.
+pub type Comparisons = HashMap<(Crate, FeatureSet), Comparison>;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    #[serde(skip)]
+    pub krate: Crate,
+    pub name: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "FeatureSet::is_default")]
+    pub features: FeatureSet,
+    pub before: Comparison,
+    pub after: Comparison,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ExperimentsDiff {
+    pub categories: HashMap<DiffCategory, Vec<DiffEntry>>,
+    /// Crates tested only by the first experiment.
+    pub only_first: Vec<DiffEntry>,
+    /// Crates tested only by the second experiment.
+    pub only_second: Vec<DiffEntry>,
+}
+
+impl ExperimentsDiff {
+    pub fn count(&self, category: DiffCategory) -> usize {
+        self.categories.get(&category).map(|c| c.len()).unwrap_or(0)
+    }
+}
+
+pub fn categorize(before: Comparison, after: Comparison) -> DiffCategory {
+    match (before, after) {
+        (Comparison::Regressed, Comparison::Regressed) => DiffCategory::StillRegressed,
+        (_, Comparison::Regressed) => DiffCategory::NewlyRegressed,
+        // Missing results don't mean the regression was fixed
+        (Comparison::Regressed, Comparison::Unknown)
+        | (Comparison::Regressed, Comparison::Skipped)
+        | (Comparison::Regressed, Comparison::Error) => DiffCategory::ChangedCategory,
+        (Comparison::Regressed, _) => DiffCategory::Fixed,
+        (before, after) if before != after => DiffCategory::ChangedCategory,
+        _ => DiffCategory::Unchanged,
+    }
+}
+
+fn entry(
+    krate: &Crate,
+    features: &FeatureSet,
+    before: Comparison,
+    after: Comparison,
+) -> Fallible<DiffEntry> {
+    // Crates are matched without the commit of GitHub repositories, so it's not shown either
+    let no_shas = HashMap::new();
+    Ok(DiffEntry {
+        krate: krate.clone(),
+        name: crate_to_name(krate, &no_shas)?,
+        url: crate_to_url(krate, &no_shas)?,
+        features: features.clone(),
+        before,
+        after,
+    })
+}
+
+fn sort_entries(entries: &mut Vec<DiffEntry>) {
+    entries.sort_by(|a, b| {
+        (&a.krate, a.features.to_string()).cmp(&(&b.krate, b.features.to_string()))
+    });
+}
+
+/// Join the results of two experiments on the crate identity, and categorize how each crate
+/// changed between the first and the second experiment.
+pub fn diff(first: &Comparisons, second: &Comparisons) -> Fallible<ExperimentsDiff> {
+    let mut result = ExperimentsDiff::default();
+
+    for (key, &before) in first {
+        let (ref krate, ref features) = *key;
+        if let Some(&after) = second.get(key) {
+            result
+                .categories
+                .entry(categorize(before, after))
+                .or_insert_with(Vec::new)
+                .push(entry(krate, features, before, after)?);
+        } else {
+            result
+                .only_first
+                .push(entry(krate, features, before, before)?);
+        }
+    }
+    for (key, &after) in second {
+        if !first.contains_key(key) {
+            let (ref krate, ref features) = *key;
+            result
+                .only_second
+                .push(entry(krate, features, after, after)?);
+        }
+    }
+
+    // Sort everything to have a stable output
+    result.categories.values_mut().for_each(sort_entries);
+    sort_entries(&mut result.only_first);
+    sort_entries(&mut result.only_second);
+
+    Ok(result)
+}
+
+/// Categorize the crates classified differently by two experiments, as returned by the compare
+/// API. The unchanged crates and the ones tested by only one experiment are not included.
+pub fn diff_changed(changed: &[ChangedCrate]) -> Fallible<ExperimentsDiff> {
+    let mut result = ExperimentsDiff::default();
+    for crate_diff in changed {
+        result
+            .categories
+            .entry(categorize(crate_diff.first, crate_diff.second))
+            .or_insert_with(Vec::new)
+            .push(entry(
+                &crate_diff.krate,
+                &crate_diff.features,
+                crate_diff.first,
+                crate_diff.second,
+            )?);
+    }
+    result.categories.values_mut().for_each(sort_entries);
+    Ok(result)
+}
+
+pub fn load_comparisons<DB: ReadResults>(
+    db: &DB,
+    config: &Config,
+    ex: &Experiment,
+) -> Fallible<Comparisons> {
+    let mut comparisons = HashMap::new();
+    for krate in &ex.crates {
+        for features in &ex.feature_sets {
+            let toolchains = ex.toolchains_for(features);
+            let comparison = compare_known(
+                db,
+                config,
+                ex,
+                &toolchains,
+                krate,
+                db.load_test_result(ex, &toolchains[0], krate)?,
+                db.load_test_result(ex, &toolchains[1], krate)?,
+            )?;
+            comparisons.insert((krate.clone(), features.clone()), comparison);
+        }
+    }
+    Ok(comparisons)
+}
+
+/// Whether the two experiments ran in different environments, which could explain some of the
+/// changes between them. Experiments without a recorded environment can't be compared.
+fn environments_differ(
+    first: &[EnvironmentFingerprint],
+    second: &[EnvironmentFingerprint],
+) -> bool {
+    if first.is_empty() || second.is_empty() {
+        return false;
+    }
+    first.iter().any(|env| !second.contains(env)) || second.iter().any(|env| !first.contains(env))
+}
+
+/// Experiment shown in the comparison report.
+#[derive(Serialize)]
+pub struct DiffExperiment<'a> {
+    pub name: &'a str,
+    pub report_url: Option<&'a str>,
+}
+
+impl<'a> From<&'a Experiment> for DiffExperiment<'a> {
+    fn from(ex: &'a Experiment) -> Self {
+        DiffExperiment {
+            name: &ex.name,
+            report_url: ex.report_url.as_ref().map(String::as_str),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DiffContext<'a> {
+    first: DiffExperiment<'a>,
+    second: DiffExperiment<'a>,
+    categories: Vec<(DiffCategory, &'a [DiffEntry])>,
+    only_first: &'a [DiffEntry],
+    only_second: &'a [DiffEntry],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unchanged: Option<usize>,
+    /// Environments of the two experiments, only set if they differ.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environments: Option<(Vec<EnvironmentFingerprint>, Vec<EnvironmentFingerprint>)>,
+    /// Stylesheet and script included in the page instead of linked, if it's not part of a
+    /// published report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_css: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_js: Option<String>,
+}
+
+impl<'a> DiffContext<'a> {
+    fn new(
+        first: DiffExperiment<'a>,
+        second: DiffExperiment<'a>,
+        diff: &'a ExperimentsDiff,
+    ) -> Self {
+        DiffContext {
+            first,
+            second,
+            categories: SHOWN_CATEGORIES
+                .iter()
+                .filter_map(|cat| diff.categories.get(cat).map(|e| (*cat, e.as_slice())))
+                .collect(),
+            only_first: &diff.only_first,
+            only_second: &diff.only_second,
+            unchanged: None,
+            environments: None,
+            inline_css: None,
+            inline_js: None,
+        }
+    }
+}
+
+/// Generate the report comparing the results of two experiments.
+pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
+    db: &DB,
+    first: &Experiment,
+    second: &Experiment,
+    dest: &W,
+    config: &Config,
+) -> Fallible<ExperimentsDiff> {
+    let diff = diff(
+        &load_comparisons(db, config, first)?,
+        &load_comparisons(db, config, second)?,
+    )?;
+
+    let first_environments = db.load_environments(first)?;
+    let second_environments = db.load_environments(second)?;
+    let environments = if environments_differ(&first_environments, &second_environments) {
+        warn!(
+            "{} and {} ran in different environments",
+            first.name, second.name
+        );
+        Some((first_environments, second_environments))
+    } else {
+        None
+    };
+
+    let context = DiffContext {
+        unchanged: Some(diff.count(DiffCategory::Unchanged)),
+        environments,
+        ..DiffContext::new(first.into(), second.into(), &diff)
+    };
+
+    info!("writing the comparison to {}", dest);
+    let html = minifier::html::minify(&assets::render_template("report/diff.html", &context)?);
+    dest.write_string("index.html", html.into(), &mime::TEXT_HTML)?;
+    let markdown = assets::render_template("report/diff.md", &context)?;
+    dest.write_string("index.md", markdown.into(), &mime::TEXT_PLAIN_UTF_8)?;
+
+    for name in &["report.css", "report.js"] {
+        let asset = assets::load(name)?;
+        dest.write_bytes(name, asset.content()?.into_owned(), asset.mime())?;
+    }
+
+    Ok(diff)
+}
+
+/// Render the comparison report as a single HTML page, with its stylesheet and script included,
+/// for example to save a comparison done outside of the server.
+pub fn render_standalone(
+    first: DiffExperiment,
+    second: DiffExperiment,
+    diff: &ExperimentsDiff,
+) -> Fallible<String> {
+    let asset = |name| -> Fallible<String> {
+        Ok(String::from_utf8(
+            assets::load(name)?.content()?.into_owned(),
+        )?)
+    };
+    let context = DiffContext {
+        inline_css: Some(asset("report.css")?),
+        inline_js: Some(asset("report.js")?),
+        ..DiffContext::new(first, second, diff)
+    };
+    assets::render_template("report/diff.html", &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        categorize, diff, diff_changed, environments_differ, gen, render_standalone, Comparisons,
+        DiffCategory, DiffExperiment,
+    };
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::{Experiment, FeatureSet};
+    use crate::report::{Comparison, DummyWriter};
+    use crate::results::{
+        ChangedCrate, DatabaseDB, EnvironmentFingerprint, TestResult, WriteResults,
+    };
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use mime;
+
+    fn krate(name: &str) -> Crate {
+        Crate::Registry(RegistryCrate {
+            name: name.into(),
+            version: "1.0.0".into(),
+        })
+    }
+
+    fn env(docker_image: &str) -> EnvironmentFingerprint {
+        EnvironmentFingerprint {
+            docker_image: Some(docker_image.into()),
+            kernel: None,
+            toolchains: Vec::new(),
+        }
+    }
+
+    fn comparisons(list: &[(&str, Comparison)]) -> Comparisons {
+        list.iter()
+            .map(|&(name, comp)| ((krate(name), FeatureSet::Default), comp))
+            .collect()
+    }
+
+    #[test]
+    fn test_categorize() {
+        use crate::report::Comparison::*;
+
+        assert_eq!(
+            categorize(Regressed, Regressed),
+            DiffCategory::StillRegressed
+        );
+        assert_eq!(categorize(Regressed, SameTestPass), DiffCategory::Fixed);
+        assert_eq!(categorize(Regressed, SameBuildFail), DiffCategory::Fixed);
+        assert_eq!(
+            categorize(Regressed, SpuriousRegressed),
+            DiffCategory::Fixed
+        );
+        assert_eq!(
+            categorize(SameTestPass, Regressed),
+            DiffCategory::NewlyRegressed
+        );
+        assert_eq!(categorize(Unknown, Regressed), DiffCategory::NewlyRegressed);
+        assert_eq!(
+            categorize(SameTestPass, SameBuildFail),
+            DiffCategory::ChangedCategory
+        );
+        assert_eq!(
+            categorize(Fixed, SameTestPass),
+            DiffCategory::ChangedCategory
+        );
+        assert_eq!(
+            categorize(Regressed, Unknown),
+            DiffCategory::ChangedCategory
+        );
+        assert_eq!(categorize(Regressed, Error), DiffCategory::ChangedCategory);
+        assert_eq!(
+            categorize(SameTestPass, SameTestPass),
+            DiffCategory::Unchanged
+        );
+        assert_eq!(categorize(Fixed, Fixed), DiffCategory::Unchanged);
+    }
+
+    #[test]
+    fn test_diff() {
+        let first = comparisons(&[
+            ("still", Comparison::Regressed),
+            ("fixed", Comparison::Regressed),
+            ("new", Comparison::SameTestPass),
+            ("changed", Comparison::SameBuildFail),
+            ("same", Comparison::SameTestPass),
+            ("removed", Comparison::Regressed),
+        ]);
+        let second = comparisons(&[
+            ("same", Comparison::SameTestPass),
+            ("changed", Comparison::SameTestPass),
+            ("new", Comparison::Regressed),
+            ("fixed", Comparison::SameTestPass),
+            ("still", Comparison::Regressed),
+            ("added", Comparison::SameTestFail),
+        ]);
+        let diff = diff(&first, &second).unwrap();
+
+        let names = |category| {
+            diff.categories[&category]
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(DiffCategory::StillRegressed), vec!["still-1.0.0"]);
+        assert_eq!(names(DiffCategory::Fixed), vec!["fixed-1.0.0"]);
+        assert_eq!(names(DiffCategory::NewlyRegressed), vec!["new-1.0.0"]);
+        assert_eq!(names(DiffCategory::ChangedCategory), vec!["changed-1.0.0"]);
+        assert_eq!(names(DiffCategory::Unchanged), vec!["same-1.0.0"]);
+
+        let changed = &diff.categories[&DiffCategory::ChangedCategory][0];
+        assert_eq!(changed.before, Comparison::SameBuildFail);
+        assert_eq!(changed.after, Comparison::SameTestPass);
+
+        // Crates in only one of the experiments are not categorized
+        assert_eq!(diff.only_first.len(), 1);
+        assert_eq!(diff.only_first[0].krate, krate("removed"));
+        assert_eq!(diff.only_second.len(), 1);
+        assert_eq!(diff.only_second[0].krate, krate("added"));
+        assert_eq!(diff.categories.values().map(|c| c.len()).sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn test_diff_identity() {
+        // Different versions of the same crate are different crates
+        let mut first = comparisons(&[("foo", Comparison::Regressed)]);
+        let second: Comparisons = vec![(
+            (
+                Crate::Registry(RegistryCrate {
+                    name: "foo".into(),
+                    version: "2.0.0".into(),
+                }),
+                FeatureSet::Default,
+            ),
+            Comparison::Regressed,
+        )]
+        .into_iter()
+        .collect();
+        let diff1 = diff(&first, &second).unwrap();
+        assert!(diff1.categories.is_empty());
+        assert_eq!(diff1.only_first.len(), 1);
+        assert_eq!(diff1.only_second.len(), 1);
+
+        // Feature sets are compared separately
+        first.insert(
+            (krate("foo"), FeatureSet::NoDefault),
+            Comparison::SameTestPass,
+        );
+        let mut second = comparisons(&[("foo", Comparison::SameTestPass)]);
+        second.insert((krate("foo"), FeatureSet::NoDefault), Comparison::Regressed);
+        let diff2 = diff(&first, &second).unwrap();
+        assert_eq!(diff2.count(DiffCategory::Fixed), 1);
+        assert_eq!(diff2.count(DiffCategory::NewlyRegressed), 1);
+        assert_eq!(
+            diff2.categories[&DiffCategory::NewlyRegressed][0].features,
+            FeatureSet::NoDefault
+        );
+
+        // GitHub repositories are matched regardless of the commit that was tested
+        let repo = Crate::GitHub(GitHubRepo {
+            org: "brson".into(),
+            name: "hello-rs".into(),
+        });
+        let first: Comparisons = vec![((repo.clone(), FeatureSet::Default), Comparison::Regressed)]
+            .into_iter()
+            .collect();
+        let second: Comparisons = vec![((repo, FeatureSet::Default), Comparison::Regressed)]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            diff(&first, &second)
+                .unwrap()
+                .count(DiffCategory::StillRegressed),
+            1
+        );
+    }
+
+    #[test]
+    fn test_gen() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+        let first = Experiment::get(&db, "first").unwrap().unwrap();
+        let second = Experiment::get(&db, "second").unwrap().unwrap();
+
+        let results = DatabaseDB::new(&db);
+        let record = |ex: &Experiment, start, end| {
+            let krate = &ex.crates[0];
+            for &(tc, res) in &[(&MAIN_TOOLCHAIN, start), (&TEST_TOOLCHAIN, end)] {
+                results
+                    .record_result(ex, tc, krate, None, &config, || Ok(res))
+                    .unwrap();
+            }
+        };
+        record(&first, TestResult::TestPass, TestResult::TestPass);
+        record(&second, TestResult::TestPass, TestResult::Error);
+
+        let writer = DummyWriter::default();
+        let diff = gen(&results, &first, &second, &writer, &config).unwrap();
+        assert_eq!(diff.count(DiffCategory::ChangedCategory), 1);
+
+        let markdown = String::from_utf8(writer.get("index.md", &mime::TEXT_PLAIN_UTF_8)).unwrap();
+        let changed = &diff.categories[&DiffCategory::ChangedCategory][0];
+        assert!(markdown.contains(&format!(
+            "[{}]({}) | test-pass | error",
+            changed.name, changed.url
+        )));
+        assert!(!markdown.contains("different environments"));
+        assert!(!writer.get("index.html", &mime::TEXT_HTML).is_empty());
+
+        // The report warns when the experiments ran in different environments
+        results
+            .record_environment(&first, &env("old-image"))
+            .unwrap();
+        results
+            .record_environment(&second, &env("new-image"))
+            .unwrap();
+        let writer = DummyWriter::default();
+        gen(&results, &first, &second, &writer, &config).unwrap();
+        let markdown = String::from_utf8(writer.get("index.md", &mime::TEXT_PLAIN_UTF_8)).unwrap();
+        assert!(markdown.contains("**Warning:** the experiments ran in different environments"));
+        let html = String::from_utf8(writer.get("index.html", &mime::TEXT_HTML)).unwrap();
+        assert!(html.contains("old-image"));
+        assert!(html.contains("new-image"));
+    }
+
+    #[test]
+    fn test_environments_differ() {
+        let both = &[env("a"), env("b")];
+        assert!(!environments_differ(&[env("a")], &[env("a")]));
+        assert!(!environments_differ(both, &[env("b"), env("a")]));
+        assert!(environments_differ(&[env("a")], &[env("b")]));
+        assert!(environments_differ(&[env("a")], both));
+
+        // Experiments without a recorded environment are not compared
+        assert!(!environments_differ(&[], &[env("a")]));
+        assert!(!environments_differ(&[env("a")], &[]));
+    }
+
+    #[test]
+    fn test_diff_changed() {
+        let changed = |name: &str, first, second| ChangedCrate {
+            krate: krate(name),
+            features: FeatureSet::Default,
+            first,
+            second,
+        };
+        let diff = diff_changed(&[
+            changed("b", Comparison::SameTestPass, Comparison::Regressed),
+            changed("fixed", Comparison::Regressed, Comparison::SameTestPass),
+            changed("a", Comparison::SameTestPass, Comparison::Regressed),
+            changed("other", Comparison::SameBuildFail, Comparison::Error),
+        ])
+        .unwrap();
+
+        let names = |category| {
+            diff.categories[&category]
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(DiffCategory::NewlyRegressed),
+            vec!["a-1.0.0", "b-1.0.0"]
+        );
+        assert_eq!(names(DiffCategory::Fixed), vec!["fixed-1.0.0"]);
+        assert_eq!(names(DiffCategory::ChangedCategory), vec!["other-1.0.0"]);
+        assert!(diff.only_first.is_empty() && diff.only_second.is_empty());
+
+        // The page doesn't depend on the other files of a report
+        let html = render_standalone(
+            DiffExperiment {
+                name: "first",
+                report_url: None,
+            },
+            DiffExperiment {
+                name: "second",
+                report_url: Some("https://crater-reports.example.com/second/"),
+            },
+            &diff,
+        )
+        .unwrap();
+        assert!(html.contains("fixed-1.0.0"));
+        assert!(html.contains("https://crater-reports.example.com/second/"));
+        assert!(!html.contains("href=\"report.css\""));
+        assert!(!html.contains("src=\"report.js\""));
+        assert!(!html.contains("crates didn't change"));
+    }
+}
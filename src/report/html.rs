@@ -1,11 +1,14 @@
 use crate::assets;
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::report::{archives::Archive, Comparison, CrateResult, ReportWriter, TestResults};
-use crate::results::{FailureReason, TestResult};
+use crate::report::{
+    archives::Archive, Comparison, CrateResult, ReportSummary, ReportWriter, TestResults,
+};
+use crate::results::{EnvironmentFingerprint, FailureReason, TestResult};
 use mime;
 use minifier;
-use std::collections::HashMap;
+use serde_json::{self, json};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Serialize)]
 enum Color {
@@ -31,6 +34,7 @@ impl ResultColor for Comparison {
             Comparison::Error => Color::Single("#d77026"),
             Comparison::SpuriousRegressed => Color::Striped("#db3026", "#d5433b"),
             Comparison::SpuriousFixed => Color::Striped("#5630db", "#5d3dcf"),
+            Comparison::KnownFailure => Color::Striped("#db3026", "#494b4a"),
         }
     }
 }
@@ -57,6 +61,8 @@ impl ResultName for FailureReason {
             FailureReason::Broken => "broken".into(),
             FailureReason::Timeout => "timed out".into(),
             FailureReason::OOM => "OOM".into(),
+            FailureReason::SetupFailed => "setup failed".into(),
+            FailureReason::DownloadFailed => "download failed".into(),
         }
     }
 }
@@ -83,7 +89,7 @@ struct NavbarItem {
 #[derive(PartialEq, Eq)]
 enum CurrentPage {
     Summary,
-    Full,
+    Results,
     Downloads,
 }
 
@@ -95,11 +101,6 @@ impl CurrentPage {
                 url: "index.html",
                 active: *self == CurrentPage::Summary,
             },
-            NavbarItem {
-                label: "Full report",
-                url: "full.html",
-                active: *self == CurrentPage::Full,
-            },
             NavbarItem {
                 label: "Downloads",
                 url: "downloads.html",
@@ -109,13 +110,89 @@ impl CurrentPage {
     }
 }
 
+/// Crates listed in each page of a category, so the pages of huge experiments don't freeze the
+/// browsers.
+const CRATES_PER_PAGE: usize = 2000;
+
+#[derive(Serialize)]
+struct PageLink {
+    label: String,
+    url: String,
+    active: bool,
+}
+
+#[derive(Serialize)]
+struct CategoryLink {
+    name: Comparison,
+    count: usize,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct DependencyRegressions<'a> {
+    name: &'a str,
+    count: u32,
+}
+
+/// Regressions counted by their root cause, only shown if some of them were caused by
+/// dependencies.
+#[derive(Serialize)]
+struct RootRegressions<'a> {
+    count: u32,
+    regressed: u32,
+    dependencies: Vec<DependencyRegressions<'a>>,
+}
+
+impl<'a> RootRegressions<'a> {
+    fn new(summary: &'a ReportSummary) -> Option<Self> {
+        if summary.dependency_regressions.is_empty() {
+            return None;
+        }
+
+        let mut dependencies = summary
+            .dependency_regressions
+            .iter()
+            .map(|(name, &count)| DependencyRegressions { name, count })
+            .collect::<Vec<_>>();
+        dependencies.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(b.name)));
+        Some(RootRegressions {
+            count: summary.root_regressions(),
+            regressed: summary.count(Comparison::Regressed),
+            dependencies,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct IndexContext<'a> {
+    ex: &'a Experiment,
+    nav: Vec<NavbarItem>,
+    root: &'static str,
+    categories: Vec<CategoryLink>,
+    prefixes: Vec<PageLink>,
+    root_regressions: Option<RootRegressions<'a>>,
+    summary: &'a ReportSummary,
+    docker_image: Option<&'a String>,
+    environments: &'a [EnvironmentFingerprint],
+
+    comparison_colors: HashMap<Comparison, Color>,
+}
+
 #[derive(Serialize)]
 struct ResultsContext<'a> {
     ex: &'a Experiment,
     nav: Vec<NavbarItem>,
-    categories: HashMap<Comparison, Vec<CrateResult>>,
-    full: bool,
-    crates_count: usize,
+    root: &'static str,
+    title: String,
+    /// Names of the toolchains, labeling the columns of their results.
+    toolchains: Vec<String>,
+    /// Category of all the crates in the page, if the page lists a single one.
+    category: Option<Comparison>,
+    crates: &'a [&'a CrateResult],
+    pages: Vec<PageLink>,
+    summary: &'a ReportSummary,
+    docker_image: Option<&'a String>,
+    environments: &'a [EnvironmentFingerprint],
 
     comparison_colors: HashMap<Comparison, Color>,
     result_colors: HashMap<TestResult, Color>,
@@ -126,87 +203,291 @@ struct ResultsContext<'a> {
 struct DownloadsContext<'a> {
     ex: &'a Experiment,
     nav: Vec<NavbarItem>,
-    crates_count: usize,
+    root: &'static str,
+    summary: &'a ReportSummary,
+    docker_image: Option<&'a String>,
+    environments: &'a [EnvironmentFingerprint],
 
     available_archives: Vec<Archive>,
 }
 
-fn write_report<W: ReportWriter>(
-    ex: &Experiment,
-    res: &TestResults,
-    full: bool,
-    to: &str,
-    dest: &W,
-) -> Fallible<()> {
-    let mut comparison_colors = HashMap::new();
-    let mut result_colors = HashMap::new();
-    let mut result_names = HashMap::new();
+/// Data shared by all the pages of the report.
+pub(super) struct ReportPage<'a> {
+    pub(super) ex: &'a Experiment,
+    pub(super) summary: &'a ReportSummary,
+    pub(super) docker_image: Option<&'a String>,
+    pub(super) environments: &'a [EnvironmentFingerprint],
+}
+
+/// Name of the page listing the crates whose name starts like `name`.
+pub(super) fn lookup_prefix(name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() => c.to_ascii_lowercase().to_string(),
+        _ => "_".into(),
+    }
+}
+
+fn category_page(category: Comparison, page: usize) -> String {
+    format!("categories/{}-{}", category, page)
+}
+
+/// Crates of the report split in the pages they're listed in: each category is paginated, and
+/// each crate is also listed in the lookup page of the first character of its name.
+pub(super) struct ReportPages<'a> {
+    categories: Vec<(Comparison, Vec<&'a CrateResult>)>,
+    prefixes: Vec<(String, Vec<&'a CrateResult>)>,
+}
 
-    let mut categories = HashMap::new();
-    for result in &res.crates {
-        // Skip some categories if this is not the full report
-        if !full && !result.res.show_in_summary() {
-            continue;
+impl<'a> ReportPages<'a> {
+    pub(super) fn new(res: &'a TestResults) -> Self {
+        let mut categories = HashMap::new();
+        let mut prefixes = HashMap::new();
+        for krate in &res.crates {
+            categories
+                .entry(krate.res)
+                .or_insert_with(Vec::new)
+                .push(krate);
+            prefixes
+                .entry(lookup_prefix(&krate.name))
+                .or_insert_with(Vec::new)
+                .push(krate);
         }
 
-        // Add the colors and names used in this run
-        comparison_colors
-            .entry(result.res)
-            .or_insert_with(|| result.res.color());
-        if let Some(ref run) = result.runs[0] {
-            result_colors
-                .entry(run.res)
-                .or_insert_with(|| run.res.color());
-            result_names
-                .entry(run.res)
-                .or_insert_with(|| run.res.name());
+        // Regressions caused by a dependency are listed after the other ones, grouped by the
+        // dependency
+        if let Some(regressed) = categories.get_mut(&Comparison::Regressed) {
+            regressed.sort_by(|a, b| a.caused_by.cmp(&b.caused_by));
         }
-        if let Some(ref run) = result.runs[1] {
-            result_colors
-                .entry(run.res)
-                .or_insert_with(|| run.res.color());
-            result_names
-                .entry(run.res)
-                .or_insert_with(|| run.res.name());
+
+        // Categories are listed in the order they're defined
+        let categories = Comparison::possible_values()
+            .iter()
+            .filter_map(|name| {
+                let category = name.parse::<Comparison>().ok()?;
+                categories
+                    .remove(&category)
+                    .map(|crates| (category, crates))
+            })
+            .collect();
+        let mut prefixes = prefixes.into_iter().collect::<Vec<_>>();
+        prefixes.sort_by(|a, b| a.0.cmp(&b.0));
+
+        ReportPages {
+            categories,
+            prefixes,
         }
+    }
+}
+
+fn comparison_colors<I: IntoIterator<Item = Comparison>>(
+    categories: I,
+) -> HashMap<Comparison, Color> {
+    categories
+        .into_iter()
+        .map(|category| (category, category.color()))
+        .collect()
+}
+
+pub(super) fn write_index<W: ReportWriter>(
+    page: &ReportPage,
+    pages: &ReportPages,
+    dest: &W,
+) -> Fallible<()> {
+    let context = IndexContext {
+        ex: page.ex,
+        nav: CurrentPage::Summary.navbar(),
+        root: "",
+        categories: pages
+            .categories
+            .iter()
+            .map(|(category, crates)| CategoryLink {
+                name: *category,
+                count: crates.len(),
+                url: format!("{}.html", category_page(*category, 1)),
+            })
+            .collect(),
+        prefixes: pages
+            .prefixes
+            .iter()
+            .map(|(prefix, _)| PageLink {
+                label: prefix.clone(),
+                url: format!("crates/{}.html", prefix),
+                active: false,
+            })
+            .collect(),
+        root_regressions: RootRegressions::new(page.summary),
+        summary: page.summary,
+        docker_image: page.docker_image,
+        environments: page.environments,
+
+        comparison_colors: comparison_colors(pages.categories.iter().map(|(c, _)| *c)),
+    };
 
-        let category = categories.entry(result.res).or_insert_with(Vec::new);
-        category.push(result.clone());
+    info!("generating index.html");
+    let html = minifier::html::minify(&assets::render_template("report/index.html", &context)?);
+    dest.write_string("index.html", html.into(), &mime::TEXT_HTML)?;
+
+    Ok(())
+}
+
+/// Write a page of crates, along with the JSON data of the same crates. Pages are in a
+/// subdirectory of the report.
+fn write_results_page<W: ReportWriter>(
+    page: &ReportPage,
+    title: String,
+    category: Option<Comparison>,
+    crates: &[&CrateResult],
+    pages: Vec<PageLink>,
+    to: &str,
+    dest: &W,
+) -> Fallible<()> {
+    let mut result_colors = HashMap::new();
+    let mut result_names = HashMap::new();
+    for run in crates.iter().flat_map(|krate| krate.runs.iter().flatten()) {
+        result_colors
+            .entry(run.res)
+            .or_insert_with(|| run.res.color());
+        result_names
+            .entry(run.res)
+            .or_insert_with(|| run.res.name());
     }
 
     let context = ResultsContext {
-        ex,
-        nav: if full {
-            CurrentPage::Full
-        } else {
-            CurrentPage::Summary
-        }
-        .navbar(),
-        categories,
-        full,
-        crates_count: ex.crates.len(),
+        ex: page.ex,
+        nav: CurrentPage::Results.navbar(),
+        root: "../",
+        title,
+        toolchains: page.ex.toolchains.iter().map(|tc| tc.to_string()).collect(),
+        category,
+        crates,
+        pages,
+        summary: page.summary,
+        docker_image: page.docker_image,
+        environments: page.environments,
 
-        comparison_colors,
+        comparison_colors: comparison_colors(crates.iter().map(|krate| krate.res)),
         result_colors,
         result_names,
     };
 
-    info!("generating {}", to);
     let html = minifier::html::minify(&assets::render_template("report/results.html", &context)?);
-    dest.write_string(to, html.into(), &mime::TEXT_HTML)?;
+    dest.write_string(format!("{}.html", to), html.into(), &mime::TEXT_HTML)?;
+    dest.write_bytes(
+        format!("{}.json", to),
+        serde_json::to_vec(&json!({ "crates": crates }))?,
+        &mime::APPLICATION_JSON,
+    )?;
 
     Ok(())
 }
 
-fn write_downloads<W: ReportWriter>(
-    ex: &Experiment,
+/// Write all the pages of the category. Pages are numbered from 1.
+pub(super) fn write_category<W: ReportWriter>(
+    page: &ReportPage,
+    pages: &ReportPages,
+    category: Comparison,
+    dest: &W,
+) -> Fallible<()> {
+    let crates = match pages.categories.iter().find(|(c, _)| *c == category) {
+        Some((_, crates)) => crates,
+        None => return Ok(()),
+    };
+
+    let chunks = crates.chunks(CRATES_PER_PAGE).collect::<Vec<_>>();
+    info!("generating {} pages of {}", chunks.len(), category);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let links = (1..=chunks.len())
+            .map(|number| PageLink {
+                label: number.to_string(),
+                // Pages link to the other pages of the same directory
+                url: format!("{}-{}.html", category, number),
+                active: number == i + 1,
+            })
+            .collect();
+        write_results_page(
+            page,
+            format!("{} ({})", category, crates.len()),
+            Some(category),
+            chunk,
+            links,
+            &category_page(category, i + 1),
+            dest,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the page listing the crates whose name starts with `prefix`.
+pub(super) fn write_lookup<W: ReportWriter>(
+    page: &ReportPage,
+    pages: &ReportPages,
+    prefix: &str,
+    dest: &W,
+) -> Fallible<()> {
+    let crates = match pages.prefixes.iter().find(|(p, _)| p == prefix) {
+        Some((_, crates)) => crates,
+        None => return Ok(()),
+    };
+
+    let links = pages
+        .prefixes
+        .iter()
+        .map(|(other, _)| PageLink {
+            label: other.clone(),
+            url: format!("{}.html", other),
+            active: other == prefix,
+        })
+        .collect();
+    write_results_page(
+        page,
+        format!("crates starting with \"{}\" ({})", prefix, crates.len()),
+        None,
+        crates,
+        links,
+        &format!("crates/{}", prefix),
+        dest,
+    )
+}
+
+/// Write the index and the pages of the crates. If `changed` is provided, only the pages of those
+/// categories and lookup prefixes are written.
+pub(super) fn write_report<W: ReportWriter>(
+    page: &ReportPage,
+    res: &TestResults,
+    changed: Option<(&HashSet<Comparison>, &HashSet<String>)>,
+    dest: &W,
+) -> Fallible<()> {
+    let pages = ReportPages::new(res);
+    write_index(page, &pages, dest)?;
+
+    for (category, _) in &pages.categories {
+        if changed.map_or(true, |(categories, _)| categories.contains(category)) {
+            write_category(page, &pages, *category, dest)?;
+        }
+    }
+    info!("generating the crate lookup pages");
+    for (prefix, _) in &pages.prefixes {
+        if changed.map_or(true, |(_, prefixes)| prefixes.contains(prefix)) {
+            write_lookup(page, &pages, prefix, dest)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn write_downloads<W: ReportWriter>(
+    page: &ReportPage,
     available_archives: Vec<Archive>,
     dest: &W,
 ) -> Fallible<()> {
     let context = DownloadsContext {
-        ex,
+        ex: page.ex,
         nav: CurrentPage::Downloads.navbar(),
-        crates_count: ex.crates.len(),
+        root: "",
+        summary: page.summary,
+        docker_image: page.docker_image,
+        environments: page.environments,
 
         available_archives,
     };
@@ -221,14 +502,26 @@ fn write_downloads<W: ReportWriter>(
 pub fn write_html_report<W: ReportWriter>(
     ex: &Experiment,
     res: &TestResults,
+    summary: &ReportSummary,
+    docker_image: Option<&String>,
+    environments: &[EnvironmentFingerprint],
     available_archives: Vec<Archive>,
     dest: &W,
 ) -> Fallible<()> {
+    let page = ReportPage {
+        ex,
+        summary,
+        docker_image,
+        environments,
+    };
+    write_report(&page, res, None, dest)?;
+    write_downloads(&page, available_archives, dest)?;
+    write_assets(dest)
+}
+
+pub(super) fn write_assets<W: ReportWriter>(dest: &W) -> Fallible<()> {
     let js_in = assets::load("report.js")?;
     let css_in = assets::load("report.css")?;
-    write_report(ex, res, false, "index.html", dest)?;
-    write_report(ex, res, true, "full.html", dest)?;
-    write_downloads(ex, available_archives, dest)?;
 
     info!("copying static assets");
     dest.write_bytes("report.js", js_in.content()?.into_owned(), js_in.mime())?;
@@ -236,3 +529,172 @@ pub fn write_html_report<W: ReportWriter>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup_prefix, write_category, ReportPage, ReportPages};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{Experiment, FeatureSet};
+    use crate::report::{
+        BuildTestResult, Comparison, CrateResult, DummyWriter, ReportSummary, TestResults,
+    };
+    use crate::results::{FailureReason, TestResult};
+
+    #[test]
+    fn test_report_pages() {
+        let result = |name: &str, res| CrateResult {
+            name: name.into(),
+            url: String::new(),
+            res,
+            runs: [None, None],
+            features: FeatureSet::Default,
+            warnings_diff: None,
+            artifacts_size_diff: None,
+            caused_by: None,
+            annotation: None,
+            history: None,
+        };
+        let mut dependency = result("aho-corasick-0.7.3", Comparison::Regressed);
+        dependency.caused_by = Some("memchr".into());
+        let res = TestResults {
+            crates: vec![
+                dependency,
+                result("serde-1.0.0", Comparison::SameTestPass),
+                result("Inflector-0.11.4", Comparison::Regressed),
+                result("syn-0.15.26", Comparison::Regressed),
+                result("_private-0.1.0", Comparison::Fixed),
+            ],
+        };
+
+        let pages = ReportPages::new(&res);
+        let names = |crates: &[&CrateResult]| {
+            crates
+                .iter()
+                .map(|krate| krate.name.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        // Categories are sorted like their definition, and keep the order of the crates except
+        // for the regressions caused by dependencies
+        assert_eq!(
+            pages
+                .categories
+                .iter()
+                .map(|(category, _)| *category)
+                .collect::<Vec<_>>(),
+            vec![
+                Comparison::Regressed,
+                Comparison::Fixed,
+                Comparison::SameTestPass
+            ]
+        );
+        assert_eq!(
+            names(&pages.categories[0].1),
+            vec!["Inflector-0.11.4", "syn-0.15.26", "aho-corasick-0.7.3"]
+        );
+
+        // Crates are looked up case insensitively
+        assert_eq!(lookup_prefix("Inflector-0.11.4"), "i");
+        assert_eq!(
+            pages
+                .prefixes
+                .iter()
+                .map(|(prefix, _)| prefix.as_str())
+                .collect::<Vec<_>>(),
+            vec!["_", "a", "i", "s"]
+        );
+        assert_eq!(
+            names(&pages.prefixes[3].1),
+            vec!["serde-1.0.0", "syn-0.15.26"]
+        );
+    }
+    #[test]
+    fn test_results_page_markup() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let run = |res| {
+            Some(BuildTestResult {
+                res,
+                log: "stable/reg/foo-1.0.0".into(),
+                warnings: None,
+                artifacts_size: None,
+                phases: Vec::new(),
+            })
+        };
+        let res = TestResults {
+            crates: vec![
+                CrateResult {
+                    name: "foo-1.0.0".into(),
+                    url: "https://crates.io/crates/foo/1.0.0".into(),
+                    res: Comparison::Regressed,
+                    runs: [
+                        run(TestResult::TestPass),
+                        run(TestResult::BuildFail(FailureReason::Unknown)),
+                    ],
+                    features: FeatureSet::Default,
+                    warnings_diff: None,
+                    artifacts_size_diff: None,
+                    caused_by: None,
+                    annotation: None,
+                    history: None,
+                },
+                CrateResult {
+                    name: "bar-1.0.0".into(),
+                    url: "https://crates.io/crates/bar/1.0.0".into(),
+                    res: Comparison::Regressed,
+                    runs: [None, None],
+                    features: FeatureSet::Default,
+                    warnings_diff: None,
+                    artifacts_size_diff: None,
+                    caused_by: Some("foo".into()),
+                    annotation: None,
+                    history: None,
+                },
+            ],
+        };
+
+        let summary = ReportSummary::new(&ex, &res);
+        let page = ReportPage {
+            ex: &ex,
+            summary: &summary,
+            docker_image: None,
+            environments: &[],
+        };
+        let writer = DummyWriter::default();
+        write_category(
+            &page,
+            &ReportPages::new(&res),
+            Comparison::Regressed,
+            &writer,
+        )
+        .unwrap();
+        let html =
+            String::from_utf8(writer.get("categories/regressed-1.html", &mime::TEXT_HTML)).unwrap();
+
+        // The results are a table with a caption, whose columns are labeled with the toolchains
+        assert!(html.contains("<table"));
+        assert!(html.contains("<caption"));
+        for tc in &ex.toolchains {
+            assert!(html.contains(&format!("<th scope=\"col\">{}</th>", tc)));
+        }
+
+        // Each crate is the header of its row
+        assert_eq!(html.matches("<th scope=\"row\">").count(), 2);
+        assert_eq!(html.matches("<tr class=\"crate\"").count(), 2);
+
+        // The colored badges are hidden from screen readers, as the results are also written out
+        assert_eq!(
+            html.matches("<b class=\"badge").count(),
+            html.matches("aria-hidden=\"true\"></b>").count()
+        );
+        assert!(html.contains("build failed"));
+        assert!(html.contains("caused by foo"));
+    }
+}
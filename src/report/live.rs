@@ -0,0 +1,244 @@
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{
+    compare, crate_results, crate_to_history_url, crate_to_name, crate_to_url, html, write_log,
+    CrateResult, ReportSummary, ReportWriter, TestResults,
+};
+use crate::results::ReadResults;
+use crate::toolchain::Toolchain;
+use mime;
+use serde_json;
+use std::collections::{HashMap, HashSet};
+
+/// Report of a running experiment, updated with the results recorded since the last update
+/// instead of loading all the results again.
+///
+/// Only the pages affected by the new results are written again. Archives of the logs are not
+/// included, as they're only generated by the full report once the experiment is completed.
+pub struct LiveReport {
+    results: TestResults,
+    summary: ReportSummary,
+    /// Position of the first result of each crate, followed by the other feature sets.
+    positions: HashMap<Crate, usize>,
+    last_result: i64,
+    published: bool,
+}
+
+impl LiveReport {
+    /// Create a live report where no crate has a result yet, without loading anything.
+    pub fn new(ex: &Experiment, config: &Config) -> Fallible<Self> {
+        let shas = HashMap::new();
+        let mut results = Vec::with_capacity(ex.crates.len() * ex.feature_sets.len());
+        let mut positions = HashMap::with_capacity(ex.crates.len());
+        for krate in &ex.crates {
+            positions.insert(krate.clone(), results.len());
+            for features in &ex.feature_sets {
+                results.push(CrateResult {
+                    name: crate_to_name(krate, &shas)?,
+                    url: crate_to_url(krate, &shas)?,
+                    res: compare(config, krate, None, None),
+                    runs: [None, None],
+                    features: features.clone(),
+                    warnings_diff: None,
+                    artifacts_size_diff: None,
+                    caused_by: None,
+                    annotation: None,
+                    history: crate_to_history_url(config, krate),
+                });
+            }
+        }
+
+        let results = TestResults { crates: results };
+        Ok(LiveReport {
+            summary: ReportSummary::new(ex, &results),
+            results,
+            positions,
+            last_result: 0,
+            published: false,
+        })
+    }
+
+    /// Id of the last result included in the report.
+    pub fn last_result(&self) -> i64 {
+        self.last_result
+    }
+
+    pub fn summary(&self) -> &ReportSummary {
+        &self.summary
+    }
+
+    /// Include the new results in the report, and write the pages they changed. Returns whether
+    /// the report was published for the first time.
+    pub fn update<DB: ReadResults, W: ReportWriter>(
+        &mut self,
+        db: &DB,
+        config: &Config,
+        ex: &Experiment,
+        new: &[(i64, Crate, Toolchain)],
+        dest: &W,
+    ) -> Fallible<bool> {
+        let shas = db.load_all_shas(ex)?;
+
+        // Only the pages listing the updated crates are written again
+        let mut categories = HashSet::new();
+        let mut prefixes = HashSet::new();
+        let mut updated = HashSet::new();
+        for &(id, ref krate, ref toolchain) in new {
+            self.last_result = self.last_result.max(id);
+            if !config.should_skip(krate) {
+                write_log(db, ex, toolchain, krate, dest)?;
+            }
+
+            let start = match self.positions.get(krate) {
+                Some(&start) if updated.insert(krate) => start,
+                // Crates not part of the experiment anymore, or already updated
+                _ => continue,
+            };
+            for (i, result) in crate_results(db, config, ex, krate, &shas)?
+                .into_iter()
+                .enumerate()
+            {
+                let old = &mut self.results.crates[start + i];
+                categories.insert(old.res);
+                categories.insert(result.res);
+                prefixes.insert(html::lookup_prefix(&result.name));
+                self.summary.remove(old);
+                self.summary.add(&result);
+                *old = result;
+            }
+        }
+
+        info!(
+            "updating the live report of {} with {} new results",
+            ex.name,
+            new.len()
+        );
+        dest.write_string(
+            "results.json",
+            serde_json::to_string(&self.results)?.into(),
+            &mime::APPLICATION_JSON,
+        )?;
+
+        let docker_image = db.load_docker_image(ex)?;
+        let environments = db.load_environments(ex)?;
+        let page = html::ReportPage {
+            ex,
+            summary: &self.summary,
+            docker_image: docker_image.as_ref(),
+            environments: &environments,
+        };
+        // All the pages are written the first time the report is published
+        let changed = if self.published {
+            Some((&categories, &prefixes))
+        } else {
+            None
+        };
+        html::write_report(&page, &self.results, changed, dest)?;
+
+        let first_publish = !self.published;
+        if first_publish {
+            dest.write_string(
+                "config.json",
+                serde_json::to_string(&ex)?.into(),
+                &mime::APPLICATION_JSON,
+            )?;
+            html::write_downloads(&page, Vec::new(), dest)?;
+            html::write_assets(dest)?;
+            self.published = true;
+        }
+
+        Ok(first_publish)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LiveReport;
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::experiments::{CapLints, Experiment, FeatureSet, Mode, Status};
+    use crate::report::{generate_report, Comparison, DummyWriter, ReportSummary};
+    use crate::results::{DummyDB, FailureReason, TestResult};
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use mime;
+
+    #[test]
+    fn test_live_report() {
+        let config = Config::default();
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        let (foo, bar) = (krate("foo"), krate("bar"));
+
+        let ex = Experiment {
+            name: "foo".to_string(),
+            crates: vec![foo.clone(), bar.clone()],
+            toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Forbid,
+            priority: 0,
+            created_at: ::chrono::Utc::now(),
+            started_at: None,
+            completed_at: None,
+            github_issue: None,
+            status: Status::Running,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: true,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+        };
+
+        let mut db = DummyDB::default();
+        let mut live = LiveReport::new(&ex, &config).unwrap();
+        assert_eq!(live.summary().count(Comparison::Unknown), 2);
+
+        let writer = DummyWriter::default();
+        let result = |db: &mut DummyDB, krate: &Crate, tc: &Toolchain, res| {
+            db.add_dummy_result(&ex, krate.clone(), tc.clone(), res);
+            db.add_dummy_log(&ex, krate.clone(), tc.clone(), b"log".to_vec());
+        };
+        result(&mut db, &foo, &MAIN_TOOLCHAIN, TestResult::TestPass);
+        let new = vec![(1, foo.clone(), MAIN_TOOLCHAIN.clone())];
+        assert!(live.update(&db, &config, &ex, &new, &writer).unwrap());
+        assert_eq!(live.last_result(), 1);
+        assert_eq!(live.summary().count(Comparison::Unknown), 2);
+        let index = String::from_utf8(writer.get("index.html", &mime::TEXT_HTML)).unwrap();
+        assert!(index.contains("The experiment is still running"));
+        writer.get("downloads.html", &mime::TEXT_HTML);
+
+        result(
+            &mut db,
+            &foo,
+            &TEST_TOOLCHAIN,
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+        let new = vec![(2, foo.clone(), TEST_TOOLCHAIN.clone())];
+        assert!(!live.update(&db, &config, &ex, &new, &writer).unwrap());
+        assert_eq!(live.last_result(), 2);
+        assert_eq!(live.summary().count(Comparison::Regressed), 1);
+        assert_eq!(live.summary().count(Comparison::Unknown), 1);
+        assert_eq!(
+            &writer.get("stable/reg/foo-1.0.0/log.txt", &mime::TEXT_PLAIN_UTF_8),
+            b"log"
+        );
+
+        // The incremental summary matches the one of a full report
+        let full = generate_report(&db, &config, &ex).unwrap();
+        assert_eq!(live.summary(), &ReportSummary::new(&ex, &full));
+    }
+}
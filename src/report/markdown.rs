@@ -0,0 +1,399 @@
+use crate::experiments::Experiment;
+use crate::report::{summary::regression_cluster, Comparison, CrateResult, TestResults};
+use crate::results::TestResult;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Crates listed in each section of `summary.md`, the others are only counted.
+pub(super) const MAX_CRATES_PER_SECTION: usize = 50;
+
+/// Categories counted at the top of the summary, in the order they're listed.
+const CATEGORIES: &[Comparison] = &[
+    Comparison::Regressed,
+    Comparison::SpuriousRegressed,
+    Comparison::Fixed,
+    Comparison::SpuriousFixed,
+    Comparison::KnownFailure,
+    Comparison::Error,
+    Comparison::Unknown,
+];
+
+/// Sections listing the crates of a category, after the regressions.
+const SECTIONS: &[(Comparison, &str)] = &[
+    (Comparison::SpuriousRegressed, "Spurious regressions"),
+    (Comparison::Fixed, "Fixes"),
+    (Comparison::Error, "Errors"),
+];
+
+/// Render the Markdown summary of the report, meant to be pasted in GitHub issues or release
+/// notes. Each section lists at most `limit` crates, and links to the logs are only included if
+/// the URL of the report is known.
+pub fn render_summary(
+    ex: &Experiment,
+    res: &TestResults,
+    report_url: Option<&str>,
+    limit: usize,
+) -> String {
+    let mut out = String::new();
+    writeln!(out, "# Crater report for `{}`", ex.name).unwrap();
+    writeln!(out).unwrap();
+    write!(
+        out,
+        "Compared `{}` with `{}` on {} crates.",
+        ex.toolchains[0],
+        ex.toolchains[1],
+        ex.crates.len()
+    )
+    .unwrap();
+    if let Some(url) = report_url {
+        write!(out, " [Open the full report]({}).", url).unwrap();
+    }
+    writeln!(out).unwrap();
+    if !ex.cargo_patches.is_empty() {
+        let patches = ex
+            .cargo_patches
+            .iter()
+            .map(|patch| match patch.rev {
+                Some(ref rev) => format!("`{}` from {} at `{}`", patch.name, patch.git, rev),
+                None => format!("`{}` from {}", patch.name, patch.git),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "The crates were built with patched dependencies: {}.",
+            patches.join(", ")
+        )
+        .unwrap();
+    }
+
+    let mut counts = HashMap::new();
+    for krate in &res.crates {
+        *counts.entry(krate.res).or_insert(0) += 1;
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "| Result | Count |").unwrap();
+    writeln!(out, "| --- | ---: |").unwrap();
+    for category in CATEGORIES {
+        if let Some(count) = counts.get(category) {
+            writeln!(out, "| {} | {} |", category.to_str(), count).unwrap();
+        }
+    }
+    writeln!(out, "| total | {} |", res.crates.len()).unwrap();
+
+    out.push_str(&render_regressions(res, report_url, limit));
+    for &(category, title) in SECTIONS {
+        let crates = res
+            .crates
+            .iter()
+            .filter(|krate| krate.res == category)
+            .collect::<Vec<_>>();
+        if !crates.is_empty() {
+            writeln!(out).unwrap();
+            writeln!(out, "## {} ({})", title, crates.len()).unwrap();
+            write_crates(&mut out, &crates, report_url, limit);
+        }
+    }
+
+    out
+}
+
+/// Render only the regressions of the report, grouped by the result on the second toolchain so
+/// the ones likely to share the same cause are listed together. Regressions caused by a dependency
+/// failing to build are grouped by that dependency instead, after the other ones. The biggest
+/// groups come first.
+pub fn render_regressions(res: &TestResults, report_url: Option<&str>, limit: usize) -> String {
+    let mut groups: Vec<(TestResult, Vec<&CrateResult>)> = Vec::new();
+    let mut dependencies: Vec<(&str, Vec<&CrateResult>)> = Vec::new();
+    for krate in &res.crates {
+        let cluster = match regression_cluster(krate) {
+            Some(cluster) => cluster,
+            None => continue,
+        };
+        if let Some(ref dependency) = krate.caused_by {
+            match dependencies
+                .iter_mut()
+                .find(|(dep, _)| *dep == dependency.as_str())
+            {
+                Some((_, crates)) => crates.push(krate),
+                None => dependencies.push((dependency.as_str(), vec![krate])),
+            }
+        } else {
+            match groups.iter_mut().find(|(res, _)| *res == cluster) {
+                Some((_, crates)) => crates.push(krate),
+                None => groups.push((cluster, vec![krate])),
+            }
+        }
+    }
+    if groups.is_empty() && dependencies.is_empty() {
+        return String::new();
+    }
+    groups.sort_by(|(a, a_crates), (b, b_crates)| {
+        b_crates
+            .len()
+            .cmp(&a_crates.len())
+            .then_with(|| a.to_string().cmp(&b.to_string()))
+    });
+    dependencies.sort_by(|(a, a_crates), (b, b_crates)| {
+        b_crates.len().cmp(&a_crates.len()).then_with(|| a.cmp(b))
+    });
+
+    let mut out = String::new();
+    let roots: usize = groups.iter().map(|(_, crates)| crates.len()).sum();
+    writeln!(out).unwrap();
+    if dependencies.is_empty() {
+        writeln!(out, "## Regressions ({})", roots).unwrap();
+    } else {
+        let caused_by_dependencies: usize =
+            dependencies.iter().map(|(_, crates)| crates.len()).sum();
+        writeln!(
+            out,
+            "## Regressions ({} root regressions, {} crates)",
+            roots + dependencies.len(),
+            roots + caused_by_dependencies
+        )
+        .unwrap();
+    }
+    for (cluster, crates) in &groups {
+        writeln!(out).unwrap();
+        writeln!(out, "### `{}` ({})", cluster, crates.len()).unwrap();
+        write_crates(&mut out, crates, report_url, limit);
+    }
+    for (dependency, crates) in &dependencies {
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "### Caused by the dependency `{}` ({})",
+            dependency,
+            crates.len()
+        )
+        .unwrap();
+        write_crates(&mut out, crates, report_url, limit);
+    }
+    out
+}
+
+fn write_crates(out: &mut String, crates: &[&CrateResult], report_url: Option<&str>, limit: usize) {
+    // Logs are stored next to the index of the report
+    let base_url = report_url.map(|url| url.trim_end_matches("index.html"));
+
+    writeln!(out).unwrap();
+    for krate in crates.iter().take(limit) {
+        write!(out, "* [{}]({})", krate.name, krate.url).unwrap();
+        if !krate.features.is_default() {
+            write!(out, " with features `{}`", krate.features).unwrap();
+        }
+        if let Some(base_url) = base_url {
+            let logs = krate
+                .runs
+                .iter()
+                .zip(&["before", "after"])
+                .filter_map(|(run, label)| {
+                    run.as_ref()
+                        .map(|run| format!("[{}]({}{}/log.txt)", label, base_url, run.log))
+                })
+                .collect::<Vec<_>>();
+            if !logs.is_empty() {
+                write!(out, " ({})", logs.join(", ")).unwrap();
+            }
+        }
+        writeln!(out).unwrap();
+    }
+    if crates.len() > limit {
+        writeln!(out, "* ...and {} more", crates.len() - limit).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_regressions, render_summary};
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::experiments::{CapLints, CargoPatch, Experiment, FeatureSet, Mode, Status};
+    use crate::report::{
+        crate_to_path_fragment, BuildTestResult, Comparison, CrateResult, TestResults,
+    };
+    use crate::results::TestResult;
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+
+    fn result(name: &str, res: Comparison, runs: [Option<TestResult>; 2]) -> CrateResult {
+        let krate = Crate::Registry(RegistryCrate {
+            name: name.into(),
+            version: "1.0.0".into(),
+        });
+        let toolchains = [&*MAIN_TOOLCHAIN, &*TEST_TOOLCHAIN];
+        let mut runs = runs.iter().zip(&toolchains).map(|(run, tc)| {
+            run.map(|res| BuildTestResult {
+                res,
+                log: crate_to_path_fragment(tc, &krate, true)
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+                warnings: None,
+                artifacts_size: None,
+                phases: Vec::new(),
+            })
+        });
+        CrateResult {
+            name: format!("{}-1.0.0", name),
+            url: format!("https://crates.io/crates/{}/1.0.0", name),
+            res,
+            runs: [runs.next().unwrap(), runs.next().unwrap()],
+            features: FeatureSet::Default,
+            warnings_diff: None,
+            artifacts_size_diff: None,
+            caused_by: None,
+            annotation: None,
+            history: None,
+        }
+    }
+
+    fn synthetic() -> (Experiment, TestResults) {
+        use crate::results::FailureReason::*;
+        use crate::results::TestResult::*;
+
+        let crates = vec![
+            result(
+                "alpha",
+                Comparison::Regressed,
+                [Some(TestPass), Some(BuildFail(Unknown))],
+            ),
+            result(
+                "beta",
+                Comparison::Regressed,
+                [Some(TestPass), Some(BuildFail(Unknown))],
+            ),
+            result(
+                "gamma",
+                Comparison::Regressed,
+                [Some(TestPass), Some(BuildFail(Unknown))],
+            ),
+            result(
+                "delta",
+                Comparison::Regressed,
+                [Some(TestPass), Some(TestFail(Unknown))],
+            ),
+            result(
+                "epsilon",
+                Comparison::SpuriousRegressed,
+                [Some(TestPass), Some(BuildFail(OOM))],
+            ),
+            result(
+                "zeta",
+                Comparison::Fixed,
+                [Some(BuildFail(Unknown)), Some(TestPass)],
+            ),
+            result(
+                "eta",
+                Comparison::SameTestPass,
+                [Some(TestPass), Some(TestPass)],
+            ),
+            result("theta", Comparison::Unknown, [Some(TestPass), None]),
+        ];
+
+        let ex = Experiment {
+            name: "pr-12345".to_string(),
+            crates: crates
+                .iter()
+                .map(|krate| {
+                    Crate::Registry(RegistryCrate {
+                        name: krate.name.trim_end_matches("-1.0.0").into(),
+                        version: "1.0.0".into(),
+                    })
+                })
+                .collect(),
+            toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Forbid,
+            priority: 0,
+            created_at: ::chrono::Utc::now(),
+            started_at: None,
+            completed_at: None,
+            github_issue: None,
+            status: Status::GeneratingReport,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+        };
+
+        (ex, TestResults { crates })
+    }
+
+    #[test]
+    fn test_render_summary_golden() {
+        let (ex, res) = synthetic();
+        let url = "https://example.com/pr-12345/index.html";
+
+        assert_eq!(
+            render_summary(&ex, &res, Some(url), 2),
+            include_str!("../../tests/report/summary.md")
+        );
+        assert_eq!(
+            render_summary(&ex, &res, None, 2),
+            include_str!("../../tests/report/summary-without-url.md")
+        );
+    }
+
+    #[test]
+    fn test_render_patched_summary() {
+        let (mut ex, res) = synthetic();
+        ex.cargo_patches.push(CargoPatch {
+            name: "tokio".into(),
+            git: "https://github.com/tokio-rs/tokio".into(),
+            rev: Some("f00".into()),
+        });
+
+        let summary = render_summary(&ex, &res, None, 2);
+        assert!(summary.contains(
+            "\nThe crates were built with patched dependencies: \
+             `tokio` from https://github.com/tokio-rs/tokio at `f00`.\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_regressions() {
+        let (_, res) = synthetic();
+        let regressions = render_regressions(&res, None, 10);
+        assert!(regressions.starts_with("\n## Regressions (4)\n"));
+        assert!(!regressions.contains("more"));
+
+        // Nothing is rendered without regressions
+        let none = TestResults {
+            crates: res
+                .crates
+                .into_iter()
+                .filter(|krate| krate.res != Comparison::Regressed)
+                .collect(),
+        };
+        assert_eq!(render_regressions(&none, None, 10), "");
+    }
+
+    #[test]
+    fn test_render_dependency_regressions() {
+        let (_, mut res) = synthetic();
+        for krate in &mut res.crates[1..3] {
+            krate.caused_by = Some("serde".into());
+        }
+
+        // Regressions caused by the same dependency count as a single root regression
+        let regressions = render_regressions(&res, None, 10);
+        assert!(regressions.starts_with("\n## Regressions (3 root regressions, 4 crates)\n"));
+        assert!(regressions.contains("\n### `build-fail:unknown` (1)\n"));
+        assert!(regressions.ends_with(concat!(
+            "\n### Caused by the dependency `serde` (2)\n",
+            "\n",
+            "* [beta-1.0.0](https://crates.io/crates/beta/1.0.0)\n",
+            "* [gamma-1.0.0](https://crates.io/crates/gamma/1.0.0)\n",
+        )));
+    }
+}
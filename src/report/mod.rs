@@ -1,8 +1,10 @@
-use crate::config::Config;
+use crate::config::{Config, KnownFailure};
 use crate::crates::{Crate, GitHubRepo};
-use crate::experiments::Experiment;
+use crate::experiments::{Experiment, FeatureSet};
 use crate::prelude::*;
-use crate::results::{ReadResults, TestResult};
+use crate::results::annotations::Annotation;
+use crate::results::{Phase, ReadResults, TestResult};
+use crate::runner;
 use crate::toolchain::Toolchain;
 use crate::utils;
 use mime::{self, Mime};
@@ -16,13 +18,27 @@ use std::fmt::{self, Display};
 use std::fs::{self, File};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use url::percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET};
 
 mod archives;
+pub mod diff;
 mod html;
+mod live;
+mod markdown;
 mod s3;
+mod summary;
 
+pub use self::archives::write_all_logs;
+pub use self::live::LiveReport;
+pub use self::markdown::{render_regressions, render_summary};
 pub use self::s3::{get_client_for_bucket, S3Prefix, S3Writer};
+pub use self::summary::ReportSummary;
+
+/// Version of the analysis and templates generating the reports, recorded in the summary of each
+/// report. It needs to be bumped when a change makes the new reports inconsistent with the ones
+/// generated before, so the outdated ones can be found and regenerated.
+pub const GENERATOR_VERSION: u32 = 1;
 
 url::define_encode_set! {
     pub REPORT_ENCODE_SET = [DEFAULT_ENCODE_SET] | { '+' }
@@ -44,6 +60,23 @@ pub struct CrateResult {
     url: String,
     pub res: Comparison,
     runs: [Option<BuildTestResult>; 2],
+    #[serde(default, skip_serializing_if = "FeatureSet::is_default")]
+    features: FeatureSet,
+    /// Change in the number of compiler warnings between the two toolchains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    warnings_diff: Option<i64>,
+    /// Change in the size of the build artifacts between the two toolchains, formatted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifacts_size_diff: Option<String>,
+    /// Dependency whose build failed, if the crate regressed only because of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    caused_by: Option<String>,
+    /// Annotation left while triaging the report, when it was generated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotation: Option<Annotation>,
+    /// Page of the server showing the results of the crate in the other experiments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history: Option<String>,
 }
 
 string_enum!(pub enum Comparison {
@@ -58,6 +91,7 @@ string_enum!(pub enum Comparison {
     SameTestPass => "test-pass",
     SpuriousRegressed => "spurious-regressed",
     SpuriousFixed => "spurious-fixed",
+    KnownFailure => "known-failure",
 });
 
 impl Comparison {
@@ -68,7 +102,8 @@ impl Comparison {
             | Comparison::Unknown
             | Comparison::Error
             | Comparison::SpuriousRegressed
-            | Comparison::SpuriousFixed => true,
+            | Comparison::SpuriousFixed
+            | Comparison::KnownFailure => true,
             Comparison::Skipped
             | Comparison::SameBuildFail
             | Comparison::SameTestFail
@@ -78,13 +113,122 @@ impl Comparison {
     }
 }
 
+/// Result matched by a classification rule: a full result like `build-fail:oom` only matches that
+/// failure reason, while `build-fail` matches all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultPattern(String);
+
+impl ResultPattern {
+    fn matches(&self, result: TestResult) -> bool {
+        let result = result.to_string();
+        result == self.0 || result.starts_with(&format!("{}:", self.0))
+    }
+}
+
+impl FromStr for ResultPattern {
+    type Err = ::failure::Error;
+
+    fn from_str(input: &str) -> Fallible<Self> {
+        TestResult::from_str(input)?;
+        Ok(ResultPattern(input.to_string()))
+    }
+}
+
+impl Display for ResultPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl_serde_from_parse!(ResultPattern, expecting = "a test result");
+
+/// Change of the number of compiler warnings between the two runs of a crate.
+string_enum!(pub enum WarningsPattern {
+    Introduced => "introduced",
+    Removed => "removed",
+    Unchanged => "unchanged",
+});
+
+impl WarningsPattern {
+    fn matches(self, before: u32, after: u32) -> bool {
+        match self {
+            WarningsPattern::Introduced => after > before,
+            WarningsPattern::Removed => after < before,
+            WarningsPattern::Unchanged => after == before,
+        }
+    }
+}
+
+/// Custom classification of a pair of results, checked before the built-in rules.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClassificationRule {
+    pub before: ResultPattern,
+    pub after: ResultPattern,
+    /// Only match if the number of warnings changed this way. Rules with it never match the
+    /// results whose warnings weren't counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<WarningsPattern>,
+    pub result: Comparison,
+}
+
+impl ClassificationRule {
+    fn new(before: &str, after: &str, result: Comparison) -> Self {
+        ClassificationRule {
+            before: ResultPattern(before.into()),
+            after: ResultPattern(after.into()),
+            warnings: None,
+            result,
+        }
+    }
+
+    /// Rules used when the config doesn't have any: the built-in rules can't compare a crate
+    /// whose tests were only skipped in one of the runs, for example because `skip-tests` was
+    /// changed in the meantime.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            ClassificationRule::new("test-pass", "test-skipped", Comparison::Skipped),
+            ClassificationRule::new("test-fail", "test-skipped", Comparison::Skipped),
+            ClassificationRule::new("test-skipped", "test-pass", Comparison::Skipped),
+            ClassificationRule::new("test-skipped", "test-fail", Comparison::Skipped),
+        ]
+    }
+
+    fn matches(&self, before: TestResult, after: TestResult, warnings: Option<(u32, u32)>) -> bool {
+        let warnings_match = match (self.warnings, warnings) {
+            (None, _) => true,
+            (Some(pattern), Some((before, after))) => pattern.matches(before, after),
+            (Some(_), None) => false,
+        };
+        warnings_match && self.before.matches(before) && self.after.matches(after)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct BuildTestResult {
     res: TestResult,
     log: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    warnings: Option<u32>,
+    /// Size in bytes of the build artifacts, if the experiment measured it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifacts_size: Option<u64>,
+    /// Results of the build and of the tests, if they were recorded separately. Their logs are
+    /// next to the one of the whole job.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    phases: Vec<PhaseRun>,
 }
 
-fn crate_to_path_fragment(toolchain: &Toolchain, krate: &Crate, encode: bool) -> PathBuf {
+#[derive(Serialize, Deserialize, Clone)]
+struct PhaseRun {
+    phase: Phase,
+    res: TestResult,
+}
+
+pub(crate) fn crate_to_path_fragment(
+    toolchain: &Toolchain,
+    krate: &Crate,
+    encode: bool,
+) -> PathBuf {
     let mut path = PathBuf::new();
     if encode {
         path.push(url_encode(&toolchain.to_string()));
@@ -122,52 +266,124 @@ fn crate_to_path_fragment(toolchain: &Toolchain, krate: &Crate, encode: bool) ->
     path
 }
 
+/// Compare the results of a single crate, once for each feature set of the experiment.
+fn crate_results<DB: ReadResults>(
+    db: &DB,
+    config: &Config,
+    ex: &Experiment,
+    krate: &Crate,
+    shas: &HashMap<GitHubRepo, String>,
+) -> Fallible<Vec<CrateResult>> {
+    let annotation = db.load_annotation(ex, krate)?;
+    let mut results = Vec::with_capacity(ex.feature_sets.len());
+    for features in &ex.feature_sets {
+        let toolchains = ex.toolchains_for(features);
+
+        // Any errors here will turn into unknown results
+        let crate_results = toolchains.iter().map(|tc| -> Fallible<BuildTestResult> {
+            let res = db
+                .load_test_result(ex, tc, krate)?
+                .ok_or_else(|| err_msg("no result"))?;
+
+            Ok(BuildTestResult {
+                res,
+                log: crate_to_path_fragment(tc, krate, true)
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+                warnings: db.load_warnings(ex, tc, krate)?,
+                artifacts_size: db.load_artifacts_size(ex, tc, krate)?,
+                phases: db
+                    .load_phases(ex, tc, krate)?
+                    .into_iter()
+                    .map(|(phase, res)| PhaseRun { phase, res })
+                    .collect(),
+            })
+        });
+        // Convert errors to Nones
+        let mut crate_results = crate_results.map(|r| r.ok()).collect::<Vec<_>>();
+        let crate2 = crate_results.pop().unwrap();
+        let crate1 = crate_results.pop().unwrap();
+        let comp = compare_known(
+            db,
+            config,
+            ex,
+            &toolchains,
+            krate,
+            crate1.as_ref().map(|b| b.res),
+            crate2.as_ref().map(|b| b.res),
+        )?;
+        let warnings_diff = match (
+            crate1.as_ref().and_then(|b| b.warnings),
+            crate2.as_ref().and_then(|b| b.warnings),
+        ) {
+            (Some(before), Some(after)) => Some(i64::from(after) - i64::from(before)),
+            _ => None,
+        };
+        let artifacts_size_diff = match (
+            crate1.as_ref().and_then(|b| b.artifacts_size),
+            crate2.as_ref().and_then(|b| b.artifacts_size),
+        ) {
+            (Some(before), Some(after)) if before != after => {
+                Some(utils::size::format_size_diff(after as i64 - before as i64))
+            }
+            _ => None,
+        };
+        // Regressions are grouped by the dependency that failed to build, if any
+        let caused_by = if comp == Comparison::Regressed {
+            failing_dependency(db, ex, &toolchains[1], krate)
+        } else {
+            None
+        };
+
+        results.push(CrateResult {
+            name: crate_to_name(krate, shas)?,
+            url: crate_to_url(krate, shas)?,
+            res: comp,
+            runs: [crate1, crate2],
+            features: features.clone(),
+            warnings_diff,
+            artifacts_size_diff,
+            caused_by,
+            annotation: annotation.clone(),
+            history: crate_to_history_url(config, krate),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Find the dependency that caused the build of the crate to fail, from its log. Missing logs are
+/// treated as the crate itself failing.
+fn failing_dependency<DB: ReadResults>(
+    db: &DB,
+    ex: &Experiment,
+    tc: &Toolchain,
+    krate: &Crate,
+) -> Option<String> {
+    match db.load_log(ex, tc, krate) {
+        Ok(Some(log)) => runner::failing_dependency(&String::from_utf8_lossy(&log)),
+        Ok(None) => None,
+        Err(err) => {
+            utils::report_failure(&err);
+            None
+        }
+    }
+}
+
 pub fn generate_report<DB: ReadResults>(
     db: &DB,
     config: &Config,
     ex: &Experiment,
 ) -> Fallible<TestResults> {
     let shas = db.load_all_shas(ex)?;
-    let res = ex
-        .crates
-        .clone()
-        .into_iter()
-        .map(|krate| {
-            // Any errors here will turn into unknown results
-            let crate_results = ex.toolchains.iter().map(|tc| -> Fallible<BuildTestResult> {
-                let res = db
-                    .load_test_result(ex, tc, &krate)?
-                    .ok_or_else(|| err_msg("no result"))?;
-
-                Ok(BuildTestResult {
-                    res,
-                    log: crate_to_path_fragment(tc, &krate, true)
-                        .to_str()
-                        .unwrap()
-                        .to_string(),
-                })
-            });
-            // Convert errors to Nones
-            let mut crate_results = crate_results.map(|r| r.ok()).collect::<Vec<_>>();
-            let crate2 = crate_results.pop().unwrap();
-            let crate1 = crate_results.pop().unwrap();
-            let comp = compare(
-                config,
-                &krate,
-                crate1.as_ref().map(|b| b.res),
-                crate2.as_ref().map(|b| b.res),
-            );
-
-            Ok(CrateResult {
-                name: crate_to_name(&krate, &shas)?,
-                url: crate_to_url(&krate, &shas)?,
-                res: comp,
-                runs: [crate1, crate2],
-            })
-        })
-        .collect::<Fallible<Vec<_>>>()?;
+    let mut crates = Vec::new();
+    for krate in &ex.crates {
+        // Each feature set is compared separately
+        crates.extend(crate_results(db, config, ex, krate, &shas)?);
+    }
 
-    Ok(TestResults { crates: res })
+    Ok(TestResults { crates })
 }
 
 const PROGRESS_FRACTION: usize = 10; // write progress every ~1/N crates
@@ -189,32 +405,58 @@ fn write_logs<DB: ReadResults, W: ReportWriter>(
             continue;
         }
 
-        for tc in &ex.toolchains {
-            let log_path = crate_to_path_fragment(tc, krate, false).join("log.txt");
-            let content = db
-                .load_log(ex, tc, krate)
-                .and_then(|c| c.ok_or_else(|| err_msg("missing logs")))
-                .with_context(|_| format!("failed to read log of {} on {}", krate, tc.to_string()));
-            let content = match content {
-                Ok(c) => c,
-                Err(e) => {
-                    utils::report_failure(&e);
-                    continue;
-                }
-            };
-            dest.write_bytes(log_path, content, &mime::TEXT_PLAIN_UTF_8)?;
+        for tc in &ex.build_toolchains() {
+            write_log(db, ex, tc, krate, dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the log of the crate on the toolchain, and the ones of its phases if they were recorded
+/// separately. Missing logs are reported but not fatal.
+fn write_log<DB: ReadResults, W: ReportWriter>(
+    db: &DB,
+    ex: &Experiment,
+    tc: &Toolchain,
+    krate: &Crate,
+    dest: &W,
+) -> Fallible<()> {
+    let dir = crate_to_path_fragment(tc, krate, false);
+    let content = db
+        .load_log(ex, tc, krate)
+        .and_then(|c| c.ok_or_else(|| err_msg("missing logs")))
+        .with_context(|_| format!("failed to read log of {} on {}", krate, tc.to_string()));
+    match content {
+        Ok(content) => dest.write_bytes(dir.join("log.txt"), content, &mime::TEXT_PLAIN_UTF_8)?,
+        Err(e) => {
+            utils::report_failure(&e);
+            return Ok(());
+        }
+    }
+
+    for (phase, _) in db.load_phases(ex, tc, krate)? {
+        if let Some(content) = db.load_phase_log(ex, tc, krate, phase)? {
+            dest.write_bytes(
+                dir.join(format!("{}.txt", phase)),
+                content,
+                &mime::TEXT_PLAIN_UTF_8,
+            )?;
         }
     }
     Ok(())
 }
 
+/// Generate and write the full report. The URL the report will be published at, if known, is
+/// used to link the logs from `summary.md`. The results are returned along with their summary.
 pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
     db: &DB,
     ex: &Experiment,
     dest: &W,
     config: &Config,
-) -> Fallible<TestResults> {
+    report_url: Option<&str>,
+) -> Fallible<(TestResults, ReportSummary)> {
     let res = generate_report(db, config, ex)?;
+    let summary = ReportSummary::new(ex, &res);
 
     info!("writing results to {}", dest);
     info!("writing metadata");
@@ -232,11 +474,27 @@ pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
     info!("writing archives");
     let available_archives = archives::write_logs_archives(db, ex, dest, config)?;
     info!("writing html files");
-    html::write_html_report(ex, &res, available_archives, dest)?;
+    let docker_image = db.load_docker_image(ex)?;
+    let environments = db.load_environments(ex)?;
+    html::write_html_report(
+        ex,
+        &res,
+        &summary,
+        docker_image.as_ref(),
+        &environments,
+        available_archives,
+        dest,
+    )?;
+    info!("writing markdown summary");
+    dest.write_string(
+        "summary.md",
+        markdown::render_summary(ex, &res, report_url, markdown::MAX_CRATES_PER_SECTION).into(),
+        &mime::TEXT_PLAIN_UTF_8,
+    )?;
     info!("writing logs");
     write_logs(db, ex, dest, config)?;
 
-    Ok(res)
+    Ok((res, summary))
 }
 
 fn crate_to_name(c: &Crate, shas: &HashMap<GitHubRepo, String>) -> Fallible<String> {
@@ -253,6 +511,16 @@ fn crate_to_name(c: &Crate, shas: &HashMap<GitHubRepo, String>) -> Fallible<Stri
     })
 }
 
+fn crate_to_history_url(config: &Config, c: &Crate) -> Option<String> {
+    config.server.public_url.as_ref().map(|url| {
+        format!(
+            "{}/crate/{}",
+            url.trim_end_matches('/'),
+            url_encode(&c.name())
+        )
+    })
+}
+
 fn crate_to_url(c: &Crate, shas: &HashMap<GitHubRepo, String>) -> Fallible<String> {
     Ok(match *c {
         Crate::Registry(ref details) => format!(
@@ -274,69 +542,147 @@ fn crate_to_url(c: &Crate, shas: &HashMap<GitHubRepo, String>) -> Fallible<Strin
     })
 }
 
-fn compare(
+pub(crate) fn compare(
     config: &Config,
     krate: &Crate,
     r1: Option<TestResult>,
     r2: Option<TestResult>,
 ) -> Comparison {
-    use crate::results::TestResult::*;
+    compare_with_warnings(config, krate, r1, r2, None)
+}
 
+/// Compare the results like `compare`, also applying the classification rules about the change
+/// of the number of warnings if they were counted in both runs.
+pub(crate) fn compare_with_warnings(
+    config: &Config,
+    krate: &Crate,
+    r1: Option<TestResult>,
+    r2: Option<TestResult>,
+    warnings: Option<(u32, u32)>,
+) -> Comparison {
     match (r1, r2) {
-        (Some(res1), Some(res2)) => match (res1, res2) {
-            (BuildFail(_), BuildFail(_)) => Comparison::SameBuildFail,
-            (TestFail(_), TestFail(_)) => Comparison::SameTestFail,
-            (TestSkipped, TestSkipped) => Comparison::SameTestSkipped,
-            (TestPass, TestPass) => Comparison::SameTestPass,
-
-            (BuildFail(reason1), TestFail(reason2))
-                if reason1.is_spurious() || reason2.is_spurious() =>
-            {
-                Comparison::SpuriousFixed
-            }
-            (BuildFail(reason), TestSkipped)
-            | (BuildFail(reason), TestPass)
-            | (TestFail(reason), TestPass)
-                if reason.is_spurious() =>
-            {
-                Comparison::SpuriousFixed
-            }
-            (BuildFail(_), TestFail(_))
-            | (BuildFail(_), TestSkipped)
-            | (BuildFail(_), TestPass)
-            | (TestFail(_), TestPass) => Comparison::Fixed,
-
-            (TestFail(reason1), BuildFail(reason2))
-                if reason1.is_spurious() || reason2.is_spurious() =>
-            {
-                Comparison::SpuriousRegressed
-            }
-            (TestPass, TestFail(reason))
-            | (TestPass, BuildFail(reason))
-            | (TestSkipped, BuildFail(reason))
-            | (TestFail(_), BuildFail(reason))
-                if reason.is_spurious() =>
-            {
-                Comparison::SpuriousRegressed
-            }
-            (TestPass, TestFail(_))
-            | (TestPass, BuildFail(_))
-            | (TestSkipped, BuildFail(_))
-            | (TestFail(_), BuildFail(_)) => Comparison::Regressed,
-
-            (Error, _) | (_, Error) => Comparison::Error,
-            (TestFail(_), TestSkipped)
-            | (TestPass, TestSkipped)
-            | (TestSkipped, TestFail(_))
-            | (TestSkipped, TestPass) => {
-                panic!("can't compare {} and {}", res1, res2);
-            }
-        },
+        (Some(res1), Some(res2)) => config
+            .server
+            .classification
+            .iter()
+            .find(|rule| rule.matches(res1, res2, warnings))
+            .map(|rule| rule.result)
+            .unwrap_or_else(|| compare_results(res1, res2)),
         _ if config.should_skip(krate) => Comparison::Skipped,
         _ => Comparison::Unknown,
     }
 }
 
+/// Known failure of the config matching the failure of the crate with the toolchain.
+pub(crate) fn known_failure<'a, DB: ReadResults>(
+    db: &DB,
+    config: &'a Config,
+    ex: &Experiment,
+    toolchain: &Toolchain,
+    krate: &Crate,
+) -> Fallible<Option<&'a KnownFailure>> {
+    // Avoid loading the diagnostics if there's nothing to match them with
+    if config.known_failures.is_empty() {
+        return Ok(None);
+    }
+    let diagnostics = db.load_toolchain_diagnostics(ex, toolchain, krate)?;
+    Ok(config.known_failure(krate, toolchain, &diagnostics))
+}
+
+/// Compare the results of the two toolchains of the comparison, applying the classification
+/// rules about warnings and classifying the regressions matching a known failure of the config as
+/// `KnownFailure`.
+pub(crate) fn compare_known<DB: ReadResults>(
+    db: &DB,
+    config: &Config,
+    ex: &Experiment,
+    toolchains: &[Toolchain; 2],
+    krate: &Crate,
+    r1: Option<TestResult>,
+    r2: Option<TestResult>,
+) -> Fallible<Comparison> {
+    // Avoid loading the warnings if no rule needs them
+    let warnings = if config
+        .server
+        .classification
+        .iter()
+        .any(|rule| rule.warnings.is_some())
+    {
+        match (
+            db.load_warnings(ex, &toolchains[0], krate)?,
+            db.load_warnings(ex, &toolchains[1], krate)?,
+        ) {
+            (Some(before), Some(after)) => Some((before, after)),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let comparison = compare_with_warnings(config, krate, r1, r2, warnings);
+    if comparison == Comparison::Regressed
+        && known_failure(db, config, ex, &toolchains[1], krate)?.is_some()
+    {
+        Ok(Comparison::KnownFailure)
+    } else {
+        Ok(comparison)
+    }
+}
+
+pub(crate) fn compare_results(res1: TestResult, res2: TestResult) -> Comparison {
+    use crate::results::TestResult::*;
+
+    match (res1, res2) {
+        (BuildFail(_), BuildFail(_)) => Comparison::SameBuildFail,
+        (TestFail(_), TestFail(_)) => Comparison::SameTestFail,
+        (TestSkipped, TestSkipped) => Comparison::SameTestSkipped,
+        (TestPass, TestPass) => Comparison::SameTestPass,
+
+        (BuildFail(reason1), TestFail(reason2))
+            if reason1.is_spurious() || reason2.is_spurious() =>
+        {
+            Comparison::SpuriousFixed
+        }
+        (BuildFail(reason), TestSkipped)
+        | (BuildFail(reason), TestPass)
+        | (TestFail(reason), TestPass)
+            if reason.is_spurious() =>
+        {
+            Comparison::SpuriousFixed
+        }
+        (BuildFail(_), TestFail(_))
+        | (BuildFail(_), TestSkipped)
+        | (BuildFail(_), TestPass)
+        | (TestFail(_), TestPass) => Comparison::Fixed,
+
+        (TestFail(reason1), BuildFail(reason2))
+            if reason1.is_spurious() || reason2.is_spurious() =>
+        {
+            Comparison::SpuriousRegressed
+        }
+        (TestPass, TestFail(reason))
+        | (TestPass, BuildFail(reason))
+        | (TestSkipped, BuildFail(reason))
+        | (TestFail(_), BuildFail(reason))
+            if reason.is_spurious() =>
+        {
+            Comparison::SpuriousRegressed
+        }
+        (TestPass, TestFail(_))
+        | (TestPass, BuildFail(_))
+        | (TestSkipped, BuildFail(_))
+        | (TestFail(_), BuildFail(_)) => Comparison::Regressed,
+
+        (Error, _) | (_, Error) => Comparison::Error,
+        (TestFail(_), TestSkipped)
+        | (TestPass, TestSkipped)
+        | (TestSkipped, TestFail(_))
+        | (TestSkipped, TestPass) => {
+            panic!("can't compare {} and {}", res1, res2);
+        }
+    }
+}
+
 pub trait ReportWriter {
     fn write_bytes<P: AsRef<Path>>(&self, path: P, b: Vec<u8>, mime: &Mime) -> Fallible<()>;
     fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()>;
@@ -442,7 +788,8 @@ mod tests {
     use crate::config::{Config, CrateConfig};
     use crate::crates::{Crate, GitHubRepo, RegistryCrate};
     use crate::experiments::{CapLints, Experiment, Mode, Status};
-    use crate::results::{DummyDB, FailureReason, TestResult};
+    use crate::results::annotations::AnnotationStatus;
+    use crate::results::{DummyDB, EnvironmentFingerprint, FailureReason, TestResult};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
     use std::collections::HashMap;
 
@@ -609,11 +956,113 @@ mod tests {
                 quiet: false,
                 update_lockfile: false,
                 broken: false,
+                setup: Vec::new(),
             },
         );
         assert_eq!(compare(&config, &reg, None, None), Comparison::Skipped);
     }
 
+    #[test]
+    fn test_compare_custom_rules() {
+        use crate::results::{FailureReason::*, TestResult::*};
+
+        let reg = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1.0".into(),
+        });
+
+        let mut config = Config::default();
+        config.server.classification = ::toml::from_str::<HashMap<String, Vec<_>>>(
+            r#"
+            rules = [
+                { before = "test-pass", after = "build-fail:oom", result = "regressed" },
+                { before = "build-fail", after = "test-pass", result = "test-pass" },
+            ]
+            "#,
+        )
+        .unwrap()
+        .remove("rules")
+        .unwrap();
+
+        let cmp = |r1, r2| compare(&config, &reg, Some(r1), Some(r2));
+
+        // Exact results only match that failure reason
+        assert_eq!(cmp(TestPass, BuildFail(OOM)), Comparison::Regressed);
+        assert_eq!(
+            cmp(TestPass, BuildFail(Timeout)),
+            Comparison::SpuriousRegressed
+        );
+
+        // Results without a reason match all of them
+        assert_eq!(cmp(BuildFail(Unknown), TestPass), Comparison::SameTestPass);
+        assert_eq!(cmp(BuildFail(OOM), TestPass), Comparison::SameTestPass);
+
+        // The built-in rules apply to the other results
+        assert_eq!(cmp(TestPass, TestFail(Unknown)), Comparison::Regressed);
+
+        assert!(ResultPattern::from_str("build-fail:oom").is_ok());
+    }
+
+    #[test]
+    fn test_compare_warnings_rules() {
+        use crate::results::TestResult::*;
+
+        let reg = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1.0".into(),
+        });
+
+        let mut config = Config::default();
+        config.server.classification = ::toml::from_str::<HashMap<String, Vec<_>>>(
+            r#"
+            [[rules]]
+            before = "test-pass"
+            after = "test-pass"
+            warnings = "introduced"
+            result = "regressed"
+            "#,
+        )
+        .unwrap()
+        .remove("rules")
+        .unwrap();
+
+        let cmp = |warnings| {
+            compare_with_warnings(&config, &reg, Some(TestPass), Some(TestPass), warnings)
+        };
+
+        // Introducing warnings is a regression
+        assert_eq!(cmp(Some((0, 3))), Comparison::Regressed);
+        assert_eq!(cmp(Some((1, 2))), Comparison::Regressed);
+
+        // Removing or keeping them isn't, and neither are the runs without counted warnings
+        assert_eq!(cmp(Some((3, 0))), Comparison::SameTestPass);
+        assert_eq!(cmp(Some((2, 2))), Comparison::SameTestPass);
+        assert_eq!(cmp(None), Comparison::SameTestPass);
+        assert_eq!(
+            compare(&config, &reg, Some(TestPass), Some(TestPass)),
+            Comparison::SameTestPass
+        );
+    }
+
+    #[test]
+    fn test_compare_default_rules() {
+        use crate::results::{FailureReason::*, TestResult::*};
+
+        let reg = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1.0".into(),
+        });
+        let config = Config::default();
+        let cmp = |r1, r2| compare(&config, &reg, Some(r1), Some(r2));
+
+        // Crates whose tests were only skipped in one of the runs can't be compared
+        assert_eq!(cmp(TestPass, TestSkipped), Comparison::Skipped);
+        assert_eq!(cmp(TestFail(Unknown), TestSkipped), Comparison::Skipped);
+        assert_eq!(cmp(TestSkipped, TestPass), Comparison::Skipped);
+        assert_eq!(cmp(TestSkipped, TestFail(OOM)), Comparison::Skipped);
+        assert_eq!(cmp(TestSkipped, TestSkipped), Comparison::SameTestSkipped);
+    }
+
     #[test]
     fn test_report_generation() {
         let config = Config::default();
@@ -639,10 +1088,32 @@ mod tests {
             assigned_to: None,
             report_url: None,
             ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
         };
 
         let mut db = DummyDB::default();
         db.add_dummy_sha(&ex, repo.clone(), "f00".to_string());
+        db.add_dummy_docker_image(&ex, "rustops/crates-build-env@sha256:0123".to_string());
+        for kernel in &["4.15.0-1023-aws", "4.18.0-1004-aws"] {
+            db.add_dummy_environment(
+                &ex,
+                EnvironmentFingerprint {
+                    docker_image: Some("rustops/crates-build-env@sha256:0123".into()),
+                    kernel: Some(kernel.to_string()),
+                    toolchains: Vec::new(),
+                },
+            );
+        }
         db.add_dummy_result(
             &ex,
             gh.clone(),
@@ -667,9 +1138,16 @@ mod tests {
             TEST_TOOLCHAIN.clone(),
             b"beta log".to_vec(),
         );
+        db.add_dummy_warnings(&ex, gh.clone(), MAIN_TOOLCHAIN.clone(), 2);
+        db.add_dummy_warnings(&ex, gh.clone(), TEST_TOOLCHAIN.clone(), 5);
+        db.add_dummy_artifacts_size(&ex, gh.clone(), MAIN_TOOLCHAIN.clone(), 2048);
+        db.add_dummy_artifacts_size(&ex, gh.clone(), TEST_TOOLCHAIN.clone(), 1024);
 
         let writer = DummyWriter::default();
-        gen(&db, &ex, &writer, &config).unwrap();
+        let report_url = "https://example.com/foo/index.html";
+        let (_, summary) = gen(&db, &ex, &writer, &config, Some(report_url)).unwrap();
+        assert_eq!(summary.count(Comparison::Regressed), 1);
+        assert_eq!(summary.results(), 1);
 
         assert_eq!(
             writer.get("config.json", &mime::APPLICATION_JSON),
@@ -680,6 +1158,39 @@ mod tests {
             &writer.get("stable/gh/brson.hello-rs/log.txt", &mime::TEXT_PLAIN_UTF_8),
             b"stable log"
         );
+
+        // The docker image used for the run is shown in the report
+        let index = writer.get("index.html", &mime::TEXT_HTML);
+        let index = String::from_utf8_lossy(&index);
+        assert!(index.contains("rustops/crates-build-env@sha256:0123"));
+
+        // Results produced in different environments are flagged
+        assert!(index.contains("4.15.0-1023-aws"));
+        assert!(index.contains("4.18.0-1004-aws"));
+        assert!(index.contains("different environments"));
+
+        // Crates are listed in the pages of their category and in the lookup pages
+        assert!(index.contains("categories/regressed-1.html"));
+        assert!(index.contains("crates/b.html"));
+        let category = writer.get("categories/regressed-1.html", &mime::TEXT_HTML);
+        let category = String::from_utf8_lossy(&category);
+        assert!(category.contains("brson.hello-rs.f00"));
+        assert!(category.contains("../beta/gh/brson.hello-rs/log.txt"));
+        // The rows can be filtered by the script of the report
+        assert!(category.contains(r#"data-name="brson.hello-rs.f00" data-category="regressed""#));
+        let lookup = writer.get("crates/b.html", &mime::TEXT_HTML);
+        assert!(String::from_utf8_lossy(&lookup).contains("brson.hello-rs.f00"));
+        let page: TestResults = serde_json::from_slice(
+            &writer.get("categories/regressed-1.json", &mime::APPLICATION_JSON),
+        )
+        .unwrap();
+        assert_eq!(page.crates.len(), 1);
+
+        // The markdown summary is published next to the HTML one
+        let markdown = writer.get("summary.md", &mime::TEXT_PLAIN_UTF_8);
+        let markdown = String::from_utf8_lossy(&markdown);
+        assert!(markdown.contains("### `build-fail:unknown` (1)"));
+        assert!(markdown.contains("https://example.com/foo/beta/gh/brson.hello-rs/log.txt"));
         assert_eq!(
             &writer.get("beta/gh/brson.hello-rs/log.txt", &mime::TEXT_PLAIN_UTF_8),
             b"beta log"
@@ -713,5 +1224,118 @@ mod tests {
             (&crate_result.runs[1]).as_ref().unwrap().log.as_str(),
             "beta/gh/brson.hello-rs"
         );
+        assert_eq!((&crate_result.runs[1]).as_ref().unwrap().warnings, Some(5));
+        assert_eq!(crate_result.warnings_diff, Some(3));
+        assert_eq!(
+            (&crate_result.runs[1]).as_ref().unwrap().artifacts_size,
+            Some(1024)
+        );
+        assert_eq!(
+            crate_result
+                .artifacts_size_diff
+                .as_ref()
+                .map(String::as_str),
+            Some("-1.0 KB")
+        );
+        assert_eq!(crate_result.caused_by, None);
+    }
+
+    #[test]
+    fn test_dependency_regressions() {
+        let config = Config::default();
+        let names = ["foo", "bar", "baz"];
+        let crates = names
+            .iter()
+            .map(|name| {
+                Crate::Registry(RegistryCrate {
+                    name: name.to_string(),
+                    version: "1.0.0".into(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let ex = Experiment {
+            name: "foo".to_string(),
+            crates: crates.clone(),
+            toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Forbid,
+            priority: 0,
+            created_at: ::chrono::Utc::now(),
+            started_at: None,
+            completed_at: None,
+            github_issue: None,
+            status: Status::GeneratingReport,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            fail_fast: false,
+            feature_sets: vec![FeatureSet::Default],
+            requested_by: None,
+            live_report: false,
+            cargo_patches: Vec::new(),
+            pre_analyze: false,
+            private: false,
+            private_readers: Vec::new(),
+            test_runner: None,
+            measure_artifacts: false,
+            silent: false,
+        };
+
+        // Only the first crate fails to build by itself, the others fail to build serde
+        let mut db = DummyDB::default();
+        for (i, (krate, name)) in crates.iter().zip(&names).enumerate() {
+            db.add_dummy_result(
+                &ex,
+                krate.clone(),
+                MAIN_TOOLCHAIN.clone(),
+                TestResult::TestPass,
+            );
+            db.add_dummy_result(
+                &ex,
+                krate.clone(),
+                TEST_TOOLCHAIN.clone(),
+                TestResult::BuildFail(FailureReason::Unknown),
+            );
+            let log = format!(
+                "[INFO] [stderr]    Compiling serde v1.0.89\n\
+                 [INFO] [stderr]    Compiling {} v1.0.0 (/opt/crater/workdir)\n\
+                 [INFO] [stderr] error: could not compile `{}`.\n",
+                name,
+                if i == 0 { name } else { "serde" }
+            );
+            db.add_dummy_log(&ex, krate.clone(), TEST_TOOLCHAIN.clone(), log.into());
+        }
+        let annotation = Annotation {
+            status: AnnotationStatus::Reported,
+            note: "filed upstream as serde#1497".into(),
+            author: "octocat".into(),
+            updated_at: ::chrono::Utc::now(),
+        };
+        db.add_dummy_annotation(&ex, crates[1].clone(), annotation.clone());
+
+        let writer = DummyWriter::default();
+        let (res, summary) = gen(&db, &ex, &writer, &config, None).unwrap();
+        let caused_by = res
+            .crates
+            .iter()
+            .map(|krate| krate.caused_by.as_ref().map(String::as_str))
+            .collect::<Vec<_>>();
+        assert_eq!(caused_by, vec![None, Some("serde"), Some("serde")]);
+        assert_eq!(summary.count(Comparison::Regressed), 3);
+        assert_eq!(summary.root_regressions(), 2);
+
+        // The root regressions are the headline of the report
+        let index = writer.get("index.html", &mime::TEXT_HTML);
+        assert!(String::from_utf8_lossy(&index).contains("2 root regressions"));
+        let category = writer.get("categories/regressed-1.html", &mime::TEXT_HTML);
+        assert!(String::from_utf8_lossy(&category).contains(r#"data-caused-by="serde""#));
+
+        // The annotations left while triaging are included in the crate rows
+        assert_eq!(res.crates[1].annotation, Some(annotation));
+        assert!(res.crates[0].annotation.is_none());
+        assert!(
+            String::from_utf8_lossy(&category).contains("reported: filed upstream as serde#1497")
+        );
     }
 }
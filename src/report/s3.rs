@@ -60,6 +60,7 @@ impl FromStr for S3Prefix {
 pub struct S3Writer {
     prefix: S3Prefix,
     client: Box<S3>,
+    public_read: bool,
 }
 
 pub fn get_client_for_bucket(bucket: &str) -> Fallible<Box<S3>> {
@@ -87,7 +88,18 @@ const S3RETRIES: u64 = 4;
 
 impl S3Writer {
     pub fn create(client: Box<S3>, prefix: S3Prefix) -> Fallible<S3Writer> {
-        Ok(S3Writer { prefix, client })
+        Ok(S3Writer {
+            prefix,
+            client,
+            public_read: true,
+        })
+    }
+
+    /// Whether the uploaded files are readable by anyone, which is needed unless the bucket
+    /// itself is public or the reports are served through something else.
+    pub fn public_read(mut self, public_read: bool) -> Self {
+        self.public_read = public_read;
+        self
     }
 }
 
@@ -96,7 +108,11 @@ impl ReportWriter for S3Writer {
         let mut retry = 0;
         loop {
             let req = PutObjectRequest {
-                acl: Some("public-read".into()),
+                acl: if self.public_read {
+                    Some("public-read".into())
+                } else {
+                    None
+                },
                 body: Some(s.clone().into()),
                 bucket: self.prefix.bucket.clone(),
                 key: self
@@ -158,8 +174,88 @@ impl Display for S3Writer {
 
 #[cfg(test)]
 mod tests {
-    use super::S3Prefix;
+    use super::{S3Prefix, S3Writer};
+    use crate::report::ReportWriter;
+    use futures::future::{self, FutureResult};
+    use http::StatusCode;
+    use rusoto_core::request::{DispatchSignedRequest, HttpDispatchError, HttpResponse};
+    use rusoto_core::signature::SignedRequest;
+    use rusoto_core::Region;
+    use rusoto_credential::StaticProvider;
+    use rusoto_s3::S3Client;
     use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Requests sent to the mocked S3 API: method, path and ACL header.
+    type Requests = Arc<Mutex<Vec<(String, String, Option<String>)>>>;
+
+    struct MockS3(Requests);
+
+    impl DispatchSignedRequest for MockS3 {
+        type Future = FutureResult<HttpResponse, HttpDispatchError>;
+
+        fn dispatch(&self, request: SignedRequest, _: Option<Duration>) -> Self::Future {
+            let acl = request
+                .headers()
+                .get("x-amz-acl")
+                .and_then(|values| values.first())
+                .map(|value| String::from_utf8_lossy(value).into_owned());
+            self.0.lock().unwrap().push((
+                request.method().to_string(),
+                request.path().to_string(),
+                acl,
+            ));
+            future::ok(HttpResponse {
+                status: StatusCode::OK,
+                body: Vec::new().into(),
+                headers: Default::default(),
+            })
+        }
+    }
+
+    fn mock_writer(requests: &Requests, public_read: bool) -> S3Writer {
+        // Any S3-compatible endpoint, like a MinIO server
+        let client = S3Client::new_with(
+            MockS3(requests.clone()),
+            StaticProvider::new_minimal("access".into(), "secret".into()),
+            Region::Custom {
+                name: "us-east-1".into(),
+                endpoint: "http://localhost:9000".into(),
+            },
+        );
+        S3Writer::create(Box::new(client), "s3://reports/ex".parse().unwrap())
+            .unwrap()
+            .public_read(public_read)
+    }
+
+    #[test]
+    fn test_s3_writer() {
+        let requests = Requests::default();
+        mock_writer(&requests, true)
+            .write_string("index.html", "report".into(), &mime::TEXT_HTML)
+            .unwrap();
+        mock_writer(&requests, false)
+            .write_string("gh/foo.bar/log.txt", "log".into(), &mime::TEXT_PLAIN)
+            .unwrap();
+
+        // Custom endpoints are addressed with the bucket in the path
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                (
+                    "PUT".to_string(),
+                    "/reports/ex/index.html".to_string(),
+                    Some("public-read".to_string())
+                ),
+                (
+                    "PUT".to_string(),
+                    "/reports/ex/gh/foo.bar/log.txt".to_string(),
+                    None
+                ),
+            ]
+        );
+    }
 
     #[test]
     fn test_parse_s3prefix() {
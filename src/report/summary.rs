@@ -0,0 +1,284 @@
+use crate::db::{Database, QueryUtils};
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{Comparison, CrateResult, TestResults, GENERATOR_VERSION};
+use crate::results::TestResult;
+use chrono::Utc;
+use std::collections::hash_map::{Entry, HashMap};
+use std::hash::Hash;
+use utoipa::ToSchema;
+
+/// Summary of the report of an experiment. The same summary is rendered in the report and stored
+/// in the database when the report is generated, to query the history of the experiments without
+/// loading all their results.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReportSummary {
+    /// Number of crates tested by the experiment.
+    pub crates: usize,
+    /// Number of results in each category. Crates tested with multiple feature sets are counted
+    /// once for each of them.
+    pub categories: HashMap<Comparison, u32>,
+    /// Number of regressions by the result of the second toolchain (like `build-fail:oom`), which
+    /// clusters the regressions likely to share the same cause.
+    pub regressions: HashMap<TestResult, u32>,
+    /// Number of regressions caused by each dependency failing to build, instead of the crate
+    /// itself.
+    #[serde(default)]
+    pub dependency_regressions: HashMap<String, u32>,
+    pub toolchains: Vec<String>,
+    /// How long running the experiment took, if it was run.
+    pub duration_secs: Option<i64>,
+    /// Where the report was published, once it's uploaded.
+    #[serde(default)]
+    pub report_url: Option<String>,
+    /// Version of the generator which produced the report, see `GENERATOR_VERSION`. Reports
+    /// generated before the version was recorded have version 0.
+    #[serde(default)]
+    pub generator_version: u32,
+}
+
+impl ReportSummary {
+    pub fn new(ex: &Experiment, res: &TestResults) -> Self {
+        let mut summary = ReportSummary::empty(ex);
+        for krate in &res.crates {
+            summary.add(krate);
+        }
+        summary
+    }
+
+    /// Summary of a report without results, to be filled with `add`.
+    pub fn empty(ex: &Experiment) -> Self {
+        let duration_secs = match (ex.started_at, ex.completed_at) {
+            (Some(started_at), Some(completed_at)) => {
+                Some((completed_at - started_at).num_seconds())
+            }
+            _ => None,
+        };
+
+        ReportSummary {
+            crates: ex.crates.len(),
+            categories: HashMap::new(),
+            regressions: HashMap::new(),
+            dependency_regressions: HashMap::new(),
+            toolchains: ex.toolchains.iter().map(|tc| tc.to_string()).collect(),
+            duration_secs,
+            report_url: None,
+            generator_version: GENERATOR_VERSION,
+        }
+    }
+
+    /// Count a result in the summary.
+    pub(super) fn add(&mut self, krate: &CrateResult) {
+        *self.categories.entry(krate.res).or_insert(0) += 1;
+        if let Some(res) = regression_cluster(krate) {
+            *self.regressions.entry(res).or_insert(0) += 1;
+        }
+        if let Some(ref dependency) = krate.caused_by {
+            *self
+                .dependency_regressions
+                .entry(dependency.clone())
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// Stop counting a result added before, for example because it was updated.
+    pub(super) fn remove(&mut self, krate: &CrateResult) {
+        decrement(&mut self.categories, krate.res);
+        if let Some(res) = regression_cluster(krate) {
+            decrement(&mut self.regressions, res);
+        }
+        if let Some(ref dependency) = krate.caused_by {
+            decrement(&mut self.dependency_regressions, dependency.clone());
+        }
+    }
+
+    pub fn count(&self, category: Comparison) -> u32 {
+        self.categories.get(&category).cloned().unwrap_or(0)
+    }
+
+    /// Number of distinct root causes of the regressions: the regressions of the crates
+    /// themselves, plus one for each dependency whose failure regressed other crates.
+    pub fn root_regressions(&self) -> u32 {
+        let caused_by_dependencies: u32 = self.dependency_regressions.values().sum();
+        self.count(Comparison::Regressed) - caused_by_dependencies
+            + self.dependency_regressions.len() as u32
+    }
+
+    /// Number of results in the report.
+    pub fn results(&self) -> u32 {
+        self.categories.values().sum()
+    }
+
+    /// Store the summary of the experiment, replacing the one of the previous report if it was
+    /// regenerated.
+    pub fn store(&self, db: &Database, ex: &str) -> Fallible<()> {
+        let summary = ::serde_json::to_string(self)?;
+        db.transaction(|t| {
+            t.execute(
+                "DELETE FROM report_summaries WHERE experiment = ?1;",
+                &[&ex],
+            )?;
+            t.execute(
+                "INSERT INTO report_summaries (experiment, summary, generated_at) \
+                 VALUES (?1, ?2, ?3);",
+                &[&ex, &summary, &Utc::now()],
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn load(db: &Database, ex: &str) -> Fallible<Option<Self>> {
+        let summary: Option<String> = db.get_row(
+            "SELECT summary FROM report_summaries WHERE experiment = ?1;",
+            &[&ex],
+            |row| row.get("summary"),
+        )?;
+        Ok(match summary {
+            Some(summary) => Some(::serde_json::from_str(&summary)?),
+            None => None,
+        })
+    }
+
+    /// Load the summaries of the `limit` most recently created experiments with a report.
+    pub fn recent(db: &Database, limit: u32) -> Fallible<Vec<(String, Self)>> {
+        let rows: Vec<(String, String)> = db.query(
+            "SELECT report_summaries.experiment, report_summaries.summary \
+             FROM report_summaries \
+             INNER JOIN experiments ON experiments.name = report_summaries.experiment \
+             ORDER BY experiments.created_at DESC LIMIT ?1;",
+            &[&limit],
+            |row| (row.get("experiment"), row.get("summary")),
+        )?;
+
+        rows.into_iter()
+            .map(|(name, summary)| Ok((name, ::serde_json::from_str(&summary)?)))
+            .collect()
+    }
+}
+
+/// Result of the second toolchain of a regression, used to cluster the regressions.
+pub(super) fn regression_cluster(krate: &CrateResult) -> Option<TestResult> {
+    match (krate.res, &krate.runs[1]) {
+        (Comparison::Regressed, Some(run)) => Some(run.res),
+        _ => None,
+    }
+}
+
+/// Decrement the counter of `key`, removing it once it reaches zero so the summary is the same
+/// as one computed from scratch.
+fn decrement<K: Eq + Hash>(counts: &mut HashMap<K, u32>, key: K) {
+    if let Entry::Occupied(mut entry) = counts.entry(key) {
+        *entry.get_mut() -= 1;
+        if *entry.get() == 0 {
+            entry.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReportSummary;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{Experiment, FeatureSet};
+    use crate::report::{BuildTestResult, Comparison, CrateResult, TestResults};
+    use crate::results::{FailureReason, TestResult};
+
+    fn result(res: Comparison) -> CrateResult {
+        CrateResult {
+            name: "foo-1.0.0".into(),
+            url: "https://crates.io/crates/foo/1.0.0".into(),
+            res,
+            runs: [None, None],
+            features: FeatureSet::Default,
+            warnings_diff: None,
+            artifacts_size_diff: None,
+            caused_by: None,
+            annotation: None,
+            history: None,
+        }
+    }
+
+    #[test]
+    fn test_store_summaries() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "first").unwrap().unwrap();
+
+        let mut oom = result(Comparison::Regressed);
+        oom.runs[1] = Some(BuildTestResult {
+            res: TestResult::BuildFail(FailureReason::OOM),
+            log: String::new(),
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+        });
+        let mut dependency = result(Comparison::Regressed);
+        dependency.caused_by = Some("serde".into());
+        let res = TestResults {
+            crates: vec![
+                oom,
+                result(Comparison::Regressed),
+                dependency.clone(),
+                dependency,
+                result(Comparison::Fixed),
+                result(Comparison::SameTestPass),
+            ],
+        };
+
+        let mut summary = ReportSummary::new(&ex, &res);
+        assert_eq!(summary.count(Comparison::Regressed), 4);
+        assert_eq!(summary.count(Comparison::Fixed), 1);
+        assert_eq!(summary.count(Comparison::Error), 0);
+        assert_eq!(summary.results(), 6);
+        // Both regressions caused by the same dependency have a single root
+        assert_eq!(summary.dependency_regressions["serde"], 2);
+        assert_eq!(summary.root_regressions(), 3);
+        assert_eq!(
+            summary.regressions[&TestResult::BuildFail(FailureReason::OOM)],
+            1
+        );
+        assert_eq!(summary.crates, ex.crates.len());
+        assert_eq!(summary.duration_secs, None);
+
+        // Updating a result keeps the summary the same as computing it from scratch
+        let mut updated = summary.clone();
+        updated.remove(&res.crates[0]);
+        assert!(updated.regressions.is_empty());
+        updated.add(&res.crates[0]);
+        assert_eq!(updated, summary);
+        updated.remove(&res.crates[2]);
+        updated.remove(&res.crates[3]);
+        assert!(updated.dependency_regressions.is_empty());
+        updated.add(&res.crates[2]);
+        updated.add(&res.crates[3]);
+        assert_eq!(updated, summary);
+
+        assert!(ReportSummary::load(&db, "first").unwrap().is_none());
+        summary.store(&db, "first").unwrap();
+        assert_eq!(
+            ReportSummary::load(&db, "first").unwrap(),
+            Some(summary.clone())
+        );
+
+        // Regenerating the report replaces the stored summary
+        summary.report_url = Some("https://example.com/first/index.html".into());
+        summary.store(&db, "first").unwrap();
+        assert_eq!(
+            ReportSummary::load(&db, "first").unwrap(),
+            Some(summary.clone())
+        );
+
+        // Only the experiments with a report are listed
+        let recent = ReportSummary::recent(&db, 10).unwrap();
+        assert_eq!(recent, vec![("first".to_string(), summary)]);
+        assert!(ReportSummary::recent(&db, 0).unwrap().is_empty());
+    }
+}
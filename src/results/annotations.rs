@@ -0,0 +1,217 @@
+use crate::crates::Crate;
+use crate::db::{Database, QueryUtils};
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+use serde_json;
+use utoipa::ToSchema;
+
+/// Maximum number of characters of the note of an annotation.
+pub const MAX_NOTE_LENGTH: usize = 1000;
+
+// How far the triage of a crate went, like "looked at it, it's spurious" or "it's a real
+// regression, and an issue was filed".
+string_enum!(pub enum AnnotationStatus {
+    Investigating => "investigating",
+    Spurious => "spurious",
+    Expected => "expected",
+    Reported => "reported",
+});
+
+/// Annotation left on a crate of an experiment while triaging its report. Each crate has at most
+/// one annotation: setting it again replaces it, recording who changed it last.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct Annotation {
+    pub status: AnnotationStatus,
+    /// Free-text note, for example the issue filed about the regression.
+    pub note: String,
+    pub author: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Annotation of a crate, as listed by the public API.
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrateAnnotation {
+    /// Crate ID, in the same format used by the public API.
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub status: AnnotationStatus,
+    pub note: String,
+    pub author: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Set the annotation of a crate, replacing the existing one. Concurrent edits are not detected:
+/// the last one wins.
+pub fn set_annotation(
+    db: &Database,
+    ex: &Experiment,
+    krate: &Crate,
+    status: AnnotationStatus,
+    note: &str,
+    author: &str,
+) -> Fallible<Annotation> {
+    let annotation = Annotation {
+        status,
+        note: note.to_string(),
+        author: author.to_string(),
+        updated_at: Utc::now(),
+    };
+
+    let serialized = serde_json::to_string(krate)?;
+    db.transaction(|t| {
+        t.execute(
+            "DELETE FROM annotations WHERE experiment = ?1 AND crate = ?2;",
+            &[&ex.name, &serialized],
+        )?;
+        t.execute(
+            "INSERT INTO annotations (experiment, crate, status, note, author, updated_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+            &[
+                &ex.name,
+                &serialized,
+                &annotation.status.to_str(),
+                &annotation.note,
+                &annotation.author,
+                &annotation.updated_at,
+            ],
+        )?;
+        Ok(())
+    })?;
+
+    Ok(annotation)
+}
+
+pub(super) fn load_annotation(
+    db: &Database,
+    ex: &Experiment,
+    krate: &Crate,
+) -> Fallible<Option<Annotation>> {
+    let row: Option<(String, String, String, DateTime<Utc>)> = db.get_row(
+        "SELECT status, note, author, updated_at FROM annotations \
+         WHERE experiment = ?1 AND crate = ?2;",
+        &[&ex.name, &serde_json::to_string(krate)?],
+        |row| {
+            (
+                row.get("status"),
+                row.get("note"),
+                row.get("author"),
+                row.get("updated_at"),
+            )
+        },
+    )?;
+
+    Ok(match row {
+        Some((status, note, author, updated_at)) => Some(Annotation {
+            status: status.parse()?,
+            note,
+            author,
+            updated_at,
+        }),
+        None => None,
+    })
+}
+
+/// Load all the annotations of the experiment, sorted by crate.
+pub fn load_annotations(db: &Database, ex: &Experiment) -> Fallible<Vec<CrateAnnotation>> {
+    let rows: Vec<(String, String, String, String, DateTime<Utc>)> = db.query(
+        "SELECT crate, status, note, author, updated_at FROM annotations \
+         WHERE experiment = ?1;",
+        &[&ex.name],
+        |row| {
+            (
+                row.get("crate"),
+                row.get("status"),
+                row.get("note"),
+                row.get("author"),
+                row.get("updated_at"),
+            )
+        },
+    )?;
+
+    let mut annotations = Vec::with_capacity(rows.len());
+    for (krate, status, note, author, updated_at) in rows {
+        let krate: Crate = serde_json::from_str(&krate)?;
+        annotations.push(CrateAnnotation {
+            krate: krate.id(),
+            status: status.parse()?,
+            note,
+            author,
+            updated_at,
+        });
+    }
+    annotations.sort_by(|a, b| a.krate.cmp(&b.krate));
+    Ok(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_annotations, set_annotation, AnnotationStatus};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment, DeleteExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::results::{DatabaseDB, ReadResults};
+
+    #[test]
+    fn test_annotations() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let (first, second) = (&ex.crates[0], &ex.crates[1]);
+        let results = DatabaseDB::new(&db);
+
+        assert!(load_annotations(&db, &ex).unwrap().is_empty());
+        assert!(results.load_annotation(&ex, first).unwrap().is_none());
+
+        set_annotation(&db, &ex, first, AnnotationStatus::Spurious, "", "alice").unwrap();
+        let stored = set_annotation(
+            &db,
+            &ex,
+            second,
+            AnnotationStatus::Reported,
+            "rust-lang/rust#12345",
+            "bob",
+        )
+        .unwrap();
+        assert_eq!(results.load_annotation(&ex, second).unwrap(), Some(stored));
+
+        // The last edit wins, and its author is recorded
+        let updated = set_annotation(
+            &db,
+            &ex,
+            first,
+            AnnotationStatus::Investigating,
+            "flaky test?",
+            "bob",
+        )
+        .unwrap();
+        let annotation = results.load_annotation(&ex, first).unwrap().unwrap();
+        assert_eq!(annotation, updated);
+        assert_eq!(annotation.author, "bob");
+
+        let mut expected = vec![first.id(), second.id()];
+        expected.sort();
+        let annotations = load_annotations(&db, &ex).unwrap();
+        assert_eq!(
+            annotations
+                .iter()
+                .map(|a| a.krate.clone())
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        // Annotations are removed with their experiment
+        DeleteExperiment {
+            name: "dummy".into(),
+        }
+        .apply(&ctx)
+        .unwrap();
+        assert!(load_annotations(&db, &ex).unwrap().is_empty());
+    }
+}
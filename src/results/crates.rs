@@ -0,0 +1,264 @@
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::db::{Database, QueryUtils};
+use crate::experiments::{Experiment, FeatureSet};
+use crate::prelude::*;
+use crate::report::{self, Comparison};
+use crate::results::export::crate_name_and_version;
+use crate::results::{DatabaseDB, ReadResults};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::types::ToSql;
+use serde_json;
+use utoipa::ToSchema;
+
+/// A crate is considered being tested if an agent recorded progress on it this recently.
+const RUNNING_CRATE_SECS: i64 = 5 * 60;
+
+string_enum!(pub enum CrateStatus {
+    Pending => "pending",
+    Running => "running",
+    Completed => "completed",
+});
+
+/// Progress of a single crate of an experiment, as returned by the public API.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrateProgress {
+    pub crate_name: String,
+    /// Version of crates.io crates, or commit of GitHub repositories.
+    pub version: Option<String>,
+    pub status: CrateStatus,
+    /// Comparison between the results of the two toolchains, once the crate is completed. Only
+    /// the first feature set of the experiment is compared.
+    pub outcome: Option<Comparison>,
+    /// When the last result of the crate was recorded.
+    pub tested_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CratesPage {
+    pub crates: Vec<CrateProgress>,
+    pub total: u32,
+    pub next_page: Option<u32>,
+}
+
+/// Load a page of the crates of the experiment sorted by crate, optionally only the ones with
+/// the provided status. Pages start from 1, and skipped crates are not listed.
+///
+/// Crates are completed once they have a result for each toolchain, and running while an agent
+/// recorded a result or a partial log for them in the last few minutes.
+pub fn load_crates_page(
+    db: &Database,
+    config: &Config,
+    ex: &Experiment,
+    status: Option<CrateStatus>,
+    page: u32,
+    per_page: u32,
+) -> Fallible<CratesPage> {
+    let crates = format!(
+        "SELECT crate, tested_at, \
+         CASE WHEN results >= ?2 THEN '{}' \
+         WHEN tested_at >= ?3 OR partial_at >= ?3 THEN '{}' \
+         ELSE '{}' END AS status \
+         FROM (SELECT experiment_crates.crate, \
+         (SELECT COUNT(*) FROM results \
+         WHERE results.experiment = ?1 AND results.crate = experiment_crates.crate) AS results, \
+         (SELECT MAX(recorded_at) FROM results \
+         WHERE results.experiment = ?1 AND results.crate = experiment_crates.crate) AS tested_at, \
+         (SELECT MAX(received_at) FROM partial_logs \
+         WHERE partial_logs.experiment = ?1 AND partial_logs.crate = experiment_crates.crate) \
+         AS partial_at \
+         FROM experiment_crates \
+         WHERE experiment_crates.experiment = ?1 AND experiment_crates.skipped = 0) AS progress",
+        CrateStatus::Completed.to_str(),
+        CrateStatus::Running.to_str(),
+        CrateStatus::Pending.to_str(),
+    );
+    // The status is one of the known ones, so it's safe to put it in the query
+    let filter = match status {
+        Some(status) => format!("WHERE status = '{}'", status.to_str()),
+        None => String::new(),
+    };
+
+    let builds_per_crate = ex.build_toolchains().len() as i64;
+    let running_since = Utc::now() - Duration::seconds(RUNNING_CRATE_SECS);
+
+    let total = db
+        .get_row(
+            &format!(
+                "SELECT COUNT(*) AS count FROM ({}) AS crates {};",
+                crates, filter
+            ),
+            &[&ex.name as &ToSql, &builds_per_crate, &running_since],
+            |row| row.get::<_, i64>("count") as u32,
+        )?
+        .unwrap_or(0);
+
+    let offset = page.saturating_sub(1).saturating_mul(per_page);
+    let rows: Vec<(String, Option<DateTime<Utc>>, String)> = db.query(
+        &format!(
+            "SELECT crate, tested_at, status FROM ({}) AS crates {} \
+             ORDER BY crate LIMIT ?4 OFFSET ?5;",
+            crates, filter
+        ),
+        &[
+            &ex.name as &ToSql,
+            &builds_per_crate,
+            &running_since,
+            &i64::from(per_page),
+            &i64::from(offset),
+        ],
+        |row| (row.get("crate"), row.get("tested_at"), row.get("status")),
+    )?;
+
+    let results_db = DatabaseDB::new(db);
+    let shas = results_db.load_all_shas(ex)?;
+    let toolchains = ex.toolchains_for(ex.feature_sets.first().unwrap_or(&FeatureSet::Default));
+
+    let mut crates = Vec::with_capacity(rows.len());
+    for (krate, tested_at, status) in rows {
+        let krate: Crate = serde_json::from_str(&krate)?;
+        let status: CrateStatus = status.parse()?;
+
+        let outcome = if status == CrateStatus::Completed {
+            Some(report::compare(
+                config,
+                &krate,
+                results_db.load_test_result(ex, &toolchains[0], &krate)?,
+                results_db.load_test_result(ex, &toolchains[1], &krate)?,
+            ))
+        } else {
+            None
+        };
+
+        let (crate_name, version) = crate_name_and_version(&krate, &shas);
+        crates.push(CrateProgress {
+            crate_name,
+            version,
+            status,
+            outcome,
+            tested_at,
+        });
+    }
+
+    Ok(CratesPage {
+        next_page: if offset.saturating_add(per_page) < total {
+            Some(page + 1)
+        } else {
+            None
+        },
+        crates,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_crates_page, CrateStatus};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::Crate;
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::Experiment;
+    use crate::report::Comparison;
+    use crate::results::db::TaskResult;
+    use crate::results::{DatabaseDB, PartialProgressData, ProgressData, TestResult};
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use base64;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_load_crates_page() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        assert!(ex.crates.len() >= 3);
+        let (completed, running, stale) = (&ex.crates[0], &ex.crates[1], &ex.crates[2]);
+
+        let results = DatabaseDB::new(&db);
+        let task = |krate: &Crate, toolchain| TaskResult {
+            krate: krate.clone(),
+            toolchain,
+            result: TestResult::TestPass,
+            log: base64::encode("ok"),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![
+                        task(completed, MAIN_TOOLCHAIN.clone()),
+                        task(completed, TEST_TOOLCHAIN.clone()),
+                        task(running, MAIN_TOOLCHAIN.clone()),
+                        task(stale, MAIN_TOOLCHAIN.clone()),
+                    ],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        results
+            .store_partial(
+                &ex,
+                &PartialProgressData {
+                    krate: running.clone(),
+                    toolchain: TEST_TOOLCHAIN.clone(),
+                    chunk: 0,
+                    log: base64::encode("Compiling"),
+                },
+                10,
+            )
+            .unwrap();
+        // The agent stopped working on this crate a while ago
+        db.execute(
+            "UPDATE results SET recorded_at = ?1 WHERE crate = ?2;",
+            &[
+                &(Utc::now() - Duration::minutes(10)),
+                &serde_json::to_string(stale).unwrap(),
+            ],
+        )
+        .unwrap();
+
+        let load = |status, page, per_page| {
+            load_crates_page(&db, &config, &ex, status, page, per_page).unwrap()
+        };
+
+        let all = load(None, 1, 1000);
+        assert_eq!(all.total as usize, ex.crates.len());
+        assert_eq!(all.next_page, None);
+
+        let page = load(Some(CrateStatus::Completed), 1, 10);
+        assert_eq!(page.total, 1);
+        assert_eq!(page.crates[0].status, CrateStatus::Completed);
+        assert_eq!(page.crates[0].outcome, Some(Comparison::SameTestPass));
+        assert!(page.crates[0].tested_at.is_some());
+
+        let page = load(Some(CrateStatus::Running), 1, 10);
+        assert_eq!(page.total, 1);
+        assert_eq!(page.crates[0].outcome, None);
+
+        // Pending crates include the ones with stale progress
+        let pending = load(Some(CrateStatus::Pending), 1, 1000);
+        assert_eq!(pending.total as usize, ex.crates.len() - 2);
+        assert!(pending
+            .crates
+            .iter()
+            .all(|krate| krate.status == CrateStatus::Pending && krate.outcome.is_none()));
+
+        let first = load(Some(CrateStatus::Pending), 1, 1);
+        assert_eq!(first.next_page, Some(2));
+        assert_eq!(first.crates, &pending.crates[..1]);
+    }
+}
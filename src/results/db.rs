@@ -1,79 +1,1105 @@
 use crate::config::Config;
 use crate::crates::{Crate, GitHubRepo};
 use crate::db::{Database, QueryUtils};
-use crate::experiments::Experiment;
+use crate::experiments::{Experiment, FeatureSet, Status};
 use crate::logs::{self, LogStorage};
 use crate::prelude::*;
-use crate::results::{DeleteResults, ReadResults, TestResult, WriteResults};
+use crate::report::{compare, compare_known, compare_results, Comparison};
+use crate::results::annotations::{self, Annotation};
+use crate::results::{
+    DeleteResults, Diagnostic, EnvironmentFingerprint, ErrorCategory, Phase, ReadResults,
+    TestResult, WriteResults,
+};
+use crate::runner::{classify_error, count_warnings, is_broken_toolchain_log, parse_diagnostics};
 use crate::toolchain::Toolchain;
+use crate::utils::duration_millis;
 use base64;
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use log::LevelFilter;
+use rusqlite::types::ToSql;
 use serde_json;
 use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct TaskResult {
     #[serde(rename = "crate")]
     pub krate: Crate,
     pub toolchain: Toolchain,
     pub result: TestResult,
+    /// Log of the job, encoded in base64.
+    #[schema(format = Byte)]
     pub log: String,
+    /// How long the job took, in milliseconds. Older agents don't send it.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// Number of compiler warnings in the log. Older agents don't send it.
+    #[serde(default)]
+    pub warnings: Option<u32>,
+    /// Size in bytes of the artifacts produced by the build, only measured for experiments with
+    /// `measure_artifacts` enabled.
+    #[serde(default)]
+    pub artifacts_size: Option<u64>,
+    /// Results of the phases of the job, only sent when `sandbox.record-phases` is enabled.
+    #[serde(default)]
+    pub phases: Vec<PhaseResult>,
+    /// Kind of error that made the job fail. Older agents don't send it, and the server
+    /// classifies the failure from the log instead.
+    #[serde(default)]
+    pub error_category: Option<ErrorCategory>,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PhaseResult {
+    pub phase: Phase,
+    pub result: TestResult,
+    /// Log of the phase, encoded in base64.
+    #[schema(format = Byte)]
+    pub log: String,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct ProgressData {
     pub results: Vec<TaskResult>,
     pub shas: Vec<(GitHubRepo, String)>,
+    /// Direct dependencies of the crates analyzed with `cargo metadata`.
+    #[serde(default)]
+    pub dependencies: Vec<(Crate, Vec<String>)>,
+    /// Edges of the resolved dependency trees of the crates analyzed with `cargo metadata`.
+    #[serde(default)]
+    pub dependency_trees: Vec<(Crate, Vec<(String, String)>)>,
+    #[serde(default)]
+    pub docker_image: Option<String>,
+    #[serde(default)]
+    pub environment: Option<EnvironmentFingerprint>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PartialProgressData {
+    #[serde(rename = "crate")]
+    pub krate: Crate,
+    pub toolchain: Toolchain,
+    pub chunk: usize,
+    /// Chunk of the log, encoded in base64.
+    #[schema(format = Byte)]
+    pub log: String,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrateHistoryEntry {
+    pub experiment: String,
+    pub experiment_created_at: DateTime<Utc>,
+    pub toolchain: String,
+    pub result: TestResult,
+}
+
+/// Diagnostics emitted while testing a crate with one of the toolchains of an experiment.
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrateDiagnostics {
+    pub toolchain: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Results of a crate in an experiment, compared between its two toolchains.
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrateRun {
+    pub experiment: String,
+    pub experiment_created_at: DateTime<Utc>,
+    /// ID of the version of the crate tested by the experiment.
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub toolchains: [String; 2],
+    pub results: [Option<TestResult>; 2],
+    pub category: Comparison,
+}
+
+/// Number of crates in each comparison category of an experiment. Crates missing the result of
+/// at least one toolchain are not counted.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq, ToSchema)]
+pub struct ResultsSummary {
+    pub regressed: u32,
+    pub fixed: u32,
+    pub spurious_regressed: u32,
+    pub spurious_fixed: u32,
+    pub build_fail: u32,
+    pub test_fail: u32,
+    pub test_skipped: u32,
+    pub test_pass: u32,
+    pub error: u32,
+}
+
+impl ResultsSummary {
+    /// Number of crates with the results of both toolchains.
+    pub fn total(&self) -> u32 {
+        self.regressed
+            + self.fixed
+            + self.spurious_regressed
+            + self.spurious_fixed
+            + self.build_fail
+            + self.test_fail
+            + self.test_skipped
+            + self.test_pass
+            + self.error
+    }
+}
+
+/// A crate classified differently by two experiments, for example regressed in the first one
+/// and fixed in the second.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChangedCrate {
+    #[serde(rename = "crate")]
+    pub krate: Crate,
+    #[serde(default, skip_serializing_if = "FeatureSet::is_default")]
+    pub features: FeatureSet,
+    pub first: Comparison,
+    pub second: Comparison,
 }
 
 pub struct DatabaseDB<'a> {
     db: &'a Database,
+    /// Sizes of the artifacts measured while building the crates, stored with their results.
+    artifacts_sizes: Mutex<HashMap<(Crate, Toolchain), u64>>,
+    /// Results and logs of the phases already completed, stored with the result of the job.
+    phases: Mutex<HashMap<(Crate, Toolchain), Vec<(Phase, TestResult, Vec<u8>)>>>,
 }
 
 impl<'a> DatabaseDB<'a> {
     pub fn new(db: &'a Database) -> Self {
-        DatabaseDB { db }
+        DatabaseDB {
+            db,
+            artifacts_sizes: Mutex::new(HashMap::new()),
+            phases: Mutex::new(HashMap::new()),
+        }
     }
 
     pub fn store(&self, ex: &Experiment, data: &ProgressData) -> Fallible<()> {
+        self.db.transaction(|t| {
+            for result in &data.results {
+                store_task_result(t, ex, result)?;
+            }
+            store_progress_metadata(t, ex, data)
+        })
+    }
+
+    /// Store each result in its own transaction, so an invalid result doesn't prevent the rest
+    /// of the batch from being stored. The outcomes are returned in the same order as the results.
+    pub fn store_each(&self, ex: &Experiment, data: &ProgressData) -> Fallible<Vec<Fallible<()>>> {
+        self.db
+            .transaction(|t| store_progress_metadata(t, ex, data))?;
+
+        let mut outcomes = Vec::with_capacity(data.results.len());
         for result in &data.results {
-            self.store_result(
-                ex,
-                &result.krate,
-                &result.toolchain,
-                result.result,
-                &base64::decode(&result.log).with_context(|_| "invalid base64 log provided")?,
-            )?;
+            match self.db.transaction(|t| store_task_result(t, ex, result)) {
+                Ok(()) => outcomes.push(Ok(())),
+                // The agent sends the whole batch again when the database is busy
+                Err(err) => {
+                    if crate::db::is_busy(&err) {
+                        return Err(err);
+                    }
+                    outcomes.push(Err(err));
+                }
+            }
+        }
+        Ok(outcomes)
+    }
+
+    pub fn store_partial(
+        &self,
+        ex: &Experiment,
+        data: &PartialProgressData,
+        max_chunks: usize,
+    ) -> Fallible<()> {
+        if data.chunk >= max_chunks {
+            bail!("too many partial log chunks for a single build");
         }
 
-        for &(ref repo, ref sha) in &data.shas {
-            self.record_sha(ex, repo, sha)?;
+        let krate = serde_json::to_string(&data.krate)?;
+        let toolchain = data.toolchain.to_string();
+
+        // Chunks arriving after the final result was recorded are stale
+        if self.db.exists(
+            "SELECT rowid FROM results \
+             WHERE experiment = ?1 AND crate = ?2 AND toolchain = ?3;",
+            &[&ex.name, &krate, &toolchain],
+        )? {
+            return Ok(());
         }
 
+        self.db.execute(
+            "INSERT INTO partial_logs (experiment, crate, toolchain, chunk, log, received_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+            &[
+                &ex.name,
+                &krate,
+                &toolchain,
+                &(data.chunk as i64),
+                &base64::decode(&data.log).with_context(|_| "invalid base64 log provided")?,
+                &Utc::now(),
+            ],
+        )?;
         Ok(())
     }
 
-    fn store_result(
+    pub fn load_partial_log(
         &self,
         ex: &Experiment,
-        krate: &Crate,
         toolchain: &Toolchain,
-        res: TestResult,
-        log: &[u8],
-    ) -> Fallible<()> {
-        self.db.execute(
-            "INSERT INTO results (experiment, crate, toolchain, result, log) \
-             VALUES (?1, ?2, ?3, ?4, ?5);",
+        krate: &Crate,
+    ) -> Fallible<Option<Vec<u8>>> {
+        let chunks: Vec<Vec<u8>> = self.db.query(
+            "SELECT log FROM partial_logs \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 \
+             ORDER BY chunk ASC;",
             &[
                 &ex.name,
-                &serde_json::to_string(krate)?,
                 &toolchain.to_string(),
-                &res.to_string(),
-                &log,
+                &serde_json::to_string(krate)?,
             ],
+            |row| row.get("log"),
+        )?;
+
+        if chunks.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(chunks.concat()))
+        }
+    }
+
+    /// Load the results of a crate across all the public experiments, sorted by the creation date
+    /// of the experiment they belong to.
+    pub fn crate_history(&self, krate: &Crate) -> Fallible<Vec<CrateHistoryEntry>> {
+        let rows: Vec<(String, DateTime<Utc>, String, String)> = self.db.query(
+            "SELECT results.experiment, experiments.created_at, results.toolchain, \
+             results.result FROM results \
+             INNER JOIN experiments ON experiments.name = results.experiment \
+             WHERE results.crate = ?1 AND experiments.private = 0 \
+             ORDER BY experiments.created_at ASC, results.experiment ASC, \
+             results.toolchain ASC;",
+            &[&serde_json::to_string(krate)?],
+            |row| {
+                (
+                    row.get("experiment"),
+                    row.get("created_at"),
+                    row.get("toolchain"),
+                    row.get("result"),
+                )
+            },
+        )?;
+
+        let mut history = Vec::with_capacity(rows.len());
+        for (experiment, experiment_created_at, toolchain, result) in rows {
+            history.push(CrateHistoryEntry {
+                experiment,
+                experiment_created_at,
+                toolchain,
+                result: result.parse()?,
+            });
+        }
+        Ok(history)
+    }
+
+    /// Load the diagnostics parsed from the logs of the crate, for each toolchain it has a result
+    /// with. Results recorded before the diagnostics were parsed have none.
+    pub fn load_diagnostics(
+        &self,
+        ex: &Experiment,
+        krate: &Crate,
+    ) -> Fallible<Vec<CrateDiagnostics>> {
+        let rows: Vec<(String, Option<String>)> = self.db.query(
+            "SELECT toolchain, diagnostics FROM results \
+             WHERE experiment = ?1 AND crate = ?2;",
+            &[&ex.name, &serde_json::to_string(krate)?],
+            |row| (row.get("toolchain"), row.get("diagnostics")),
+        )?;
+
+        let mut result = Vec::with_capacity(rows.len());
+        for (toolchain, diagnostics) in rows {
+            result.push(CrateDiagnostics {
+                toolchain,
+                diagnostics: match diagnostics {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => Vec::new(),
+                },
+            });
+        }
+        // Listed in the same order as the toolchains of the experiment
+        result.sort_by_key(|entry| {
+            ex.toolchains
+                .iter()
+                .position(|tc| tc.to_string() == entry.toolchain)
+        });
+        Ok(result)
+    }
+
+    /// Load the results of all the versions of a crate across the public experiments, newest
+    /// first, compared like in the reports. Crates are looked up by `Crate::name`.
+    pub fn crate_runs(&self, config: &Config, name: &str) -> Fallible<Vec<CrateRun>> {
+        let rows: Vec<(String, String, String, String)> = self.db.query(
+            "SELECT results.experiment, results.crate, results.toolchain, results.result \
+             FROM results \
+             INNER JOIN experiments ON experiments.name = results.experiment \
+             WHERE results.crate_name = ?1 AND experiments.private = 0 \
+             ORDER BY experiments.created_at DESC, results.experiment ASC;",
+            &[&name],
+            |row| {
+                (
+                    row.get("experiment"),
+                    row.get("crate"),
+                    row.get("toolchain"),
+                    row.get("result"),
+                )
+            },
+        )?;
+
+        // The rows of an experiment are next to each other
+        let mut runs = Vec::new();
+        let mut experiment: Option<(Experiment, HashMap<(String, String), TestResult>)> = None;
+        for (ex_name, krate, toolchain, result) in rows {
+            if experiment.as_ref().map(|(ex, _)| &ex.name) != Some(&ex_name) {
+                if let Some((ex, results)) = experiment.take() {
+                    runs.extend(compare_runs(config, &ex, &results)?);
+                }
+                let ex = Experiment::get(&self.db, &ex_name)?
+                    .ok_or_else(|| err_msg(format!("missing experiment {}", ex_name)))?;
+                experiment = Some((ex, HashMap::new()));
+            }
+            if let Some((_, ref mut results)) = experiment {
+                results.insert((krate, toolchain), result.parse()?);
+            }
+        }
+        if let Some((ex, results)) = experiment {
+            runs.extend(compare_runs(config, &ex, &results)?);
+        }
+        Ok(runs)
+    }
+
+    /// Find the crates whose classification differs between two experiments. Only the crates
+    /// with the results of both toolchains in both experiments are compared, separately for each
+    /// feature set tested by both experiments. The crates classified as known failures by either
+    /// experiment are only included if `include_known` is set.
+    pub fn changed_crates(
+        &self,
+        config: &Config,
+        first: &Experiment,
+        second: &Experiment,
+        include_known: bool,
+    ) -> Fallible<Vec<ChangedCrate>> {
+        let mut changed = Vec::new();
+        for features in &first.feature_sets {
+            if !second.feature_sets.contains(features) {
+                continue;
+            }
+            let first_tcs = first.toolchains_for(features);
+            let second_tcs = second.toolchains_for(features);
+
+            let rows: Vec<(String, String, String, String, String)> = self.db.query(
+                "SELECT a1.crate, a1.result AS first_start, a2.result AS first_end, \
+                 b1.result AS second_start, b2.result AS second_end \
+                 FROM results a1 \
+                 INNER JOIN results a2 \
+                 ON a2.experiment = a1.experiment AND a2.crate = a1.crate \
+                 INNER JOIN results b1 ON b1.crate = a1.crate \
+                 INNER JOIN results b2 \
+                 ON b2.experiment = b1.experiment AND b2.crate = b1.crate \
+                 WHERE a1.experiment = ?1 AND a1.toolchain = ?2 AND a2.toolchain = ?3 \
+                 AND b1.experiment = ?4 AND b1.toolchain = ?5 AND b2.toolchain = ?6 \
+                 ORDER BY a1.crate;",
+                &[
+                    &first.name,
+                    &first_tcs[0].to_string(),
+                    &first_tcs[1].to_string(),
+                    &second.name,
+                    &second_tcs[0].to_string(),
+                    &second_tcs[1].to_string(),
+                ],
+                |row| {
+                    (
+                        row.get("crate"),
+                        row.get("first_start"),
+                        row.get("first_end"),
+                        row.get("second_start"),
+                        row.get("second_end"),
+                    )
+                },
+            )?;
+
+            for (krate, first_start, first_end, second_start, second_end) in rows {
+                let krate: Crate = serde_json::from_str(&krate)?;
+                let first = compare_known(
+                    self,
+                    config,
+                    first,
+                    &first_tcs,
+                    &krate,
+                    Some(first_start.parse()?),
+                    Some(first_end.parse()?),
+                )?;
+                let second = compare_known(
+                    self,
+                    config,
+                    second,
+                    &second_tcs,
+                    &krate,
+                    Some(second_start.parse()?),
+                    Some(second_end.parse()?),
+                )?;
+                let known = first == Comparison::KnownFailure || second == Comparison::KnownFailure;
+                if first != second && (include_known || !known) {
+                    changed.push(ChangedCrate {
+                        krate,
+                        features: features.clone(),
+                        first,
+                        second,
+                    });
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    pub fn results_summary(&self, ex: &Experiment) -> Fallible<ResultsSummary> {
+        let mut rows: Vec<(String, String, u32)> = Vec::new();
+        for features in &ex.feature_sets {
+            let toolchains = ex.toolchains_for(features);
+            rows.extend(self.db.query(
+                "SELECT r1.result AS result1, r2.result AS result2, COUNT(*) AS count \
+                 FROM results r1 INNER JOIN results r2 \
+                 ON r2.experiment = r1.experiment AND r2.crate = r1.crate \
+                 WHERE r1.experiment = ?1 AND r1.toolchain = ?2 AND r2.toolchain = ?3 \
+                 GROUP BY r1.result, r2.result;",
+                &[
+                    &ex.name,
+                    &toolchains[0].to_string(),
+                    &toolchains[1].to_string(),
+                ],
+                |row| {
+                    (
+                        row.get("result1"),
+                        row.get("result2"),
+                        row.get::<_, i64>("count") as u32,
+                    )
+                },
+            )?);
+        }
+
+        let mut summary = ResultsSummary::default();
+        for (result1, result2, count) in rows {
+            let counter = match compare_results(result1.parse()?, result2.parse()?) {
+                Comparison::Regressed => &mut summary.regressed,
+                Comparison::Fixed => &mut summary.fixed,
+                Comparison::SpuriousRegressed => &mut summary.spurious_regressed,
+                Comparison::SpuriousFixed => &mut summary.spurious_fixed,
+                Comparison::SameBuildFail => &mut summary.build_fail,
+                Comparison::SameTestFail => &mut summary.test_fail,
+                Comparison::SameTestSkipped => &mut summary.test_skipped,
+                Comparison::SameTestPass => &mut summary.test_pass,
+                Comparison::Error => &mut summary.error,
+                // Known failures are only told apart from the regressions in the reports
+                Comparison::Skipped | Comparison::Unknown | Comparison::KnownFailure => continue,
+            };
+            *counter += count;
+        }
+        Ok(summary)
+    }
+
+    /// Check whether any of the provided crates regressed between the two toolchains.
+    pub fn has_regression<'a, I>(&self, ex: &Experiment, crates: I) -> Fallible<bool>
+    where
+        I: IntoIterator<Item = &'a Crate>,
+    {
+        for krate in crates {
+            for features in &ex.feature_sets {
+                let toolchains = ex.toolchains_for(features);
+                let start = self.load_test_result(ex, &toolchains[0], krate)?;
+                let end = self.load_test_result(ex, &toolchains[1], krate)?;
+                if let (Some(start), Some(end)) = (start, end) {
+                    if compare_results(start, end) == Comparison::Regressed {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Load the crates and toolchains of the results recorded after the result with the `since`
+    /// row id, along with their row id, in the order they were recorded.
+    pub fn results_since(
+        &self,
+        ex: &Experiment,
+        since: i64,
+    ) -> Fallible<Vec<(i64, Crate, Toolchain)>> {
+        let rows: Vec<(i64, String, String)> = self.db.query(
+            "SELECT rowid, crate, toolchain FROM results \
+             WHERE experiment = ?1 AND rowid > ?2 ORDER BY rowid;",
+            &[&ex.name, &since],
+            |row| (row.get("rowid"), row.get("crate"), row.get("toolchain")),
+        )?;
+
+        rows.into_iter()
+            .map(|(id, krate, toolchain)| {
+                Ok((id, serde_json::from_str(&krate)?, toolchain.parse()?))
+            })
+            .collect()
+    }
+
+    pub fn logs_stats(&self) -> Fallible<LogsStats> {
+        let (results, total_size) = self
+            .db
+            .get_row(
+                "SELECT COUNT(*) AS count, \
+                 COALESCE(SUM(LENGTH(COALESCE(log_blobs.log, results.log))), 0) AS size \
+                 FROM results LEFT JOIN log_blobs ON log_blobs.hash = results.log_hash;",
+                &[],
+                |row| (row.get::<_, i64>("count"), row.get::<_, i64>("size")),
+            )?
+            .unwrap_or((0, 0));
+
+        // Results stored before logs were deduplicated still contain their own log
+        let (blobs, blobs_size) = self
+            .db
+            .get_row(
+                "SELECT COUNT(*) AS count, COALESCE(SUM(LENGTH(log)), 0) AS size \
+                 FROM log_blobs;",
+                &[],
+                |row| (row.get::<_, i64>("count"), row.get::<_, i64>("size")),
+            )?
+            .unwrap_or((0, 0));
+        let (legacy, legacy_size) = self
+            .db
+            .get_row(
+                "SELECT COUNT(*) AS count, COALESCE(SUM(LENGTH(log)), 0) AS size \
+                 FROM results WHERE log_hash IS NULL;",
+                &[],
+                |row| (row.get::<_, i64>("count"), row.get::<_, i64>("size")),
+            )?
+            .unwrap_or((0, 0));
+
+        Ok(LogsStats {
+            results: results as u64,
+            unique_logs: (blobs + legacy) as u64,
+            total_size: total_size as u64,
+            stored_size: (blobs_size + legacy_size) as u64,
+        })
+    }
+
+    /// Storage used by the logs of each experiment, the largest first. Results sharing the same log
+    /// are counted once in the stored size, but each experiment storing a log is charged for it.
+    pub fn usage(&self) -> Fallible<Vec<ExperimentUsage>> {
+        let stored_sizes = self
+            .db
+            .query(
+                "SELECT experiment, CAST(SUM(stored_log_size) AS BIGINT) AS size FROM ( \
+                 SELECT DISTINCT experiment, log_hash, stored_log_size FROM results \
+                 WHERE log_hash IS NOT NULL \
+                 UNION ALL \
+                 SELECT experiment, log_hash, stored_log_size FROM results \
+                 WHERE log_hash IS NULL \
+                 ) AS stored GROUP BY experiment;",
+                &[],
+                |row| {
+                    (
+                        row.get::<_, String>("experiment"),
+                        row.get::<_, i64>("size"),
+                    )
+                },
+            )?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let total_stored_size = self
+            .db
+            .get_row(
+                "SELECT COALESCE(CAST(SUM(stored_log_size) AS BIGINT), 0) AS size FROM ( \
+                 SELECT DISTINCT log_hash, stored_log_size FROM results \
+                 WHERE log_hash IS NOT NULL \
+                 UNION ALL \
+                 SELECT log_hash, stored_log_size FROM results WHERE log_hash IS NULL \
+                 ) AS stored;",
+                &[],
+                |row| row.get::<_, i64>("size"),
+            )?
+            .unwrap_or(0);
+
+        let mut usage = self.db.query(
+            "SELECT experiment, COUNT(*) AS count, CAST(SUM(log_size) AS BIGINT) AS size, \
+             CAST(SUM(stored_log_size) AS BIGINT) AS compressed_size \
+             FROM results GROUP BY experiment;",
+            &[],
+            |row| {
+                let experiment: String = row.get("experiment");
+                let stored_size = stored_sizes.get(&experiment).cloned().unwrap_or(0);
+                ExperimentUsage {
+                    results: row.get::<_, i64>("count") as u64,
+                    raw_size: row.get::<_, i64>("size") as u64,
+                    compressed_size: row.get::<_, i64>("compressed_size") as u64,
+                    stored_size: stored_size as u64,
+                    share: if total_stored_size > 0 {
+                        stored_size as f64 / total_stored_size as f64
+                    } else {
+                        0.0
+                    },
+                    experiment,
+                }
+            },
+        )?;
+        usage.sort_by(|a, b| b.stored_size.cmp(&a.stored_size));
+
+        Ok(usage)
+    }
+
+    /// Delete the logs of a completed experiment, keeping its results. Returns the number of
+    /// results whose log was removed.
+    pub fn purge_logs(&self, ex: &Experiment) -> Fallible<usize> {
+        self.db.transaction(|t| {
+            // The status is loaded again to avoid racing with the reports generator
+            let status: Status = t
+                .get_row(
+                    "SELECT status FROM experiments WHERE name = ?1;",
+                    &[&ex.name],
+                    |r| r.get::<_, String>("status"),
+                )?
+                .ok_or_else(|| err_msg("the experiment doesn't exist anymore"))?
+                .parse()?;
+            if status != Status::Completed {
+                bail!("only the logs of completed experiments can be purged");
+            }
+
+            let hashes = t.query(
+                "SELECT DISTINCT log_hash FROM results \
+                 WHERE experiment = ?1 AND log_hash IS NOT NULL;",
+                &[&ex.name],
+                |row| row.get::<_, String>("log_hash"),
+            )?;
+            let purged = t.execute(
+                "UPDATE results SET log = X'', log_hash = NULL, log_size = 0, stored_log_size = 0 \
+                 WHERE experiment = ?1 AND log_size > 0;",
+                &[&ex.name],
+            )?;
+
+            // Logs shared with other experiments are kept
+            for hash in &hashes {
+                t.execute(
+                    "DELETE FROM log_blobs WHERE hash = ?1 \
+                     AND NOT EXISTS (SELECT 1 FROM results WHERE log_hash = ?1);",
+                    &[hash],
+                )?;
+            }
+
+            Ok(purged)
+        })
+    }
+
+    /// Whether the logs of the experiment were purged with `purge_logs`. All the other results
+    /// have their log stored in the blobs table, even when it's empty.
+    pub fn logs_purged(&self, ex: &Experiment) -> Fallible<bool> {
+        Ok(self.db.exists(
+            "SELECT crate FROM results WHERE experiment = ?1 AND log_hash IS NULL;",
+            &[&ex.name],
+        )?)
+    }
+
+    /// Delete the failed results of a toolchain whose log shows the toolchain itself was broken,
+    /// so the crates are tested again. Returns the number of deleted results.
+    pub fn invalidate_broken_toolchain_results(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+    ) -> Fallible<usize> {
+        let rows: Vec<(String, Vec<u8>, Option<String>)> = self.db.query(
+            "SELECT crate, COALESCE(log_blobs.log, results.log) AS log, log_blobs.compression \
+             FROM results \
+             LEFT JOIN log_blobs ON log_blobs.hash = results.log_hash \
+             WHERE experiment = ?1 AND toolchain = ?2 AND result != 'test-pass';",
+            &[&ex.name, &toolchain.to_string()],
+            |row| (row.get("crate"), row.get("log"), row.get("compression")),
+        )?;
+
+        let mut invalidated = 0;
+        for (krate, log, compression) in rows {
+            let log = decompress_log(log, compression)?;
+            if !is_broken_toolchain_log(&String::from_utf8_lossy(&log)) {
+                continue;
+            }
+            self.db.execute(
+                "DELETE FROM results WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+                &[&ex.name, &toolchain.to_string(), &krate],
+            )?;
+            invalidated += 1;
+        }
+        Ok(invalidated)
+    }
+
+    pub fn in_progress_builds(&self, ex: &Experiment) -> Fallible<Vec<(Crate, Toolchain)>> {
+        let rows: Vec<(String, String)> = self.db.query(
+            "SELECT DISTINCT crate, toolchain FROM partial_logs \
+             WHERE experiment = ?1 ORDER BY crate, toolchain;",
+            &[&ex.name],
+            |row| (row.get("crate"), row.get("toolchain")),
+        )?;
+
+        let mut builds = Vec::new();
+        for (krate, toolchain) in rows {
+            builds.push((serde_json::from_str(&krate)?, toolchain.parse()?));
+        }
+        Ok(builds)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LogsStats {
+    pub results: u64,
+    pub unique_logs: u64,
+    /// Size of the logs of all the results, as if they weren't deduplicated.
+    pub total_size: u64,
+    /// Size of the logs actually stored in the database.
+    pub stored_size: u64,
+}
+
+impl LogsStats {
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.stored_size == 0 {
+            1.0
+        } else {
+            self.total_size as f64 / self.stored_size as f64
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExperimentUsage {
+    pub experiment: String,
+    pub results: u64,
+    /// Size of the logs of the results, before they're compressed and deduplicated.
+    pub raw_size: u64,
+    /// Size of the compressed logs of the results, as if they weren't deduplicated.
+    pub compressed_size: u64,
+    /// Size of the compressed logs actually stored for the experiment.
+    pub stored_size: u64,
+    /// Fraction of all the stored logs used by the experiment.
+    pub share: f64,
+}
+
+/// Identify the content of a log, to store identical logs only once.
+pub(crate) fn log_hash(log: &[u8]) -> String {
+    crate::utils::hex::to_hex(ring::digest::digest(&ring::digest::SHA256, log).as_ref())
+}
+
+// Codecs the logs in the `log_blobs` table can be compressed with. Logs stored before they were
+// compressed, or that would be larger once compressed, have no codec.
+string_enum!(enum LogCompression {
+    Gzip => "gzip",
+});
+
+/// Compress a log before storing it, returning the codec used if compressing it saved space.
+fn compress_log(log: &[u8]) -> Fallible<(Vec<u8>, Option<LogCompression>)> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(log)?;
+    let compressed = encoder.finish()?;
+    if compressed.len() < log.len() {
+        Ok((compressed, Some(LogCompression::Gzip)))
+    } else {
+        Ok((log.to_vec(), None))
+    }
+}
+
+/// Read back a stored log, decompressing it with the codec recorded next to it.
+fn decompress_log(log: Vec<u8>, compression: Option<String>) -> Fallible<Vec<u8>> {
+    let compression = match compression {
+        Some(compression) => compression.parse()?,
+        None => return Ok(log),
+    };
+    match compression {
+        LogCompression::Gzip => {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(log.as_slice())
+                .read_to_end(&mut decompressed)
+                .with_context(|_| "failed to decompress a stored log")?;
+            Ok(decompressed)
+        }
+    }
+}
+
+fn store_result<D: QueryUtils>(
+    db: &D,
+    ex: &Experiment,
+    krate: &Crate,
+    toolchain: &Toolchain,
+    res: TestResult,
+    log: &[u8],
+    duration: Option<Duration>,
+    warnings: Option<u32>,
+    artifacts_size: Option<u64>,
+    error_category: Option<ErrorCategory>,
+) -> Fallible<()> {
+    let name = krate.name();
+    let krate = serde_json::to_string(krate)?;
+    let toolchain = toolchain.to_string();
+    // Identical logs are deduplicated by their content before compressing them
+    let hash = log_hash(log);
+    let (stored_log, compression) = compress_log(log)?;
+    let diagnostics = parse_diagnostics(&String::from_utf8_lossy(log));
+    let diagnostics = if diagnostics.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&diagnostics)?)
+    };
+
+    // Replacing the old result wouldn't remove its log blob if it's not used anymore, while the
+    // trigger on the table does that when the result is deleted
+    db.execute(
+        "DELETE FROM results WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+        &[&ex.name, &toolchain, &krate],
+    )?;
+    db.execute(
+        "INSERT OR IGNORE INTO log_blobs (hash, log, compression) VALUES (?1, ?2, ?3);",
+        &[
+            &hash as &ToSql,
+            &stored_log,
+            &compression.map(|compression| compression.to_str()),
+        ],
+    )?;
+    db.execute(
+        "INSERT INTO results \
+         (experiment, crate, toolchain, result, log, log_hash, recorded_at, duration_ms, \
+         warnings, log_size, stored_log_size, crate_name, artifacts_size, error_category, \
+         diagnostics) \
+         VALUES (?1, ?2, ?3, ?4, X'', ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);",
+        &[
+            &ex.name,
+            &krate,
+            &toolchain,
+            &res.to_string(),
+            &hash,
+            &Utc::now(),
+            &duration.map(|d| duration_millis(d) as i64),
+            &warnings,
+            &(log.len() as i64),
+            &(stored_log.len() as i64),
+            &name,
+            &artifacts_size.map(|size| size as i64),
+            &error_category.map(|category| category.to_str()),
+            &diagnostics,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Store the result and the log of a phase of the job. It must be stored after the result of the
+/// whole job, as replacing that result deletes its phases.
+fn store_phase<D: QueryUtils>(
+    db: &D,
+    ex: &Experiment,
+    krate: &Crate,
+    toolchain: &Toolchain,
+    phase: Phase,
+    res: TestResult,
+    log: &[u8],
+) -> Fallible<()> {
+    let (stored_log, compression) = compress_log(log)?;
+    db.execute(
+        "INSERT INTO result_phases \
+         (experiment, crate, toolchain, phase, result, log, compression) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+        &[
+            &ex.name as &ToSql,
+            &serde_json::to_string(krate)?,
+            &toolchain.to_string(),
+            &phase.to_str(),
+            &res.to_string(),
+            &stored_log,
+            &compression.map(|compression| compression.to_str()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Compare the results of each crate in the experiment, for each feature set with at least one
+/// result. The results are keyed by the serialized crate and the toolchain.
+fn compare_runs(
+    config: &Config,
+    ex: &Experiment,
+    results: &HashMap<(String, String), TestResult>,
+) -> Fallible<Vec<CrateRun>> {
+    let mut crates = results.keys().map(|(krate, _)| krate).collect::<Vec<_>>();
+    crates.sort();
+    crates.dedup();
+
+    let mut runs = Vec::new();
+    for krate in crates {
+        let parsed: Crate = serde_json::from_str(krate)?;
+        for features in &ex.feature_sets {
+            let toolchains = ex.toolchains_for(features);
+            let toolchains = [toolchains[0].to_string(), toolchains[1].to_string()];
+            let result = |tc: &String| results.get(&(krate.clone(), tc.clone())).cloned();
+            let crate_results = [result(&toolchains[0]), result(&toolchains[1])];
+            if crate_results.iter().all(Option::is_none) {
+                continue;
+            }
+
+            runs.push(CrateRun {
+                experiment: ex.name.clone(),
+                experiment_created_at: ex.created_at,
+                krate: parsed.id(),
+                category: compare(config, &parsed, crate_results[0], crate_results[1]),
+                toolchains,
+                results: crate_results,
+            });
+        }
+    }
+    Ok(runs)
+}
+
+fn store_task_result<D: QueryUtils>(db: &D, ex: &Experiment, result: &TaskResult) -> Fallible<()> {
+    let log = base64::decode(&result.log).with_context(|_| "invalid base64 log provided")?;
+    let error_category = result
+        .error_category
+        .or_else(|| classify_error(result.result, &String::from_utf8_lossy(&log)));
+    store_result(
+        db,
+        ex,
+        &result.krate,
+        &result.toolchain,
+        result.result,
+        &log,
+        result.duration_ms.map(Duration::from_millis),
+        result.warnings,
+        result.artifacts_size,
+        error_category,
+    )?;
+    for phase in &result.phases {
+        store_phase(
+            db,
+            ex,
+            &result.krate,
+            &result.toolchain,
+            phase.phase,
+            phase.result,
+            &base64::decode(&phase.log).with_context(|_| "invalid base64 phase log provided")?,
+        )?;
+    }
+
+    // The final log replaces the chunks streamed while the crate was building
+    db.execute(
+        "DELETE FROM partial_logs WHERE experiment = ?1 AND crate = ?2 AND toolchain = ?3;",
+        &[
+            &ex.name,
+            &serde_json::to_string(&result.krate)?,
+            &result.toolchain.to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Store the information about the environment sent along with the results.
+fn store_progress_metadata<D: QueryUtils>(
+    db: &D,
+    ex: &Experiment,
+    data: &ProgressData,
+) -> Fallible<()> {
+    for &(ref repo, ref sha) in &data.shas {
+        store_sha(db, ex, repo, sha)?;
+    }
+
+    for &(ref krate, ref dependencies) in &data.dependencies {
+        store_dependencies(db, ex, krate, dependencies)?;
+    }
+
+    for &(ref krate, ref edges) in &data.dependency_trees {
+        store_dependency_tree(db, ex, krate, edges)?;
+    }
+
+    if let Some(ref digest) = data.docker_image {
+        store_docker_image(db, ex, digest)?;
+    }
+
+    if let Some(ref env) = data.environment {
+        store_environment(db, ex, env)?;
+    }
+
+    Ok(())
+}
+
+fn store_sha<D: QueryUtils>(db: &D, ex: &Experiment, repo: &GitHubRepo, sha: &str) -> Fallible<()> {
+    db.execute(
+        "INSERT OR IGNORE INTO shas (experiment, org, name, sha) VALUES (?1, ?2, ?3, ?4)",
+        &[&ex.name, &repo.org, &repo.name, &sha],
+    )?;
+    Ok(())
+}
+
+/// Store the direct dependencies of a crate, replacing the ones recorded when it was prepared
+/// before.
+fn store_dependencies<D: QueryUtils>(
+    db: &D,
+    ex: &Experiment,
+    krate: &Crate,
+    dependencies: &[String],
+) -> Fallible<()> {
+    let krate = serde_json::to_string(krate)?;
+    db.execute(
+        "DELETE FROM crate_dependencies WHERE experiment = ?1 AND crate = ?2;",
+        &[&ex.name, &krate],
+    )?;
+    for dependency in dependencies {
+        db.execute(
+            "INSERT OR IGNORE INTO crate_dependencies (experiment, crate, dependency) \
+             VALUES (?1, ?2, ?3);",
+            &[&ex.name, &krate, dependency],
+        )?;
+    }
+    Ok(())
+}
+
+/// Store the edges of the resolved dependency tree of a crate, replacing the ones recorded when it
+/// was prepared before.
+fn store_dependency_tree<D: QueryUtils>(
+    db: &D,
+    ex: &Experiment,
+    krate: &Crate,
+    edges: &[(String, String)],
+) -> Fallible<()> {
+    let krate = serde_json::to_string(krate)?;
+    db.execute(
+        "DELETE FROM dependency_edges WHERE experiment = ?1 AND crate = ?2;",
+        &[&ex.name, &krate],
+    )?;
+    for &(ref package, ref dependency) in edges {
+        db.execute(
+            "INSERT OR IGNORE INTO dependency_edges (experiment, crate, package, dependency) \
+             VALUES (?1, ?2, ?3, ?4);",
+            &[&ex.name, &krate, package, dependency],
         )?;
-        Ok(())
     }
+    Ok(())
+}
+
+fn store_environment<D: QueryUtils>(
+    db: &D,
+    ex: &Experiment,
+    env: &EnvironmentFingerprint,
+) -> Fallible<()> {
+    db.execute(
+        "INSERT INTO environments (experiment, fingerprint) VALUES (?1, ?2);",
+        &[&ex.name, &serde_json::to_string(env)?],
+    )?;
+    Ok(())
+}
+
+fn store_docker_image<D: QueryUtils>(db: &D, ex: &Experiment, digest: &str) -> Fallible<()> {
+    db.execute(
+        "UPDATE experiments SET docker_image = ?1 WHERE name = ?2;",
+        &[&digest, &ex.name],
+    )?;
+    Ok(())
 }
 
 impl<'a> ReadResults for DatabaseDB<'a> {
@@ -97,14 +1123,42 @@ impl<'a> ReadResults for DatabaseDB<'a> {
             .collect())
     }
 
+    fn load_docker_image(&self, ex: &Experiment) -> Fallible<Option<String>> {
+        Ok(self
+            .db
+            .get_row(
+                "SELECT docker_image FROM experiments WHERE name = ?1;",
+                &[&ex.name],
+                |row| row.get("docker_image"),
+            )?
+            .and_then(|digest| digest))
+    }
+
+    fn load_environments(&self, ex: &Experiment) -> Fallible<Vec<EnvironmentFingerprint>> {
+        let rows: Vec<String> = self.db.query(
+            "SELECT fingerprint FROM environments WHERE experiment = ?1 \
+             ORDER BY rowid;",
+            &[&ex.name],
+            |row| row.get("fingerprint"),
+        )?;
+
+        let mut environments = Vec::with_capacity(rows.len());
+        for fingerprint in rows {
+            environments.push(serde_json::from_str(&fingerprint)?);
+        }
+        Ok(environments)
+    }
+
     fn load_log(
         &self,
         ex: &Experiment,
         toolchain: &Toolchain,
         krate: &Crate,
     ) -> Fallible<Option<Vec<u8>>> {
-        Ok(self.db.get_row(
-            "SELECT log FROM results \
+        let log: Option<(Vec<u8>, Option<String>)> = self.db.get_row(
+            "SELECT COALESCE(log_blobs.log, results.log) AS log, log_blobs.compression \
+             FROM results \
+             LEFT JOIN log_blobs ON log_blobs.hash = results.log_hash \
              WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 \
              LIMIT 1;",
             &[
@@ -112,8 +1166,12 @@ impl<'a> ReadResults for DatabaseDB<'a> {
                 &toolchain.to_string(),
                 &serde_json::to_string(krate)?,
             ],
-            |row| row.get("log"),
-        )?)
+            |row| (row.get("log"), row.get("compression")),
+        )?;
+        match log {
+            Some((log, compression)) => Ok(Some(decompress_log(log, compression)?)),
+            None => Ok(None),
+        }
     }
 
     fn load_test_result(
@@ -143,27 +1201,220 @@ impl<'a> ReadResults for DatabaseDB<'a> {
             Ok(None)
         }
     }
-}
 
-impl<'a> WriteResults for DatabaseDB<'a> {
-    fn get_result(
+    fn load_warnings(
         &self,
         ex: &Experiment,
         toolchain: &Toolchain,
         krate: &Crate,
-    ) -> Fallible<Option<TestResult>> {
-        self.load_test_result(ex, toolchain, krate)
-    }
+    ) -> Fallible<Option<u32>> {
+        let warnings = self.db.get_row(
+            "SELECT warnings FROM results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 \
+             LIMIT 1;",
+            &[
+                &ex.name,
+                &toolchain.to_string(),
+                &serde_json::to_string(krate)?,
+            ],
+            |row| row.get("warnings"),
+        )?;
+        Ok(warnings.and_then(|warnings| warnings))
+    }
 
-    fn record_sha(&self, ex: &Experiment, repo: &GitHubRepo, sha: &str) -> Fallible<()> {
-        self.db.execute(
-            "INSERT INTO shas (experiment, org, name, sha) VALUES (?1, ?2, ?3, ?4)",
-            &[&ex.name, &repo.org, &repo.name, &sha],
+    fn load_artifacts_size(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<u64>> {
+        let size: Option<Option<i64>> = self.db.get_row(
+            "SELECT artifacts_size FROM results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 \
+             LIMIT 1;",
+            &[
+                &ex.name,
+                &toolchain.to_string(),
+                &serde_json::to_string(krate)?,
+            ],
+            |row| row.get("artifacts_size"),
+        )?;
+        Ok(size.and_then(|size| size).map(|size| size as u64))
+    }
+
+    fn load_phases(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Vec<(Phase, TestResult)>> {
+        let phases: Vec<(String, String)> = self.db.query(
+            "SELECT phase, result FROM result_phases \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+            &[
+                &ex.name,
+                &toolchain.to_string(),
+                &serde_json::to_string(krate)?,
+            ],
+            |row| (row.get("phase"), row.get("result")),
+        )?;
+        let mut phases = phases
+            .into_iter()
+            .map(|(phase, result)| -> Fallible<(Phase, TestResult)> {
+                Ok((phase.parse()?, result.parse()?))
+            })
+            .collect::<Fallible<Vec<_>>>()?;
+        // The build always comes before the tests
+        phases.sort_by_key(|&(phase, _)| phase as u8);
+        Ok(phases)
+    }
+
+    fn load_phase_log(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        phase: Phase,
+    ) -> Fallible<Option<Vec<u8>>> {
+        let log: Option<(Vec<u8>, Option<String>)> = self.db.get_row(
+            "SELECT log, compression FROM result_phases \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 AND phase = ?4 \
+             LIMIT 1;",
+            &[
+                &ex.name as &ToSql,
+                &toolchain.to_string(),
+                &serde_json::to_string(krate)?,
+                &phase.to_str(),
+            ],
+            |row| (row.get("log"), row.get("compression")),
+        )?;
+        match log {
+            Some((log, compression)) => Ok(Some(decompress_log(log, compression)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_annotation(&self, ex: &Experiment, krate: &Crate) -> Fallible<Option<Annotation>> {
+        annotations::load_annotation(self.db, ex, krate)
+    }
+
+    fn load_toolchain_diagnostics(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Vec<Diagnostic>> {
+        let diagnostics: Option<Option<String>> = self.db.get_row(
+            "SELECT diagnostics FROM results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 \
+             LIMIT 1;",
+            &[
+                &ex.name,
+                &toolchain.to_string(),
+                &serde_json::to_string(krate)?,
+            ],
+            |row| row.get("diagnostics"),
         )?;
+        match diagnostics.and_then(|diagnostics| diagnostics) {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+impl<'a> WriteResults for DatabaseDB<'a> {
+    fn get_result(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<TestResult>> {
+        self.load_test_result(ex, toolchain, krate)
+    }
+
+    fn record_sha(&self, ex: &Experiment, repo: &GitHubRepo, sha: &str) -> Fallible<()> {
+        store_sha(self.db, ex, repo, sha)
+    }
+
+    fn record_dependencies(
+        &self,
+        ex: &Experiment,
+        krate: &Crate,
+        dependencies: &[String],
+    ) -> Fallible<()> {
+        store_dependencies(self.db, ex, krate, dependencies)
+    }
+
+    fn record_dependency_tree(
+        &self,
+        ex: &Experiment,
+        krate: &Crate,
+        edges: &[(String, String)],
+    ) -> Fallible<()> {
+        store_dependency_tree(self.db, ex, krate, edges)
+    }
+
+    fn record_docker_image(&self, ex: &Experiment, digest: &str) -> Fallible<()> {
+        store_docker_image(self.db, ex, digest)
+    }
 
+    fn record_environment(&self, ex: &Experiment, env: &EnvironmentFingerprint) -> Fallible<()> {
+        store_environment(self.db, ex, env)
+    }
+
+    fn record_broken_toolchain(&self, ex: &Experiment, toolchain: &Toolchain) -> Fallible<()> {
+        let invalidated = self.invalidate_broken_toolchain_results(ex, toolchain)?;
+        if invalidated > 0 {
+            info!(
+                "invalidated {} results caused by a broken toolchain",
+                invalidated
+            );
+        }
+        Ok(())
+    }
+
+    fn record_artifacts_size(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        size: u64,
+    ) -> Fallible<()> {
+        // The result is stored only after the build completes
+        self.artifacts_sizes
+            .lock()
+            .unwrap()
+            .insert((krate.clone(), toolchain.clone()), size);
+        Ok(())
+    }
+
+    fn record_phase(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        phase: Phase,
+        result: TestResult,
+        log: &[u8],
+    ) -> Fallible<()> {
+        self.phases
+            .lock()
+            .unwrap()
+            .entry((krate.clone(), toolchain.clone()))
+            .or_insert_with(Vec::new)
+            .push((phase, result, log.to_vec()));
         Ok(())
     }
 
+    fn stop_requested(&self) -> bool {
+        // Fail-fast experiments are only stopped by the server
+        false
+    }
+
+    fn set_unhealthy(&self, _reason: Option<String>) {
+        // Local runs have nobody to report to, the problem is already logged
+    }
+
     fn record_result<F>(
         &self,
         ex: &Experiment,
@@ -177,45 +1428,92 @@ impl<'a> WriteResults for DatabaseDB<'a> {
         F: FnOnce() -> Fallible<TestResult>,
     {
         let storage = existing_logs.unwrap_or_else(|| LogStorage::new(LevelFilter::Info, config));
+        let start = Instant::now();
         let result = logs::capture(&storage, f)?;
+        let duration = start.elapsed();
         let output = storage.to_string();
-        self.store_result(ex, krate, toolchain, result, output.as_bytes())?;
+        let artifacts_size = self
+            .artifacts_sizes
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()));
+        let phases = self
+            .phases
+            .lock()
+            .unwrap()
+            .remove(&(krate.clone(), toolchain.clone()))
+            .unwrap_or_default();
+        self.db.transaction(|t| {
+            store_result(
+                t,
+                ex,
+                krate,
+                toolchain,
+                result,
+                output.as_bytes(),
+                Some(duration),
+                Some(count_warnings(&output)),
+                artifacts_size,
+                classify_error(result, &output),
+            )?;
+            for (phase, phase_result, log) in &phases {
+                store_phase(t, ex, krate, toolchain, *phase, *phase_result, log)?;
+            }
+            Ok(())
+        })?;
         Ok(result)
     }
 }
 
 impl<'a> DeleteResults for DatabaseDB<'a> {
     fn delete_all_results(&self, ex: &Experiment) -> Fallible<()> {
-        self.db
-            .execute("DELETE FROM results WHERE experiment = ?1;", &[&ex.name])?;
-        Ok(())
+        self.db.transaction(|t| {
+            t.execute("DELETE FROM results WHERE experiment = ?1;", &[&ex.name])?;
+            t.execute(
+                "DELETE FROM crate_assignments WHERE experiment = ?1;",
+                &[&ex.name],
+            )?;
+            Ok(())
+        })
     }
 
     fn delete_result(&self, ex: &Experiment, tc: &Toolchain, krate: &Crate) -> Fallible<()> {
-        self.db.execute(
-            "DELETE FROM results WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
-            &[
-                &ex.name,
-                &tc.to_string(),
-                &serde_json::to_string(krate).unwrap(),
-            ],
-        )?;
-        Ok(())
+        let krate = serde_json::to_string(krate)?;
+        self.db.transaction(|t| {
+            t.execute(
+                "DELETE FROM results WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3;",
+                &[&ex.name, &tc.to_string(), &krate],
+            )?;
+            // The crate isn't completed anymore
+            t.execute(
+                "DELETE FROM crate_assignments WHERE experiment = ?1 AND crate = ?2;",
+                &[&ex.name, &krate],
+            )?;
+            Ok(())
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DatabaseDB, ProgressData, TaskResult};
+    use super::{
+        log_hash, ChangedCrate, CrateDiagnostics, CrateHistoryEntry, CrateRun, DatabaseDB,
+        LogsStats, PartialProgressData, PhaseResult, ProgressData, ResultsSummary, TaskResult,
+    };
     use crate::actions::{Action, ActionsCtx, CreateExperiment};
     use crate::config::Config;
     use crate::crates::{Crate, GitHubRepo, RegistryCrate};
-    use crate::db::Database;
-    use crate::experiments::Experiment;
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::{Experiment, FeatureSet, Status};
     use crate::prelude::*;
-    use crate::results::{DeleteResults, FailureReason, ReadResults, TestResult, WriteResults};
-    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use crate::report::Comparison;
+    use crate::results::{
+        DeleteResults, Diagnostic, EnvironmentFingerprint, ErrorCategory, FailureReason, Phase,
+        ReadResults, TestResult, ToolchainFingerprint, WriteResults,
+    };
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
     use base64;
+    use rusqlite::types::ToSql;
 
     #[test]
     fn test_shas() {
@@ -277,6 +1575,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_docker_image() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // Create a dummy experiment to attach the results to
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        assert!(results.load_docker_image(&ex).unwrap().is_none());
+
+        // The last recorded digest is the one returned
+        results.record_docker_image(&ex, "sha256:0123").unwrap();
+        results
+            .record_docker_image(&ex, "rustops/crates-build-env@sha256:abcd")
+            .unwrap();
+        assert_eq!(
+            results.load_docker_image(&ex).unwrap(),
+            Some("rustops/crates-build-env@sha256:abcd".into())
+        );
+    }
+
+    fn dummy_environment(kernel: &str) -> EnvironmentFingerprint {
+        EnvironmentFingerprint {
+            docker_image: Some("rustops/crates-build-env@sha256:0123".into()),
+            kernel: Some(kernel.into()),
+            toolchains: vec![ToolchainFingerprint {
+                toolchain: MAIN_TOOLCHAIN.to_string(),
+                rustc: Some("rustc 1.30.0 (da5f414c2 2018-10-24)".into()),
+                cargo: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_environments() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        assert!(results.load_environments(&ex).unwrap().is_empty());
+
+        // Recording the same environment multiple times stores it only once
+        results
+            .record_environment(&ex, &dummy_environment("4.15.0"))
+            .unwrap();
+        results
+            .record_environment(&ex, &dummy_environment("4.18.0"))
+            .unwrap();
+        results
+            .record_environment(&ex, &dummy_environment("4.15.0"))
+            .unwrap();
+        assert_eq!(
+            results.load_environments(&ex).unwrap(),
+            vec![dummy_environment("4.15.0"), dummy_environment("4.18.0")]
+        );
+    }
+
     #[test]
     fn test_results() {
         crate::logs::init_test();
@@ -366,7 +1732,9 @@ mod tests {
     }
 
     #[test]
-    fn test_store() {
+    fn test_warnings() {
+        crate::logs::init_test();
+
         let db = Database::temp().unwrap();
         let results = DatabaseDB::new(&db);
         let config = Config::default();
@@ -374,7 +1742,6 @@ mod tests {
 
         crate::crates::lists::setup_test_lists(&db, &config).unwrap();
 
-        // Create a dummy experiment to attach the results to
         CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
         let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
 
@@ -383,46 +1750,1160 @@ mod tests {
             version: "1".into(),
         });
 
-        // Store a result and some SHAs
+        // A build emitting N warnings records N
+        results
+            .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                for i in 0..4 {
+                    info!("[stderr] warning: unused variable: `x{}`", i);
+                    info!("[stderr]  --> src/lib.rs:{}:9", i + 1);
+                }
+                info!("[stderr] warning: 4 warnings emitted");
+                Ok(TestResult::TestPass)
+            })
+            .unwrap();
+        assert_eq!(
+            results.load_warnings(&ex, &MAIN_TOOLCHAIN, &krate).unwrap(),
+            Some(4)
+        );
+
+        // Results sent by older agents don't have the count
         results
             .store(
                 &ex,
                 &ProgressData {
                     results: vec![TaskResult {
                         krate: krate.clone(),
-                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        toolchain: TEST_TOOLCHAIN.clone(),
                         result: TestResult::TestPass,
                         log: base64::encode("foo"),
+                        duration_ms: None,
+                        warnings: None,
+                        artifacts_size: None,
+                        phases: Vec::new(),
+                        error_category: None,
                     }],
-                    shas: vec![
-                        (
-                            GitHubRepo {
-                                org: "foo".into(),
-                                name: "bar".into(),
-                            },
-                            "42".into(),
-                        ),
-                        (
-                            GitHubRepo {
-                                org: "foo".into(),
-                                name: "baz".into(),
-                            },
-                            "beef".into(),
-                        ),
-                    ],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
                 },
             )
             .unwrap();
-
         assert_eq!(
-            results.load_log(&ex, &MAIN_TOOLCHAIN, &krate).unwrap(),
-            Some("foo".as_bytes().to_vec())
+            results.load_warnings(&ex, &TEST_TOOLCHAIN, &krate).unwrap(),
+            None
         );
+    }
+
+    #[test]
+    fn test_error_category() {
+        crate::logs::init_test();
+
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        let category = |toolchain: &Toolchain| {
+            db.get_row(
+                "SELECT error_category FROM results WHERE toolchain = ?1;",
+                &[&toolchain.to_string()],
+                |row| row.get::<_, Option<String>>("error_category"),
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        // Local runs classify the failure from the log
+        results
+            .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                info!("[stderr] error[E0308]: mismatched types");
+                Ok(TestResult::BuildFail(FailureReason::Unknown))
+            })
+            .unwrap();
         assert_eq!(
-            results
-                .load_test_result(&ex, &MAIN_TOOLCHAIN, &krate)
-                .unwrap(),
-            Some(TestResult::TestPass)
+            category(&MAIN_TOOLCHAIN),
+            Some("type-check-error".to_string())
         );
+
+        // The category sent by the agent is kept, and the server classifies the failures sent
+        // by older agents
+        let task = |toolchain: &Toolchain, error_category| TaskResult {
+            krate: krate.clone(),
+            toolchain: toolchain.clone(),
+            result: TestResult::BuildFail(FailureReason::Unknown),
+            log: base64::encode("[INFO] [stderr] error: linking with `cc` failed"),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category,
+        };
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![
+                        task(&MAIN_TOOLCHAIN, Some(ErrorCategory::OutOfMemory)),
+                        task(&TEST_TOOLCHAIN, None),
+                    ],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(category(&MAIN_TOOLCHAIN), Some("out-of-memory".to_string()));
+        assert_eq!(category(&TEST_TOOLCHAIN), Some("linker-error".to_string()));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        crate::logs::init_test();
+
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        assert!(results.load_diagnostics(&ex, &krate).unwrap().is_empty());
+
+        results
+            .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                info!(concat!(
+                    "[stdout] {{\"reason\":\"compiler-message\",\"message\":{{\"message\":",
+                    "\"mismatched types\",\"code\":{{\"code\":\"E0308\"}},\"level\":\"error\",",
+                    "\"spans\":[{{\"file_name\":\"src/lib.rs\",\"line_start\":5,",
+                    "\"column_start\":12,\"is_primary\":true}}]}}}}"
+                ));
+                Ok(TestResult::BuildFail(FailureReason::Unknown))
+            })
+            .unwrap();
+        results
+            .record_result(&ex, &TEST_TOOLCHAIN, &krate, None, &config, || {
+                Ok(TestResult::TestPass)
+            })
+            .unwrap();
+
+        assert_eq!(
+            results.load_diagnostics(&ex, &krate).unwrap(),
+            vec![
+                CrateDiagnostics {
+                    toolchain: MAIN_TOOLCHAIN.to_string(),
+                    diagnostics: vec![Diagnostic {
+                        level: "error".into(),
+                        code: Some("E0308".into()),
+                        message: "mismatched types".into(),
+                        file: Some("src/lib.rs".into()),
+                        line: Some(5),
+                        column: Some(12),
+                    }],
+                },
+                CrateDiagnostics {
+                    toolchain: TEST_TOOLCHAIN.to_string(),
+                    diagnostics: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_phases() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+
+        // The crate builds but its tests fail
+        results
+            .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                info!("compiling");
+                results.record_phase(
+                    &ex,
+                    &MAIN_TOOLCHAIN,
+                    &krate,
+                    Phase::Build,
+                    TestResult::TestPass,
+                    b"compiling",
+                )?;
+                info!("test failed");
+                results.record_phase(
+                    &ex,
+                    &MAIN_TOOLCHAIN,
+                    &krate,
+                    Phase::Test,
+                    TestResult::TestFail(FailureReason::Unknown),
+                    b"test failed",
+                )?;
+                Ok(TestResult::TestFail(FailureReason::Unknown))
+            })
+            .unwrap();
+        assert_eq!(
+            results.load_phases(&ex, &MAIN_TOOLCHAIN, &krate).unwrap(),
+            vec![
+                (Phase::Build, TestResult::TestPass),
+                (Phase::Test, TestResult::TestFail(FailureReason::Unknown)),
+            ]
+        );
+        let phase_log = |phase| {
+            results
+                .load_phase_log(&ex, &MAIN_TOOLCHAIN, &krate, phase)
+                .unwrap()
+        };
+        assert_eq!(phase_log(Phase::Build), Some(b"compiling".to_vec()));
+        assert_eq!(phase_log(Phase::Test), Some(b"test failed".to_vec()));
+
+        // The same is sent by the agents
+        let phase = |phase, result, log: &str| PhaseResult {
+            phase,
+            result,
+            log: base64::encode(log),
+        };
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: TEST_TOOLCHAIN.clone(),
+                        result: TestResult::TestFail(FailureReason::OOM),
+                        log: base64::encode("foo"),
+                        duration_ms: None,
+                        warnings: None,
+                        artifacts_size: None,
+                        phases: vec![
+                        error_category: None,
+                            phase(Phase::Build, TestResult::TestPass, "bar"),
+                            phase(Phase::Test, TestResult::TestFail(FailureReason::OOM), "baz"),
+                        ],
+                    }],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            results.load_phases(&ex, &TEST_TOOLCHAIN, &krate).unwrap(),
+            vec![
+                (Phase::Build, TestResult::TestPass),
+                (Phase::Test, TestResult::TestFail(FailureReason::OOM)),
+            ]
+        );
+        assert_eq!(
+            results
+                .load_phase_log(&ex, &TEST_TOOLCHAIN, &krate, Phase::Test)
+                .unwrap(),
+            Some(b"baz".to_vec())
+        );
+
+        // Replacing the result removes the phases of the old one
+        results
+            .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                Ok(TestResult::TestPass)
+            })
+            .unwrap();
+        assert!(results
+            .load_phases(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_empty());
+        assert_eq!(phase_log(Phase::Build), None);
+    }
+
+    #[test]
+    fn test_store() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // Create a dummy experiment to attach the results to
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+
+        // Store a result and some SHAs
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        result: TestResult::TestPass,
+                        log: base64::encode("foo"),
+                        duration_ms: None,
+                        warnings: None,
+                        artifacts_size: None,
+                        phases: Vec::new(),
+                        error_category: None,
+                    }],
+                    shas: vec![
+                        (
+                            GitHubRepo {
+                                org: "foo".into(),
+                                name: "bar".into(),
+                            },
+                            "42".into(),
+                        ),
+                        (
+                            GitHubRepo {
+                                org: "foo".into(),
+                                name: "baz".into(),
+                            },
+                            "beef".into(),
+                        ),
+                    ],
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: Some("rustops/crates-build-env@sha256:0123".into()),
+                    environment: Some(dummy_environment("4.15.0")),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            results.load_log(&ex, &MAIN_TOOLCHAIN, &krate).unwrap(),
+            Some("foo".as_bytes().to_vec())
+        );
+        assert_eq!(
+            results.load_docker_image(&ex).unwrap(),
+            Some("rustops/crates-build-env@sha256:0123".into())
+        );
+        assert_eq!(
+            results.load_environments(&ex).unwrap(),
+            vec![dummy_environment("4.15.0")]
+        );
+        assert_eq!(
+            results
+                .load_test_result(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            Some(TestResult::TestPass)
+        );
+    }
+
+    #[test]
+    fn test_partial_logs() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // Create a dummy experiment to attach the results to
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        let partial = |chunk, log: &str| PartialProgressData {
+            krate: krate.clone(),
+            toolchain: MAIN_TOOLCHAIN.clone(),
+            chunk,
+            log: base64::encode(log),
+        };
+
+        // Chunks are concatenated in order, regardless of when they arrived
+        results.store_partial(&ex, &partial(1, "bar"), 4).unwrap();
+        results.store_partial(&ex, &partial(0, "foo"), 4).unwrap();
+        assert_eq!(
+            results
+                .load_partial_log(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            Some("foobar".as_bytes().to_vec())
+        );
+        assert_eq!(
+            results.in_progress_builds(&ex).unwrap(),
+            vec![(krate.clone(), MAIN_TOOLCHAIN.clone())]
+        );
+
+        // The number of chunks for each build is bounded
+        assert!(results.store_partial(&ex, &partial(4, "baz"), 4).is_err());
+
+        // Storing the final result removes the staged chunks
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate.clone(),
+                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        result: TestResult::TestPass,
+                        log: base64::encode("foobarbaz"),
+                        duration_ms: None,
+                        warnings: None,
+                        artifacts_size: None,
+                        phases: Vec::new(),
+                        error_category: None,
+                    }],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        assert!(results
+            .load_partial_log(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_none());
+        assert!(results.in_progress_builds(&ex).unwrap().is_empty());
+
+        // Late chunks are ignored once the result is stored
+        results.store_partial(&ex, &partial(2, "qux"), 4).unwrap();
+        assert!(results
+            .load_partial_log(&ex, &MAIN_TOOLCHAIN, &krate)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_crate_history() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // The older experiment sorts after the newer one alphabetically
+        CreateExperiment::dummy("older").apply(&ctx).unwrap();
+        CreateExperiment::dummy("newer").apply(&ctx).unwrap();
+        CreateExperiment::dummy("private").apply(&ctx).unwrap();
+        db.execute(
+            "UPDATE experiments SET private = 1 WHERE name = 'private';",
+            &[],
+        )
+        .unwrap();
+        let older = Experiment::get(&db, "older").unwrap().unwrap();
+        let newer = Experiment::get(&db, "newer").unwrap().unwrap();
+        let private = Experiment::get(&db, "private").unwrap().unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        let other = Crate::Registry(RegistryCrate {
+            name: "rand".into(),
+            version: "1".into(),
+        });
+
+        let record = |ex: &Experiment, krate: &Crate, res: TestResult| {
+            results
+                .record_result(ex, &MAIN_TOOLCHAIN, krate, None, &config, || Ok(res))
+                .unwrap();
+        };
+        record(
+            &newer,
+            &krate,
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+        record(&older, &krate, TestResult::TestPass);
+        record(&older, &other, TestResult::TestPass);
+        record(&private, &krate, TestResult::TestPass);
+
+        // Results of private experiments are excluded
+        let history = results.crate_history(&krate).unwrap();
+        assert_eq!(
+            history,
+            vec![
+                CrateHistoryEntry {
+                    experiment: "older".into(),
+                    experiment_created_at: older.created_at,
+                    toolchain: MAIN_TOOLCHAIN.to_string(),
+                    result: TestResult::TestPass,
+                },
+                CrateHistoryEntry {
+                    experiment: "newer".into(),
+                    experiment_created_at: newer.created_at,
+                    toolchain: MAIN_TOOLCHAIN.to_string(),
+                    result: TestResult::BuildFail(FailureReason::Unknown),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_crate_runs() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("older").apply(&ctx).unwrap();
+        CreateExperiment::dummy("newer").apply(&ctx).unwrap();
+        CreateExperiment::dummy("private").apply(&ctx).unwrap();
+        db.execute(
+            "UPDATE experiments SET private = 1 WHERE name = 'private';",
+            &[],
+        )
+        .unwrap();
+        let older = Experiment::get(&db, "older").unwrap().unwrap();
+        let newer = Experiment::get(&db, "newer").unwrap().unwrap();
+        let private = Experiment::get(&db, "private").unwrap().unwrap();
+
+        let version = |version: &str| {
+            Crate::Registry(RegistryCrate {
+                name: "lazy_static".into(),
+                version: version.into(),
+            })
+        };
+        let other = Crate::Registry(RegistryCrate {
+            name: "lazy_static_macros".into(),
+            version: "1".into(),
+        });
+
+        let record = |ex: &Experiment, tc: &Toolchain, krate: &Crate, res: TestResult| {
+            results
+                .record_result(ex, tc, krate, None, &config, || Ok(res))
+                .unwrap();
+        };
+        record(&older, &MAIN_TOOLCHAIN, &version("1"), TestResult::TestPass);
+        record(&older, &TEST_TOOLCHAIN, &version("1"), TestResult::TestPass);
+        record(&newer, &MAIN_TOOLCHAIN, &version("2"), TestResult::TestPass);
+        record(
+            &newer,
+            &TEST_TOOLCHAIN,
+            &version("2"),
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+        record(&newer, &MAIN_TOOLCHAIN, &other, TestResult::TestPass);
+        record(
+            &private,
+            &MAIN_TOOLCHAIN,
+            &version("2"),
+            TestResult::TestPass,
+        );
+
+        // Results of private experiments and of crates sharing a prefix are excluded
+        let toolchains = [MAIN_TOOLCHAIN.to_string(), TEST_TOOLCHAIN.to_string()];
+        assert_eq!(
+            results.crate_runs(&config, "lazy_static").unwrap(),
+            vec![
+                CrateRun {
+                    experiment: "newer".into(),
+                    experiment_created_at: newer.created_at,
+                    krate: "reg/lazy_static/2".into(),
+                    toolchains: toolchains.clone(),
+                    results: [
+                        Some(TestResult::TestPass),
+                        Some(TestResult::BuildFail(FailureReason::Unknown)),
+                    ],
+                    category: Comparison::Regressed,
+                },
+                CrateRun {
+                    experiment: "older".into(),
+                    experiment_created_at: older.created_at,
+                    krate: "reg/lazy_static/1".into(),
+                    toolchains: toolchains.clone(),
+                    results: [Some(TestResult::TestPass), Some(TestResult::TestPass)],
+                    category: Comparison::SameTestPass,
+                },
+            ]
+        );
+        assert!(results.crate_runs(&config, "missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_changed_crates() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+        let first = Experiment::get(&db, "first").unwrap().unwrap();
+        let second = Experiment::get(&db, "second").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            })
+        };
+        let record = |ex: &Experiment, name: &str, res1: TestResult, res2: TestResult| {
+            results
+                .record_result(ex, &MAIN_TOOLCHAIN, &krate(name), None, &config, || {
+                    Ok(res1)
+                })
+                .unwrap();
+            results
+                .record_result(ex, &TEST_TOOLCHAIN, &krate(name), None, &config, || {
+                    Ok(res2)
+                })
+                .unwrap();
+        };
+        let fail = TestResult::BuildFail(FailureReason::Unknown);
+        let pass = TestResult::TestPass;
+
+        // Regressed in the first experiment, fixed in the second one
+        record(&first, "a", pass, fail);
+        record(&second, "a", pass, pass);
+        // Classified the same way by both experiments
+        record(&first, "b", pass, fail);
+        record(&second, "b", pass, fail);
+        record(&first, "c", pass, pass);
+        record(&second, "c", pass, pass);
+        // Newly regressed in the second experiment
+        record(&first, "d", fail, fail);
+        record(&second, "d", pass, fail);
+        // Only tested by one of the experiments
+        record(&first, "e", pass, fail);
+
+        assert_eq!(
+            results
+                .changed_crates(&config, &first, &second, false)
+                .unwrap(),
+            vec![
+                ChangedCrate {
+                    krate: krate("a"),
+                    features: FeatureSet::Default,
+                    first: Comparison::Regressed,
+                    second: Comparison::SameTestPass,
+                },
+                ChangedCrate {
+                    krate: krate("d"),
+                    features: FeatureSet::Default,
+                    first: Comparison::SameBuildFail,
+                    second: Comparison::Regressed,
+                },
+            ]
+        );
+        assert!(results
+            .changed_crates(&config, &first, &first, false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_results_summary() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        assert_eq!(
+            results.results_summary(&ex).unwrap(),
+            ResultsSummary::default()
+        );
+
+        let record = |name: &str, res1: TestResult, res2: Option<TestResult>| {
+            let krate = Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            });
+            results
+                .record_result(&ex, &MAIN_TOOLCHAIN, &krate, None, &config, || Ok(res1))
+                .unwrap();
+            if let Some(res2) = res2 {
+                results
+                    .record_result(&ex, &TEST_TOOLCHAIN, &krate, None, &config, || Ok(res2))
+                    .unwrap();
+            }
+        };
+        let unknown = FailureReason::Unknown;
+        record(
+            "a",
+            TestResult::TestPass,
+            Some(TestResult::BuildFail(unknown)),
+        );
+        record(
+            "b",
+            TestResult::TestPass,
+            Some(TestResult::TestFail(unknown)),
+        );
+        record(
+            "c",
+            TestResult::BuildFail(unknown),
+            Some(TestResult::TestPass),
+        );
+        record(
+            "d",
+            TestResult::TestPass,
+            Some(TestResult::BuildFail(FailureReason::OOM)),
+        );
+        record("e", TestResult::TestPass, Some(TestResult::TestPass));
+        record("f", TestResult::TestPass, Some(TestResult::TestPass));
+        record(
+            "g",
+            TestResult::BuildFail(unknown),
+            Some(TestResult::BuildFail(unknown)),
+        );
+        record("h", TestResult::Error, Some(TestResult::TestPass));
+        // Crates without both results are not counted
+        record("i", TestResult::TestPass, None);
+
+        assert_eq!(
+            results.results_summary(&ex).unwrap(),
+            ResultsSummary {
+                regressed: 2,
+                fixed: 1,
+                spurious_regressed: 1,
+                spurious_fixed: 0,
+                build_fail: 1,
+                test_fail: 0,
+                test_skipped: 0,
+                test_pass: 2,
+                error: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalidate_broken_toolchain_results() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            })
+        };
+        let broken_log = "[INFO] [stderr] error: couldn't load codegen backend";
+        let task_result = |name: &str, toolchain: &Toolchain, result, log: &str| TaskResult {
+            krate: krate(name),
+            toolchain: toolchain.clone(),
+            result,
+            log: base64::encode(log),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![
+                        task_result(
+                            "broken",
+                            &TEST_TOOLCHAIN,
+                            TestResult::BuildFail(FailureReason::Unknown),
+                            broken_log,
+                        ),
+                        task_result(
+                            "failed",
+                            &TEST_TOOLCHAIN,
+                            TestResult::BuildFail(FailureReason::Unknown),
+                            "[INFO] [stderr] error[E0425]: cannot find value `x`",
+                        ),
+                        task_result(
+                            "other-toolchain",
+                            &MAIN_TOOLCHAIN,
+                            TestResult::BuildFail(FailureReason::Unknown),
+                            broken_log,
+                        ),
+                    ],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+
+        results
+            .record_broken_toolchain(&ex, &TEST_TOOLCHAIN)
+            .unwrap();
+
+        let get = |name: &str, toolchain: &Toolchain| {
+            results
+                .load_test_result(&ex, toolchain, &krate(name))
+                .unwrap()
+        };
+        assert!(get("broken", &TEST_TOOLCHAIN).is_none());
+        assert!(get("failed", &TEST_TOOLCHAIN).is_some());
+        assert!(get("other-toolchain", &MAIN_TOOLCHAIN).is_some());
+    }
+
+    #[test]
+    fn test_logs_deduplication() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            })
+        };
+        let task_result = |name: &str, log: &str| TaskResult {
+            krate: krate(name),
+            toolchain: MAIN_TOOLCHAIN.clone(),
+            result: TestResult::TestPass,
+            log: base64::encode(log),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        let store = |results_list| {
+            results
+                .store(
+                    &ex,
+                    &ProgressData {
+                        results: results_list,
+                        shas: Vec::new(),
+                        dependencies: Vec::new(),
+                        dependency_trees: Vec::new(),
+                        docker_image: None,
+                        environment: None,
+                    },
+                )
+                .unwrap();
+        };
+        let blobs = || {
+            db.get_row("SELECT COUNT(*) AS count FROM log_blobs;", &[], |row| {
+                row.get::<_, i64>("count")
+            })
+            .unwrap()
+            .unwrap()
+        };
+
+        store(vec![
+            task_result("a", "build successful"),
+            task_result("b", "build successful"),
+            task_result("c", "build successful"),
+            task_result("d", "error: linking failed"),
+        ]);
+        assert_eq!(blobs(), 2);
+        assert_eq!(
+            results.load_log(&ex, &MAIN_TOOLCHAIN, &krate("b")).unwrap(),
+            Some(b"build successful".to_vec())
+        );
+
+        // A log is removed only after the last result using it is gone
+        store(vec![task_result("d", "build successful")]);
+        assert_eq!(blobs(), 1);
+        results
+            .delete_result(&ex, &MAIN_TOOLCHAIN, &krate("a"))
+            .unwrap();
+        assert_eq!(blobs(), 1);
+
+        // Results stored before the deduplication are still readable
+        db.execute(
+            "INSERT INTO results (experiment, crate, toolchain, result, log) \
+             VALUES (?1, ?2, ?3, ?4, ?5);",
+            &[
+                &ex.name,
+                &serde_json::to_string(&krate("legacy")).unwrap(),
+                &MAIN_TOOLCHAIN.to_string(),
+                &TestResult::TestPass.to_string(),
+                &b"legacy log".to_vec(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            results
+                .load_log(&ex, &MAIN_TOOLCHAIN, &krate("legacy"))
+                .unwrap(),
+            Some(b"legacy log".to_vec())
+        );
+
+        let stats = results.logs_stats().unwrap();
+        assert_eq!(
+            stats,
+            LogsStats {
+                results: 4,
+                unique_logs: 2,
+                total_size: 16 * 3 + 10,
+                stored_size: 16 + 10,
+            }
+        );
+        assert!(stats.dedup_ratio() > 2.0);
+
+        results.delete_all_results(&ex).unwrap();
+        assert_eq!(blobs(), 0);
+    }
+
+    #[test]
+    fn test_compressed_logs() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            })
+        };
+        let log = "[INFO] [stderr]    Compiling libc v0.2.50\n".repeat(100);
+
+        // New logs are compressed when they're stored
+        results
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![TaskResult {
+                        krate: krate("new"),
+                        toolchain: MAIN_TOOLCHAIN.clone(),
+                        result: TestResult::TestPass,
+                        log: base64::encode(&log),
+                        duration_ms: None,
+                        warnings: None,
+                        artifacts_size: None,
+                        phases: Vec::new(),
+                        error_category: None,
+                    }],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        let (compression, size) = db
+            .get_row(
+                "SELECT compression, LENGTH(log) AS size FROM log_blobs;",
+                &[],
+                |row| {
+                    (
+                        row.get::<_, Option<String>>("compression"),
+                        row.get::<_, i64>("size"),
+                    )
+                },
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(compression.as_ref().map(String::as_str), Some("gzip"));
+        assert!((size as usize) < log.len());
+
+        // The usage reports both the size of the log and the size of the compressed blob
+        let usage = results.usage().unwrap();
+        assert_eq!(usage[0].raw_size, log.len() as u64);
+        assert_eq!(usage[0].compressed_size, size as u64);
+        assert_eq!(usage[0].stored_size, size as u64);
+
+        // Logs stored before they were compressed are still readable
+        let legacy = log.replace("libc", "cfg-if");
+        db.execute(
+            "INSERT INTO log_blobs (hash, log) VALUES (?1, ?2);",
+            &[
+                &log_hash(legacy.as_bytes()) as &ToSql,
+                &legacy.as_bytes().to_vec(),
+            ],
+        )
+        .unwrap();
+        db.execute(
+            "INSERT INTO results \
+             (experiment, crate, toolchain, result, log, log_hash, log_size, stored_log_size) \
+             VALUES (?1, ?2, ?3, ?4, X'', ?5, ?6, ?6);",
+            &[
+                &ex.name as &ToSql,
+                &serde_json::to_string(&krate("legacy")).unwrap(),
+                &MAIN_TOOLCHAIN.to_string(),
+                &TestResult::TestPass.to_string(),
+                &log_hash(legacy.as_bytes()),
+                &(legacy.len() as i64),
+            ],
+        )
+        .unwrap();
+
+        let load = |name: &str| {
+            results
+                .load_log(&ex, &MAIN_TOOLCHAIN, &krate(name))
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(load("new"), log.as_bytes());
+        assert_eq!(load("legacy"), legacy.as_bytes());
+    }
+
+    #[test]
+    fn test_usage_and_purge_logs() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+        let mut first = Experiment::get(&db, "first").unwrap().unwrap();
+        let second = Experiment::get(&db, "second").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            })
+        };
+        let store = |ex: &Experiment, logs: &[(&str, &str)]| {
+            let results_list = logs
+                .iter()
+                .map(|&(name, log)| TaskResult {
+                    krate: krate(name),
+                    toolchain: MAIN_TOOLCHAIN.clone(),
+                    result: TestResult::TestPass,
+                    log: base64::encode(log),
+                    duration_ms: None,
+                    warnings: None,
+                    artifacts_size: None,
+                    phases: Vec::new(),
+                    error_category: None,
+                })
+                .collect();
+            results
+                .store(
+                    ex,
+                    &ProgressData {
+                        results: results_list,
+                        shas: Vec::new(),
+                        dependencies: Vec::new(),
+                        dependency_trees: Vec::new(),
+                        docker_image: None,
+                        environment: None,
+                    },
+                )
+                .unwrap();
+        };
+
+        store(
+            &first,
+            &[
+                ("a", "build successful"),
+                ("b", "build successful"),
+                ("c", "error"),
+            ],
+        );
+        store(&second, &[("a", "build successful")]);
+
+        let usage = results.usage().unwrap();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].experiment, "first");
+        assert_eq!(usage[0].results, 3);
+        assert_eq!(usage[0].raw_size, 16 * 2 + 5);
+        // Short logs are stored uncompressed
+        assert_eq!(usage[0].compressed_size, 16 * 2 + 5);
+        assert_eq!(usage[0].stored_size, 16 + 5);
+        assert!((usage[0].share - 1.0).abs() < 1e-9);
+        assert_eq!(usage[1].experiment, "second");
+        assert_eq!(usage[1].results, 1);
+        assert_eq!(usage[1].raw_size, 16);
+        assert_eq!(usage[1].compressed_size, 16);
+        assert_eq!(usage[1].stored_size, 16);
+        assert!((usage[1].share - 16.0 / 21.0).abs() < 1e-9);
+
+        // Only the logs of completed experiments can be purged
+        assert!(results.purge_logs(&first).is_err());
+        first.set_status(&db, Status::Completed).unwrap();
+        assert!(!results.logs_purged(&first).unwrap());
+        assert_eq!(results.purge_logs(&first).unwrap(), 3);
+        assert!(results.logs_purged(&first).unwrap());
+        assert!(!results.logs_purged(&second).unwrap());
+
+        // The results are kept, and the logs shared with other experiments too
+        assert_eq!(
+            results
+                .load_test_result(&first, &MAIN_TOOLCHAIN, &krate("a"))
+                .unwrap(),
+            Some(TestResult::TestPass)
+        );
+        assert_eq!(
+            results
+                .load_log(&first, &MAIN_TOOLCHAIN, &krate("a"))
+                .unwrap(),
+            Some(Vec::new())
+        );
+        assert_eq!(
+            results
+                .load_log(&second, &MAIN_TOOLCHAIN, &krate("a"))
+                .unwrap(),
+            Some(b"build successful".to_vec())
+        );
+        let blobs: i64 = db
+            .get_row("SELECT COUNT(*) AS count FROM log_blobs;", &[], |row| {
+                row.get("count")
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(blobs, 1);
+
+        let usage = results.usage().unwrap();
+        assert_eq!(usage[0].experiment, "second");
+        assert_eq!(usage[1].experiment, "first");
+        assert_eq!(usage[1].raw_size, 0);
+        assert_eq!(usage[1].compressed_size, 0);
+        assert_eq!(usage[1].stored_size, 0);
     }
 }
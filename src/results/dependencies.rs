@@ -0,0 +1,233 @@
+use crate::crates::Crate;
+use crate::db::{Database, QueryUtils};
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::results::export::crate_name_and_version;
+use crate::results::{DatabaseDB, ReadResults};
+use serde_json;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use utoipa::ToSchema;
+
+/// Direct dependencies of the crates of an experiment, recorded by the agents when the experiment
+/// has `pre_analyze` enabled.
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct DependencyGraph {
+    /// Adjacency list of the graph: the direct dependencies of each analyzed crate.
+    pub crates: BTreeMap<String, Vec<String>>,
+    /// Number of analyzed crates directly depending on each dependency.
+    pub crates_with_direct_dep_on: BTreeMap<String, u32>,
+}
+
+/// Load the dependency graph of the experiment. Only the crates already prepared by the agents
+/// are included, so the graph is filled in while the experiment runs.
+pub fn load_dependency_graph(db: &Database, ex: &Experiment) -> Fallible<DependencyGraph> {
+    let rows: Vec<(String, String)> = db.query(
+        "SELECT crate, dependency FROM crate_dependencies WHERE experiment = ?1 \
+         ORDER BY crate, dependency;",
+        &[&ex.name],
+        |row| (row.get("crate"), row.get("dependency")),
+    )?;
+    let shas = DatabaseDB::new(db).load_all_shas(ex)?;
+
+    let mut crates: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (krate, dependency) in rows {
+        let krate: Crate = serde_json::from_str(&krate)?;
+        let (name, _) = crate_name_and_version(&krate, &shas);
+        crates.entry(name).or_insert_with(Vec::new).push(dependency);
+    }
+
+    let mut crates_with_direct_dep_on = BTreeMap::new();
+    for dependencies in crates.values_mut() {
+        // Multiple versions of the same crate are merged together
+        dependencies.sort();
+        dependencies.dedup();
+        for dependency in dependencies.iter() {
+            *crates_with_direct_dep_on
+                .entry(dependency.clone())
+                .or_insert(0) += 1;
+        }
+    }
+
+    Ok(DependencyGraph {
+        crates,
+        crates_with_direct_dep_on,
+    })
+}
+
+/// A package in the resolved dependency trees of multiple crates, which might be the common cause
+/// of their regressions.
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct SharedDependency {
+    pub dependency: String,
+    pub crates: Vec<String>,
+}
+
+/// Find the packages shared by the resolved dependency trees of at least two of the provided
+/// crates, named like in the dependency graph. The packages shared by the most crates are listed
+/// first.
+pub fn shared_dependencies(
+    db: &Database,
+    ex: &Experiment,
+    crates: &[String],
+) -> Fallible<Vec<SharedDependency>> {
+    let rows: Vec<(String, String)> = db.query(
+        "SELECT DISTINCT crate, dependency FROM dependency_edges WHERE experiment = ?1;",
+        &[&ex.name],
+        |row| (row.get("crate"), row.get("dependency")),
+    )?;
+    let shas = DatabaseDB::new(db).load_all_shas(ex)?;
+    let crates = crates.iter().collect::<HashSet<_>>();
+
+    let mut dependents: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (krate, dependency) in rows {
+        let krate: Crate = serde_json::from_str(&krate)?;
+        let (name, _) = crate_name_and_version(&krate, &shas);
+        if crates.contains(&name) {
+            dependents
+                .entry(dependency)
+                .or_insert_with(BTreeSet::new)
+                .insert(name);
+        }
+    }
+
+    let mut shared = dependents
+        .into_iter()
+        .filter(|(_, crates)| crates.len() > 1)
+        .map(|(dependency, crates)| SharedDependency {
+            dependency,
+            crates: crates.into_iter().collect(),
+        })
+        .collect::<Vec<_>>();
+    // The sort is stable, so the dependencies shared by the same number of crates stay sorted
+    shared.sort_by(|a, b| b.crates.len().cmp(&a.crates.len()));
+    Ok(shared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_dependency_graph, shared_dependencies, SharedDependency};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::results::{DatabaseDB, WriteResults};
+
+    #[test]
+    fn test_load_dependency_graph() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        // Nothing is recorded for experiments without the analysis
+        let graph = load_dependency_graph(&db, &ex).unwrap();
+        assert!(graph.crates.is_empty());
+        assert!(graph.crates_with_direct_dep_on.is_empty());
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        let deps = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let results = DatabaseDB::new(&db);
+        results
+            .record_dependencies(&ex, &krate("foo"), &deps(&["libc", "serde"]))
+            .unwrap();
+        results
+            .record_dependencies(&ex, &krate("bar"), &deps(&["serde"]))
+            .unwrap();
+        results
+            .record_dependencies(&ex, &krate("baz"), &deps(&["rand"]))
+            .unwrap();
+        // Preparing a crate again replaces its dependencies
+        results
+            .record_dependencies(&ex, &krate("baz"), &deps(&[]))
+            .unwrap();
+
+        let graph = load_dependency_graph(&db, &ex).unwrap();
+        assert_eq!(graph.crates.len(), 2);
+        assert_eq!(graph.crates["foo"], deps(&["libc", "serde"]));
+        assert_eq!(graph.crates["bar"], deps(&["serde"]));
+        assert_eq!(graph.crates_with_direct_dep_on.len(), 2);
+        assert_eq!(graph.crates_with_direct_dep_on["serde"], 2);
+        assert_eq!(graph.crates_with_direct_dep_on["libc"], 1);
+    }
+
+    #[test]
+    fn test_shared_dependencies() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        let edge = |from: &str, to: &str| (from.to_string(), to.to_string());
+        let results = DatabaseDB::new(&db);
+        // Both crates use `libc`, but `foo` only through `rand`
+        results
+            .record_dependency_tree(
+                &ex,
+                &krate("foo"),
+                &[edge("foo", "rand"), edge("rand", "libc")],
+            )
+            .unwrap();
+        results
+            .record_dependency_tree(
+                &ex,
+                &krate("bar"),
+                &[edge("bar", "libc"), edge("bar", "serde")],
+            )
+            .unwrap();
+        results
+            .record_dependency_tree(&ex, &krate("baz"), &[edge("baz", "serde")])
+            .unwrap();
+        results
+            .record_dependency_tree(&ex, &krate("qux"), &[edge("qux", "serde")])
+            .unwrap();
+
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            shared_dependencies(&db, &ex, &names(&["foo", "bar"])).unwrap(),
+            vec![SharedDependency {
+                dependency: "libc".into(),
+                crates: names(&["bar", "foo"]),
+            }]
+        );
+
+        // The dependencies shared by the most crates come first
+        let shared = shared_dependencies(&db, &ex, &names(&["foo", "bar", "baz", "qux"])).unwrap();
+        assert_eq!(shared.len(), 2);
+        assert_eq!(shared[0].dependency, "serde");
+        assert_eq!(shared[0].crates, names(&["bar", "baz", "qux"]));
+        assert_eq!(shared[1].dependency, "libc");
+
+        // A single crate doesn't share anything
+        assert!(shared_dependencies(&db, &ex, &names(&["foo"]))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,15 +1,21 @@
 use crate::crates::{Crate, GitHubRepo};
 use crate::experiments::Experiment;
 use crate::prelude::*;
-use crate::results::{ReadResults, TestResult};
+use crate::results::annotations::Annotation;
+use crate::results::{Diagnostic, EnvironmentFingerprint, Phase, ReadResults, TestResult};
 use crate::toolchain::Toolchain;
 use std::collections::HashMap;
 
 #[derive(Default)]
 struct DummyData {
     shas: HashMap<GitHubRepo, String>,
+    docker_image: Option<String>,
+    environments: Vec<EnvironmentFingerprint>,
     logs: HashMap<(Crate, Toolchain), Vec<u8>>,
     results: HashMap<(Crate, Toolchain), TestResult>,
+    warnings: HashMap<(Crate, Toolchain), u32>,
+    artifacts_sizes: HashMap<(Crate, Toolchain), u64>,
+    annotations: HashMap<Crate, Annotation>,
 }
 
 #[derive(Default)]
@@ -33,6 +39,21 @@ impl DummyDB {
             .insert(repo, sha);
     }
 
+    pub fn add_dummy_docker_image(&mut self, ex: &Experiment, digest: String) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .docker_image = Some(digest);
+    }
+
+    pub fn add_dummy_environment(&mut self, ex: &Experiment, env: EnvironmentFingerprint) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .environments
+            .push(env);
+    }
+
     pub fn add_dummy_log(&mut self, ex: &Experiment, krate: Crate, tc: Toolchain, log: Vec<u8>) {
         self.experiments
             .entry(ex.name.to_string())
@@ -54,6 +75,36 @@ impl DummyDB {
             .results
             .insert((krate, tc), res);
     }
+
+    pub fn add_dummy_warnings(&mut self, ex: &Experiment, krate: Crate, tc: Toolchain, count: u32) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .warnings
+            .insert((krate, tc), count);
+    }
+
+    pub fn add_dummy_artifacts_size(
+        &mut self,
+        ex: &Experiment,
+        krate: Crate,
+        tc: Toolchain,
+        size: u64,
+    ) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .artifacts_sizes
+            .insert((krate, tc), size);
+    }
+
+    pub fn add_dummy_annotation(&mut self, ex: &Experiment, krate: Crate, annotation: Annotation) {
+        self.experiments
+            .entry(ex.name.to_string())
+            .or_insert_with(DummyData::default)
+            .annotations
+            .insert(krate, annotation);
+    }
 }
 
 impl ReadResults for DummyDB {
@@ -61,6 +112,14 @@ impl ReadResults for DummyDB {
         Ok(self.get_data(ex)?.shas.clone())
     }
 
+    fn load_docker_image(&self, ex: &Experiment) -> Fallible<Option<String>> {
+        Ok(self.get_data(ex)?.docker_image.clone())
+    }
+
+    fn load_environments(&self, ex: &Experiment) -> Fallible<Vec<EnvironmentFingerprint>> {
+        Ok(self.get_data(ex)?.environments.clone())
+    }
+
     fn load_log(
         &self,
         ex: &Experiment,
@@ -86,4 +145,62 @@ impl ReadResults for DummyDB {
             .get(&(krate.clone(), toolchain.clone()))
             .cloned())
     }
+
+    fn load_warnings(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<u32>> {
+        Ok(self
+            .get_data(ex)?
+            .warnings
+            .get(&(krate.clone(), toolchain.clone()))
+            .cloned())
+    }
+
+    fn load_artifacts_size(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<u64>> {
+        Ok(self
+            .get_data(ex)?
+            .artifacts_sizes
+            .get(&(krate.clone(), toolchain.clone()))
+            .cloned())
+    }
+
+    fn load_phases(
+        &self,
+        _ex: &Experiment,
+        _toolchain: &Toolchain,
+        _krate: &Crate,
+    ) -> Fallible<Vec<(Phase, TestResult)>> {
+        Ok(Vec::new())
+    }
+
+    fn load_phase_log(
+        &self,
+        _ex: &Experiment,
+        _toolchain: &Toolchain,
+        _krate: &Crate,
+        _phase: Phase,
+    ) -> Fallible<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn load_annotation(&self, ex: &Experiment, krate: &Crate) -> Fallible<Option<Annotation>> {
+        Ok(self.get_data(ex)?.annotations.get(krate).cloned())
+    }
+
+    fn load_toolchain_diagnostics(
+        &self,
+        _ex: &Experiment,
+        _toolchain: &Toolchain,
+        _krate: &Crate,
+    ) -> Fallible<Vec<Diagnostic>> {
+        Ok(Vec::new())
+    }
 }
@@ -0,0 +1,579 @@
+use crate::config::Config;
+use crate::crates::{Crate, GitHubRepo};
+use crate::db::{Database, QueryUtils};
+use crate::experiments::{Experiment, FeatureSet};
+use crate::prelude::*;
+use crate::report::{self, Comparison};
+use crate::results::annotations::{self, AnnotationStatus};
+use crate::results::{DatabaseDB, FailureReason, ReadResults, TestResult};
+use crate::toolchain::Toolchain;
+use csv;
+use rusqlite::types::ToSql;
+use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use utoipa::ToSchema;
+
+/// Text rustc prints when it panics, used to flag internal compiler errors in the logs.
+const ICE_MARKER: &str = "internal compiler error";
+
+string_enum!(pub enum ExportFormat {
+    Json => "json",
+    Csv => "csv",
+});
+
+// Crates are tested once for each toolchain, so regressions caused by spurious failures like
+// timeouts or running out of memory are the ones likely to be flaky. Only the other regressions
+// have a high confidence.
+string_enum!(pub enum Confidence {
+    Low => "low",
+    High => "high",
+});
+
+/// A single result of an experiment, that is one crate tested with one toolchain. This is the
+/// schema of both the `crater export` command and the results endpoint of the public API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportedResult {
+    pub name: String,
+    /// Version of crates.io crates, or commit of GitHub repositories.
+    pub version: Option<String>,
+    pub toolchain: String,
+    pub result: TestResult,
+    /// Comparison between this result and the one of the other toolchain.
+    pub category: Comparison,
+    pub flaky: bool,
+    pub oom: bool,
+    pub ice: bool,
+    pub duration_ms: Option<u64>,
+    /// Only available after the report of the experiment is generated.
+    pub log_url: Option<String>,
+    /// Triage annotation of the crate, if any.
+    #[serde(default)]
+    pub annotation: Option<AnnotationStatus>,
+    #[serde(default)]
+    pub annotation_note: Option<String>,
+}
+
+/// A crate that passed with the first toolchain of the experiment but failed with the second one.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct Regression {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// Version of crates.io crates, or commit of GitHub repositories.
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "FeatureSet::is_default")]
+    pub features: FeatureSet,
+    pub baseline_outcome: TestResult,
+    pub new_outcome: TestResult,
+    /// Log of the second toolchain, only available after the report of the experiment is
+    /// generated.
+    pub log_url: Option<String>,
+    /// Reason of the known failure matching the regression, if it's included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub known_failure: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportedPage {
+    pub results: Vec<ExportedResult>,
+    pub total: u32,
+    pub next_page: Option<u32>,
+}
+
+/// Count the results of the experiment.
+pub fn count_results(db: &Database, ex: &Experiment) -> Fallible<u32> {
+    Ok(db
+        .get_row(
+            "SELECT COUNT(*) AS count FROM results WHERE experiment = ?1;",
+            &[&ex.name],
+            |row| row.get::<_, i64>("count") as u32,
+        )?
+        .unwrap_or(0))
+}
+
+/// Load the results of the experiment sorted by crate and toolchain, skipping the first `offset`
+/// of them. If `limit` is `None` all the remaining results are loaded.
+pub fn load_results(
+    db: &Database,
+    config: &Config,
+    ex: &Experiment,
+    offset: u32,
+    limit: Option<u32>,
+) -> Fallible<Vec<ExportedResult>> {
+    // PostgreSQL doesn't accept negative limits, so the largest one is used to load everything
+    let limit = limit.map(i64::from).unwrap_or_else(i64::max_value);
+    let rows: Vec<(String, String, String, Option<i64>)> = db.query(
+        "SELECT crate, toolchain, result, duration_ms FROM results \
+         WHERE experiment = ?1 \
+         ORDER BY crate, toolchain \
+         LIMIT ?2 OFFSET ?3;",
+        &[&ex.name as &ToSql, &limit, &i64::from(offset)],
+        |row| {
+            (
+                row.get("crate"),
+                row.get("toolchain"),
+                row.get("result"),
+                row.get("duration_ms"),
+            )
+        },
+    )?;
+
+    let results_db = DatabaseDB::new(db);
+    let shas = results_db.load_all_shas(ex)?;
+    let logs_base = logs_base(ex);
+    let annotations = annotations::load_annotations(db, ex)?
+        .into_iter()
+        .map(|annotation| (annotation.krate.clone(), annotation))
+        .collect::<HashMap<_, _>>();
+
+    let mut results = Vec::with_capacity(rows.len());
+    for (krate, toolchain, result, duration_ms) in rows {
+        let krate: Crate = serde_json::from_str(&krate)?;
+        let toolchain: Toolchain = toolchain.parse()?;
+        let result: TestResult = result.parse()?;
+
+        // Logs can be stored compressed, so they're searched once loaded
+        let ice = match results_db.load_log(ex, &toolchain, &krate)? {
+            Some(log) => String::from_utf8_lossy(&log).contains(ICE_MARKER),
+            None => false,
+        };
+
+        let toolchains = ex.toolchains_for(&toolchain.features);
+        let category = report::compare_known(
+            &results_db,
+            config,
+            ex,
+            &toolchains,
+            &krate,
+            results_db.load_test_result(ex, &toolchains[0], &krate)?,
+            results_db.load_test_result(ex, &toolchains[1], &krate)?,
+        )?;
+
+        let (name, version) = crate_name_and_version(&krate, &shas);
+        results.push(ExportedResult {
+            name,
+            version,
+            toolchain: toolchain.to_string(),
+            result,
+            category,
+            flaky: category == Comparison::SpuriousRegressed
+                || category == Comparison::SpuriousFixed,
+            oom: match result {
+                TestResult::BuildFail(reason) | TestResult::TestFail(reason) => {
+                    reason == FailureReason::OOM
+                }
+                _ => false,
+            },
+            ice,
+            duration_ms: duration_ms.map(|ms| ms as u64),
+            log_url: log_url(logs_base.as_ref(), &toolchain, &krate),
+            annotation: annotations.get(&krate.id()).map(|a| a.status),
+            annotation_note: annotations.get(&krate.id()).map(|a| a.note.clone()),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Load the regressions of the experiment sorted by crate, optionally only the ones with a high
+/// confidence. The regressions matching a known failure of the config are only included if
+/// `include_known` is set.
+pub fn load_regressions(
+    db: &Database,
+    config: &Config,
+    ex: &Experiment,
+    confidence: Confidence,
+    include_known: bool,
+) -> Fallible<Vec<Regression>> {
+    let rows: Vec<(String, String, String)> = db.query(
+        "SELECT crate, toolchain, result FROM results WHERE experiment = ?1;",
+        &[&ex.name],
+        |row| (row.get("crate"), row.get("toolchain"), row.get("result")),
+    )?;
+    let mut crates = HashSet::new();
+    let mut results = HashMap::with_capacity(rows.len());
+    for (krate, toolchain, result) in rows {
+        let krate: Crate = serde_json::from_str(&krate)?;
+        let toolchain: Toolchain = toolchain.parse()?;
+        let result: TestResult = result.parse()?;
+        crates.insert(krate.clone());
+        results.insert((krate, toolchain), result);
+    }
+
+    let results_db = DatabaseDB::new(db);
+    let shas = results_db.load_all_shas(ex)?;
+    let logs_base = logs_base(ex);
+
+    let mut regressions = Vec::new();
+    for krate in &crates {
+        for features in &ex.feature_sets {
+            let toolchains = ex.toolchains_for(features);
+            let (baseline, new) = match (
+                results.get(&(krate.clone(), toolchains[0].clone())),
+                results.get(&(krate.clone(), toolchains[1].clone())),
+            ) {
+                (Some(&baseline), Some(&new)) => (baseline, new),
+                _ => continue,
+            };
+
+            match report::compare(config, krate, Some(baseline), Some(new)) {
+                Comparison::Regressed => {}
+                Comparison::SpuriousRegressed if confidence == Confidence::Low => {}
+                _ => continue,
+            }
+            let known = report::known_failure(&results_db, config, ex, &toolchains[1], krate)?;
+            if known.is_some() && !include_known {
+                continue;
+            }
+
+            let (name, version) = crate_name_and_version(krate, &shas);
+            regressions.push(Regression {
+                krate: name,
+                version,
+                features: features.clone(),
+                baseline_outcome: baseline,
+                new_outcome: new,
+                log_url: log_url(logs_base.as_ref(), &toolchains[1], krate),
+                known_failure: known.map(|known| known.reason.clone()),
+            });
+        }
+    }
+
+    regressions.sort_by(|a, b| {
+        (&a.krate, &a.version, a.features.to_string()).cmp(&(
+            &b.krate,
+            &b.version,
+            b.features.to_string(),
+        ))
+    });
+    Ok(regressions)
+}
+
+/// Base URL of the logs of the experiment, once its report is generated.
+fn logs_base(ex: &Experiment) -> Option<String> {
+    ex.report_url.as_ref().map(|url| {
+        let base = url.trim_end_matches("index.html");
+        if base.ends_with('/') {
+            base.to_string()
+        } else {
+            format!("{}/", base)
+        }
+    })
+}
+
+fn log_url(logs_base: Option<&String>, toolchain: &Toolchain, krate: &Crate) -> Option<String> {
+    logs_base.map(|base| {
+        format!(
+            "{}{}/log.txt",
+            base,
+            report::crate_to_path_fragment(toolchain, krate, true).display()
+        )
+    })
+}
+
+/// Load a page of the results of the experiment. Pages start from 1.
+pub fn load_page(
+    db: &Database,
+    config: &Config,
+    ex: &Experiment,
+    page: u32,
+    per_page: u32,
+) -> Fallible<ExportedPage> {
+    let total = count_results(db, ex)?;
+    let offset = page.saturating_sub(1).saturating_mul(per_page);
+    let results = load_results(db, config, ex, offset, Some(per_page))?;
+
+    Ok(ExportedPage {
+        next_page: if offset.saturating_add(per_page) < total {
+            Some(page + 1)
+        } else {
+            None
+        },
+        results,
+        total,
+    })
+}
+
+pub(super) fn crate_name_and_version(
+    krate: &Crate,
+    shas: &HashMap<GitHubRepo, String>,
+) -> (String, Option<String>) {
+    match *krate {
+        Crate::Registry(ref details) => (details.name.clone(), Some(details.version.clone())),
+        Crate::GitHub(ref repo) => (
+            format!("{}/{}", repo.org, repo.name),
+            shas.get(repo).cloned(),
+        ),
+        Crate::Local(ref name) => (name.clone(), None),
+    }
+}
+
+/// Serialize the results in the provided format.
+pub fn write_results<W: Write>(
+    results: &[ExportedResult],
+    format: ExportFormat,
+    mut dest: W,
+) -> Fallible<()> {
+    match format {
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut dest, results)?,
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut dest);
+            for result in results {
+                writer.serialize(result)?;
+            }
+            writer.flush()?;
+        }
+    }
+    dest.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        load_page, load_regressions, load_results, write_results, Confidence, ExportFormat,
+    };
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::{Config, KnownFailure};
+    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::report::Comparison;
+    use crate::results::annotations::{set_annotation, AnnotationStatus};
+    use crate::results::db::TaskResult;
+    use crate::results::{DatabaseDB, FailureReason, ProgressData, TestResult};
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use base64;
+
+    fn setup(db: &Database, config: &Config) -> Experiment {
+        let ctx = ActionsCtx::new(db, config);
+        crate::crates::lists::setup_test_lists(db, config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(db, "dummy").unwrap().unwrap();
+
+        let registry = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1.0.0".into(),
+        });
+        let repo = GitHubRepo {
+            org: "brson".into(),
+            name: "hello-rs".into(),
+        };
+        let github = Crate::GitHub(repo.clone());
+
+        let task = |krate: &Crate, toolchain: &Toolchain, result, log: &str, ms| TaskResult {
+            krate: krate.clone(),
+            toolchain: toolchain.clone(),
+            result,
+            log: base64::encode(log),
+            duration_ms: ms,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        DatabaseDB::new(db)
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![
+                        task(
+                            &registry,
+                            &MAIN_TOOLCHAIN,
+                            TestResult::TestPass,
+                            "ok",
+                            Some(1500),
+                        ),
+                        task(
+                            &registry,
+                            &TEST_TOOLCHAIN,
+                            TestResult::BuildFail(FailureReason::Unknown),
+                            "error: internal compiler error: unexpected panic",
+                            Some(2000),
+                        ),
+                        task(
+                            &github,
+                            &MAIN_TOOLCHAIN,
+                            TestResult::BuildFail(FailureReason::OOM),
+                            "out of memory",
+                            Some(300),
+                        ),
+                        task(&github, &TEST_TOOLCHAIN, TestResult::TestPass, "ok", None),
+                    ],
+                    shas: vec![(repo, "f45e5e3289dd46aaec8392134a12c019aca3d117".into())],
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        set_annotation(
+            db,
+            &ex,
+            &registry,
+            AnnotationStatus::Reported,
+            "filed #1",
+            "octocat",
+        )
+        .unwrap();
+
+        ex.set_report_url(db, "https://example.com/dummy/index.html")
+            .unwrap();
+        ex
+    }
+
+    #[test]
+    fn test_export_golden() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ex = setup(&db, &config);
+        let results = load_results(&db, &config, &ex, 0, None).unwrap();
+
+        let mut json = Vec::new();
+        write_results(&results, ExportFormat::Json, &mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            include_str!("../../tests/export/results.json").trim_end()
+        );
+
+        let mut csv = Vec::new();
+        write_results(&results, ExportFormat::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            include_str!("../../tests/export/results.csv")
+        );
+    }
+
+    #[test]
+    fn test_load_regressions() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ex = setup(&db, &config);
+
+        let regressions = load_regressions(&db, &config, &ex, Confidence::Low, false).unwrap();
+        assert_eq!(regressions.len(), 1);
+        let regression = &regressions[0];
+        assert_eq!(regression.krate, "lazy_static");
+        assert_eq!(regression.version, Some("1.0.0".into()));
+        assert_eq!(regression.baseline_outcome, TestResult::TestPass);
+        assert_eq!(
+            regression.new_outcome,
+            TestResult::BuildFail(FailureReason::Unknown)
+        );
+        assert_eq!(
+            regression.log_url.as_ref().map(|url| url.as_str()),
+            Some("https://example.com/dummy/beta/reg/lazy_static-1.0.0/log.txt")
+        );
+        assert_eq!(
+            load_regressions(&db, &config, &ex, Confidence::High, false).unwrap(),
+            regressions
+        );
+    }
+
+    #[test]
+    fn test_known_failures() {
+        let db = Database::temp().unwrap();
+        let mut config = Config::default();
+        let ex = setup(&db, &config);
+        config.known_failures.push(KnownFailure {
+            crate_pattern: "lazy_*".into(),
+            toolchain_pattern: "*".into(),
+            error_code: None,
+            reason: "upstream bug".into(),
+        });
+
+        assert!(load_regressions(&db, &config, &ex, Confidence::Low, false)
+            .unwrap()
+            .is_empty());
+        let included = load_regressions(&db, &config, &ex, Confidence::Low, true).unwrap();
+        assert_eq!(included.len(), 1);
+        assert_eq!(included[0].krate, "lazy_static");
+        assert_eq!(included[0].known_failure, Some("upstream bug".into()));
+
+        // The exported results are classified the same way
+        let results = load_results(&db, &config, &ex, 0, None).unwrap();
+        assert!(results
+            .iter()
+            .filter(|result| result.name == "lazy_static")
+            .all(|result| result.category == Comparison::KnownFailure));
+    }
+
+    #[test]
+    fn test_spurious_regressions_confidence() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ex = setup(&db, &config);
+
+        // Running out of memory is likely to be flaky
+        let krate = Crate::Registry(RegistryCrate {
+            name: "bitflags".into(),
+            version: "1.0.0".into(),
+        });
+        for (toolchain, result) in &[
+            (&*MAIN_TOOLCHAIN, TestResult::TestPass),
+            (&*TEST_TOOLCHAIN, TestResult::BuildFail(FailureReason::OOM)),
+        ] {
+            DatabaseDB::new(&db)
+                .store(
+                    &ex,
+                    &ProgressData {
+                        results: vec![TaskResult {
+                            krate: krate.clone(),
+                            toolchain: (*toolchain).clone(),
+                            result: *result,
+                            log: base64::encode("log"),
+                            duration_ms: None,
+                            warnings: None,
+                            artifacts_size: None,
+                            phases: Vec::new(),
+                            error_category: None,
+                        }],
+                        shas: Vec::new(),
+                        dependencies: Vec::new(),
+                        dependency_trees: Vec::new(),
+                        docker_image: None,
+                        environment: None,
+                    },
+                )
+                .unwrap();
+        }
+
+        let all = load_regressions(&db, &config, &ex, Confidence::Low, false).unwrap();
+        assert_eq!(
+            all.iter().map(|r| r.krate.as_str()).collect::<Vec<_>>(),
+            vec!["bitflags", "lazy_static"]
+        );
+        let high = load_regressions(&db, &config, &ex, Confidence::High, false).unwrap();
+        assert_eq!(
+            high.iter().map(|r| r.krate.as_str()).collect::<Vec<_>>(),
+            vec!["lazy_static"]
+        );
+    }
+
+    #[test]
+    fn test_export_pagination() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ex = setup(&db, &config);
+        let all = load_results(&db, &config, &ex, 0, None).unwrap();
+
+        let first = load_page(&db, &config, &ex, 1, 3).unwrap();
+        assert_eq!(first.total, 4);
+        assert_eq!(first.next_page, Some(2));
+        assert_eq!(first.results.as_slice(), &all[..3]);
+
+        let second = load_page(&db, &config, &ex, 2, 3).unwrap();
+        assert_eq!(second.total, 4);
+        assert_eq!(second.next_page, None);
+        assert_eq!(second.results.as_slice(), &all[3..]);
+
+        assert!(load_page(&db, &config, &ex, 3, 3)
+            .unwrap()
+            .results
+            .is_empty());
+    }
+}
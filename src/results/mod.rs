@@ -1,21 +1,49 @@
+pub mod annotations;
+pub mod crates;
 mod db;
+pub mod dependencies;
 #[cfg(test)]
 mod dummy;
+pub mod export;
 
 use crate::config::Config;
 use crate::crates::{Crate, GitHubRepo};
 use crate::experiments::Experiment;
 use crate::logs::LogStorage;
 use crate::prelude::*;
-pub use crate::results::db::{DatabaseDB, ProgressData};
+use crate::results::annotations::Annotation;
+pub(crate) use crate::results::db::log_hash;
+pub use crate::results::db::{
+    ChangedCrate, CrateDiagnostics, CrateHistoryEntry, CrateRun, DatabaseDB, ExperimentUsage,
+    LogsStats, PartialProgressData, PhaseResult, ProgressData, ResultsSummary, TaskResult,
+};
 #[cfg(test)]
 pub use crate::results::dummy::DummyDB;
 use crate::toolchain::Toolchain;
 use std::collections::HashMap;
 use std::{fmt, str::FromStr};
+use utoipa::ToSchema;
+
+/// Description of the environment an experiment ran in, used to spot differences between runs.
+/// Every part of it is optional, as failing to compute one of them shouldn't stop the experiment.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct EnvironmentFingerprint {
+    pub docker_image: Option<String>,
+    pub kernel: Option<String>,
+    pub toolchains: Vec<ToolchainFingerprint>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct ToolchainFingerprint {
+    pub toolchain: String,
+    pub rustc: Option<String>,
+    pub cargo: Option<String>,
+}
 
 pub trait ReadResults {
     fn load_all_shas(&self, ex: &Experiment) -> Fallible<HashMap<GitHubRepo, String>>;
+    fn load_docker_image(&self, ex: &Experiment) -> Fallible<Option<String>>;
+    fn load_environments(&self, ex: &Experiment) -> Fallible<Vec<EnvironmentFingerprint>>;
     fn load_log(
         &self,
         ex: &Experiment,
@@ -28,6 +56,40 @@ pub trait ReadResults {
         toolchain: &Toolchain,
         krate: &Crate,
     ) -> Fallible<Option<TestResult>>;
+    fn load_warnings(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<u32>>;
+    fn load_artifacts_size(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<u64>>;
+    /// Results of the phases of the job, if they were recorded separately.
+    fn load_phases(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Vec<(Phase, TestResult)>>;
+    fn load_phase_log(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        phase: Phase,
+    ) -> Fallible<Option<Vec<u8>>>;
+    fn load_annotation(&self, ex: &Experiment, krate: &Crate) -> Fallible<Option<Annotation>>;
+    /// Errors and warnings parsed from the log of the job.
+    fn load_toolchain_diagnostics(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Vec<Diagnostic>>;
 }
 
 pub trait WriteResults {
@@ -38,6 +100,43 @@ pub trait WriteResults {
         krate: &Crate,
     ) -> Fallible<Option<TestResult>>;
     fn record_sha(&self, ex: &Experiment, repo: &GitHubRepo, sha: &str) -> Fallible<()>;
+    fn record_dependencies(
+        &self,
+        ex: &Experiment,
+        krate: &Crate,
+        dependencies: &[String],
+    ) -> Fallible<()>;
+    fn record_dependency_tree(
+        &self,
+        ex: &Experiment,
+        krate: &Crate,
+        edges: &[(String, String)],
+    ) -> Fallible<()>;
+    fn record_docker_image(&self, ex: &Experiment, digest: &str) -> Fallible<()>;
+    fn record_environment(&self, ex: &Experiment, env: &EnvironmentFingerprint) -> Fallible<()>;
+    fn record_broken_toolchain(&self, ex: &Experiment, toolchain: &Toolchain) -> Fallible<()>;
+    /// Record the size of the artifacts of the build, stored along with its result.
+    fn record_artifacts_size(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        size: u64,
+    ) -> Fallible<()>;
+    /// Record the result and the log of a single phase of the job, stored along with its result.
+    fn record_phase(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+        phase: Phase,
+        result: TestResult,
+        log: &[u8],
+    ) -> Fallible<()>;
+    fn stop_requested(&self) -> bool;
+    /// Report that the experiment is paused because of a problem on the machine running it, or
+    /// that it's running again if `reason` is `None`.
+    fn set_unhealthy(&self, reason: Option<String>);
     fn record_result<F>(
         &self,
         ex: &Experiment,
@@ -99,6 +198,18 @@ macro_rules! test_result_enum {
                 }
             }
         }
+
+        impl_string_schema!($name, values = {
+            let mut values = Vec::new();
+            $(
+                values.push($with_reason_repr.to_string());
+                for reason in FailureReason::possible_values() {
+                    values.push(format!("{}:{}", $with_reason_repr, reason));
+                }
+            )*
+            $(values.push($reasonless_repr.to_string());)*
+            values
+        });
     }
 }
 
@@ -117,13 +228,15 @@ string_enum!(pub enum FailureReason {
     Broken => "broken",
     OOM => "oom",
     Timeout => "timeout",
+    SetupFailed => "setup-failed",
+    DownloadFailed => "download-failed",
 });
 
 impl FailureReason {
     pub(crate) fn is_spurious(self) -> bool {
         match self {
-            FailureReason::Unknown | FailureReason::Broken => false,
-            FailureReason::OOM | FailureReason::Timeout => true,
+            FailureReason::Unknown | FailureReason::Broken | FailureReason::SetupFailed => false,
+            FailureReason::OOM | FailureReason::Timeout | FailureReason::DownloadFailed => true,
         }
     }
 }
@@ -142,6 +255,43 @@ test_result_enum!(pub enum TestResult {
 
 impl_serde_from_parse!(TestResult, expecting = "a test result");
 
+/// Kind of error that made a job fail, found in its log to show the patterns of the failures.
+string_enum!(pub enum ErrorCategory {
+    TypeCheckError => "type-check-error",
+    LinkerError => "linker-error",
+    OutOfMemory => "out-of-memory",
+    ProcMacroError => "proc-macro-error",
+    MissingFeature => "missing-feature",
+    UnstableFeature => "unstable-feature",
+    DocTestError => "doc-test-error",
+    TestFailure => "test-failure",
+    Timeout => "timeout",
+    Unknown => "unknown",
+});
+
+/// Diagnostic emitted by the compiler while building a crate, parsed from its JSON output.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct Diagnostic {
+    /// `error` or `warning`.
+    pub level: String,
+    /// Error code (like `E0308`) or name of the lint that emitted the diagnostic.
+    pub code: Option<String>,
+    pub message: String,
+    /// Location of the primary span, relative to the root of the crate.
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+/// Part of a job whose result and log can be stored separately from the ones of the whole job,
+/// when `sandbox.record-phases` is enabled.
+string_enum!(pub enum Phase {
+    Setup => "setup",
+    Build => "build",
+    Test => "test",
+});
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -171,6 +321,8 @@ mod tests {
         test_from_str! {
             "build-fail:unknown" => BuildFail(Unknown),
             "build-fail:oom" => BuildFail(OOM),
+            "build-fail:setup-failed" => BuildFail(SetupFailed),
+            "build-fail:download-failed" => BuildFail(DownloadFailed),
             "test-fail:timeout" => TestFail(Timeout),
             "test-pass" => TestPass,
             "error" => Error,
@@ -1,6 +1,6 @@
 use crate::dirs::{CARGO_HOME, RUSTUP_HOME};
 use crate::docker::DockerEnv;
-use crate::docker::{ContainerBuilder, MountPerms};
+use crate::docker::{ContainerBuilder, MountPerms, SETUP_FAILED_EXIT_CODE};
 use crate::native;
 use crate::prelude::*;
 use crate::utils::size::Size;
@@ -63,6 +63,7 @@ pub(crate) struct RunCommand {
     binary: Binary,
     args: Vec<OsString>,
     env: Vec<(OsString, OsString)>,
+    secret_env: Vec<(OsString, OsString)>,
     cd: Option<PathBuf>,
     quiet: bool,
     enable_timeout: bool,
@@ -76,6 +77,7 @@ impl RunCommand {
             binary: runnable.binary(),
             args: Vec::new(),
             env: Vec::new(),
+            secret_env: Vec::new(),
             cd: None,
             quiet: false,
             enable_timeout: true,
@@ -98,6 +100,17 @@ impl RunCommand {
         self
     }
 
+    /// Set an environment variable whose value is never included in the logs or in the errors.
+    pub(crate) fn secret_env<S1: AsRef<OsStr>, S2: AsRef<OsStr>>(
+        mut self,
+        key: S1,
+        value: S2,
+    ) -> Self {
+        self.secret_env
+            .push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
     pub(crate) fn cd<P: AsRef<Path>>(mut self, path: P) -> Self {
         self.cd = Some(path.as_ref().to_path_buf());
         self
@@ -167,6 +180,9 @@ impl RunCommand {
         }
 
         let cmdstr = format!("{:?}", cmd);
+        for &(ref k, ref v) in &self.secret_env {
+            cmd.env(k, v);
+        }
 
         if let Some(ref cd) = self.cd {
             cmd.current_dir(cd);
@@ -196,6 +212,7 @@ impl RunCommand {
 pub(crate) struct SandboxedCommand<'a> {
     command: RunCommand,
     container: ContainerBuilder<'a>,
+    apt_packages: Vec<String>,
 }
 
 impl<'a> SandboxedCommand<'a> {
@@ -204,7 +221,18 @@ impl<'a> SandboxedCommand<'a> {
             .env("USER_ID", native::current_user().to_string())
             .enable_networking(false);
 
-        SandboxedCommand { command, container }
+        SandboxedCommand {
+            command,
+            container,
+            apt_packages: Vec::new(),
+        }
+    }
+
+    /// Install these packages from the local cache of the image before running the command. The
+    /// container exits with `SETUP_FAILED_EXIT_CODE` if they can't be installed.
+    pub(crate) fn apt_install(mut self, packages: Vec<String>) -> Self {
+        self.apt_packages = packages;
+        self
     }
 
     pub(crate) fn memory_limit(mut self, limit: Option<Size>) -> Self {
@@ -222,7 +250,28 @@ impl<'a> SandboxedCommand<'a> {
         self
     }
 
-    pub(crate) fn run(mut self) -> Fallible<()> {
+    pub(crate) fn run(self) -> Fallible<()> {
+        let quiet = self.command.quiet;
+        let (container, cmd) = self.into_container();
+        debug!("running `{}` in the sandbox", cmd.join(" "));
+        container.cmd(cmd).run(quiet)
+    }
+
+    /// Start an interactive shell in the sandbox instead of running the command, which is shown
+    /// to the user so they can run it themselves.
+    pub(crate) fn shell(self) -> Fallible<()> {
+        let (container, cmd) = self.into_container();
+        info!(
+            "starting a shell in the sandbox, the command is `{}`",
+            cmd.join(" ")
+        );
+        container
+            .cmd(vec!["bash".into()])
+            .interactive(true)
+            .run(false)
+    }
+
+    fn into_container(mut self) -> (ContainerBuilder<'a>, Vec<String>) {
         // Build the full CLI
         let mut cmd = Vec::new();
         cmd.push(
@@ -241,6 +290,13 @@ impl<'a> SandboxedCommand<'a> {
             cmd.push(arg.to_string_lossy().to_string());
         }
 
+        if !self.apt_packages.is_empty() {
+            cmd = with_apt_install(cmd);
+            self.container = self
+                .container
+                .env("CRATER_APT_PACKAGES", self.apt_packages.join(" "));
+        }
+
         let source_dir = match self.command.cd {
             Some(path) => path,
             None => PathBuf::from("."),
@@ -251,8 +307,7 @@ impl<'a> SandboxedCommand<'a> {
             .mount(source_dir, "/opt/crater/workdir", MountPerms::ReadOnly)
             .env("SOURCE_DIR", "/opt/crater/workdir")
             .env("MAP_USER_ID", native::current_user().to_string())
-            .workdir("/opt/crater/workdir")
-            .cmd(cmd);
+            .workdir("/opt/crater/workdir");
 
         for (key, value) in self.command.env {
             self.container = self.container.env(
@@ -260,6 +315,12 @@ impl<'a> SandboxedCommand<'a> {
                 value.to_string_lossy().as_ref(),
             );
         }
+        for (key, value) in self.command.secret_env {
+            self.container = self.container.secret_env(
+                key.to_string_lossy().as_ref(),
+                value.to_string_lossy().as_ref(),
+            );
+        }
 
         if self.command.local_rustup {
             self.container = self
@@ -274,10 +335,22 @@ impl<'a> SandboxedCommand<'a> {
                 .env("RUSTUP_HOME", "/opt/crater/rustup-home");
         }
 
-        self.container.run(self.command.quiet)
+        (self.container, cmd)
     }
 }
 
+/// Wrap the command in a shell installing the packages listed in `CRATER_APT_PACKAGES` first. The
+/// network is disabled in the sandbox, so only the packages cached in the image can be installed.
+fn with_apt_install(cmd: Vec<String>) -> Vec<String> {
+    let script = format!(
+        "apt-get install -y --no-download $CRATER_APT_PACKAGES || exit {}; exec \"$@\"",
+        SETUP_FAILED_EXIT_CODE
+    );
+    let mut wrapped = vec!["sh".into(), "-c".into(), script, "sh".into()];
+    wrapped.extend(cmd);
+    wrapped
+}
+
 struct ProcessOutput {
     status: ExitStatus,
     stdout: Vec<String>,
@@ -397,3 +470,20 @@ fn log_command(
         stderr,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::with_apt_install;
+
+    #[test]
+    fn test_with_apt_install() {
+        let cmd = with_apt_install(vec!["cargo".into(), "build".into()]);
+        assert_eq!(&cmd[..2], &["sh", "-c"]);
+        assert_eq!(
+            cmd[2],
+            "apt-get install -y --no-download $CRATER_APT_PACKAGES || exit 97; exec \"$@\""
+        );
+        // The command is passed as the arguments of the script, so it's not parsed by the shell
+        assert_eq!(&cmd[3..], &["sh", "cargo", "build"]);
+    }
+}
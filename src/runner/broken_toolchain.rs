@@ -0,0 +1,68 @@
+use crate::toolchain::Toolchain;
+
+/// Messages printed when the toolchain itself is broken, rather than the crate being tested.
+static SIGNATURES: &[&str] = &[
+    "couldn't load codegen backend",
+    "error while loading shared libraries: librustc",
+    "error while loading shared libraries: libstd",
+    "is not installed for the toolchain",
+];
+
+/// Missing standard library crates are caused by a broken sysroot, unless the crate is being
+/// built for a target that isn't installed (in which case rustc adds this note).
+static MISSING_SYSROOT_CRATES: &[&str] =
+    &["can't find crate for `std`", "can't find crate for `core`"];
+static MISSING_TARGET_NOTE: &str = "target may not be installed";
+
+#[derive(Debug, Fail)]
+#[fail(display = "the toolchain {} is broken", _0)]
+pub(crate) struct BrokenToolchain(pub(crate) Toolchain);
+
+/// Check whether the log of a failed build contains the signature of a broken toolchain.
+pub(crate) fn is_broken_toolchain_log(log: &str) -> bool {
+    if SIGNATURES.iter().any(|sig| log.contains(sig)) {
+        return true;
+    }
+
+    MISSING_SYSROOT_CRATES.iter().any(|sig| log.contains(sig)) && !log.contains(MISSING_TARGET_NOTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_broken_toolchain_log;
+
+    #[test]
+    fn test_is_broken_toolchain_log() {
+        // Captured from builds with a corrupted toolchain
+        assert!(is_broken_toolchain_log(concat!(
+            "[INFO] [stderr]    Compiling libc v0.2.43\n",
+            "[INFO] [stderr] error: couldn't load codegen backend \"/opt/crater/rustup-home/",
+            "toolchains/4b6f4a6-alt/lib/rustlib/x86_64-unknown-linux-gnu/codegen-backends/",
+            "librustc_codegen_llvm-llvm.so\": \"libLLVM-8svn.so: cannot open shared object ",
+            "file: No such file or directory\"\n",
+            "[INFO] [stderr] error: Could not compile `libc`.\n",
+        )));
+        assert!(is_broken_toolchain_log(concat!(
+            "[INFO] [stderr] /opt/crater/rustup-home/toolchains/nightly/bin/rustc: error while ",
+            "loading shared libraries: librustc_driver-0ad2d8d4.so: cannot open shared object ",
+            "file: No such file or directory\n",
+        )));
+        assert!(is_broken_toolchain_log(concat!(
+            "[INFO] [stderr]    Compiling lazy_static v1.1.0\n",
+            "[INFO] [stderr] error[E0463]: can't find crate for `std`\n",
+            "[INFO] [stderr]   |\n",
+            "[INFO] [stderr]   = note: the `x86_64-unknown-linux-gnu` sysroot is empty\n",
+        )));
+
+        // Failures caused by the crate itself
+        assert!(!is_broken_toolchain_log(concat!(
+            "[INFO] [stderr]    Compiling hello v0.1.0\n",
+            "[INFO] [stderr] error[E0425]: cannot find value `x` in this scope\n",
+        )));
+        assert!(!is_broken_toolchain_log(concat!(
+            "[INFO] [stderr] error[E0463]: can't find crate for `core`\n",
+            "[INFO] [stderr]   |\n",
+            "[INFO] [stderr]   = note: the `thumbv7em-none-eabihf` target may not be installed\n",
+        )));
+    }
+}
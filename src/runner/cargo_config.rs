@@ -0,0 +1,69 @@
+use crate::config::Config;
+use crate::prelude::*;
+use std::fs;
+use std::path::Path;
+use toml::Value;
+use url::Url;
+
+const MIRROR_SOURCE: &str = "crater-mirror";
+
+fn render(mirror: &Url) -> String {
+    format!(
+        "[source.crates-io]\nreplace-with = {}\n\n[source.{}]\nregistry = {}\n",
+        Value::String(MIRROR_SOURCE.into()),
+        MIRROR_SOURCE,
+        Value::String(mirror.to_string()),
+    )
+}
+
+/// Configure cargo to replace crates.io with the registry mirror, if one is set. The
+/// configuration is stored in crater's cargo home, which is also mounted in the sandbox.
+pub(super) fn write(config: &Config, cargo_home: &Path) -> Fallible<()> {
+    let path = cargo_home.join("config");
+
+    if let Some(ref mirror) = config.sandbox.registry_mirror {
+        info!("replacing crates.io with the registry mirror at {}", mirror);
+        fs::create_dir_all(cargo_home)?;
+        fs::write(&path, render(mirror).as_bytes())?;
+    } else if path.exists() {
+        // Remove the replacement written when a mirror was configured
+        fs::remove_file(&path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write;
+    use crate::config::Config;
+    use std::fs;
+    use tempfile::tempdir;
+    use toml::Value;
+
+    #[test]
+    fn test_registry_mirror() {
+        let cargo_home = tempdir().unwrap();
+        let path = cargo_home.path().join("config");
+
+        let mut config = Config::default();
+        config.sandbox.registry_mirror = Some("https://mirror.example.com/index".parse().unwrap());
+        write(&config, cargo_home.path()).unwrap();
+
+        let content: Value = ::toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let source = &content["source"];
+        assert_eq!(
+            source["crates-io"]["replace-with"].as_str(),
+            Some("crater-mirror")
+        );
+        assert_eq!(
+            source["crater-mirror"]["registry"].as_str(),
+            Some("https://mirror.example.com/index")
+        );
+
+        // The replacement is removed when the mirror is not configured anymore
+        config.sandbox.registry_mirror = None;
+        write(&config, cargo_home.path()).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::results::Diagnostic;
+use crate::runner::warnings::strip_prefixes;
+use serde_json::Value;
+
+/// Format of the messages cargo is asked to emit: JSON objects containing the diagnostics rendered
+/// as usual, so the log can still be understood without parsing it.
+pub(super) static MESSAGE_FORMAT: &str = "json-diagnostic-rendered-ansi";
+
+/// Summaries emitted by rustc after the diagnostics of a crate, which don't point to anything.
+fn is_summary(level: &str, message: &str) -> bool {
+    message.starts_with("aborting due to") || (level == "warning" && message.ends_with(" emitted"))
+}
+
+/// Parse a diagnostic emitted with `--message-format=json`, either wrapped by cargo in a
+/// `compiler-message` or printed by rustc itself. Only the errors and warnings are returned.
+pub(super) fn parse_line(line: &str) -> Option<Diagnostic> {
+    let value: Value = ::serde_json::from_str(line).ok()?;
+    let diagnostic = if value["reason"] == "compiler-message" {
+        &value["message"]
+    } else {
+        &value
+    };
+
+    let level = diagnostic["level"].as_str()?;
+    let message = diagnostic["message"].as_str()?;
+    if !(level.starts_with("error") || level == "warning") || is_summary(level, message) {
+        return None;
+    }
+
+    let spans = diagnostic["spans"].as_array().map(Vec::as_slice);
+    let span = spans.and_then(|spans| {
+        spans
+            .iter()
+            .find(|span| span["is_primary"] == true)
+            .or_else(|| spans.first())
+    });
+    let number = |key: &str| span.and_then(|span| span[key].as_u64()).map(|n| n as u32);
+    Some(Diagnostic {
+        level: level.to_string(),
+        code: diagnostic["code"]["code"].as_str().map(String::from),
+        message: message.to_string(),
+        file: span.and_then(|span| span["file_name"].as_str().map(String::from)),
+        line: number("line_start"),
+        column: number("column_start"),
+    })
+}
+
+/// Extract the diagnostics from the log of a job, in the order they were emitted. The ones emitted
+/// more than once, for example when the library is built again for its tests, are only kept once.
+pub(crate) fn parse_diagnostics(log: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for line in log.lines().map(strip_prefixes) {
+        if !line.starts_with('{') {
+            continue;
+        }
+        if let Some(diagnostic) = parse_line(line) {
+            if !diagnostics.contains(&diagnostic) {
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_diagnostics;
+    use crate::results::Diagnostic;
+
+    #[test]
+    fn test_parse_diagnostics() {
+        // Captured from `cargo build --message-format=json-diagnostic-rendered-ansi`, trimmed
+        let log = concat!(
+            "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"unused variable: `x`\",\"code\":{\"code\":",
+            "\"unused_variables\",\"explanation\":null},\"level\":\"warning\",\"spans\":[{",
+            "\"file_name\":\"src/main.rs\",\"line_start\":2,\"column_start\":9,",
+            "\"is_primary\":true}],\"children\":[],\"rendered\":\"\\u001b[33mwarning\"}}\n",
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"mismatched types\",\"code\":{\"code\":\"E0308\",",
+            "\"explanation\":\"...\"},\"level\":\"error\",\"spans\":[{\"file_name\":",
+            "\"src/lib.rs\",\"line_start\":1,\"column_start\":1,\"is_primary\":false},",
+            "{\"file_name\":\"src/lib.rs\",\"line_start\":5,\"column_start\":12,",
+            "\"is_primary\":true}],\"children\":[],\"rendered\":\"error\"}}\n",
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"aborting due to previous error\",\"code\":null,",
+            "\"level\":\"error\",\"spans\":[],\"children\":[],\"rendered\":\"error\"}}\n",
+            "[INFO] [stdout] {\"reason\":\"build-finished\",\"success\":false}\n",
+            "[INFO] [stderr] error: could not compile `hello`.\n",
+            // Emitted again while building the tests
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"unused variable: `x`\",\"code\":{\"code\":",
+            "\"unused_variables\",\"explanation\":null},\"level\":\"warning\",\"spans\":[{",
+            "\"file_name\":\"src/main.rs\",\"line_start\":2,\"column_start\":9,",
+            "\"is_primary\":true}],\"children\":[],\"rendered\":\"\\u001b[33mwarning\"}}\n",
+        );
+
+        assert_eq!(
+            parse_diagnostics(log),
+            vec![
+                Diagnostic {
+                    level: "warning".into(),
+                    code: Some("unused_variables".into()),
+                    message: "unused variable: `x`".into(),
+                    file: Some("src/main.rs".into()),
+                    line: Some(2),
+                    column: Some(9),
+                },
+                Diagnostic {
+                    level: "error".into(),
+                    code: Some("E0308".into()),
+                    message: "mismatched types".into(),
+                    file: Some("src/lib.rs".into()),
+                    line: Some(5),
+                    column: Some(12),
+                },
+            ]
+        );
+
+        // Errors without a location are kept, like the ones of the linker
+        let log = concat!(
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"linking with `cc` failed: exit code: 1\",\"code\":null,",
+            "\"level\":\"error\",\"spans\":[],\"children\":[],\"rendered\":\"error\"}}\n",
+        );
+        assert_eq!(
+            parse_diagnostics(log),
+            vec![Diagnostic {
+                level: "error".into(),
+                code: None,
+                message: "linking with `cc` failed: exit code: 1".into(),
+                file: None,
+                line: None,
+                column: None,
+            }]
+        );
+
+        // Logs of the builds before the switch to JSON have no diagnostics
+        assert!(parse_diagnostics("[INFO] [stderr] error[E0308]: mismatched types\n").is_empty());
+    }
+}
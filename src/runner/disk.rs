@@ -0,0 +1,151 @@
+use crate::config::DiskSpaceConfig;
+use crate::dirs::WORK_DIR;
+use crate::prelude::*;
+use crate::results::WriteResults;
+use crate::utils;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Prevent the builds from failing in confusing ways when the disk fills up, by checking there's
+/// enough free space before preparing each crate.
+pub(super) struct DiskGuard {
+    min_free: u64,
+    retry_interval: Duration,
+    /// Only one worker at a time checks the disk, so the others wait while the agent is paused.
+    lock: Mutex<()>,
+}
+
+impl DiskGuard {
+    pub(super) fn new(config: &DiskSpaceConfig) -> Self {
+        DiskGuard {
+            min_free: config.min_free.to_bytes() as u64,
+            retry_interval: Duration::from_secs(config.retry_interval),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Ensure there's enough free space to prepare a crate, removing the crate sources cache and
+    /// the stopped containers if there isn't. If that's not enough the agent is reported as
+    /// unhealthy, and this waits until there's enough space or the experiment is stopped.
+    pub(super) fn ensure_free_space<DB: WriteResults>(&self, db: &DB) {
+        self.ensure_with(
+            db,
+            || crate::native::free_disk_space(&WORK_DIR),
+            || {
+                crate::crates::prune_registry_cache()?;
+                crate::docker::prune_containers()
+            },
+        )
+    }
+
+    fn ensure_with<DB, S, C>(&self, db: &DB, free_space: S, cleanup: C)
+    where
+        DB: WriteResults,
+        S: Fn() -> Fallible<u64>,
+        C: Fn() -> Fallible<()>,
+    {
+        let _lock = self.lock.lock().unwrap();
+
+        let mut unhealthy = false;
+        loop {
+            let free = match free_space() {
+                Ok(free) => free,
+                Err(err) => {
+                    // Not knowing the free space shouldn't prevent the crates from being built
+                    warn!("failed to check the free disk space");
+                    utils::report_failure(&err);
+                    return;
+                }
+            };
+            if free >= self.min_free {
+                if unhealthy {
+                    info!("enough disk space was freed, resuming the experiment");
+                    db.set_unhealthy(None);
+                }
+                return;
+            }
+
+            // Cleaning up is attempted again after each pause, as the workers might have left
+            // something behind in the meantime
+            warn!(
+                "only {} MB of free disk space left, cleaning up",
+                free / 1024 / 1024
+            );
+            if let Err(err) = cleanup() {
+                utils::report_failure(&err);
+            }
+
+            match free_space() {
+                Ok(free) if free < self.min_free => {}
+                _ => continue,
+            }
+            if !unhealthy {
+                let reason = format!(
+                    "less than {} MB of free disk space",
+                    self.min_free / 1024 / 1024
+                );
+                error!("{}, pausing the experiment", reason);
+                db.set_unhealthy(Some(reason));
+                unhealthy = true;
+            }
+            if db.stop_requested() {
+                return;
+            }
+            thread::sleep(self.retry_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiskGuard;
+    use crate::config::DiskSpaceConfig;
+    use crate::db::Database;
+    use crate::results::DatabaseDB;
+    use crate::utils::size::Size;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_cleanup_when_disk_is_full() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let guard = DiskGuard::new(&DiskSpaceConfig {
+            min_free: Size::Gigabytes(1),
+            retry_interval: 0,
+        });
+        let (low, enough) = (1024 * 1024, 2 * 1024 * 1024 * 1024);
+
+        let free = Cell::new(enough);
+        let cleanups = Cell::new(0);
+        let cleanup = || {
+            cleanups.set(cleanups.get() + 1);
+            free.set(enough);
+            Ok(())
+        };
+
+        // Nothing is removed while there's enough space
+        guard.ensure_with(&results, || Ok(free.get()), &cleanup);
+        assert_eq!(cleanups.get(), 0);
+
+        // Running low on space triggers the cleanup
+        free.set(low);
+        guard.ensure_with(&results, || Ok(free.get()), &cleanup);
+        assert_eq!(cleanups.get(), 1);
+
+        // If cleaning up isn't enough the guard waits and tries again
+        let checks = Cell::new(0);
+        guard.ensure_with(
+            &results,
+            || {
+                checks.set(checks.get() + 1);
+                Ok(if checks.get() > 4 { enough } else { low })
+            },
+            || {
+                cleanups.set(cleanups.get() + 1);
+                Ok(())
+            },
+        );
+        assert_eq!(cleanups.get(), 3);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::results::{ErrorCategory, FailureReason, TestResult};
+use crate::runner::diagnostics;
+use crate::runner::warnings::strip_prefixes;
+
+/// Categories found in the compiler errors, from the most to the least specific: a linker error
+/// is usually followed by errors about the crate that couldn't be compiled, for example.
+static DIAGNOSTIC_CATEGORIES: &[ErrorCategory] = &[
+    ErrorCategory::LinkerError,
+    ErrorCategory::ProcMacroError,
+    ErrorCategory::UnstableFeature,
+    ErrorCategory::MissingFeature,
+    ErrorCategory::TypeCheckError,
+];
+
+struct Diagnostic {
+    code: Option<String>,
+    message: String,
+}
+
+impl Diagnostic {
+    /// Parse a diagnostic emitted with `--error-format=json`, either by rustc itself or wrapped
+    /// by cargo in a `compiler-message`. Only the errors are returned.
+    fn from_json(line: &str) -> Option<Self> {
+        let diagnostic = diagnostics::parse_line(line)?;
+        if diagnostic.level != "error" {
+            return None;
+        }
+
+        Some(Diagnostic {
+            code: diagnostic.code,
+            message: diagnostic.message,
+        })
+    }
+
+    /// Parse the first line of a rendered error, like `error[E0308]: mismatched types`.
+    fn from_text(line: &str) -> Option<Self> {
+        if line.starts_with("error: ") {
+            Some(Diagnostic {
+                code: None,
+                message: line["error: ".len()..].to_string(),
+            })
+        } else if line.starts_with("error[") {
+            let end = line.find("]: ")?;
+            Some(Diagnostic {
+                code: Some(line["error[".len()..end].to_string()),
+                message: line[end + "]: ".len()..].to_string(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn category(&self) -> Option<ErrorCategory> {
+        let message = self.message.as_str();
+        let code = self.code.as_ref().map(|code| code.as_str());
+        if message.starts_with("linking with `")
+            || message.starts_with("linker `")
+            || message.contains("could not find native static library")
+        {
+            Some(ErrorCategory::LinkerError)
+        } else if message.contains("proc-macro derive panicked")
+            || message.contains("proc macro panicked")
+            || message.contains("custom attribute panicked")
+        {
+            Some(ErrorCategory::ProcMacroError)
+        } else if code == Some("E0554")
+            || code == Some("E0658")
+            || message.contains("requires a nightly version")
+        {
+            Some(ErrorCategory::UnstableFeature)
+        } else if message.contains("does not have these features")
+            || message.contains("does not have the feature")
+            || message.contains("requires the features")
+        {
+            Some(ErrorCategory::MissingFeature)
+        } else if code.map(|code| code.starts_with('E')).unwrap_or(false) {
+            Some(ErrorCategory::TypeCheckError)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether the log shows a failed doc test, with the sections libtest prints for them (like
+/// `---- src/lib.rs - foo (line 3) stdout ----`) or the message printed by cargo.
+fn doc_tests_failed(log: &str) -> bool {
+    log.lines().map(strip_prefixes).any(|line| {
+        (line.starts_with("---- ") && line.contains(" - ") && line.contains("(line "))
+            || (line.contains("to rerun pass") && line.contains("--doc"))
+    })
+}
+
+/// Classify the failure of a job from its result and its log. Both the diagnostics emitted with
+/// `--error-format=json` and the rendered ones are understood. Jobs that didn't fail have no
+/// category.
+pub(crate) fn classify_error(result: TestResult, log: &str) -> Option<ErrorCategory> {
+    let reason = match result {
+        TestResult::BuildFail(reason) | TestResult::TestFail(reason) => Some(reason),
+        TestResult::Error => None,
+        TestResult::TestPass | TestResult::TestSkipped => return None,
+    };
+    match reason {
+        Some(FailureReason::OOM) => return Some(ErrorCategory::OutOfMemory),
+        Some(FailureReason::Timeout) => return Some(ErrorCategory::Timeout),
+        _ => {}
+    }
+
+    let is_test_fail = if let TestResult::TestFail(_) = result {
+        true
+    } else {
+        false
+    };
+    // Doc tests failing to compile also emit errors, which are not the ones of the crate
+    if is_test_fail && doc_tests_failed(log) {
+        return Some(ErrorCategory::DocTestError);
+    }
+
+    let found = log
+        .lines()
+        .map(strip_prefixes)
+        .filter_map(|line| {
+            if line.starts_with('{') {
+                Diagnostic::from_json(line)
+            } else {
+                Diagnostic::from_text(line)
+            }
+        })
+        .filter_map(|diagnostic| diagnostic.category())
+        .collect::<Vec<_>>();
+    if let Some(category) = DIAGNOSTIC_CATEGORIES
+        .iter()
+        .find(|category| found.contains(category))
+    {
+        return Some(*category);
+    }
+
+    Some(if is_test_fail {
+        ErrorCategory::TestFailure
+    } else {
+        ErrorCategory::Unknown
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::classify_error;
+    use crate::results::{ErrorCategory, FailureReason, TestResult};
+
+    const BUILD_FAIL: TestResult = TestResult::BuildFail(FailureReason::Unknown);
+    const TEST_FAIL: TestResult = TestResult::TestFail(FailureReason::Unknown);
+
+    #[test]
+    fn test_classify_results() {
+        assert_eq!(classify_error(TestResult::TestPass, ""), None);
+        assert_eq!(classify_error(TestResult::TestSkipped, ""), None);
+        assert_eq!(
+            classify_error(TestResult::Error, ""),
+            Some(ErrorCategory::Unknown)
+        );
+        assert_eq!(classify_error(BUILD_FAIL, ""), Some(ErrorCategory::Unknown));
+        assert_eq!(
+            classify_error(TEST_FAIL, ""),
+            Some(ErrorCategory::TestFailure)
+        );
+        assert_eq!(
+            classify_error(
+                TestResult::BuildFail(FailureReason::OOM),
+                "error[E0308]: foo"
+            ),
+            Some(ErrorCategory::OutOfMemory)
+        );
+        assert_eq!(
+            classify_error(TestResult::TestFail(FailureReason::Timeout), ""),
+            Some(ErrorCategory::Timeout)
+        );
+    }
+
+    #[test]
+    fn test_classify_json_diagnostics() {
+        let log = concat!(
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"unused variable: `x`\",\"code\":{\"code\":\"unused_variables\"},",
+            "\"level\":\"warning\",\"spans\":[]}}\n",
+            "[INFO] [stdout] {\"reason\":\"compiler-message\",\"package_id\":\"hello 0.1.0\",",
+            "\"message\":{\"message\":\"mismatched types\",\"code\":{\"code\":\"E0308\"},",
+            "\"level\":\"error\",\"spans\":[]}}\n",
+        );
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::TypeCheckError)
+        );
+
+        // Emitted by rustc itself
+        let log = concat!(
+            "[INFO] [stderr] {\"message\":\"`#![feature]` may not be used on the stable release ",
+            "channel\",\"code\":{\"code\":\"E0554\"},\"level\":\"error\",\"spans\":[]}\n",
+        );
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::UnstableFeature)
+        );
+    }
+
+    #[test]
+    fn test_classify_rendered_diagnostics() {
+        let log = concat!(
+            "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+            "[INFO] [stderr] error: linking with `cc` failed: exit code: 1\n",
+            "[INFO] [stderr] error: aborting due to previous error\n",
+            "[INFO] [stderr] error: could not compile `hello`.\n",
+        );
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::LinkerError)
+        );
+
+        // The linker error is more specific than the type errors following it
+        let log = concat!(
+            "[INFO] [stderr] error[E0425]: cannot find value `x` in this scope\n",
+            "[INFO] [stderr] error: linking with `cc` failed: exit code: 1\n",
+        );
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::LinkerError)
+        );
+
+        let log = "[INFO] [stderr] error: proc-macro derive panicked\n";
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::ProcMacroError)
+        );
+
+        let log = concat!(
+            "[INFO] [stderr] error: Package `hello v0.1.0 (/opt/crater/workdir)` does not have ",
+            "these features: `foo`\n",
+        );
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::MissingFeature)
+        );
+
+        let log = "[INFO] [stderr] error[E0658]: use of unstable library feature 'foo'\n";
+        assert_eq!(
+            classify_error(BUILD_FAIL, log),
+            Some(ErrorCategory::UnstableFeature)
+        );
+
+        // Errors in the tests
+        let log = "[INFO] [stderr] error[E0308]: mismatched types\n";
+        assert_eq!(
+            classify_error(TEST_FAIL, log),
+            Some(ErrorCategory::TypeCheckError)
+        );
+    }
+
+    #[test]
+    fn test_classify_doc_tests() {
+        let log = concat!(
+            "[INFO] [stdout] ---- src/lib.rs - foo (line 3) stdout ----\n",
+            "[INFO] [stdout] error[E0308]: mismatched types\n",
+            "[INFO] [stderr] error: test failed, to rerun pass '--doc'\n",
+        );
+        assert_eq!(
+            classify_error(TEST_FAIL, log),
+            Some(ErrorCategory::DocTestError)
+        );
+
+        let log = concat!(
+            "[INFO] [stdout] ---- tests::foo stdout ----\n",
+            "[INFO] [stdout] thread 'tests::foo' panicked at 'assertion failed'\n",
+        );
+        assert_eq!(
+            classify_error(TEST_FAIL, log),
+            Some(ErrorCategory::TestFailure)
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::results::{EnvironmentFingerprint, ToolchainFingerprint};
+use crate::run::RunCommand;
+use crate::tools::{CARGO, RUSTUP};
+use crate::utils;
+
+fn capture(what: &str, cmd: RunCommand) -> Option<String> {
+    match cmd.run_capture() {
+        Ok((stdout, _)) => Some(stdout.join("\n").trim().to_string()),
+        Err(err) => {
+            warn!("failed to detect the {} of the environment", what);
+            utils::report_failure(&err);
+            None
+        }
+    }
+}
+
+/// Describe the environment the experiment is running in. Errors are logged and the related
+/// parts of the fingerprint are left empty, as they shouldn't prevent the experiment from running.
+pub(super) fn compute(ex: &Experiment, docker_image: Option<&str>) -> EnvironmentFingerprint {
+    let toolchains = ex
+        .toolchains
+        .iter()
+        .map(|tc| {
+            let name = tc.rustup_name();
+            ToolchainFingerprint {
+                toolchain: tc.to_string(),
+                rustc: capture(
+                    "rustc version",
+                    RunCommand::new(&RUSTUP).args(&["run", name.as_str(), "rustc", "-vV"]),
+                ),
+                cargo: capture(
+                    "cargo version",
+                    RunCommand::new(CARGO.toolchain(tc)).args(&["-V"]),
+                ),
+            }
+        })
+        .collect();
+
+    EnvironmentFingerprint {
+        docker_image: docker_image.map(|image| image.to_string()),
+        kernel: capture("kernel version", RunCommand::new("uname").args(&["-r"])),
+        toolchains,
+    }
+}
@@ -17,6 +17,7 @@
 //                                   +---+ tc2 <---+
 
 use crate::config::Config;
+use crate::crates::Crate;
 use crate::experiments::{Experiment, Mode};
 use crate::prelude::*;
 use crate::results::{TestResult, WriteResults};
@@ -24,6 +25,7 @@ use crate::runner::{
     tasks::{Task, TaskStep},
     RunnerState,
 };
+use crate::toolchain::Toolchain;
 use failure::AsFail;
 use petgraph::{dot::Dot, graph::NodeIndex, stable_graph::StableDiGraph, Direction};
 use std::fmt::{self, Debug};
@@ -185,6 +187,16 @@ impl TasksGraph {
         self.graph.remove_node(node);
     }
 
+    /// Allow a running task to be picked up again.
+    pub(super) fn mark_as_pending(&mut self, node: NodeIndex) {
+        if let Node::Task {
+            ref mut running, ..
+        } = self.graph[node]
+        {
+            *running = false;
+        }
+    }
+
     pub(super) fn mark_as_failed<DB: WriteResults, F: AsFail>(
         &mut self,
         node: NodeIndex,
@@ -214,6 +226,19 @@ impl TasksGraph {
         Ok(())
     }
 
+    /// Crates that still have tasks to run, in the order the workers are going to pick them.
+    pub(super) fn pending_crates(&self) -> Vec<Crate> {
+        // Each crate completed node depends only on the cleanup task of its crate
+        self.graph
+            .neighbors(self.root)
+            .filter_map(|id| self.graph.neighbors(id).next())
+            .filter_map(|id| match self.graph[id] {
+                Node::Task { ref task, .. } => Some(task.krate.clone()),
+                Node::CrateCompleted | Node::Root => None,
+            })
+            .collect()
+    }
+
     pub(super) fn pending_crates_count(&self) -> usize {
         self.graph.neighbors(self.root).count()
     }
@@ -223,6 +248,41 @@ impl TasksGraph {
     }
 }
 
+/// Step building the crate with the toolchain, depending on the mode of the experiment.
+pub(super) fn build_step(
+    ex: &Experiment,
+    config: &Config,
+    krate: &Crate,
+    tc: &Toolchain,
+) -> TaskStep {
+    let quiet = config.is_quiet(krate);
+    match ex.mode {
+        Mode::BuildOnly => TaskStep::BuildOnly {
+            tc: tc.clone(),
+            quiet,
+        },
+        Mode::BuildAndTest if !ex.ignore_blacklist && config.should_skip_tests(krate) => {
+            TaskStep::BuildOnly {
+                tc: tc.clone(),
+                quiet,
+            }
+        }
+        Mode::BuildAndTest => TaskStep::BuildAndTest {
+            tc: tc.clone(),
+            quiet,
+        },
+        Mode::CheckOnly => TaskStep::CheckOnly {
+            tc: tc.clone(),
+            quiet,
+        },
+        Mode::Rustdoc => TaskStep::Rustdoc {
+            tc: tc.clone(),
+            quiet,
+        },
+        Mode::UnstableFeatures => TaskStep::UnstableFeatures { tc: tc.clone() },
+    }
+}
+
 pub(super) fn build_graph(ex: &Experiment, config: &Config) -> TasksGraph {
     let mut graph = TasksGraph::new();
 
@@ -239,39 +299,12 @@ pub(super) fn build_graph(ex: &Experiment, config: &Config) -> TasksGraph {
             &[],
         );
 
-        let quiet = config.is_quiet(krate);
         let mut builds = Vec::new();
-        for tc in &ex.toolchains {
+        for tc in &ex.build_toolchains() {
             let build_id = graph.add_task(
                 Task {
                     krate: krate.clone(),
-                    step: match ex.mode {
-                        Mode::BuildOnly => TaskStep::BuildOnly {
-                            tc: tc.clone(),
-                            quiet,
-                        },
-                        Mode::BuildAndTest
-                            if !ex.ignore_blacklist && config.should_skip_tests(krate) =>
-                        {
-                            TaskStep::BuildOnly {
-                                tc: tc.clone(),
-                                quiet,
-                            }
-                        }
-                        Mode::BuildAndTest => TaskStep::BuildAndTest {
-                            tc: tc.clone(),
-                            quiet,
-                        },
-                        Mode::CheckOnly => TaskStep::CheckOnly {
-                            tc: tc.clone(),
-                            quiet,
-                        },
-                        Mode::Rustdoc => TaskStep::Rustdoc {
-                            tc: tc.clone(),
-                            quiet,
-                        },
-                        Mode::UnstableFeatures => TaskStep::UnstableFeatures { tc: tc.clone() },
-                    },
+                    step: build_step(ex, config, krate, tc),
                 },
                 &[prepare_id],
             );
@@ -292,3 +325,50 @@ pub(super) fn build_graph(ex: &Experiment, config: &Config) -> TasksGraph {
 
     graph
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{build_graph, Node};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{Experiment, FeatureSet};
+    use crate::runner::tasks::TaskStep;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_feature_sets() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment {
+            feature_sets: vec![FeatureSet::Default, FeatureSet::NoDefault],
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        // Collect the feature sets each crate is built with on each toolchain
+        let graph = build_graph(&ex, &config);
+        let mut builds = HashMap::new();
+        for id in graph.graph.node_indices() {
+            if let Node::Task { ref task, .. } = graph.graph[id] {
+                if let TaskStep::BuildAndTest { ref tc, .. } = task.step {
+                    builds
+                        .entry((task.krate.clone(), tc.with_features(&FeatureSet::Default)))
+                        .or_insert_with(Vec::new)
+                        .push(tc.features.clone());
+                }
+            }
+        }
+
+        assert_eq!(builds.len(), ex.crates.len() * 2);
+        for features in builds.values() {
+            assert_eq!(features, &[FeatureSet::Default, FeatureSet::NoDefault]);
+        }
+    }
+}
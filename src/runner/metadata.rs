@@ -0,0 +1,236 @@
+use crate::prelude::*;
+use serde_json;
+use std::collections::HashMap;
+
+/// Subset of the output of `cargo metadata --format-version 1` needed to find the dependencies of
+/// a crate.
+#[derive(Deserialize)]
+pub(super) struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    /// Missing if the dependencies were not resolved.
+    resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Resolve {
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    id: String,
+    /// Ids of the packages this one depends on.
+    dependencies: Vec<String>,
+}
+
+impl Metadata {
+    pub(super) fn parse(metadata: &str) -> Fallible<Self> {
+        Ok(serde_json::from_str(metadata)?)
+    }
+
+    fn members(&self) -> Vec<&Package> {
+        self.packages
+            .iter()
+            .filter(|package| self.workspace_members.contains(&package.id))
+            .collect()
+    }
+
+    /// Names of the packages the crate directly depends on. The dependencies of all the members
+    /// of a workspace are included, except the ones between the members themselves.
+    pub(super) fn direct_dependencies(&self) -> Vec<String> {
+        let members = self.members();
+        let mut dependencies = members
+            .iter()
+            .flat_map(|package| package.dependencies.iter())
+            .filter(|dep| !members.iter().any(|member| member.name == dep.name))
+            .map(|dep| dep.name.clone())
+            .collect::<Vec<_>>();
+        dependencies.sort();
+        dependencies.dedup();
+        dependencies
+    }
+
+    /// Edges of the resolved dependency tree, as pairs of package names. Only the names are kept
+    /// to store the tree compactly, so multiple versions of the same package are merged, and the
+    /// edges pointing to the members of the workspace are skipped.
+    pub(super) fn dependency_edges(&self) -> Fallible<Vec<(String, String)>> {
+        let resolve = match self.resolve {
+            Some(ref resolve) => resolve,
+            None => bail!("the dependencies of the crate were not resolved"),
+        };
+        let names = self
+            .packages
+            .iter()
+            .map(|package| (package.id.as_str(), package.name.as_str()))
+            .collect::<HashMap<_, _>>();
+        let name = |id: &str| {
+            names
+                .get(id)
+                .map(|name| name.to_string())
+                .ok_or_else(|| err_msg(format!("unknown package in the dependency tree: {}", id)))
+        };
+
+        let mut edges = Vec::new();
+        for node in &resolve.nodes {
+            for dependency in &node.dependencies {
+                if self.workspace_members.contains(dependency) {
+                    continue;
+                }
+                edges.push((name(&node.id)?, name(dependency)?));
+            }
+        }
+        edges.sort();
+        edges.dedup();
+        Ok(edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+
+    #[test]
+    fn test_dependencies() {
+        // Captured from `cargo metadata --format-version 1` (trimmed) on a workspace with a
+        // proc-macro member
+        let metadata = r#"{
+            "packages": [
+                {
+                    "name": "hello",
+                    "version": "0.1.0",
+                    "id": "hello 0.1.0 (path+file:///opt/crater/workdir)",
+                    "source": null,
+                    "dependencies": [
+                        {
+                            "name": "serde",
+                            "source": "registry+https://github.com/rust-lang/crates.io-index",
+                            "req": "^1.0",
+                            "kind": null,
+                            "rename": null,
+                            "optional": false
+                        },
+                        {
+                            "name": "hello-macros",
+                            "source": null,
+                            "req": "*",
+                            "kind": null,
+                            "rename": null,
+                            "optional": false
+                        },
+                        {
+                            "name": "serde",
+                            "source": "registry+https://github.com/rust-lang/crates.io-index",
+                            "req": "^1.0",
+                            "kind": "build",
+                            "rename": null,
+                            "optional": false
+                        }
+                    ]
+                },
+                {
+                    "name": "hello-macros",
+                    "version": "0.1.0",
+                    "id": "hello-macros 0.1.0 (path+file:///opt/crater/workdir/macros)",
+                    "source": null,
+                    "dependencies": [
+                        {
+                            "name": "syn",
+                            "source": "registry+https://github.com/rust-lang/crates.io-index",
+                            "req": "^0.15",
+                            "kind": null,
+                            "rename": null,
+                            "optional": false
+                        }
+                    ]
+                },
+                {
+                    "name": "serde",
+                    "version": "1.0.89",
+                    "id": "serde 1.0.89 (registry+https://github.com/rust-lang/crates.io-index)",
+                    "source": "registry+https://github.com/rust-lang/crates.io-index",
+                    "dependencies": [
+                        {
+                            "name": "serde_derive",
+                            "source": "registry+https://github.com/rust-lang/crates.io-index",
+                            "req": "= 1.0.89",
+                            "kind": null,
+                            "rename": null,
+                            "optional": true
+                        }
+                    ]
+                },
+                {
+                    "name": "syn",
+                    "version": "0.15.29",
+                    "id": "syn 0.15.29 (registry+https://github.com/rust-lang/crates.io-index)",
+                    "source": "registry+https://github.com/rust-lang/crates.io-index",
+                    "dependencies": []
+                }
+            ],
+            "workspace_members": [
+                "hello 0.1.0 (path+file:///opt/crater/workdir)",
+                "hello-macros 0.1.0 (path+file:///opt/crater/workdir/macros)"
+            ],
+            "resolve": {
+                "nodes": [
+                    {
+                        "id": "hello 0.1.0 (path+file:///opt/crater/workdir)",
+                        "dependencies": [
+                            "hello-macros 0.1.0 (path+file:///opt/crater/workdir/macros)",
+                            "serde 1.0.89 (registry+https://github.com/rust-lang/crates.io-index)"
+                        ]
+                    },
+                    {
+                        "id": "hello-macros 0.1.0 (path+file:///opt/crater/workdir/macros)",
+                        "dependencies": [
+                            "syn 0.15.29 (registry+https://github.com/rust-lang/crates.io-index)"
+                        ]
+                    },
+                    {
+                        "id": "serde 1.0.89 (registry+https://github.com/rust-lang/crates.io-index)",
+                        "dependencies": []
+                    },
+                    {
+                        "id": "syn 0.15.29 (registry+https://github.com/rust-lang/crates.io-index)",
+                        "dependencies": []
+                    }
+                ],
+                "root": "hello 0.1.0 (path+file:///opt/crater/workdir)"
+            },
+            "target_directory": "/opt/crater/target",
+            "version": 1,
+            "workspace_root": "/opt/crater/workdir"
+        }"#;
+
+        let metadata = Metadata::parse(metadata).unwrap();
+
+        // The dependencies of the dependencies and the members of the workspace are excluded
+        assert_eq!(
+            metadata.direct_dependencies(),
+            vec!["serde".to_string(), "syn".to_string()]
+        );
+
+        // The resolved tree only contains the packages actually used, without the optional
+        // dependencies that are not enabled
+        let edge = |from: &str, to: &str| (from.to_string(), to.to_string());
+        assert_eq!(
+            metadata.dependency_edges().unwrap(),
+            vec![edge("hello", "serde"), edge("hello-macros", "syn")]
+        );
+
+        assert!(Metadata::parse("error: failed to parse manifest").is_err());
+    }
+}
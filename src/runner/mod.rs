@@ -1,24 +1,58 @@
+mod broken_toolchain;
+mod cargo_config;
+mod diagnostics;
+mod disk;
+mod error_category;
+mod fingerprint;
 mod graph;
+mod metadata;
+mod prefetch;
 mod prepare;
+mod reproduce;
+mod root_cause;
+mod secrets;
 mod tasks;
 mod test;
 mod toml_frobber;
 mod unstable_features;
+mod warnings;
 
 use crate::config::Config;
-use crate::crates::Crate;
+use crate::crates::{Crate, GitHubRepo};
+use crate::dirs::CARGO_HOME;
 use crate::docker::DockerEnv;
-use crate::experiments::Experiment;
+use crate::experiments::{Experiment, FeatureSet};
 use crate::logs::LogStorage;
 use crate::prelude::*;
 use crate::results::{FailureReason, TestResult, WriteResults};
+use crate::runner::broken_toolchain::BrokenToolchain;
+use crate::runner::disk::DiskGuard;
 use crate::runner::graph::{build_graph, WalkResult};
+use crate::runner::prefetch::Prefetcher;
+use crate::runner::secrets::{ExperimentSecrets, Secrets};
+use crate::runner::tasks::TaskStep;
+use crate::toolchain::Toolchain;
 use crate::utils;
 use crossbeam_utils::thread::scope;
+use failure::Error;
+use log::LevelFilter;
 use std::collections::HashMap;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::thread;
+use std::time::Instant;
+
+pub use crate::runner::reproduce::{reproduce, ReproducedResult, Reproduction};
+
+pub(crate) use crate::runner::broken_toolchain::is_broken_toolchain_log;
+pub(crate) use crate::runner::diagnostics::parse_diagnostics;
+pub(crate) use crate::runner::error_category::classify_error;
+pub(crate) use crate::runner::root_cause::failing_dependency;
+pub(crate) use crate::runner::warnings::count_warnings;
+
+/// Maximum number of times a broken toolchain is reinstalled during a single run.
+const MAX_TOOLCHAIN_REINSTALLS: usize = 3;
 
 #[derive(Debug, Fail)]
 #[fail(display = "overridden task result to {}", _0)]
@@ -30,17 +64,34 @@ struct RunnerStateInner {
 
 struct RunnerState {
     inner: Mutex<RunnerStateInner>,
+    secrets: ExperimentSecrets,
+    /// Commits the GitHub repositories are checked out at instead of their latest one.
+    pinned_shas: HashMap<GitHubRepo, String>,
 }
 
 impl RunnerState {
-    fn new() -> Self {
+    fn new(secrets: ExperimentSecrets) -> Self {
         RunnerState {
             inner: Mutex::new(RunnerStateInner {
                 prepare_logs: HashMap::new(),
             }),
+            secrets,
+            pinned_shas: HashMap::new(),
+        }
+    }
+
+    fn pinned_sha(&self, krate: &Crate) -> Option<&str> {
+        match *krate {
+            Crate::GitHub(ref repo) => self.pinned_shas.get(repo).map(|sha| sha.as_str()),
+            Crate::Registry(_) | Crate::Local(_) => None,
         }
     }
 
+    /// Create the storage of a build log, scrubbing the secrets of the experiment from it.
+    fn log_storage(&self, config: &Config) -> LogStorage {
+        LogStorage::new(LevelFilter::Info, config).redact(self.secrets.values())
+    }
+
     fn lock(&self) -> std::sync::MutexGuard<RunnerStateInner> {
         self.inner.lock().unwrap()
     }
@@ -68,63 +119,222 @@ pub fn run_ex<DB: WriteResults + Sync>(
     res
 }
 
-fn run_ex_inner<DB: WriteResults + Sync>(
+fn broken_toolchain(err: &Error) -> Option<&Toolchain> {
+    for cause in err.iter_chain() {
+        if let Some(&BrokenToolchain(ref tc)) = cause.downcast_ctx() {
+            return Some(tc);
+        }
+    }
+    None
+}
+
+fn handle_broken_toolchain<DB: WriteResults>(
+    ex: &Experiment,
+    db: &DB,
+    reinstalls: &Mutex<HashMap<Toolchain, (usize, Instant)>>,
+    tc: &Toolchain,
+    task_started_at: Instant,
+) -> Fallible<()> {
+    // The same installation is used by all the feature sets
+    let tc = &tc.with_features(&FeatureSet::Default);
+
+    // The lock is held during the reinstall, pausing the other threads finding the same problem
+    let mut reinstalls = reinstalls.lock().unwrap();
+    let attempts = match reinstalls.get(tc) {
+        // Another thread already reinstalled the toolchain after this task started
+        Some(&(_, reinstalled_at)) if reinstalled_at > task_started_at => return Ok(()),
+        Some(&(attempts, _)) => attempts,
+        None => 0,
+    };
+
+    warn!("the toolchain {} is broken, reinstalling it", tc);
+    for features in &ex.feature_sets {
+        db.record_broken_toolchain(ex, &tc.with_features(features))?;
+    }
+    if attempts >= MAX_TOOLCHAIN_REINSTALLS {
+        bail!(
+            "the toolchain is still broken after {} reinstalls",
+            attempts
+        );
+    }
+
+    tc.reinstall()?;
+    reinstalls.insert(tc.clone(), (attempts + 1, Instant::now()));
+    Ok(())
+}
+
+/// Result recorded for the tasks of a crate after one of them failed with an error.
+fn failure_result(config: &Config, krate: &Crate, err: &Error) -> TestResult {
+    for cause in err.iter_chain() {
+        if let Some(&OverrideResult(res)) = cause.downcast_ctx() {
+            return res;
+        }
+    }
+
+    if config.is_broken(krate) {
+        TestResult::BuildFail(FailureReason::Broken)
+    } else {
+        TestResult::Error
+    }
+}
+
+/// Check and install everything the crates of the experiment are built with.
+fn prepare_environment<DB: WriteResults>(
     ex: &Experiment,
     db: &DB,
-    threads_count: usize,
     config: &Config,
     docker_env: &str,
-) -> Fallible<()> {
+) -> Fallible<(DockerEnv, ExperimentSecrets)> {
+    // The patches are checked when the experiment is created, but the allowed sources might have
+    // changed since then
+    for patch in &ex.cargo_patches {
+        if !config.is_patch_allowed(patch) {
+            bail!("patching dependencies with {} is not allowed", patch.git);
+        }
+    }
+
+    let secrets = Secrets::load()?.for_experiment(&ex.name);
+
     let docker_env = DockerEnv::new(docker_env);
     docker_env.ensure_exists_locally()?;
 
+    let digest = docker_env.digest()?;
+    info!("using docker image {}", digest);
+    db.record_docker_image(ex, &digest)?;
+
     info!("ensuring all the tools are installed");
     crate::tools::install()?;
-
-    info!("computing the tasks graph...");
-    let graph = Mutex::new(build_graph(ex, config));
+    cargo_config::write(config, Path::new(&*CARGO_HOME))?;
 
     info!("preparing the execution...");
     for tc in &ex.toolchains {
         tc.prepare()?;
     }
 
+    info!("computing the environment fingerprint...");
+    let environment = fingerprint::compute(ex, Some(digest.as_str()));
+    db.record_environment(ex, &environment)?;
+
+    Ok((docker_env, secrets))
+}
+
+fn run_ex_inner<DB: WriteResults + Sync>(
+    ex: &Experiment,
+    db: &DB,
+    threads_count: usize,
+    config: &Config,
+    docker_env: &str,
+) -> Fallible<()> {
+    let (docker_env, secrets) = prepare_environment(ex, db, config, docker_env)?;
+
+    info!("computing the tasks graph...");
+    let graph = Mutex::new(build_graph(ex, config));
+
     info!("running tasks in {} threads...", threads_count);
 
     // An HashMap is used instead of an HashSet because Thread is not Eq+Hash
     let parked_threads: Mutex<HashMap<thread::ThreadId, thread::Thread>> =
         Mutex::new(HashMap::new());
-    let state = RunnerState::new();
+    let state = RunnerState::new(secrets);
+    let reinstalls: Mutex<HashMap<Toolchain, (usize, Instant)>> = Mutex::new(HashMap::new());
+    let aborted = AtomicBool::new(false);
+    let prefetcher = Prefetcher::new(&config.prefetch);
+    let disk_guard = DiskGuard::new(&config.disk_space);
+    let prefetch_order = graph.lock().unwrap().pending_crates();
 
     scope(|scope| -> Fallible<()> {
         let mut threads = Vec::new();
 
+        let prefetch = scope.builder().name("prefetch".into()).spawn(|| {
+            // Crates with all their results already recorded are not going to be prepared again
+            let crates = prefetch_order
+                .into_iter()
+                .filter(|krate| {
+                    ex.build_toolchains()
+                        .iter()
+                        .any(|tc| db.get_result(ex, tc, krate).unwrap_or(None).is_none())
+                })
+                .collect::<Vec<_>>();
+
+            match crate::crates::registry_cache_size() {
+                Ok(cache_size) => {
+                    prefetcher.run(&crates, cache_size, |krate: &Crate| krate.prefetch(config))
+                }
+                Err(err) => {
+                    warn!("can't compute the size of the source cache, not prefetching");
+                    utils::report_failure(&err);
+                }
+            }
+        })?;
+        // The prefetcher must be stopped even if spawning the workers fails, as the scope waits
+        // for it to return
+        scopeguard::defer! {{
+            prefetcher.stop();
+        }}
+
         for i in 0..threads_count {
             let name = format!("worker-{}", i);
             let join = scope.builder().name(name).spawn(|| -> Fallible<()> {
                 // This uses a `loop` instead of a `while let` to avoid locking the graph too much
                 loop {
+                    if aborted.load(Ordering::SeqCst) || db.stop_requested() {
+                        break;
+                    }
+
                     let walk_result = graph.lock().unwrap().next_task(ex, db);
                     match walk_result {
                         WalkResult::Task(id, task) => {
                             info!("running task: {:?}", task);
+                            if let TaskStep::Prepare = task.step {
+                                disk_guard.ensure_free_space(db);
+                                prefetcher.crate_started();
+                            }
+                            let started_at = Instant::now();
                             if let Err(e) = task.run(config, ex, db, &docker_env, &state) {
-                                error!("task failed, marking childs as failed too: {:?}", task);
-                                utils::report_failure(&e);
+                                if let Some(tc) = broken_toolchain(&e) {
+                                    let recovered = handle_broken_toolchain(
+                                        ex,
+                                        db,
+                                        &reinstalls,
+                                        tc,
+                                        started_at,
+                                    );
+                                    if recovered.is_ok() {
+                                        // Run the task again with the fixed toolchain
+                                        graph.lock().unwrap().mark_as_pending(id);
+                                    } else {
+                                        // Stop all the threads without recording bogus results
+                                        aborted.store(true, Ordering::SeqCst);
+                                    }
+
+                                    let mut parked = parked_threads.lock().unwrap();
+                                    for (_id, thread) in parked.drain() {
+                                        thread.unpark();
+                                    }
 
-                                let mut result = if config.is_broken(&task.krate) {
-                                    TestResult::BuildFail(FailureReason::Broken)
-                                } else {
-                                    TestResult::Error
-                                };
+                                    recovered.with_context(|_| {
+                                        format!("failed to recover the toolchain {}", tc)
+                                    })?;
+                                    continue;
+                                }
 
-                                for err in e.iter_chain() {
-                                    if let Some(&OverrideResult(res)) = err.downcast_ctx() {
-                                        result = res;
-                                        break;
+                                // Results can't be recorded anymore once the experiment stopped
+                                if db.stop_requested() {
+                                    let mut parked = parked_threads.lock().unwrap();
+                                    for (_id, thread) in parked.drain() {
+                                        thread.unpark();
                                     }
+                                    break;
                                 }
 
+                                log_fields!(error, "task failed, marking childs as failed too";
+                                    "experiment" => ex.name,
+                                    "crate" => task.krate,
+                                    "step" => format!("{:?}", task.step),
+                                );
+                                utils::report_failure(&e);
+
+                                let result = failure_result(config, &task.krate, &e);
                                 graph
                                     .lock()
                                     .unwrap()
@@ -148,7 +358,10 @@ fn run_ex_inner<DB: WriteResults + Sync>(
                                 let current = thread::current();
                                 parked_threads.insert(current.id(), current);
                             }
-                            thread::park();
+                            // Avoid parking forever if the run was aborted in the meantime
+                            if !aborted.load(Ordering::SeqCst) && !db.stop_requested() {
+                                thread::park();
+                            }
                         }
                         WalkResult::NotBlocked => unreachable!("NotBlocked leaked from the run"),
                         WalkResult::Finished => break,
@@ -175,6 +388,12 @@ fn run_ex_inner<DB: WriteResults + Sync>(
             }
         }
 
+        // Prefetching is just an optimization, so its failures don't affect the experiment
+        prefetcher.stop();
+        if let Err(panic) = prefetch.join() {
+            crate::utils::report_panic(&panic);
+        }
+
         if clean_exit {
             Ok(())
         } else {
@@ -182,6 +401,11 @@ fn run_ex_inner<DB: WriteResults + Sync>(
         }
     })?;
 
+    if db.stop_requested() {
+        info!("the experiment was stopped before running all the tasks");
+        return Ok(());
+    }
+
     // Only the root node must be present
     let mut g = graph.lock().unwrap();
     assert!(g.next_task(ex, db).is_finished());
@@ -0,0 +1,180 @@
+use crate::config::PrefetchConfig;
+use crate::crates::Crate;
+use crate::prelude::*;
+use crate::utils;
+use std::sync::{Condvar, Mutex};
+
+struct PrefetchState {
+    /// Number of crates the workers started preparing.
+    started: usize,
+    stopped: bool,
+}
+
+/// Download the sources of the next crates in the background while the current ones are built.
+/// Prefetching is only an optimization: if a download fails the crate is fetched again when it's
+/// prepared.
+pub(super) struct Prefetcher {
+    ahead: usize,
+    max_cache_size: u64,
+    state: Mutex<PrefetchState>,
+    wakeup: Condvar,
+}
+
+impl Prefetcher {
+    pub(super) fn new(config: &PrefetchConfig) -> Self {
+        Prefetcher {
+            ahead: config.crates,
+            max_cache_size: config.max_cache_size.to_bytes() as u64,
+            state: Mutex::new(PrefetchState {
+                started: 0,
+                stopped: false,
+            }),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// Notify the prefetcher a worker started preparing a crate, allowing another one to be
+    /// downloaded.
+    pub(super) fn crate_started(&self) {
+        self.state.lock().unwrap().started += 1;
+        self.wakeup.notify_all();
+    }
+
+    pub(super) fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.wakeup.notify_all();
+    }
+
+    /// Fetch the crates in the order they're going to be prepared, staying at most `ahead` crates
+    /// in front of the workers. This returns when all the crates are fetched, when the cache is
+    /// full, or when the prefetcher is stopped.
+    pub(super) fn run<F>(&self, crates: &[Crate], cache_size: u64, fetch: F)
+    where
+        F: Fn(&Crate) -> Fallible<u64>,
+    {
+        if self.ahead == 0 {
+            return;
+        }
+
+        let mut cache_size = cache_size;
+        for (i, krate) in crates.iter().enumerate() {
+            {
+                let mut state = self.state.lock().unwrap();
+                while !state.stopped && i >= state.started + self.ahead {
+                    state = self.wakeup.wait(state).unwrap();
+                }
+                if state.stopped {
+                    return;
+                }
+            }
+
+            if cache_size >= self.max_cache_size {
+                info!("the source cache is full, stopping the prefetch");
+                return;
+            }
+
+            match fetch(krate) {
+                Ok(downloaded) => cache_size += downloaded,
+                Err(err) => {
+                    warn!("failed to prefetch {}, it will be fetched later", krate);
+                    utils::report_failure(&err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prefetcher;
+    use crate::config::PrefetchConfig;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::prelude::*;
+    use crate::utils::size::Size;
+    use std::collections::HashSet;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    const NOTHING_HAPPENED: Duration = Duration::from_millis(100);
+
+    fn crates(count: usize) -> Vec<Crate> {
+        (0..count)
+            .map(|i| {
+                Crate::Registry(RegistryCrate {
+                    name: format!("crate{}", i),
+                    version: "1.0.0".into(),
+                })
+            })
+            .collect()
+    }
+
+    fn config(crates: usize, max_cache_size: Size) -> PrefetchConfig {
+        PrefetchConfig {
+            crates,
+            max_cache_size,
+        }
+    }
+
+    #[test]
+    fn test_prefetch_ahead_of_builds() {
+        let crates = crates(4);
+        let prefetcher = Arc::new(Prefetcher::new(&config(2, Size::Gigabytes(1))));
+        let cache = Arc::new(Mutex::new(HashSet::new()));
+        let (fetched_send, fetched) = mpsc::channel();
+
+        let prefetch = {
+            let (crates, prefetcher, cache) = (crates.clone(), prefetcher.clone(), cache.clone());
+            thread::spawn(move || {
+                prefetcher.run(&crates, 0, |krate| {
+                    cache.lock().unwrap().insert(krate.clone());
+                    fetched_send.send(krate.clone()).unwrap();
+                    Ok(1)
+                })
+            })
+        };
+
+        // The first crates are downloaded before any build starts, but not the following ones
+        assert_eq!(fetched.recv_timeout(TIMEOUT).unwrap(), crates[0]);
+        assert_eq!(fetched.recv_timeout(TIMEOUT).unwrap(), crates[1]);
+        assert!(fetched.recv_timeout(NOTHING_HAPPENED).is_err());
+
+        // Each crate consumed by the builds is already in the cache, and allows another one to
+        // be prefetched
+        for (consumed, next) in crates.iter().zip(&crates[2..]) {
+            assert!(cache.lock().unwrap().contains(consumed));
+            prefetcher.crate_started();
+            assert_eq!(&fetched.recv_timeout(TIMEOUT).unwrap(), next);
+        }
+
+        prefetch.join().unwrap();
+        assert_eq!(cache.lock().unwrap().len(), crates.len());
+    }
+
+    #[test]
+    fn test_prefetch_limits() {
+        let crates = crates(10);
+
+        // Prefetching stops when the cache is full
+        let fetched = Mutex::new(Vec::new());
+        Prefetcher::new(&config(10, Size::Bytes(10))).run(&crates, 4, |krate| {
+            fetched.lock().unwrap().push(krate.clone());
+            Ok(3)
+        });
+        assert_eq!(fetched.into_inner().unwrap(), &crates[..2]);
+
+        // Failed downloads don't stop the prefetch
+        let attempts = Mutex::new(0);
+        Prefetcher::new(&config(10, Size::Gigabytes(1))).run(&crates, 0, |_| {
+            *attempts.lock().unwrap() += 1;
+            bail!("download failed");
+        });
+        assert_eq!(attempts.into_inner().unwrap(), crates.len());
+
+        // Stopping the prefetcher doesn't wait for the builds to progress
+        let prefetcher = Prefetcher::new(&config(1, Size::Gigabytes(1)));
+        prefetcher.stop();
+        prefetcher.run(&crates, 0, |_| panic!("nothing should be fetched"));
+    }
+}
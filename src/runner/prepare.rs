@@ -1,22 +1,29 @@
-use crate::config::Config;
+use crate::config::{Config, SetupStep};
 use crate::crates::Crate;
 use crate::dirs::crate_source_dir;
 use crate::experiments::Experiment;
+use crate::logs::{self, LogStorage};
 use crate::prelude::*;
-use crate::results::{FailureReason, TestResult, WriteResults};
+use crate::results::{FailureReason, Phase, TestResult, WriteResults};
 use crate::run::RunCommand;
+use crate::runner::metadata::Metadata;
+use crate::runner::secrets::ExperimentSecrets;
 use crate::runner::toml_frobber::TomlFrobber;
 use crate::runner::OverrideResult;
 use crate::toolchain::Toolchain;
 use crate::tools::CARGO;
-use std::path::PathBuf;
+use crate::utils;
+use log::LevelFilter;
+use std::path::{Path, PathBuf};
 
 pub(super) struct PrepareCrate<'a, DB: WriteResults + 'a> {
     experiment: &'a Experiment,
     krate: &'a Crate,
     config: &'a Config,
     db: &'a DB,
-    source_dirs: Vec<(&'a Toolchain, PathBuf)>,
+    secrets: &'a ExperimentSecrets,
+    source_dirs: Vec<(Toolchain, PathBuf)>,
+    pinned_sha: Option<&'a str>,
 }
 
 impl<'a, DB: WriteResults + 'a> PrepareCrate<'a, DB> {
@@ -25,11 +32,15 @@ impl<'a, DB: WriteResults + 'a> PrepareCrate<'a, DB> {
         krate: &'a Crate,
         config: &'a Config,
         db: &'a DB,
+        secrets: &'a ExperimentSecrets,
     ) -> Self {
         let source_dirs = experiment
-            .toolchains
-            .iter()
-            .map(|tc| (tc, crate_source_dir(experiment, tc, krate)))
+            .build_toolchains()
+            .into_iter()
+            .map(|tc| {
+                let source_dir = crate_source_dir(experiment, &tc, krate);
+                (tc, source_dir)
+            })
             .collect();
 
         PrepareCrate {
@@ -37,25 +48,58 @@ impl<'a, DB: WriteResults + 'a> PrepareCrate<'a, DB> {
             krate,
             config,
             db,
+            secrets,
             source_dirs,
+            pinned_sha: None,
         }
     }
 
+    /// Check out the GitHub repository at this commit instead of its latest one.
+    pub(super) fn pin_sha(mut self, sha: Option<&'a str>) -> Self {
+        self.pinned_sha = sha;
+        self
+    }
+
+    /// Cargo needs the secrets to download the dependencies from private registries.
+    fn with_secrets(&self, mut cmd: RunCommand) -> RunCommand {
+        for (key, value) in self.secrets.env() {
+            cmd = cmd.secret_env(key, value);
+        }
+        cmd
+    }
+
     pub(super) fn prepare(&self) -> Fallible<()> {
-        self.krate.fetch()?;
+        self.krate.fetch(self.config).with_context(|_| {
+            OverrideResult(TestResult::BuildFail(FailureReason::DownloadFailed))
+        })?;
         for (_, source_dir) in &self.source_dirs {
             self.krate.copy_to(source_dir)?;
+            if let Some(sha) = self.pinned_sha {
+                RunCommand::new("git")
+                    .args(&["checkout", "--detach", sha])
+                    .cd(source_dir)
+                    .run()
+                    .with_context(|_| format!("failed to check out commit {}", sha))?;
+            }
         }
         self.capture_sha()?;
+        self.run_setup()?;
         self.validate_manifest()?;
         self.frob_toml()?;
         self.capture_lockfile()?;
         self.fetch_deps()?;
+        if self.experiment.pre_analyze {
+            self.analyze_deps();
+        }
         Ok(())
     }
 
     fn capture_sha(&self) -> Fallible<()> {
         if let Crate::GitHub(ref repo) = self.krate {
+            if let Some(sha) = self.pinned_sha {
+                return self.db.record_sha(self.experiment, repo, sha);
+            }
+
             let dir = repo.cached_path();
             let r = RunCommand::new("git")
                 .args(&["rev-parse", "HEAD"])
@@ -89,6 +133,39 @@ impl<'a, DB: WriteResults + 'a> PrepareCrate<'a, DB> {
         Ok(())
     }
 
+    /// Run the setup steps of the crate, recording their outcome as the setup phase of the result
+    /// of each toolchain, even if the other phases aren't recorded.
+    fn run_setup(&self) -> Fallible<()> {
+        let steps = self.config.setup_steps(&self.krate);
+        if steps.is_empty() {
+            return Ok(());
+        }
+
+        for (tc, source_dir) in &self.source_dirs {
+            info!("running setup of {} on toolchain {}", self.krate, tc);
+
+            let storage =
+                LogStorage::new(LevelFilter::Info, self.config).redact(self.secrets.values());
+            let res = logs::capture(&storage, || run_setup_steps(steps, source_dir));
+            let result = match &res {
+                Ok(()) => TestResult::TestPass,
+                Err(_) => TestResult::BuildFail(FailureReason::SetupFailed),
+            };
+            self.db.record_phase(
+                self.experiment,
+                tc,
+                self.krate,
+                Phase::Setup,
+                result,
+                storage.to_string().as_bytes(),
+            )?;
+            res.with_context(|_| {
+                OverrideResult(TestResult::BuildFail(FailureReason::SetupFailed))
+            })?;
+        }
+        Ok(())
+    }
+
     fn validate_manifest(&self) -> Fallible<()> {
         for (tc, source_dir) in &self.source_dirs {
             info!("validating manifest of {} on toolchain {}", self.krate, tc);
@@ -116,6 +193,7 @@ impl<'a, DB: WriteResults + 'a> PrepareCrate<'a, DB> {
             let path = source_dir.join("Cargo.toml");
             let mut frobber = TomlFrobber::new(&self.krate, &path)?;
             frobber.frob();
+            frobber.apply_patches(&self.experiment.cargo_patches);
             frobber.save(&path)?;
         }
         Ok(())
@@ -131,26 +209,100 @@ impl<'a, DB: WriteResults + 'a> PrepareCrate<'a, DB> {
                 return Ok(());
             }
 
-            RunCommand::new(CARGO.toolchain(toolchain).unstable_features(true))
-                .args(&[
-                    "generate-lockfile",
-                    "--manifest-path",
-                    "Cargo.toml",
-                    "-Zno-index-update",
-                ])
-                .cd(source_dir)
-                .run()?;
+            self.with_secrets(RunCommand::new(
+                CARGO.toolchain(toolchain).unstable_features(true),
+            ))
+            .args(&[
+                "generate-lockfile",
+                "--manifest-path",
+                "Cargo.toml",
+                "-Zno-index-update",
+            ])
+            .cd(source_dir)
+            .run()?;
         }
         Ok(())
     }
 
     fn fetch_deps(&self) -> Fallible<()> {
         for (toolchain, source_dir) in &self.source_dirs {
-            RunCommand::new(CARGO.toolchain(toolchain))
+            self.with_secrets(RunCommand::new(CARGO.toolchain(toolchain)))
                 .args(&["fetch", "--locked", "--manifest-path", "Cargo.toml"])
                 .cd(source_dir)
                 .run()?;
         }
         Ok(())
     }
+
+    /// Record the direct dependencies and the resolved dependency tree of the crate. The analysis is only informational, so the
+    /// crate is still tested if it fails.
+    fn analyze_deps(&self) {
+        if let Err(err) = self.record_dependencies() {
+            warn!("failed to analyze the dependencies of {}", self.krate);
+            utils::report_failure(&err);
+        }
+    }
+
+    fn record_dependencies(&self) -> Fallible<()> {
+        // The dependencies are the same with both toolchains, as they share the lockfile
+        let (toolchain, source_dir) = match self.source_dirs.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        info!("analyzing the dependencies of {}", self.krate);
+
+        let (stdout, _) = self
+            .with_secrets(RunCommand::new(CARGO.toolchain(toolchain)))
+            .args(&[
+                "metadata",
+                "--format-version",
+                "1",
+                "--locked",
+                "--manifest-path",
+                "Cargo.toml",
+            ])
+            .cd(source_dir)
+            .hide_output(true)
+            .run_capture()?;
+        let metadata = Metadata::parse(&stdout.join("\n"))?;
+
+        self.db
+            .record_dependencies(self.experiment, self.krate, &metadata.direct_dependencies())
+            .with_context(|_| format!("failed to record the dependencies of {}", self.krate))?;
+        self.db
+            .record_dependency_tree(self.experiment, self.krate, &metadata.dependency_edges()?)
+            .with_context(|_| format!("failed to record the dependency tree of {}", self.krate))?;
+        Ok(())
+    }
+}
+
+fn run_setup_steps(steps: &[SetupStep], source_dir: &Path) -> Fallible<()> {
+    for step in steps {
+        match step {
+            SetupStep::SubmoduleInit => {
+                info!("initializing git submodules");
+                RunCommand::new("git")
+                    .args(&["submodule", "update", "--init", "--recursive"])
+                    .cd(source_dir)
+                    .run()?;
+            }
+            SetupStep::CreateFile { path, content } => {
+                info!("creating file {}", path);
+                let dest = source_dir.join(path);
+                if let Some(parent) = dest.parent() {
+                    ::std::fs::create_dir_all(parent)?;
+                }
+                ::std::fs::write(&dest, content.as_bytes())?;
+            }
+            // The packages are installed in the container running cargo, as the changes to a
+            // container don't outlive it. See `runner::test::cargo_command`.
+            SetupStep::AptInstall { packages } => {
+                info!(
+                    "apt packages to install in the sandbox: {}",
+                    packages.join(" ")
+                );
+            }
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,396 @@
+//! Run a single crate of an experiment outside of it, exactly like the agents would, to debug the
+//! result it got.
+
+use crate::config::Config;
+use crate::crates::{Crate, GitHubRepo};
+use crate::db::Database;
+use crate::experiments::{Experiment, FeatureSet};
+use crate::logs::{self, LogStorage};
+use crate::prelude::*;
+use crate::report::{self, Comparison};
+use crate::results::{
+    DatabaseDB, EnvironmentFingerprint, Phase, ReadResults, TestResult, WriteResults,
+};
+use crate::runner::graph::build_step;
+use crate::runner::tasks::{Task, TaskCtx, TaskStep};
+use crate::runner::{broken_toolchain, failure_result, prepare_environment, test, RunnerState};
+use crate::toolchain::Toolchain;
+use crate::utils;
+use log::LevelFilter;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+/// Everything needed to run a crate the same way an experiment did.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct Reproduction {
+    /// The experiment, with only the crate left in it.
+    pub experiment: Experiment,
+    /// Commit of the GitHub repository tested by the experiment, if it was recorded.
+    pub sha: Option<String>,
+}
+
+impl Reproduction {
+    pub fn load(db: &Database, name: &str, krate: &Crate) -> Fallible<Option<Self>> {
+        let mut ex = match Experiment::get(db, name)? {
+            Some(ex) => ex,
+            None => return Ok(None),
+        };
+        if !ex.crates.contains(krate) {
+            return Ok(None);
+        }
+
+        let sha = match *krate {
+            Crate::GitHub(ref repo) => DatabaseDB::new(db).load_all_shas(&ex)?.remove(repo),
+            Crate::Registry(_) | Crate::Local(_) => None,
+        };
+        ex.crates = vec![krate.clone()];
+        // Only meaningful for the experiment itself
+        ex.assigned_to = None;
+
+        Ok(Some(Reproduction {
+            experiment: ex,
+            sha,
+        }))
+    }
+}
+
+/// Result of the crate with the toolchains of one of the feature sets of the experiment.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReproducedResult {
+    pub features: FeatureSet,
+    pub results: [Option<TestResult>; 2],
+    pub comparison: Comparison,
+}
+
+/// Keeps the results in memory instead of recording them in the experiment.
+#[derive(Default)]
+struct ReproduceDB {
+    results: Mutex<HashMap<Toolchain, TestResult>>,
+}
+
+impl WriteResults for ReproduceDB {
+    fn get_result(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        _krate: &Crate,
+    ) -> Fallible<Option<TestResult>> {
+        Ok(self.results.lock().unwrap().get(toolchain).cloned())
+    }
+
+    fn record_sha(&self, _ex: &Experiment, repo: &GitHubRepo, sha: &str) -> Fallible<()> {
+        info!("testing commit {} of {}", sha, repo.slug());
+        Ok(())
+    }
+
+    fn record_dependencies(
+        &self,
+        _ex: &Experiment,
+        _krate: &Crate,
+        _dependencies: &[String],
+    ) -> Fallible<()> {
+        Ok(())
+    }
+
+    fn record_dependency_tree(
+        &self,
+        _ex: &Experiment,
+        _krate: &Crate,
+        _edges: &[(String, String)],
+    ) -> Fallible<()> {
+        Ok(())
+    }
+
+    fn record_docker_image(&self, _ex: &Experiment, _digest: &str) -> Fallible<()> {
+        Ok(())
+    }
+
+    fn record_environment(&self, _ex: &Experiment, _env: &EnvironmentFingerprint) -> Fallible<()> {
+        Ok(())
+    }
+
+    fn record_broken_toolchain(&self, _ex: &Experiment, _toolchain: &Toolchain) -> Fallible<()> {
+        Ok(())
+    }
+
+    fn record_artifacts_size(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        _krate: &Crate,
+        size: u64,
+    ) -> Fallible<()> {
+        info!("the artifacts built with {} take {} bytes", toolchain, size);
+        Ok(())
+    }
+
+    fn record_phase(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        _krate: &Crate,
+        phase: Phase,
+        result: TestResult,
+        _log: &[u8],
+    ) -> Fallible<()> {
+        // The log of the phase was already printed while running it
+        info!("{} phase with {}: {}", phase, toolchain, result);
+        Ok(())
+    }
+
+    fn stop_requested(&self) -> bool {
+        false
+    }
+
+    fn set_unhealthy(&self, reason: Option<String>) {
+        if let Some(reason) = reason {
+            warn!("{}", reason);
+        }
+    }
+
+    fn record_result<F>(
+        &self,
+        _ex: &Experiment,
+        toolchain: &Toolchain,
+        _krate: &Crate,
+        existing_logs: Option<LogStorage>,
+        config: &Config,
+        f: F,
+    ) -> Fallible<TestResult>
+    where
+        F: FnOnce() -> Fallible<TestResult>,
+    {
+        // The log is already shown by the logger of the CLI, it's only captured for the checks
+        // inspecting it
+        let storage = existing_logs.unwrap_or_else(|| LogStorage::new(LevelFilter::Info, config));
+        let result = logs::capture(&storage, f)?;
+        self.results
+            .lock()
+            .unwrap()
+            .insert(toolchain.clone(), result);
+        Ok(result)
+    }
+}
+
+fn compare_results(
+    config: &Config,
+    ex: &Experiment,
+    krate: &Crate,
+    results: &HashMap<Toolchain, TestResult>,
+) -> Vec<ReproducedResult> {
+    ex.feature_sets
+        .iter()
+        .map(|features| {
+            let toolchains = ex.toolchains_for(features);
+            let results = [
+                results.get(&toolchains[0]).cloned(),
+                results.get(&toolchains[1]).cloned(),
+            ];
+            ReproducedResult {
+                features: features.clone(),
+                results,
+                comparison: report::compare(config, krate, results[0], results[1]),
+            }
+        })
+        .collect()
+}
+
+/// Toolchain the shell is started with: the last one the crate failed with, as regressions are
+/// usually caused by the second toolchain, or the last one if it never failed.
+fn shell_toolchain(ex: &Experiment, results: &HashMap<Toolchain, TestResult>) -> Toolchain {
+    let toolchains = ex.build_toolchains();
+    toolchains
+        .iter()
+        .rev()
+        .find(|tc| match results.get(*tc) {
+            Some(TestResult::TestPass) | Some(TestResult::TestSkipped) => false,
+            Some(_) | None => true,
+        })
+        .unwrap_or_else(|| toolchains.last().unwrap())
+        .clone()
+}
+
+/// Build the crate of the reproduction with the toolchains of the experiment, running the same
+/// sandboxed commands as the agents. The output of the builds is logged as it comes. With `shell`,
+/// an interactive shell is started in the sandbox before the sources are removed.
+pub fn reproduce(
+    repro: &Reproduction,
+    config: &Config,
+    docker_env: &str,
+    shell: bool,
+) -> Fallible<Vec<ReproducedResult>> {
+    if !crate::docker::is_running() {
+        return Err(err_msg("docker is not running"));
+    }
+
+    let ex = &repro.experiment;
+    let krate = match ex.crates.as_slice() {
+        [krate] => krate,
+        _ => bail!("only a single crate can be reproduced"),
+    };
+
+    let db = ReproduceDB::default();
+    let sha = repro.sha.as_ref().map(String::as_str);
+    let res = reproduce_inner(ex, krate, sha, &db, config, docker_env, shell);
+
+    let target_dir = &crate::toolchain::ex_target_dir(&ex.name);
+    if target_dir.exists() {
+        utils::fs::remove_dir_all(target_dir)?;
+    }
+
+    res?;
+    let results = db.results.lock().unwrap();
+    Ok(compare_results(config, ex, krate, &results))
+}
+
+fn reproduce_inner(
+    ex: &Experiment,
+    krate: &Crate,
+    sha: Option<&str>,
+    db: &ReproduceDB,
+    config: &Config,
+    docker_env: &str,
+    shell: bool,
+) -> Fallible<()> {
+    let (docker_env, secrets) = prepare_environment(ex, db, config, docker_env)?;
+    let mut state = RunnerState::new(secrets);
+    if let (Crate::GitHub(repo), Some(sha)) = (krate, sha) {
+        state.pinned_shas.insert(repo.clone(), sha.to_string());
+    }
+
+    let task = |step| Task {
+        krate: krate.clone(),
+        step,
+    };
+    let prepare = task(TaskStep::Prepare);
+    let builds = ex
+        .build_toolchains()
+        .iter()
+        .map(|tc| task(build_step(ex, config, krate, tc)))
+        .collect::<Vec<_>>();
+
+    info!("running task: {:?}", prepare);
+    if let Err(err) = prepare.run(config, ex, db, &docker_env, &state) {
+        let result = failure_result(config, krate, &err);
+        for build in &builds {
+            build.mark_as_failed(ex, db, &state, config, &err, result)?;
+        }
+        if shell {
+            warn!("not starting a shell, as the crate couldn't be prepared");
+        }
+        return Ok(());
+    }
+
+    for build in &builds {
+        info!("running task: {:?}", build);
+        if let Err(err) = build.run(config, ex, db, &docker_env, &state) {
+            if broken_toolchain(&err).is_some() {
+                return Err(err);
+            }
+            let result = failure_result(config, krate, &err);
+            build.mark_as_failed(ex, db, &state, config, &err, result)?;
+        }
+    }
+
+    if shell {
+        let tc = shell_toolchain(ex, &db.results.lock().unwrap());
+        let ctx = TaskCtx::new(config, db, ex, &tc, krate, &docker_env, &state, false);
+        test::shell(&ctx)?;
+    }
+
+    task(TaskStep::Cleanup).run(config, ex, db, &docker_env, &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_results, shell_toolchain, ReproducedResult, Reproduction};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::{Experiment, FeatureSet};
+    use crate::report::Comparison;
+    use crate::results::{FailureReason, TestResult};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_reproduction() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy")
+            .apply(&ActionsCtx::new(&db, &config))
+            .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let krate = ex.crates[0].clone();
+
+        let repro = Reproduction::load(&db, "dummy", &krate).unwrap().unwrap();
+        assert_eq!(repro.experiment.crates, vec![krate.clone()]);
+        assert_eq!(repro.experiment.toolchains, ex.toolchains);
+        assert!(repro.sha.is_none());
+
+        let missing = Crate::Registry(RegistryCrate {
+            name: "not-in-the-experiment".into(),
+            version: "1.0.0".into(),
+        });
+        assert!(Reproduction::load(&db, "dummy", &missing)
+            .unwrap()
+            .is_none());
+        assert!(Reproduction::load(&db, "missing", &krate)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_compare_results() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment {
+            feature_sets: vec![FeatureSet::Default, FeatureSet::NoDefault],
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ActionsCtx::new(&db, &config))
+        .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let krate = ex.crates[0].clone();
+
+        let default = ex.toolchains_for(&FeatureSet::Default);
+        let no_default = ex.toolchains_for(&FeatureSet::NoDefault);
+        let mut results = HashMap::new();
+        results.insert(default[0].clone(), TestResult::TestPass);
+        results.insert(
+            default[1].clone(),
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+        results.insert(no_default[0].clone(), TestResult::TestPass);
+        results.insert(no_default[1].clone(), TestResult::TestPass);
+
+        assert_eq!(
+            compare_results(&config, &ex, &krate, &results),
+            vec![
+                ReproducedResult {
+                    features: FeatureSet::Default,
+                    results: [
+                        Some(TestResult::TestPass),
+                        Some(TestResult::BuildFail(FailureReason::Unknown))
+                    ],
+                    comparison: Comparison::Regressed,
+                },
+                ReproducedResult {
+                    features: FeatureSet::NoDefault,
+                    results: [Some(TestResult::TestPass), Some(TestResult::TestPass)],
+                    comparison: Comparison::SameTestPass,
+                },
+            ]
+        );
+
+        // The shell is started where the crate regressed
+        assert_eq!(shell_toolchain(&ex, &results), default[1]);
+        results.insert(default[1].clone(), TestResult::TestPass);
+        assert_eq!(shell_toolchain(&ex, &results), no_default[1]);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::runner::warnings::strip_prefixes;
+
+/// Messages printed by cargo when a package fails to build, followed by the package name (and
+/// sometimes its version and source) between backticks.
+static FAILURE_MESSAGES: &[&str] = &[
+    "error: could not compile `",
+    "error: failed to run custom build command for `",
+];
+
+/// Prefix of the lines printed by cargo when it starts building a package.
+static COMPILING: &str = "Compiling ";
+
+/// Packages are described by cargo like `foo v1.0.0 (/path/to/foo)`: the ones with a local path
+/// are part of the crate being tested, while the dependencies come from a registry or git. Older
+/// versions of cargo show the path as a `file://` URL.
+fn is_local_package(description: &str) -> bool {
+    description.contains(" (/") || description.contains(" (file://")
+}
+
+fn package_name(description: &str) -> &str {
+    description.split_whitespace().next().unwrap_or("")
+}
+
+/// Find the dependency that caused a build to fail, if the log shows that all the packages that
+/// failed to build are dependencies of the crate being tested rather than the crate itself.
+pub(crate) fn failing_dependency(log: &str) -> Option<String> {
+    let mut local = Vec::new();
+    let mut failing = Vec::new();
+    for line in log.lines().map(strip_prefixes) {
+        let line = line.trim_start();
+        if line.starts_with(COMPILING) {
+            let description = &line[COMPILING.len()..];
+            if is_local_package(description) {
+                local.push(package_name(description));
+            }
+            continue;
+        }
+
+        for message in FAILURE_MESSAGES {
+            // Older versions of cargo capitalize the messages
+            let prefix = line.get(..message.len());
+            if prefix.map_or(false, |prefix| prefix.eq_ignore_ascii_case(message)) {
+                let rest = &line[message.len()..];
+                if let Some(end) = rest.find('`') {
+                    let description = &rest[..end];
+                    if is_local_package(description) {
+                        local.push(package_name(description));
+                    }
+                    failing.push(package_name(description));
+                }
+            }
+        }
+    }
+
+    if failing.iter().any(|name| local.contains(name)) {
+        return None;
+    }
+    failing.first().map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::failing_dependency;
+
+    #[test]
+    fn test_failing_dependency() {
+        // Captured from a build failing in a dependency
+        let log = concat!(
+            "[INFO] [stderr]    Compiling libc v0.2.50\n",
+            "[INFO] [stderr]    Compiling typenum v1.10.0\n",
+            "[INFO] [stderr]    Compiling generic-array v0.12.0\n",
+            "[INFO] [stderr] error[E0308]: mismatched types\n",
+            "[INFO] [stderr]   --> /opt/crater/cargo-home/registry/src/github.com-1ecc6299db9ec823/",
+            "generic-array-0.12.0/src/lib.rs:176:9\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] error: aborting due to previous error\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] For more information about this error, try `rustc --explain E0308`.\n",
+            "[INFO] [stderr] error: Could not compile `generic-array`.\n",
+            "[INFO] [stderr] warning: build failed, waiting for other jobs to finish...\n",
+            "[INFO] [stderr] error: build failed\n",
+        );
+        assert_eq!(failing_dependency(log), Some("generic-array".into()));
+
+        // Newer versions of cargo add the number of errors and the target kind
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr]    Compiling serde v1.0.89\n",
+                "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+                "[INFO] [stderr] error: could not compile `serde` (lib) due to 2 previous errors\n",
+            )),
+            Some("serde".into())
+        );
+
+        // Dependencies fetched from git are not part of the crate either
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr]    Compiling foo v0.1.0 ",
+                "(https://github.com/bar/foo#f1b2c3d4)\n",
+                "[INFO] [stderr] error: could not compile `foo` due to previous error\n",
+            )),
+            Some("foo".into())
+        );
+
+        // Build scripts of the dependencies can fail too
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr]    Compiling openssl-sys v0.9.43\n",
+                "[INFO] [stderr] error: failed to run custom build command for ",
+                "`openssl-sys v0.9.43`\n",
+                "[INFO] [stderr] process didn't exit successfully: ",
+                "`/opt/crater/target/debug/build/openssl-sys-6b7e31c0/build-script-main` ",
+                "(exit code: 101)\n",
+            )),
+            Some("openssl-sys".into())
+        );
+    }
+
+    #[test]
+    fn test_failing_crate_itself() {
+        // Captured from a build failing in the crate being tested
+        let log = concat!(
+            "[INFO] [stderr]    Compiling libc v0.2.50\n",
+            "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+            "[INFO] [stderr] error[E0425]: cannot find value `x` in this scope\n",
+            "[INFO] [stderr]  --> src/main.rs:2:20\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] error: aborting due to previous error\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] error: Could not compile `hello`.\n",
+        );
+        assert_eq!(failing_dependency(log), None);
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr]    Compiling hello v0.1.0 (file:///opt/crater/workdir)\n",
+                "[INFO] [stderr] error: Could not compile `hello`.\n",
+            )),
+            None
+        );
+
+        // Members of a workspace are part of the crate
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr]    Compiling hello-macros v0.1.0 (/opt/crater/workdir/macros)\n",
+                "[INFO] [stderr] error: could not compile `hello-macros` due to 3 previous errors\n",
+            )),
+            None
+        );
+
+        // If both the crate and a dependency fail, the crate is the root cause
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr]    Compiling serde v1.0.89\n",
+                "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+                "[INFO] [stderr] error: could not compile `serde`.\n",
+                "[INFO] [stderr] error: could not compile `hello`.\n",
+            )),
+            None
+        );
+        assert_eq!(
+            failing_dependency(concat!(
+                "[INFO] [stderr] error: failed to run custom build command for ",
+                "`hello v0.1.0 (/opt/crater/workdir)`\n",
+            )),
+            None
+        );
+
+        // Failures without a package are not caused by dependencies
+        assert_eq!(
+            failing_dependency("[INFO] [stdout] test tests::foo ... FAILED\n"),
+            None
+        );
+        assert_eq!(failing_dependency(""), None);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::prelude::*;
+use serde_derive::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+static SECRETS_PATH: &str = "secrets.toml";
+
+/// Environment variables injected in the builds of an experiment, for example to authenticate
+/// to a private registry. They're read from `secrets.toml` on the machine running the
+/// experiment, are never sent to the server or stored in the database, and their values are
+/// scrubbed from the build logs.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Secrets {
+    #[serde(default)]
+    experiments: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl Secrets {
+    pub(crate) fn load() -> Fallible<Secrets> {
+        let path = Path::new(SECRETS_PATH);
+        if !path.exists() {
+            return Ok(Secrets::default());
+        }
+
+        let content = ::std::fs::read_to_string(path)?;
+        Ok(::toml::from_str(&content)
+            .with_context(|_| format!("failed to parse {}", SECRETS_PATH))?)
+    }
+
+    /// The secrets of the experiment, empty if it has none.
+    pub(crate) fn for_experiment(mut self, name: &str) -> ExperimentSecrets {
+        ExperimentSecrets {
+            env: self.experiments.remove(name).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ExperimentSecrets {
+    env: BTreeMap<String, String>,
+}
+
+impl ExperimentSecrets {
+    pub(crate) fn env(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.env.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// The values to redact from the logs.
+    pub(crate) fn values(&self) -> Vec<String> {
+        self.env.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Secrets;
+    use crate::config::Config;
+    use crate::logs::{self, LogStorage};
+    use crate::run::RunCommand;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_secrets_passed_and_redacted() {
+        logs::init_test();
+
+        let secrets: Secrets = ::toml::from_str(
+            "[experiments.foo]\n\
+             CRATER_TEST_REGISTRY_TOKEN = \"hunter2\"\n",
+        )
+        .unwrap();
+        let secrets = secrets.for_experiment("foo");
+        assert_eq!(
+            secrets.env().collect::<Vec<_>>(),
+            vec![("CRATER_TEST_REGISTRY_TOKEN", "hunter2")]
+        );
+
+        let storage =
+            LogStorage::new(LevelFilter::Info, &Config::default()).redact(secrets.values());
+        let (stdout, _) = logs::capture(&storage, || {
+            let mut cmd =
+                RunCommand::new("sh").args(&["-c", "echo token: $CRATER_TEST_REGISTRY_TOKEN"]);
+            for (key, value) in secrets.env() {
+                cmd = cmd.secret_env(key, value);
+            }
+            cmd.run_capture().unwrap()
+        });
+
+        // The build receives the secret, but it doesn't end up in the stored log
+        assert_eq!(stdout, vec!["token: hunter2".to_string()]);
+        let log = storage.to_string();
+        assert!(log.contains("[stdout] token: [REDACTED]"));
+        assert!(!log.contains("hunter2"));
+
+        // Other experiments don't get the secrets
+        let secrets: Secrets = ::toml::from_str("[experiments.foo]\nA = \"b\"\n").unwrap();
+        assert_eq!(secrets.for_experiment("bar").env().count(), 0);
+    }
+}
@@ -3,14 +3,13 @@ use crate::crates::Crate;
 use crate::dirs;
 use crate::docker::DockerEnv;
 use crate::experiments::Experiment;
-use crate::logs::{self, LogStorage};
+use crate::logs;
 use crate::prelude::*;
 use crate::results::{TestResult, WriteResults};
 use crate::runner::{prepare::PrepareCrate, test, RunnerState};
 use crate::toolchain::Toolchain;
 use crate::utils;
 use failure::AsFail;
-use log::LevelFilter;
 use std::fmt;
 
 pub(super) struct TaskCtx<'ctx, DB: WriteResults + 'ctx> {
@@ -25,7 +24,7 @@ pub(super) struct TaskCtx<'ctx, DB: WriteResults + 'ctx> {
 }
 
 impl<'ctx, DB: WriteResults + 'ctx> TaskCtx<'ctx, DB> {
-    fn new(
+    pub(super) fn new(
         config: &'ctx Config,
         db: &'ctx DB,
         experiment: &'ctx Experiment,
@@ -170,20 +169,21 @@ impl Task {
         match self.step {
             TaskStep::Cleanup => {
                 // Ensure source directories are cleaned up
-                for tc in &ex.toolchains {
+                for tc in &ex.build_toolchains() {
                     let _ = utils::fs::remove_dir_all(&dirs::crate_source_dir(ex, tc, &self.krate));
                 }
                 // Remove stored logs
                 state.lock().prepare_logs.remove(&self.krate);
             }
             TaskStep::Prepare => {
-                let storage = LogStorage::new(LevelFilter::Info, config);
+                let storage = state.log_storage(config);
                 state
                     .lock()
                     .prepare_logs
                     .insert(self.krate.clone(), storage.clone());
                 logs::capture(&storage, || {
-                    let prepare = PrepareCrate::new(ex, &self.krate, config, db);
+                    let prepare = PrepareCrate::new(ex, &self.krate, config, db, &state.secrets)
+                        .pin_sha(state.pinned_sha(&self.krate));
                     prepare.prepare()
                 })?;
             }
@@ -1,9 +1,17 @@
+use crate::config::SetupStep;
+use crate::crates::Crate;
 use crate::docker::{DockerError, MountPerms};
+use crate::experiments::{Experiment, TestRunner};
+use crate::logs;
 use crate::prelude::*;
-use crate::results::{FailureReason, TestResult, WriteResults};
-use crate::run::{RunCommand, RunCommandError};
+use crate::results::{FailureReason, Phase, TestResult, WriteResults};
+use crate::run::{RunCommand, RunCommandError, SandboxedCommand};
+use crate::runner::broken_toolchain::{is_broken_toolchain_log, BrokenToolchain};
+use crate::runner::diagnostics::MESSAGE_FORMAT;
 use crate::runner::tasks::TaskCtx;
+use crate::toolchain::Toolchain;
 use crate::tools::CARGO;
+use crate::utils;
 use failure::Error;
 use std::path::Path;
 
@@ -11,6 +19,8 @@ fn failure_reason(err: &Error) -> FailureReason {
     for cause in err.iter_chain() {
         if let Some(&DockerError::ContainerOOM) = cause.downcast_ctx() {
             return FailureReason::OOM;
+        } else if let Some(&DockerError::SetupFailed) = cause.downcast_ctx() {
+            return FailureReason::SetupFailed;
         } else if let Some(&RunCommandError::NoOutputFor(_)) = cause.downcast_ctx() {
             return FailureReason::Timeout;
         } else if let Some(&RunCommandError::Timeout(_)) = cause.downcast_ctx() {
@@ -21,11 +31,11 @@ fn failure_reason(err: &Error) -> FailureReason {
     FailureReason::Unknown
 }
 
-fn run_cargo<DB: WriteResults>(
-    ctx: &TaskCtx<DB>,
+fn cargo_command<'ctx, DB: WriteResults>(
+    ctx: &TaskCtx<'ctx, DB>,
     source_path: &Path,
     args: &[&str],
-) -> Fallible<()> {
+) -> Fallible<SandboxedCommand<'ctx>> {
     let target_dir = ctx.toolchain.target_dir(&ctx.experiment.name);
     ::std::fs::create_dir_all(&target_dir)?;
 
@@ -41,20 +51,88 @@ fn run_cargo<DB: WriteResults>(
         "RUSTFLAGS"
     };
 
-    RunCommand::new(CARGO.toolchain(ctx.toolchain))
+    // The packages are installed again in every container, as its changes are discarded with it
+    let apt_packages = ctx
+        .config
+        .setup_steps(ctx.krate)
+        .iter()
+        .flat_map(|step| match step {
+            SetupStep::AptInstall { packages } => packages.clone(),
+            _ => Vec::new(),
+        })
+        .collect();
+
+    let mut cmd = RunCommand::new(CARGO.toolchain(ctx.toolchain))
         .args(args)
+        .args(&ctx.toolchain.features.cargo_args())
         .quiet(ctx.quiet)
         .cd(source_path)
         .env("CARGO_TARGET_DIR", "/opt/crater/target")
         .env("CARGO_INCREMENTAL", "0")
         .env("RUST_BACKTRACE", "full")
-        .env(rustflags_env, rustflags)
-        .sandboxed(&ctx.docker_env)
+        .env(rustflags_env, rustflags);
+    for (key, value) in ctx.state.secrets.env() {
+        cmd = cmd.secret_env(key, value);
+    }
+
+    Ok(cmd
+        .sandboxed(ctx.docker_env)
+        .apt_install(apt_packages)
         .mount(target_dir, "/opt/crater/target", MountPerms::ReadWrite)
-        .memory_limit(Some(ctx.config.sandbox.memory_limit))
-        .run()?;
+        .memory_limit(Some(ctx.config.sandbox.memory_limit)))
+}
 
-    Ok(())
+/// Ask cargo to emit the diagnostics as JSON, so they can be extracted from the log. Nextest
+/// passes the option to the cargo building the tests.
+fn with_message_format<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let option = if args.first() == Some(&"nextest") {
+        "--cargo-message-format"
+    } else {
+        "--message-format"
+    };
+    let mut args = args.to_vec();
+    args.push(option);
+    args.push(MESSAGE_FORMAT);
+    args
+}
+
+fn run_cargo<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    source_path: &Path,
+    args: &[&str],
+) -> Fallible<()> {
+    cargo_command(ctx, source_path, &with_message_format(args))?.run()
+}
+
+/// Open an interactive shell in the sandbox the crate is built in, with the same environment as
+/// cargo.
+pub(super) fn shell<DB: WriteResults>(ctx: &TaskCtx<DB>) -> Fallible<()> {
+    let source_path = crate::dirs::crate_source_dir(ctx.experiment, ctx.toolchain, ctx.krate);
+    cargo_command(ctx, &source_path, &[])?.shell()
+}
+
+/// Run the build, recording the size of the artifacts it produced if the experiment measures
+/// them. The target directory is reused by the following crates, so only its growth is counted.
+fn measure_artifacts<DB: WriteResults, F>(
+    db: &DB,
+    ex: &Experiment,
+    toolchain: &Toolchain,
+    krate: &Crate,
+    target_dir: &Path,
+    f: F,
+) -> Fallible<TestResult>
+where
+    F: FnOnce() -> Fallible<TestResult>,
+{
+    if !ex.measure_artifacts {
+        return f();
+    }
+
+    let before = utils::fs::dir_size(target_dir)?;
+    let result = f()?;
+    let size = utils::fs::dir_size(target_dir)?.saturating_sub(before);
+    db.record_artifacts_size(ex, toolchain, krate, size)?;
+    Ok(result)
 }
 
 pub(super) fn run_test<DB: WriteResults>(
@@ -66,7 +144,12 @@ pub(super) fn run_test<DB: WriteResults>(
         .db
         .get_result(ctx.experiment, ctx.toolchain, ctx.krate)?
     {
-        info!("skipping crate {}. existing result: {}", ctx.krate, res);
+        log_fields!(info, "skipping crate with an existing result";
+            "experiment" => ctx.experiment.name,
+            "crate" => ctx.krate,
+            "toolchain" => ctx.toolchain,
+            "result" => res,
+        );
     } else {
         let source_path = crate::dirs::crate_source_dir(ctx.experiment, ctx.toolchain, ctx.krate);
         let log_storage = ctx
@@ -74,45 +157,104 @@ pub(super) fn run_test<DB: WriteResults>(
             .lock()
             .prepare_logs
             .get(&ctx.krate)
-            .map(|s| s.duplicate());
-        ctx.db.record_result(
+            .map(|s| s.duplicate())
+            .unwrap_or_else(|| ctx.state.log_storage(ctx.config));
+        // The storage is shared, allowing to inspect the log before the result is recorded
+        let log = log_storage.clone();
+        let result = ctx.db.record_result(
             ctx.experiment,
             ctx.toolchain,
             ctx.krate,
-            log_storage,
+            Some(log_storage),
             ctx.config,
             || {
-                info!(
-                    "{} {} against {} for {}",
-                    action,
-                    ctx.krate,
-                    ctx.toolchain.to_string(),
-                    ctx.experiment.name
+                log_fields!(info, action;
+                    "experiment" => ctx.experiment.name,
+                    "crate" => ctx.krate,
+                    "toolchain" => ctx.toolchain,
                 );
-                test_fn(ctx, &source_path)
+                let target_dir = ctx.toolchain.target_dir(&ctx.experiment.name);
+                let result = measure_artifacts(
+                    ctx.db,
+                    ctx.experiment,
+                    ctx.toolchain,
+                    ctx.krate,
+                    &target_dir,
+                    || test_fn(ctx, &source_path),
+                )?;
+
+                // Don't record failures caused by a broken toolchain, as they'd be bogus
+                if result != TestResult::TestPass && is_broken_toolchain_log(&log.to_string()) {
+                    return Err(BrokenToolchain(ctx.toolchain.clone()).into());
+                }
+                Ok(result)
             },
         )?;
+        log_fields!(info, "recorded the result";
+            "experiment" => ctx.experiment.name,
+            "crate" => ctx.krate,
+            "toolchain" => ctx.toolchain,
+            "result" => result,
+        );
     }
     Ok(())
 }
 
+/// Run a phase of the job, recording its result and its log separately if the configuration asks
+/// for it. The log is still part of the one of the whole job.
+fn run_phase<DB: WriteResults, F>(ctx: &TaskCtx<DB>, phase: Phase, f: F) -> Fallible<()>
+where
+    F: FnOnce() -> Fallible<()>,
+{
+    if !ctx.config.sandbox.record_phases {
+        return f();
+    }
+
+    let storage = ctx.state.log_storage(ctx.config);
+    let res = logs::capture(&storage, f);
+    let result = match (&res, phase) {
+        (Ok(()), _) => TestResult::TestPass,
+        (Err(err), Phase::Build) => TestResult::BuildFail(failure_reason(err)),
+        (Err(err), Phase::Test) => TestResult::TestFail(failure_reason(err)),
+        (Err(_), Phase::Setup) => TestResult::BuildFail(FailureReason::SetupFailed),
+    };
+    ctx.db.record_phase(
+        ctx.experiment,
+        ctx.toolchain,
+        ctx.krate,
+        phase,
+        result,
+        storage.to_string().as_bytes(),
+    )?;
+    res
+}
+
 fn build<DB: WriteResults>(ctx: &TaskCtx<DB>, source_path: &Path) -> Fallible<()> {
     run_cargo(ctx, source_path, &["build", "--frozen"])?;
     run_cargo(ctx, source_path, &["test", "--frozen", "--no-run"])?;
     Ok(())
 }
 
+/// Arguments of cargo running the tests with the runner. Both toolchains of an experiment use the
+/// same runner, and a crate without tests passes with either of them.
+fn test_args(runner: Option<TestRunner>) -> &'static [&'static str] {
+    match runner {
+        None | Some(TestRunner::Libtest) => &["test", "--frozen"],
+        Some(TestRunner::Nextest) => &["nextest", "run", "--frozen", "--no-tests=pass"],
+    }
+}
+
 fn test<DB: WriteResults>(ctx: &TaskCtx<DB>, source_path: &Path) -> Fallible<()> {
-    run_cargo(ctx, source_path, &["test", "--frozen"])
+    run_cargo(ctx, source_path, test_args(ctx.experiment.test_runner))
 }
 
 pub(super) fn test_build_and_test<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     source_path: &Path,
 ) -> Fallible<TestResult> {
-    let build_r = build(ctx, source_path);
+    let build_r = run_phase(ctx, Phase::Build, || build(ctx, source_path));
     let test_r = if build_r.is_ok() {
-        Some(test(ctx, source_path))
+        Some(run_phase(ctx, Phase::Test, || test(ctx, source_path)))
     } else {
         None
     };
@@ -129,7 +271,7 @@ pub(super) fn test_build_only<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     source_path: &Path,
 ) -> Fallible<TestResult> {
-    if let Err(err) = build(ctx, source_path) {
+    if let Err(err) = run_phase(ctx, Phase::Build, || build(ctx, source_path)) {
         Ok(TestResult::BuildFail(failure_reason(&err)))
     } else {
         Ok(TestResult::TestSkipped)
@@ -164,7 +306,7 @@ pub(super) fn test_rustdoc<DB: WriteResults>(
     // Make sure to remove the built documentation
     // There is no point in storing it after the build is done
     let target_dir = ctx.toolchain.target_dir(&ctx.experiment.name);
-    crate::utils::fs::remove_dir_all(&target_dir.join("doc"))?;
+    utils::fs::remove_dir_all(&target_dir.join("doc"))?;
 
     if let Err(err) = res {
         Ok(TestResult::BuildFail(failure_reason(&err)))
@@ -172,3 +314,89 @@ pub(super) fn test_rustdoc<DB: WriteResults>(
         Ok(TestResult::TestPass)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{measure_artifacts, test_args, with_message_format};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::{Experiment, TestRunner};
+    use crate::results::{DatabaseDB, ReadResults, TestResult, WriteResults};
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_test_args() {
+        assert_eq!(test_args(None), &["test", "--frozen"]);
+        assert_eq!(test_args(Some(TestRunner::Libtest)), test_args(None));
+        assert_eq!(
+            test_args(Some(TestRunner::Nextest)),
+            &["nextest", "run", "--frozen", "--no-tests=pass"]
+        );
+    }
+
+    #[test]
+    fn test_with_message_format() {
+        assert_eq!(
+            with_message_format(&["build", "--frozen"]),
+            &[
+                "build",
+                "--frozen",
+                "--message-format",
+                "json-diagnostic-rendered-ansi"
+            ]
+        );
+        assert_eq!(
+            with_message_format(test_args(Some(TestRunner::Nextest))),
+            &[
+                "nextest",
+                "run",
+                "--frozen",
+                "--no-tests=pass",
+                "--cargo-message-format",
+                "json-diagnostic-rendered-ansi"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_measure_artifacts() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let mut create = CreateExperiment::dummy("measured");
+        create.measure_artifacts = true;
+        create.apply(&ctx).unwrap();
+        CreateExperiment::dummy("unmeasured").apply(&ctx).unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        let target_dir = tempdir().unwrap();
+        // Artifacts left by the previous crates are not counted
+        fs::write(target_dir.path().join("previous"), vec![0; 1024]).unwrap();
+
+        let build = |name: &str, tc: &Toolchain| {
+            let ex = Experiment::get(&db, name).unwrap().unwrap();
+            results
+                .record_result(&ex, tc, &krate, None, &config, || {
+                    measure_artifacts(&results, &ex, tc, &krate, target_dir.path(), || {
+                        fs::write(target_dir.path().join(tc.to_string()), vec![0; 100])?;
+                        Ok(TestResult::TestPass)
+                    })
+                })
+                .unwrap();
+            results.load_artifacts_size(&ex, tc, &krate).unwrap()
+        };
+
+        assert_eq!(build("measured", &MAIN_TOOLCHAIN), Some(100));
+        assert_eq!(build("unmeasured", &TEST_TOOLCHAIN), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::crates::Crate;
+use crate::experiments::CargoPatch;
 use crate::prelude::*;
 use std::path::Path;
 use toml::value::Table;
@@ -43,6 +44,26 @@ impl<'a> TomlFrobber<'a> {
         info!("finished frobbing {}", self.krate);
     }
 
+    /// Replace dependencies with the git repositories patching them in the experiment, overriding
+    /// the crate's own patches of the same dependencies.
+    pub(super) fn apply_patches(&mut self, patches: &[CargoPatch]) {
+        if patches.is_empty() {
+            return;
+        }
+
+        let krate = self.krate.to_string();
+        let crates_io = table_entry(table_entry(&mut self.table, "patch"), "crates-io");
+        for patch in patches {
+            let mut source = Table::new();
+            source.insert("git".into(), Value::String(patch.git.clone()));
+            if let Some(ref rev) = patch.rev {
+                source.insert("rev".into(), Value::String(rev.clone()));
+            }
+            crates_io.insert(patch.name.clone(), Value::Table(source));
+            info!("patched {} with {} in {}", patch.name, patch.git, krate);
+        }
+    }
+
     #[allow(clippy::ptr_arg)]
     fn test_existance(dir: &Path, value: &Array, folder: &str) -> Array {
         value
@@ -171,10 +192,25 @@ impl<'a> TomlFrobber<'a> {
     }
 }
 
+/// Get the table stored at `key`, replacing the value if it's not a table.
+fn table_entry<'t>(table: &'t mut Table, key: &str) -> &'t mut Table {
+    let value = table
+        .entry(key.to_string())
+        .or_insert_with(|| Value::Table(Table::new()));
+    if !value.is_table() {
+        *value = Value::Table(Table::new());
+    }
+    match value {
+        Value::Table(table) => table,
+        _ => unreachable!(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::TomlFrobber;
     use crate::crates::Crate;
+    use crate::experiments::CargoPatch;
     use toml::{self, Value};
 
     #[test]
@@ -253,4 +289,53 @@ mod tests {
 
         assert_eq!(Value::Table(frobber.table), result);
     }
+
+    #[test]
+    fn test_apply_patches() {
+        let toml = toml! {
+            [package]
+            name = "foo"
+            version = "1.0"
+
+            [dependencies]
+            tokio = "0.1"
+
+            [patch.crates-io]
+            tokio = { path = "../tokio" }
+            mio = { git = "https://github.com/carllerche/mio" }
+        };
+
+        let result = toml! {
+            [package]
+            name = "foo"
+            version = "1.0"
+
+            [dependencies]
+            tokio = "0.1"
+
+            [patch.crates-io]
+            tokio = { git = "https://github.com/tokio-rs/tokio", rev = "f00" }
+            mio = { git = "https://github.com/carllerche/mio" }
+            futures = { git = "https://github.com/rust-lang-nursery/futures-rs" }
+        };
+
+        let patches = vec![
+            CargoPatch {
+                name: "tokio".into(),
+                git: "https://github.com/tokio-rs/tokio".into(),
+                rev: Some("f00".into()),
+            },
+            CargoPatch {
+                name: "futures".into(),
+                git: "https://github.com/rust-lang-nursery/futures-rs".into(),
+                rev: None,
+            },
+        ];
+
+        let krate = Crate::Local("build-pass".to_string());
+        let mut frobber = TomlFrobber::new_with_table(&krate, toml.as_table().unwrap().clone());
+        frobber.apply_patches(&patches);
+
+        assert_eq!(Value::Table(frobber.table), result);
+    }
 }
@@ -0,0 +1,100 @@
+use crate::runner::diagnostics::parse_line;
+
+/// Prefix of the lines of the build output in the captured logs.
+static OUTPUT_PREFIX: &str = "] ";
+
+/// Strip the log level and stream markers (like `[INFO] [stderr] `) added when capturing logs.
+pub(super) fn strip_prefixes(mut line: &str) -> &str {
+    while line.starts_with('[') {
+        match line.find(OUTPUT_PREFIX) {
+            Some(end) => line = &line[end + OUTPUT_PREFIX.len()..],
+            None => break,
+        }
+    }
+    line
+}
+
+/// Count the compiler warnings in the log of a build, emitted either as JSON or rendered.
+///
+/// Only the diagnostics pointing to a location in the source code are counted, which excludes
+/// the summaries emitted by rustc (like `2 warnings emitted`) and the warnings emitted by cargo.
+pub(crate) fn count_warnings(log: &str) -> u32 {
+    let mut count = 0;
+    let mut in_warning = false;
+    for line in log.lines().map(strip_prefixes) {
+        if line.starts_with('{') {
+            if let Some(diagnostic) = parse_line(line) {
+                if diagnostic.level == "warning" && diagnostic.file.is_some() {
+                    count += 1;
+                }
+            }
+            in_warning = false;
+        } else if line.starts_with("warning: ") || line.starts_with("warning[") {
+            in_warning = true;
+        } else if in_warning && line.trim_start().starts_with("--> ") {
+            count += 1;
+            in_warning = false;
+        } else {
+            in_warning = false;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::count_warnings;
+
+    #[test]
+    fn test_count_warnings() {
+        // Captured from a build emitting three warnings
+        let log = concat!(
+            "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+            "[INFO] [stderr] warning: unused variable: `x`\n",
+            "[INFO] [stderr]  --> src/main.rs:2:9\n",
+            "[INFO] [stderr]   |\n",
+            "[INFO] [stderr] 2 |     let x = 1;\n",
+            "[INFO] [stderr]   |         ^ help: consider using `_x` instead\n",
+            "[INFO] [stderr]   |\n",
+            "[INFO] [stderr]   = note: #[warn(unused_variables)] on by default\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] warning: function is never used: `foo`\n",
+            "[INFO] [stderr]  --> src/main.rs:5:1\n",
+            "[INFO] [stderr]   |\n",
+            "[INFO] [stderr] 5 | fn foo() {}\n",
+            "[INFO] [stderr]   | ^^^^^^^^\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] warning[E0170]: pattern binding `Bar` is named the same as one of ",
+            "the variants of the type `Foo`\n",
+            "[INFO] [stderr]   --> src/main.rs:12:9\n",
+            "[INFO] [stderr] \n",
+            "[INFO] [stderr] warning: 3 warnings emitted\n",
+            "[INFO] [stderr]     Finished dev [unoptimized + debuginfo] target(s) in 0.52s\n",
+        );
+        assert_eq!(count_warnings(log), 3);
+
+        // Warnings emitted by cargo are not compiler warnings
+        assert_eq!(
+            count_warnings(concat!(
+                "[INFO] [stderr] warning: unused manifest key: package.foo\n",
+                "[INFO] [stderr]    Compiling hello v0.1.0 (/opt/crater/workdir)\n",
+            )),
+            0
+        );
+
+        assert_eq!(count_warnings(""), 0);
+
+        // Emitted with `--message-format=json-diagnostic-rendered-ansi`
+        assert_eq!(
+            count_warnings(concat!(
+                "[INFO] [stdout] {\"reason\":\"compiler-message\",\"message\":{\"message\":",
+                "\"unused variable: `x`\",\"code\":{\"code\":\"unused_variables\"},",
+                "\"level\":\"warning\",\"spans\":[{\"file_name\":\"src/main.rs\",",
+                "\"line_start\":2,\"column_start\":9,\"is_primary\":true}]}}\n",
+                "[INFO] [stdout] {\"reason\":\"compiler-message\",\"message\":{\"message\":",
+                "\"1 warning emitted\",\"code\":null,\"level\":\"warning\",\"spans\":[]}}\n",
+            )),
+            1
+        );
+    }
+}
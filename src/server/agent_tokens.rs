@@ -0,0 +1,138 @@
+//! Tokens of the agents rotated through the Admin API. Once an agent's token is rotated, the one
+//! listed in `tokens.toml` is rejected and only the new token is accepted, even after a restart.
+//! Only hashes of the new tokens are stored in the database.
+
+use crate::db::{Database, QueryUtils};
+use crate::prelude::*;
+use crate::server::tokens::Tokens;
+use crate::utils::hex::to_hex;
+use chrono::Utc;
+use ring::digest;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+pub(super) fn hash(token: &str) -> String {
+    to_hex(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+#[derive(Clone)]
+pub struct AgentTokens {
+    db: Database,
+    /// Hash of the current token of each rotated agent.
+    rotated: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl AgentTokens {
+    pub fn new(db: Database) -> Fallible<Self> {
+        let rotated = db
+            .query("SELECT agent, token_hash FROM agent_tokens;", &[], |row| {
+                (row.get("agent"), row.get("token_hash"))
+            })?
+            .into_iter()
+            .collect();
+        Ok(AgentTokens {
+            db,
+            rotated: Arc::new(RwLock::new(rotated)),
+        })
+    }
+
+    /// Name of the agent owning the token, if it's still valid.
+    pub fn agent_name(&self, tokens: &Tokens, token: &str) -> Option<String> {
+        let rotated = self.rotated.read().unwrap();
+        let token_hash = hash(token);
+        if let Some((name, _)) = rotated.iter().find(|(_, hash)| **hash == token_hash) {
+            return Some(name.clone());
+        }
+
+        match tokens.agents.get(token) {
+            Some(name) if !rotated.contains_key(name) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Replace the token of the agent with a new one, which is returned. The previous token is
+    /// rejected as soon as this returns. Returns `None` if the agent doesn't exist.
+    pub fn rotate(&self, tokens: &Tokens, agent: &str) -> Fallible<Option<String>> {
+        if !tokens.agents.values().any(|name| name == agent) {
+            return Ok(None);
+        }
+
+        // The lock is held while the database is updated, so no request is authenticated with
+        // the old token after the rotation
+        let mut rotated = self.rotated.write().unwrap();
+        let token = Tokens::generate();
+        let token_hash = hash(&token);
+        self.db.execute(
+            "INSERT INTO agent_tokens (agent, token_hash, rotated_at) VALUES (?1, ?2, ?3);",
+            &[&agent, &token_hash, &Utc::now()],
+        )?;
+        rotated.insert(agent.to_string(), token_hash);
+        Ok(Some(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AgentTokens;
+    use crate::db::Database;
+    use crate::server::tokens::Tokens;
+
+    #[test]
+    fn test_rotate() {
+        let db = Database::temp().unwrap();
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("old-token".into(), "agent-1".into());
+        tokens.agents.insert("other-token".into(), "agent-2".into());
+
+        let agent_tokens = AgentTokens::new(db.clone()).unwrap();
+        assert_eq!(
+            agent_tokens
+                .agent_name(&tokens, "old-token")
+                .as_ref()
+                .map(String::as_str),
+            Some("agent-1")
+        );
+        assert_eq!(agent_tokens.rotate(&tokens, "missing").unwrap(), None);
+
+        // The old token is rejected and the new one accepted
+        let new = agent_tokens.rotate(&tokens, "agent-1").unwrap().unwrap();
+        assert_ne!(new, "old-token");
+        assert_eq!(agent_tokens.agent_name(&tokens, "old-token"), None);
+        assert_eq!(
+            agent_tokens
+                .agent_name(&tokens, &new)
+                .as_ref()
+                .map(String::as_str),
+            Some("agent-1")
+        );
+        assert_eq!(
+            agent_tokens
+                .agent_name(&tokens, "other-token")
+                .as_ref()
+                .map(String::as_str),
+            Some("agent-2")
+        );
+
+        // The rotation is kept after a restart
+        let reloaded = AgentTokens::new(db.clone()).unwrap();
+        assert_eq!(reloaded.agent_name(&tokens, "old-token"), None);
+        assert_eq!(
+            reloaded
+                .agent_name(&tokens, &new)
+                .as_ref()
+                .map(String::as_str),
+            Some("agent-1")
+        );
+
+        // Rotating again invalidates the previous rotated token
+        let newer = agent_tokens.rotate(&tokens, "agent-1").unwrap().unwrap();
+        assert_eq!(agent_tokens.agent_name(&tokens, &new), None);
+        assert_eq!(
+            agent_tokens
+                .agent_name(&tokens, &newer)
+                .as_ref()
+                .map(String::as_str),
+            Some("agent-1")
+        );
+    }
+}
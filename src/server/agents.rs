@@ -1,26 +1,62 @@
 use crate::db::{Database, QueryUtils};
-use crate::experiments::{Assignee, Experiment};
+use crate::experiments::{Assignee, CrateAssignment, Experiment, Status};
 use crate::prelude::*;
+use crate::server::api_types::{AgentInfo, HostInfo};
 use crate::server::tokens::Tokens;
 use chrono::Duration;
 use chrono::{DateTime, Utc};
+use rusqlite::types::ToSql;
 use std::collections::HashSet;
+use utoipa::ToSchema;
 
 /// Number of seconds without an heartbeat after an agent should be considered unreachable.
 const INACTIVE_AFTER: i64 = 300;
+/// Agents update themselves rarely, so heartbeats changing the git revision again less than this
+/// many seconds after the previous change come from multiple processes sharing the same token.
+/// The conflict is shown for as long.
+const REVISION_CONFLICT_WINDOW: i64 = 300;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
 pub enum AgentStatus {
     Working,
     Idle,
+    /// The agent is reachable, but it paused because of a problem on its machine.
+    Unhealthy,
     Unreachable,
 }
 
+impl AgentStatus {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            AgentStatus::Working => "working",
+            AgentStatus::Idle => "idle",
+            AgentStatus::Unhealthy => "unhealthy",
+            AgentStatus::Unreachable => "unreachable",
+        }
+    }
+}
+
+/// How the git revision reported with an heartbeat differs from the previous one.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RevisionChange {
+    /// Same revision as before, or the first one recorded.
+    Unchanged,
+    /// The agent updated itself from the previous revision.
+    Updated(String),
+    /// The revision changed again right after the previous change, as processes sharing the
+    /// token of the agent overwrite each other's revision.
+    Conflict(String),
+}
+
 pub struct Agent {
     name: String,
     experiment: Option<Experiment>,
     last_heartbeat: Option<DateTime<Utc>>,
     git_revision: Option<String>,
+    host: Option<HostInfo>,
+    unhealthy: Option<String>,
+    revision_conflict_at: Option<DateTime<Utc>>,
 }
 
 impl Agent {
@@ -33,6 +69,15 @@ impl Agent {
         self.git_revision.as_ref()
     }
 
+    pub fn host(&self) -> Option<&HostInfo> {
+        self.host.as_ref()
+    }
+
+    /// Why the agent paused, if it reported a problem with its latest heartbeat.
+    pub fn unhealthy(&self) -> Option<&String> {
+        self.unhealthy.as_ref()
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -45,10 +90,20 @@ impl Agent {
         self.last_heartbeat.as_ref()
     }
 
+    /// Whether heartbeats with different git revisions were recently received, meaning more than
+    /// one process uses the token of the agent.
+    pub fn conflict(&self) -> bool {
+        self.revision_conflict_at
+            .map(|at| Utc::now() - Duration::seconds(REVISION_CONFLICT_WINDOW) < at)
+            .unwrap_or(false)
+    }
+
     pub fn status(&self) -> AgentStatus {
         if let Some(ref heartbeat) = self.last_heartbeat {
             if Utc::now() - Duration::seconds(INACTIVE_AFTER) < *heartbeat {
-                if self.experiment.is_some() {
+                if self.unhealthy.is_some() {
+                    return AgentStatus::Unhealthy;
+                } else if self.experiment.is_some() {
                     return AgentStatus::Working;
                 } else {
                     return AgentStatus::Idle;
@@ -58,6 +113,20 @@ impl Agent {
 
         AgentStatus::Unreachable
     }
+
+    /// State of the agent, as returned by the public API.
+    pub fn info(&self) -> AgentInfo {
+        AgentInfo {
+            name: self.name.clone(),
+            status: self.status(),
+            last_heartbeat: self.last_heartbeat,
+            git_revision: self.git_revision.clone(),
+            assigned_experiment: self.experiment.as_ref().map(|ex| ex.name.clone()),
+            unhealthy: self.unhealthy.clone(),
+            host: self.host.clone(),
+            conflict: self.conflict(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -99,6 +168,11 @@ impl Agents {
                     name: row.get("name"),
                     last_heartbeat: row.get("last_heartbeat"),
                     git_revision: row.get("git_revision"),
+                    host: row
+                        .get::<_, Option<String>>("host")
+                        .and_then(|host| ::serde_json::from_str(&host).ok()),
+                    unhealthy: row.get("unhealthy"),
+                    revision_conflict_at: row.get("revision_conflict_at"),
                     experiment: None, // Lazy loaded after this
                 }
             })?
@@ -107,8 +181,7 @@ impl Agents {
             .collect()
     }
 
-    #[cfg(test)]
-    fn get(&self, name: &str) -> Fallible<Option<Agent>> {
+    pub fn get(&self, name: &str) -> Fallible<Option<Agent>> {
         let row = self
             .db
             .get_row("SELECT * FROM agents WHERE name = ?1;", &[&name], |row| {
@@ -116,6 +189,11 @@ impl Agents {
                     name: row.get("name"),
                     last_heartbeat: row.get("last_heartbeat"),
                     git_revision: row.get("git_revision"),
+                    host: row
+                        .get::<_, Option<String>>("host")
+                        .and_then(|host| ::serde_json::from_str(&host).ok()),
+                    unhealthy: row.get("unhealthy"),
+                    revision_conflict_at: row.get("revision_conflict_at"),
                     experiment: None, // Lazy loaded after this
                 }
             })?;
@@ -137,10 +215,107 @@ impl Agents {
         Ok(())
     }
 
-    pub fn set_git_revision(&self, agent: &str, revision: &str) -> Fallible<()> {
+    /// Record the git revision the agent is running, returning how it changed. A revision
+    /// changing again right after the previous change is recorded as a conflict.
+    pub fn set_git_revision(&self, agent: &str, revision: &str) -> Fallible<RevisionChange> {
+        let now = Utc::now();
+        let (previous, changed_at): (Option<String>, Option<DateTime<Utc>>) = self
+            .db
+            .get_row(
+                "SELECT git_revision, git_revision_changed_at FROM agents WHERE name = ?1;",
+                &[&agent],
+                |row| (row.get("git_revision"), row.get("git_revision_changed_at")),
+            )?
+            .ok_or_else(|| err_msg(format!("missing agent {}", agent)))?;
+
+        let previous = match previous {
+            Some(ref previous) if previous != revision => previous.clone(),
+            // The first revision recorded isn't a change
+            _ => {
+                let changes = self.db.execute(
+                    "UPDATE agents SET git_revision = ?1 WHERE name = ?2;",
+                    &[&revision, &agent],
+                )?;
+                assert_eq!(changes, 1);
+                return Ok(RevisionChange::Unchanged);
+            }
+        };
+
+        let conflict = changed_at
+            .map(|at| now - Duration::seconds(REVISION_CONFLICT_WINDOW) < at)
+            .unwrap_or(false);
+        let changes = if conflict {
+            self.db.execute(
+                "UPDATE agents SET git_revision = ?1, git_revision_changed_at = ?2, \
+                 revision_conflict_at = ?2 WHERE name = ?3;",
+                &[&revision as &ToSql, &now, &agent],
+            )?
+        } else {
+            self.db.execute(
+                "UPDATE agents SET git_revision = ?1, git_revision_changed_at = ?2 \
+                 WHERE name = ?3;",
+                &[&revision as &ToSql, &now, &agent],
+            )?
+        };
+        assert_eq!(changes, 1);
+
+        Ok(if conflict {
+            RevisionChange::Conflict(previous)
+        } else {
+            RevisionChange::Updated(previous)
+        })
+    }
+
+    /// Record the problem reported by the agent with its heartbeat, or that it has none.
+    pub fn set_unhealthy(&self, agent: &str, reason: Option<&str>) -> Fallible<()> {
+        let changes = self.db.execute(
+            "UPDATE agents SET unhealthy = ?1 WHERE name = ?2;",
+            &[&reason, &agent],
+        )?;
+        assert_eq!(changes, 1);
+
+        Ok(())
+    }
+
+    /// Put the experiments run by unreachable agents back in the queue, so other agents can
+    /// resume them. The crates those agents were testing become pending again, while the
+    /// completed ones are kept. Returns the names of the released experiments.
+    pub fn release_unreachable(&self) -> Fallible<Vec<String>> {
+        let cutoff = Utc::now() - Duration::seconds(INACTIVE_AFTER);
+        self.db.transaction(|t| {
+            let experiments: Vec<String> = t.query(
+                "SELECT experiments.name FROM experiments \
+                 INNER JOIN agents ON experiments.assigned_to = 'agent:' || agents.name \
+                 WHERE experiments.status = ?1 AND agents.last_heartbeat < ?2;",
+                &[&Status::Running.to_str() as &ToSql, &cutoff],
+                |row| row.get("name"),
+            )?;
+
+            for ex in &experiments {
+                t.execute(
+                    "UPDATE crate_assignments SET status = ?1, agent = NULL, updated_at = ?2 \
+                     WHERE experiment = ?3 AND status = ?4;",
+                    &[
+                        &CrateAssignment::Pending.to_str() as &ToSql,
+                        &Utc::now(),
+                        &ex.as_str(),
+                        &CrateAssignment::InProgress.to_str(),
+                    ],
+                )?;
+                t.execute(
+                    "UPDATE experiments SET status = ?1, assigned_to = NULL WHERE name = ?2;",
+                    &[&Status::Queued.to_str(), &ex.as_str()],
+                )?;
+            }
+
+            Ok(experiments)
+        })
+    }
+
+    pub fn set_host(&self, agent: &str, host: &HostInfo) -> Fallible<()> {
         let changes = self.db.execute(
-            "UPDATE agents SET git_revision = ?1 WHERE name = ?2;",
-            &[&revision, &agent],
+            "UPDATE agents SET host = ?1 WHERE name = ?2;",
+            &[&::serde_json::to_string(host)?, &agent],
         )?;
         assert_eq!(changes, 1);
 
@@ -150,12 +325,17 @@ impl Agents {
 
 #[cfg(test)]
 mod tests {
-    use super::{AgentStatus, Agents};
+    use super::{AgentStatus, Agents, RevisionChange};
     use crate::actions::{Action, ActionsCtx, CreateExperiment};
     use crate::config::Config;
-    use crate::db::Database;
-    use crate::experiments::{Assignee, Experiment};
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::{Assignee, CrateAssignment, Experiment, Status};
+    use crate::results::{DatabaseDB, ProgressData, TaskResult, TestResult};
+    use crate::server::api_types::HostInfo;
     use crate::server::tokens::Tokens;
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use base64;
+    use chrono::{Duration, Utc};
 
     #[test]
     fn test_agents_synchronize() {
@@ -213,6 +393,92 @@ mod tests {
         assert!(first_heartbeat < agent.last_heartbeat.unwrap());
     }
 
+    #[test]
+    fn test_git_revision_changes() {
+        let db = Database::temp().unwrap();
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token".into(), "agent".into());
+        let agents = Agents::new(db, &tokens).unwrap();
+
+        // The first revision recorded isn't a change
+        assert_eq!(
+            agents.set_git_revision("agent", "aaaaaaa").unwrap(),
+            RevisionChange::Unchanged
+        );
+        assert_eq!(
+            agents.set_git_revision("agent", "aaaaaaa").unwrap(),
+            RevisionChange::Unchanged
+        );
+        assert_eq!(
+            agents.set_git_revision("agent", "bbbbbbb").unwrap(),
+            RevisionChange::Updated("aaaaaaa".into())
+        );
+
+        let agent = agents.get("agent").unwrap().unwrap();
+        assert_eq!(agent.git_revision(), Some(&"bbbbbbb".to_string()));
+        assert!(!agent.conflict());
+    }
+
+    #[test]
+    fn test_git_revision_conflicts() {
+        let db = Database::temp().unwrap();
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token".into(), "agent".into());
+        let agents = Agents::new(db.clone(), &tokens).unwrap();
+
+        agents.set_git_revision("agent", "aaaaaaa").unwrap();
+        assert_eq!(
+            agents.set_git_revision("agent", "bbbbbbb").unwrap(),
+            RevisionChange::Updated("aaaaaaa".into())
+        );
+
+        // Two processes sharing the token send their heartbeats in turns
+        assert_eq!(
+            agents.set_git_revision("agent", "aaaaaaa").unwrap(),
+            RevisionChange::Conflict("bbbbbbb".into())
+        );
+        let agent = agents.get("agent").unwrap().unwrap();
+        assert!(agent.conflict());
+        assert!(agent.info().conflict);
+
+        // Updating again long after the previous update is not a conflict
+        let long_ago = Utc::now() - Duration::hours(1);
+        db.execute(
+            "UPDATE agents SET git_revision_changed_at = ?1, revision_conflict_at = ?1;",
+            &[&long_ago],
+        )
+        .unwrap();
+        assert!(!agents.get("agent").unwrap().unwrap().conflict());
+        assert_eq!(
+            agents.set_git_revision("agent", "ccccccc").unwrap(),
+            RevisionChange::Updated("aaaaaaa".into())
+        );
+        assert!(!agents.get("agent").unwrap().unwrap().info().conflict);
+    }
+
+    #[test]
+    fn test_host_recording() {
+        let db = Database::temp().unwrap();
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token".into(), "agent".into());
+        let agents = Agents::new(db, &tokens).unwrap();
+
+        // Agents that didn't report their host yet have no metadata
+        let agent = agents.get("agent").unwrap().unwrap();
+        assert!(agent.host().is_none());
+
+        let host = HostInfo {
+            os: "Ubuntu 18.04.1 LTS".into(),
+            kernel: Some("4.15.0-43-generic".into()),
+            docker: None,
+        };
+        agents.set_host("agent", &host).unwrap();
+
+        let agent = agents.get("agent").unwrap().unwrap();
+        assert_eq!(agent.host(), Some(&host));
+        assert_eq!(agents.all().unwrap()[0].host(), Some(&host));
+    }
+
     #[test]
     fn test_agent_status() {
         let db = Database::temp().unwrap();
@@ -241,5 +507,117 @@ mod tests {
         // After an experiment is assigned to the agent, the agent is working
         let agent = agents.get("agent").unwrap().unwrap();
         assert_eq!(agent.status(), AgentStatus::Working);
+
+        // Agents reporting a problem are unhealthy until the next heartbeat without it
+        agents
+            .set_unhealthy("agent", Some("less than 10240 MB of free disk space"))
+            .unwrap();
+        let agent = agents.get("agent").unwrap().unwrap();
+        assert_eq!(agent.status(), AgentStatus::Unhealthy);
+        assert_eq!(
+            agent.unhealthy(),
+            Some(&"less than 10240 MB of free disk space".to_string())
+        );
+
+        agents.set_unhealthy("agent", None).unwrap();
+        let agent = agents.get("agent").unwrap().unwrap();
+        assert_eq!(agent.status(), AgentStatus::Working);
+
+        let info = agent.info();
+        assert_eq!(info.name, "agent");
+        assert_eq!(info.status, AgentStatus::Working);
+        assert!(info.last_heartbeat.is_some());
+        assert_eq!(info.assigned_experiment, Some("dummy".to_string()));
+        assert!(info.unhealthy.is_none());
+    }
+
+    #[test]
+    fn test_release_unreachable() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token1".into(), "agent1".into());
+        tokens.agents.insert("token2".into(), "agent2".into());
+        let agents = Agents::new(db.clone(), &tokens).unwrap();
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+
+        agents.record_heartbeat("agent1").unwrap();
+        let (_, mut ex) = Experiment::next(&db, &Assignee::Agent("agent1".into()))
+            .unwrap()
+            .unwrap();
+        ex.remove_completed_crates(&db).unwrap();
+        ex.assign_crates(&db, "agent1").unwrap();
+        let total = ex.crates.len();
+        assert!(total >= 2);
+
+        // Complete the first crate
+        let completed = ex.crates[0].clone();
+        let task = |toolchain| TaskResult {
+            krate: completed.clone(),
+            toolchain,
+            result: TestResult::TestPass,
+            log: base64::encode("ok"),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        DatabaseDB::new(&db)
+            .store(
+                &ex,
+                &ProgressData {
+                    results: vec![task(MAIN_TOOLCHAIN.clone()), task(TEST_TOOLCHAIN.clone())],
+                    shas: Vec::new(),
+                    dependencies: Vec::new(),
+                    dependency_trees: Vec::new(),
+                    docker_image: None,
+                    environment: None,
+                },
+            )
+            .unwrap();
+        ex.complete_crates(&db, "agent1", &[completed.clone(), ex.crates[1].clone()])
+            .unwrap();
+        assert_eq!(
+            ex.count_assigned_crates(&db, CrateAssignment::Completed)
+                .unwrap(),
+            1
+        );
+
+        // Nothing is released while the agent is reachable
+        assert!(agents.release_unreachable().unwrap().is_empty());
+
+        db.execute(
+            "UPDATE agents SET last_heartbeat = ?1 WHERE name = ?2;",
+            &[&(Utc::now() - Duration::minutes(10)), &"agent1"],
+        )
+        .unwrap();
+        assert_eq!(agents.release_unreachable().unwrap(), vec!["dummy"]);
+
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Queued);
+        assert_eq!(ex.assigned_to, None);
+        assert_eq!(
+            ex.count_assigned_crates(&db, CrateAssignment::InProgress)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            ex.count_assigned_crates(&db, CrateAssignment::Completed)
+                .unwrap(),
+            1
+        );
+
+        // Another agent resumes the experiment without testing the completed crate again
+        let (_, mut ex) = Experiment::next(&db, &Assignee::Agent("agent2".into()))
+            .unwrap()
+            .unwrap();
+        ex.remove_completed_crates(&db).unwrap();
+        assert_eq!(ex.crates.len(), total - 1);
+        assert!(!ex.crates.contains(&completed));
     }
 }
@@ -1,5 +1,10 @@
 use crate::config::Config;
+use crate::experiments::{CapLints, Mode, Status};
 use crate::prelude::*;
+use crate::results::ResultsSummary;
+use crate::server::agents::AgentStatus;
+use crate::server::HttpError;
+use chrono::{DateTime, Utc};
 use http::header::{HeaderValue, CONTENT_TYPE};
 use http::Response;
 use http::StatusCode;
@@ -8,21 +13,165 @@ use serde::Serialize;
 use std::fmt;
 use std::fmt::Display;
 use std::str::FromStr;
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::{self, ContentBuilder, RefOr, ResponseBuilder};
+use utoipa::{ToResponse, ToSchema};
 
-#[derive(Serialize, Deserialize)]
+/// Latest version of the agent API, served under the `/agent-api/v{version}/` prefix.
+pub const AGENT_API_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct AgentConfig {
     pub agent_name: String,
+    /// The content of the server's `config.toml`.
+    #[schema(value_type = Object)]
     pub crater_config: Config,
+    /// Latest version of the agent API supported by the server. Servers predating the versioned
+    /// API don't send it.
+    #[serde(default)]
+    pub api_version: Option<u32>,
+    /// Where the agents download the binary they should replace themselves with, if any.
+    #[serde(default)]
+    pub agent_update_url: Option<String>,
+    /// Hex-encoded SHA256 checksum of the binary at `agent-update-url`.
+    #[serde(default)]
+    pub agent_update_sha256: Option<String>,
+}
+
+/// Environment of the host an agent is running on, reported by the agent when it starts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct HostInfo {
+    pub os: String,
+    pub kernel: Option<String>,
+    pub docker: Option<String>,
+}
+
+/// State of an agent, as returned by the public API.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct AgentInfo {
+    pub name: String,
+    pub status: AgentStatus,
+    pub last_heartbeat: Option<DateTime<Utc>>,
+    pub git_revision: Option<String>,
+    /// Experiment the agent is running, if any.
+    pub assigned_experiment: Option<String>,
+    /// Why the agent paused, if it reported a problem with its latest heartbeat.
+    pub unhealthy: Option<String>,
+    pub host: Option<HostInfo>,
+    /// Whether heartbeats with different git revisions were recently received, meaning more
+    /// than one process uses the token of the agent.
+    #[serde(default)]
+    pub conflict: bool,
+}
+
+/// How many crates the agents built recently, as returned by the public API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThroughputEstimate {
+    /// Length of the window the crates were counted in.
+    pub window_minutes: u32,
+    /// Crates built in the window, counting each toolchain separately.
+    pub crates: u64,
+    pub crates_per_minute: f64,
+    /// Agents which recorded at least one result in the window.
+    pub agents: Vec<AgentThroughput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct AgentThroughput {
+    pub name: String,
+    pub crates: u64,
+    pub crates_per_minute: f64,
+}
+
+/// Experiment, as listed by the public API.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExperimentSummary {
+    pub name: String,
+    pub status: Status,
+    pub mode: Mode,
+    pub priority: i32,
+    pub assigned_to: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub requested_by: Option<String>,
+    pub github_issue_url: Option<String>,
+    pub report_url: Option<String>,
+    /// Number of crates tested, excluding the skipped ones.
+    pub crates: u32,
+    /// Percentage of the results recorded, 100 once the experiment finished running.
+    pub progress: u8,
+}
+
+/// Everything known about an experiment, as returned by the public API.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExperimentDetails {
+    #[serde(flatten)]
+    pub summary: ExperimentSummary,
+    pub toolchains: Vec<String>,
+    pub cap_lints: CapLints,
+    pub completed_jobs: u32,
+    pub total_jobs: u32,
+    pub results: ResultsSummary,
+    /// When the experiment is expected to finish running, if it's running.
+    pub estimated_end: Option<DateTime<Utc>>,
+}
+
+/// Sent by `/api/v1/reports/events` every time the report of an experiment changes status.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReportEvent {
+    pub experiment: String,
+    pub status: Status,
+    pub at: DateTime<Utc>,
+    /// How long generating the report took, once it's generated or failed.
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Outcome of a single item of a batch request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ItemOutcome {
+    Success,
+    Error { error: String },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 #[serde(tag = "status", rename_all = "kebab-case")]
 pub enum ApiResponse<T> {
-    Success { result: T },
-    InternalError { error: String },
+    Success {
+        result: T,
+    },
+    /// Only some items of a batch request were processed, in the same order as the request.
+    Partial {
+        result: T,
+        items: Vec<ItemOutcome>,
+    },
+    InternalError {
+        error: String,
+    },
     Unauthorized,
     NotFound,
+    PayloadTooLarge {
+        error: String,
+    },
+    RequestTimeout {
+        error: String,
+    },
+    BadRequest {
+        error: String,
+    },
+    ServiceUnavailable {
+        error: String,
+    },
 }
 
 impl ApiResponse<()> {
@@ -37,15 +186,51 @@ impl ApiResponse<()> {
     pub(in crate::server) fn not_found() -> ApiResponse<()> {
         ApiResponse::NotFound
     }
+
+    pub(in crate::server) fn payload_too_large() -> ApiResponse<()> {
+        ApiResponse::PayloadTooLarge {
+            error: HttpError::PayloadTooLarge.to_string(),
+        }
+    }
+
+    pub(in crate::server) fn request_timeout() -> ApiResponse<()> {
+        ApiResponse::RequestTimeout {
+            error: HttpError::RequestTimeout.to_string(),
+        }
+    }
+
+    pub(in crate::server) fn service_unavailable(error: String) -> ApiResponse<()> {
+        ApiResponse::ServiceUnavailable { error }
+    }
+
+    pub(in crate::server) fn bad_request() -> ApiResponse<()> {
+        ApiResponse::BadRequest {
+            error: HttpError::BadRequest.to_string(),
+        }
+    }
 }
 
 impl<T> ApiResponse<T> {
+    /// Respond to a batch request, listing the outcome of each item if any of them failed.
+    pub(in crate::server) fn batch(result: T, items: Vec<ItemOutcome>) -> ApiResponse<T> {
+        if items.iter().all(|item| *item == ItemOutcome::Success) {
+            ApiResponse::Success { result }
+        } else {
+            ApiResponse::Partial { result, items }
+        }
+    }
+
     fn status_code(&self) -> StatusCode {
         match *self {
             ApiResponse::Success { .. } => StatusCode::OK,
+            ApiResponse::Partial { .. } => StatusCode::MULTI_STATUS,
             ApiResponse::InternalError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ApiResponse::Unauthorized => StatusCode::UNAUTHORIZED,
             ApiResponse::NotFound => StatusCode::NOT_FOUND,
+            ApiResponse::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiResponse::RequestTimeout { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiResponse::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            ApiResponse::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
@@ -62,6 +247,75 @@ impl<T: Serialize> ApiResponse<T> {
     }
 }
 
+/// Describe an error response of the API, whose body has the `status` field set to one of the
+/// `statuses` and, if `with_error` is set, an `error` field with its message.
+fn error_response(description: &str, statuses: &[&str], with_error: bool) -> openapi::Response {
+    let status = ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(statuses.iter().cloned()));
+    let mut body = ObjectBuilder::new()
+        .property("status", status)
+        .required("status");
+    if with_error {
+        body = body
+            .property("error", ObjectBuilder::new().schema_type(Type::String))
+            .required("error");
+    }
+
+    ResponseBuilder::new()
+        .description(description)
+        .content(
+            "application/json",
+            ContentBuilder::new().schema(Some(body)).build(),
+        )
+        .build()
+}
+
+macro_rules! error_responses {
+    ($($name:ident => ($description:expr, statuses = [$($status:expr),*], error = $error:expr),)*) => {
+        $(
+            pub(in crate::server) struct $name;
+
+            impl<'r> ToResponse<'r> for $name {
+                fn response() -> (&'r str, RefOr<openapi::Response>) {
+                    (
+                        stringify!($name),
+                        error_response($description, &[$($status),*], $error).into(),
+                    )
+                }
+            }
+        )*
+    };
+}
+
+// Responses of the errors shared by the endpoints, referenced by the OpenAPI description of the
+// server instead of repeating them in each endpoint.
+error_responses! {
+    BadRequest => (
+        "The request is malformed.",
+        statuses = ["bad-request"],
+        error = true
+    ),
+    Unauthorized => (
+        "The token is missing, invalid or not allowed to use this endpoint.",
+        statuses = ["unauthorized"],
+        error = false
+    ),
+    NotFound => ("The resource doesn't exist.", statuses = ["not-found"], error = false),
+    PayloadTooLarge => (
+        "The request body exceeds the limit configured in `server.body-limits`.",
+        statuses = ["payload-too-large"],
+        error = true
+    ),
+    RequestTimeout => (
+        "The request wasn't handled within the limit configured in `server.timeouts`, or the \
+         database was locked by other requests for too long. The request can be sent again.",
+        statuses = ["request-timeout", "service-unavailable"],
+        error = true
+    ),
+    InternalError => ("The request failed.", statuses = ["internal-error"], error = true),
+}
+
 #[derive(Debug, Clone)]
 pub struct CraterToken {
     pub token: String,
@@ -1,8 +1,12 @@
 use crate::config::Config;
 use crate::prelude::*;
+use crate::server::agent_tokens::AgentTokens;
 use crate::server::github::GitHubApi;
+use crate::server::read_only_tokens::ReadOnlyTokens;
+use crate::server::tokens::Tokens;
 use crate::server::{Data, HttpError};
 use http::header::{HeaderMap, AUTHORIZATION, USER_AGENT};
+use http::Method;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
@@ -13,9 +17,15 @@ lazy_static! {
         Regex::new(r"^crater(-agent)?/(?P<sha>[a-f0-9]{7,40})( \(.*\))?$").unwrap();
 }
 
-#[derive(Copy, Clone)]
+/// Minimum kind of token required by an endpoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TokenType {
     Agent,
+    /// Accepts both agent and read-only tokens, but read-only tokens are only allowed to perform
+    /// GET and HEAD requests.
+    ReadOnly,
+    /// Only accepts admin tokens: agent tokens are not enough to change stored data.
+    Admin,
 }
 
 pub struct AuthDetails {
@@ -44,7 +54,67 @@ fn git_revision(user_agent: &str) -> Option<String> {
         .map(|cap| cap["sha"].to_string())
 }
 
-fn check_auth(data: &Data, headers: &HeaderMap, token_type: TokenType) -> Option<AuthDetails> {
+fn token_name(
+    tokens: &Tokens,
+    agent_tokens: &AgentTokens,
+    read_only_tokens: &ReadOnlyTokens,
+    token: &str,
+    method: &Method,
+    token_type: TokenType,
+) -> Option<String> {
+    if let TokenType::Admin = token_type {
+        return tokens.admins.get(token).cloned();
+    }
+
+    // Rotated agent tokens are only known by the database
+    if let Some(name) = agent_tokens.agent_name(tokens, token) {
+        return Some(name);
+    }
+
+    match token_type {
+        TokenType::Agent | TokenType::Admin => None,
+        TokenType::ReadOnly => match *method {
+            Method::GET | Method::HEAD => tokens
+                .read_only
+                .get(token)
+                .cloned()
+                .or_else(|| read_only_tokens.name(token)),
+            _ => None,
+        },
+    }
+}
+
+/// Token sent in the `Authorization` header of the request, if any.
+fn request_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_token)
+}
+
+/// Kind of the token sent by the caller, regardless of the kind required by the endpoint. Admin
+/// tokens take precedence over agent tokens, which take precedence over read-only ones.
+pub(in crate::server) fn caller_token_type(data: &Data, headers: &HeaderMap) -> Option<TokenType> {
+    let token = request_token(headers)?;
+    if data.tokens.admins.contains_key(token) {
+        Some(TokenType::Admin)
+    } else if data.agent_tokens.agent_name(&data.tokens, token).is_some() {
+        Some(TokenType::Agent)
+    } else if data.tokens.read_only.contains_key(token)
+        || data.read_only_tokens.name(token).is_some()
+    {
+        Some(TokenType::ReadOnly)
+    } else {
+        None
+    }
+}
+
+fn check_auth(
+    data: &Data,
+    headers: &HeaderMap,
+    method: &Method,
+    token_type: TokenType,
+) -> Option<AuthDetails> {
     // Try to extract the git revision from the User-Agent header
     let git_revision = if let Some(ua_value) = headers.get(USER_AGENT) {
         if let Ok(ua) = ua_value.to_str() {
@@ -56,36 +126,30 @@ fn check_auth(data: &Data, headers: &HeaderMap, token_type: TokenType) -> Option
         None
     };
 
-    if let Some(authorization_value) = headers.get(AUTHORIZATION) {
-        if let Ok(authorization) = authorization_value.to_str() {
-            if let Some(token) = parse_token(authorization) {
-                let tokens = match token_type {
-                    TokenType::Agent => &data.tokens.agents,
-                };
-
-                if let Some(name) = tokens.get(token) {
-                    return Some(AuthDetails {
-                        name: name.clone(),
-                        git_revision,
-                    });
-                }
-            }
-        }
-    }
-
-    None
+    let token = request_token(headers)?;
+    token_name(
+        &data.tokens,
+        &data.agent_tokens,
+        &data.read_only_tokens,
+        token,
+        method,
+        token_type,
+    )
+    .map(|name| AuthDetails { name, git_revision })
 }
 
 pub fn auth_filter(
     data: Arc<Data>,
     token_type: TokenType,
 ) -> impl Filter<Extract = (AuthDetails,), Error = Rejection> + Clone {
-    warp::header::headers_cloned().and_then(move |headers| {
-        match check_auth(&data, &headers, token_type) {
-            Some(details) => Ok(details),
-            None => Err(warp::reject::custom(HttpError::Forbidden.compat())),
-        }
-    })
+    warp::header::headers_cloned()
+        .and(warp::method())
+        .and_then(
+            move |headers, method| match check_auth(&data, &headers, &method, token_type) {
+                Some(details) => Ok(details),
+                None => Err(warp::reject::custom(HttpError::Forbidden.compat())),
+            },
+        )
 }
 
 #[derive(Debug, Clone)]
@@ -179,7 +243,16 @@ impl ACL {
 
 #[cfg(test)]
 mod tests {
-    use super::{git_revision, parse_token};
+    use super::{caller_token_type, git_revision, parse_token, token_name, TokenType};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::server::agent_tokens::AgentTokens;
+    use crate::server::github::GitHubApi;
+    use crate::server::read_only_tokens::ReadOnlyTokens;
+    use crate::server::tokens::Tokens;
+    use crate::server::Data;
+    use http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use http::Method;
 
     #[test]
     fn test_parse_token() {
@@ -190,6 +263,152 @@ mod tests {
         assert_eq!(parse_token("CraterToken foo bar"), None);
     }
 
+    #[test]
+    fn test_token_name() {
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("agent-token".into(), "agent".into());
+        tokens.read_only.insert("ro-token".into(), "viewer".into());
+        tokens.admins.insert("admin-token".into(), "admin".into());
+        let db = Database::temp().unwrap();
+        let agent_tokens = AgentTokens::new(db.clone()).unwrap();
+        let read_only_tokens = ReadOnlyTokens::new(db).unwrap();
+        let created = read_only_tokens
+            .create(&tokens, "dashboard", "admin")
+            .unwrap()
+            .unwrap();
+
+        let name = |token: &str, method, token_type| {
+            token_name(
+                &tokens,
+                &agent_tokens,
+                &read_only_tokens,
+                token,
+                &method,
+                token_type,
+            )
+        };
+
+        // Agent tokens are accepted everywhere
+        assert_eq!(
+            name("agent-token", Method::POST, TokenType::Agent),
+            Some("agent".into())
+        );
+        assert_eq!(
+            name("agent-token", Method::GET, TokenType::ReadOnly),
+            Some("agent".into())
+        );
+        assert_eq!(
+            name("agent-token", Method::POST, TokenType::ReadOnly),
+            Some("agent".into())
+        );
+
+        // Read-only tokens are only accepted for reads
+        assert_eq!(
+            name("ro-token", Method::GET, TokenType::ReadOnly),
+            Some("viewer".into())
+        );
+        assert_eq!(
+            name("ro-token", Method::HEAD, TokenType::ReadOnly),
+            Some("viewer".into())
+        );
+        for method in &[Method::POST, Method::PATCH, Method::PUT, Method::DELETE] {
+            assert_eq!(name("ro-token", method.clone(), TokenType::ReadOnly), None);
+        }
+        assert_eq!(name("ro-token", Method::GET, TokenType::Agent), None);
+
+        // So are the ones created through the Admin API
+        assert_eq!(
+            name(&created, Method::GET, TokenType::ReadOnly),
+            Some("dashboard".into())
+        );
+        assert_eq!(name(&created, Method::POST, TokenType::ReadOnly), None);
+        assert_eq!(name(&created, Method::GET, TokenType::Agent), None);
+        assert_eq!(name(&created, Method::GET, TokenType::Admin), None);
+
+        // Only admin tokens are accepted by admin endpoints
+        assert_eq!(
+            name("admin-token", Method::POST, TokenType::Admin),
+            Some("admin".into())
+        );
+        assert_eq!(name("agent-token", Method::POST, TokenType::Admin), None);
+        assert_eq!(name("ro-token", Method::GET, TokenType::Admin), None);
+        assert_eq!(name("admin-token", Method::POST, TokenType::Agent), None);
+
+        assert_eq!(name("unknown", Method::GET, TokenType::ReadOnly), None);
+    }
+
+    #[test]
+    fn test_rotated_token_name() {
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("agent-token".into(), "agent".into());
+        let db = Database::temp().unwrap();
+        let agent_tokens = AgentTokens::new(db.clone()).unwrap();
+        let read_only_tokens = ReadOnlyTokens::new(db).unwrap();
+
+        let name = |token: &str, method, token_type| {
+            token_name(
+                &tokens,
+                &agent_tokens,
+                &read_only_tokens,
+                token,
+                &method,
+                token_type,
+            )
+        };
+        let new = agent_tokens.rotate(&tokens, "agent").unwrap().unwrap();
+
+        // The token from tokens.toml is rejected everywhere after the rotation
+        for &token_type in &[TokenType::Agent, TokenType::ReadOnly, TokenType::Admin] {
+            assert_eq!(name("agent-token", Method::POST, token_type), None);
+        }
+        assert_eq!(
+            name(&new, Method::POST, TokenType::Agent),
+            Some("agent".into())
+        );
+        assert_eq!(
+            name(&new, Method::GET, TokenType::ReadOnly),
+            Some("agent".into())
+        );
+        assert_eq!(name(&new, Method::POST, TokenType::Admin), None);
+    }
+
+    #[test]
+    fn test_caller_token_type() {
+        let config = Config::default();
+        let mut tokens = Tokens::default();
+        tokens.bot.api_token = "github-token".into();
+        tokens.agents.insert("agent-token".into(), "agent".into());
+        tokens.read_only.insert("ro-token".into(), "viewer".into());
+        tokens.admins.insert("admin-token".into(), "admin".into());
+        let github = GitHubApi::new(&config.github, &tokens).unwrap();
+        let db = Database::temp().unwrap();
+        let data = Data::new(config, db, tokens, github, "crater-bot".into()).unwrap();
+        let created = data
+            .read_only_tokens
+            .create(&data.tokens, "dashboard", "admin")
+            .unwrap()
+            .unwrap();
+
+        let token_type = |token: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(token) = token {
+                let value = format!("CraterToken {}", token);
+                headers.insert(AUTHORIZATION, HeaderValue::from_str(&value).unwrap());
+            }
+            caller_token_type(&data, &headers)
+        };
+
+        assert_eq!(token_type(Some("admin-token")), Some(TokenType::Admin));
+        assert_eq!(token_type(Some("agent-token")), Some(TokenType::Agent));
+        assert_eq!(token_type(Some("ro-token")), Some(TokenType::ReadOnly));
+        assert_eq!(
+            token_type(Some(created.as_str())),
+            Some(TokenType::ReadOnly)
+        );
+        assert_eq!(token_type(Some("unknown")), None);
+        assert_eq!(token_type(None), None);
+    }
+
     #[test]
     fn test_git_revision() {
         for sha in &["0000000", "0000000000000000000000000000000000000000"] {
@@ -0,0 +1,155 @@
+use crate::config::BackupsConfig;
+use crate::db::Database;
+use crate::dirs::WORK_DIR;
+use crate::prelude::*;
+use crate::report::{get_client_for_bucket, S3Prefix};
+use crate::utils;
+use chrono::Utc;
+use rusoto_s3::{DeleteObjectRequest, ListObjectsV2Request, PutObjectRequest, S3};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir_in;
+
+static SNAPSHOT_PREFIX: &str = "crater-";
+static SNAPSHOT_EXTENSION: &str = ".db";
+
+enum Destination {
+    Directory(PathBuf),
+    S3(S3Prefix),
+}
+
+impl Destination {
+    fn new(dest: &str) -> Fallible<Self> {
+        if dest.starts_with("s3://") {
+            Ok(Destination::S3(dest.parse()?))
+        } else {
+            fs::create_dir_all(dest)?;
+            Ok(Destination::Directory(dest.into()))
+        }
+    }
+
+    fn s3_key(prefix: &S3Prefix, name: &str) -> String {
+        prefix.prefix.join(name).to_string_lossy().into()
+    }
+
+    fn store(&self, name: &str, snapshot: &Path) -> Fallible<()> {
+        match self {
+            Destination::Directory(dir) => {
+                fs::copy(snapshot, dir.join(name))?;
+            }
+            Destination::S3(prefix) => {
+                get_client_for_bucket(&prefix.bucket)?
+                    .put_object(PutObjectRequest {
+                        bucket: prefix.bucket.clone(),
+                        key: Destination::s3_key(prefix, name),
+                        body: Some(fs::read(snapshot)?.into()),
+                        ..Default::default()
+                    })
+                    .sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the snapshots stored in the destination.
+    fn list(&self) -> Fallible<Vec<String>> {
+        let mut names = Vec::new();
+        match self {
+            Destination::Directory(dir) => {
+                for entry in fs::read_dir(dir)? {
+                    names.push(entry?.file_name().to_string_lossy().into_owned());
+                }
+            }
+            Destination::S3(prefix) => {
+                let client = get_client_for_bucket(&prefix.bucket)?;
+                let mut continuation_token = None;
+                loop {
+                    let output = client
+                        .list_objects_v2(ListObjectsV2Request {
+                            bucket: prefix.bucket.clone(),
+                            prefix: Some(Destination::s3_key(prefix, SNAPSHOT_PREFIX)),
+                            continuation_token,
+                            ..Default::default()
+                        })
+                        .sync()?;
+                    for object in output.contents.unwrap_or_default() {
+                        if let Some(key) = object.key {
+                            names.push(key.rsplit('/').next().unwrap().to_string());
+                        }
+                    }
+
+                    continuation_token = output.next_continuation_token;
+                    if continuation_token.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        names
+            .retain(|name| name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_EXTENSION));
+        Ok(names)
+    }
+
+    fn remove(&self, name: &str) -> Fallible<()> {
+        match self {
+            Destination::Directory(dir) => fs::remove_file(dir.join(name))?,
+            Destination::S3(prefix) => {
+                get_client_for_bucket(&prefix.bucket)?
+                    .delete_object(DeleteObjectRequest {
+                        bucket: prefix.bucket.clone(),
+                        key: Destination::s3_key(prefix, name),
+                        ..Default::default()
+                    })
+                    .sync()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn take_snapshot(db: &Database, dest: &Destination, keep: usize) -> Fallible<()> {
+    // The names sort chronologically, which is relied on to find the oldest snapshots
+    let name = format!(
+        "{}{}{}",
+        SNAPSHOT_PREFIX,
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        SNAPSHOT_EXTENSION
+    );
+
+    let dir = tempdir_in(&*WORK_DIR)?;
+    let path = dir.path().join(&name);
+    db.backup(&path)?;
+    dest.store(&name, &path)?;
+    info!("stored the database backup {}", name);
+
+    let mut snapshots = dest.list()?;
+    snapshots.sort();
+    let outdated = snapshots.len().saturating_sub(keep);
+    for name in &snapshots[..outdated] {
+        dest.remove(name)?;
+        info!("removed the old database backup {}", name);
+    }
+
+    Ok(())
+}
+
+/// Back up the database in the background every `interval-hours`.
+pub fn spawn(db: Database, config: &BackupsConfig) -> Fallible<()> {
+    let dest = Destination::new(&config.destination)?;
+    let interval = Duration::from_secs(config.interval_hours * 3600);
+    let keep = config.keep;
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        if let Err(err) = take_snapshot(&db, &dest, keep) {
+            error!("failed to back up the database");
+            utils::report_failure(&err);
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use crate::prelude::*;
+use crate::server::timeout::{self, Deadline};
+use crate::server::{Data, HttpError};
+use bytes::buf::Buf;
+use futures::{Future, Stream};
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::sync::Arc;
+use tokio::util::FutureExt;
+use warp::{self, body::BodyStream, Filter, Rejection};
+
+/// Read the whole request body, rejecting the request if the client doesn't send it before the
+/// deadline of its token. This prevents slow clients from holding the server's resources
+/// indefinitely.
+pub(in crate::server) fn bytes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Vec<u8>,), Error = Rejection> + Clone {
+    timeout::caller_deadline(data)
+        .and(warp::body::stream())
+        .and_then(|deadline: Deadline, body: BodyStream| {
+            body.fold(Vec::new(), |mut content, chunk| -> Result<_, warp::Error> {
+                content.extend_from_slice(chunk.bytes());
+                Ok(content)
+            })
+            .timeout(deadline.duration)
+            .map_err(|err| match err.into_inner() {
+                Some(err) => warp::reject::custom(err),
+                None => warp::reject::custom(HttpError::RequestTimeout.compat()),
+            })
+        })
+}
+
+/// Deserialize the JSON request body, with the same deadline as `bytes`.
+pub(in crate::server) fn json<T: DeserializeOwned + Send>(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    bytes(data).and_then(|body: Vec<u8>| {
+        serde_json::from_slice(&body).map_err(|err| {
+            debug!("invalid JSON request body: {}", err);
+            warp::reject::custom(err)
+        })
+    })
+}
@@ -0,0 +1,248 @@
+use crate::prelude::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Only the calls made in this window are considered when deciding whether to open the circuit.
+const WINDOW: Duration = Duration::from_secs(60);
+/// How long calls fail fast after the circuit is opened, before a probe is allowed.
+const OPEN_FOR: Duration = Duration::from_secs(60);
+/// Avoid opening the circuit because of a single unlucky call.
+const MIN_CALLS: usize = 5;
+/// The circuit opens when more than this fraction of the calls in the window failed.
+const MAX_FAILURE_RATE: f64 = 0.5;
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "too many recent failures, not calling {} for {} more seconds",
+    name, retry_in_secs
+)]
+pub struct CircuitOpen {
+    name: &'static str,
+    retry_in_secs: u64,
+}
+
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum CircuitState {
+    /// Calls are allowed.
+    Closed,
+    /// Calls fail fast.
+    Open,
+    /// A single call is allowed to check whether the service recovered.
+    HalfOpen,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub recent_calls: usize,
+    pub recent_failures: usize,
+}
+
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { probing: bool },
+}
+
+struct Inner {
+    state: State,
+    /// When each recent call finished, and whether it succeeded.
+    calls: VecDeque<(Instant, bool)>,
+}
+
+impl Inner {
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.calls.front() {
+            if at + WINDOW >= now {
+                break;
+            }
+            self.calls.pop_front();
+        }
+    }
+
+    fn failures(&self) -> usize {
+        self.calls.iter().filter(|&&(_, success)| !success).count()
+    }
+}
+
+/// Stop calling an external service for a while when most of the recent calls to it failed, so
+/// crater doesn't wait on a service that's down or rate limiting it.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str) -> Self {
+        CircuitBreaker {
+            name,
+            inner: Arc::new(Mutex::new(Inner {
+                state: State::Closed,
+                calls: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Execute the call if the circuit allows it. Only the errors for which `is_failure` returns
+    /// true count as failures of the service.
+    pub fn call<T, F, P>(&self, is_failure: P, f: F) -> Fallible<T>
+    where
+        F: FnOnce() -> Fallible<T>,
+        P: FnOnce(&failure::Error) -> bool,
+    {
+        self.check(Instant::now())?;
+        let result = f();
+        let success = match result {
+            Ok(_) => true,
+            Err(ref err) => !is_failure(err),
+        };
+        self.record(Instant::now(), success);
+        result
+    }
+
+    /// Return an error if calls are not allowed right now. If the circuit is half-open this
+    /// reserves the probe call, so the result of the call must be recorded afterwards.
+    fn check(&self, now: Instant) -> Fallible<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let retry_in = match inner.state {
+            State::Closed => return Ok(()),
+            State::Open { until } if now >= until => {
+                inner.state = State::HalfOpen { probing: true };
+                return Ok(());
+            }
+            State::Open { until } => until - now,
+            State::HalfOpen { probing: false } => {
+                inner.state = State::HalfOpen { probing: true };
+                return Ok(());
+            }
+            // Another call is already checking whether the service recovered
+            State::HalfOpen { probing: true } => Duration::from_secs(0),
+        };
+
+        Err(CircuitOpen {
+            name: self.name,
+            retry_in_secs: retry_in.as_secs(),
+        }
+        .into())
+    }
+
+    fn record(&self, now: Instant, success: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::HalfOpen { .. } if success => {
+                info!("{} recovered, closing the circuit", self.name);
+                inner.state = State::Closed;
+                inner.calls.clear();
+            }
+            State::HalfOpen { .. } => {
+                warn!("{} is still failing, keeping the circuit open", self.name);
+                inner.state = State::Open {
+                    until: now + OPEN_FOR,
+                };
+            }
+            // Calls started before the circuit was opened don't change anything
+            State::Open { .. } => {}
+            State::Closed => {
+                inner.calls.push_back((now, success));
+                inner.expire(now);
+
+                let calls = inner.calls.len();
+                let failures = inner.failures();
+                if calls >= MIN_CALLS && failures as f64 / calls as f64 > MAX_FAILURE_RATE {
+                    warn!(
+                        "{} out of {} recent calls to {} failed, opening the circuit",
+                        failures, calls, self.name
+                    );
+                    inner.state = State::Open {
+                        until: now + OPEN_FOR,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Return an error if the circuit is open, without reserving the probe call.
+    pub fn ensure_closed(&self) -> Fallible<()> {
+        let now = Instant::now();
+        match self.inner.lock().unwrap().state {
+            State::Open { until } if now < until => Err(CircuitOpen {
+                name: self.name,
+                retry_in_secs: (until - now).as_secs(),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.expire(now);
+
+        CircuitStatus {
+            state: match inner.state {
+                State::Closed => CircuitState::Closed,
+                State::Open { until } if now < until => CircuitState::Open,
+                State::Open { .. } | State::HalfOpen { .. } => CircuitState::HalfOpen,
+            },
+            recent_calls: inner.calls.len(),
+            recent_failures: inner.failures(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitBreaker, CircuitState, MIN_CALLS, OPEN_FOR, WINDOW};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new("test");
+        let start = Instant::now();
+        let state = |breaker: &CircuitBreaker| breaker.status().state;
+
+        // A few failures aren't enough to open the circuit
+        for _ in 0..MIN_CALLS - 1 {
+            breaker.check(start).unwrap();
+            breaker.record(start, false);
+        }
+        assert_eq!(state(&breaker), CircuitState::Closed);
+
+        // Failures are forgotten after the window expires
+        let later = start + WINDOW + Duration::from_secs(1);
+        for _ in 0..MIN_CALLS {
+            breaker.check(later).unwrap();
+            breaker.record(later, true);
+        }
+        assert_eq!(breaker.status().recent_failures, 0);
+
+        // The circuit opens when more than half of the recent calls failed
+        for _ in 0..MIN_CALLS + 1 {
+            breaker.check(later).unwrap();
+            breaker.record(later, false);
+        }
+        assert_eq!(state(&breaker), CircuitState::Open);
+        assert!(breaker.check(later).is_err());
+        assert!(breaker.ensure_closed().is_err());
+
+        // After a while a single probe is allowed, and it opens the circuit again if it fails
+        let probe = later + OPEN_FOR;
+        breaker.check(probe).unwrap();
+        assert!(breaker.check(probe).is_err());
+        breaker.record(probe, false);
+        assert!(breaker.check(probe).is_err());
+
+        // A successful probe closes the circuit
+        let probe = probe + OPEN_FOR;
+        breaker.check(probe).unwrap();
+        breaker.record(probe, true);
+        assert_eq!(state(&breaker), CircuitState::Closed);
+        breaker.check(probe).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use crate::server::Data;
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, HOST, ORIGIN, VARY,
+};
+use http::{Method, Response, StatusCode};
+use hyper::Body;
+use std::sync::Arc;
+use warp::{self, Filter, Rejection};
+
+const ALLOWED_METHODS: &str = "GET, POST, PUT, PATCH, DELETE";
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type";
+const MAX_AGE_SECS: &str = "3600";
+
+fn allowed_origin(allowed: &[String], headers: &HeaderMap) -> Option<HeaderValue> {
+    let origin = headers.get(ORIGIN)?;
+    let origin_str = origin.to_str().ok()?;
+    if allowed.iter().any(|o| o == origin_str) {
+        Some(origin.clone())
+    } else {
+        None
+    }
+}
+
+fn cors_headers(
+    allowed: &[String],
+    headers: &HeaderMap,
+    preflight: bool,
+) -> Vec<(HeaderName, HeaderValue)> {
+    let mut result = Vec::new();
+    if allowed.is_empty() {
+        return result;
+    }
+
+    // The response changes depending on the origin, so caches must be aware of that
+    result.push((VARY, HeaderValue::from_static("Origin")));
+    if let Some(origin) = allowed_origin(allowed, headers) {
+        result.push((ACCESS_CONTROL_ALLOW_ORIGIN, origin));
+        if preflight {
+            result.push((
+                ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static(ALLOWED_METHODS),
+            ));
+            result.push((
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOWED_HEADERS),
+            ));
+            result.push((
+                ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from_static(MAX_AGE_SECS),
+            ));
+        }
+    }
+    result
+}
+
+/// Whether the page that sent the request is allowed to open a websocket. Browsers don't apply
+/// the CORS policy to websockets, so the server rejects the pages served by other origins than
+/// itself and the allowed ones. Requests not sent by browsers don't have an origin.
+fn websocket_origin_allowed(allowed: &[String], headers: &HeaderMap) -> bool {
+    let origin = match headers.get(ORIGIN).map(|origin| origin.to_str()) {
+        Some(Ok(origin)) => origin,
+        Some(Err(_)) => return false,
+        None => return true,
+    };
+    if allowed_origin(allowed, headers).is_some() {
+        return true;
+    }
+
+    let host = headers.get(HOST).and_then(|host| host.to_str().ok());
+    host.is_some() && origin.splitn(2, "://").nth(1) == host
+}
+
+/// Whether a websocket can be opened by the page that sent the request.
+pub fn websocket_allowed(data: &Data, headers: &HeaderMap) -> bool {
+    websocket_origin_allowed(&data.config.server.cors_allowed_origins, headers)
+}
+
+/// Add the CORS headers to a response, if the origin of the request is allowed.
+pub fn add_headers(data: &Data, headers: &HeaderMap, resp: &mut Response<Body>) {
+    for (name, value) in cors_headers(&data.config.server.cors_allowed_origins, headers, false) {
+        resp.headers_mut().insert(name, value);
+    }
+}
+
+/// Answer the CORS preflight requests. Authentication is not required, as browsers don't send
+/// the credentials when doing the preflight.
+pub fn preflight(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::method().and(warp::header::headers_cloned()).and_then(
+        move |method: Method, headers: HeaderMap| {
+            if method != Method::OPTIONS {
+                return Err(warp::reject());
+            }
+
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = StatusCode::NO_CONTENT;
+            for (name, value) in
+                cors_headers(&data.config.server.cors_allowed_origins, &headers, true)
+            {
+                resp.headers_mut().insert(name, value);
+            }
+            Ok(resp)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cors_headers, websocket_origin_allowed};
+    use http::header::{
+        HeaderMap, HeaderValue, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, HOST,
+        ORIGIN,
+    };
+
+    #[test]
+    fn test_cors_headers() {
+        let allowed = vec!["https://dashboard.example.com".to_string()];
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ORIGIN,
+            HeaderValue::from_static("https://dashboard.example.com"),
+        );
+
+        // No CORS headers are sent if no origin is allowed
+        assert!(cors_headers(&[], &headers, true).is_empty());
+
+        let result = cors_headers(&allowed, &headers, false);
+        assert!(result
+            .iter()
+            .any(|(name, value)| name == ACCESS_CONTROL_ALLOW_ORIGIN
+                && value == "https://dashboard.example.com"));
+        assert!(!result
+            .iter()
+            .any(|(name, _)| name == ACCESS_CONTROL_ALLOW_METHODS));
+
+        let result = cors_headers(&allowed, &headers, true);
+        assert!(result
+            .iter()
+            .any(|(name, _)| name == ACCESS_CONTROL_ALLOW_METHODS));
+
+        // Other origins are not allowed
+        headers.insert(ORIGIN, HeaderValue::from_static("https://evil.example.com"));
+        assert!(!cors_headers(&allowed, &headers, true)
+            .iter()
+            .any(|(name, _)| name == ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn test_websocket_origin_allowed() {
+        let allowed = vec!["https://dashboard.example.com".to_string()];
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("crater.example.com"));
+
+        // Clients other than browsers don't send an origin
+        assert!(websocket_origin_allowed(&[], &headers));
+
+        // Pages served by the server itself are allowed
+        headers.insert(
+            ORIGIN,
+            HeaderValue::from_static("https://crater.example.com"),
+        );
+        assert!(websocket_origin_allowed(&[], &headers));
+
+        // Other pages are only allowed if their origin is
+        headers.insert(
+            ORIGIN,
+            HeaderValue::from_static("https://dashboard.example.com"),
+        );
+        assert!(!websocket_origin_allowed(&[], &headers));
+        assert!(websocket_origin_allowed(&allowed, &headers));
+        headers.insert(ORIGIN, HeaderValue::from_static("https://evil.example.com"));
+        assert!(!websocket_origin_allowed(&allowed, &headers));
+    }
+}
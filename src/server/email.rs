@@ -0,0 +1,92 @@
+//! Digests of the published reports, emailed to the recipients configured in the `[server.email]`
+//! section of `config.toml`. The digest is the Markdown summary of the report, sent both as plain
+//! text and rendered as HTML.
+
+use crate::config::EmailConfig;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{render_summary, TestResults};
+use crate::server::tokens::Tokens;
+use lettre::smtp::authentication::Credentials;
+use lettre::{SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use pulldown_cmark::{html, Options, Parser};
+
+/// Crates listed in each section of the digest, the full list is in the report.
+const CRATES_PER_SECTION: usize = 10;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Email {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl Email {
+    fn from_markdown(subject: String, markdown: String) -> Self {
+        let mut html = String::new();
+        html::push_html(
+            &mut html,
+            Parser::new_ext(&markdown, Options::ENABLE_TABLES),
+        );
+        Email {
+            subject,
+            text: markdown,
+            html,
+        }
+    }
+
+    /// Counts of the report, the biggest groups of regressions and the link to the full report.
+    pub fn digest(ex: &Experiment, res: &TestResults, report_url: &str) -> Self {
+        Email::from_markdown(
+            format!("Crater report for {}", ex.name),
+            render_summary(ex, res, Some(report_url), CRATES_PER_SECTION),
+        )
+    }
+
+    /// Email checking the configuration, sent by `crater test-email`.
+    pub fn test() -> Self {
+        Email::from_markdown(
+            "Crater test email".into(),
+            "The email digests of the Crater reports are configured correctly.".into(),
+        )
+    }
+
+    /// Send the email to all the recipients.
+    pub fn send(&self, config: &EmailConfig, tokens: &Tokens) -> Fallible<()> {
+        let mut builder = EmailBuilder::new()
+            .from(config.from.as_str())
+            .subject(self.subject.as_str())
+            .alternative(self.html.as_str(), self.text.as_str());
+        for recipient in &config.recipients {
+            builder = builder.to(recipient.as_str());
+        }
+        let email = builder.build()?;
+
+        let mut client = SmtpClient::new_simple(&config.smtp_host)?;
+        if let Some(ref credentials) = tokens.email {
+            client = client.credentials(Credentials::new(
+                credentials.username.clone(),
+                credentials.password.clone(),
+            ));
+        }
+        client.transport().send(email.into())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Email;
+
+    #[test]
+    fn test_from_markdown() {
+        let email = Email::from_markdown(
+            "subject".into(),
+            "# Report\n\n| Result | Count |\n| --- | ---: |\n| regressed | 2 |\n".into(),
+        );
+        assert!(email.text.starts_with("# Report"));
+        assert!(email.html.contains("<h1>Report</h1>"));
+        assert!(email.html.contains("<td>regressed</td>"));
+    }
+}
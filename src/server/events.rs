@@ -0,0 +1,232 @@
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::results::{TaskResult, TestResult};
+use crate::server::api_types::ReportEvent;
+use crate::utils;
+use chrono::Utc;
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Sent to the subscribers of an experiment every time an agent records a result for it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProgressEvent {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub toolchain: String,
+    pub result: TestResult,
+}
+
+/// Dispatch the progress of each experiment to the clients subscribed to it.
+#[derive(Clone, Default)]
+pub struct ProgressEvents {
+    subscribers: Arc<Mutex<HashMap<String, Vec<UnboundedSender<ProgressEvent>>>>>,
+}
+
+impl ProgressEvents {
+    pub fn new() -> Self {
+        ProgressEvents::default()
+    }
+
+    /// Receive the events of the experiment. The subscription ends when the receiver is dropped.
+    pub fn subscribe(&self, ex: &str) -> UnboundedReceiver<ProgressEvent> {
+        let (send, recv) = mpsc::unbounded();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .entry(ex.to_string())
+            .or_insert_with(Vec::new)
+            .push(send);
+        recv
+    }
+
+    pub fn publish<'a, I>(&self, ex: &Experiment, results: I)
+    where
+        I: IntoIterator<Item = &'a TaskResult>,
+    {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let senders = match subscribers.get_mut(&ex.name) {
+            Some(senders) => senders,
+            None => return,
+        };
+
+        for result in results {
+            let event = ProgressEvent {
+                krate: result.krate.id(),
+                toolchain: result.toolchain.to_string(),
+                result: result.result,
+            };
+            // Sending only fails if the client went away
+            senders.retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+        }
+
+        if senders.is_empty() {
+            subscribers.remove(&ex.name);
+        }
+    }
+}
+
+/// Dispatch the changes of the status of the reports to the subscribed clients.
+#[derive(Clone, Default)]
+pub struct ReportEvents {
+    subscribers: Arc<Mutex<Vec<UnboundedSender<ReportEvent>>>>,
+}
+
+impl ReportEvents {
+    pub fn new() -> Self {
+        ReportEvents::default()
+    }
+
+    /// Receive the events of all the experiments. The subscription ends when the receiver is
+    /// dropped.
+    pub fn subscribe(&self) -> UnboundedReceiver<ReportEvent> {
+        let (send, recv) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().push(send);
+        recv
+    }
+
+    /// Announce the current status of the experiment, with how long generating its report took
+    /// if it's done.
+    pub fn publish(&self, ex: &Experiment, duration: Option<Duration>, error: Option<String>) {
+        // The events are served by the public API
+        if ex.private {
+            return;
+        }
+
+        let event = ReportEvent {
+            experiment: ex.name.clone(),
+            status: ex.status,
+            at: Utc::now(),
+            duration_ms: duration.map(utils::duration_millis),
+            error,
+        };
+        // Sending only fails if the client went away
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProgressEvent, ProgressEvents, ReportEvents};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::{Experiment, Status};
+    use crate::results::{FailureReason, TaskResult, TestResult};
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use futures::Stream;
+    use std::time::Duration;
+
+    #[test]
+    fn test_progress_events() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+        let first = Experiment::get(&db, "first").unwrap().unwrap();
+
+        let events = ProgressEvents::new();
+        let first_events = events.subscribe("first");
+        let second_events = events.subscribe("second");
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1.0.0".into(),
+        });
+        let task = |toolchain, result| TaskResult {
+            krate: krate.clone(),
+            toolchain,
+            result,
+            log: String::new(),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        events.publish(
+            &first,
+            &[
+                task(MAIN_TOOLCHAIN.clone(), TestResult::TestPass),
+                task(
+                    TEST_TOOLCHAIN.clone(),
+                    TestResult::BuildFail(FailureReason::Unknown),
+                ),
+            ],
+        );
+
+        // Dropping the dispatcher ends the subscriptions, so the streams can be collected
+        drop(events);
+        assert_eq!(
+            first_events.wait().collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![
+                ProgressEvent {
+                    krate: "reg/lazy_static/1.0.0".into(),
+                    toolchain: MAIN_TOOLCHAIN.to_string(),
+                    result: TestResult::TestPass,
+                },
+                ProgressEvent {
+                    krate: "reg/lazy_static/1.0.0".into(),
+                    toolchain: TEST_TOOLCHAIN.to_string(),
+                    result: TestResult::BuildFail(FailureReason::Unknown),
+                },
+            ]
+        );
+        // Subscribers of other experiments don't receive anything
+        assert!(second_events.wait().next().is_none());
+    }
+
+    #[test]
+    fn test_report_events() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("public").apply(&ctx).unwrap();
+        CreateExperiment {
+            private: true,
+            ..CreateExperiment::dummy("private")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let mut public = Experiment::get(&db, "public").unwrap().unwrap();
+        let mut private = Experiment::get(&db, "private").unwrap().unwrap();
+
+        let events = ReportEvents::new();
+        let received = events.subscribe();
+
+        public.set_status(&db, Status::NeedsReport).unwrap();
+        events.publish(&public, None, None);
+        private.set_status(&db, Status::NeedsReport).unwrap();
+        events.publish(&private, None, None);
+        public.set_status(&db, Status::ReportFailed).unwrap();
+        events.publish(
+            &public,
+            Some(Duration::from_millis(1500)),
+            Some("no space left".into()),
+        );
+
+        drop(events);
+        let received = received.wait().collect::<Result<Vec<_>, _>>().unwrap();
+        // Private experiments are not announced
+        assert_eq!(
+            received
+                .iter()
+                .map(|e| (e.experiment.as_str(), e.status, e.duration_ms))
+                .collect::<Vec<_>>(),
+            vec![
+                ("public", Status::NeedsReport, None),
+                ("public", Status::ReportFailed, Some(1500)),
+            ]
+        );
+        assert_eq!(received[1].error.as_ref().unwrap(), "no space left");
+    }
+}
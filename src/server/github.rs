@@ -1,129 +1,448 @@
+use crate::config::GitHubConfig;
 use crate::prelude::*;
-use crate::server::tokens::Tokens;
+use crate::server::circuit_breaker::CircuitBreaker;
+use crate::server::tokens::{BotTokens, Tokens};
 use crate::utils;
-use http::header::AUTHORIZATION;
+use chrono::{DateTime, Utc};
+use http::header::{HeaderMap, ACCEPT, AUTHORIZATION};
 use http::Method;
 use http::StatusCode;
-use reqwest::RequestBuilder;
+use reqwest::{RequestBuilder, Response};
+use ring::{rand::SystemRandom, signature};
 use serde_json::json;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use untrusted;
+
+/// GitHub rejects JWTs valid for more than 10 minutes.
+const JWT_VALIDITY_SECS: i64 = 9 * 60;
+/// Installation tokens are refreshed a bit before they expire, to avoid using an expired one.
+const INSTALLATION_TOKEN_MARGIN_SECS: i64 = 5 * 60;
+/// GitHub Apps endpoints are still in preview.
+const APPS_PREVIEW: &str = "application/vnd.github.machine-man-preview+json";
 
 #[derive(Debug, Fail)]
 pub enum GitHubError {
     #[fail(display = "request to GitHub API failed with status {}: {}", _0, _1)]
     RequestFailed(StatusCode, String),
+    #[fail(display = "GitHub API rate limit exceeded: {}", message)]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+}
+
+/// Whether the error was caused by GitHub being unavailable or rate limiting crater, rather than
+/// by the request itself.
+pub(in crate::server) fn is_outage(err: &::failure::Error) -> bool {
+    match err.downcast_ref::<GitHubError>() {
+        Some(GitHubError::RequestFailed(status, _)) => {
+            status.is_server_error()
+                || *status == StatusCode::FORBIDDEN
+                || *status == StatusCode::TOO_MANY_REQUESTS
+        }
+        Some(GitHubError::RateLimited { .. }) => true,
+        // Network errors and unexpected responses
+        None => true,
+    }
+}
+
+/// How long to wait before retrying a failed request, or `None` if retrying it won't help. Server
+/// errors are retried after `backoff`, while rate limited requests wait for the limit to reset.
+pub(in crate::server) fn retry_delay(
+    err: &::failure::Error,
+    backoff: Duration,
+) -> Option<Duration> {
+    match err.downcast_ref::<GitHubError>() {
+        Some(GitHubError::RateLimited { retry_after, .. }) => Some(retry_after.unwrap_or(backoff)),
+        Some(GitHubError::RequestFailed(status, _)) if status.is_server_error() => Some(backoff),
+        _ => None,
+    }
+}
+
+/// Time until GitHub accepts requests again, from either the `Retry-After` header or the reset
+/// time of the rate limit.
+fn rate_limit_reset(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let header = |name| headers.get(name)?.to_str().ok()?.parse::<i64>().ok();
+
+    if let Some(secs) = header("retry-after") {
+        Some(Duration::from_secs(secs.max(0) as u64))
+    } else if let Some(reset) = header("x-ratelimit-reset") {
+        Some(Duration::from_secs((reset - now.timestamp()).max(0) as u64))
+    } else {
+        None
+    }
+}
+
+fn request_failed(mut response: Response) -> ::failure::Error {
+    let status = response.status();
+    // Error pages returned by GitHub when it's down are not always JSON
+    let message = response
+        .json::<Error>()
+        .map(|error| error.message)
+        .unwrap_or_else(|_| status.to_string());
+
+    // GitHub also uses 403 for requests over the rate limit, with no remaining requests
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && response
+                .headers()
+                .get("x-ratelimit-remaining")
+                .map(|remaining| remaining == "0")
+                .unwrap_or(false));
+
+    if rate_limited {
+        GitHubError::RateLimited {
+            message,
+            retry_after: rate_limit_reset(response.headers(), Utc::now()),
+        }
+        .into()
+    } else {
+        GitHubError::RequestFailed(status, message).into()
+    }
+}
+
+/// URL of an API endpoint from its path relative to the root of the API, like
+/// `orgs/rust-lang/teams`. The root of GitHub Enterprise APIs is under `/api/v3`, so the path is
+/// appended to it instead of replacing it. Absolute URLs, like the ones of the issues received in
+/// the webhooks, are used as-is.
+fn endpoint_url(api_url: &str, path: &str) -> String {
+    if path.starts_with("https://") || path.starts_with("http://") {
+        path.to_string()
+    } else {
+        format!(
+            "{}/{}",
+            api_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
 }
 
+/// How crater authenticates with the GitHub API.
 #[derive(Clone)]
-pub struct GitHubApi {
-    token: String,
+pub enum GitHubAuth {
+    PersonalAccessToken(String),
+    App(AppCredentials),
 }
 
-impl GitHubApi {
-    pub fn new(tokens: &Tokens) -> Self {
-        GitHubApi {
-            token: tokens.bot.api_token.clone(),
+impl GitHubAuth {
+    /// Use the GitHub App if it's configured, or the personal access token otherwise.
+    pub fn from_tokens(tokens: &BotTokens) -> Fallible<Self> {
+        match (
+            tokens.github_app_id,
+            &tokens.github_app_private_key_path,
+            tokens.github_app_installation_id,
+        ) {
+            (Some(app_id), Some(key_path), Some(installation_id)) => Ok(GitHubAuth::App(
+                AppCredentials::load(app_id, key_path, installation_id)?,
+            )),
+            (None, None, None) if !tokens.api_token.is_empty() => {
+                Ok(GitHubAuth::PersonalAccessToken(tokens.api_token.clone()))
+            }
+            (None, None, None) => bail!("no GitHub API token or GitHub App configured"),
+            _ => bail!(
+                "github-app-id, github-app-private-key-path and github-app-installation-id \
+                 must all be set to authenticate as a GitHub App"
+            ),
         }
     }
+}
 
-    fn build_request(&self, method: Method, url: &str) -> RequestBuilder {
-        let url = if !url.starts_with("https://") {
-            format!("https://api.github.com/{}", url)
-        } else {
-            url.to_string()
-        };
+#[derive(Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct App {
+    slug: String,
+}
+
+/// Credentials of a GitHub App installation. The app authenticates with JWTs signed with its
+/// private key, which are then exchanged for short-lived installation tokens.
+#[derive(Clone)]
+pub struct AppCredentials {
+    app_id: u64,
+    installation_id: u64,
+    key: Arc<signature::RSAKeyPair>,
+    token: Arc<Mutex<Option<InstallationToken>>>,
+}
 
-        utils::http::prepare_sync(method, &url)
-            .header(AUTHORIZATION, format!("token {}", self.token))
+impl AppCredentials {
+    pub fn load(app_id: u64, key_path: &Path, installation_id: u64) -> Fallible<Self> {
+        let pem = ::std::fs::read_to_string(key_path)?;
+        let der = pem_to_der(&pem)?;
+        let key = signature::RSAKeyPair::from_der(untrusted::Input::from(&der))
+            .or_else(|_| signature::RSAKeyPair::from_pkcs8(untrusted::Input::from(&der)))
+            .map_err(|_| err_msg("invalid GitHub App private key"))?;
+
+        Ok(AppCredentials {
+            app_id,
+            installation_id,
+            key: Arc::new(key),
+            token: Arc::new(Mutex::new(None)),
+        })
     }
 
-    pub fn username(&self) -> Fallible<String> {
-        let response: User = self.build_request(Method::GET, "user").send()?.json()?;
-        Ok(response.login)
+    /// Generate a JWT authenticating the app itself.
+    fn jwt(&self, now: DateTime<Utc>) -> Fallible<String> {
+        let message = jwt_message(self.app_id, now);
+
+        let mut state = signature::RSASigningState::new(self.key.clone())
+            .map_err(|_| err_msg("failed to prepare the JWT signature"))?;
+        let mut signature = vec![0; self.key.public_modulus_len()];
+        state
+            .sign(
+                &signature::RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| err_msg("failed to sign the JWT"))?;
+
+        Ok(format!(
+            "{}.{}",
+            message,
+            base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)
+        ))
     }
 
-    pub fn post_comment(&self, issue_url: &str, body: &str) -> Fallible<()> {
-        let mut response = self
-            .build_request(Method::POST, &format!("{}/comments", issue_url))
-            .json(&json!({
-                "body": body,
-            }))
+    /// Return the current installation token, requesting a new one if it's about to expire.
+    fn installation_token(&self, api_url: &str) -> Fallible<String> {
+        let now = Utc::now();
+        let mut cached = self.token.lock().unwrap();
+        if let Some(ref token) = *cached {
+            if token.expires_at.timestamp() - now.timestamp() > INSTALLATION_TOKEN_MARGIN_SECS {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let url = endpoint_url(
+            api_url,
+            &format!("app/installations/{}/access_tokens", self.installation_id),
+        );
+        let mut response = utils::http::prepare_sync(Method::POST, &url)
+            .header(AUTHORIZATION, format!("Bearer {}", self.jwt(now)?))
+            .header(ACCEPT, APPS_PREVIEW)
             .send()?;
+        if response.status() != StatusCode::CREATED {
+            return Err(request_failed(response));
+        }
 
-        if response.status() == StatusCode::CREATED {
-            Ok(())
-        } else {
-            let error: Error = response.json()?;
-            Err(GitHubError::RequestFailed(response.status(), error.message).into())
+        let token: InstallationToken = response.json()?;
+        info!(
+            "generated a new GitHub App installation token, expiring at {}",
+            token.expires_at
+        );
+        let result = token.token.clone();
+        *cached = Some(token);
+        Ok(result)
+    }
+
+    /// Name of the bot user the app acts as.
+    fn username(&self, api_url: &str) -> Fallible<String> {
+        let url = endpoint_url(api_url, "app");
+        let mut response = utils::http::prepare_sync(Method::GET, &url)
+            .header(AUTHORIZATION, format!("Bearer {}", self.jwt(Utc::now())?))
+            .header(ACCEPT, APPS_PREVIEW)
+            .send()?;
+        if response.status() != StatusCode::OK {
+            return Err(request_failed(response));
         }
+
+        let app: App = response.json()?;
+        Ok(format!("{}[bot]", app.slug))
+    }
+}
+
+/// The signed part of a JWT authenticating the app, with its header and claims.
+fn jwt_message(app_id: u64, now: DateTime<Utc>) -> String {
+    let encode = |value: serde_json::Value| {
+        base64::encode_config(value.to_string().as_bytes(), base64::URL_SAFE_NO_PAD)
+    };
+
+    format!(
+        "{}.{}",
+        encode(json!({"alg": "RS256", "typ": "JWT"})),
+        encode(json!({
+            // Allow for some clock drift between crater and GitHub
+            "iat": now.timestamp() - 60,
+            "exp": now.timestamp() + JWT_VALIDITY_SECS,
+            "iss": app_id,
+        })),
+    )
+}
+
+fn pem_to_der(pem: &str) -> Fallible<Vec<u8>> {
+    let encoded = pem
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect::<String>();
+    Ok(base64::decode(&encoded)?)
+}
+
+#[derive(Clone)]
+pub struct GitHubApi {
+    api_url: String,
+    auth: GitHubAuth,
+    breaker: CircuitBreaker,
+}
+
+impl GitHubApi {
+    pub fn new(config: &GitHubConfig, tokens: &Tokens) -> Fallible<Self> {
+        Ok(GitHubApi {
+            api_url: config.api_url.clone(),
+            auth: GitHubAuth::from_tokens(&tokens.bot)?,
+            breaker: CircuitBreaker::new("the GitHub API"),
+        })
+    }
+
+    /// Whether the URL points to the configured GitHub API, and can be called with its
+    /// credentials.
+    pub fn is_api_url(&self, url: &str) -> bool {
+        url.starts_with(&format!("{}/", self.api_url.trim_end_matches('/')))
+    }
+
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    fn call<T, F: FnOnce() -> Fallible<T>>(&self, f: F) -> Fallible<T> {
+        self.breaker.call(is_outage, f)
+    }
+
+    fn build_request(&self, method: Method, url: &str) -> Fallible<RequestBuilder> {
+        let url = endpoint_url(&self.api_url, url);
+
+        let token = match self.auth {
+            GitHubAuth::PersonalAccessToken(ref token) => token.clone(),
+            GitHubAuth::App(ref app) => app.installation_token(&self.api_url)?,
+        };
+        Ok(utils::http::prepare_sync(method, &url)
+            .header(AUTHORIZATION, format!("token {}", token)))
+    }
+
+    pub fn username(&self) -> Fallible<String> {
+        self.call(|| match self.auth {
+            GitHubAuth::PersonalAccessToken(_) => {
+                let response: User = self.build_request(Method::GET, "user")?.send()?.json()?;
+                Ok(response.login)
+            }
+            GitHubAuth::App(ref app) => app.username(&self.api_url),
+        })
+    }
+
+    /// Post a comment on the issue, returning the API URL of the new comment.
+    pub fn post_comment(&self, issue_url: &str, body: &str) -> Fallible<String> {
+        self.call(|| {
+            let mut response = self
+                .build_request(Method::POST, &format!("{}/comments", issue_url))?
+                .json(&json!({
+                    "body": body,
+                }))
+                .send()?;
+
+            if response.status() == StatusCode::CREATED {
+                let comment: PostedComment = response.json()?;
+                Ok(comment.url)
+            } else {
+                Err(request_failed(response))
+            }
+        })
+    }
+
+    pub fn edit_comment(&self, comment_url: &str, body: &str) -> Fallible<()> {
+        self.call(|| {
+            let response = self
+                .build_request(Method::PATCH, comment_url)?
+                .json(&json!({
+                    "body": body,
+                }))
+                .send()?;
+
+            if response.status() == StatusCode::OK {
+                Ok(())
+            } else {
+                Err(request_failed(response))
+            }
+        })
     }
 
     pub fn list_labels(&self, issue_url: &str) -> Fallible<Vec<Label>> {
-        let mut response = self
-            .build_request(Method::GET, &format!("{}/labels", issue_url))
-            .send()?;
+        self.call(|| {
+            let mut response = self
+                .build_request(Method::GET, &format!("{}/labels", issue_url))?
+                .send()?;
 
-        if response.status() == StatusCode::OK {
-            Ok(response.json()?)
-        } else {
-            let error: Error = response.json()?;
-            Err(GitHubError::RequestFailed(response.status(), error.message).into())
-        }
+            if response.status() == StatusCode::OK {
+                Ok(response.json()?)
+            } else {
+                Err(request_failed(response))
+            }
+        })
     }
 
     pub fn add_label(&self, issue_url: &str, label: &str) -> Fallible<()> {
-        let mut response = self
-            .build_request(Method::POST, &format!("{}/labels", issue_url))
-            .json(&json!([label]))
-            .send()?;
+        self.call(|| {
+            let response = self
+                .build_request(Method::POST, &format!("{}/labels", issue_url))?
+                .json(&json!([label]))
+                .send()?;
 
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            let error: Error = response.json()?;
-            Err(GitHubError::RequestFailed(response.status(), error.message).into())
-        }
+            if response.status() == StatusCode::OK {
+                Ok(())
+            } else {
+                Err(request_failed(response))
+            }
+        })
     }
 
     pub fn remove_label(&self, issue_url: &str, label: &str) -> Fallible<()> {
-        let mut response = self
-            .build_request(Method::DELETE, &format!("{}/labels/{}", issue_url, label))
-            .send()?;
+        self.call(|| {
+            let response = self
+                .build_request(Method::DELETE, &format!("{}/labels/{}", issue_url, label))?
+                .send()?;
 
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            let error: Error = response.json()?;
-            Err(GitHubError::RequestFailed(response.status(), error.message).into())
-        }
+            if response.status() == StatusCode::OK {
+                Ok(())
+            } else {
+                Err(request_failed(response))
+            }
+        })
     }
 
     pub fn list_teams(&self, org: &str) -> Fallible<HashMap<String, usize>> {
-        let mut response = self
-            .build_request(Method::GET, &format!("orgs/{}/teams", org))
-            .send()?;
+        self.call(|| {
+            let mut response = self
+                .build_request(Method::GET, &format!("orgs/{}/teams", org))?
+                .send()?;
 
-        if response.status() == StatusCode::OK {
-            let teams: Vec<Team> = response.json()?;
-            Ok(teams.into_iter().map(|t| (t.slug, t.id)).collect())
-        } else {
-            let error: Error = response.json()?;
-            Err(GitHubError::RequestFailed(response.status(), error.message).into())
-        }
+            if response.status() == StatusCode::OK {
+                let teams: Vec<Team> = response.json()?;
+                Ok(teams.into_iter().map(|t| (t.slug, t.id)).collect())
+            } else {
+                Err(request_failed(response))
+            }
+        })
     }
 
     pub fn team_members(&self, team: usize) -> Fallible<Vec<String>> {
-        let mut response = self
-            .build_request(Method::GET, &format!("teams/{}/members", team))
-            .send()?;
+        self.call(|| {
+            let mut response = self
+                .build_request(Method::GET, &format!("teams/{}/members", team))?
+                .send()?;
 
-        if response.status() == StatusCode::OK {
-            let users: Vec<User> = response.json()?;
-            Ok(users.into_iter().map(|u| u.login).collect())
-        } else {
-            let error: Error = response.json()?;
-            Err(GitHubError::RequestFailed(response.status(), error.message).into())
-        }
+            if response.status() == StatusCode::OK {
+                let users: Vec<User> = response.json()?;
+                Ok(users.into_iter().map(|u| u.login).collect())
+            } else {
+                Err(request_failed(response))
+            }
+        })
     }
 }
 
@@ -137,6 +456,11 @@ pub struct User {
     pub login: String,
 }
 
+#[derive(Deserialize)]
+struct PostedComment {
+    url: String,
+}
+
 #[derive(Deserialize)]
 pub struct EventIssueComment {
     pub action: String,
@@ -174,3 +498,114 @@ pub struct Team {
     pub id: usize,
     pub slug: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{endpoint_url, jwt_message, rate_limit_reset, GitHubApi, JWT_VALIDITY_SECS};
+    use crate::config::GitHubConfig;
+    use crate::server::tokens::Tokens;
+    use chrono::{TimeZone, Utc};
+    use http::header::{HeaderMap, HeaderValue};
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limit_reset() {
+        let now = Utc.timestamp(1_500_000_000, 0);
+        let headers = |list: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for &(name, value) in list {
+                headers.insert(name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+
+        assert_eq!(rate_limit_reset(&headers(&[]), now), None);
+        assert_eq!(
+            rate_limit_reset(&headers(&[("x-ratelimit-reset", "1500000042")]), now),
+            Some(Duration::from_secs(42))
+        );
+        // Retry-After takes precedence over the reset time
+        assert_eq!(
+            rate_limit_reset(
+                &headers(&[("retry-after", "10"), ("x-ratelimit-reset", "1500000042")]),
+                now
+            ),
+            Some(Duration::from_secs(10))
+        );
+        // Don't wait if the limit already reset
+        assert_eq!(
+            rate_limit_reset(&headers(&[("x-ratelimit-reset", "1400000000")]), now),
+            Some(Duration::from_secs(0))
+        );
+    }
+
+    #[test]
+    fn test_jwt_message() {
+        let now = Utc.timestamp(1_500_000_000, 0);
+        let message = jwt_message(42, now);
+
+        let parts = message
+            .split('.')
+            .map(|part| {
+                let decoded = base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap();
+                serde_json::from_slice::<serde_json::Value>(&decoded).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0]["alg"], "RS256");
+        assert_eq!(parts[1]["iss"], 42);
+        assert_eq!(parts[1]["iat"], 1_500_000_000 - 60);
+        assert_eq!(parts[1]["exp"], 1_500_000_000 + JWT_VALIDITY_SECS);
+    }
+
+    #[test]
+    fn test_endpoint_url() {
+        let github = "https://api.github.com";
+        assert_eq!(
+            endpoint_url(github, "orgs/rust-lang/teams"),
+            "https://api.github.com/orgs/rust-lang/teams"
+        );
+        assert_eq!(endpoint_url(github, "app"), "https://api.github.com/app");
+
+        // The API of GitHub Enterprise is under a prefix, which must be kept
+        for enterprise in &[
+            "https://github.example.com/api/v3",
+            "https://github.example.com/api/v3/",
+        ] {
+            assert_eq!(
+                endpoint_url(enterprise, "teams/42/members"),
+                "https://github.example.com/api/v3/teams/42/members"
+            );
+            assert_eq!(
+                endpoint_url(enterprise, "app/installations/1/access_tokens"),
+                "https://github.example.com/api/v3/app/installations/1/access_tokens"
+            );
+        }
+
+        // The URLs of the issues and comments are already absolute
+        let issue = "https://github.example.com/api/v3/repos/rust-lang/rust/issues/1";
+        assert_eq!(
+            endpoint_url("https://github.example.com/api/v3", issue),
+            issue
+        );
+    }
+
+    #[test]
+    fn test_is_api_url() {
+        let mut tokens = Tokens::default();
+        tokens.bot.api_token = "github-token".into();
+
+        let github = GitHubApi::new(&GitHubConfig::default(), &tokens).unwrap();
+        assert!(github.is_api_url("https://api.github.com/repos/rust-lang/rust/issues/1"));
+        assert!(!github.is_api_url("https://api.github.com.example.com/repos/a/b/issues/1"));
+
+        let config = GitHubConfig {
+            api_url: "https://github.example.com/api/v3".into(),
+            ..GitHubConfig::default()
+        };
+        let github = GitHubApi::new(&config, &tokens).unwrap();
+        assert!(github.is_api_url("https://github.example.com/api/v3/repos/a/b/issues/1"));
+        assert!(!github.is_api_url("https://api.github.com/repos/rust-lang/rust/issues/1"));
+        assert!(!github.is_api_url("https://github.example.com/repos/a/b/issues/1"));
+    }
+}
@@ -1,9 +1,191 @@
+use crate::db::QueryUtils;
 use crate::prelude::*;
+use crate::server::github;
 use crate::server::Data;
+use chrono::Utc;
+use std::thread;
+use std::time::Duration;
 
-pub enum Label {
-    ExperimentQueued,
-    ExperimentCompleted,
+/// Attempts made for each call to the GitHub API before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How often the messages queued while GitHub was failing are sent again.
+const QUEUE_DELIVERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Retry the call while GitHub is failing or rate limiting crater, waiting between attempts with
+/// `sleep`, never longer than `max_delay`. Returns `None` if the call still fails after all the
+/// attempts.
+fn retry<T, F, S>(max_delay: Duration, mut call: F, mut sleep: S) -> Fallible<Option<T>>
+where
+    F: FnMut() -> Fallible<T>,
+    S: FnMut(Duration),
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let err = match call() {
+            Ok(result) => return Ok(Some(result)),
+            Err(err) => err,
+        };
+        // The rate limit can take up to an hour to reset, and the thread can't wait that long
+        let delay = match github::retry_delay(&err, backoff) {
+            Some(delay) => delay.min(max_delay),
+            None => return Err(err),
+        };
+
+        if attempt >= MAX_ATTEMPTS {
+            warn!(
+                "the GitHub API call still failed after {} attempts: {}",
+                attempt, err
+            );
+            return Ok(None);
+        }
+        warn!(
+            "the GitHub API call failed, retrying in {} seconds: {}",
+            delay.as_secs(),
+            err
+        );
+        sleep(delay);
+
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+string_enum!(pub enum Label {
+    ExperimentQueued => "experiment-queued",
+    ExperimentCompleted => "experiment-completed",
+});
+
+/// Save the message to send it once GitHub works again, instead of waiting for it.
+fn queue(data: &Data, issue_url: &str, message: &str, label: Option<Label>) -> Fallible<()> {
+    warn!("GitHub is failing, queueing the message to {}", issue_url);
+    data.db.execute(
+        "INSERT INTO queued_messages (issue_url, message, label, queued_at) \
+         VALUES (?1, ?2, ?3, ?4);",
+        &[
+            &issue_url,
+            &message,
+            &label.map(|label| label.to_str()),
+            &Utc::now(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Post the comment and apply the label. `None` is returned if GitHub kept failing and the
+/// comment was not posted, while the label is not critical and is skipped in that case.
+fn deliver(
+    data: &Data,
+    issue_url: &str,
+    message: &str,
+    label: Option<Label>,
+) -> Fallible<Option<String>> {
+    let max_delay = Duration::from_secs(data.config.github.max_retry_delay);
+    let comment_url = match retry(
+        max_delay,
+        || data.github.post_comment(issue_url, message),
+        thread::sleep,
+    )? {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+
+    if let Some(label) = label {
+        let label = match label {
+            Label::ExperimentQueued => &data.config.server.labels.experiment_queued,
+            Label::ExperimentCompleted => &data.config.server.labels.experiment_completed,
+        };
+
+        // The comment is already posted, so it must not be queued again if GitHub fails now
+        macro_rules! retry {
+            ($call:expr) => {
+                match retry(max_delay, || $call, thread::sleep) {
+                    Ok(Some(result)) => result,
+                    Ok(None) => return Ok(Some(comment_url)),
+                    Err(ref err) if github::is_outage(err) => return Ok(Some(comment_url)),
+                    Err(err) => return Err(err),
+                }
+            };
+        }
+
+        // Remove all the labels matching the provided regex
+        // If the label is already present don't reapply it though
+        let regex = &data.config.server.labels.remove;
+        let current_labels = retry!(data.github.list_labels(issue_url));
+        let mut label_already_present = false;
+        for current_label in &current_labels {
+            if current_label.name == *label {
+                label_already_present = true;
+            } else if regex.is_match(&current_label.name) {
+                retry!(data.github.remove_label(issue_url, &current_label.name));
+            }
+        }
+
+        if !label_already_present {
+            retry!(data.github.add_label(issue_url, label));
+        }
+    }
+
+    Ok(Some(comment_url))
+}
+
+/// Send the messages queued while GitHub was failing, oldest first, returning how many were
+/// delivered. Delivery stops at the first message GitHub fails to accept, so the order of the
+/// messages is kept.
+pub(super) fn deliver_queued(data: &Data) -> Fallible<usize> {
+    let queued = data.db.query(
+        "SELECT rowid, issue_url, message, label FROM queued_messages ORDER BY rowid;",
+        &[],
+        |row| {
+            let rowid: i64 = row.get("rowid");
+            let issue_url: String = row.get("issue_url");
+            let message: String = row.get("message");
+            let label: Option<String> = row.get("label");
+            (rowid, issue_url, message, label)
+        },
+    )?;
+
+    let mut delivered = 0;
+    for (rowid, issue_url, message, label) in queued {
+        if data.github.circuit_breaker().ensure_closed().is_err() {
+            break;
+        }
+        let label = match label {
+            Some(label) => Some(label.parse()?),
+            None => None,
+        };
+
+        match deliver(data, &issue_url, &message, label) {
+            Ok(Some(_)) => delivered += 1,
+            Ok(None) => break,
+            Err(ref err) if github::is_outage(err) => break,
+            // Retrying won't help, for example if the issue was deleted
+            Err(err) => {
+                error!("dropping the queued message to {}", issue_url);
+                crate::utils::report_failure(&err);
+            }
+        }
+        data.db
+            .execute("DELETE FROM queued_messages WHERE rowid = ?1;", &[&rowid])?;
+    }
+    Ok(delivered)
+}
+
+/// Periodically send the messages queued while GitHub was failing.
+pub(super) fn spawn_delivery(data: Data) {
+    thread::spawn(move || loop {
+        match deliver_queued(&data) {
+            Ok(0) => {}
+            Ok(delivered) => info!("delivered {} queued GitHub messages", delivered),
+            Err(err) => {
+                error!("failed to deliver the queued GitHub messages");
+                crate::utils::report_failure(&err);
+            }
+        }
+        thread::sleep(QUEUE_DELIVERY_INTERVAL);
+    });
 }
 
 struct Line {
@@ -13,6 +195,8 @@ struct Line {
 
 pub struct Message {
     lines: Vec<Line>,
+    /// Markdown blocks shown between the lines and the notes.
+    sections: Vec<String>,
     notes: Vec<Line>,
     new_label: Option<Label>,
 }
@@ -21,6 +205,7 @@ impl Message {
     pub fn new() -> Message {
         Message {
             lines: Vec::new(),
+            sections: Vec::new(),
             notes: Vec::new(),
             new_label: None,
         }
@@ -34,6 +219,16 @@ impl Message {
         self
     }
 
+    /// Add a block of Markdown to the message, like the list of regressions of a report. Empty
+    /// blocks are ignored.
+    pub fn section<S: Into<String>>(mut self, content: S) -> Self {
+        let content = content.into();
+        if !content.trim().is_empty() {
+            self.sections.push(content);
+        }
+        self
+    }
+
     pub fn note<S1: Into<String>, S2: Into<String>>(mut self, emoji: S1, content: S2) -> Self {
         self.notes.push(Line {
             emoji: emoji.into(),
@@ -47,7 +242,58 @@ impl Message {
         self
     }
 
-    pub fn send(mut self, issue_url: &str, data: &Data) -> Fallible<()> {
+    pub fn send(self, issue_url: &str, data: &Data) -> Fallible<()> {
+        self.post(issue_url, data).map(|_| ())
+    }
+
+    /// Send the message, returning the API URL of the comment so it can be edited later. `None`
+    /// is returned if GitHub is failing: the message is then queued and sent in the background
+    /// once GitHub works again, so the callers never wait on it.
+    pub fn post(self, issue_url: &str, data: &Data) -> Fallible<Option<String>> {
+        let (message, new_label) = self.render();
+
+        // Messages sent while others are still queued would be posted before them
+        let pending = data.db.exists(
+            "SELECT rowid FROM queued_messages WHERE issue_url = ?1;",
+            &[&issue_url],
+        )?;
+        if pending || data.github.circuit_breaker().ensure_closed().is_err() {
+            queue(data, issue_url, &message, new_label)?;
+            return Ok(None);
+        }
+
+        match deliver(data, issue_url, &message, new_label) {
+            Ok(Some(comment_url)) => Ok(Some(comment_url)),
+            Ok(None) => {
+                queue(data, issue_url, &message, new_label)?;
+                Ok(None)
+            }
+            Err(ref err) if github::is_outage(err) => {
+                queue(data, issue_url, &message, new_label)?;
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replace the content of a comment posted before. Labels are not changed.
+    pub fn edit(self, comment_url: &str, data: &Data) -> Fallible<()> {
+        data.github.circuit_breaker().ensure_closed()?;
+
+        let (message, _) = self.render();
+        if retry(
+            Duration::from_secs(data.config.github.max_retry_delay),
+            || data.github.edit_comment(comment_url, &message),
+            thread::sleep,
+        )?
+        .is_none()
+        {
+            warn!("failed to edit the comment {}", comment_url);
+        }
+        Ok(())
+    }
+
+    fn render(mut self) -> (String, Option<Label>) {
         // Always add a note at the bottom explaining what this is
         self = self.note(
             "information_source",
@@ -62,36 +308,102 @@ impl Message {
         for line in self.lines {
             message.push_str(&format!(":{}: {}\n", line.emoji, line.content));
         }
+        for section in self.sections {
+            message.push_str(&format!("\n{}\n", section.trim()));
+        }
         for line in self.notes {
             message.push_str(&format!("\n:{}: {}", line.emoji, line.content));
         }
 
-        data.github.post_comment(issue_url, &message)?;
+        (message, self.new_label)
+    }
+}
 
-        if let Some(label) = self.new_label {
-            let label = match label {
-                Label::ExperimentQueued => &data.config.server.labels.experiment_queued,
-                Label::ExperimentCompleted => &data.config.server.labels.experiment_completed,
-            };
+#[cfg(test)]
+mod tests {
+    use super::{retry, MAX_ATTEMPTS};
+    use crate::prelude::*;
+    use crate::server::github::GitHubError;
+    use http::StatusCode;
+    use std::time::Duration;
 
-            // Remove all the labels matching the provided regex
-            // If the label is already present don't reapply it though
-            let regex = &data.config.server.labels.remove;
-            let current_labels = data.github.list_labels(issue_url)?;
-            let mut label_already_present = false;
-            for current_label in &current_labels {
-                if current_label.name == *label {
-                    label_already_present = true;
-                } else if regex.is_match(&current_label.name) {
-                    data.github.remove_label(issue_url, &current_label.name)?;
-                }
+    const MAX_DELAY: Duration = Duration::from_secs(300);
+
+    fn failing(status: StatusCode) -> impl FnMut() -> Fallible<()> {
+        move || Err(GitHubError::RequestFailed(status, "failed".into()).into())
+    }
+
+    #[test]
+    fn test_retry() {
+        let secs = |list: &[u64]| {
+            list.iter()
+                .map(|&s| Duration::from_secs(s))
+                .collect::<Vec<_>>()
+        };
+
+        // Server errors are retried with exponential backoff, and ignored if they persist
+        let mut sleeps = Vec::new();
+        let mut calls = 0;
+        let mut call = failing(StatusCode::BAD_GATEWAY);
+        let result = retry(
+            MAX_DELAY,
+            || {
+                calls += 1;
+                call()
+            },
+            |delay| sleeps.push(delay),
+        );
+        assert!(result.unwrap().is_none());
+        assert_eq!(calls, MAX_ATTEMPTS);
+        assert_eq!(sleeps, secs(&[1, 2, 4, 8]));
+
+        // Rate limited calls wait until the limit resets
+        let mut sleeps = Vec::new();
+        let mut responses = vec![
+            Ok(42),
+            Err(GitHubError::RateLimited {
+                message: "slow down".into(),
+                retry_after: None,
             }
+            .into()),
+            Err(GitHubError::RateLimited {
+                message: "slow down".into(),
+                retry_after: Some(Duration::from_secs(30)),
+            }
+            .into()),
+        ];
+        let result = retry(
+            MAX_DELAY,
+            || responses.pop().unwrap(),
+            |delay| sleeps.push(delay),
+        );
+        assert_eq!(result.unwrap(), Some(42));
+        assert_eq!(sleeps, secs(&[30, 2]));
 
-            if !label_already_present {
-                data.github.add_label(issue_url, label)?;
+        // But never longer than the configured maximum
+        let mut sleeps = Vec::new();
+        let mut responses = vec![
+            Ok(42),
+            Err(GitHubError::RateLimited {
+                message: "slow down".into(),
+                retry_after: Some(Duration::from_secs(3600)),
             }
-        }
+            .into()),
+        ];
+        let result = retry(
+            MAX_DELAY,
+            || responses.pop().unwrap(),
+            |delay| sleeps.push(delay),
+        );
+        assert_eq!(result.unwrap(), Some(42));
+        assert_eq!(sleeps, vec![MAX_DELAY]);
 
-        Ok(())
+        // Other errors are not retried
+        let mut sleeps = Vec::new();
+        let result = retry(MAX_DELAY, failing(StatusCode::NOT_FOUND), |delay| {
+            sleeps.push(delay)
+        });
+        assert!(result.is_err());
+        assert!(sleeps.is_empty());
     }
 }
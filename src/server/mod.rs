@@ -1,21 +1,46 @@
+#[macro_use]
+mod timeout;
+
+mod agent_tokens;
 pub mod agents;
 pub mod api_types;
 mod auth;
+mod backups;
+mod body;
+mod circuit_breaker;
+mod cors;
+pub mod email;
+mod events;
 mod github;
 mod messages;
+mod openapi;
+mod private_reports;
+mod read_only_tokens;
 mod reports;
 mod routes;
+mod running_comments;
+#[cfg(test)]
+pub(crate) mod testing;
+mod throughput;
 pub mod tokens;
 
 use crate::config::Config;
 use crate::db::Database;
 use crate::prelude::*;
+use crate::server::agent_tokens::AgentTokens;
 use crate::server::agents::Agents;
 use crate::server::auth::ACL;
 use crate::server::github::GitHubApi;
+use crate::server::read_only_tokens::ReadOnlyTokens;
 use crate::server::tokens::Tokens;
-use http::{self, header::HeaderValue, Response};
+use futures_cpupool::CpuPool;
+use http::{
+    self,
+    header::{HeaderMap, HeaderValue},
+    Response,
+};
 use hyper::Body;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use warp::{self, Filter};
 
@@ -30,6 +55,12 @@ pub enum HttpError {
     NotFound,
     #[fail(display = "forbidden")]
     Forbidden,
+    #[fail(display = "the request body exceeds the size limit of this endpoint")]
+    PayloadTooLarge,
+    #[fail(display = "request timed out")]
+    RequestTimeout,
+    #[fail(display = "invalid request")]
+    BadRequest,
 }
 
 #[derive(Clone)]
@@ -38,57 +69,247 @@ pub struct Data {
     pub config: Config,
     pub github: GitHubApi,
     pub tokens: Tokens,
+    pub agent_tokens: AgentTokens,
+    pub read_only_tokens: ReadOnlyTokens,
     pub agents: Agents,
     pub db: Database,
     pub reports_worker: reports::ReportsWorker,
+    /// Whether the GitHub credentials were checked to work when the server started.
+    pub github_validated: bool,
+    pub progress_events: events::ProgressEvents,
+    pub report_events: events::ReportEvents,
+    pub throughput: throughput::Throughput,
     pub acl: ACL,
+    /// Threads running the handlers of the API endpoints, so they can be timed out.
+    pub handlers: CpuPool,
+    /// Number of logs archives being streamed by the web UI.
+    pub archive_streams: Arc<AtomicUsize>,
+}
+
+impl Data {
+    fn new(
+        config: Config,
+        db: Database,
+        tokens: Tokens,
+        github: GitHubApi,
+        bot_username: String,
+    ) -> Fallible<Self> {
+        let agents = Agents::new(db.clone(), &tokens)?;
+        let agent_tokens = AgentTokens::new(db.clone())?;
+        let read_only_tokens = ReadOnlyTokens::new(db.clone())?;
+        let acl = ACL::new(&config, &github)?;
+
+        Ok(Data {
+            bot_username,
+            config,
+            github,
+            tokens,
+            agent_tokens,
+            read_only_tokens,
+            agents,
+            db,
+            reports_worker: reports::ReportsWorker::new(),
+            github_validated: false,
+            progress_events: events::ProgressEvents::new(),
+            report_events: events::ReportEvents::new(),
+            throughput: throughput::Throughput::new(),
+            acl,
+            handlers: CpuPool::new(timeout::HANDLER_THREADS),
+            archive_streams: Arc::new(AtomicUsize::new(0)),
+        })
+    }
 }
 
 pub fn run(config: Config) -> Fallible<()> {
-    let db = Database::open()?;
+    let db = Database::open(&config)?;
     let tokens = tokens::Tokens::load()?;
-    let github = GitHubApi::new(&tokens);
-    let agents = Agents::new(db.clone(), &tokens)?;
+    let github = GitHubApi::new(&config.github, &tokens)?;
     let bot_username = github.username()?;
-    let acl = ACL::new(&config, &github)?;
 
     info!("bot username: {}", bot_username);
 
-    let data = Data {
-        bot_username,
-        config,
-        github,
-        tokens,
-        agents,
-        db: db.clone(),
-        reports_worker: reports::ReportsWorker::new(),
-        acl,
-    };
+    let mut data = Data::new(config, db.clone(), tokens, github, bot_username)?;
+    // Fetching the username of the bot fails if the credentials are wrong
+    data.github_validated = true;
 
     data.reports_worker.spawn(data.clone());
+    messages::spawn_delivery(data.clone());
+    if let Some(ref backups) = data.config.database.backups {
+        if data.config.database.postgres_url.is_some() {
+            bail!("scheduled backups are only supported with the SQLite database");
+        }
+        backups::spawn(db.clone(), backups)?;
+    }
 
     info!("running server...");
 
-    let data = Arc::new(data);
+    warp::serve(routes(Arc::new(data))).run(([127, 0, 0, 1], 8000));
+
+    Ok(())
+}
+
+/// Prefix of the current version of the agent API.
+fn agent_api() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::path("agent-api").and(warp::path("v1"))
+}
+
+/// Prefix of the current version of the public API.
+fn public_api() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::path("api").and(warp::path("v1"))
+}
 
+fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    // Websockets are upgraded before reaching the other routes, which always return a response:
+    // the route checks the origin and returns the errors itself, as the upgrade isn't a response
+    // the CORS headers can be added to
+    let progress = public_api()
+        .and(routes::progress::routes(data.clone()))
+        .with(warp::reply::with::header(
+            http::header::SERVER,
+            SERVER_HEADER.as_str(),
+        ));
+
+    let data_cloned = data.clone();
     let routes = warp::any()
         .and(
-            warp::any()
-                .and(warp::path("webhooks").and(routes::webhooks::routes(data.clone())))
-                .or(warp::path("agent-api").and(routes::agent::routes(data.clone())))
+            cors::preflight(data.clone())
+                .or(warp::path("webhooks").and(routes::webhooks::routes(data.clone())))
+                .unify()
+                .or(agent_api().and(routes::agent::routes(data.clone())))
+                .unify()
+                .or(warp::path("agent-api").and(routes::agent::legacy_redirects()))
+                .unify()
+                .or(warp::path("admin-api").and(routes::admin::routes(data.clone())))
+                .unify()
+                .or(public_api().and(routes::api::routes(data.clone())))
+                .unify()
+                .or(warp::path("health").and(routes::health::routes(data.clone())))
+                .unify()
+                .or(routes::health::probes(data.clone()))
+                .unify()
+                .or(warp::path("private-reports")
+                    .and(routes::private_reports::routes(data.clone())))
                 .unify()
                 .or(routes::ui::routes(data.clone()))
                 .unify(),
         )
-        .map(|mut resp: Response<Body>| {
+        .and(warp::header::headers_cloned())
+        .map(move |mut resp: Response<Body>, headers: HeaderMap| {
             resp.headers_mut().insert(
                 http::header::SERVER,
                 HeaderValue::from_static(&SERVER_HEADER),
             );
+            cors::add_headers(&data_cloned, &headers, &mut resp);
             resp
         });
 
-    warp::serve(routes).run(([127, 0, 0, 1], 8000));
+    progress.or(routes)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::{agent_api, public_api, routes, Data};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::server::github::GitHubApi;
+    use crate::server::tokens::Tokens;
+    use http::StatusCode;
+    use std::sync::Arc;
+    use warp::{self, Filter};
+
+    /// Whether the filter routes the request, even if it then rejects it (for example because
+    /// it lacks a token or a body): warp only rejects the requests no route matches with a
+    /// `404 Not Found` or a `405 Method Not Allowed`.
+    macro_rules! routed {
+        ($filter:expr, $method:expr, $path:expr) => {
+            match warp::test::request()
+                .method($method)
+                .path($path)
+                // Websockets are only routed when asked to upgrade the connection
+                .header("connection", "upgrade")
+                .header("upgrade", "websocket")
+                .header("sec-websocket-version", "13")
+                .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .filter($filter)
+            {
+                Ok(_) => true,
+                Err(rejection) => match rejection.status() {
+                    StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => false,
+                    _ => true,
+                },
+            }
+        };
+    }
+
+    fn data() -> Data {
+        let config = Config::default();
+        let mut tokens = Tokens::default();
+        // The GitHub API is never called, but the client needs a token
+        tokens.bot.api_token = "github-token".into();
+        let github = GitHubApi::new(&config.github, &tokens).unwrap();
+        let db = Database::temp().unwrap();
+        Data::new(config, db, tokens, github, "crater-bot".into()).unwrap()
+    }
+
+    /// Replace the parameters of a path of the OpenAPI description with sample values.
+    fn sample_path(path: &str) -> String {
+        path.split('/')
+            .map(|segment| match segment {
+                "{crate-id}" => "reg/lazy_static/1",
+                param if param.starts_with('{') => "sample",
+                segment => segment,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    #[test]
+    fn test_openapi_spec_routes() {
+        let data = Arc::new(data());
+
+        // The route groups are mounted like in `routes`, but without recovering their rejections
+        // into error responses, as those can't be told apart from the ones of the handlers. The
+        // web UI and the redirects of the old agent paths are not described.
+        let progress = public_api().and(routes::progress::routes(data.clone()));
+        let api = public_api().and(routes::api::endpoints(data.clone()));
+        let agent = agent_api().and(routes::agent::endpoints(data.clone()));
+        let admin = warp::path("admin-api").and(routes::admin::endpoints(data.clone()));
+        let health = warp::path("health").and(routes::health::routes(data.clone()));
+        let probes = routes::health::probes(data.clone());
+        let webhooks = warp::path("webhooks").and(routes::webhooks::routes(data.clone()));
+        let is_routed = |method: &str, path: &str| {
+            routed!(&progress, method, path)
+                || routed!(&api, method, path)
+                || routed!(&agent, method, path)
+                || routed!(&admin, method, path)
+                || routed!(&health, method, path)
+                || routed!(&probes, method, path)
+                || routed!(&webhooks, method, path)
+        };
+
+        // The check notices the paths and the methods the server doesn't route
+        assert!(!is_routed("GET", "/api/v1/undocumented"));
+        assert!(!is_routed("DELETE", "/api/v1/experiments"));
+
+        let spec: serde_json::Value = serde_json::from_str(&super::openapi::SPEC).unwrap();
+        let mut operations = 0;
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                if !["get", "put", "post", "delete", "patch"].contains(&method.as_str()) {
+                    continue;
+                }
+                operations += 1;
+                assert!(
+                    is_routed(&method.to_uppercase(), &sample_path(path)),
+                    "{} {} is documented but not routed",
+                    method,
+                    path
+                );
+            }
+        }
+        assert!(operations > 0);
+    }
 }
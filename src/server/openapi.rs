@@ -0,0 +1,96 @@
+use crate::server::api_types::{
+    BadRequest, InternalError, NotFound, PayloadTooLarge, RequestTimeout, Unauthorized,
+};
+use crate::server::routes::{admin, agent, api, health, progress, webhooks};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+lazy_static! {
+    /// The description only depends on the code, so it's serialized once.
+    pub(in crate::server) static ref SPEC: String = ApiDoc::openapi()
+        .to_json()
+        .expect("failed to serialize the OpenAPI description");
+}
+
+/// Tokens accepted by the endpoints, all sent in the `Authorization` header.
+static SECURITY_SCHEMES: &[(&str, &str)] = &[
+    (
+        "agentToken",
+        "A token from the `[agents]` section of `tokens.toml`, sent as `CraterToken {token}`.",
+    ),
+    (
+        "readOnlyToken",
+        "A token from the `[read-only]` or `[agents]` section of `tokens.toml`, sent as \
+         `CraterToken {token}`. Read-only tokens can only be used for GET and HEAD requests.",
+    ),
+    (
+        "adminToken",
+        "A token from the `[admins]` section of `tokens.toml`, sent as `CraterToken {token}`.",
+    ),
+];
+
+/// OpenAPI description of the HTTP API of the server, generated from the annotations of the
+/// handlers. Each group of routes describes its endpoints relative to the prefix it's mounted at.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Crater",
+        version = "1",
+        description = "HTTP API of the crater server. Every endpoint except the websocket, the \
+                       stream of report events and the webhooks replies with a JSON object, whose \
+                       `status` field is `success` when the request succeeds. See the docs \
+                       directory of the repository for more details.",
+        license(name = "MIT OR Apache-2.0")
+    ),
+    nest(
+        (path = "/api/v1", api = api::ApiDoc, tags = ["public"]),
+        (path = "/api/v1", api = progress::ApiDoc, tags = ["public"]),
+        (path = "/agent-api/v1", api = agent::ApiDoc, tags = ["agent"]),
+        (path = "/admin-api", api = admin::ApiDoc, tags = ["admin"]),
+        (path = "/webhooks", api = webhooks::ApiDoc, tags = ["webhooks"]),
+    ),
+    // The health checks don't share a prefix, so they're described with their full paths
+    paths(
+        health::endpoint_health,
+        health::endpoint_healthz,
+        health::endpoint_readyz
+    ),
+    components(responses(
+        BadRequest,
+        Unauthorized,
+        NotFound,
+        PayloadTooLarge,
+        RequestTimeout,
+        InternalError
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "public", description = "Public API, mounted at `/api/v1/`."),
+        (
+            name = "agent",
+            description = "API used by the agents, mounted at `/agent-api/v1/`. The unversioned \
+                           paths redirect to the first version."
+        ),
+        (name = "admin", description = "API for the administrators, mounted at `/admin-api/`."),
+        (name = "health", description = "Health checks."),
+        (name = "webhooks", description = "GitHub webhooks."),
+    )
+)]
+pub(in crate::server) struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for &(name, description) in SECURITY_SCHEMES {
+            components.add_security_scheme(
+                name,
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                    "Authorization",
+                    description,
+                ))),
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Reports of private experiments are uploaded without the public ACL, and served by the server
+//! itself to whoever holds a link signed with the key configured in `tokens.toml`. The links are
+//! only handed out to the tokens listed in the readers of the experiment, and expire after the
+//! duration configured in `[server.reports]`.
+
+use crate::config::{Config, ReportsDestination};
+use crate::prelude::*;
+use crate::server::reports;
+use crate::server::tokens::Tokens;
+use crate::utils::hex::{from_hex, to_hex};
+use chrono::{DateTime, Duration, Utc};
+use futures::{Future, Stream};
+use ring::{digest, hmac};
+use rusoto_s3::{GetObjectError, GetObjectRequest, S3};
+use std::fs;
+use std::io;
+
+/// Path of the private reports in the destination, separate from the public ones.
+pub fn prefix(name: &str) -> String {
+    format!("private/{}", name)
+}
+
+fn signing_key(tokens: &Tokens) -> Fallible<hmac::SigningKey> {
+    let key = tokens
+        .private_reports_key
+        .as_ref()
+        .ok_or_else(|| err_msg("no key to sign the links to private reports is configured"))?;
+    Ok(hmac::SigningKey::new(&digest::SHA256, key.as_bytes()))
+}
+
+fn message(name: &str, expires: i64) -> String {
+    format!("{}:{}", name, expires)
+}
+
+/// Link to the index of the private report, valid until `expires`.
+pub fn signed_path(tokens: &Tokens, name: &str, expires: DateTime<Utc>) -> Fallible<String> {
+    let expires = expires.timestamp();
+    let signature = hmac::sign(&signing_key(tokens)?, message(name, expires).as_bytes());
+    Ok(format!(
+        "/private-reports/{}/{}/{}/index.html",
+        name,
+        expires,
+        to_hex(signature.as_ref())
+    ))
+}
+
+/// Check the link was signed by this server for the experiment, and didn't expire yet.
+pub fn verify(
+    tokens: &Tokens,
+    name: &str,
+    expires: i64,
+    signature: &str,
+    now: DateTime<Utc>,
+) -> Fallible<bool> {
+    if expires <= now.timestamp() {
+        return Ok(false);
+    }
+    let signature = match from_hex(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(false),
+    };
+    Ok(hmac::verify_with_own_key(
+        &signing_key(tokens)?,
+        message(name, expires).as_bytes(),
+        &signature,
+    )
+    .is_ok())
+}
+
+/// Expiry of the links generated now.
+pub fn link_expiry(config: &Config, now: DateTime<Utc>) -> DateTime<Utc> {
+    now + Duration::hours(i64::from(config.server.reports.private_link_hours))
+}
+
+/// Load a file of the private report, or `None` if it doesn't exist.
+pub fn read(config: &Config, tokens: &Tokens, name: &str, path: &str) -> Fallible<Option<Vec<u8>>> {
+    // Paths escaping the report could read other files of the destination
+    if path
+        .split('/')
+        .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Ok(None);
+    }
+    let key = format!("{}/{}", prefix(name), path);
+
+    match config.server.reports.destination {
+        ReportsDestination::Directory { path: ref dir } => match fs::read(dir.join(&key)) {
+            Ok(content) => Ok(Some(content)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        },
+        ReportsDestination::Bucket { .. } => {
+            let req = GetObjectRequest {
+                bucket: tokens.reports_bucket.bucket.clone(),
+                key,
+                ..Default::default()
+            };
+            match reports::bucket_client(tokens)?.get_object(req).sync() {
+                Ok(object) => match object.body {
+                    Some(body) => Ok(Some(body.concat2().wait()?)),
+                    None => Ok(Some(Vec::new())),
+                },
+                Err(GetObjectError::NoSuchKey(_)) => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Content type of the files included in the reports.
+pub fn content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("gz") => "application/gzip",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_type, read, signed_path, verify};
+    use crate::config::{Config, ReportsDestination};
+    use crate::server::tokens::Tokens;
+    use chrono::{Duration, TimeZone, Utc};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn parse(path: &str) -> (String, i64, String) {
+        let parts = path.split('/').collect::<Vec<_>>();
+        assert_eq!(parts[1], "private-reports");
+        assert_eq!(parts[5], "index.html");
+        (parts[2].into(), parts[3].parse().unwrap(), parts[4].into())
+    }
+
+    #[test]
+    fn test_signed_links() {
+        let mut tokens = Tokens::default();
+        let now = Utc.ymd(2019, 1, 1).and_hms(0, 0, 0);
+        let expires = now + Duration::hours(24);
+
+        // Links can't be signed without a key
+        assert!(signed_path(&tokens, "foo", expires).is_err());
+        tokens.private_reports_key = Some("secret".into());
+
+        let (name, expiry, signature) = parse(&signed_path(&tokens, "foo", expires).unwrap());
+        assert_eq!(name, "foo");
+        assert_eq!(expiry, expires.timestamp());
+        assert!(verify(&tokens, "foo", expiry, &signature, now).unwrap());
+
+        // The link expires
+        let later = now + Duration::hours(23);
+        assert!(verify(&tokens, "foo", expiry, &signature, later).unwrap());
+        assert!(!verify(&tokens, "foo", expiry, &signature, expires).unwrap());
+        let after = expires + Duration::seconds(1);
+        assert!(!verify(&tokens, "foo", expiry, &signature, after).unwrap());
+
+        // The expiry and the experiment are covered by the signature
+        assert!(!verify(&tokens, "foo", expiry + 3600, &signature, now).unwrap());
+        assert!(!verify(&tokens, "bar", expiry, &signature, now).unwrap());
+        assert!(!verify(&tokens, "foo", expiry, "not-hex", now).unwrap());
+
+        // Changing the key invalidates the existing links
+        tokens.private_reports_key = Some("rotated".into());
+        assert!(!verify(&tokens, "foo", expiry, &signature, now).unwrap());
+    }
+
+    #[test]
+    fn test_read_from_directory() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.server.reports.destination = ReportsDestination::Directory {
+            path: dir.path().into(),
+        };
+        let tokens = Tokens::default();
+
+        fs::create_dir_all(dir.path().join("private/foo")).unwrap();
+        fs::write(dir.path().join("private/foo/index.html"), b"report").unwrap();
+        fs::write(dir.path().join("public.html"), b"public").unwrap();
+
+        assert_eq!(
+            read(&config, &tokens, "foo", "index.html").unwrap(),
+            Some(b"report".to_vec())
+        );
+        assert_eq!(read(&config, &tokens, "foo", "missing.html").unwrap(), None);
+        assert_eq!(
+            read(&config, &tokens, "foo", "../../public.html").unwrap(),
+            None
+        );
+
+        assert_eq!(content_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(
+            content_type("stable/reg/foo-1.0.0/log.txt"),
+            "text/plain; charset=utf-8"
+        );
+    }
+}
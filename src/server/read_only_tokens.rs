@@ -0,0 +1,110 @@
+//! Read-only tokens created through the Admin API, accepted alongside the ones listed in the
+//! `[read-only]` section of `tokens.toml`. Only hashes of the tokens are stored in the database.
+
+use crate::db::{Database, QueryUtils};
+use crate::prelude::*;
+use crate::server::agent_tokens::hash;
+use crate::server::tokens::Tokens;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone)]
+pub struct ReadOnlyTokens {
+    db: Database,
+    /// Name of the owner of each token, keyed by the hash of the token.
+    created: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl ReadOnlyTokens {
+    pub fn new(db: Database) -> Fallible<Self> {
+        let created = db
+            .query(
+                "SELECT name, token_hash FROM read_only_tokens;",
+                &[],
+                |row| (row.get("token_hash"), row.get("name")),
+            )?
+            .into_iter()
+            .collect();
+        Ok(ReadOnlyTokens {
+            db,
+            created: Arc::new(RwLock::new(created)),
+        })
+    }
+
+    /// Name of the owner of the token, if it was created through the Admin API.
+    pub fn name(&self, token: &str) -> Option<String> {
+        self.created.read().unwrap().get(&hash(token)).cloned()
+    }
+
+    /// Create a new read-only token, which is returned. Returns `None` if a read-only token with
+    /// the same name already exists, either in `tokens.toml` or in the database.
+    pub fn create(&self, tokens: &Tokens, name: &str, author: &str) -> Fallible<Option<String>> {
+        // The lock is held while the database is updated, so two tokens with the same name can't
+        // be created at the same time
+        let mut created = self.created.write().unwrap();
+        if tokens.read_only.values().any(|existing| existing == name)
+            || created.values().any(|existing| existing == name)
+        {
+            return Ok(None);
+        }
+
+        let token = Tokens::generate();
+        let token_hash = hash(&token);
+        self.db.execute(
+            "INSERT INTO read_only_tokens (name, token_hash, created_at, created_by) \
+             VALUES (?1, ?2, ?3, ?4);",
+            &[&name, &token_hash, &Utc::now(), &author],
+        )?;
+        created.insert(token_hash, name.to_string());
+        Ok(Some(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReadOnlyTokens;
+    use crate::db::Database;
+    use crate::server::tokens::Tokens;
+
+    #[test]
+    fn test_create() {
+        let db = Database::temp().unwrap();
+        let mut tokens = Tokens::default();
+        tokens
+            .read_only
+            .insert("static-token".into(), "viewer".into());
+
+        let read_only_tokens = ReadOnlyTokens::new(db.clone()).unwrap();
+        assert_eq!(read_only_tokens.name("static-token"), None);
+
+        let token = read_only_tokens
+            .create(&tokens, "dashboard", "admin")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            read_only_tokens.name(&token).as_ref().map(String::as_str),
+            Some("dashboard")
+        );
+        assert_eq!(read_only_tokens.name("unknown"), None);
+
+        // Names are unique, including the ones of the tokens in tokens.toml
+        assert_eq!(
+            read_only_tokens
+                .create(&tokens, "dashboard", "admin")
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            read_only_tokens.create(&tokens, "viewer", "admin").unwrap(),
+            None
+        );
+
+        // The tokens are kept after a restart
+        let reloaded = ReadOnlyTokens::new(db.clone()).unwrap();
+        assert_eq!(
+            reloaded.name(&token).as_ref().map(String::as_str),
+            Some("dashboard")
+        );
+    }
+}
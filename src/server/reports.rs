@@ -1,42 +1,368 @@
+use crate::actions::ExperimentError;
+use crate::config::{Config, ReportsDestination};
 use crate::experiments::{Experiment, Status};
 use crate::prelude::*;
-use crate::report::{self, Comparison, TestResults};
+use crate::report::{
+    self, diff::DiffCategory, Comparison, LiveReport, ReportSummary, ReportWriter, TestResults,
+};
 use crate::results::DatabaseDB;
+use crate::server::email::Email;
 use crate::server::messages::{Label, Message};
+use crate::server::private_reports;
+use crate::server::tokens::Tokens;
 use crate::server::Data;
 use crate::utils;
+use chrono::{DateTime, Utc};
+use mime::Mime;
 use rusoto_core::request::HttpClient;
 use rusoto_s3::S3Client;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::Read;
+use std::path::Path;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 // Automatically wake up the reports generator thread every 10 minutes to check for new jobs
 const AUTOMATIC_THREAD_WAKEUP: u64 = 600;
 
-fn generate_report(data: &Data, ex: &Experiment, results: &DatabaseDB) -> Fallible<TestResults> {
-    let client = S3Client::new_with(
+/// The reports generator thread beats every time it wakes up and after every report, so it's only
+/// considered dead after going without a heartbeat for longer than the largest reports take to
+/// be generated.
+const HEARTBEAT_TIMEOUT: i64 = 2 * 60 * 60;
+
+/// Regressions listed in each group of the completion comment, the full list is in the report.
+const COMMENT_CRATES_PER_SECTION: usize = 10;
+
+/// Where a report is published, as configured in the `[server.reports]` section.
+enum ReportDestination {
+    Directory(report::FileWriter),
+    Bucket(report::S3Writer),
+}
+
+/// Client of the bucket configured in `tokens.toml`.
+pub(super) fn bucket_client(tokens: &Tokens) -> Fallible<S3Client> {
+    let bucket = &tokens.reports_bucket;
+    Ok(S3Client::new_with(
         HttpClient::new()?,
-        data.tokens.reports_bucket.to_aws_credentials(),
-        data.tokens.reports_bucket.region.to_region()?,
-    );
-    let dest = format!("s3://{}/{}", data.tokens.reports_bucket.bucket, &ex.name);
-    let writer = report::S3Writer::create(Box::new(client), dest.parse()?)?;
+        bucket.to_aws_credentials(),
+        bucket.region.to_region()?,
+    ))
+}
+
+impl ReportDestination {
+    /// Files of private reports are never uploaded with the public ACL.
+    fn new(config: &Config, tokens: &Tokens, prefix: &str, private: bool) -> Fallible<Self> {
+        Ok(match config.server.reports.destination {
+            ReportsDestination::Directory { ref path } => {
+                ReportDestination::Directory(report::FileWriter::create(path.join(prefix))?)
+            }
+            ReportsDestination::Bucket { public_read } => {
+                let dest = format!("s3://{}/{}", tokens.reports_bucket.bucket, prefix);
+                ReportDestination::Bucket(
+                    report::S3Writer::create(Box::new(bucket_client(tokens)?), dest.parse()?)?
+                        .public_read(public_read && !private),
+                )
+            }
+        })
+    }
+}
+
+impl ReportWriter for ReportDestination {
+    fn write_bytes<P: AsRef<Path>>(&self, path: P, b: Vec<u8>, mime: &Mime) -> Fallible<()> {
+        match self {
+            ReportDestination::Directory(writer) => writer.write_bytes(path, b, mime),
+            ReportDestination::Bucket(writer) => writer.write_bytes(path, b, mime),
+        }
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        match self {
+            ReportDestination::Directory(writer) => writer.write_string(path, s, mime),
+            ReportDestination::Bucket(writer) => writer.write_string(path, s, mime),
+        }
+    }
+
+    fn copy<P: AsRef<Path>, R: Read>(&self, r: &mut R, path: P, mime: &Mime) -> Fallible<()> {
+        match self {
+            ReportDestination::Directory(writer) => writer.copy(r, path, mime),
+            ReportDestination::Bucket(writer) => writer.copy(r, path, mime),
+        }
+    }
+}
+
+impl Display for ReportDestination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportDestination::Directory(writer) => writer.fmt(f),
+            ReportDestination::Bucket(writer) => writer.fmt(f),
+        }
+    }
+}
+
+/// URL the report is served from, independently of where it was written to.
+fn public_url(config: &Config, tokens: &Tokens, prefix: &str) -> String {
+    let base_url = match config.server.reports.public_url {
+        Some(ref url) => url.clone(),
+        None => tokens
+            .reports_bucket
+            .public_url
+            .replace("{bucket}", &tokens.reports_bucket.bucket),
+    };
+    format!("{}/{}/index.html", base_url.trim_end_matches('/'), prefix)
+}
+
+/// Generate and publish the report, returning its summary and the results of the crates, used to
+/// list the regressions in the completion comment and the email digest.
+///
+/// Private reports are published separately, and their results are not returned so they're not
+/// shared anywhere else.
+fn generate_report(
+    data: &Data,
+    ex: &Experiment,
+    results: &DatabaseDB,
+) -> Fallible<(ReportSummary, Option<TestResults>)> {
+    if ex.private {
+        let prefix = private_reports::prefix(&ex.name);
+        let writer = ReportDestination::new(&data.config, &data.tokens, &prefix, true)?;
+        let (_, summary) = report::gen(results, &ex, &writer, &data.config, None)?;
+        summary.store(&data.db, &ex.name)?;
+        return Ok((summary, None));
+    }
+
+    let writer = ReportDestination::new(&data.config, &data.tokens, &ex.name, false)?;
+    let report_url = public_url(&data.config, &data.tokens, &ex.name);
+    let (res, mut summary) = report::gen(results, &ex, &writer, &data.config, Some(&report_url))?;
+
+    // Regenerating the report replaces the summary of the previous one
+    summary.report_url = Some(report_url);
+    summary.store(&data.db, &ex.name)?;
+
+    Ok((summary, Some(res)))
+}
+
+/// Email the digest of the published report, if it's configured. Failing to send it doesn't
+/// prevent the experiment from completing.
+fn send_digest(data: &Data, ex: &Experiment, res: &TestResults, report_url: &str) {
+    if ex.silent {
+        return;
+    }
+    if let Some(ref config) = data.config.server.email {
+        let email = Email::digest(ex, res, report_url);
+        match email.send(config, &data.tokens) {
+            Ok(()) => info!("emailed the digest of the report of {}", ex.name),
+            Err(err) => {
+                error!("failed to email the digest of the report of {}", ex.name);
+                utils::report_failure(&err);
+            }
+        }
+    }
+}
+
+/// Publish again the report of a completed experiment from its stored results, replacing the
+/// previous one without changing the status of the experiment.
+fn regenerate_report(data: &Data, ex: &mut Experiment, results: &DatabaseDB) -> Fallible<()> {
+    // The logs could have been purged after the regeneration was requested
+    if results.logs_purged(ex)? {
+        return Err(ExperimentError::LogsPurged(ex.name.clone()).into());
+    }
+
+    generate_report(data, ex, results)?;
+    if !ex.private {
+        let report_url = public_url(&data.config, &data.tokens, &ex.name);
+        ex.set_report_url(&data.db, &report_url)?;
+    }
+    Ok(())
+}
+
+/// Live report of a running experiment, and when it was last published.
+struct LiveState {
+    report: LiveReport,
+    updated_at: Option<Instant>,
+}
+
+fn live_prefix(ex: &Experiment) -> String {
+    format!("live/{}", ex.name)
+}
+
+/// Update the live reports of the running experiments that opted into them, once enough new
+/// results were recorded or enough time passed since the last update.
+fn update_live_reports(
+    data: &Data,
+    results: &DatabaseDB,
+    live: &mut HashMap<String, LiveState>,
+) -> Fallible<()> {
+    let running = Experiment::unfinished(&data.db)?
+        .into_iter()
+        .filter(|ex| ex.status == Status::Running && ex.live_report && !ex.private)
+        .collect::<Vec<_>>();
+
+    // Forget the reports of the experiments that stopped running
+    live.retain(|name, _| running.iter().any(|ex| ex.name == *name));
+
+    let config = &data.config.server.live_reports;
+    let interval = Duration::from_secs(config.interval_minutes * 60);
+    for ex in &running {
+        if !live.contains_key(&ex.name) {
+            let report = LiveReport::new(ex, &data.config)?;
+            live.insert(
+                ex.name.clone(),
+                LiveState {
+                    report,
+                    updated_at: None,
+                },
+            );
+        }
+        let state = live.get_mut(&ex.name).unwrap();
+
+        // The report is published as soon as there are results
+        let new = results.results_since(ex, state.report.last_result())?;
+        let due = match state.updated_at {
+            Some(updated_at) => {
+                new.len() >= config.results as usize || updated_at.elapsed() >= interval
+            }
+            None => true,
+        };
+        if new.is_empty() || !due {
+            continue;
+        }
+
+        let prefix = live_prefix(ex);
+        let writer = ReportDestination::new(&data.config, &data.tokens, &prefix, false)?;
+        let first_publish = state
+            .report
+            .update(results, &data.config, ex, &new, &writer)?;
+        state.updated_at = Some(Instant::now());
+
+        if let (true, Some(github_issue)) = (first_publish, ex.notification_issue()) {
+            Message::new()
+                .line(
+                    "satellite",
+                    format!(
+                        "A [live report]({}) of **`{}`** is available, updated while the \
+                         experiment is running.",
+                        public_url(&data.config, &data.tokens, &prefix),
+                        ex.name,
+                    ),
+                )
+                .send(&github_issue.api_url, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn generate_comparison(
+    data: &Data,
+    first: &Experiment,
+    second: &Experiment,
+    issue_url: &str,
+) -> Fallible<()> {
+    let prefix = format!("compare/{}/{}", first.name, second.name);
+    let writer = ReportDestination::new(&data.config, &data.tokens, &prefix, false)?;
+    let diff = report::diff::gen(
+        &DatabaseDB::new(&data.db),
+        first,
+        second,
+        &writer,
+        &data.config,
+    )?;
 
-    let res = report::gen(results, &ex, &writer, &data.config)?;
+    Message::new()
+        .line(
+            "bar_chart",
+            format!(
+                "Compared **`{}`** with **`{}`**: {} newly regressed, {} still regressed \
+                 and {} fixed.",
+                second.name,
+                first.name,
+                diff.count(DiffCategory::NewlyRegressed),
+                diff.count(DiffCategory::StillRegressed),
+                diff.count(DiffCategory::Fixed),
+            ),
+        )
+        .line(
+            "newspaper",
+            format!(
+                "[Open the comparison]({}).",
+                public_url(&data.config, &data.tokens, &prefix)
+            ),
+        )
+        .send(issue_url, data)?;
 
-    Ok(res)
+    Ok(())
+}
+
+/// Generate the report comparing the two experiments in the background, as loading the results of
+/// both of them can take a while.
+pub fn spawn_comparison(data: &Data, first: Experiment, second: Experiment, issue_url: String) {
+    let data = data.clone();
+    thread::spawn(move || {
+        info!(
+            "comparing experiments {} and {}...",
+            first.name, second.name
+        );
+        if let Err(err) = generate_comparison(&data, &first, &second, &issue_url) {
+            error!(
+                "failed to compare experiments {} and {}",
+                first.name, second.name
+            );
+            utils::report_failure(&err);
+
+            let message = Message::new().line(
+                "rotating_light",
+                format!(
+                    "Comparison of **`{}`** with **`{}`** failed: {}",
+                    second.name, first.name, err
+                ),
+            );
+            if let Err(err) = message.send(&issue_url, &data) {
+                utils::report_failure(&err);
+            }
+        }
+    });
 }
 
 fn reports_thread(data: &Data, wakes: &mpsc::Receiver<()>) -> Fallible<()> {
-    let timeout = Duration::from_secs(AUTOMATIC_THREAD_WAKEUP);
     let results = DatabaseDB::new(&data.db);
+    let mut live = HashMap::new();
 
     loop {
+        data.reports_worker.beat();
         let mut ex = match Experiment::first_by_status(&data.db, Status::NeedsReport)? {
             Some(ex) => ex,
             None => {
-                // This will sleep AUTOMATIC_THREAD_WAKEUP seconds *or* until a wake is received
+                // New reports are generated before the regenerated ones
+                if let Some(mut ex) = Experiment::first_report_regeneration(&data.db)? {
+                    info!("regenerating report for experiment {}...", ex.name);
+                    match regenerate_report(data, &mut ex, &results) {
+                        Ok(()) => info!("report for the experiment {} regenerated!", ex.name),
+                        Err(err) => {
+                            error!("failed to regenerate the report of {}", ex.name);
+                            utils::report_failure(&err);
+                        }
+                    }
+                    ex.clear_report_regeneration(&data.db)?;
+                    continue;
+                }
+
+                if let Err(err) = update_live_reports(data, &results, &mut live) {
+                    error!("failed to update the live reports");
+                    utils::report_failure(&err);
+                    // Start again from scratch, in case the reports are out of sync
+                    live.clear();
+                }
+
+                // Live reports need to be updated even if no result is recorded
+                let mut timeout = Duration::from_secs(AUTOMATIC_THREAD_WAKEUP);
+                if !live.is_empty() {
+                    let interval = data.config.server.live_reports.interval_minutes * 60;
+                    timeout = timeout.min(Duration::from_secs(interval));
+                }
+
+                // This will sleep until the timeout *or* until a wake is received
                 if let Err(mpsc::RecvTimeoutError::Disconnected) = wakes.recv_timeout(timeout) {
                     thread::sleep(timeout);
                 }
@@ -44,99 +370,152 @@ fn reports_thread(data: &Data, wakes: &mpsc::Receiver<()>) -> Fallible<()> {
                 continue;
             }
         };
-        let name = ex.name.clone();
+        live.remove(&ex.name);
+        complete_experiment(data, &mut ex, &results)?;
+    }
+}
 
-        info!("generating report for experiment {}...", name);
-        ex.set_status(&data.db, Status::GeneratingReport)?;
+/// Post the message on the issue of the experiment. Failing to post it doesn't prevent the
+/// experiment from changing status: messages are queued while GitHub is down, and other errors
+/// are only logged.
+fn notify(data: &Data, ex: &Experiment, issue_url: &str, message: Message) {
+    if let Err(err) = message.send(issue_url, data) {
+        error!("failed to post the message about {} on GitHub", ex.name);
+        utils::report_failure(&err);
+    }
+}
 
-        match generate_report(data, &ex, &results) {
-            Err(err) => {
-                ex.set_status(&data.db, Status::ReportFailed)?;
-                error!("failed to generate the report of {}", name);
-                utils::report_failure(&err);
+/// Generate the report of an experiment that finished running, and tell its issue about it.
+fn complete_experiment(data: &Data, ex: &mut Experiment, results: &DatabaseDB) -> Fallible<()> {
+    let name = ex.name.clone();
+    info!("generating report for experiment {}...", name);
+    ex.set_status(&data.db, Status::GeneratingReport)?;
+    data.report_events.publish(ex, None, None);
+    let started = Instant::now();
 
-                if let Some(ref github_issue) = ex.github_issue {
-                    Message::new()
-                        .line(
-                            "rotating_light",
-                            format!("Report generation of **`{}`** failed: {}", name, err),
-                        )
-                        .line(
-                            "hammer_and_wrench",
-                            "If the error is fixed use the `retry-report` command.",
-                        )
-                        .note(
-                            "sos",
-                            "Can someone from the infra team check in on this? @rust-lang/infra",
-                        )
-                        .send(&github_issue.api_url, data)?;
-                }
+    match generate_report(data, ex, results) {
+        Err(err) => {
+            ex.set_status(&data.db, Status::ReportFailed)?;
+            error!("failed to generate the report of {}", name);
+            utils::report_failure(&err);
+            data.report_events
+                .publish(ex, Some(started.elapsed()), Some(err.to_string()));
 
-                continue;
+            if let Some(github_issue) = ex.notification_issue() {
+                let message = Message::new()
+                    .line(
+                        "rotating_light",
+                        format!("Report generation of **`{}`** failed: {}", name, err),
+                    )
+                    .line(
+                        "hammer_and_wrench",
+                        "If the error is fixed use the `retry-report` command.",
+                    )
+                    .note(
+                        "sos",
+                        "Can someone from the infra team check in on this? @rust-lang/infra",
+                    );
+                notify(data, ex, &github_issue.api_url, message);
+            }
+        }
+        Ok((_, None)) => {
+            ex.set_status(&data.db, Status::Completed)?;
+            info!("private report for the experiment {} generated!", name);
+
+            if let Some(github_issue) = ex.notification_issue() {
+                let message = Message::new()
+                    .line("tada", format!("Experiment **`{}`** is completed!", name))
+                    .line(
+                        "lock",
+                        format!(
+                            "The report is private: its readers can request a link to it \
+                             from `/api/v1/experiments/{}/report-link`.",
+                            name
+                        ),
+                    )
+                    .set_label(Label::ExperimentCompleted);
+                notify(data, ex, &github_issue.api_url, message);
             }
-            Ok(res) => {
-                let base_url = data
-                    .tokens
-                    .reports_bucket
-                    .public_url
-                    .replace("{bucket}", &data.tokens.reports_bucket.bucket);
-                let report_url = format!("{}/{}/index.html", base_url, name);
-
-                ex.set_status(&data.db, Status::Completed)?;
-                ex.set_report_url(&data.db, &report_url)?;
-                info!("report for the experiment {} generated successfully!", name);
-
-                let (mut regressed, mut fixed) = (0, 0);
-                res.crates.iter().for_each(|krate| {
-                    match krate.res {
-                        Comparison::Regressed => regressed += 1,
-                        Comparison::Fixed => fixed += 1,
-                        _ => (),
-                    };
-                });
-
-                if let Some(ref github_issue) = ex.github_issue {
-                    Message::new()
-                        .line("tada", format!("Experiment **`{}`** is completed!", name))
-                        .line(
-                            "bar_chart",
-                            format!(
-                                " {} regressed and {} fixed ({} total)",
-                                regressed,
-                                fixed,
-                                res.crates.len(),
-                            ),
-                        )
-                        .line(
-                            "newspaper",
-                            format!("[Open the full report]({}).", report_url),
-                        )
-                        .note(
-                            "warning",
-                            format!(
-                                "If you notice any spurious failure [please add them to the \
-                                 blacklist]({}/blob/master/config.toml)!",
-                                crate::CRATER_REPO_URL,
-                            ),
-                        )
-                        .set_label(Label::ExperimentCompleted)
-                        .send(&github_issue.api_url, data)?;
+        }
+        Ok((summary, Some(res))) => {
+            let report_url = public_url(&data.config, &data.tokens, &name);
+
+            ex.set_status(&data.db, Status::Completed)?;
+            ex.set_report_url(&data.db, &report_url)?;
+            info!("report for the experiment {} generated successfully!", name);
+            data.report_events
+                .publish(ex, Some(started.elapsed()), None);
+            send_digest(data, ex, &res, &report_url);
+
+            if let Some(github_issue) = ex.notification_issue() {
+                let mut completed = format!("Experiment **`{}`** is completed!", name);
+                if let Some(ref requested_by) = ex.requested_by {
+                    completed.push_str(&format!(" cc @{}", requested_by));
                 }
+
+                // Regressions caused by the same dependency are counted once
+                let regressed = if summary.dependency_regressions.is_empty() {
+                    format!("{} regressed", summary.count(Comparison::Regressed))
+                } else {
+                    format!(
+                        "{} root regressions ({} crates regressed)",
+                        summary.root_regressions(),
+                        summary.count(Comparison::Regressed),
+                    )
+                };
+
+                let message = Message::new()
+                    .line("tada", completed)
+                    .line(
+                        "bar_chart",
+                        format!(
+                            " {} and {} fixed ({} total)",
+                            regressed,
+                            summary.count(Comparison::Fixed),
+                            summary.results(),
+                        ),
+                    )
+                    .line(
+                        "newspaper",
+                        format!("[Open the full report]({}).", report_url),
+                    )
+                    .section(report::render_regressions(
+                        &res,
+                        Some(&report_url),
+                        COMMENT_CRATES_PER_SECTION,
+                    ))
+                    .note(
+                        "warning",
+                        format!(
+                            "If you notice any spurious failure [please add them to the \
+                             blacklist]({}/blob/master/config.toml)!",
+                            crate::CRATER_REPO_URL,
+                        ),
+                    )
+                    .set_label(Label::ExperimentCompleted);
+                notify(data, ex, &github_issue.api_url, message);
             }
         }
     }
+    Ok(())
 }
 
 #[derive(Clone, Default)]
-pub struct ReportsWorker(Arc<Mutex<Option<mpsc::Sender<()>>>>);
+pub struct ReportsWorker {
+    waker: Arc<Mutex<Option<mpsc::Sender<()>>>>,
+    heartbeat: Arc<Mutex<Option<DateTime<Utc>>>>,
+}
 
 impl ReportsWorker {
     pub fn new() -> Self {
-        ReportsWorker(Arc::new(Mutex::new(None)))
+        ReportsWorker {
+            waker: Arc::new(Mutex::new(None)),
+            heartbeat: Arc::new(Mutex::new(None)),
+        }
     }
 
     pub fn spawn(&self, data: Data) {
-        let waker = self.0.clone();
+        let waker = self.waker.clone();
         thread::spawn(move || {
             // Set up a new waker channel
             let (wake_send, wake_recv) = mpsc::channel();
@@ -161,7 +540,7 @@ impl ReportsWorker {
     pub fn wake(&self) {
         // We don't really care if the wake fails: the reports generator thread wakes up on its own
         // every few minutes, so this just speeds up the process
-        if let Some(waker) = self.0.lock().ok().as_ref().and_then(|opt| opt.as_ref()) {
+        if let Some(waker) = self.waker.lock().ok().as_ref().and_then(|opt| opt.as_ref()) {
             if waker.send(()).is_err() {
                 warn!("can't wake the reports generator, will have to wait");
             }
@@ -169,4 +548,139 @@ impl ReportsWorker {
             warn!("no report generator to wake up!");
         }
     }
+
+    /// Record that the reports generator thread is still working.
+    pub(super) fn beat(&self) {
+        *self.heartbeat.lock().unwrap() = Some(Utc::now());
+    }
+
+    /// Whether the reports generator thread is running and not stuck at `now`.
+    pub(super) fn is_alive(&self, now: DateTime<Utc>) -> bool {
+        match *self.heartbeat.lock().unwrap() {
+            Some(heartbeat) => {
+                now.signed_duration_since(heartbeat).num_seconds() < HEARTBEAT_TIMEOUT
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete_experiment, public_url, ReportDestination};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::{Config, ReportsDestination};
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::{Experiment, GitHubIssue, Status};
+    use crate::prelude::*;
+    use crate::report;
+    use crate::results::DatabaseDB;
+    use crate::server::github::GitHubApi;
+    use crate::server::messages;
+    use crate::server::tokens::Tokens;
+    use crate::server::Data;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_publish_to_directory() {
+        let dir = tempdir().unwrap();
+        let db = Database::temp().unwrap();
+        let mut config = Config::default();
+        config.server.reports.destination = ReportsDestination::Directory {
+            path: dir.path().into(),
+        };
+        config.server.reports.public_url = Some("https://reports.example.com/".into());
+        let mut tokens = Tokens::default();
+        tokens.reports_bucket.public_url = "https://{bucket}.s3.amazonaws.com".into();
+
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let url = public_url(&config, &tokens, &ex.name);
+        assert_eq!(url, "https://reports.example.com/dummy/index.html");
+        let writer = ReportDestination::new(&config, &tokens, &ex.name, false).unwrap();
+        report::gen(&DatabaseDB::new(&db), &ex, &writer, &config, Some(&url)).unwrap();
+
+        let report = dir.path().join("dummy");
+        assert!(report.join("index.html").is_file());
+        assert!(report.join("results.json").is_file());
+        assert!(report.join("config.json").is_file());
+
+        // Without an override the URL of the bucket is used
+        config.server.reports.public_url = None;
+        assert_eq!(
+            public_url(&config, &tokens, "live/dummy"),
+            "https://crater-reports.s3.amazonaws.com/live/dummy/index.html"
+        );
+    }
+
+    #[test]
+    fn test_complete_with_github_down() {
+        let dir = tempdir().unwrap();
+        let db = Database::temp().unwrap();
+        let mut config = Config::default();
+        config.server.reports.destination = ReportsDestination::Directory {
+            path: dir.path().into(),
+        };
+        let mut tokens = Tokens::default();
+        tokens.bot.api_token = "github-token".into();
+
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment {
+            github_issue: Some(GitHubIssue {
+                api_url: "https://api.github.com/repos/rust-lang/rust/issues/1".into(),
+                html_url: "https://github.com/rust-lang/rust/issues/1".into(),
+                number: 1,
+            }),
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        let github = GitHubApi::new(&config.github, &tokens).unwrap();
+        let data = Data::new(config, db.clone(), tokens, github, "crater-bot".into()).unwrap();
+
+        // Open the circuit, as if the recent calls to GitHub failed
+        for _ in 0..10 {
+            let _ = data
+                .github
+                .circuit_breaker()
+                .call(|_| true, || Err::<(), _>(err_msg("GitHub is down")));
+        }
+        assert!(data.github.circuit_breaker().ensure_closed().is_err());
+
+        let mut ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        complete_experiment(&data, &mut ex, &DatabaseDB::new(&db)).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Completed);
+
+        let queued = db
+            .query(
+                "SELECT issue_url, message, label FROM queued_messages;",
+                &[],
+                |row| {
+                    let issue_url: String = row.get("issue_url");
+                    let message: String = row.get("message");
+                    let label: Option<String> = row.get("label");
+                    (issue_url, message, label)
+                },
+            )
+            .unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(
+            queued[0].0,
+            "https://api.github.com/repos/rust-lang/rust/issues/1"
+        );
+        assert!(queued[0].1.contains("Experiment **`dummy`** is completed!"));
+        assert_eq!(queued[0].2, Some("experiment-completed".to_string()));
+
+        // The message stays queued until GitHub works again
+        assert_eq!(messages::deliver_queued(&data).unwrap(), 0);
+        assert!(db
+            .exists("SELECT rowid FROM queued_messages;", &[])
+            .unwrap());
+    }
 }
@@ -0,0 +1,505 @@
+use crate::crates::Crate;
+use crate::experiments::{Experiment, InvalidStatusOverride, Status};
+use crate::prelude::*;
+use crate::results::DatabaseDB;
+use crate::server::api_types::{
+    ApiResponse, BadRequest, InternalError, NotFound, PayloadTooLarge, RequestTimeout, Unauthorized,
+};
+use crate::server::auth::{auth_filter, AuthDetails, TokenType};
+use crate::server::{body, timeout, Data, HttpError};
+use crate::toolchain::Toolchain;
+use failure::Compat;
+use http::{Response, StatusCode};
+use hyper::Body;
+use std::sync::Arc;
+use utoipa::{OpenApi, ToSchema};
+use warp::{self, Filter, Rejection};
+
+/// Endpoints of the admin API, relative to its prefix.
+#[derive(OpenApi)]
+#[openapi(paths(
+    endpoint_invalidate_results,
+    endpoint_purge_logs,
+    endpoint_set_status,
+    endpoint_rotate_token,
+    endpoint_create_read_only_token,
+))]
+pub struct ApiDoc;
+
+/// The routes of the admin API, before the rejections are turned into JSON errors.
+pub fn endpoints(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_cloned = data.clone();
+    let data_filter = warp::any().map(move || data_cloned.clone());
+    let limits = &data.config.server.body_limits;
+    let deadline = timeout::caller_deadline(data.clone());
+
+    let invalidate_results = warp::post2()
+        .and(warp::path("invalidate-results"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(
+            limits.agent.to_bytes() as u64
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Admin))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_invalidate_results(
+            body: InvalidateResultsData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let purge_logs = warp::post2()
+        .and(warp::path("purge-logs"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(
+            limits.agent.to_bytes() as u64
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Admin))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_purge_logs(
+            body: PurgeLogsData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let set_status = warp::post2()
+        .and(warp::path("experiment"))
+        .and(warp::path::param())
+        .and(warp::path("set-status"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(
+            limits.agent.to_bytes() as u64
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Admin))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_set_status(
+            name: String,
+            body: SetStatusData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let rotate_token = warp::post2()
+        .and(warp::path("agent"))
+        .and(warp::path::param())
+        .and(warp::path("rotate-token"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Admin))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_rotate_token(
+            name: String,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let create_read_only_token = warp::post2()
+        .and(warp::path("read-only-token"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(
+            limits.agent.to_bytes() as u64
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Admin))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_create_read_only_token(
+            body: CreateReadOnlyTokenData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    warp::any()
+        .and(
+            invalidate_results
+                .or(purge_logs)
+                .unify()
+                .or(set_status)
+                .unify()
+                .or(rotate_token)
+                .unify()
+                .or(create_read_only_token)
+                .unify(),
+        )
+        .map(handle_results)
+}
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    endpoints(data).recover(handle_errors).unify()
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct InvalidateResultsData {
+    experiment: String,
+    /// Crate IDs, in the same format used by the public API.
+    crates: Vec<String>,
+    /// Defaults to all the toolchains of the experiment.
+    #[serde(default)]
+    toolchains: Option<Vec<Toolchain>>,
+    #[serde(default)]
+    reopen: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct InvalidatedResults {
+    removed: usize,
+}
+
+/// Delete the results of some crates
+///
+/// The crates are tested again. Fails while the report of the experiment is being generated.
+#[utoipa::path(
+    post,
+    path = "/invalidate-results",
+    request_body = InvalidateResultsData,
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<InvalidatedResults>
+        ),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("adminToken" = []))
+)]
+fn endpoint_invalidate_results(
+    body: InvalidateResultsData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let mut ex = match Experiment::get(&data.db, &body.experiment)? {
+        Some(ex) => ex,
+        None => return Err(HttpError::NotFound.into()),
+    };
+    let crates = body
+        .crates
+        .iter()
+        .map(|id| Crate::from_id(id).map_err(|_| HttpError::BadRequest))
+        .collect::<Result<Vec<_>, _>>()?;
+    let toolchains = body.toolchains.unwrap_or_else(|| ex.build_toolchains());
+
+    let removed = ex.invalidate_results(&data.db, &crates, &toolchains, body.reopen)?;
+    info!(
+        "{} invalidated {} results of experiment {}, which is now {}",
+        auth.name, removed, ex.name, ex.status
+    );
+
+    Ok(ApiResponse::Success {
+        result: InvalidatedResults { removed },
+    }
+    .into_response()?)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct PurgeLogsData {
+    experiment: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PurgedLogs {
+    purged: usize,
+}
+
+/// Delete the logs of a completed experiment
+///
+/// The results are kept, and logs shared with other experiments are kept for them.
+#[utoipa::path(
+    post,
+    path = "/purge-logs",
+    request_body = PurgeLogsData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<PurgedLogs>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("adminToken" = []))
+)]
+fn endpoint_purge_logs(
+    body: PurgeLogsData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let ex = match Experiment::get(&data.db, &body.experiment)? {
+        Some(ex) => ex,
+        None => return Err(HttpError::NotFound.into()),
+    };
+
+    let purged = DatabaseDB::new(&data.db).purge_logs(&ex)?;
+    info!(
+        "{} purged the logs of {} results of experiment {}",
+        auth.name, purged, ex.name
+    );
+
+    Ok(ApiResponse::Success {
+        result: PurgedLogs { purged },
+    }
+    .into_response()?)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct SetStatusData {
+    status: Status,
+    /// Allow transitions that don't make sense during normal operations.
+    #[serde(default)]
+    force: bool,
+}
+
+/// Change the status of an experiment
+///
+/// Meant to recover experiments stuck in a status. Transitions that don't make sense during normal
+/// operations, like queueing a completed experiment again, are rejected unless `force` is true.
+/// Every change is recorded with the name of the administrator who made it.
+#[utoipa::path(
+    post,
+    path = "/experiment/{name}/set-status",
+    params(("name" = String, Path)),
+    request_body = SetStatusData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<bool>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("adminToken" = []))
+)]
+fn endpoint_set_status(
+    name: String,
+    body: SetStatusData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let mut ex = match Experiment::get(&data.db, &name)? {
+        Some(ex) => ex,
+        None => return Err(HttpError::NotFound.into()),
+    };
+
+    let from = ex.status;
+    ex.override_status(&data.db, body.status, &auth.name, body.force)?;
+    info!(
+        "{} changed the status of experiment {} from {} to {}{}",
+        auth.name,
+        ex.name,
+        from,
+        ex.status,
+        if body.force { " (forced)" } else { "" }
+    );
+    if ex.status == Status::NeedsReport {
+        data.report_events.publish(&ex, None, None);
+        data.reports_worker.wake();
+    }
+
+    Ok(ApiResponse::Success { result: true }.into_response()?)
+}
+
+#[derive(Serialize, ToSchema)]
+struct NewToken {
+    token: String,
+}
+
+/// Replace the token of an agent
+///
+/// Meant to revoke a compromised token. The token of the agent listed in `tokens.toml` (or returned
+/// by a previous rotation) is rejected as soon as the request succeeds, and only the returned token
+/// is accepted from then on, even after the server restarts. The agent has to be reconfigured with
+/// the new token.
+#[utoipa::path(
+    post,
+    path = "/agent/{name}/rotate-token",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<NewToken>),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("adminToken" = []))
+)]
+fn endpoint_rotate_token(
+    name: String,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let token = match data.agent_tokens.rotate(&data.tokens, &name)? {
+        Some(token) => token,
+        None => return Err(HttpError::NotFound.into()),
+    };
+    info!("{} rotated the token of agent {}", auth.name, name);
+
+    Ok(ApiResponse::Success {
+        result: NewToken { token },
+    }
+    .into_response()?)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct CreateReadOnlyTokenData {
+    /// Name of the token, unique among the read-only tokens.
+    name: String,
+}
+
+/// Create a read-only token
+///
+/// The token is accepted like the ones in the `[read-only]` section of `tokens.toml`. Only a hash of
+/// it is stored in the database. Names already in use are rejected.
+#[utoipa::path(
+    post,
+    path = "/read-only-token",
+    request_body = CreateReadOnlyTokenData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<NewToken>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("adminToken" = []))
+)]
+fn endpoint_create_read_only_token(
+    body: CreateReadOnlyTokenData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    if body.name.is_empty() {
+        return Err(HttpError::BadRequest.into());
+    }
+    let token = match data
+        .read_only_tokens
+        .create(&data.tokens, &body.name, &auth.name)?
+    {
+        Some(token) => token,
+        None => return Err(HttpError::BadRequest.into()),
+    };
+    info!("{} created the read-only token {}", auth.name, body.name);
+
+    Ok(ApiResponse::Success {
+        result: NewToken { token },
+    }
+    .into_response()?)
+}
+
+fn handle_results(resp: Fallible<Response<Body>>) -> Response<Body> {
+    match resp {
+        Ok(resp) => resp,
+        Err(err) => match err.downcast_ref::<HttpError>() {
+            Some(HttpError::NotFound) => ApiResponse::not_found().into_response().unwrap(),
+            Some(HttpError::BadRequest) => ApiResponse::bad_request().into_response().unwrap(),
+            _ if err.downcast_ref::<InvalidStatusOverride>().is_some() => {
+                ApiResponse::<()>::BadRequest {
+                    error: err.to_string(),
+                }
+                .into_response()
+                .unwrap()
+            }
+            _ => ApiResponse::internal_error(err.to_string())
+                .into_response()
+                .unwrap(),
+        },
+    }
+}
+
+fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
+    let error = if let Some(compat) = err.find_cause::<Compat<HttpError>>() {
+        Some(*compat.get_ref())
+    } else if let StatusCode::NOT_FOUND = err.status() {
+        Some(HttpError::NotFound)
+    } else if let StatusCode::METHOD_NOT_ALLOWED = err.status() {
+        Some(HttpError::NotFound)
+    } else if let StatusCode::PAYLOAD_TOO_LARGE = err.status() {
+        Some(HttpError::PayloadTooLarge)
+    } else {
+        None
+    };
+
+    match error {
+        Some(HttpError::NotFound) => Ok(ApiResponse::not_found().into_response().unwrap()),
+        Some(HttpError::Forbidden) => Ok(ApiResponse::unauthorized().into_response().unwrap()),
+        Some(HttpError::PayloadTooLarge) => {
+            Ok(ApiResponse::payload_too_large().into_response().unwrap())
+        }
+        Some(HttpError::RequestTimeout) => {
+            Ok(ApiResponse::request_timeout().into_response().unwrap())
+        }
+        Some(HttpError::BadRequest) => Ok(ApiResponse::bad_request().into_response().unwrap()),
+        None => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::server::testing::TestServer;
+    use crate::server::tokens::Tokens;
+    use reqwest::header::AUTHORIZATION;
+    use reqwest::{Client, StatusCode};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_create_read_only_token() {
+        let mut tokens = Tokens::default();
+        tokens.admins.insert("admin-token".into(), "admin".into());
+        let server =
+            TestServer::start(Config::default(), Database::temp().unwrap(), tokens).unwrap();
+        let client = Client::new();
+
+        let create = |token: &str, name: &str| {
+            client
+                .post(&format!("{}/admin-api/read-only-token", server.url()))
+                .header(AUTHORIZATION, format!("CraterToken {}", token))
+                .json(&json!({ "name": name }))
+                .send()
+                .unwrap()
+        };
+        let list_agents = |token: &str| {
+            client
+                .get(&format!("{}/api/v1/agents", server.url()))
+                .header(AUTHORIZATION, format!("CraterToken {}", token))
+                .send()
+                .unwrap()
+                .status()
+        };
+
+        let mut resp = create("admin-token", "dashboard");
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = resp.json().unwrap();
+        let token = body["result"]["token"].as_str().unwrap().to_string();
+
+        // The new token is accepted by the endpoints requiring a read-only token
+        assert_eq!(list_agents(&token), StatusCode::OK);
+        assert_eq!(list_agents("unknown"), StatusCode::UNAUTHORIZED);
+
+        // Names are unique, and only admins can create tokens
+        assert_eq!(
+            create("admin-token", "dashboard").status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(create(&token, "other").status(), StatusCode::UNAUTHORIZED);
+    }
+}
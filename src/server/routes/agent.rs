@@ -1,57 +1,135 @@
+use crate::crates::Crate;
+use crate::db::{self, Database};
 use crate::experiments::{Assignee, Experiment, Status};
 use crate::prelude::*;
-use crate::results::{DatabaseDB, ProgressData};
-use crate::server::api_types::{AgentConfig, ApiResponse};
+use crate::results::{DatabaseDB, PartialProgressData, ProgressData, WriteResults};
+use crate::server::agents::RevisionChange;
+use crate::server::api_types::{
+    AgentConfig, ApiResponse, BadRequest, HostInfo, InternalError, ItemOutcome, PayloadTooLarge,
+    RequestTimeout, Unauthorized, AGENT_API_VERSION,
+};
 use crate::server::auth::{auth_filter, AuthDetails, TokenType};
-use crate::server::messages::Message;
-use crate::server::{Data, HttpError};
+use crate::server::{body, running_comments, timeout, Data, HttpError};
+use crate::toolchain::Toolchain;
+use crate::utils;
+use chrono::Utc;
 use failure::Compat;
-use http::{Response, StatusCode};
+use http::header::{HeaderValue, LOCATION};
+use http::{Method, Response, StatusCode};
 use hyper::Body;
 use std::sync::Arc;
-use warp::{self, Filter, Rejection};
+use utoipa::{OpenApi, ToSchema};
+use warp::{self, path::Tail, Filter, Rejection};
 
-pub fn routes(
+/// Endpoints of the agent API, relative to the prefix of its current version.
+#[derive(OpenApi)]
+#[openapi(paths(
+    endpoint_config,
+    endpoint_next_experiment,
+    endpoint_complete_experiment,
+    endpoint_record_progress,
+    endpoint_record_progress_partial,
+    endpoint_record_broken_toolchain,
+    endpoint_heartbeat,
+))]
+pub struct ApiDoc;
+
+/// The routes of the agent API. Unmatched and unauthenticated requests are rejected.
+pub fn endpoints(
     data: Arc<Data>,
 ) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
     let data_cloned = data.clone();
     let data_filter = warp::any().map(move || data_cloned.clone());
+    let limits = &data.config.server.body_limits;
+    let deadline = timeout::caller_deadline(data.clone());
 
     let config = warp::get2()
         .and(warp::path("config"))
         .and(warp::path::end())
         .and(data_filter.clone())
         .and(auth_filter(data.clone(), TokenType::Agent))
-        .map(endpoint_config);
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_config(data: Arc<Data>, auth: AuthDetails)));
 
     let next_experiment = warp::get2()
         .and(warp::path("next-experiment"))
         .and(warp::path::end())
         .and(data_filter.clone())
         .and(auth_filter(data.clone(), TokenType::Agent))
-        .map(endpoint_next_experiment);
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_next_experiment(data: Arc<Data>, auth: AuthDetails)));
 
     let complete_experiment = warp::post2()
         .and(warp::path("complete-experiment"))
         .and(warp::path::end())
         .and(data_filter.clone())
         .and(auth_filter(data.clone(), TokenType::Agent))
-        .map(endpoint_complete_experiment);
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_complete_experiment(data: Arc<Data>, auth: AuthDetails)));
 
     let record_progress = warp::post2()
         .and(warp::path("record-progress"))
         .and(warp::path::end())
-        .and(warp::body::json())
+        .and(warp::body::content_length_limit(
+            limits.results.to_bytes() as u64
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Agent))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_record_progress(
+            result: ProgressData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    // Allow some overhead over the chunk size for the base64 encoding and the JSON body
+    let partial_limit = data.config.partial_logs.max_chunk_size.to_bytes() as u64 * 2 + 4096;
+    let record_progress_partial = warp::post2()
+        .and(warp::path("record-progress-partial"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(partial_limit))
+        .and(body::json(data.clone()))
         .and(data_filter.clone())
         .and(auth_filter(data.clone(), TokenType::Agent))
-        .map(endpoint_record_progress);
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_record_progress_partial(
+            result: PartialProgressData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let record_broken_toolchain = warp::post2()
+        .and(warp::path("record-broken-toolchain"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(
+            limits.agent.to_bytes() as u64
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Agent))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_record_broken_toolchain(
+            body: BrokenToolchainData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
 
     let heartbeat = warp::post2()
         .and(warp::path("heartbeat"))
         .and(warp::path::end())
+        .and(warp::body::content_length_limit(
+            limits.agent.to_bytes() as u64
+        ))
+        .and(body::bytes(data.clone()))
         .and(data_filter.clone())
         .and(auth_filter(data.clone(), TokenType::Agent))
-        .map(endpoint_heartbeat);
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_heartbeat(
+            body: Vec<u8>,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
 
     warp::any()
         .and(
@@ -62,43 +140,124 @@ pub fn routes(
                 .unify()
                 .or(record_progress)
                 .unify()
+                .or(record_progress_partial)
+                .unify()
+                .or(record_broken_toolchain)
+                .unify()
                 .or(heartbeat)
                 .unify(),
         )
         .map(handle_results)
-        .recover(handle_errors)
-        .unify()
 }
 
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    endpoints(data).recover(handle_errors).unify()
+}
+
+/// Redirect the requests sent to the paths used before the agent API was versioned to the first
+/// version of the API. The redirects will be removed two releases after the versioning, when all
+/// the agents are expected to be updated.
+pub fn legacy_redirects() -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::path::tail())
+        .map(|method: Method, tail: Tail| legacy_redirect(&method, tail.as_str()))
+}
+
+fn legacy_redirect(method: &Method, path: &str) -> Response<Body> {
+    // Clients follow 301 redirects of POST requests with GET requests, dropping the body
+    let status = if *method == Method::GET {
+        StatusCode::MOVED_PERMANENTLY
+    } else {
+        StatusCode::PERMANENT_REDIRECT
+    };
+
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = status;
+    // The path was already parsed from the request URI, so it's a valid header value
+    if let Ok(location) = HeaderValue::from_str(&format!("/agent-api/v1/{}", path)) {
+        resp.headers_mut().insert(LOCATION, location);
+    }
+    resp
+}
+
+/// Configuration of the agent
+///
+/// Should be called when the agent starts. The response is tied to the API token.
+#[utoipa::path(
+    get,
+    path = "/config",
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<AgentConfig>),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
 fn endpoint_config(data: Arc<Data>, auth: AuthDetails) -> Fallible<Response<Body>> {
     Ok(ApiResponse::Success {
         result: AgentConfig {
             agent_name: auth.name,
             crater_config: data.config.clone(),
+            api_version: Some(AGENT_API_VERSION),
+            agent_update_url: data
+                .config
+                .server
+                .agent_update
+                .as_ref()
+                .map(|u| u.url.clone()),
+            agent_update_sha256: data
+                .config
+                .server
+                .agent_update
+                .as_ref()
+                .map(|u| u.sha256.clone()),
         },
     }
     .into_response()?)
 }
 
+/// Experiment the agent should run
+///
+/// Assigns the next queued experiment to the agent, and keeps returning it until it's completed.
+/// The result is `null` when no experiment is available.
+#[utoipa::path(
+    get,
+    path = "/next-experiment",
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<Option<Experiment>>
+        ),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
 fn endpoint_next_experiment(data: Arc<Data>, auth: AuthDetails) -> Fallible<Response<Body>> {
+    for ex in data.agents.release_unreachable()? {
+        warn!(
+            "the agent running experiment {} is unreachable, putting it back in the queue",
+            ex
+        );
+    }
+
     let next = Experiment::next(&data.db, &Assignee::Agent(auth.name.clone()))?;
 
     let result = if let Some((new, mut ex)) = next {
         if new {
-            if let Some(ref github_issue) = ex.github_issue {
-                Message::new()
-                    .line(
-                        "construction",
-                        format!(
-                            "Experiment **`{}`** is now **running** on agent `{}`.",
-                            ex.name, auth.name,
-                        ),
-                    )
-                    .send(&github_issue.api_url, &data)?;
-            }
+            running_comments::post(&data, &ex, &auth.name)?;
         }
 
         ex.remove_completed_crates(&data.db)?;
+        if data.config.server.weighted_scheduling {
+            ex.interleave_by_duration(&data.db)?;
+        }
+        ex.assign_crates(&data.db, &auth.name)?;
         Some(ex)
     } else {
         None
@@ -107,40 +266,281 @@ fn endpoint_next_experiment(data: Arc<Data>, auth: AuthDetails) -> Fallible<Resp
     Ok(ApiResponse::Success { result }.into_response()?)
 }
 
+/// Mark the experiment run by the agent as complete
+///
+/// The server then generates the report of the experiment.
+#[utoipa::path(
+    post,
+    path = "/complete-experiment",
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<bool>),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
 fn endpoint_complete_experiment(data: Arc<Data>, auth: AuthDetails) -> Fallible<Response<Body>> {
     let mut ex = Experiment::run_by(&data.db, &Assignee::Agent(auth.name.clone()))?
         .ok_or_else(|| err_msg("no experiment run by this agent"))?;
 
     ex.set_status(&data.db, Status::NeedsReport)?;
     info!("experiment {} completed, marked as needs-report", ex.name);
+    data.report_events.publish(&ex, None, None);
     data.reports_worker.wake(); // Ensure the reports worker is awake
 
     Ok(ApiResponse::Success { result: true }.into_response()?)
 }
 
+/// Record the results of some jobs
+///
+/// Replies with `false` if the server stopped the experiment because of fail-fast mode. Each result
+/// is stored on its own: if some of them couldn't be stored, the response lists the outcome of each
+/// result.
+#[utoipa::path(
+    post,
+    path = "/record-progress",
+    request_body = ProgressData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<bool>),
+        (
+            status = 207,
+            description = "Only some of the results were stored.",
+            body = ApiResponse<bool>
+        ),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
 fn endpoint_record_progress(
     result: ProgressData,
     data: Arc<Data>,
     auth: AuthDetails,
 ) -> Fallible<Response<Body>> {
-    let experiment = Experiment::run_by(&data.db, &Assignee::Agent(auth.name.clone()))?
+    let mut experiment = Experiment::run_by(&data.db, &Assignee::Agent(auth.name.clone()))?
         .ok_or_else(|| err_msg("no experiment run by this agent"))?;
 
+    log_fields!(info, "received progress";
+        "experiment" => experiment.name,
+        "agent" => auth.name,
+        "results" => result.results.len(),
+    );
+
+    let db = DatabaseDB::new(&data.db);
+    let outcomes = db.store_each(&experiment, &result)?;
+    let mut stored = Vec::with_capacity(outcomes.len());
+    for (task, outcome) in result.results.iter().zip(&outcomes) {
+        match outcome {
+            Ok(()) => stored.push(task),
+            Err(err) => log_fields!(warn, "failed to store the result";
+                "experiment" => experiment.name,
+                "agent" => auth.name,
+                "crate" => task.krate,
+                "toolchain" => task.toolchain,
+                "error" => err,
+            ),
+        }
+    }
+    data.progress_events
+        .publish(&experiment, stored.iter().cloned());
+    data.throughput.record(&auth.name, stored.len(), Utc::now());
+    experiment.complete_crates(&data.db, &auth.name, stored.iter().map(|r| &r.krate))?;
+
+    // Failing to update the comment shouldn't make the agent upload the results again
+    if let Err(err) = running_comments::update(&data, &experiment, &auth.name) {
+        utils::report_failure(&err);
+    }
+
+    let crates = stored.iter().map(|r| &r.krate);
+    let stopped = stop_on_regression(&data.db, &mut experiment, crates)?;
+    if stopped {
+        data.report_events.publish(&experiment, None, None);
+    }
+    if stopped || experiment.live_report {
+        data.reports_worker.wake(); // Ensure the reports worker is awake
+    }
+
+    Ok(record_progress_response(&outcomes, !stopped).into_response()?)
+}
+
+/// The result tells the agent whether it should keep running the experiment, and the response
+/// lists which results were stored if some of them failed.
+fn record_progress_response(outcomes: &[Fallible<()>], keep_running: bool) -> ApiResponse<bool> {
+    let items = outcomes
+        .iter()
+        .map(|outcome| match outcome {
+            Ok(()) => ItemOutcome::Success,
+            Err(err) => ItemOutcome::Error {
+                error: err.to_string(),
+            },
+        })
+        .collect();
+    ApiResponse::batch(keep_running, items)
+}
+
+/// Stop a fail-fast experiment if one of the crates regressed, returning whether it was stopped.
+fn stop_on_regression<'a, I>(db: &Database, ex: &mut Experiment, crates: I) -> Fallible<bool>
+where
+    I: IntoIterator<Item = &'a Crate>,
+{
+    if !ex.fail_fast || !DatabaseDB::new(db).has_regression(ex, crates)? {
+        return Ok(false);
+    }
+
+    ex.set_status(db, Status::NeedsReport)?;
     info!(
-        "received progress on experiment {} from agent {}",
-        experiment.name, auth.name,
+        "experiment {} regressed in fail-fast mode, marked as needs-report",
+        ex.name
     );
+    Ok(true)
+}
+
+/// Upload a chunk of the log of a running job
+///
+/// Only available if partial logs are enabled in the server configuration.
+#[utoipa::path(
+    post,
+    path = "/record-progress-partial",
+    request_body = PartialProgressData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<bool>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
+fn endpoint_record_progress_partial(
+    result: PartialProgressData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    if !data.config.partial_logs.enabled {
+        bail!("partial logs are disabled on this server");
+    }
+
+    let experiment = Experiment::run_by(&data.db, &Assignee::Agent(auth.name.clone()))?
+        .ok_or_else(|| err_msg("no experiment run by this agent"))?;
 
     let db = DatabaseDB::new(&data.db);
-    db.store(&experiment, &result)?;
+    db.store_partial(&experiment, &result, data.config.partial_logs.max_chunks)?;
 
     Ok(ApiResponse::Success { result: true }.into_response()?)
 }
 
-fn endpoint_heartbeat(data: Arc<Data>, auth: AuthDetails) -> Fallible<Response<Body>> {
+#[derive(Deserialize, ToSchema)]
+struct BrokenToolchainData {
+    toolchain: Toolchain,
+}
+
+/// Report a broken toolchain
+///
+/// The results of the toolchain caused by it being broken are deleted, so the crates are tested
+/// again.
+#[utoipa::path(
+    post,
+    path = "/record-broken-toolchain",
+    request_body = BrokenToolchainData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<bool>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
+fn endpoint_record_broken_toolchain(
+    body: BrokenToolchainData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let experiment = Experiment::run_by(&data.db, &Assignee::Agent(auth.name.clone()))?
+        .ok_or_else(|| err_msg("no experiment run by this agent"))?;
+
+    warn!(
+        "agent {} reported the toolchain {} is broken while running experiment {}",
+        auth.name, body.toolchain, experiment.name,
+    );
+
+    let db = DatabaseDB::new(&data.db);
+    db.record_broken_toolchain(&experiment, &body.toolchain)?;
+
+    Ok(ApiResponse::Success { result: true }.into_response()?)
+}
+
+#[derive(Default, Deserialize, ToSchema)]
+struct HeartbeatData {
+    #[serde(default)]
+    host: Option<HostInfo>,
+    /// Why the agent paused, if it can't run experiments right now. Heartbeats without it mark
+    /// the agent as healthy again.
+    #[serde(default)]
+    unhealthy: Option<String>,
+}
+
+/// Signal the agent is alive
+///
+/// Should be called when the agent starts and every minute.
+#[utoipa::path(
+    post,
+    path = "/heartbeat",
+    request_body(
+        content = Option<HeartbeatData>,
+        description = "Older agents send the heartbeat without a body."
+    ),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<bool>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("agentToken" = []))
+)]
+fn endpoint_heartbeat(
+    body: Vec<u8>,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    // Older agents send the heartbeat without a body
+    let body: HeartbeatData = if body.is_empty() {
+        HeartbeatData::default()
+    } else {
+        ::serde_json::from_slice(&body)?
+    };
+
     if let Some(rev) = auth.git_revision {
-        data.agents.set_git_revision(&auth.name, &rev)?;
+        match data.agents.set_git_revision(&auth.name, &rev)? {
+            RevisionChange::Unchanged => {}
+            RevisionChange::Updated(previous) => info!(
+                "agent {} updated itself from revision {} to {}",
+                auth.name, previous, rev
+            ),
+            RevisionChange::Conflict(previous) => warn!(
+                "agent {} switched from revision {} to {} right after the previous change, \
+                 its token is probably used by more than one process",
+                auth.name, previous, rev
+            ),
+        }
+    }
+    if let Some(host) = body.host {
+        data.agents.set_host(&auth.name, &host)?;
+    }
+
+    if let Some(ref reason) = body.unhealthy {
+        warn!("agent {} is unhealthy: {}", auth.name, reason);
     }
+    data.agents
+        .set_unhealthy(&auth.name, body.unhealthy.as_ref().map(|s| s.as_str()))?;
 
     data.agents.record_heartbeat(&auth.name)?;
     Ok(ApiResponse::Success { result: true }.into_response()?)
@@ -149,6 +549,10 @@ fn endpoint_heartbeat(data: Arc<Data>, auth: AuthDetails) -> Fallible<Response<B
 fn handle_results(resp: Fallible<Response<Body>>) -> Response<Body> {
     match resp {
         Ok(resp) => resp,
+        // The agent retries the request when the server is unavailable
+        Err(ref err) if db::is_busy(err) => ApiResponse::service_unavailable(err.to_string())
+            .into_response()
+            .unwrap(),
         Err(err) => ApiResponse::internal_error(err.to_string())
             .into_response()
             .unwrap(),
@@ -162,6 +566,8 @@ fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
         Some(HttpError::NotFound)
     } else if let StatusCode::METHOD_NOT_ALLOWED = err.status() {
         Some(HttpError::NotFound)
+    } else if let StatusCode::PAYLOAD_TOO_LARGE = err.status() {
+        Some(HttpError::PayloadTooLarge)
     } else {
         None
     };
@@ -169,6 +575,187 @@ fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
     match error {
         Some(HttpError::NotFound) => Ok(ApiResponse::not_found().into_response().unwrap()),
         Some(HttpError::Forbidden) => Ok(ApiResponse::unauthorized().into_response().unwrap()),
+        Some(HttpError::PayloadTooLarge) => {
+            Ok(ApiResponse::payload_too_large().into_response().unwrap())
+        }
+        Some(HttpError::RequestTimeout) => {
+            Ok(ApiResponse::request_timeout().into_response().unwrap())
+        }
+        Some(HttpError::BadRequest) => Ok(ApiResponse::bad_request().into_response().unwrap()),
         None => Err(err),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{legacy_redirect, record_progress_response, stop_on_regression};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::{Experiment, Status};
+    use crate::results::{
+        DatabaseDB, FailureReason, ProgressData, ReadResults, TaskResult, TestResult, WriteResults,
+    };
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use base64;
+    use futures::{Future, Stream};
+    use http::header::LOCATION;
+    use http::{Method, StatusCode};
+    use serde_json::{self, json};
+
+    fn record(db: &Database, ex: &Experiment, name: &str, start: TestResult, end: TestResult) {
+        let results = DatabaseDB::new(db);
+        let config = Config::default();
+        let krate = Crate::Registry(RegistryCrate {
+            name: name.into(),
+            version: "1".into(),
+        });
+        results
+            .record_result(ex, &MAIN_TOOLCHAIN, &krate, None, &config, || Ok(start))
+            .unwrap();
+        results
+            .record_result(ex, &TEST_TOOLCHAIN, &krate, None, &config, || Ok(end))
+            .unwrap();
+    }
+
+    fn start(ctx: &ActionsCtx, db: &Database, create: CreateExperiment) -> Experiment {
+        let name = create.name.clone();
+        create.apply(ctx).unwrap();
+        let mut ex = Experiment::get(db, &name).unwrap().unwrap();
+        ex.set_status(db, Status::Running).unwrap();
+        ex
+    }
+
+    #[test]
+    fn test_stop_on_regression() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let fail = TestResult::TestFail(FailureReason::Unknown);
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1".into(),
+            })
+        };
+
+        let mut ex = start(
+            &ctx,
+            &db,
+            CreateExperiment {
+                fail_fast: true,
+                ..CreateExperiment::dummy("fail-fast")
+            },
+        );
+
+        // Fixed crates don't stop the experiment
+        record(&db, &ex, "a", fail, TestResult::TestPass);
+        assert!(!stop_on_regression(&db, &mut ex, &[krate("a")]).unwrap());
+        assert_eq!(ex.status, Status::Running);
+
+        // The first regression completes the experiment early
+        record(&db, &ex, "b", TestResult::TestPass, fail);
+        assert!(stop_on_regression(&db, &mut ex, &[krate("a"), krate("b")]).unwrap());
+        let ex = Experiment::get(&db, "fail-fast").unwrap().unwrap();
+        assert_eq!(ex.status, Status::NeedsReport);
+        assert!(ex.completed_at.is_some());
+
+        // Experiments without fail-fast keep running after a regression
+        let mut ex = start(&ctx, &db, CreateExperiment::dummy("normal"));
+        record(&db, &ex, "b", TestResult::TestPass, fail);
+        assert!(!stop_on_regression(&db, &mut ex, &[krate("b")]).unwrap());
+        assert_eq!(ex.status, Status::Running);
+    }
+
+    #[test]
+    fn test_record_progress_partial_failure() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        let ex = start(&ctx, &db, CreateExperiment::dummy("dummy"));
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        let task = |toolchain, log: &str| TaskResult {
+            krate: krate.clone(),
+            toolchain,
+            result: TestResult::TestPass,
+            log: log.into(),
+            duration_ms: None,
+            warnings: None,
+            artifacts_size: None,
+            phases: Vec::new(),
+            error_category: None,
+        };
+        let data = ProgressData {
+            results: vec![
+                task(MAIN_TOOLCHAIN.clone(), &base64::encode("ok")),
+                task(TEST_TOOLCHAIN.clone(), "not base64!"),
+            ],
+            shas: Vec::new(),
+            dependencies: Vec::new(),
+            dependency_trees: Vec::new(),
+            docker_image: None,
+            environment: None,
+        };
+
+        // The bad result doesn't prevent the other one from being stored
+        let results = DatabaseDB::new(&db);
+        let outcomes = results.store_each(&ex, &data).unwrap();
+        assert_eq!(
+            results
+                .load_test_result(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            Some(TestResult::TestPass)
+        );
+        assert_eq!(
+            results
+                .load_test_result(&ex, &TEST_TOOLCHAIN, &krate)
+                .unwrap(),
+            None
+        );
+
+        let resp = record_progress_response(&outcomes, true)
+            .into_response()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let body = resp.into_body().concat2().wait().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "status": "partial",
+                "result": true,
+                "items": [
+                    {"status": "success"},
+                    {"status": "error", "error": "invalid base64 log provided"},
+                ],
+            })
+        );
+
+        // Batches without failures are plain successes
+        let resp = record_progress_response(&outcomes[..1], true)
+            .into_response()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_legacy_redirect() {
+        let resp = legacy_redirect(&Method::GET, "next-experiment");
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[LOCATION], "/agent-api/v1/next-experiment");
+
+        // The redirects of POST requests must preserve the method and the body
+        let resp = legacy_redirect(&Method::POST, "record-progress");
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(resp.headers()[LOCATION], "/agent-api/v1/record-progress");
+    }
+}
@@ -0,0 +1,2023 @@
+use crate::crates::Crate;
+use crate::db::{Database, QueryUtils, Row};
+use crate::experiments::{progress_percent, Experiment, FeatureSet, Status};
+use crate::prelude::*;
+use crate::report::ReportSummary;
+use crate::results::annotations::{
+    self, Annotation, AnnotationStatus, CrateAnnotation, MAX_NOTE_LENGTH,
+};
+use crate::results::crates::{self, CrateStatus, CratesPage};
+use crate::results::dependencies::{self, DependencyGraph, SharedDependency};
+use crate::results::export::{self, Confidence, ExportFormat, ExportedPage, Regression};
+use crate::results::{ChangedCrate, CrateDiagnostics, CrateHistoryEntry, CrateRun, DatabaseDB};
+use crate::runner::Reproduction;
+use crate::server::api_types::{
+    AgentInfo, ApiResponse, BadRequest, ExperimentDetails, ExperimentSummary, InternalError,
+    NotFound, PayloadTooLarge, ReportEvent, RequestTimeout, ThroughputEstimate, Unauthorized,
+};
+use crate::server::auth::{auth_filter, AuthDetails, TokenType};
+use crate::server::throughput::DEFAULT_WINDOW_MINUTES;
+use crate::server::{body, openapi, private_reports, timeout, Data, HttpError};
+use crate::utils::string::glob_matches;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use failure::{Compat, Error};
+use futures::Stream;
+use http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use hyper::Body;
+use rusqlite::types::ToSql;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use url::form_urlencoded;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use warp::{self, path::Tail, Filter, Rejection};
+
+/// Number of seconds the summary is cached for, both by the server and by the clients.
+const SUMMARY_CACHE_SECS: u64 = 30;
+/// Agents send an heartbeat every minute, consider them active if they missed at most one.
+const ACTIVE_AGENT_SECS: i64 = 120;
+/// Number of results returned in each page of the results of an experiment, if not provided.
+const DEFAULT_RESULTS_PER_PAGE: u32 = 100;
+const MAX_RESULTS_PER_PAGE: u32 = 1000;
+/// Number of days covered by the trends of the regressions, if not provided.
+const DEFAULT_TREND_DAYS: u32 = 90;
+/// Description of the path parameters identifying a crate, in the OpenAPI description.
+const CRATE_ID_DESCRIPTION: &str =
+    "`reg/{name}/{version}`, `gh/{org}/{repo}` or `local/{name}`; it can contain slashes.";
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct Summary {
+    pub queued_experiments: u32,
+    pub running_experiments: u32,
+    pub completed_experiments: u32,
+    pub active_agents: u32,
+    pub crates_tested_today: u32,
+    pub passed_results: u32,
+    pub failed_results: u32,
+    /// Failed results by kind of error, the ones recorded before the errors were classified
+    /// being counted as `unknown`.
+    pub failures_by_category: BTreeMap<String, u32>,
+    pub queue_depth: u32,
+}
+
+impl Summary {
+    pub fn load(db: &Database) -> Fallible<Self> {
+        let now = Utc::now();
+        let last_week = now - Duration::days(7);
+        let last_month = now - Duration::days(30);
+        let active_since = now - Duration::seconds(ACTIVE_AGENT_SECS);
+        let today = now.date().and_hms(0, 0, 0);
+
+        let mut failures_by_category = BTreeMap::new();
+        for (category, count) in db.query(
+            "SELECT COALESCE(error_category, 'unknown') AS category, COUNT(*) AS failures
+             FROM results
+             WHERE recorded_at >= ?1 AND result NOT IN ('test-pass', 'test-skipped')
+             GROUP BY COALESCE(error_category, 'unknown');",
+            &[&last_month],
+            |row| {
+                (
+                    row.get::<_, String>("category"),
+                    row.get::<_, i64>("failures"),
+                )
+            },
+        )? {
+            failures_by_category.insert(category, count as u32);
+        }
+
+        let summary = db.get_row(
+            "WITH
+                recent_experiments AS (
+                    SELECT status FROM experiments WHERE created_at >= ?1
+                ),
+                recent_results AS (
+                    SELECT result FROM results WHERE recorded_at >= ?2
+                ),
+                active_agents AS (
+                    SELECT name FROM agents WHERE last_heartbeat >= ?3
+                ),
+                tested_today AS (
+                    SELECT DISTINCT crate FROM results WHERE recorded_at >= ?4
+                )
+             SELECT
+                (SELECT COUNT(*) FROM recent_experiments WHERE status = ?5) AS queued,
+                (SELECT COUNT(*) FROM recent_experiments WHERE status = ?6) AS running,
+                (SELECT COUNT(*) FROM recent_experiments WHERE status = ?7) AS completed,
+                (SELECT COUNT(*) FROM active_agents) AS active_agents,
+                (SELECT COUNT(*) FROM tested_today) AS tested_today,
+                (SELECT COUNT(*) FROM recent_results
+                    WHERE result IN ('test-pass', 'test-skipped')) AS passed,
+                (SELECT COUNT(*) FROM recent_results) AS total,
+                (SELECT COUNT(*) FROM experiments WHERE status = ?5) AS queue_depth;",
+            &[
+                &last_week,
+                &last_month,
+                &active_since,
+                &today,
+                &Status::Queued.to_str(),
+                &Status::Running.to_str(),
+                &Status::Completed.to_str(),
+            ],
+            |row| {
+                let count = |col: &str| row.get::<_, i64>(col) as u32;
+                Summary {
+                    queued_experiments: count("queued"),
+                    running_experiments: count("running"),
+                    completed_experiments: count("completed"),
+                    active_agents: count("active_agents"),
+                    crates_tested_today: count("tested_today"),
+                    passed_results: count("passed"),
+                    failed_results: count("total") - count("passed"),
+                    failures_by_category: failures_by_category.clone(),
+                    queue_depth: count("queue_depth"),
+                }
+            },
+        )?;
+
+        summary.ok_or_else(|| err_msg("the summary query returned no rows"))
+    }
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ResultsQuery {
+    #[param(inline, required = false, default = "json")]
+    format: ExportFormat,
+    #[param(required = false, minimum = 1, default = 1)]
+    page: u32,
+    #[param(
+        rename = "per-page",
+        required = false,
+        minimum = 1,
+        maximum = 1000,
+        default = 100
+    )]
+    per_page: u32,
+}
+
+impl ResultsQuery {
+    fn parse(query: &str) -> Fallible<Self> {
+        let mut parsed = ResultsQuery {
+            format: ExportFormat::Json,
+            page: 1,
+            per_page: DEFAULT_RESULTS_PER_PAGE,
+        };
+
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "format" => parsed.format = value.parse().map_err(|_| HttpError::BadRequest)?,
+                "page" => parsed.page = value.parse().map_err(|_| HttpError::BadRequest)?,
+                "per-page" => parsed.per_page = value.parse().map_err(|_| HttpError::BadRequest)?,
+                _ => {}
+            }
+        }
+
+        if parsed.page == 0 || parsed.per_page == 0 {
+            return Err(HttpError::BadRequest.into());
+        }
+        parsed.per_page = parsed.per_page.min(MAX_RESULTS_PER_PAGE);
+
+        Ok(parsed)
+    }
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CratesQuery {
+    #[param(inline)]
+    status: Option<CrateStatus>,
+    #[param(required = false, minimum = 1, default = 1)]
+    page: u32,
+    #[param(
+        rename = "per-page",
+        required = false,
+        minimum = 1,
+        maximum = 1000,
+        default = 100
+    )]
+    per_page: u32,
+}
+
+impl CratesQuery {
+    fn parse(query: &str) -> Fallible<Self> {
+        let mut parsed = CratesQuery {
+            status: None,
+            page: 1,
+            per_page: DEFAULT_RESULTS_PER_PAGE,
+        };
+
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "status" => parsed.status = Some(value.parse().map_err(|_| HttpError::BadRequest)?),
+                "page" => parsed.page = value.parse().map_err(|_| HttpError::BadRequest)?,
+                "per-page" => parsed.per_page = value.parse().map_err(|_| HttpError::BadRequest)?,
+                _ => {}
+            }
+        }
+
+        if parsed.page == 0 || parsed.per_page == 0 {
+            return Err(HttpError::BadRequest.into());
+        }
+        parsed.per_page = parsed.per_page.min(MAX_RESULTS_PER_PAGE);
+
+        Ok(parsed)
+    }
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RegressionsQuery {
+    #[param(inline, required = false, default = "low")]
+    confidence: Confidence,
+    /// Include the crates matching a known failure of the server's config.
+    #[param(required = false, default = false)]
+    include_known: bool,
+}
+
+impl RegressionsQuery {
+    fn parse(query: &str) -> Fallible<Self> {
+        let mut parsed = RegressionsQuery {
+            confidence: Confidence::Low,
+            include_known: false,
+        };
+
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            if key == "confidence" {
+                parsed.confidence = value.parse().map_err(|_| HttpError::BadRequest)?;
+            } else if key == "include_known" {
+                parsed.include_known = value.parse().map_err(|_| HttpError::BadRequest)?;
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Filters of the list of experiments.
+#[derive(Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExperimentsQuery {
+    /// Only return the experiments requested by this GitHub user.
+    requested_by: Option<String>,
+    /// Only return the experiments with this status.
+    #[param(inline)]
+    status: Option<Status>,
+    /// Only return the experiments assigned to this assignee, for example `agent:agent-1`.
+    assignee: Option<String>,
+}
+
+impl ExperimentsQuery {
+    fn parse(query: &str) -> Fallible<Self> {
+        let mut parsed = ExperimentsQuery::default();
+
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "requested_by" => parsed.requested_by = Some(value.into_owned()),
+                "status" => parsed.status = Some(value.parse().map_err(|_| HttpError::BadRequest)?),
+                "assignee" => parsed.assignee = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Experiments the trends of the regressions are computed from.
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TrendsQuery {
+    /// Glob pattern the names of the experiments must match, like `nightly-weekly-*`, where `*`
+    /// matches any text and `?` a single character.
+    #[param(required = false, default = "*")]
+    experiment_pattern: String,
+    /// Number of days covered.
+    #[param(required = false, minimum = 1, default = 90)]
+    days: u32,
+}
+
+impl TrendsQuery {
+    fn parse(query: &str) -> Fallible<Self> {
+        let mut parsed = TrendsQuery {
+            experiment_pattern: "*".into(),
+            days: DEFAULT_TREND_DAYS,
+        };
+
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "experiment_pattern" => parsed.experiment_pattern = value.into_owned(),
+                "days" => parsed.days = value.parse().map_err(|_| HttpError::BadRequest)?,
+                _ => {}
+            }
+        }
+
+        if parsed.days == 0 {
+            return Err(HttpError::BadRequest.into());
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Outcomes of the experiments created on the same day.
+#[derive(Serialize, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrendPoint {
+    pub date: NaiveDate,
+    pub total_crates: u32,
+    pub regressions: u32,
+    pub improvements: u32,
+    /// Share of the crates that regressed, between 0 and 1.
+    pub regression_rate: f64,
+}
+
+impl TrendPoint {
+    /// Aggregate the outcomes of the public experiments matching the query by the day they were
+    /// created, oldest first. Days without experiments are skipped.
+    fn load(db: &Database, query: &TrendsQuery, now: DateTime<Utc>) -> Fallible<Vec<Self>> {
+        let since = now - Duration::days(i64::from(query.days));
+        let experiments = db.query(
+            "SELECT name, created_at FROM experiments \
+             WHERE created_at >= ?1 AND private = 0 ORDER BY created_at;",
+            &[&since],
+            |row| -> (String, DateTime<Utc>) { (row.get("name"), row.get("created_at")) },
+        )?;
+
+        let results = DatabaseDB::new(db);
+        let mut points = BTreeMap::new();
+        for (name, created_at) in experiments {
+            if !glob_matches(&query.experiment_pattern, &name) {
+                continue;
+            }
+            let ex = match Experiment::get(db, &name)? {
+                Some(ex) => ex,
+                // Deleted in the meantime
+                None => continue,
+            };
+
+            let summary = results.results_summary(&ex)?;
+            let date = created_at.naive_utc().date();
+            let point = points.entry(date).or_insert(TrendPoint {
+                date,
+                total_crates: 0,
+                regressions: 0,
+                improvements: 0,
+                regression_rate: 0.0,
+            });
+            point.total_crates += summary.total();
+            point.regressions += summary.regressed;
+            point.improvements += summary.fixed;
+        }
+
+        Ok(points
+            .into_iter()
+            .map(|(_, mut point)| {
+                if point.total_crates > 0 {
+                    point.regression_rate =
+                        f64::from(point.regressions) / f64::from(point.total_crates);
+                }
+                point
+            })
+            .collect())
+    }
+}
+
+/// Minutes of the window the throughput of the agents is estimated over.
+fn parse_throughput_window(query: &str) -> Fallible<u32> {
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        if key == "window" {
+            return Ok(value.parse().map_err(|_| HttpError::BadRequest)?);
+        }
+    }
+    Ok(DEFAULT_WINDOW_MINUTES)
+}
+
+/// Progress shown for an experiment: the results of the experiments that finished running can
+/// be incomplete, for example when it was aborted.
+fn summary_progress(status: Status, completed_jobs: u32, total_jobs: u32) -> u8 {
+    match status {
+        Status::Queued | Status::Running => progress_percent(completed_jobs, total_jobs),
+        _ => 100,
+    }
+}
+
+impl ExperimentSummary {
+    /// Load the public experiments matching the filters, most recent first.
+    fn list(db: &Database, query: &ExperimentsQuery) -> Fallible<Vec<Self>> {
+        let status = query.status.map(|status| status.to_str());
+        let mut clauses = vec!["private = 0".to_string()];
+        let mut params: Vec<&ToSql> = Vec::new();
+        if let Some(ref user) = query.requested_by {
+            params.push(user);
+            clauses.push(format!("requested_by = ?{}", params.len()));
+        }
+        if let Some(ref assignee) = query.assignee {
+            params.push(assignee);
+            clauses.push(format!("assigned_to = ?{}", params.len()));
+        }
+        if let Some(ref status) = status {
+            params.push(status);
+            clauses.push(format!("status = ?{}", params.len()));
+        }
+        let filter = format!("WHERE {}", clauses.join(" AND "));
+
+        let experiments = db.query(
+            &format!(
+                "SELECT name, status, mode, priority, assigned_to, created_at, started_at, \
+                 completed_at, requested_by, github_issue_url, report_url, feature_sets, \
+                 (SELECT COUNT(*) FROM experiment_crates \
+                    WHERE experiment = experiments.name AND skipped = 0) AS crates, \
+                 (SELECT COUNT(*) FROM results WHERE experiment = experiments.name) AS results \
+                 FROM experiments {} ORDER BY created_at DESC;",
+                filter
+            ),
+            &params,
+            |row| -> Fallible<ExperimentSummary> {
+                let status: Status = row.get::<_, String>("status").parse()?;
+                let feature_sets: Vec<FeatureSet> =
+                    serde_json::from_str(&row.get::<_, String>("feature_sets"))?;
+                let crates = row.get::<_, i64>("crates") as u32;
+                // Each crate is built with both toolchains for each feature set
+                let total_jobs = crates * 2 * feature_sets.len() as u32;
+
+                Ok(ExperimentSummary {
+                    name: row.get("name"),
+                    status,
+                    mode: row.get::<_, String>("mode").parse()?,
+                    priority: row.get("priority"),
+                    assigned_to: row.get("assigned_to"),
+                    created_at: row.get("created_at"),
+                    started_at: row.get("started_at"),
+                    completed_at: row.get("completed_at"),
+                    requested_by: row.get("requested_by"),
+                    github_issue_url: row.get("github_issue_url"),
+                    report_url: row.get("report_url"),
+                    crates,
+                    progress: summary_progress(
+                        status,
+                        row.get::<_, i64>("results") as u32,
+                        total_jobs,
+                    ),
+                })
+            },
+        )?;
+        experiments.into_iter().collect()
+    }
+}
+
+impl ExperimentDetails {
+    fn load(db: &Database, ex: &Experiment) -> Fallible<Self> {
+        let (completed_jobs, total_jobs) = ex.raw_progress(db)?;
+        let summary = ExperimentSummary {
+            name: ex.name.clone(),
+            status: ex.status,
+            mode: ex.mode,
+            priority: ex.priority,
+            assigned_to: ex.assigned_to.as_ref().map(|a| a.to_string()),
+            created_at: ex.created_at,
+            started_at: ex.started_at,
+            completed_at: ex.completed_at,
+            requested_by: ex.requested_by.clone(),
+            github_issue_url: ex.github_issue.as_ref().map(|i| i.html_url.clone()),
+            report_url: ex.report_url.clone(),
+            crates: total_jobs / ex.build_toolchains().len() as u32,
+            progress: summary_progress(ex.status, completed_jobs, total_jobs),
+        };
+
+        Ok(ExperimentDetails {
+            summary,
+            toolchains: ex.toolchains.iter().map(|tc| tc.to_string()).collect(),
+            cap_lints: ex.cap_lints,
+            completed_jobs,
+            total_jobs,
+            results: DatabaseDB::new(db).results_summary(ex)?,
+            estimated_end: ex.estimated_end(completed_jobs, total_jobs, Utc::now()),
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+struct SummaryCache {
+    inner: Arc<Mutex<Option<(Instant, Summary)>>>,
+}
+
+impl SummaryCache {
+    fn get_or_load(&self, db: &Database) -> Fallible<Summary> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(&(loaded_at, ref summary)) = inner.as_ref() {
+            if loaded_at.elapsed().as_secs() < SUMMARY_CACHE_SECS {
+                return Ok(summary.clone());
+            }
+        }
+
+        let summary = Summary::load(db)?;
+        *inner = Some((Instant::now(), summary.clone()));
+        Ok(summary)
+    }
+}
+
+/// Endpoints of the public API, relative to its prefix.
+#[derive(OpenApi)]
+#[openapi(paths(
+    endpoint_summary,
+    endpoint_crate_history,
+    endpoint_crate_runs,
+    endpoint_experiments,
+    endpoint_experiment,
+    endpoint_experiment_results,
+    endpoint_experiment_crates,
+    endpoint_experiment_regressions,
+    endpoint_experiment_summary,
+    endpoint_experiment_dependency_graph,
+    endpoint_experiment_shared_dependencies,
+    endpoint_experiment_annotations,
+    endpoint_set_annotation,
+    endpoint_experiment_reproduction,
+    endpoint_experiment_diagnostics,
+    endpoint_report_link,
+    endpoint_compare,
+    endpoint_agents,
+    endpoint_agent,
+    endpoint_throughput,
+    endpoint_trends,
+    endpoint_report_events,
+    endpoint_openapi,
+))]
+pub struct ApiDoc;
+
+/// The routes of the public API, without turning the rejections into error responses.
+pub fn endpoints(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_cloned = data.clone();
+    let data_filter = warp::any().map(move || data_cloned.clone());
+    let cache = SummaryCache::default();
+    let cache_filter = warp::any().map(move || cache.clone());
+    let deadline = timeout::caller_deadline(data.clone());
+
+    let summary = warp::get2()
+        .and(warp::path("summary"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(cache_filter)
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_summary(data: Arc<Data>, cache: SummaryCache)));
+
+    let crate_history = warp::get2()
+        .and(warp::path("crate-history"))
+        .and(warp::path::tail())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_crate_history(krate: Tail, data: Arc<Data>)));
+
+    let crate_runs = warp::get2()
+        .and(warp::path("crates"))
+        .and(warp::path::param())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_crate_runs(name: String, data: Arc<Data>)));
+
+    // The query string is optional, and warp rejects requests without one
+    let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+
+    let experiments = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiments(query: String, data: Arc<Data>)));
+
+    let experiment = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment(name: String, data: Arc<Data>)));
+
+    let experiment_results = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("results"))
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_results(
+            name: String,
+            query: String,
+            data: Arc<Data>,
+        )));
+
+    let experiment_crates = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("crates"))
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_crates(
+            name: String,
+            query: String,
+            data: Arc<Data>,
+        )));
+
+    let experiment_regressions = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("regressions"))
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_regressions(
+            name: String,
+            query: String,
+            data: Arc<Data>,
+        )));
+
+    let experiment_summary = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("summary"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_summary(name: String, data: Arc<Data>)));
+
+    let experiment_dependency_graph = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("dependency-graph"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_dependency_graph(
+            name: String,
+            data: Arc<Data>,
+        )));
+
+    let experiment_shared_dependencies = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("shared-dependencies"))
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_shared_dependencies(
+            name: String,
+            query: String,
+            data: Arc<Data>,
+        )));
+
+    let experiment_annotations = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("annotations"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_annotations(name: String, data: Arc<Data>)));
+
+    let experiment_reproduction = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("reproduce"))
+        .and(warp::path::tail())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_reproduction(
+            name: String,
+            krate: Tail,
+            data: Arc<Data>,
+        )));
+
+    let experiment_diagnostics = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("diagnostics"))
+        .and(warp::path::tail())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_experiment_diagnostics(
+            name: String,
+            krate: Tail,
+            data: Arc<Data>,
+        )));
+
+    // Annotations are the only writes of the public API, so they require an admin token
+    let set_annotation = warp::put2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("annotations"))
+        .and(warp::path::tail())
+        .and(warp::body::content_length_limit(
+            data.config.server.body_limits.agent.to_bytes() as u64,
+        ))
+        .and(body::json(data.clone()))
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::Admin))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_set_annotation(
+            name: String,
+            krate: Tail,
+            body: AnnotationData,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let report_link = warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("report-link"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::ReadOnly))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_report_link(
+            name: String,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let compare = warp::get2()
+        .and(warp::path("compare"))
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_compare(
+            first: String,
+            second: String,
+            query: String,
+            data: Arc<Data>,
+        )));
+
+    // The agents' hosts are only shown to the holders of a token
+    let agents = warp::get2()
+        .and(warp::path("agents"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::ReadOnly))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_agents(data: Arc<Data>, auth: AuthDetails)));
+
+    let agent = warp::get2()
+        .and(warp::path("agents"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::ReadOnly))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_agent(name: String, data: Arc<Data>, auth: AuthDetails)));
+
+    let throughput = warp::get2()
+        .and(warp::path("throughput"))
+        .and(warp::path::end())
+        .and(query.clone())
+        .and(data_filter.clone())
+        .and(auth_filter(data.clone(), TokenType::ReadOnly))
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_throughput(
+            query: String,
+            data: Arc<Data>,
+            auth: AuthDetails,
+        )));
+
+    let trends = warp::get2()
+        .and(warp::path("trends"))
+        .and(warp::path::end())
+        .and(query)
+        .and(data_filter.clone())
+        .and(deadline.clone())
+        .and_then(with_deadline!(endpoint_trends(query: String, data: Arc<Data>)));
+
+    let report_events = warp::get2()
+        .and(warp::path("reports"))
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .map(endpoint_report_events);
+
+    let openapi = warp::get2()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .map(endpoint_openapi);
+
+    warp::any()
+        .and(
+            summary
+                .or(crate_history)
+                .unify()
+                .or(crate_runs)
+                .unify()
+                .or(experiments)
+                .unify()
+                .or(experiment)
+                .unify()
+                .or(experiment_results)
+                .unify()
+                .or(experiment_crates)
+                .unify()
+                .or(experiment_regressions)
+                .unify()
+                .or(experiment_summary)
+                .unify()
+                .or(experiment_dependency_graph)
+                .unify()
+                .or(experiment_shared_dependencies)
+                .unify()
+                .or(experiment_annotations)
+                .unify()
+                .or(set_annotation)
+                .unify()
+                .or(experiment_reproduction)
+                .unify()
+                .or(experiment_diagnostics)
+                .unify()
+                .or(report_link)
+                .unify()
+                .or(compare)
+                .unify()
+                .or(agents)
+                .unify()
+                .or(agent)
+                .unify()
+                .or(throughput)
+                .unify()
+                .or(trends)
+                .unify()
+                .or(report_events)
+                .unify()
+                .or(openapi)
+                .unify(),
+        )
+        .map(handle_results)
+}
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    endpoints(data).recover(handle_errors).unify()
+}
+
+/// Load an experiment whose data is public: private experiments are only reachable through the
+/// signed links to their report.
+pub(super) fn public_experiment(db: &Database, name: &str) -> Fallible<Experiment> {
+    match Experiment::get(db, name)? {
+        Some(ref ex) if ex.private => Err(HttpError::NotFound.into()),
+        Some(ex) => Ok(ex),
+        None => Err(HttpError::NotFound.into()),
+    }
+}
+
+/// Overview of the crater system
+///
+/// Counts of recent experiments, agents and results. The response is cached for 30 seconds.
+#[utoipa::path(
+    get,
+    path = "/summary",
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Summary>),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_summary(data: Arc<Data>, cache: SummaryCache) -> Fallible<Response<Body>> {
+    let result = cache.get_or_load(&data.db)?;
+
+    let mut resp = ApiResponse::Success { result }.into_response()?;
+    resp.headers_mut().insert(
+        CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={}", SUMMARY_CACHE_SECS))?,
+    );
+    Ok(resp)
+}
+
+/// Stream of the changes of the status of the reports
+///
+/// Server-sent events, one every time the report of an experiment needs to be generated, starts
+/// being generated, is generated or fails to be. Each event is named `report` and its data is a
+/// JSON object. Private experiments are not included. The response never ends while the server is
+/// running.
+#[utoipa::path(
+    get,
+    path = "/reports/events",
+    responses((
+        status = 200,
+        description = "The stream of events.",
+        body = ReportEvent,
+        content_type = "text/event-stream"
+    ))
+)]
+fn endpoint_report_events(data: Arc<Data>) -> Fallible<Response<Body>> {
+    let events = data.report_events.subscribe().then(|event| {
+        let event = event.expect("receiving the events never fails");
+        serde_json::to_string(&event).map(|json| format!("event: report\ndata: {}\n\n", json))
+    });
+
+    let mut resp = Response::new(Body::wrap_stream(events));
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    resp.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(resp)
+}
+
+/// This description
+///
+/// The OpenAPI description of the crater server, generated from the annotations of the handlers.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((
+        status = 200,
+        description = "The description.",
+        body = Object,
+        content_type = "application/json"
+    ))
+)]
+fn endpoint_openapi() -> Fallible<Response<Body>> {
+    let mut resp = Response::new(openapi::SPEC.as_str().into());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(resp)
+}
+
+/// Results of a crate across every experiment
+///
+/// The results are sorted by the creation date of their experiment.
+#[utoipa::path(
+    get,
+    path = "/crate-history/{crate-id}",
+    params(("crate-id" = String, Path, description = CRATE_ID_DESCRIPTION)),
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<Vec<CrateHistoryEntry>>
+        ),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_crate_history(krate: Tail, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let krate = Crate::from_id(krate.as_str()).map_err(|_| HttpError::NotFound)?;
+    let result = DatabaseDB::new(&data.db).crate_history(&krate)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Results of every version of a crate across the experiments
+///
+/// The results of each version of the crate tested by a public experiment are compared like in the
+/// report, newest experiment first. Returns an empty list for crates without results.
+#[utoipa::path(
+    get,
+    path = "/crates/{name}/history",
+    params((
+        "name" = String,
+        Path,
+        description = "Name of the crate on crates.io, or `{org}.{repo}` for GitHub repositories."
+    )),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Vec<CrateRun>>),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_crate_runs(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let result = DatabaseDB::new(&data.db).crate_runs(&data.config, &name)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// List of the experiments
+///
+/// The most recently created experiments are returned first. The filters are combined.
+#[utoipa::path(
+    get,
+    path = "/experiments",
+    params(ExperimentsQuery),
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<Vec<ExperimentSummary>>
+        ),
+        (status = 400, response = BadRequest),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiments(query: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let query = ExperimentsQuery::parse(&query)?;
+    let result = ExperimentSummary::list(&data.db, &query)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Show an experiment
+///
+/// Configuration and progress of an experiment. Private experiments return a 404.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<ExperimentDetails>),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let ex = public_experiment(&data.db, &name)?;
+    let result = ExperimentDetails::load(&data.db, &ex)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Paginated results of an experiment
+///
+/// The results are sorted by crate and toolchain. With `format=csv` the page is returned as CSV,
+/// with a header row.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/results",
+    params(("name" = String, Path), ResultsQuery),
+    responses(
+        (status = 200, description = "The request succeeded.", content(
+            (ApiResponse<ExportedPage> = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 400, response = BadRequest),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_results(
+    name: String,
+    query: String,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let query = ResultsQuery::parse(&query)?;
+    let ex = public_experiment(&data.db, &name)?;
+    let page = export::load_page(&data.db, &data.config, &ex, query.page, query.per_page)?;
+
+    match query.format {
+        ExportFormat::Json => Ok(ApiResponse::Success { result: page }.into_response()?),
+        ExportFormat::Csv => {
+            let mut content = Vec::new();
+            export::write_results(&page.results, ExportFormat::Csv, &mut content)?;
+
+            let mut resp = Response::new(content.into());
+            resp.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+            Ok(resp)
+        }
+    }
+}
+
+/// Paginated progress of the crates of an experiment
+///
+/// The crates are sorted by name, and skipped crates are not listed. A crate is `running` while an
+/// agent recorded progress on it in the last five minutes, and `completed` once it has a result for
+/// each toolchain.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/crates",
+    params(("name" = String, Path), CratesQuery),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<CratesPage>),
+        (status = 400, response = BadRequest),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_crates(
+    name: String,
+    query: String,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let query = CratesQuery::parse(&query)?;
+    let ex = public_experiment(&data.db, &name)?;
+    let result = crates::load_crates_page(
+        &data.db,
+        &data.config,
+        &ex,
+        query.status,
+        query.page,
+        query.per_page,
+    )?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Regressions of an experiment
+///
+/// Crates that passed with the first toolchain of the experiment but failed with the second one,
+/// sorted by crate. With `confidence=high` the regressions caused by spurious failures, like
+/// timeouts or running out of memory, are excluded. The regressions matching a known failure of the
+/// server's config are excluded unless `include_known=true` is passed.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/regressions",
+    params(("name" = String, Path), RegressionsQuery),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Vec<Regression>>),
+        (status = 400, response = BadRequest),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_regressions(
+    name: String,
+    query: String,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let query = RegressionsQuery::parse(&query)?;
+    let ex = public_experiment(&data.db, &name)?;
+    let result = export::load_regressions(
+        &data.db,
+        &data.config,
+        &ex,
+        query.confidence,
+        query.include_known,
+    )?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Summary of the report of an experiment
+///
+/// The summary is stored when the report is generated, and updated when the report is regenerated.
+/// Experiments without a report return a 404.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/summary",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<ReportSummary>),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_summary(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    public_experiment(&data.db, &name)?;
+    let result = ReportSummary::load(&data.db, &name)?.ok_or(HttpError::NotFound)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Dependency graph of the crates of an experiment
+///
+/// The direct dependencies of each crate, recorded with `cargo metadata` while preparing the crates
+/// of experiments with `pre_analyze` enabled. Only the crates already prepared are included.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/dependency-graph",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<DependencyGraph>),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_dependency_graph(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let ex = public_experiment(&data.db, &name)?;
+    let result = dependencies::load_dependency_graph(&data.db, &ex)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Dependencies shared by the regressed crates of an experiment
+///
+/// Packages in the resolved dependency trees of at least two regressed crates, which might be the
+/// common cause of their regressions, sorted by the number of crates sharing them. The trees are
+/// recorded with `cargo metadata` while preparing the crates of experiments with `pre_analyze`
+/// enabled. With `confidence=high` the regressions caused by spurious failures are excluded.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/shared-dependencies",
+    params(("name" = String, Path), RegressionsQuery),
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<Vec<SharedDependency>>
+        ),
+        (status = 400, response = BadRequest),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_shared_dependencies(
+    name: String,
+    query: String,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let query = RegressionsQuery::parse(&query)?;
+    let ex = public_experiment(&data.db, &name)?;
+    let regressed = export::load_regressions(
+        &data.db,
+        &data.config,
+        &ex,
+        query.confidence,
+        query.include_known,
+    )?
+    .into_iter()
+    .map(|regression| regression.krate)
+    .collect::<Vec<_>>();
+    let result = dependencies::shared_dependencies(&data.db, &ex, &regressed)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Triage annotations of the crates of an experiment
+///
+/// Annotations left on the crates while triaging the report, sorted by crate.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/annotations",
+    params(("name" = String, Path)),
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<Vec<CrateAnnotation>>
+        ),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_annotations(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let ex = public_experiment(&data.db, &name)?;
+    let result = annotations::load_annotations(&data.db, &ex)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct AnnotationData {
+    status: AnnotationStatus,
+    #[serde(default)]
+    note: String,
+}
+
+/// Annotate a crate of an experiment
+///
+/// Set the triage status and note of a crate, replacing its current annotation. Concurrent edits
+/// are not detected: the last one wins, and its author is recorded. Requires an admin token.
+///
+/// Annotations are shown in the report after it's generated again, or in the live report when the
+/// crate gets new results.
+#[utoipa::path(
+    put,
+    path = "/experiments/{name}/annotations/{crate-id}",
+    params(
+        ("name" = String, Path),
+        ("crate-id" = String, Path, description = CRATE_ID_DESCRIPTION),
+    ),
+    request_body = AnnotationData,
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Annotation>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 413, response = PayloadTooLarge),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("adminToken" = []))
+)]
+fn endpoint_set_annotation(
+    name: String,
+    krate: Tail,
+    body: AnnotationData,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let ex = public_experiment(&data.db, &name)?;
+    let krate = Crate::from_id(krate.as_str()).map_err(|_| HttpError::NotFound)?;
+    if !ex.crates.contains(&krate) {
+        return Err(HttpError::NotFound.into());
+    }
+    if body.note.chars().count() > MAX_NOTE_LENGTH {
+        return Err(HttpError::BadRequest.into());
+    }
+
+    let result =
+        annotations::set_annotation(&data.db, &ex, &krate, body.status, &body.note, &auth.name)?;
+    info!(
+        "{} annotated {} in experiment {} as {}",
+        auth.name, krate, ex.name, body.status
+    );
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Everything needed to run a crate of an experiment again
+///
+/// The configuration of the experiment with only the crate in it, and the commit tested if the
+/// crate is a GitHub repository. Used by `crater reproduce --remote`.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/reproduce/{crate-id}",
+    params(
+        ("name" = String, Path),
+        ("crate-id" = String, Path, description = CRATE_ID_DESCRIPTION),
+    ),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Reproduction>),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_reproduction(
+    name: String,
+    krate: Tail,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    public_experiment(&data.db, &name)?;
+    let krate = Crate::from_id(krate.as_str()).map_err(|_| HttpError::NotFound)?;
+    let result = Reproduction::load(&data.db, &name, &krate)?.ok_or(HttpError::NotFound)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Diagnostics emitted by the compiler while testing a crate
+///
+/// The errors and warnings parsed from the JSON output of the compiler, for each toolchain the crate
+/// has a result with. Results recorded before the output was parsed have no diagnostics.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/diagnostics/{crate-id}",
+    params(
+        ("name" = String, Path),
+        ("crate-id" = String, Path, description = CRATE_ID_DESCRIPTION),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The request succeeded.",
+            body = ApiResponse<Vec<CrateDiagnostics>>
+        ),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_experiment_diagnostics(
+    name: String,
+    krate: Tail,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let ex = public_experiment(&data.db, &name)?;
+    let krate = Crate::from_id(krate.as_str()).map_err(|_| HttpError::NotFound)?;
+    if !ex.crates.contains(&krate) {
+        return Err(HttpError::NotFound.into());
+    }
+    let result = DatabaseDB::new(&data.db).load_diagnostics(&ex, &krate)?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct ReportLink {
+    /// Path of the report on this server, without the host.
+    url: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Request a link to a private report
+///
+/// Generate a link to the report of a completed private experiment, valid for the duration
+/// configured in `private-link-hours`. Requires an agent or read-only token listed in the readers
+/// of the experiment. Public experiments are not found.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/report-link",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<ReportLink>),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("readOnlyToken" = []))
+)]
+fn endpoint_report_link(
+    name: String,
+    data: Arc<Data>,
+    auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let ex = Experiment::get(&data.db, &name)?.ok_or(HttpError::NotFound)?;
+    if !ex.private || ex.status != Status::Completed {
+        return Err(HttpError::NotFound.into());
+    }
+    if !ex.private_readers.contains(&auth.name) {
+        return Err(HttpError::Forbidden.into());
+    }
+
+    let expires_at = private_reports::link_expiry(&data.config, Utc::now());
+    let url = private_reports::signed_path(&data.tokens, &ex.name, expires_at)?;
+    info!(
+        "{} requested a link to the private report of {}",
+        auth.name, ex.name
+    );
+
+    Ok(ApiResponse::Success {
+        result: ReportLink { url, expires_at },
+    }
+    .into_response()?)
+}
+
+/// Crates classified differently by two experiments
+///
+/// Only the crates with the results of both toolchains in both experiments are compared, separately
+/// for each feature set tested by both experiments. The crates classified as `known-failure` by
+/// either experiment are excluded unless `include_known=true` is passed.
+#[utoipa::path(
+    get,
+    path = "/compare/{first}/{second}",
+    params(("first" = String, Path), ("second" = String, Path), RegressionsQuery),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Vec<ChangedCrate>>),
+        (status = 400, response = BadRequest),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_compare(
+    first: String,
+    second: String,
+    query: String,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let query = RegressionsQuery::parse(&query)?;
+    let first = public_experiment(&data.db, &first)?;
+    let second = public_experiment(&data.db, &second)?;
+    let result = DatabaseDB::new(&data.db).changed_crates(
+        &data.config,
+        &first,
+        &second,
+        query.include_known,
+    )?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// List the agents
+///
+/// State of all the agents configured on the server, sorted by name. Requires an agent or read-only
+/// token.
+#[utoipa::path(
+    get,
+    path = "/agents",
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Vec<AgentInfo>>),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("readOnlyToken" = []))
+)]
+fn endpoint_agents(data: Arc<Data>, _auth: AuthDetails) -> Fallible<Response<Body>> {
+    let result = data
+        .agents
+        .all()?
+        .iter()
+        .map(|agent| agent.info())
+        .collect::<Vec<_>>();
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Show an agent
+///
+/// State of a single agent. Requires an agent or read-only token.
+#[utoipa::path(
+    get,
+    path = "/agents/{name}",
+    params(("name" = String, Path)),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<AgentInfo>),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("readOnlyToken" = []))
+)]
+fn endpoint_agent(name: String, data: Arc<Data>, _auth: AuthDetails) -> Fallible<Response<Body>> {
+    let agent = data.agents.get(&name)?.ok_or(HttpError::NotFound)?;
+
+    Ok(ApiResponse::Success {
+        result: agent.info(),
+    }
+    .into_response()?)
+}
+
+/// Estimate the throughput of the agents
+///
+/// Crates built per minute by all the agents and by each of them, computed from the results they
+/// recorded in the last minutes. The results are only kept in memory for an hour, so the estimate
+/// starts over when the server restarts. Requires an agent or read-only token.
+#[utoipa::path(
+    get,
+    path = "/throughput",
+    params((
+        "window" = Option<u32>,
+        Query,
+        minimum = 1,
+        maximum = 60,
+        description = "Minutes the crates are counted over, 10 by default and at most 60."
+    )),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<ThroughputEstimate>),
+        (status = 400, response = BadRequest),
+        (status = 401, response = Unauthorized),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    ),
+    security(("readOnlyToken" = []))
+)]
+fn endpoint_throughput(
+    query: String,
+    data: Arc<Data>,
+    _auth: AuthDetails,
+) -> Fallible<Response<Body>> {
+    let window = parse_throughput_window(&query)?;
+    let result = data.throughput.estimate(window, Utc::now());
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+/// Regression trends across experiments
+///
+/// Outcomes of the public experiments created in the last days, aggregated by the day they were
+/// created and sorted from the oldest day. Days without experiments are skipped.
+#[utoipa::path(
+    get,
+    path = "/trends",
+    params(TrendsQuery),
+    responses(
+        (status = 200, description = "The request succeeded.", body = ApiResponse<Vec<TrendPoint>>),
+        (status = 400, response = BadRequest),
+        (status = 500, response = InternalError),
+        (status = 503, response = RequestTimeout),
+    )
+)]
+fn endpoint_trends(query: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let query = TrendsQuery::parse(&query)?;
+    let result = TrendPoint::load(&data.db, &query, Utc::now())?;
+
+    Ok(ApiResponse::Success { result }.into_response()?)
+}
+
+fn handle_results(resp: Fallible<Response<Body>>) -> Response<Body> {
+    match resp {
+        Ok(resp) => resp,
+        Err(err) => error_response(&err),
+    }
+}
+
+/// JSON response describing the error returned by a handler.
+pub(super) fn error_response(err: &Error) -> Response<Body> {
+    match err.downcast_ref::<HttpError>() {
+        Some(HttpError::NotFound) => ApiResponse::not_found().into_response().unwrap(),
+        Some(HttpError::BadRequest) => ApiResponse::bad_request().into_response().unwrap(),
+        Some(HttpError::Forbidden) => ApiResponse::unauthorized().into_response().unwrap(),
+        _ => ApiResponse::internal_error(err.to_string())
+            .into_response()
+            .unwrap(),
+    }
+}
+
+fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
+    let error = if let Some(compat) = err.find_cause::<Compat<HttpError>>() {
+        Some(*compat.get_ref())
+    } else if let StatusCode::NOT_FOUND = err.status() {
+        Some(HttpError::NotFound)
+    } else if let StatusCode::METHOD_NOT_ALLOWED = err.status() {
+        Some(HttpError::NotFound)
+    } else {
+        None
+    };
+
+    match error {
+        Some(HttpError::NotFound) => Ok(ApiResponse::not_found().into_response().unwrap()),
+        Some(HttpError::Forbidden) => Ok(ApiResponse::unauthorized().into_response().unwrap()),
+        Some(HttpError::PayloadTooLarge) => {
+            Ok(ApiResponse::payload_too_large().into_response().unwrap())
+        }
+        Some(HttpError::RequestTimeout) => {
+            Ok(ApiResponse::request_timeout().into_response().unwrap())
+        }
+        Some(HttpError::BadRequest) => Ok(ApiResponse::bad_request().into_response().unwrap()),
+        None => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        public_experiment, CratesQuery, ExperimentsQuery, RegressionsQuery, ResultsQuery, Summary,
+        TrendPoint, TrendsQuery, DEFAULT_RESULTS_PER_PAGE, DEFAULT_TREND_DAYS,
+        MAX_RESULTS_PER_PAGE,
+    };
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::{Assignee, Experiment, Status};
+    use crate::prelude::*;
+    use crate::results::crates::CrateStatus;
+    use crate::results::export::{Confidence, ExportFormat};
+    use crate::results::{DatabaseDB, FailureReason, TestResult, WriteResults};
+    use crate::server::agents::Agents;
+    use crate::server::api_types::{ExperimentDetails, ExperimentSummary};
+    use crate::server::tokens::Tokens;
+    use crate::server::{openapi, HttpError};
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use chrono::{Duration, Utc};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_summary() {
+        crate::logs::init_test();
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token1".into(), "agent1".into());
+        tokens.agents.insert("token2".into(), "agent2".into());
+        let agents = Agents::new(db.clone(), &tokens).unwrap();
+        agents.record_heartbeat("agent1").unwrap();
+
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+        CreateExperiment::dummy("third").apply(&ctx).unwrap();
+
+        let mut running = Experiment::get(&db, "second").unwrap().unwrap();
+        running.set_status(&db, Status::Running).unwrap();
+
+        let krate = Crate::Registry(RegistryCrate {
+            name: "lazy_static".into(),
+            version: "1".into(),
+        });
+        let results = DatabaseDB::new(&db);
+        results
+            .record_result(&running, &MAIN_TOOLCHAIN, &krate, None, &config, || {
+                Ok(TestResult::TestPass)
+            })
+            .unwrap();
+        results
+            .record_result(&running, &TEST_TOOLCHAIN, &krate, None, &config, || {
+                Ok(TestResult::Error)
+            })
+            .unwrap();
+        let gh = Crate::GitHub(GitHubRepo {
+            org: "rust-lang".into(),
+            name: "rust".into(),
+        });
+        results
+            .record_result(&running, &MAIN_TOOLCHAIN, &gh, None, &config, || {
+                info!("[stderr] error: linking with `cc` failed: exit code: 1");
+                Ok(TestResult::BuildFail(FailureReason::Unknown))
+            })
+            .unwrap();
+
+        assert_eq!(
+            Summary::load(&db).unwrap(),
+            Summary {
+                queued_experiments: 2,
+                running_experiments: 1,
+                completed_experiments: 0,
+                active_agents: 1,
+                crates_tested_today: 2,
+                passed_results: 1,
+                failed_results: 2,
+                failures_by_category: vec![
+                    ("linker-error".to_string(), 1),
+                    ("unknown".to_string(), 1)
+                ]
+                .into_iter()
+                .collect(),
+                queue_depth: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_trends() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let now = Utc::now();
+        let first_day = now - Duration::days(10);
+        let second_day = now - Duration::days(2);
+        for &(name, private, created_at) in &[
+            ("nightly-weekly-1", false, first_day),
+            ("nightly-weekly-2", false, second_day),
+            ("nightly-weekly-3", false, second_day),
+            ("nightly-weekly-old", false, now - Duration::days(200)),
+            ("nightly-weekly-private", true, first_day),
+            ("pr-1", false, first_day),
+        ] {
+            CreateExperiment {
+                private,
+                ..CreateExperiment::dummy(name)
+            }
+            .apply(&ctx)
+            .unwrap();
+            db.execute(
+                "UPDATE experiments SET created_at = ?1 WHERE name = ?2;",
+                &[&created_at, &name],
+            )
+            .unwrap();
+        }
+
+        let results = DatabaseDB::new(&db);
+        let krate = |name: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: "1.0.0".into(),
+            })
+        };
+        let fail = TestResult::BuildFail(FailureReason::Unknown);
+        let pass = TestResult::TestPass;
+        for &(ex, ref krate, first, second) in &[
+            ("nightly-weekly-1", krate("foo"), pass, fail),
+            ("nightly-weekly-1", krate("bar"), pass, pass),
+            ("nightly-weekly-2", krate("foo"), fail, pass),
+            ("nightly-weekly-3", krate("foo"), pass, pass),
+            ("nightly-weekly-old", krate("foo"), pass, fail),
+            ("nightly-weekly-private", krate("foo"), pass, fail),
+            ("pr-1", krate("foo"), pass, fail),
+        ] {
+            let ex = Experiment::get(&db, ex).unwrap().unwrap();
+            results
+                .record_result(&ex, &MAIN_TOOLCHAIN, krate, None, &config, || Ok(first))
+                .unwrap();
+            results
+                .record_result(&ex, &TEST_TOOLCHAIN, krate, None, &config, || Ok(second))
+                .unwrap();
+        }
+
+        // The experiments created on the same day are aggregated together
+        let query = TrendsQuery::parse("experiment_pattern=nightly-weekly-*").unwrap();
+        assert_eq!(query.days, DEFAULT_TREND_DAYS);
+        assert_eq!(
+            TrendPoint::load(&db, &query, now).unwrap(),
+            vec![
+                TrendPoint {
+                    date: first_day.naive_utc().date(),
+                    total_crates: 2,
+                    regressions: 1,
+                    improvements: 0,
+                    regression_rate: 0.5,
+                },
+                TrendPoint {
+                    date: second_day.naive_utc().date(),
+                    total_crates: 2,
+                    regressions: 0,
+                    improvements: 1,
+                    regression_rate: 0.0,
+                },
+            ]
+        );
+
+        let query = TrendsQuery::parse("experiment_pattern=nightly-weekly-*&days=5").unwrap();
+        let points = TrendPoint::load(&db, &query, now).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].date, second_day.naive_utc().date());
+
+        // All the public experiments are included by default
+        let query = TrendsQuery::parse("").unwrap();
+        let points = TrendPoint::load(&db, &query, now).unwrap();
+        assert_eq!(points[0].total_crates, 3);
+        assert_eq!(points[0].regressions, 2);
+
+        assert!(TrendsQuery::parse("days=0").is_err());
+        assert!(TrendsQuery::parse("days=many").is_err());
+    }
+
+    #[test]
+    fn test_results_query() {
+        let query = ResultsQuery::parse("").unwrap();
+        assert_eq!(query.format, ExportFormat::Json);
+        assert_eq!(query.page, 1);
+        assert_eq!(query.per_page, DEFAULT_RESULTS_PER_PAGE);
+
+        let query = ResultsQuery::parse("format=csv&page=3&per-page=20").unwrap();
+        assert_eq!(query.format, ExportFormat::Csv);
+        assert_eq!(query.page, 3);
+        assert_eq!(query.per_page, 20);
+
+        // Huge pages are capped
+        let query = ResultsQuery::parse("per-page=1000000").unwrap();
+        assert_eq!(query.per_page, MAX_RESULTS_PER_PAGE);
+
+        assert!(ResultsQuery::parse("format=xml").is_err());
+        assert!(ResultsQuery::parse("page=0").is_err());
+        assert!(ResultsQuery::parse("per-page=many").is_err());
+    }
+
+    #[test]
+    fn test_crates_query() {
+        let query = CratesQuery::parse("").unwrap();
+        assert_eq!(query.status, None);
+        assert_eq!(query.page, 1);
+        assert_eq!(query.per_page, DEFAULT_RESULTS_PER_PAGE);
+
+        let query = CratesQuery::parse("status=pending&page=2&per-page=50").unwrap();
+        assert_eq!(query.status, Some(CrateStatus::Pending));
+        assert_eq!(query.page, 2);
+        assert_eq!(query.per_page, 50);
+
+        assert!(CratesQuery::parse("status=unknown").is_err());
+        assert!(CratesQuery::parse("page=0").is_err());
+    }
+
+    #[test]
+    fn test_regressions_query() {
+        let query = RegressionsQuery::parse("").unwrap();
+        assert_eq!(query.confidence, Confidence::Low);
+        assert!(!query.include_known);
+
+        let query = RegressionsQuery::parse("confidence=high&include_known=true").unwrap();
+        assert_eq!(query.confidence, Confidence::High);
+        assert!(query.include_known);
+
+        assert!(RegressionsQuery::parse("confidence=certain").is_err());
+        assert!(RegressionsQuery::parse("include_known=yes").is_err());
+    }
+
+    #[test]
+    fn test_experiments_filters() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment {
+            requested_by: Some("alice".into()),
+            ..CreateExperiment::dummy("first")
+        }
+        .apply(&ctx)
+        .unwrap();
+        CreateExperiment {
+            requested_by: Some("bob".into()),
+            ..CreateExperiment::dummy("second")
+        }
+        .apply(&ctx)
+        .unwrap();
+        CreateExperiment::dummy("third").apply(&ctx).unwrap();
+        CreateExperiment {
+            requested_by: Some("alice".into()),
+            private: true,
+            ..CreateExperiment::dummy("private")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        let names = |query| {
+            let query = ExperimentsQuery::parse(query).unwrap();
+            let mut names = ExperimentSummary::list(&db, &query)
+                .unwrap()
+                .into_iter()
+                .map(|ex| ex.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        // Private experiments are never listed
+        assert_eq!(names(""), vec!["first", "second", "third"]);
+        assert_eq!(names("requested_by=alice"), vec!["first"]);
+        assert!(names("requested_by=carol").is_empty());
+
+        // Filters are combined
+        let mut second = Experiment::get(&db, "second").unwrap().unwrap();
+        second.set_status(&db, Status::Running).unwrap();
+        second
+            .set_assigned_to(&db, Some(&Assignee::Agent("agent-1".into())))
+            .unwrap();
+        assert_eq!(names("status=queued"), vec!["first", "third"]);
+        assert_eq!(names("assignee=agent%3Aagent-1"), vec!["second"]);
+        assert!(names("status=queued&assignee=agent%3Aagent-1").is_empty());
+        assert!(ExperimentsQuery::parse("status=sleeping").is_err());
+
+        // The list and the details of an experiment agree on its progress
+        DatabaseDB::new(&db)
+            .record_result(
+                &second,
+                &MAIN_TOOLCHAIN,
+                &second.crates[0],
+                None,
+                &config,
+                || Ok(TestResult::TestPass),
+            )
+            .unwrap();
+        let listed =
+            ExperimentSummary::list(&db, &ExperimentsQuery::parse("status=running").unwrap())
+                .unwrap()
+                .remove(0);
+        let details = ExperimentDetails::load(&db, &second).unwrap();
+        assert_eq!(details.summary.crates, listed.crates);
+        assert_eq!(details.completed_jobs, 1);
+        assert_eq!(details.total_jobs, listed.crates * 2);
+        assert_eq!(details.summary.progress, listed.progress);
+        assert_eq!(listed.progress, second.progress(&db).unwrap());
+        assert_eq!(
+            details.summary.assigned_to.as_ref().unwrap(),
+            "agent:agent-1"
+        );
+    }
+
+    #[test]
+    fn test_public_experiment() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("public").apply(&ctx).unwrap();
+        CreateExperiment {
+            private: true,
+            ..CreateExperiment::dummy("private")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        assert_eq!(public_experiment(&db, "public").unwrap().name, "public");
+        for name in &["private", "missing"] {
+            let err = public_experiment(&db, name).unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&HttpError::NotFound));
+        }
+    }
+
+    #[test]
+    fn test_openapi_spec() {
+        let spec: serde_json::Value = serde_json::from_str(&openapi::SPEC).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3.1."));
+
+        // Every operation has a unique ID and only requires the tokens it describes
+        let mut operation_ids = HashSet::new();
+        for item in spec["paths"].as_object().unwrap().values() {
+            for operation in item.as_object().unwrap().values() {
+                let id = operation["operationId"].as_str().unwrap();
+                assert!(operation_ids.insert(id), "duplicate operation {}", id);
+                for requirement in operation["security"].as_array().into_iter().flatten() {
+                    for scheme in requirement.as_object().unwrap().keys() {
+                        assert!(
+                            spec["components"]["securitySchemes"][scheme].is_object(),
+                            "missing security scheme {}",
+                            scheme
+                        );
+                    }
+                }
+            }
+        }
+
+        // All the references point to existing components
+        let spec_str = spec.to_string();
+        for reference in spec_str.split("\"$ref\":\"#/components/").skip(1) {
+            let reference = &reference[..reference.find('"').unwrap()];
+            let mut parts = reference.split('/');
+            let (kind, name) = (parts.next().unwrap(), parts.next().unwrap());
+            assert!(
+                spec["components"][kind][name].is_object(),
+                "missing component {}",
+                reference
+            );
+        }
+    }
+}
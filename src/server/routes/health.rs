@@ -0,0 +1,292 @@
+use crate::db::{Database, QueryUtils};
+use crate::prelude::*;
+use crate::server::api_types::ApiResponse;
+use crate::server::circuit_breaker::CircuitStatus;
+use crate::server::Data;
+use chrono::{DateTime, Utc};
+use http::Response;
+use hyper::Body;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use warp::{self, Filter, Rejection};
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+struct Health {
+    /// State of the circuit breaker of the GitHub API calls.
+    github: CircuitStatus,
+}
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    warp::get2()
+        .and(warp::path::end())
+        .and(data_filter)
+        .map(endpoint_health)
+}
+
+/// Status of the services crater depends on
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "The request succeeded.", body = ApiResponse<Health>))
+)]
+pub(in crate::server) fn endpoint_health(data: Arc<Data>) -> Response<Body> {
+    let result = Health {
+        github: data.github.circuit_breaker().status(),
+    };
+
+    ApiResponse::Success { result }.into_response().unwrap()
+}
+
+/// Liveness and readiness probes, served at `/healthz` and `/readyz` for Kubernetes.
+pub fn probes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    let healthz = warp::get2()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .map(endpoint_healthz);
+
+    let readyz = warp::get2()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(data_filter)
+        .map(endpoint_readyz);
+
+    healthz.or(readyz).unify()
+}
+
+/// Liveness probe
+///
+/// Checks that a trivial query to the database succeeds (`database`).
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses(
+        (
+            status = 200,
+            description = "All the checks passed, and the result maps their names to `ok`.",
+            body = ApiResponse<BTreeMap<String, String>>
+        ),
+        (
+            status = 503,
+            description = "At least one check failed, and the error lists their names.",
+            body = ApiResponse<BTreeMap<String, String>>
+        ),
+    )
+)]
+pub(in crate::server) fn endpoint_healthz(data: Arc<Data>) -> Response<Body> {
+    probe_response(&liveness_checks(&data))
+}
+
+/// Readiness probe
+///
+/// Checks that the database is usable (`database`), that all the migrations were applied
+/// (`migrations`), that the reports generator thread is alive (`reports-worker`) and that the
+/// GitHub credentials were validated when the server started (`github`).
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (
+            status = 200,
+            description = "All the checks passed, and the result maps their names to `ok`.",
+            body = ApiResponse<BTreeMap<String, String>>
+        ),
+        (
+            status = 503,
+            description = "At least one check failed, and the error lists their names.",
+            body = ApiResponse<BTreeMap<String, String>>
+        ),
+    )
+)]
+pub(in crate::server) fn endpoint_readyz(data: Arc<Data>) -> Response<Body> {
+    probe_response(&readiness_checks(&data, Utc::now()))
+}
+
+/// Whether a trivial query succeeds.
+fn database_usable(db: &Database) -> bool {
+    match db.exists("SELECT 1;", &[]) {
+        Ok(_) => true,
+        Err(err) => {
+            warn!("the database health check failed: {}", err);
+            false
+        }
+    }
+}
+
+/// Whether all the migrations known by this version of crater, and only them, were applied.
+fn migrations_applied(db: &Database) -> bool {
+    match db.schema_status() {
+        Ok(status) => status.pending.is_empty() && status.unknown.is_empty(),
+        Err(err) => {
+            warn!("the migrations health check failed: {}", err);
+            false
+        }
+    }
+}
+
+/// Checks passed by a process that doesn't need to be restarted.
+fn liveness_checks(data: &Data) -> Vec<(&'static str, bool)> {
+    vec![("database", database_usable(&data.db))]
+}
+
+/// Checks passed by a server able to handle all the requests at `now`.
+fn readiness_checks(data: &Data, now: DateTime<Utc>) -> Vec<(&'static str, bool)> {
+    vec![
+        ("database", database_usable(&data.db)),
+        ("migrations", migrations_applied(&data.db)),
+        ("reports-worker", data.reports_worker.is_alive(now)),
+        ("github", data.github_validated),
+    ]
+}
+
+/// Only the names of the failed checks are returned, as the errors could contain secrets like the
+/// URL of the database.
+fn probe_response(checks: &[(&'static str, bool)]) -> Response<Body> {
+    let failed = checks
+        .iter()
+        .filter(|(_, passed)| !passed)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    if failed.is_empty() {
+        let result = checks
+            .iter()
+            .map(|(name, _)| (*name, "ok"))
+            .collect::<BTreeMap<_, _>>();
+        ApiResponse::Success { result }.into_response().unwrap()
+    } else {
+        ApiResponse::service_unavailable(format!("failed checks: {}", failed.join(", ")))
+            .into_response()
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{liveness_checks, probe_response, readiness_checks};
+    use crate::config::Config;
+    use crate::db::{Database, QueryUtils};
+    use crate::server::github::GitHubApi;
+    use crate::server::tokens::Tokens;
+    use crate::server::Data;
+    use chrono::{Duration, Utc};
+    use http::StatusCode;
+
+    fn failed(checks: &[(&'static str, bool)]) -> Vec<&'static str> {
+        checks
+            .iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    fn ready_data() -> Data {
+        let config = Config::default();
+        let mut tokens = Tokens::default();
+        tokens.bot.api_token = "github-token".into();
+        let github = GitHubApi::new(&config.github, &tokens).unwrap();
+        let mut data = Data::new(
+            config,
+            Database::temp().unwrap(),
+            tokens,
+            github,
+            "crater-bot".into(),
+        )
+        .unwrap();
+        data.github_validated = true;
+        data.reports_worker.beat();
+        data
+    }
+
+    #[test]
+    fn test_all_checks_pass() {
+        let data = ready_data();
+        let now = Utc::now();
+        assert!(failed(&liveness_checks(&data)).is_empty());
+        assert!(failed(&readiness_checks(&data, now)).is_empty());
+        assert_eq!(
+            probe_response(&readiness_checks(&data, now)).status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn test_database_down() {
+        let mut data = ready_data();
+        data.db = Database::unreachable();
+        let now = Utc::now();
+
+        let checks = liveness_checks(&data);
+        assert_eq!(failed(&checks), vec!["database"]);
+        assert_eq!(
+            probe_response(&checks).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            failed(&readiness_checks(&data, now)),
+            vec!["database", "migrations"]
+        );
+    }
+
+    #[test]
+    fn test_migrations_pending() {
+        let data = ready_data();
+        data.db
+            .execute(
+                "DELETE FROM migrations WHERE name = ?1;",
+                &[&"create_queued_messages_table"],
+            )
+            .unwrap();
+
+        // The process is still alive, but shouldn't receive requests
+        assert!(failed(&liveness_checks(&data)).is_empty());
+        assert_eq!(
+            failed(&readiness_checks(&data, Utc::now())),
+            vec!["migrations"]
+        );
+    }
+
+    #[test]
+    fn test_reports_worker_stuck() {
+        let data = ready_data();
+        let later = Utc::now() + Duration::hours(3);
+        assert_eq!(
+            failed(&readiness_checks(&data, later)),
+            vec!["reports-worker"]
+        );
+
+        // The worker never started
+        let mut data = ready_data();
+        data.reports_worker = Default::default();
+        assert_eq!(
+            failed(&readiness_checks(&data, Utc::now())),
+            vec!["reports-worker"]
+        );
+    }
+
+    #[test]
+    fn test_github_not_validated() {
+        let mut data = ready_data();
+        data.github_validated = false;
+        let checks = readiness_checks(&data, Utc::now());
+        assert_eq!(failed(&checks), vec!["github"]);
+        assert!(failed(&liveness_checks(&data)).is_empty());
+        assert_eq!(
+            probe_response(&checks).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod admin;
 pub mod agent;
+pub mod api;
+pub mod health;
+pub mod private_reports;
+pub mod progress;
 pub mod ui;
 pub mod webhooks;
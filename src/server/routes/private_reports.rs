@@ -0,0 +1,71 @@
+use crate::server::private_reports;
+use crate::server::Data;
+use chrono::Utc;
+use http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use hyper::Body;
+use std::sync::Arc;
+use warp::{self, path::Tail, Filter, Rejection};
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    warp::get2()
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::tail())
+        .and(data_filter)
+        .map(endpoint_file)
+}
+
+fn error(status: StatusCode, message: &'static str) -> Response<Body> {
+    let mut resp = Response::new(format!("{}\n", message).into());
+    *resp.status_mut() = status;
+    resp
+}
+
+fn endpoint_file(
+    name: String,
+    expires: i64,
+    signature: String,
+    path: Tail,
+    data: Arc<Data>,
+) -> Response<Body> {
+    let result = private_reports::verify(&data.tokens, &name, expires, &signature, Utc::now())
+        .and_then(|valid| {
+            if valid {
+                private_reports::read(&data.config, &data.tokens, &name, path.as_str()).map(Some)
+            } else {
+                Ok(None)
+            }
+        });
+
+    match result {
+        Ok(Some(Some(content))) => {
+            let mut resp = Response::new(content.into());
+            resp.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(private_reports::content_type(path.as_str())),
+            );
+            // Proxies must not keep serving the report after the link expired
+            resp.headers_mut()
+                .insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+            resp
+        }
+        Ok(Some(None)) => error(StatusCode::NOT_FOUND, "404: Not Found"),
+        Ok(None) => error(
+            StatusCode::FORBIDDEN,
+            "403: The link is invalid or expired, request a new one",
+        ),
+        Err(err) => {
+            crate::utils::report_failure(&err);
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "500: Internal Server Error",
+            )
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::server::api_types::{NotFound, Unauthorized};
+use crate::server::events::ProgressEvent;
+use crate::server::routes::api;
+use crate::server::{cors, Data, HttpError};
+use failure::{Compat, Error};
+use futures::sync::mpsc::UnboundedReceiver;
+use futures::{Future, Sink, Stream};
+use http::header::HeaderMap;
+use http::Response;
+use hyper::Body;
+use serde_json;
+use std::sync::Arc;
+use utoipa::OpenApi;
+use warp::ws::{Message, WebSocket, Ws2};
+use warp::{self, Filter, Rejection, Reply};
+
+/// Websocket streaming the progress of an experiment. The errors are returned with the same JSON
+/// responses as the other endpoints of the public API.
+pub fn routes(data: Arc<Data>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    warp::get2()
+        .and(warp::path("experiments"))
+        .and(warp::path::param())
+        .and(warp::path("progress"))
+        .and(warp::path::end())
+        .and(warp::header::headers_cloned())
+        .and(data_filter.clone())
+        .and_then(|name: String, headers: HeaderMap, data: Arc<Data>| {
+            match check_subscription(&data, &name, &headers) {
+                Ok(_) => Ok(name),
+                Err(err) => Err(warp::reject::custom(err.compat())),
+            }
+        })
+        .and(warp::ws2())
+        .and(data_filter)
+        .map(endpoint_progress)
+        .recover(handle_errors)
+}
+
+/// Endpoints of the public API served as websockets, relative to its prefix.
+#[derive(OpenApi)]
+#[openapi(paths(endpoint_progress))]
+pub struct ApiDoc;
+
+/// Websocket notified of every new result
+///
+/// Upgrades the connection to a websocket. Each result recorded for the experiment is sent as a
+/// text message containing a JSON object with the crate ID, the toolchain and the result.
+#[utoipa::path(
+    get,
+    path = "/experiments/{name}/progress",
+    params(("name" = String, Path)),
+    responses(
+        (status = 101, description = "The connection was upgraded to a websocket."),
+        (status = 401, response = Unauthorized),
+        (status = 404, response = NotFound),
+    )
+)]
+fn endpoint_progress(name: String, ws: Ws2, data: Arc<Data>) -> impl Reply {
+    let events = data.progress_events.subscribe(&name);
+    ws.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+/// Check the client is allowed to follow the experiment. Private experiments are hidden like in
+/// the rest of the public API.
+fn check_subscription(data: &Data, name: &str, headers: &HeaderMap) -> Fallible<Experiment> {
+    if !cors::websocket_allowed(data, headers) {
+        return Err(HttpError::Forbidden.into());
+    }
+    api::public_experiment(&data.db, name)
+}
+
+fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
+    match err.find_cause::<Compat<Error>>() {
+        Some(compat) => Ok(api::error_response(compat.get_ref())),
+        None => Err(err),
+    }
+}
+
+fn stream_events(
+    socket: WebSocket,
+    events: UnboundedReceiver<ProgressEvent>,
+) -> impl Future<Item = (), Error = ()> {
+    let (sink, stream) = socket.split();
+
+    let send = events
+        .and_then(|event| {
+            serde_json::to_string(&event)
+                .map(Message::text)
+                .map_err(|err| error!("failed to serialize a progress event: {}", err))
+        })
+        .forward(sink.sink_map_err(|err| debug!("failed to send a progress event: {}", err)))
+        .map(|_| ());
+
+    // Messages from the client are ignored, but reading them is needed to notice it went away
+    let recv = stream
+        .for_each(|_| Ok(()))
+        .map_err(|err| debug!("progress websocket closed: {}", err));
+
+    send.select(recv).map(|_| ()).map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::results::TestResult;
+    use crate::server::testing::TestServer;
+    use crate::server::tokens::Tokens;
+    use crate::toolchain::MAIN_TOOLCHAIN;
+    use base64;
+    use reqwest::header::{AUTHORIZATION, ORIGIN};
+    use reqwest::{Client, StatusCode};
+    use serde_json::{self, json, Value};
+    use std::time::Duration;
+    use tungstenite::{self, Message};
+    use url::Url;
+
+    #[test]
+    fn test_progress_websocket() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy")
+            .apply(&ActionsCtx::new(&db, &config))
+            .unwrap();
+        let krate = Experiment::get(&db, "dummy").unwrap().unwrap().crates[0].clone();
+
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("agent-token".into(), "agent".into());
+        let server = TestServer::start(config, db, tokens).unwrap();
+        let client = Client::new();
+        let progress_url =
+            |name: &str| format!("{}/api/v1/experiments/{}/progress", server.url(), name);
+
+        // The errors are returned as JSON, like in the rest of the API
+        let mut resp = client.get(&progress_url("missing")).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.json::<Value>().unwrap()["status"], "not-found");
+        let resp = client
+            .get(&progress_url("dummy"))
+            .header(ORIGIN, "https://evil.example.com")
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let url = Url::parse(&progress_url("dummy").replacen("http", "ws", 1)).unwrap();
+        let (mut socket, _) = tungstenite::connect(url).unwrap();
+        socket
+            .get_mut()
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        // Simulate an agent recording the result of a crate
+        let agent_request = |request: reqwest::RequestBuilder| {
+            let resp = request
+                .header(AUTHORIZATION, "CraterToken agent-token")
+                .send()
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        };
+        agent_request(client.get(&format!("{}/agent-api/v1/next-experiment", server.url())));
+        agent_request(
+            client
+                .post(&format!("{}/agent-api/v1/record-progress", server.url()))
+                .json(&json!({
+                    "results": [
+                        {
+                            "crate": krate,
+                            "toolchain": MAIN_TOOLCHAIN.clone(),
+                            "result": TestResult::TestPass,
+                            "log": base64::encode("log"),
+                        },
+                    ],
+                    "shas": [],
+                })),
+        );
+
+        let event: Value = match socket.read_message().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("unexpected message: {:?}", message),
+        };
+        assert_eq!(
+            event,
+            json!({
+                "crate": krate.id(),
+                "toolchain": MAIN_TOOLCHAIN.to_string(),
+                "result": TestResult::TestPass,
+            })
+        );
+    }
+}
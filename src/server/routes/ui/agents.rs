@@ -1,5 +1,6 @@
 use crate::prelude::*;
 use crate::server::agents::AgentStatus;
+use crate::server::api_types::HostInfo;
 use crate::server::routes::ui::{render_template, LayoutContext};
 use crate::server::Data;
 use chrono::SecondsFormat;
@@ -12,9 +13,11 @@ struct AgentData {
     name: String,
     status_class: &'static str,
     status_pretty: &'static str,
+    unhealthy: Option<String>,
     last_heartbeat: Option<String>,
     assigned_experiment: Option<String>,
     git_revision: Option<String>,
+    host: Option<HostInfo>,
 }
 
 #[derive(Serialize)]
@@ -26,9 +29,11 @@ struct ListContext {
 pub fn endpoint_list(data: Arc<Data>) -> Fallible<Response<Body>> {
     let mut agents = Vec::new();
     for agent in &data.agents.all()? {
-        let (status_class, status_pretty, show_assigned) = match agent.status() {
+        let status = agent.status();
+        let (status_class, status_pretty, show_assigned) = match status {
             AgentStatus::Working => ("orange", "Working", true),
             AgentStatus::Idle => ("green", "Online", false),
+            AgentStatus::Unhealthy => ("red", "Unhealthy", true),
             AgentStatus::Unreachable => ("red", "Unreachable", false),
         };
 
@@ -36,6 +41,11 @@ pub fn endpoint_list(data: Arc<Data>) -> Fallible<Response<Body>> {
             name: agent.name().to_string(),
             status_class,
             status_pretty,
+            unhealthy: if status == AgentStatus::Unhealthy {
+                agent.unhealthy().cloned()
+            } else {
+                None
+            },
             last_heartbeat: agent
                 .last_heartbeat()
                 .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true)),
@@ -45,6 +55,7 @@ pub fn endpoint_list(data: Arc<Data>) -> Fallible<Response<Body>> {
                 None
             },
             git_revision: agent.git_revision().cloned(),
+            host: agent.host().cloned(),
         });
     }
 
@@ -0,0 +1,83 @@
+use crate::prelude::*;
+use crate::report::Comparison;
+use crate::results::DatabaseDB;
+use crate::server::routes::ui::{render_template, LayoutContext};
+use crate::server::Data;
+use chrono::SecondsFormat;
+use http::Response;
+use hyper::Body;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct ResultData {
+    name: String,
+    log_url: String,
+}
+
+#[derive(Serialize)]
+struct RunData {
+    experiment: String,
+    created_at: String,
+    #[serde(rename = "crate")]
+    krate: String,
+    toolchains: [String; 2],
+    results: Vec<Option<ResultData>>,
+    category: &'static str,
+    category_class: &'static str,
+}
+
+#[derive(Serialize)]
+struct HistoryContext {
+    layout: LayoutContext,
+    name: String,
+    runs: Vec<RunData>,
+}
+
+fn category_class(category: Comparison) -> &'static str {
+    match category {
+        Comparison::Regressed => "red",
+        Comparison::Fixed => "green",
+        Comparison::SpuriousRegressed | Comparison::SpuriousFixed | Comparison::Error => "orange",
+        _ => "",
+    }
+}
+
+pub fn endpoint_history(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let runs = DatabaseDB::new(&data.db)
+        .crate_runs(&data.config, &name)?
+        .into_iter()
+        .map(|run| {
+            let results = run
+                .results
+                .iter()
+                .zip(&run.toolchains)
+                .map(|(result, toolchain)| {
+                    result.map(|result| ResultData {
+                        name: result.to_string(),
+                        log_url: format!("/ex/{}/log/{}/{}", run.experiment, toolchain, run.krate),
+                    })
+                })
+                .collect();
+            RunData {
+                created_at: run
+                    .experiment_created_at
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                category: run.category.to_str(),
+                category_class: category_class(run.category),
+                experiment: run.experiment,
+                krate: run.krate,
+                toolchains: run.toolchains,
+                results,
+            }
+        })
+        .collect();
+
+    render_template(
+        "ui/crate.html",
+        &HistoryContext {
+            layout: LayoutContext::new(),
+            name,
+            runs,
+        },
+    )
+}
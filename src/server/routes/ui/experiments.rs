@@ -1,12 +1,24 @@
 use crate::experiments::{Experiment, Mode, Status};
 use crate::prelude::*;
+use crate::report::write_all_logs;
+use crate::results::annotations::{self, AnnotationStatus};
+use crate::results::{DatabaseDB, ReadResults, ResultsSummary};
 use crate::server::routes::ui::{render_template, LayoutContext};
 use crate::server::{Data, HttpError};
+use crate::toolchain::Toolchain;
 use chrono::{Duration, SecondsFormat, Utc};
 use chrono_humanize::{Accuracy, HumanTime, Tense};
-use http::Response;
+use futures::sink::Wait;
+use futures::sync::mpsc;
+use futures::{Sink, Stream};
+use http::header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE, RETRY_AFTER};
+use http::{Response, StatusCode};
 use hyper::Body;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
+use warp::path::Tail;
 
 #[derive(Serialize)]
 struct ExperimentData {
@@ -106,6 +118,7 @@ struct ExperimentExt {
 
     github_url: Option<String>,
     report_url: Option<String>,
+    cargo_patches: Vec<String>,
 
     created_at: String,
     started_at: Option<String>,
@@ -116,6 +129,28 @@ struct ExperimentExt {
     duration: Option<String>,
     estimated_end: Option<String>,
     average_job_duration: Option<String>,
+
+    in_progress: Vec<InProgressBuild>,
+    summary: ResultsSummary,
+
+    annotations: Vec<AnnotationRow>,
+    annotation_statuses: &'static [&'static str],
+}
+
+#[derive(Serialize)]
+struct InProgressBuild {
+    krate: String,
+    toolchain: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct AnnotationRow {
+    krate: String,
+    status: &'static str,
+    note: String,
+    author: String,
+    updated_at: String,
 }
 
 #[derive(Serialize)]
@@ -125,7 +160,8 @@ struct ExperimentContext {
 }
 
 pub fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
-    if let Some(ex) = Experiment::get(&data.db, &name)? {
+    // The results and annotations of private experiments are only published in their report
+    if let Some(ex) = Experiment::get(&data.db, &name)?.filter(|ex| !ex.private) {
         let (completed_jobs, total_jobs) = ex.raw_progress(&data.db)?;
 
         let (duration, estimated_end, average_job_duration) = if completed_jobs > 0
@@ -166,11 +202,36 @@ pub fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<B
             (None, None, None)
         };
 
+        let results = DatabaseDB::new(&data.db);
+        let summary = results.results_summary(&ex)?;
+        let in_progress = results
+            .in_progress_builds(&ex)?
+            .into_iter()
+            .map(|(krate, toolchain)| InProgressBuild {
+                url: format!("/ex/{}/log/{}/{}", ex.name, toolchain, krate.id()),
+                krate: krate.to_string(),
+                toolchain: toolchain.to_string(),
+            })
+            .collect();
+        let annotations = annotations::load_annotations(&data.db, &ex)?
+            .into_iter()
+            .map(|annotation| AnnotationRow {
+                krate: annotation.krate,
+                status: annotation.status.to_str(),
+                note: annotation.note,
+                author: annotation.author,
+                updated_at: annotation
+                    .updated_at
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            })
+            .collect();
+
         let experiment = ExperimentExt {
             common: ExperimentData::new(&data, &ex)?,
 
             github_url: ex.github_issue.map(|i| i.html_url.clone()),
             report_url: ex.report_url.clone(),
+            cargo_patches: ex.cargo_patches.iter().map(|p| p.to_string()).collect(),
 
             created_at: ex.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
             started_at: ex
@@ -185,6 +246,12 @@ pub fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<B
             duration,
             estimated_end,
             average_job_duration,
+
+            in_progress,
+            summary,
+
+            annotations,
+            annotation_statuses: AnnotationStatus::possible_values(),
         };
 
         render_template(
@@ -198,3 +265,172 @@ pub fn endpoint_experiment(name: String, data: Arc<Data>) -> Fallible<Response<B
         Err(HttpError::NotFound.into())
     }
 }
+
+#[derive(Serialize)]
+struct LogContext {
+    layout: LayoutContext,
+    experiment: String,
+    krate: String,
+    toolchain: String,
+    log: String,
+    in_progress: bool,
+}
+
+pub fn endpoint_log(
+    name: String,
+    toolchain: String,
+    krate: Tail,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    // Logs of private experiments are only published in their report
+    let ex = Experiment::get(&data.db, &name)?
+        .filter(|ex| !ex.private)
+        .ok_or(HttpError::NotFound)?;
+    let toolchain: Toolchain = toolchain.parse().map_err(|_| HttpError::NotFound)?;
+    let krate = ex
+        .crates
+        .iter()
+        .find(|c| c.id() == krate.as_str())
+        .ok_or(HttpError::NotFound)?;
+
+    let db = DatabaseDB::new(&data.db);
+    let (log, in_progress) = if let Some(log) = db.load_log(&ex, &toolchain, krate)? {
+        (log, false)
+    } else if let Some(log) = db.load_partial_log(&ex, &toolchain, krate)? {
+        (log, true)
+    } else {
+        return Err(HttpError::NotFound.into());
+    };
+
+    render_template(
+        "ui/log.html",
+        &LogContext {
+            layout: LayoutContext::new(),
+            experiment: ex.name.clone(),
+            krate: krate.to_string(),
+            toolchain: toolchain.to_string(),
+            log: String::from_utf8_lossy(&log).into_owned(),
+            in_progress,
+        },
+    )
+}
+
+/// Size of the chunks the logs archive is streamed in.
+const ARCHIVE_CHUNK_SIZE: usize = 64 * 1024;
+/// Number of chunks generated ahead of the client before the generation blocks.
+const ARCHIVE_CHUNKS_BUFFERED: usize = 16;
+/// Number of logs archives streamed at the same time, as each one is generated by its own thread
+/// reading all the logs of the experiment.
+const MAX_ARCHIVE_STREAMS: usize = 4;
+/// Seconds the clients are asked to wait before downloading the archive again when too many are
+/// being streamed.
+const ARCHIVE_RETRY_AFTER_SECS: &str = "60";
+
+/// Slot of a logs archive being streamed, released when it's dropped.
+struct ArchiveStream(Arc<AtomicUsize>);
+
+impl ArchiveStream {
+    fn acquire(streams: &Arc<AtomicUsize>) -> Option<Self> {
+        if streams.fetch_add(1, Ordering::SeqCst) >= MAX_ARCHIVE_STREAMS {
+            streams.fetch_sub(1, Ordering::SeqCst);
+            None
+        } else {
+            Some(ArchiveStream(streams.clone()))
+        }
+    }
+}
+
+impl Drop for ArchiveStream {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Writer sending everything written to it as the chunks of a streamed response body.
+struct ChunksWriter(Wait<mpsc::Sender<Vec<u8>>>);
+
+impl Write for ChunksWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0
+            .flush()
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the client disconnected"))
+    }
+}
+
+pub fn endpoint_logs_archive(name: String, data: Arc<Data>) -> Fallible<Response<Body>> {
+    // Logs of private experiments are only published in their report
+    let ex = Experiment::get(&data.db, &name)?
+        .filter(|ex| !ex.private)
+        .ok_or(HttpError::NotFound)?;
+    let disposition =
+        HeaderValue::from_str(&format!("attachment; filename=\"{}-logs.tar.gz\"", ex.name))?;
+
+    let stream = match ArchiveStream::acquire(&data.archive_streams) {
+        Some(stream) => stream,
+        None => {
+            let mut resp = Response::new(
+                "Too many logs archives are being downloaded, try again later.\n".into(),
+            );
+            *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            resp.headers_mut().insert(
+                RETRY_AFTER,
+                HeaderValue::from_static(ARCHIVE_RETRY_AFTER_SECS),
+            );
+            return Ok(resp);
+        }
+    };
+
+    // The archive is generated while it's sent, to avoid keeping the logs of all the crates in
+    // memory at the same time
+    let (sender, receiver) = mpsc::channel(ARCHIVE_CHUNKS_BUFFERED);
+    let db = data.db.clone();
+    thread::spawn(move || {
+        // The slot is released once the client received the archive or disconnected
+        let _stream = stream;
+        let writer = BufWriter::with_capacity(ARCHIVE_CHUNK_SIZE, ChunksWriter(sender.wait()));
+        let result = write_all_logs(&DatabaseDB::new(&db), &ex, writer)
+            .and_then(|mut writer| Ok(writer.flush()?));
+        if let Err(err) = result {
+            crate::utils::report_failure(
+                &err.context(format!("failed to stream the logs of {}", ex.name)),
+            );
+        }
+    });
+
+    let body = receiver.map_err(|()| io::Error::from(io::ErrorKind::BrokenPipe));
+    let mut resp = Response::new(Body::wrap_stream(body));
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/gzip"));
+    resp.headers_mut().insert(CONTENT_DISPOSITION, disposition);
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveStream, MAX_ARCHIVE_STREAMS};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_archive_streams_limit() {
+        let streams = Arc::new(AtomicUsize::new(0));
+        let mut acquired = (0..MAX_ARCHIVE_STREAMS)
+            .map(|_| ArchiveStream::acquire(&streams).unwrap())
+            .collect::<Vec<_>>();
+        assert!(ArchiveStream::acquire(&streams).is_none());
+        assert_eq!(streams.load(Ordering::SeqCst), MAX_ARCHIVE_STREAMS);
+
+        // Finishing a stream lets another one start
+        acquired.pop();
+        assert!(ArchiveStream::acquire(&streams).is_some());
+        drop(acquired);
+        assert_eq!(streams.load(Ordering::SeqCst), 0);
+    }
+}
@@ -9,7 +9,9 @@ use std::sync::Arc;
 use warp::{self, Filter, Rejection};
 
 mod agents;
+mod crates;
 mod experiments;
+mod usage;
 
 #[derive(Serialize)]
 struct LayoutContext {
@@ -41,12 +43,42 @@ pub fn routes(
         .and(data_filter.clone())
         .map(experiments::endpoint_experiment);
 
+    let log = warp::get2()
+        .and(warp::path("ex"))
+        .and(warp::path::param())
+        .and(warp::path("log"))
+        .and(warp::path::param())
+        .and(warp::path::tail())
+        .and(data_filter.clone())
+        .map(experiments::endpoint_log);
+
+    let logs_archive = warp::get2()
+        .and(warp::path("ex"))
+        .and(warp::path::param())
+        .and(warp::path("logs.tar.gz"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .map(experiments::endpoint_logs_archive);
+
+    let crate_history = warp::get2()
+        .and(warp::path("crate"))
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .map(crates::endpoint_history);
+
     let agents = warp::get2()
         .and(warp::path("agents"))
         .and(warp::path::end())
         .and(data_filter.clone())
         .map(agents::endpoint_list);
 
+    let usage = warp::get2()
+        .and(warp::path("usage"))
+        .and(warp::path::end())
+        .and(data_filter.clone())
+        .map(usage::endpoint_usage);
+
     let assets = warp::get2()
         .and(warp::path("assets"))
         .and(warp::path::param())
@@ -58,8 +90,16 @@ pub fn routes(
             queue
                 .or(experiment)
                 .unify()
+                .or(log)
+                .unify()
+                .or(logs_archive)
+                .unify()
+                .or(crate_history)
+                .unify()
                 .or(agents)
                 .unify()
+                .or(usage)
+                .unify()
                 .or(assets)
                 .unify(),
         )
@@ -0,0 +1,63 @@
+use crate::db::QueryUtils;
+use crate::experiments::Status;
+use crate::prelude::*;
+use crate::results::DatabaseDB;
+use crate::server::routes::ui::{render_template, LayoutContext};
+use crate::server::Data;
+use crate::utils::size::format_size;
+use http::Response;
+use hyper::Body;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct UsageData {
+    experiment: String,
+    results: u64,
+    raw_size: String,
+    compressed_size: String,
+    stored_size: String,
+    share: String,
+    can_purge: bool,
+}
+
+#[derive(Serialize)]
+struct UsageContext {
+    layout: LayoutContext,
+    experiments: Vec<UsageData>,
+}
+
+pub fn endpoint_usage(data: Arc<Data>) -> Fallible<Response<Body>> {
+    let completed = data
+        .db
+        .query(
+            "SELECT name FROM experiments WHERE status = ?1;",
+            &[&Status::Completed.to_str()],
+            |row| row.get::<_, String>("name"),
+        )?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let experiments = DatabaseDB::new(&data.db)
+        .usage()?
+        .into_iter()
+        .map(|usage| UsageData {
+            results: usage.results,
+            raw_size: format_size(usage.raw_size),
+            compressed_size: format_size(usage.compressed_size),
+            stored_size: format_size(usage.stored_size),
+            share: format!("{:.1}%", usage.share * 100.0),
+            // Purged experiments don't have anything left to purge
+            can_purge: completed.contains(&usage.experiment) && usage.stored_size > 0,
+            experiment: usage.experiment,
+        })
+        .collect();
+
+    render_template(
+        "ui/usage.html",
+        &UsageContext {
+            layout: LayoutContext::new(),
+            experiments,
+        },
+    )
+}
@@ -1,4 +1,4 @@
-use crate::experiments::{CapLints, CrateSelect, Mode};
+use crate::experiments::{CapLints, CrateSelect, Mode, TestRunner};
 use crate::toolchain::Toolchain;
 
 #[derive(Debug, Fail)]
@@ -110,6 +110,15 @@ generate_parser!(pub enum Command {
         cap_lints: Option<CapLints> = "cap-lints",
         priority: Option<i32> = "p",
         ignore_blacklist: Option<bool> = "ignore-blacklist",
+        fail_fast: Option<bool> = "fail-fast",
+        live_report: Option<bool> = "live-report",
+        pre_analyze: Option<bool> = "pre-analyze",
+        private: Option<bool> = "private",
+        test_runner: Option<TestRunner> = "test-runner",
+        measure_artifacts: Option<bool> = "measure-artifacts",
+        silent: Option<bool> = "silent",
+        allow_duplicate: Option<bool> = "allow-duplicate",
+        dry_run: Option<bool> = "dry-run",
     })
 
     "abort" => Abort(AbortArgs {
@@ -122,8 +131,17 @@ generate_parser!(pub enum Command {
         name: Option<String> = "name",
     })
 
+    "regenerate-report" => RegenerateReport(RegenerateReportArgs {
+        name: Option<String> = "name",
+    })
+
     "reload-acl" => ReloadACL(ReloadACLArgs {})
 
+    "compare" => Compare(CompareArgs {
+        name: Option<String> = "name",
+        base: Option<String> = "base",
+    })
+
     _ => Edit(EditArgs {
         name: Option<String> = "name",
         start: Option<Toolchain> = "start",
@@ -133,6 +151,13 @@ generate_parser!(pub enum Command {
         cap_lints: Option<CapLints> = "cap-lints",
         priority: Option<i32> = "p",
         ignore_blacklist: Option<bool> = "ignore-blacklist",
+        fail_fast: Option<bool> = "fail-fast",
+        live_report: Option<bool> = "live-report",
+        pre_analyze: Option<bool> = "pre-analyze",
+        private: Option<bool> = "private",
+        test_runner: Option<TestRunner> = "test-runner",
+        measure_artifacts: Option<bool> = "measure-artifacts",
+        silent: Option<bool> = "silent",
     })
 });
 
@@ -1,10 +1,15 @@
 use crate::actions::{self, Action, ActionsCtx};
 use crate::db::{Database, QueryUtils};
-use crate::experiments::{CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status};
+use crate::experiments::{
+    CapLints, CrateSelect, Experiment, FeatureSet, GitHubIssue, Mode, Status,
+};
 use crate::prelude::*;
 use crate::server::github::Issue;
 use crate::server::messages::{Label, Message};
-use crate::server::routes::webhooks::args::{AbortArgs, EditArgs, RetryReportArgs, RunArgs};
+use crate::server::reports;
+use crate::server::routes::webhooks::args::{
+    AbortArgs, CompareArgs, EditArgs, RegenerateReportArgs, RetryReportArgs, RunArgs,
+};
 use crate::server::Data;
 
 pub fn ping(data: &Data, issue: &Issue) -> Fallible<()> {
@@ -15,10 +20,16 @@ pub fn ping(data: &Data, issue: &Issue) -> Fallible<()> {
     Ok(())
 }
 
-pub fn run(host: &str, data: &Data, issue: &Issue, args: RunArgs) -> Fallible<()> {
-    let name = setup_run_name(&data.db, issue, args.name)?;
+pub fn run(host: &str, data: &Data, issue: &Issue, sender: &str, args: RunArgs) -> Fallible<()> {
+    // Dry runs don't store anything, not even the name of the experiment
+    let dry_run = args.dry_run.unwrap_or(false);
+    let name = match args.name {
+        Some(name) if dry_run => name,
+        None if dry_run => generate_new_experiment_name(&data.db, issue)?,
+        name => setup_run_name(&data.db, issue, name)?,
+    };
 
-    actions::CreateExperiment {
+    let create = actions::CreateExperiment {
         name: name.clone(),
         toolchains: [
             args.start
@@ -27,6 +38,8 @@ pub fn run(host: &str, data: &Data, issue: &Issue, args: RunArgs) -> Fallible<()
         ],
         mode: args.mode.unwrap_or(Mode::BuildAndTest),
         crates: args.crates.unwrap_or(CrateSelect::Full),
+        crate_list: None,
+        pinned_crates: Vec::new(),
         cap_lints: args.cap_lints.unwrap_or(CapLints::Forbid),
         priority: args.priority.unwrap_or(0),
         github_issue: Some(GitHubIssue {
@@ -35,8 +48,36 @@ pub fn run(host: &str, data: &Data, issue: &Issue, args: RunArgs) -> Fallible<()
             number: issue.number,
         }),
         ignore_blacklist: args.ignore_blacklist.unwrap_or(false),
+        fail_fast: args.fail_fast.unwrap_or(false),
+        feature_sets: vec![FeatureSet::Default],
+        requested_by: Some(sender.to_string()),
+        live_report: args.live_report.unwrap_or(false),
+        cargo_patches: Vec::new(),
+        pre_analyze: args.pre_analyze.unwrap_or(false),
+        private: args.private.unwrap_or(false),
+        private_readers: Vec::new(),
+        test_runner: args.test_runner,
+        measure_artifacts: args.measure_artifacts.unwrap_or(false),
+        silent: args.silent.unwrap_or(false),
+        allow_duplicate: args.allow_duplicate.unwrap_or(false),
+    };
+    let ctx = ActionsCtx::new(&data.db, &data.config);
+
+    if dry_run {
+        let dry_run = create.dry_run(&ctx)?;
+        Message::new()
+            .line(
+                "mag",
+                format!(
+                    "Experiment **`{}`** is valid, nothing was created. This is the experiment that would run:",
+                    name
+                ),
+            )
+            .section(format!("```toml\n{}```", dry_run.to_toml()?))
+            .send(&issue.url, data)?;
+        return Ok(());
     }
-    .apply(&ActionsCtx::new(&data.db, &data.config))?;
+    create.apply(&ctx)?;
 
     Message::new()
         .line(
@@ -67,6 +108,16 @@ pub fn edit(data: &Data, issue: &Issue, args: EditArgs) -> Fallible<()> {
         cap_lints: args.cap_lints,
         priority: args.priority,
         ignore_blacklist: args.ignore_blacklist,
+        fail_fast: args.fail_fast,
+        feature_sets: None,
+        live_report: args.live_report,
+        cargo_patches: None,
+        pre_analyze: args.pre_analyze,
+        private: args.private,
+        private_readers: None,
+        test_runner: args.test_runner,
+        measure_artifacts: args.measure_artifacts,
+        silent: args.silent,
     }
     .apply(&ActionsCtx::new(&data.db, &data.config))?;
 
@@ -92,6 +143,7 @@ pub fn retry_report(data: &Data, issue: &Issue, args: RetryReportArgs) -> Fallib
         }
 
         experiment.set_status(&data.db, Status::NeedsReport)?;
+        data.report_events.publish(&experiment, None, None);
         data.reports_worker.wake();
 
         Message::new()
@@ -108,6 +160,27 @@ pub fn retry_report(data: &Data, issue: &Issue, args: RetryReportArgs) -> Fallib
     }
 }
 
+pub fn regenerate_report(data: &Data, issue: &Issue, args: RegenerateReportArgs) -> Fallible<()> {
+    let name = get_name(&data.db, issue, args.name)?;
+
+    actions::RegenerateReport { name: name.clone() }
+        .apply(&ActionsCtx::new(&data.db, &data.config))?;
+    data.reports_worker.wake();
+
+    Message::new()
+        .line(
+            "hammer_and_wrench",
+            format!(
+                "Regeneration of the report for **`{}`** queued: it will replace the current \
+                 report once it's generated.",
+                name
+            ),
+        )
+        .send(&issue.url, data)?;
+
+    Ok(())
+}
+
 pub fn abort(data: &Data, issue: &Issue, args: AbortArgs) -> Fallible<()> {
     let name = get_name(&data.db, issue, args.name)?;
 
@@ -132,6 +205,43 @@ pub fn reload_acl(data: &Data, issue: &Issue) -> Fallible<()> {
     Ok(())
 }
 
+pub fn compare(data: &Data, issue: &Issue, args: CompareArgs) -> Fallible<()> {
+    let name = get_name(&data.db, issue, args.name)?;
+    let base = args
+        .base
+        .ok_or_else(|| err_msg("missing experiment to compare with"))?;
+
+    let completed = |name: &str| -> Fallible<Experiment> {
+        match Experiment::get(&data.db, name)? {
+            Some(ref ex) if ex.status != Status::Completed => bail!(
+                "the **`{}`** experiment is not completed yet (status: {})!",
+                name,
+                ex.status
+            ),
+            Some(ref ex) if ex.private => bail!(
+                "the **`{}`** experiment is private, and can't be compared!",
+                name
+            ),
+            Some(ex) => Ok(ex),
+            None => bail!("an experiment named **`{}`** doesn't exist!", name),
+        }
+    };
+    let (base, ex) = (completed(&base)?, completed(&name)?);
+
+    Message::new()
+        .line(
+            "hourglass",
+            format!(
+                "Comparing **`{}`** with **`{}`**, the report will be posted here.",
+                ex.name, base.name
+            ),
+        )
+        .send(&issue.url, data)?;
+    reports::spawn_comparison(data, base, ex, issue.url.clone());
+
+    Ok(())
+}
+
 fn get_name(db: &Database, issue: &Issue, name: Option<String>) -> Fallible<String> {
     if let Some(name) = name {
         store_experiment_name(db, issue, &name)?;
@@ -5,15 +5,16 @@ use crate::prelude::*;
 use crate::server::github::{EventIssueComment, Issue};
 use crate::server::messages::Message;
 use crate::server::routes::webhooks::args::Command;
-use crate::server::Data;
-use bytes::buf::Buf;
+use crate::server::{body, Data, HttpError};
+use failure::Compat;
 use http::{HeaderMap, Response, StatusCode};
 use hyper::Body;
 use ring;
 use serde_json;
 use std::str::FromStr;
 use std::sync::Arc;
-use warp::{self, filters::body::FullBody, Filter, Rejection};
+use utoipa::OpenApi;
+use warp::{self, Filter, Rejection};
 
 fn process_webhook(
     payload: &[u8],
@@ -36,6 +37,14 @@ fn process_webhook(
                 return Ok(());
             }
 
+            // Replies are posted to the URL in the payload, with the credentials of the bot
+            if !data.github.is_api_url(&p.issue.url) {
+                bail!(
+                    "the issue {} is not served by the configured GitHub API",
+                    p.issue.url
+                );
+            }
+
             if let Err(e) = process_command(host, &p.sender.login, &p.comment.body, &p.issue, data)
             {
                 Message::new()
@@ -100,7 +109,7 @@ fn process_command(
             }
 
             Command::Run(args) => {
-                commands::run(host, data, issue, args)?;
+                commands::run(host, data, issue, sender, args)?;
             }
 
             Command::Edit(args) => {
@@ -111,6 +120,10 @@ fn process_command(
                 commands::retry_report(data, issue, args)?;
             }
 
+            Command::RegenerateReport(args) => {
+                commands::regenerate_report(data, issue, args)?;
+            }
+
             Command::Abort(args) => {
                 commands::abort(data, issue, args)?;
             }
@@ -118,6 +131,10 @@ fn process_command(
             Command::ReloadACL(_) => {
                 commands::reload_acl(data, issue)?;
             }
+
+            Command::Compare(args) => {
+                commands::compare(data, issue, args)?;
+            }
         }
 
         break;
@@ -164,7 +181,7 @@ fn verify_signature(secret: &str, payload: &[u8], raw_signature: &str) -> bool {
     ring::hmac::verify(&key, payload, &signature).is_ok()
 }
 
-fn receive_endpoint(data: Arc<Data>, headers: HeaderMap, body: FullBody) -> Fallible<()> {
+fn receive_endpoint(data: Arc<Data>, headers: HeaderMap, body: Vec<u8>) -> Fallible<()> {
     let signature = headers
         .get("X-Hub-Signature")
         .and_then(|h| h.to_str().ok())
@@ -178,32 +195,101 @@ fn receive_endpoint(data: Arc<Data>, headers: HeaderMap, body: FullBody) -> Fall
         .and_then(|h| h.to_str().ok())
         .ok_or_else(|| err_msg("missing header Host\n"))?;
 
-    process_webhook(body.bytes(), host, signature, event, &data)
+    process_webhook(&body, host, signature, event, &data)
 }
 
 pub fn routes(
     data: Arc<Data>,
 ) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let limit = data.config.server.body_limits.webhooks.to_bytes() as u64;
+    let request_body = body::bytes(data.clone());
     let data_filter = warp::any().map(move || data.clone());
 
     warp::post2()
         .and(warp::path::end())
         .and(data_filter)
         .and(warp::header::headers_cloned())
-        .and(warp::body::concat())
-        .map(|data: Arc<Data>, headers: HeaderMap, body: FullBody| {
-            let mut resp: Response<Body>;
-            match receive_endpoint(data, headers, body) {
-                Ok(()) => resp = Response::new("OK\n".into()),
-                Err(err) => {
-                    error!("error while processing webhook");
-                    crate::utils::report_failure(&err);
-
-                    resp = Response::new(format!("Error: {}\n", err).into());
-                    *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-                }
-            }
+        .and(warp::body::content_length_limit(limit))
+        .and(request_body)
+        .map(endpoint_webhooks)
+        .recover(handle_errors)
+        .unify()
+}
+
+/// Endpoint receiving the webhooks, relative to its prefix.
+#[derive(OpenApi)]
+#[openapi(paths(endpoint_webhooks))]
+pub struct ApiDoc;
+
+/// GitHub webhooks
+///
+/// Receives the events of the GitHub repositories the bot is installed on. Requests are
+/// authenticated with the `X-Hub-Signature` header, computed with the webhooks secret.
+#[utoipa::path(
+    post,
+    path = "",
+    params(
+        ("X-GitHub-Event" = String, Header),
+        ("X-Hub-Signature" = String, Header),
+    ),
+    request_body(content = Object, description = "The payload of the event, sent by GitHub."),
+    responses(
+        (
+            status = 200,
+            description = "The event was processed.",
+            body = String,
+            content_type = "text/plain"
+        ),
+        (
+            status = 413,
+            description = "The payload exceeds the limit configured in `server.body-limits`.",
+            body = String,
+            content_type = "text/plain"
+        ),
+        (
+            status = 500,
+            description = "The signature is missing or invalid, or processing the event failed.",
+            body = String,
+            content_type = "text/plain"
+        ),
+        (
+            status = 503,
+            description = "Receiving the payload took longer than the limit configured in \
+                           `server.timeouts`.",
+            body = String,
+            content_type = "text/plain"
+        ),
+    )
+)]
+fn endpoint_webhooks(data: Arc<Data>, headers: HeaderMap, body: Vec<u8>) -> Response<Body> {
+    let mut resp: Response<Body>;
+    match receive_endpoint(data, headers, body) {
+        Ok(()) => resp = Response::new("OK\n".into()),
+        Err(err) => {
+            error!("error while processing webhook");
+            crate::utils::report_failure(&err);
+
+            resp = Response::new(format!("Error: {}\n", err).into());
+            *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    resp
+}
+
+fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
+    let (error, status) = if let StatusCode::PAYLOAD_TOO_LARGE = err.status() {
+        (HttpError::PayloadTooLarge, StatusCode::PAYLOAD_TOO_LARGE)
+    } else if let Some(HttpError::RequestTimeout) = err
+        .find_cause::<Compat<HttpError>>()
+        .map(|compat| *compat.get_ref())
+    {
+        (HttpError::RequestTimeout, StatusCode::SERVICE_UNAVAILABLE)
+    } else {
+        return Err(err);
+    };
 
-            resp
-        })
+    let mut resp = Response::new(format!("Error: {}\n", error).into());
+    *resp.status_mut() = status;
+    Ok(resp)
 }
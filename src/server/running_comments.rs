@@ -0,0 +1,216 @@
+use crate::db::{Database, QueryUtils};
+use crate::experiments::{Assignee, Experiment};
+use crate::prelude::*;
+use crate::server::messages::Message;
+use crate::server::Data;
+use chrono::{DateTime, Duration, Utc};
+
+/// Percentages of completed builds announced by editing the "now running" comment.
+const MILESTONES: &[u32] = &[50, 90];
+/// Number of recent experiments of an agent used to estimate its throughput.
+const THROUGHPUT_HISTORY: u32 = 10;
+
+/// Builds completed per second, given the start, the end and the number of builds of some runs.
+fn throughput(runs: &[(DateTime<Utc>, DateTime<Utc>, u32)]) -> Option<f64> {
+    let (secs, builds) = runs
+        .iter()
+        .fold((0, 0), |(secs, builds), &(start, end, count)| {
+            (secs + (end - start).num_seconds(), builds + count)
+        });
+
+    if secs > 0 && builds > 0 {
+        Some(f64::from(builds) / secs as f64)
+    } else {
+        None
+    }
+}
+
+fn agent_throughput(db: &Database, agent: &Assignee) -> Fallible<Option<f64>> {
+    let runs = db.query(
+        "SELECT experiments.started_at, experiments.completed_at, COUNT(*) AS builds \
+         FROM experiments JOIN results ON results.experiment = experiments.name \
+         WHERE experiments.assigned_to = ?1 \
+         AND experiments.started_at IS NOT NULL AND experiments.completed_at IS NOT NULL \
+         GROUP BY experiments.name, experiments.started_at, experiments.completed_at \
+         ORDER BY experiments.completed_at DESC LIMIT ?2;",
+        &[&agent.to_string(), &THROUGHPUT_HISTORY],
+        |row| {
+            (
+                row.get("started_at"),
+                row.get("completed_at"),
+                row.get("builds"),
+            )
+        },
+    )?;
+
+    Ok(throughput(&runs))
+}
+
+fn estimate_completion(
+    now: DateTime<Utc>,
+    remaining: u32,
+    throughput: Option<f64>,
+) -> Option<DateTime<Utc>> {
+    throughput.map(|throughput| {
+        now + Duration::seconds((f64::from(remaining) / throughput).ceil() as i64)
+    })
+}
+
+/// Highest milestone reached by the experiment that wasn't announced yet.
+fn next_milestone(done: u32, total: u32, announced: u32) -> Option<u32> {
+    if total == 0 {
+        return None;
+    }
+
+    let percent = u64::from(done) * 100 / u64::from(total);
+    MILESTONES
+        .iter()
+        .rev()
+        .find(|&&milestone| u64::from(milestone) <= percent && milestone > announced)
+        .cloned()
+}
+
+fn message(
+    ex: &Experiment,
+    agent: &str,
+    total: u32,
+    milestone: Option<u32>,
+    eta: Option<DateTime<Utc>>,
+) -> Message {
+    let crates = total / (ex.build_toolchains().len() as u32).max(1);
+    let mut message = Message::new()
+        .line(
+            "construction",
+            format!(
+                "Experiment **`{}`** is now **running** on agent `{}`.",
+                ex.name, agent,
+            ),
+        )
+        .line("package", format!("**{}** crates will be tested.", crates));
+
+    if let Some(milestone) = milestone {
+        message = message.line(
+            "hourglass_flowing_sand",
+            format!("**{}%** of the crates are done.", milestone),
+        );
+    }
+    if let Some(eta) = eta {
+        message = message.line(
+            "alarm_clock",
+            format!(
+                "The experiment should be completed around **{}**.",
+                eta.format("%Y-%m-%d %H:%M UTC")
+            ),
+        );
+    }
+
+    message
+}
+
+/// Announce the experiment started running on the agent, with an estimate of when it's going to
+/// be completed based on the previous experiments of the agent.
+pub fn post(data: &Data, ex: &Experiment, agent: &str) -> Fallible<()> {
+    let github_issue = match ex.notification_issue() {
+        Some(github_issue) => github_issue,
+        None => return Ok(()),
+    };
+
+    let (done, total) = ex.raw_progress(&data.db)?;
+    let throughput = agent_throughput(&data.db, &Assignee::Agent(agent.into()))?;
+    let eta = estimate_completion(Utc::now(), total.saturating_sub(done), throughput);
+
+    let posted = message(ex, agent, total, None, eta).post(&github_issue.api_url, data)?;
+    if let Some(comment_url) = posted {
+        data.db.transaction(|t| {
+            t.execute(
+                "DELETE FROM running_comments WHERE experiment = ?1;",
+                &[&ex.name],
+            )?;
+            t.execute(
+                "INSERT INTO running_comments (experiment, comment_url, milestone) \
+                 VALUES (?1, ?2, 0);",
+                &[&ex.name, &comment_url],
+            )?;
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Edit the comment posted when the experiment started running once it reaches a milestone,
+/// updating the estimate with the progress of this run.
+pub fn update(data: &Data, ex: &Experiment, agent: &str) -> Fallible<()> {
+    let comment = data.db.get_row(
+        "SELECT comment_url, milestone FROM running_comments WHERE experiment = ?1;",
+        &[&ex.name],
+        |row| {
+            (
+                row.get::<_, String>("comment_url"),
+                row.get::<_, u32>("milestone"),
+            )
+        },
+    )?;
+    let (comment_url, announced) = match comment {
+        Some(comment) => comment,
+        None => return Ok(()),
+    };
+
+    let (done, total) = ex.raw_progress(&data.db)?;
+    let milestone = match next_milestone(done, total, announced) {
+        Some(milestone) => milestone,
+        None => return Ok(()),
+    };
+
+    // Mark the milestone as announced first, to avoid editing the comment multiple times
+    data.db.execute(
+        "UPDATE running_comments SET milestone = ?1 WHERE experiment = ?2;",
+        &[&milestone, &ex.name],
+    )?;
+
+    let now = Utc::now();
+    let throughput = ex
+        .started_at
+        .and_then(|started_at| throughput(&[(started_at, now, done)]));
+    let eta = estimate_completion(now, total.saturating_sub(done), throughput);
+    message(ex, agent, total, Some(milestone), eta).edit(&comment_url, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_completion, next_milestone, throughput};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_estimate_completion() {
+        let start = Utc.ymd(2019, 1, 1).and_hms(0, 0, 0);
+        let hours = |h| start + Duration::hours(h);
+
+        // The throughput is averaged over all the runs
+        let runs = [(start, hours(1), 3600), (hours(2), hours(5), 3600)];
+        assert_eq!(throughput(&runs), Some(0.5));
+        assert_eq!(
+            estimate_completion(start, 3600, throughput(&runs)),
+            Some(hours(2))
+        );
+
+        // Without history there is no estimate
+        assert_eq!(throughput(&[]), None);
+        assert_eq!(throughput(&[(start, start, 10)]), None);
+        assert_eq!(estimate_completion(start, 3600, None), None);
+    }
+
+    #[test]
+    fn test_next_milestone() {
+        assert_eq!(next_milestone(10, 100, 0), None);
+        assert_eq!(next_milestone(50, 100, 0), Some(50));
+        assert_eq!(next_milestone(60, 100, 50), None);
+        assert_eq!(next_milestone(95, 100, 50), Some(90));
+        assert_eq!(next_milestone(100, 100, 90), None);
+
+        // Skipped milestones are not announced separately
+        assert_eq!(next_milestone(95, 100, 0), Some(90));
+
+        assert_eq!(next_milestone(0, 0, 0), None);
+    }
+}
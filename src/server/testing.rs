@@ -0,0 +1,78 @@
+//! Crater server running in the tests, on an ephemeral port of localhost.
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::prelude::*;
+use crate::server::github::GitHubApi;
+use crate::server::tokens::Tokens;
+use crate::server::{routes, Data};
+use futures::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+pub(crate) struct TestServer {
+    config: Config,
+    db: Database,
+    tokens: Tokens,
+    addr: SocketAddr,
+    runtime: Option<Runtime>,
+}
+
+impl TestServer {
+    /// Start the server, allowing the agents in the tokens to connect to it.
+    pub(crate) fn start(config: Config, db: Database, mut tokens: Tokens) -> Fallible<Self> {
+        // The GitHub API is never called, but the client needs a token
+        tokens.bot.api_token = "github-token".into();
+        let mut server = TestServer {
+            config,
+            db,
+            tokens,
+            addr: ([127, 0, 0, 1], 0).into(),
+            runtime: None,
+        };
+        server.spawn()?;
+        Ok(server)
+    }
+
+    fn spawn(&mut self) -> Fallible<()> {
+        let data = Data::new(
+            self.config.clone(),
+            self.db.clone(),
+            self.tokens.clone(),
+            GitHubApi::new(&self.config.github, &self.tokens)?,
+            "crater-bot".into(),
+        )?;
+
+        // The first server picks the port, and the restarted ones reuse it
+        let (addr, server) = warp::serve(routes(Arc::new(data))).bind_ephemeral(self.addr);
+        let mut runtime = Runtime::new()?;
+        runtime.spawn(server);
+        self.addr = addr;
+        self.runtime = Some(runtime);
+        Ok(())
+    }
+
+    pub(crate) fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Stop the server, dropping the requests being processed.
+    pub(crate) fn stop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_now().wait().unwrap();
+        }
+    }
+
+    /// Start the server again on the same port, with the same database.
+    pub(crate) fn restart(&mut self) -> Fallible<()> {
+        self.stop();
+        self.spawn()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
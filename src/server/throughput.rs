@@ -0,0 +1,144 @@
+//! Estimate of how fast the agents are building crates, from the results they recently recorded.
+
+use crate::server::api_types::{AgentThroughput, ThroughputEstimate};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Results recorded before this are forgotten, so it's also the longest window of the estimates.
+pub const MAX_WINDOW_MINUTES: u32 = 60;
+pub const DEFAULT_WINDOW_MINUTES: u32 = 10;
+
+struct Record {
+    at: DateTime<Utc>,
+    agent: String,
+    crates: u64,
+}
+
+/// Results recorded by the agents in the last hour, only kept in memory: the estimate starts
+/// over when the server restarts.
+#[derive(Clone, Default)]
+pub struct Throughput {
+    records: Arc<Mutex<VecDeque<Record>>>,
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Throughput::default()
+    }
+
+    /// Count the crates built by the agent, with the results it just recorded.
+    pub fn record(&self, agent: &str, crates: usize, at: DateTime<Utc>) {
+        if crates == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock().unwrap();
+        records.push_back(Record {
+            at,
+            agent: agent.to_string(),
+            crates: crates as u64,
+        });
+
+        let cutoff = at - Duration::minutes(i64::from(MAX_WINDOW_MINUTES));
+        while records.front().map(|r| r.at < cutoff).unwrap_or(false) {
+            records.pop_front();
+        }
+    }
+
+    /// Crates built in the minutes before `now`, by all the agents and by each of them.
+    pub fn estimate(&self, window_minutes: u32, now: DateTime<Utc>) -> ThroughputEstimate {
+        let window_minutes = window_minutes.max(1).min(MAX_WINDOW_MINUTES);
+        let cutoff = now - Duration::minutes(i64::from(window_minutes));
+        let per_minute = |crates: u64| crates as f64 / f64::from(window_minutes);
+
+        let mut agents = BTreeMap::new();
+        for record in self.records.lock().unwrap().iter() {
+            if record.at >= cutoff && record.at <= now {
+                *agents.entry(record.agent.clone()).or_insert(0) += record.crates;
+            }
+        }
+
+        let crates = agents.values().sum();
+        ThroughputEstimate {
+            window_minutes,
+            crates,
+            crates_per_minute: per_minute(crates),
+            agents: agents
+                .into_iter()
+                .map(|(name, crates)| AgentThroughput {
+                    name,
+                    crates,
+                    crates_per_minute: per_minute(crates),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Throughput;
+    use crate::server::api_types::{AgentThroughput, ThroughputEstimate};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_throughput() {
+        let throughput = Throughput::new();
+        let start = Utc.ymd(2019, 1, 1).and_hms(12, 0, 0);
+        let at = |minutes| start + Duration::minutes(minutes);
+
+        // Results recorded by two agents over 20 minutes
+        throughput.record("agent-1", 2, at(0));
+        for minute in 10..20 {
+            throughput.record("agent-1", 3, at(minute));
+            throughput.record("agent-2", 1, at(minute));
+        }
+        // Requests without any stored result
+        throughput.record("agent-3", 0, at(15));
+
+        assert_eq!(
+            throughput.estimate(10, at(20)),
+            ThroughputEstimate {
+                window_minutes: 10,
+                crates: 40,
+                crates_per_minute: 4.0,
+                agents: vec![
+                    AgentThroughput {
+                        name: "agent-1".into(),
+                        crates: 30,
+                        crates_per_minute: 3.0,
+                    },
+                    AgentThroughput {
+                        name: "agent-2".into(),
+                        crates: 10,
+                        crates_per_minute: 1.0,
+                    },
+                ],
+            }
+        );
+
+        // The first results are only counted in the longer windows
+        let estimate = throughput.estimate(40, at(20));
+        assert_eq!(estimate.crates, 42);
+        assert_eq!(estimate.agents[0].crates, 32);
+
+        // The window can't be longer than the results are kept
+        assert_eq!(throughput.estimate(1000, at(20)).window_minutes, 60);
+
+        // Agents which stopped recording results are not listed anymore
+        assert_eq!(
+            throughput.estimate(10, at(60)),
+            ThroughputEstimate {
+                window_minutes: 10,
+                crates: 0,
+                crates_per_minute: 0.0,
+                agents: Vec::new(),
+            }
+        );
+
+        // The old results are forgotten
+        throughput.record("agent-2", 1, at(90));
+        assert_eq!(throughput.records.lock().unwrap().len(), 1);
+    }
+}
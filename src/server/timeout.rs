@@ -0,0 +1,132 @@
+//! Deadlines of the requests, preventing slow clients and slow handlers from holding the server's
+//! resources indefinitely. The deadline is chosen by the kind of token the caller sent, as agents
+//! upload large payloads and expect their requests to complete.
+
+use crate::config::Config;
+use crate::prelude::*;
+use crate::server::auth::{caller_token_type, TokenType};
+use crate::server::{Data, HttpError};
+use futures::Future;
+use futures_cpupool::CpuPool;
+use http::HeaderMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::util::FutureExt;
+use warp::{self, Filter, Rejection};
+
+/// Number of threads running the handlers, which block while accessing the database.
+pub(in crate::server) const HANDLER_THREADS: usize = 16;
+
+/// Wrap a handler into a callback for `Filter::and_then`, running it with the `Deadline` extracted
+/// after the handler's arguments. The arguments are listed with their types:
+///
+/// ```ignore
+/// .and(timeout::caller_deadline(data.clone()))
+/// .and_then(with_deadline!(endpoint_config(data: Arc<Data>, auth: AuthDetails)))
+/// ```
+macro_rules! with_deadline {
+    ($handler:ident($($arg:ident: $ty:ty),* $(,)*)) => {
+        |$($arg: $ty,)* deadline: crate::server::timeout::Deadline| {
+            deadline.run(move || $handler($($arg),*))
+        }
+    };
+}
+
+/// Time the caller has to send the request and get a response, depending on the kind of token it
+/// sent, if any. Read-only tokens don't grant more time than anonymous requests.
+pub(in crate::server) fn duration(config: &Config, token: Option<TokenType>) -> Duration {
+    let timeouts = &config.server.timeouts;
+    Duration::from_secs(match token {
+        Some(TokenType::Agent) | Some(TokenType::Admin) => timeouts.agent,
+        Some(TokenType::ReadOnly) | None => timeouts.anonymous,
+    })
+}
+
+#[derive(Clone)]
+pub(in crate::server) struct Deadline {
+    pub(in crate::server) duration: Duration,
+    handlers: CpuPool,
+}
+
+impl Deadline {
+    /// Run the handler in the thread pool of the server, rejecting the request with
+    /// `HttpError::RequestTimeout` if it doesn't complete before the deadline. The handler keeps
+    /// running in the background, but the response is sent and the connection is released.
+    pub(in crate::server) fn run<T, F>(self, handler: F) -> impl Future<Item = T, Error = Rejection>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.handlers
+            .spawn_fn(move || Ok::<_, ()>(handler()))
+            .timeout(self.duration)
+            .map_err(|_| warp::reject::custom(HttpError::RequestTimeout.compat()))
+    }
+}
+
+/// Extract the deadline of the request from the token the caller sent.
+pub(in crate::server) fn caller_deadline(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Deadline,), Error = Rejection> + Clone {
+    warp::header::headers_cloned().map(move |headers: HeaderMap| Deadline {
+        duration: duration(&data.config, caller_token_type(&data, &headers)),
+        handlers: data.handlers.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{duration, Deadline};
+    use crate::config::Config;
+    use crate::server::auth::TokenType;
+    use crate::server::HttpError;
+    use failure::Compat;
+    use futures_cpupool::CpuPool;
+    use std::thread;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_duration() {
+        let mut config = Config::default();
+        config.server.timeouts.agent = 30;
+        config.server.timeouts.anonymous = 10;
+
+        assert_eq!(
+            duration(&config, Some(TokenType::Agent)),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            duration(&config, Some(TokenType::Admin)),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            duration(&config, Some(TokenType::ReadOnly)),
+            Duration::from_secs(10)
+        );
+        assert_eq!(duration(&config, None), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut runtime = Runtime::new().unwrap();
+        let deadline = Deadline {
+            duration: Duration::from_millis(200),
+            handlers: CpuPool::new(1),
+        };
+
+        // Handlers completing in time return their result
+        let result = runtime.block_on(deadline.clone().run(|| 42)).ok();
+        assert_eq!(result, Some(42));
+
+        // Slow handlers are rejected with a timeout
+        let err = runtime
+            .block_on(deadline.run(|| thread::sleep(Duration::from_secs(2))))
+            .unwrap_err();
+        assert_eq!(
+            err.find_cause::<Compat<HttpError>>()
+                .map(|err| *err.get_ref()),
+            Some(HttpError::RequestTimeout)
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use crate::prelude::*;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use rusoto_core::Region;
 use rusoto_credential::StaticProvider;
 use serde_derive::Deserialize;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 static TOKENS_PATH: &'static str = "tokens.toml";
 
@@ -30,7 +31,12 @@ impl BucketRegion {
 #[serde(rename_all = "kebab-case")]
 pub struct BotTokens {
     pub webhooks_secret: String,
+    /// Personal access token, used unless a GitHub App is configured.
+    #[serde(default)]
     pub api_token: String,
+    pub github_app_id: Option<u64>,
+    pub github_app_private_key_path: Option<PathBuf>,
+    pub github_app_installation_id: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -55,6 +61,23 @@ pub struct Tokens {
     pub bot: BotTokens,
     pub reports_bucket: ReportsBucket,
     pub agents: HashMap<String, String>,
+    #[serde(default)]
+    pub read_only: HashMap<String, String>,
+    #[serde(default)]
+    pub admins: HashMap<String, String>,
+    /// Key signing the links to the reports of private experiments.
+    #[serde(default)]
+    pub private_reports_key: Option<String>,
+    /// Credentials of the SMTP server configured in the `[server.email]` section of `config.toml`.
+    #[serde(default)]
+    pub email: Option<EmailCredentials>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmailCredentials {
+    pub username: String,
+    pub password: String,
 }
 
 #[cfg(test)]
@@ -64,6 +87,9 @@ impl Default for Tokens {
             bot: BotTokens {
                 webhooks_secret: String::new(),
                 api_token: String::new(),
+                github_app_id: None,
+                github_app_private_key_path: None,
+                github_app_installation_id: None,
             },
             reports_bucket: ReportsBucket {
                 region: BucketRegion::S3 {
@@ -75,6 +101,10 @@ impl Default for Tokens {
                 secret_key: String::new(),
             },
             agents: HashMap::new(),
+            read_only: HashMap::new(),
+            admins: HashMap::new(),
+            private_reports_key: None,
+            email: None,
         }
     }
 }
@@ -85,4 +115,9 @@ impl Tokens {
         let res = ::toml::from_str(&content)?;
         Ok(res)
     }
+
+    /// Generate a new random token, to be added to `tokens.toml`.
+    pub fn generate() -> String {
+        thread_rng().sample_iter(&Alphanumeric).take(32).collect()
+    }
 }
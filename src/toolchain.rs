@@ -1,4 +1,5 @@
 use crate::dirs::TARGET_DIR;
+use crate::experiments::{FeatureSet, FeatureSetParseError};
 use crate::prelude::*;
 use crate::run::RunCommand;
 use crate::tools::CARGO;
@@ -8,6 +9,7 @@ use std::borrow::Cow;
 use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
+use utoipa::ToSchema;
 
 pub(crate) static MAIN_TOOLCHAIN_NAME: &str = "stable";
 
@@ -22,6 +24,7 @@ pub(crate) static MAIN_TOOLCHAIN: Toolchain = Toolchain {
         name: Cow::Borrowed(MAIN_TOOLCHAIN_NAME),
     },
     rustflags: None,
+    features: FeatureSet::Default,
 };
 
 /// This toolchain is used during internal tests, and must be different than MAIN_TOOLCHAIN
@@ -31,9 +34,10 @@ pub(crate) static TEST_TOOLCHAIN: Toolchain = Toolchain {
         name: Cow::Borrowed("beta"),
     },
     rustflags: None,
+    features: FeatureSet::Default,
 };
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone, ToSchema)]
 #[serde(rename_all = "kebab-case", tag = "type")]
 pub enum ToolchainSource {
     Dist {
@@ -46,10 +50,12 @@ pub enum ToolchainSource {
     },
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone, ToSchema)]
 pub struct Toolchain {
     pub source: ToolchainSource,
     pub rustflags: Option<String>,
+    #[serde(default, skip_serializing_if = "FeatureSet::is_default")]
+    pub features: FeatureSet,
 }
 
 impl Toolchain {
@@ -64,6 +70,27 @@ impl Toolchain {
         Ok(())
     }
 
+    /// Remove the toolchain and install it again, for example after its installation got
+    /// corrupted.
+    pub(crate) fn reinstall(&self) -> Fallible<()> {
+        let name = self.rustup_name();
+        info!("removing toolchain {}", name);
+        RunCommand::new(&RUSTUP)
+            .args(&["toolchain", "uninstall", name.as_str()])
+            .run()
+            .with_context(|_| format!("unable to remove toolchain {} via rustup", name))?;
+
+        self.prepare()
+    }
+
+    /// The same toolchain, building crates with the provided feature set.
+    pub fn with_features(&self, features: &FeatureSet) -> Toolchain {
+        Toolchain {
+            features: features.clone(),
+            ..self.clone()
+        }
+    }
+
     pub fn rustup_name(&self) -> String {
         match self.source {
             ToolchainSource::Dist { ref name } => name.to_string(),
@@ -119,6 +146,10 @@ impl fmt::Display for Toolchain {
             write!(f, "+rustflags={}", flag)?;
         }
 
+        if !self.features.is_default() {
+            write!(f, "+features={}", self.features)?;
+        }
+
         Ok(())
     }
 }
@@ -131,6 +162,8 @@ pub enum ToolchainParseError {
     InvalidSourceName(String),
     #[fail(display = "invalid toolchain flag: {}", _0)]
     InvalidFlag(String),
+    #[fail(display = "invalid toolchain features: {}", _0)]
+    InvalidFeatures(FeatureSetParseError),
 }
 
 impl FromStr for Toolchain {
@@ -168,6 +201,7 @@ impl FromStr for Toolchain {
         };
 
         let mut rustflags = None;
+        let mut features = FeatureSet::Default;
         for part in parts {
             if let Some(equal_idx) = part.find('=') {
                 let (flag, value_with_equal) = part.split_at(equal_idx);
@@ -179,6 +213,11 @@ impl FromStr for Toolchain {
 
                 match flag {
                     "rustflags" => rustflags = Some(value),
+                    "features" => {
+                        features = value
+                            .parse()
+                            .map_err(ToolchainParseError::InvalidFeatures)?
+                    }
                     unknown => return Err(ToolchainParseError::InvalidFlag(unknown.to_string())),
                 }
             } else {
@@ -186,7 +225,11 @@ impl FromStr for Toolchain {
             }
         }
 
-        Ok(Toolchain { source, rustflags })
+        Ok(Toolchain {
+            source,
+            rustflags,
+            features,
+        })
     }
 }
 
@@ -232,6 +275,7 @@ fn init_toolchain_from_ci(alt: bool, sha: &str) -> Fallible<()> {
 #[cfg(test)]
 mod tests {
     use super::{Toolchain, ToolchainSource};
+    use crate::experiments::FeatureSet;
     use std::str::FromStr;
 
     #[test]
@@ -243,12 +287,24 @@ mod tests {
                     test_from_str!($str => Toolchain {
                         source: $source,
                         rustflags: None,
+                        features: FeatureSet::Default,
                     });
 
                     // Test parsing with flags
                     test_from_str!(concat!($str, "+rustflags=foo bar") => Toolchain {
                         source: $source,
                         rustflags: Some("foo bar".to_string()),
+                        features: FeatureSet::Default,
+                    });
+                    test_from_str!(concat!($str, "+features=all-features") => Toolchain {
+                        source: $source,
+                        rustflags: None,
+                        features: FeatureSet::All,
+                    });
+                    test_from_str!(concat!($str, "+rustflags=foo+features=foo,bar") => Toolchain {
+                        source: $source,
+                        rustflags: Some("foo".to_string()),
+                        features: FeatureSet::Features(vec!["foo".into(), "bar".into()]),
                     });
                 )*
             };
@@ -291,6 +347,7 @@ mod tests {
         assert!(Toolchain::from_str("foo#0000000000000000000000000000000000000000").is_err());
         assert!(Toolchain::from_str("stable+rustflags").is_err());
         assert!(Toolchain::from_str("stable+rustflags=").is_err());
-        assert!(Toolchain::from_str("stable+donotusethisflag=ever").is_err())
+        assert!(Toolchain::from_str("stable+donotusethisflag=ever").is_err());
+        assert!(Toolchain::from_str("stable+features=foo,,bar").is_err());
     }
 }
@@ -19,6 +19,22 @@ pub(crate) fn remove_dir_all(dir: &Path) -> Fallible<()> {
     })
 }
 
+/// Total size in bytes of the files in the directory, which is empty if it doesn't exist.
+pub(crate) fn dir_size(dir: &Path) -> Fallible<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut size = 0;
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
 pub(crate) fn copy_dir(src_dir: &Path, dest_dir: &Path) -> Fallible<()> {
     info!("copying {} to {}", src_dir.display(), dest_dir.display());
 
@@ -9,6 +9,14 @@ pub(crate) enum HexError {
     InvalidLength,
 }
 
+pub(crate) fn to_hex(input: &[u8]) -> String {
+    let mut result = String::with_capacity(input.len() * 2);
+    for byte in input {
+        result.push_str(&format!("{:02x}", byte));
+    }
+    result
+}
+
 pub(crate) fn from_hex(input: &str) -> Result<Vec<u8>, HexError> {
     let mut result = Vec::with_capacity(input.len() / 2);
 
@@ -44,7 +52,7 @@ pub(crate) fn from_hex(input: &str) -> Result<Vec<u8>, HexError> {
 
 #[cfg(test)]
 mod tests {
-    use super::{from_hex, HexError};
+    use super::{from_hex, to_hex, HexError};
 
     #[test]
     fn test_from_hex() {
@@ -60,4 +68,14 @@ mod tests {
         // Invalid length
         assert_eq!(from_hex("000").unwrap_err(), HexError::InvalidLength);
     }
+
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(
+            to_hex(&[0x00, 0x01, 0x02, 0x10, 0xFF, 0xFF, 0xFF]),
+            "00010210ffffff"
+        );
+        assert_eq!(from_hex(&to_hex(b"crater")).unwrap(), b"crater");
+    }
 }
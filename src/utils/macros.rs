@@ -37,6 +37,7 @@ macro_rules! string_enum {
         }
 
         impl_serde_from_parse!($name, expecting="foo");
+        impl_string_schema!($name, values = $name::possible_values().iter().cloned());
     }
 }
 
@@ -78,3 +79,25 @@ macro_rules! impl_serde_from_parse {
         }
     };
 }
+
+/// Describe a type serialized as a string in the OpenAPI description of the server, either
+/// listing all the values it can have or showing an example of them.
+macro_rules! impl_string_schema {
+    ($for:ident, values = $values:expr) => {
+        impl_string_schema!($for, |schema| schema.enum_values(Some($values)));
+    };
+    ($for:ident, example = $example:expr) => {
+        impl_string_schema!($for, |schema| schema.examples([$example]));
+    };
+    ($for:ident, |$schema:ident| $customize:expr) => {
+        impl ::utoipa::PartialSchema for $for {
+            fn schema() -> ::utoipa::openapi::RefOr<::utoipa::openapi::schema::Schema> {
+                let $schema = ::utoipa::openapi::ObjectBuilder::new()
+                    .schema_type(::utoipa::openapi::schema::Type::String);
+                $customize.into()
+            }
+        }
+
+        impl ::utoipa::ToSchema for $for {}
+    };
+}
@@ -41,6 +41,11 @@ where
     f()
 }
 
+/// Total number of whole milliseconds in the duration.
+pub(crate) fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
 pub fn report_panic(e: &Any) {
     if let Some(e) = e.downcast_ref::<String>() {
         error!("panicked: {}", e)
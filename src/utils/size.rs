@@ -63,9 +63,42 @@ impl FromStr for Size {
 
 impl_serde_from_parse!(Size, expecting = "a size");
 
+const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+
+/// Format the number of bytes in the largest unit it's at least one of.
+pub(crate) fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Format a change in size, always prefixed by its sign.
+pub(crate) fn format_size_diff(diff: i64) -> String {
+    let sign = if diff < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_size(diff.abs() as u64))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Size;
+    use super::{format_size, format_size_diff, Size};
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+        assert_eq!(format_size_diff(1536), "+1.5 KB");
+        assert_eq!(format_size_diff(-512), "-512 B");
+    }
 
     #[test]
     fn test_size() {
@@ -41,9 +41,37 @@ pub(crate) fn split_quoted(input: &str) -> Result<Vec<String>, SplitQuotedError>
     }
 }
 
+/// Whether the input matches the glob pattern, where `*` matches any sequence of characters and
+/// `?` matches a single character.
+pub(crate) fn glob_matches(pattern: &str, input: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let input = input.chars().collect::<Vec<_>>();
+
+    let (mut p, mut i) = (0, 0);
+    // Position of the last `*` in the pattern, and of the input it's matched up to
+    let mut star = None;
+    while i < input.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == input[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            // Let the last `*` match one more character
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 #[cfg(test)]
 mod tests {
-    use super::split_quoted;
+    use super::{glob_matches, split_quoted};
 
     #[test]
     fn test_split_quoted() {
@@ -69,4 +97,20 @@ mod tests {
         // Unbalanced quotes
         assert!(split_quoted("a b \" c").is_err());
     }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("lazy_static", "lazy_static"));
+        assert!(!glob_matches("lazy_static", "lazy_static2"));
+        assert!(glob_matches("tokio-*", "tokio-io"));
+        assert!(glob_matches("tokio-*", "tokio-"));
+        assert!(!glob_matches("tokio-*", "tokio"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*-sys", "openssl-sys"));
+        assert!(glob_matches("*ssl*", "openssl-sys"));
+        assert!(glob_matches("nightly-2019-0?-*", "nightly-2019-03-01"));
+        assert!(!glob_matches("nightly-2019-0?-*", "nightly-2019-10-01"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("a*b*c", "aXbYbZ"));
+    }
 }